@@ -4,7 +4,7 @@
 use crate::tui;
 
 use std::fs;
-use std::io::Stdout;
+use std::io::{Stdout, Write};
 
 use std::collections::VecDeque;
 
@@ -14,41 +14,76 @@ use fuzzy_matcher::FuzzyMatcher;
 
 use crate::torrent_manager::DiskIoOperation;
 
-use crate::config::{PeerSortColumn, Settings, SortDirection, TorrentSettings, TorrentSortColumn};
-use crate::token_bucket::TokenBucket;
+use crate::config::{
+    AddOptions, InsufficientSpaceAction, PeerSortColumn, PerformanceProfile, SeedGoalAction,
+    SeedGoals, Settings, SortDirection, TorrentLimitPolicy, TorrentSettings, TorrentSortColumn,
+};
+use crate::token_bucket::{BandwidthPriority, TokenBucket};
 
 use crate::tui_events;
+use crate::tui_formatters::{format_bytes, format_time, share_ratio};
 
+use crate::ban_list::{peer_ip, BanList};
+use crate::bitfield::Bitfield;
 use crate::config::get_watch_path;
+use crate::external_ip::{self, ExternalIp, IpChange, IpSource};
+use crate::geoip::GeoIpDatabase;
+use crate::hash_pool::HashPool;
+use crate::ip_filter::{self, IpFilter};
 
 use crate::resource_manager::ResourceType;
 
+use crate::torrent_file::create::{self, CreateOptions};
 use crate::torrent_file::parser::from_bytes;
+use crate::torrent_manager::files::FilePriority;
+use crate::torrent_manager::manager::magnet_select_only;
+use crate::torrent_manager::manager::magnet_trackers;
+use crate::torrent_manager::state::PeerSource;
 use crate::torrent_manager::ManagerCommand;
 use crate::torrent_manager::ManagerEvent;
 use crate::torrent_manager::TorrentManager;
 use crate::torrent_manager::TorrentParameters;
 
 use crate::config::get_app_paths;
+use crate::config::partial_pieces_path;
 use crate::config::save_settings;
-
-use std::collections::HashMap;
+#[cfg(unix)]
+use crate::config::try_load_settings;
+#[cfg(unix)]
+use crate::control_socket::{self, ControlCommand};
+use crate::errors::{CreateTorrentError, ManagerInitError, PortCheckError};
+#[cfg(unix)]
+use crate::logging;
+use crate::storage::check_writable;
+use crate::storage::FileRename;
+use crate::streaming;
+
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::ops::RangeInclusive;
 use tokio::io::AsyncReadExt;
 use tokio::signal;
 use tokio::sync::broadcast;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::Mutex;
+use tokio::sync::Semaphore;
 
 use std::sync::Arc;
-use std::time::Instant;
+use std::task::Poll;
+use std::time::{Instant, SystemTime};
 
+#[cfg(feature = "dht")]
+use crate::config::DhtMode;
+#[cfg(feature = "dht")]
+use crate::config::{load_dht_nodes, save_dht_nodes};
 #[cfg(feature = "dht")]
 use mainline::{async_dht::AsyncDht, Dht};
 #[cfg(not(feature = "dht"))]
 type AsyncDht = ();
+#[cfg(feature = "dht")]
+use crate::networking::dht_client::{self, DhtClient};
 
-use sha1::Digest;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
@@ -56,7 +91,7 @@ use std::time::Duration;
 use notify::{Config, Error as NotifyError, Event, RecommendedWatcher, RecursiveMode, Watcher};
 
 use ratatui::{backend::CrosstermBackend, Terminal};
-use ratatui_explorer::FileExplorer;
+use ratatui_explorer::{FileExplorer, Theme};
 use std::cell::RefCell;
 use throbber_widgets_tui::ThrobberState;
 
@@ -66,8 +101,13 @@ use data_encoding::BASE32;
 
 use tracing::{event as tracing_event, Level};
 
+use crate::networking::interface_addresses;
+use crate::networking::utp::{UtpSocket, UtpStream};
+use crate::networking::{BoxedPeerStream, IncomingPeer};
+use crate::port_check::{self, PortChecker};
 use crate::resource_manager::{ResourceManager, ResourceManagerClient};
-use tokio::net::TcpStream;
+use crate::scheduler::{self, RateProfile};
+use crate::tracker::SwarmCounts;
 use tokio::sync::mpsc;
 
 use tokio::time;
@@ -88,6 +128,21 @@ const MINUTES_HISTORY_MAX: usize = 48 * 60; // 48 hours of per-minute data
 const FILE_HANDLE_MINIMUM: usize = 64;
 const SAFE_BUDGET_PERCENTAGE: f64 = 0.85;
 
+const STATUS_MESSAGE_HISTORY: usize = 5;
+const STATUS_MESSAGE_LIFETIME: Duration = Duration::from_secs(8);
+const BELL_DEBOUNCE: Duration = Duration::from_secs(5);
+
+// Hard cap on waiting for torrents to flush piece writes and announce 'stopped'
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How often the DHT status panel re-reads the DHT node
+#[cfg(feature = "dht")]
+const DHT_STATUS_REFRESH_SECS: u64 = 5;
+// The first port check waits for trackers and peers to tell us our external IP
+const PORT_CHECK_STARTUP_DELAY: Duration = Duration::from_secs(30);
+// Self-connect checks wait this long at a time for the external IP
+const PORT_CHECK_RETRY: Duration = Duration::from_secs(60);
+
 #[derive(Debug, Default)]
 pub struct ThrobberHolder {
     pub torrent_sparkline: ThrobberState,
@@ -175,6 +230,8 @@ pub struct CalculatedLimits {
     pub max_connected_peers: usize,
     pub disk_read_permits: usize,
     pub disk_write_permits: usize,
+    // Fixed by `connection_attempt_permits`; the self-tuner leaves it alone
+    pub connection_attempt_permits: usize,
 }
 impl CalculatedLimits {
     pub fn into_map(self) -> HashMap<ResourceType, usize> {
@@ -183,6 +240,10 @@ impl CalculatedLimits {
         map.insert(ResourceType::PeerConnection, self.max_connected_peers);
         map.insert(ResourceType::DiskRead, self.disk_read_permits);
         map.insert(ResourceType::DiskWrite, self.disk_write_permits);
+        map.insert(
+            ResourceType::ConnectionAttempt,
+            self.connection_attempt_permits,
+        );
         map
     }
 }
@@ -265,18 +326,32 @@ impl Default for SelectedHeader {
     }
 }
 
-pub const TORRENT_HEADERS: &[TorrentSortColumn] = &[
-    TorrentSortColumn::Name,
-    TorrentSortColumn::Down,
-    TorrentSortColumn::Up,
-];
-
 pub enum AppCommand {
     AddTorrentFromFile(PathBuf),
     AddTorrentFromPathFile(PathBuf),
     AddMagnetFromFile(PathBuf),
     ClientShutdown(PathBuf),
     PortFileChanged(PathBuf),
+    // A freshly loaded copy of the `ip_filter` blocklist
+    IpFilterLoaded(IpFilter),
+    // Our external address, as `external_ip_check_url` reports it
+    ExternalIpReported(IpAddr, IpSource),
+    // Whether peers can reach this port, from a port check
+    PortChecked(u16, Result<bool, PortCheckError>),
+    // A streaming server request for the manager of this info hash
+    Stream(Vec<u8>, ManagerCommand),
+    // A torrent from the create dialog was written, or failed; seeded from
+    // `seed_from` when set
+    TorrentCreated {
+        torrent_path: PathBuf,
+        seed_from: Option<PathBuf>,
+        result: Result<(), CreateTorrentError>,
+    },
+    #[cfg(unix)]
+    Control(ControlCommand),
+    // SIGHUP: re-read settings.toml and apply what can change while running
+    #[cfg(unix)]
+    ReloadSettings,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -286,6 +361,63 @@ pub enum ConfigItem {
     WatchFolder,
     GlobalDownloadLimit,
     GlobalUploadLimit,
+    PerformanceProfile,
+}
+
+/// A row of the create torrent dialog.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CreateItem {
+    Trackers,
+    WebSeeds,
+    PieceSize,
+    Version,
+    Private,
+    Comment,
+    Seed,
+    Create,
+}
+
+/// A row of the add torrent dialog.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AddItem {
+    DownloadFolder,
+    Category,
+    Paused,
+    Sequential,
+    SkipCheck,
+    Add,
+}
+
+impl AddItem {
+    pub const ALL: [AddItem; 6] = [
+        AddItem::DownloadFolder,
+        AddItem::Category,
+        AddItem::Paused,
+        AddItem::Sequential,
+        AddItem::SkipCheck,
+        AddItem::Add,
+    ];
+}
+
+impl CreateItem {
+    pub const ALL: [CreateItem; 8] = [
+        CreateItem::Trackers,
+        CreateItem::WebSeeds,
+        CreateItem::PieceSize,
+        CreateItem::Version,
+        CreateItem::Private,
+        CreateItem::Comment,
+        CreateItem::Seed,
+        CreateItem::Create,
+    ];
+}
+
+/// Trackers and web seeds that came with a torrent added a second time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateSources {
+    pub info_hash: Vec<u8>,
+    pub trackers: Vec<String>,
+    pub web_seeds: Vec<String>,
 }
 
 #[derive(Default)]
@@ -295,10 +427,22 @@ pub enum AppMode {
     Normal,
     PowerSaving,
     DownloadPathPicker(FileExplorer),
+    // Picks the default download folder from the welcome screen
+    SetupFolderPicker(FileExplorer),
     DeleteConfirm {
         info_hash: Vec<u8>,
         with_files: bool,
     },
+    // A torrent reached its seed goals; offers to act now or keep seeding before
+    // its grace period runs out
+    SeedGoalConfirm {
+        info_hash: Vec<u8>,
+        action: SeedGoalAction,
+    },
+    // A torrent added again brought trackers or web seeds; asks before merging them
+    // into the one already there
+    MergeSourcesConfirm(DuplicateSources),
+    QuitConfirm,
     Config {
         settings_edit: Box<Settings>,
         selected_index: usize,
@@ -310,6 +454,47 @@ pub enum AppMode {
         for_item: ConfigItem,
         file_explorer: FileExplorer,
     },
+    NetworkAudit {
+        scroll_offset: u16,
+    },
+    // A torrent's files, where their priorities are set
+    Files {
+        info_hash: Vec<u8>,
+        selected: usize,
+    },
+    // Text box for a torrent's name, or with `file` set, for the path of the file at
+    // that position in the files list
+    Rename {
+        info_hash: Vec<u8>,
+        file: Option<usize>,
+        buffer: String,
+    },
+    // Options for the magnet link or torrent path `input` before it's added
+    AddTorrent {
+        input: String,
+        options: AddOptions,
+        selected_index: usize,
+    },
+    // Picks the download folder from the add torrent dialog
+    AddTorrentPathPicker {
+        input: String,
+        options: AddOptions,
+        file_explorer: FileExplorer,
+    },
+    // Picks the folder to move a torrent's files to
+    LocationPicker {
+        info_hash: Vec<u8>,
+        file_explorer: FileExplorer,
+    },
+    // Picks the file or folder to make a torrent of
+    CreatePathPicker(FileExplorer),
+    // The rest of the create torrent dialog; `seed` adds the result once written
+    CreateTorrent {
+        options: Box<CreateOptions>,
+        seed: bool,
+        selected_index: usize,
+        editing: Option<(CreateItem, String)>,
+    },
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -317,20 +502,11 @@ pub enum TorrentControlState {
     #[default]
     Running,
     Paused,
+    // Waiting, paused, for a download or seed slot to free up
+    Queued,
     Deleting,
 }
 
-pub const PEER_HEADERS: &[PeerSortColumn] = &[
-    PeerSortColumn::Flags,
-    PeerSortColumn::Address,
-    PeerSortColumn::Client,
-    PeerSortColumn::Action,
-    PeerSortColumn::Completed,
-    PeerSortColumn::DL,
-    PeerSortColumn::UL,
-    PeerSortColumn::TotalDL,
-    PeerSortColumn::TotalUL,
-];
 #[derive(Debug, Clone, Default)]
 pub struct PeerInfo {
     pub address: String,
@@ -339,12 +515,47 @@ pub struct PeerInfo {
     pub peer_choking: bool,
     pub am_interested: bool,
     pub peer_interested: bool,
-    pub bitfield: Vec<bool>,
+    pub bitfield: Bitfield,
     pub download_speed_bps: u64,
     pub upload_speed_bps: u64,
     pub total_downloaded: u64,
     pub total_uploaded: u64,
     pub last_action: String,
+    // Filled in by the app from the GeoIP database, if one is configured
+    pub country: Option<String>,
+    pub source: PeerSource,
+}
+
+/// One file of a torrent, for the files view. Pad files are left out.
+#[derive(Debug, Clone, Default)]
+pub struct TorrentFileInfo {
+    // Index in the metadata, counting pad files; what priorities are set by
+    pub index: usize,
+    // Relative to the download folder
+    pub path: String,
+    pub size: u64,
+    // Bytes in pieces we have
+    pub bytes_done: u64,
+    pub priority: FilePriority,
+}
+
+/// A short confirmation shown in the status area until it expires.
+#[derive(Debug, Clone)]
+pub struct StatusMessage {
+    pub text: String,
+    pub created_at: Instant,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TrackerInfo {
+    pub url: String,
+    pub next_announce_in: Duration,
+    pub interval: Option<Duration>,
+    // Last counts this tracker gave us, from a scrape or an announce
+    pub swarm: Option<SwarmCounts>,
+    // Announces failed in a row, and why the last one did
+    pub failures: u32,
+    pub last_error: Option<String>,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -364,9 +575,54 @@ pub struct TorrentState {
     pub eta: Duration,
     pub peers: Vec<PeerInfo>,
     pub activity_message: String,
+    pub is_queued_for_validation: bool,
     pub next_announce_in: Duration,
+    // None when this torrent isn't using the DHT
+    pub next_dht_refresh_in: Option<Duration>,
+    pub trackers: Vec<TrackerInfo>,
+    // Counts from the tracker that sees the largest swarm
+    pub swarm: Option<SwarmCounts>,
+    // Distinct peers the last DHT lookup returned; a rough swarm size when no
+    // tracker reports one
+    pub dht_peers: Option<usize>,
+    // Seeds and leechers by DHT scrape (BEP 33), when the closest nodes support it
+    pub dht_swarm: Option<SwarmCounts>,
+    pub is_private: bool,
     pub total_size: u64,
     pub bytes_written: u64,
+    pub wasted_bytes: u64,
+    // Set by the user per torrent; the manager is told through ManagerCommand
+    pub upload_disabled: bool,
+    pub download_disabled: bool,
+    pub max_download_bytes: Option<u64>,
+    // The manager's file priorities, saved with the torrent
+    pub file_priorities: Option<Vec<FilePriority>>,
+    // Files the user renamed, saved with the torrent and handed to the manager
+    pub file_renames: Vec<FileRename>,
+    // Empty until the metadata is known
+    pub files: Vec<TorrentFileInfo>,
+    // Lifetime totals, restored from the saved settings and kept up to date here
+    pub total_downloaded: u64,
+    pub total_uploaded: u64,
+    // Lifetime time spent seeding, saved like the totals
+    pub seeding_time: Duration,
+    // None follows the global seed goals
+    pub seed_goals: Option<SeedGoals>,
+    pub category: Option<String>,
+    // The user's name for the torrent; `torrent_name` stays the metadata's
+    pub display_name: Option<String>,
+    // Pieces are picked in order instead of rarest first
+    pub sequential: bool,
+    // Its share of the global speed limits while other torrents want them too
+    pub bandwidth_priority: BandwidthPriority,
+    // Peer discovery the user switched off; `is_private` turns both off regardless
+    pub dht_disabled: bool,
+    pub pex_disabled: bool,
+    // Trackers and web seeds merged in from duplicates, saved with the torrent
+    pub extra_trackers: Vec<String>,
+    pub extra_web_seeds: Vec<String>,
+    // Set while the torrent downloads to the incomplete folder
+    pub completed_path: Option<PathBuf>,
 
     pub blocks_in_history: Vec<u64>,
     pub blocks_out_history: Vec<u64>,
@@ -374,6 +630,24 @@ pub struct TorrentState {
     pub blocks_out_this_tick: u64,
 }
 
+/// Retry bookkeeping for a torrent whose manager failed to start.
+#[derive(Debug, Clone)]
+pub struct InitFailure {
+    // Storage failures may clear up on their own; bad metadata never will.
+    pub transient: bool,
+    pub attempts: u32,
+    pub next_retry_at: Option<Instant>,
+}
+
+/// Torrents paused because a process named in `pause_when_process_running` is open.
+#[derive(Debug, Clone)]
+pub struct ProcessPause {
+    pub process: String,
+    pub torrents: Vec<Vec<u8>>,
+    // Only leave power saving on resume if we were the ones to enter it
+    pub entered_power_saving: bool,
+}
+
 #[derive(Default, Debug)]
 pub struct TorrentDisplayState {
     pub latest_state: TorrentState,
@@ -400,6 +674,102 @@ pub struct TorrentDisplayState {
     pub peer_discovery_history: Vec<u64>,
     pub peer_connection_history: Vec<u64>,
     pub peer_disconnect_history: Vec<u64>,
+
+    // Set for saved torrents whose .torrent file couldn't be loaded on startup, or
+    // whose manager failed to start.
+    pub load_error: Option<String>,
+    pub init_failure: Option<InitFailure>,
+    // Last time the torrent moved any data (or when it was first reported)
+    pub last_active_at: Option<Instant>,
+    // When the torrent was added or restored this session
+    pub added_at: Option<Instant>,
+    // Whether any data was downloaded this session, so finishing the startup
+    // re-check doesn't count as completing
+    pub downloaded_this_session: bool,
+    // Last update that found the torrent seeding, to count seeding time from
+    pub seeding_since: Option<Instant>,
+}
+
+impl TorrentState {
+    /// The name to show: the user's, or else the one from the metadata.
+    pub fn name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.torrent_name)
+    }
+
+    /// Whether every piece is done, as far as the last report goes.
+    pub fn is_complete(&self) -> bool {
+        self.number_of_pieces_total > 0
+            && self.number_of_pieces_completed >= self.number_of_pieces_total
+    }
+
+    /// Share ratio for seed goals. Measured against the torrent's size when less
+    /// than that was downloaded, so seeding files we already had isn't an instant
+    /// infinite ratio.
+    pub fn seed_ratio(&self) -> f64 {
+        let base = self.total_downloaded.max(self.total_size);
+        if base == 0 {
+            return 0.0;
+        }
+        self.total_uploaded as f64 / base as f64
+    }
+
+    /// How long until the first of `goals` is reached, uploading at
+    /// `upload_speed_bps`. `None` when there is no goal, or only a ratio goal
+    /// while nothing is being uploaded.
+    pub fn seed_goal_eta(&self, goals: &SeedGoals, upload_speed_bps: u64) -> Option<Duration> {
+        let time_eta = goals
+            .seed_time_secs
+            .map(|secs| Duration::from_secs(secs.saturating_sub(self.seeding_time.as_secs())));
+        let ratio_eta = goals.ratio.and_then(|ratio| {
+            let base = self.total_downloaded.max(self.total_size);
+            let remaining = (ratio * base as f64) as u64;
+            let remaining = remaining.saturating_sub(self.total_uploaded);
+            if remaining == 0 {
+                Some(Duration::ZERO)
+            } else {
+                (upload_speed_bps > 0)
+                    .then(|| Duration::from_secs(remaining.div_ceil(upload_speed_bps)))
+            }
+        });
+        time_eta.into_iter().chain(ratio_eta).min()
+    }
+}
+
+impl TorrentDisplayState {
+    /// Whether the torrent has moved no data for at least `threshold`.
+    pub fn is_inactive(&self, threshold: Duration) -> bool {
+        match self.last_active_at {
+            Some(last_active_at) => last_active_at.elapsed() >= threshold,
+            None => true,
+        }
+    }
+}
+
+/// One line of the first-run setup check on the welcome screen. `result` holds what
+/// was found, or what is wrong and how to fix it.
+#[derive(Clone, Debug)]
+pub struct SetupCheck {
+    pub label: &'static str,
+    pub result: Result<String, String>,
+}
+
+/// What the DHT node reports about itself, refreshed every few seconds.
+#[derive(Debug, Clone, Default)]
+pub struct DhtStatus {
+    pub routing_table_nodes: usize,
+    // Our address as other nodes see it, once enough of them agree
+    pub public_address: Option<SocketAddr>,
+    pub firewalled: bool,
+    pub server_mode: bool,
+    pub local_port: u16,
+    // Rough number of nodes in the whole DHT
+    pub network_size_estimate: usize,
+    // Queries from other nodes, averaged since the previous refresh
+    pub queries_per_sec: f64,
+    #[cfg(feature = "dht")]
+    queries_received: u64,
+    #[cfg(feature = "dht")]
+    refreshed_at: Option<Instant>,
 }
 
 #[derive(Default)]
@@ -408,18 +778,50 @@ pub struct AppState {
     pub shutdown_progress: f64,
     pub system_warning: Option<String>,
     pub system_error: Option<String>,
+    pub status_messages: VecDeque<StatusMessage>,
+    // True while saved torrents are loaded on startup; they skip the torrent limit
+    pub restoring_torrents: bool,
+    pub process_pause: Option<ProcessPause>,
+    // Global limits the bandwidth schedule has in force, and the torrents it paused
+    pub rate_profile: RateProfile,
+    pub schedule_paused: Vec<Vec<u8>>,
+    // Set while the user has everything paused, with the torrents that paused
+    pub all_paused: Option<Vec<Vec<u8>>>,
+    // When torrents that reached their seed goals will be paused or removed
+    pub seed_goal_deadlines: HashMap<Vec<u8>, Instant>,
+    // Torrents whose seed goals already fired. They only fire again after the goals
+    // change so that the torrent falls short of them.
+    pub seed_goals_fired: HashSet<Vec<u8>>,
+    // Sources of a torrent just added again, until they are merged into the one
+    // already there or turned down
+    pub duplicate_sources: Option<DuplicateSources>,
+    // When the terminal bell last rang, and the error it last rang for
+    pub last_bell_at: Option<Instant>,
+    pub last_bell_error: Option<String>,
+    // Last time settings.toml was written, by autosave or on request
+    pub last_saved_at: Option<Instant>,
     pub limits: CalculatedLimits,
 
     pub mode: AppMode,
+    pub setup_checks: Vec<SetupCheck>,
     pub show_help: bool,
     pub externally_accessable_port: bool,
+    // When a port check or a peer connecting to us last showed whether the port is open
+    pub port_checked_at: Option<Instant>,
+    pub port_check_running: bool,
     pub anonymize_torrent_names: bool,
 
     pub pending_torrent_path: Option<PathBuf>,
     pub pending_torrent_link: String,
+    // Set by V so the next paste opens the add dialog instead of adding right away
+    pub paste_with_options: bool,
+    pub file_picker_error: Option<String>,
     pub torrents: HashMap<Vec<u8>, TorrentDisplayState>,
 
     pub torrent_list_order: Vec<Vec<u8>>,
+    // Every torrent in queue order, which decides who gets a download or seed slot.
+    // Torrents are saved in this order.
+    pub torrent_queue: Vec<Vec<u8>>,
 
     pub total_download_history: Vec<u64>,
     pub total_upload_history: Vec<u64>,
@@ -436,6 +838,8 @@ pub struct AppState {
     pub session_total_uploaded: u64,
 
     pub cpu_usage: f32,
+    pub hash_threads: usize,
+    pub hash_threads_busy: usize,
     pub ram_usage_percent: f32,
     pub avg_disk_read_bps: u64,
     pub avg_disk_write_bps: u64,
@@ -469,9 +873,16 @@ pub struct AppState {
     pub torrent_sort: (TorrentSortColumn, SortDirection),
     pub peer_sort: (PeerSortColumn, SortDirection),
     pub selected_torrent_index: usize,
+    // Row in the peer table, used while a peer column header is selected
+    pub selected_peer_index: usize,
+    pub banned_peers: usize,
+    // None until the first refresh, and always without the DHT
+    pub dht_status: Option<DhtStatus>,
 
     pub is_searching: bool,
     pub search_query: String,
+    // Only torrents in this category are listed
+    pub category_filter: Option<String>,
 
     pub graph_mode: GraphDisplayMode,
     pub minute_avg_dl_history: Vec<u64>,
@@ -490,6 +901,8 @@ pub struct AppState {
     pub recently_processed_files: HashMap<PathBuf, Instant>,
 
     pub throbber_holder: RefCell<ThrobberHolder>,
+
+    pub geoip: Option<GeoIpDatabase>,
 }
 
 pub struct App {
@@ -497,13 +910,37 @@ pub struct App {
     pub client_configs: Settings,
 
     pub listener: tokio::net::TcpListener,
-
-    pub torrent_manager_incoming_peer_txs: HashMap<Vec<u8>, Sender<(TcpStream, Vec<u8>)>>,
+    // IPv6 peers, when the system keeps IPv6 sockets apart from IPv4 ones. On
+    // dual-stack systems `listener` itself is the IPv6 socket and takes both.
+    // With a `listen_interface`, one socket per address family it has.
+    pub listener_v6: Option<tokio::net::TcpListener>,
+    // Peers on `extra_listen_ports`
+    pub extra_listeners: Vec<tokio::net::TcpListener>,
+    // Bound on UDP `client_port` when `peer_transport` includes uTP
+    pub utp_socket: Option<UtpSocket>,
+    pub utp_incoming_rx: Option<mpsc::Receiver<(UtpStream, SocketAddr)>>,
+
+    pub torrent_manager_incoming_peer_txs: HashMap<Vec<u8>, Sender<IncomingPeer>>,
     pub torrent_manager_command_txs: HashMap<Vec<u8>, Sender<ManagerCommand>>,
     pub distributed_hash_table: AsyncDht,
+    // Scrapes over IPv4 and lookups over IPv6 (BEP 32, 33); None when the DHT is off
+    // or no socket of that family could be bound
+    #[cfg(feature = "dht")]
+    pub dht4: Option<DhtClient>,
+    #[cfg(feature = "dht")]
+    pub dht6: Option<DhtClient>,
     pub resource_manager: ResourceManagerClient,
+    pub hash_pool: HashPool,
+    pub validation_permits: Arc<Semaphore>,
     pub global_dl_bucket: Arc<Mutex<TokenBucket>>,
     pub global_ul_bucket: Arc<Mutex<TokenBucket>>,
+    pub ban_list: BanList,
+    pub external_ip: ExternalIp,
+    pub next_port_check_at: Option<Instant>,
+    // Whether the running port check connects to our own external address, and
+    // whether a peer has connected to us since the last check finished
+    pub port_check_self_connect: bool,
+    pub incoming_peer_since_port_check: bool,
 
     pub torrent_tx: broadcast::Sender<TorrentState>,
     pub torrent_rx: broadcast::Receiver<TorrentState>,
@@ -517,9 +954,12 @@ pub struct App {
 }
 impl App {
     pub async fn new(client_configs: Settings) -> Result<Self, Box<dyn std::error::Error>> {
-        let listener =
-            tokio::net::TcpListener::bind(format!("0.0.0.0:{}", client_configs.client_port))
-                .await?;
+        let (listener, listener_v6) =
+            bind_peer_listeners(&client_configs, client_configs.client_port).await?;
+        let extra_listeners = bind_extra_listeners(&client_configs).await;
+        let (utp_socket, utp_incoming_rx) = bind_utp(&client_configs, client_configs.client_port)
+            .await
+            .unzip();
 
         let (manager_event_tx, manager_event_rx) = mpsc::channel::<ManagerEvent>(100);
         let (app_command_tx, app_command_rx) = mpsc::channel::<AppCommand>(10);
@@ -549,34 +989,59 @@ impl App {
             ResourceType::DiskWrite,
             (limits.disk_write_permits, limits.disk_read_permits * 2),
         );
+        // Every waiting attempt already holds a peer connection permit
+        rm_limits.insert(
+            ResourceType::ConnectionAttempt,
+            (
+                limits.connection_attempt_permits,
+                limits.max_connected_peers,
+            ),
+        );
         let (resource_manager, resource_manager_client) =
             ResourceManager::new(rm_limits, shutdown_tx.clone());
         tokio::spawn(resource_manager.run());
 
-        #[cfg(feature = "dht")]
-        let bootstrap_nodes: Vec<&str> = client_configs
-            .bootstrap_nodes
-            .iter()
-            .map(AsRef::as_ref)
-            .collect();
+        let hash_pool = HashPool::new(client_configs.hash_thread_count());
+        let validation_permits = Arc::new(Semaphore::new(
+            client_configs.max_concurrent_validations.max(1),
+        ));
 
         #[cfg(feature = "dht")]
-        let distributed_hash_table = Dht::builder()
-            .bootstrap(&bootstrap_nodes)
-            .port(client_configs.client_port)
-            .server_mode()
-            .build()?
-            .as_async();
+        let distributed_hash_table = build_dht(
+            &client_configs,
+            client_configs.client_port,
+            &load_dht_nodes(),
+        )?;
 
         #[cfg(not(feature = "dht"))]
         let distributed_hash_table = ();
 
+        #[cfg(feature = "dht")]
+        let (dht4, dht6) = build_dht_clients(&client_configs).await;
+
         let dl_limit = client_configs.global_download_limit_bps as f64;
         let ul_limit = client_configs.global_upload_limit_bps as f64;
         let global_dl_bucket = Arc::new(Mutex::new(TokenBucket::new(dl_limit, dl_limit)));
         let global_ul_bucket = Arc::new(Mutex::new(TokenBucket::new(ul_limit, ul_limit)));
 
+        let geoip = client_configs
+            .geoip_database_path
+            .as_ref()
+            .and_then(|path| match GeoIpDatabase::open(path) {
+                Ok(db) => Some(db),
+                Err(e) => {
+                    tracing_event!(
+                        Level::WARN,
+                        "Failed to open GeoIP database {:?}: {}",
+                        path,
+                        e
+                    );
+                    None
+                }
+            });
+
         let app_state = AppState {
+            geoip,
             system_warning,
             system_error: None,
             limits: limits.clone(),
@@ -605,12 +1070,27 @@ impl App {
             app_state,
             client_configs: client_configs.clone(),
             listener,
+            listener_v6,
+            extra_listeners,
+            utp_socket,
+            utp_incoming_rx,
             torrent_manager_incoming_peer_txs: HashMap::new(),
             torrent_manager_command_txs: HashMap::new(),
             distributed_hash_table,
+            #[cfg(feature = "dht")]
+            dht4,
+            #[cfg(feature = "dht")]
+            dht6,
             resource_manager: resource_manager_client,
+            hash_pool,
+            validation_permits,
             global_dl_bucket,
             global_ul_bucket,
+            ban_list: BanList::new(),
+            external_ip: ExternalIp::default(),
+            next_port_check_at: Some(Instant::now() + PORT_CHECK_STARTUP_DELAY),
+            port_check_self_connect: false,
+            incoming_peer_since_port_check: false,
             torrent_tx,
             torrent_rx,
             manager_event_tx,
@@ -624,6 +1104,7 @@ impl App {
 
         let mut torrents_to_load = app.client_configs.torrents.clone();
         torrents_to_load.sort_by_key(|t| !t.validation_status);
+        app.app_state.restoring_torrents = true;
         for torrent_config in torrents_to_load {
             if torrent_config.torrent_or_magnet.starts_with("magnet:") {
                 app.add_magnet_torrent(
@@ -639,14 +1120,21 @@ impl App {
                     PathBuf::from(&torrent_config.torrent_or_magnet),
                     torrent_config.download_path.clone(),
                     torrent_config.validation_status,
-                    torrent_config.torrent_control_state,
+                    torrent_config.torrent_control_state.clone(),
                 )
                 .await;
+                app.keep_unloaded_torrent(&torrent_config);
             }
         }
+        app.app_state.restoring_torrents = false;
+        // Restoring saved torrents isn't news to the user.
+        app.app_state.status_messages.clear();
+
+        app.check_default_download_folder();
 
         if app.app_state.torrents.is_empty() {
             app.app_state.mode = AppMode::Welcome;
+            app.refresh_setup_checks();
         }
 
         let is_leeching = app.app_state.torrents.values().any(|t| {
@@ -657,41 +1145,124 @@ impl App {
         Ok(app)
     }
 
+    /// Runs the client until it is told to quit. With no terminal it runs headless:
+    /// keyboard input and drawing are skipped, and everything else runs as usual.
     pub async fn run(
         &mut self,
-        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+        mut terminal: Option<&mut Terminal<CrosstermBackend<Stdout>>>,
     ) -> Result<(), Box<dyn std::error::Error>> {
         self.process_pending_commands().await;
 
-        // --- Spawn TUI event handler task ---
-        let tui_event_tx_clone = self.tui_event_tx.clone();
-        let mut tui_shutdown_rx = self.shutdown_tx.subscribe();
-        tokio::spawn(async move {
-            loop {
-                tokio::select! {
-                    _ = tui_shutdown_rx.recv() => break,
-
-                    result = tokio::task::spawn_blocking(event::read) => {
-                        let event = match result {
-                            Ok(Ok(e)) => e,
-                            Ok(Err(e)) => {
-                                tracing_event!(Level::ERROR, "Crossterm event read error: {}", e);
-                                break;
-                            }
-                            Err(e) => {
-                                tracing_event!(Level::ERROR, "Blocking TUI read task panicked: {}", e);
-                                break;
-                            }
-                        };
+        #[cfg(unix)]
+        if self.client_configs.control_socket {
+            if let Some(path) = control_socket::socket_path() {
+                if let Err(e) = control_socket::spawn_listener(
+                    path,
+                    self.app_command_tx.clone(),
+                    self.shutdown_tx.subscribe(),
+                ) {
+                    tracing_event!(Level::WARN, "Could not start control socket: {}", e);
+                }
+            }
+        }
 
-                        if tui_event_tx_clone.send(event).await.is_err() {
+        // Service managers and `docker stop` ask us to quit with SIGTERM.
+        #[cfg(unix)]
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                let app_command_tx = self.app_command_tx.clone();
+                tokio::spawn(async move {
+                    if sigterm.recv().await.is_some() {
+                        let _ = app_command_tx
+                            .send(AppCommand::Control(ControlCommand::Shutdown))
+                            .await;
+                    }
+                });
+            }
+            Err(e) => tracing_event!(Level::WARN, "Could not listen for SIGTERM: {}", e),
+        }
+        #[cfg(unix)]
+        match signal::unix::signal(signal::unix::SignalKind::hangup()) {
+            Ok(mut sighup) => {
+                let app_command_tx = self.app_command_tx.clone();
+                tokio::spawn(async move {
+                    while sighup.recv().await.is_some() {
+                        if app_command_tx
+                            .send(AppCommand::ReloadSettings)
+                            .await
+                            .is_err()
+                        {
                             break;
                         }
                     }
+                });
+            }
+            Err(e) => tracing_event!(Level::WARN, "Could not listen for SIGHUP: {}", e),
+        }
 
-                }
+        if let Some(port) = self.client_configs.streaming_port {
+            if let Err(e) = streaming::spawn_server(
+                port,
+                self.app_command_tx.clone(),
+                self.shutdown_tx.subscribe(),
+            )
+            .await
+            {
+                tracing_event!(Level::WARN, "Could not start streaming server: {}", e);
+                self.app_state.system_error =
+                    Some(format!("Streaming server failed on port {}: {}", port, e));
             }
-        });
+        }
+
+        if let Some(source) = self.client_configs.ip_filter.clone() {
+            ip_filter::spawn_refresh(
+                source,
+                self.client_configs.ip_filter_refresh_interval(),
+                self.app_command_tx.clone(),
+                self.shutdown_tx.subscribe(),
+            );
+        }
+
+        if let Some(url) = self.client_configs.external_ip_check_url.clone() {
+            external_ip::spawn_check(
+                url,
+                self.client_configs.external_ip_check_interval(),
+                self.app_command_tx.clone(),
+                self.shutdown_tx.subscribe(),
+            );
+        }
+
+        // --- Spawn TUI event handler task (skipped when headless) ---
+        if terminal.is_some() {
+            let tui_event_tx_clone = self.tui_event_tx.clone();
+            let mut tui_shutdown_rx = self.shutdown_tx.subscribe();
+            tokio::spawn(async move {
+                loop {
+                    tokio::select! {
+                        _ = tui_shutdown_rx.recv() => break,
+
+                        result = tokio::task::spawn_blocking(event::read) => {
+                            let event = match result {
+                                Ok(Ok(e)) => e,
+                                Ok(Err(e)) => {
+                                    tracing_event!(Level::ERROR, "Crossterm event read error: {}", e);
+                                    break;
+                                }
+                                Err(e) => {
+                                    tracing_event!(Level::ERROR, "Blocking TUI read task panicked: {}", e);
+                                    break;
+                                }
+                            };
+
+                            if tui_event_tx_clone.send(event).await.is_err() {
+                                break;
+                            }
+                        }
+
+                    }
+                }
+            });
+        }
 
         let (notify_tx, mut notify_rx) = mpsc::channel::<Result<Event, NotifyError>>(100);
         let mut watcher = RecommendedWatcher::new(
@@ -706,11 +1277,17 @@ impl App {
             },
             Config::default(),
         )?;
-        if let Some(path) = &self.client_configs.watch_folder {
+        for rule in &self.client_configs.watch_folders {
+            let path = &rule.path;
             if let Err(e) = watcher.watch(path, RecursiveMode::NonRecursive) {
                 tracing_event!(Level::ERROR, "Failed to watch user path {:?}: {}", path, e);
             } else {
-                tracing_event!(Level::INFO, "Watching user path: {:?}", path);
+                tracing_event!(
+                    Level::INFO,
+                    "Watching user path: {:?} (label: {:?})",
+                    path,
+                    rule.label
+                );
             }
         }
         if let Some((watch_path, _)) = get_watch_path() {
@@ -757,44 +1334,40 @@ impl App {
                 _ = signal::ctrl_c() => {
                     self.app_state.should_quit = true;
                 }
-                Ok(Ok((mut stream, _addr))) = tokio::time::timeout(Duration::from_secs(2), self.listener.accept()) => {
-                    if !self.app_state.externally_accessable_port {
-                        self.app_state.externally_accessable_port = true;
+                Ok(Ok((stream, addr))) = tokio::time::timeout(Duration::from_secs(2), self.listener.accept()) => {
+                    // uTP only: the TCP listener stays bound for the port check but peers go elsewhere.
+                    if self.client_configs.peer_transport.uses_tcp() || self.utp_socket.is_none() {
+                        self.route_incoming_peer(Box::new(stream), addr);
+                    }
+                }
+                Ok((stream, addr)) = accept_if_bound(self.listener_v6.as_ref()) => {
+                    if self.client_configs.peer_transport.uses_tcp() || self.utp_socket.is_none() {
+                        self.route_incoming_peer(Box::new(stream), addr);
                     }
-
-                    let torrent_manager_incoming_peer_txs_clone = self.torrent_manager_incoming_peer_txs.clone();
-                    let resource_manager_clone = self.resource_manager.clone();
-                    let mut permit_shutdown_rx = self.shutdown_tx.subscribe();
-                    tokio::spawn(async move {
-                        let _session_permit = tokio::select! {
-                            permit_result = resource_manager_clone.acquire_peer_connection() => {
-                                match permit_result {
-                                    Ok(permit) => Some(permit),
-                                    Err(_) => {
-                                        tracing_event!(Level::DEBUG, "Failed to acquire permit. Manager shut down?");
-                                        None
-                                    }
-                                }
-                            }
-                            _ = permit_shutdown_rx.recv() => {
-                                None
-                            }
-                        };
-                        let mut buffer = vec![0u8; 68];
-                        if (stream.read_exact(&mut buffer).await).is_ok() {
-                            let peer_info_hash = &buffer[28..48];
-                            if let Some(torrent_manager_tx) = torrent_manager_incoming_peer_txs_clone.get(peer_info_hash) {
-                                let torrent_manager_tx_clone = torrent_manager_tx.clone();
-                                let _ = torrent_manager_tx_clone.send((stream, buffer)).await;
-                            }
-                        }
-                    });
+                }
+                Ok((stream, addr)) = accept_any(&self.extra_listeners) => {
+                    if self.client_configs.peer_transport.uses_tcp() || self.utp_socket.is_none() {
+                        self.route_incoming_peer(Box::new(stream), addr);
+                    }
+                }
+                (stream, addr) = next_utp_connection(&mut self.utp_incoming_rx) => {
+                    self.route_incoming_peer(Box::new(stream), addr);
                 }
                 Some(event) = self.manager_event_rx.recv() => {
                     match event {
                         ManagerEvent::DeletionComplete(info_hash, result) => {
-                            if let Err(e) = result {
-                                tracing_event!(Level::ERROR, "Deletion failed for torrent: {}", e);
+                            let torrent_name = self
+                                .app_state
+                                .torrents
+                                .get(&info_hash)
+                                .map(|t| t.latest_state.torrent_name.clone())
+                                .unwrap_or_default();
+                            match result {
+                                Ok(()) => self.push_status_message(format!("Removed '{}'", torrent_name)),
+                                Err(e) => {
+                                    tracing_event!(Level::ERROR, "Deletion failed for torrent: {}", e);
+                                    self.push_status_message(format!("Could not fully remove '{}': {}", torrent_name, e));
+                                }
                             }
 
                             self.client_configs.torrents.retain(|t| {
@@ -816,10 +1389,16 @@ impl App {
                                 }
                             });
 
+                            // The manager saved its unfinished pieces on the way out, for a run that won't come.
+                            if let Some(path) = partial_pieces_path(&info_hash) {
+                                let _ = fs::remove_file(path);
+                            }
+
                             self.app_state.torrents.remove(&info_hash);
                             self.torrent_manager_command_txs.remove(&info_hash);
                             self.torrent_manager_incoming_peer_txs.remove(&info_hash);
                             self.app_state.torrent_list_order.retain(|ih| *ih != info_hash);
+                            self.app_state.torrent_queue.retain(|ih| *ih != info_hash);
 
                             if self.app_state.selected_torrent_index >= self.app_state.torrent_list_order.len() && !self.app_state.torrent_list_order.is_empty() {
                                 self.app_state.selected_torrent_index = self.app_state.torrent_list_order.len() - 1;
@@ -918,6 +1497,106 @@ impl App {
                                 torrent.latest_state.blocks_out_this_tick += 1;
                              }
                         }
+                        ManagerEvent::InsufficientDiskSpace { info_hash, required_bytes, available_bytes, resuming } => {
+                            let torrent_name = self
+                                .app_state
+                                .torrents
+                                .get(&info_hash)
+                                .map(|t| t.latest_state.torrent_name.clone())
+                                .unwrap_or_default();
+                            let space_msg = format!(
+                                "Not enough disk space for '{}': needs {}, only {} free.",
+                                torrent_name,
+                                format_bytes(required_bytes),
+                                format_bytes(available_bytes)
+                            );
+
+                            match self.client_configs.on_insufficient_space {
+                                InsufficientSpaceAction::Refuse if !resuming => {
+                                    if let Some(manager_tx) = self.torrent_manager_command_txs.get(&info_hash) {
+                                        let _ = manager_tx.try_send(ManagerCommand::Shutdown);
+                                    }
+                                    if let Some(torrent) = self.app_state.torrents.get_mut(&info_hash) {
+                                        torrent.latest_state.torrent_control_state = TorrentControlState::Deleting;
+                                    }
+                                    self.app_state.system_error = Some(format!("{} Torrent was not added.", space_msg));
+                                }
+                                _ => {
+                                    if let Some(torrent) = self.app_state.torrents.get_mut(&info_hash) {
+                                        torrent.latest_state.torrent_control_state = TorrentControlState::Paused;
+                                    }
+                                    let outcome = if resuming { "Torrent stays paused." } else { "Torrent was paused." };
+                                    self.app_state.system_error = Some(format!("{} {}", space_msg, outcome));
+                                }
+                            }
+                            self.app_state.ui_needs_redraw = true;
+                        }
+                        ManagerEvent::ExternalIpReported { ip, source } => {
+                            self.report_external_ip(ip, source).await;
+                        }
+                        ManagerEvent::DownloadCapReached { info_hash } => {
+                            if let Some(torrent) = self.app_state.torrents.get_mut(&info_hash) {
+                                torrent.latest_state.torrent_control_state = TorrentControlState::Paused;
+                                let torrent_name = torrent.latest_state.torrent_name.clone();
+                                self.push_status_message(format!("'{}' reached its download cap and was paused", torrent_name));
+                            }
+                        }
+                        ManagerEvent::SourcesAdded { info_hash, trackers, web_seeds } => {
+                            if let Some(torrent) = self.app_state.torrents.get_mut(&info_hash) {
+                                let torrent_name = torrent.latest_state.name().to_string();
+                                let message = if trackers.is_empty() && web_seeds.is_empty() {
+                                    format!("'{}' is already added, with the same trackers", torrent_name)
+                                } else {
+                                    format!("'{}' is already added; merged {} new trackers and {} web seeds into it", torrent_name, trackers.len(), web_seeds.len())
+                                };
+                                torrent.latest_state.extra_trackers.extend(trackers);
+                                torrent.latest_state.extra_web_seeds.extend(web_seeds);
+                                self.push_status_message(message);
+                            }
+                        }
+                        ManagerEvent::ExistingFilesFound { info_hash, renames } => {
+                            if let Some(torrent) = self.app_state.torrents.get_mut(&info_hash) {
+                                let message = format!("Found {} existing files of '{}' under another folder and seeding them from there", renames.len(), torrent.latest_state.name());
+                                for rename in renames {
+                                    torrent.latest_state.file_renames.retain(|r| r.index != rename.index);
+                                    torrent.latest_state.file_renames.push(rename);
+                                }
+                                self.push_status_message(message);
+                            }
+                        }
+                        ManagerEvent::FileRenamed { info_hash, result } => {
+                            if let Some(torrent) = self.app_state.torrents.get_mut(&info_hash) {
+                                let torrent_name = torrent.latest_state.name().to_string();
+                                match result {
+                                    Ok(rename) => {
+                                        let message = format!("Renamed a file of '{}' to {}", torrent_name, rename.path.display());
+                                        torrent.latest_state.file_renames.retain(|r| r.index != rename.index);
+                                        torrent.latest_state.file_renames.push(rename);
+                                        self.push_status_message(message);
+                                    }
+                                    Err(e) => {
+                                        self.push_status_message(format!("Could not rename a file of '{}': {}", torrent_name, e));
+                                    }
+                                }
+                            }
+                        }
+                        ManagerEvent::FilesMoved { info_hash, result } => {
+                            if let Some(torrent) = self.app_state.torrents.get_mut(&info_hash) {
+                                let torrent_name = torrent.latest_state.name().to_string();
+                                match result {
+                                    Ok(download_path) => {
+                                        torrent.latest_state.completed_path = None;
+                                        let message = format!("Moved '{}' to {}", torrent_name, download_path.display());
+                                        torrent.latest_state.download_path = download_path;
+                                        self.push_status_message(message);
+                                    }
+                                    Err(e) => {
+                                        tracing_event!(Level::ERROR, "Could not move '{}': {}", torrent_name, e);
+                                        self.push_status_message(format!("Could not move '{}': {}", torrent_name, e));
+                                    }
+                                }
+                            }
+                        }
                     }
                 }
 
@@ -929,7 +1608,14 @@ impl App {
                     self.app_state.session_total_downloaded += message.bytes_downloaded_this_tick;
                     self.app_state.session_total_uploaded += message.bytes_uploaded_this_tick;
 
+                    let info_hash = message.info_hash.clone();
                     let display_state = self.app_state.torrents.entry(message.info_hash).or_default();
+                    let was_complete = display_state.latest_state.number_of_pieces_total > 0
+                        && display_state.latest_state.number_of_pieces_completed
+                            >= display_state.latest_state.number_of_pieces_total;
+                    if message.bytes_downloaded_this_tick > 0 {
+                        display_state.downloaded_this_session = true;
+                    }
 
                     display_state.latest_state.number_of_successfully_connected_peers = message.number_of_successfully_connected_peers;
                     display_state.latest_state.number_of_pieces_total = message.number_of_pieces_total;
@@ -938,6 +1624,12 @@ impl App {
                     display_state.latest_state.upload_speed_bps = message.upload_speed_bps;
                     display_state.latest_state.eta = message.eta;
                     display_state.latest_state.next_announce_in = message.next_announce_in;
+                    display_state.latest_state.next_dht_refresh_in = message.next_dht_refresh_in;
+                    display_state.latest_state.trackers = message.trackers;
+                    display_state.latest_state.swarm = message.swarm;
+                    display_state.latest_state.dht_peers = message.dht_peers;
+                    display_state.latest_state.dht_swarm = message.dht_swarm;
+                    display_state.latest_state.is_private = message.is_private;
 
                     // Also update the name if the manager discovered it from metadata
                     if !message.torrent_name.is_empty() {
@@ -945,6 +1637,11 @@ impl App {
                     }
                     display_state.latest_state.total_size = message.total_size;
                     display_state.latest_state.bytes_written = message.bytes_written;
+                    display_state.latest_state.wasted_bytes = message.wasted_bytes;
+                    display_state.latest_state.file_priorities = message.file_priorities;
+                    display_state.latest_state.files = message.files;
+                    display_state.latest_state.total_downloaded += message.bytes_downloaded_this_tick;
+                    display_state.latest_state.total_uploaded += message.bytes_uploaded_this_tick;
 
                     display_state.download_history.push(display_state.latest_state.download_speed_bps);
                     display_state.upload_history.push(display_state.latest_state.upload_speed_bps);
@@ -961,10 +1658,53 @@ impl App {
 
                     display_state.smoothed_download_speed_bps = display_state.latest_state.download_speed_bps;
                     display_state.smoothed_upload_speed_bps = display_state.latest_state.upload_speed_bps;
+                    if display_state.smoothed_download_speed_bps > 0
+                        || display_state.smoothed_upload_speed_bps > 0
+                        || display_state.last_active_at.is_none()
+                    {
+                        display_state.last_active_at = Some(Instant::now());
+                    }
                     display_state.latest_state.peers = message.peers;
+                    if let Some(geoip) = &self.app_state.geoip {
+                        for peer in &mut display_state.latest_state.peers {
+                            peer.country = peer_ip(&peer.address)
+                                .and_then(|ip| geoip.lookup_label(ip.to_canonical()));
+                        }
+                    }
 
                     display_state.latest_state.activity_message = message.activity_message;
-
+                    display_state.latest_state.is_queued_for_validation = message.is_queued_for_validation;
+
+                    let is_complete = message.number_of_pieces_total > 0
+                        && message.number_of_pieces_completed >= message.number_of_pieces_total;
+                    let just_completed = !was_complete
+                        && display_state.downloaded_this_session
+                        && is_complete;
+
+                    let is_seeding = is_complete
+                        && display_state.load_error.is_none()
+                        && display_state.latest_state.torrent_control_state == TorrentControlState::Running;
+                    let now = Instant::now();
+                    if let Some(since) = display_state.seeding_since.filter(|_| is_seeding) {
+                        display_state.latest_state.seeding_time += now.saturating_duration_since(since);
+                    }
+                    display_state.seeding_since = is_seeding.then_some(now);
+                    let goals = display_state
+                        .latest_state
+                        .seed_goals
+                        .as_ref()
+                        .unwrap_or(&self.client_configs.seed_goals);
+                    let goals_reached = goals.reached(
+                        display_state.latest_state.seed_ratio(),
+                        display_state.latest_state.seeding_time.as_secs(),
+                    );
+                    if !goals_reached {
+                        self.app_state.seed_goals_fired.remove(&info_hash);
+                    }
+                    let seed_goal_action = (is_seeding
+                        && goals_reached
+                        && !self.app_state.seed_goals_fired.contains(&info_hash))
+                    .then_some(goals.action);
 
                     let current_swarm_availability = aggregate_peers_to_availability(
                         &display_state.latest_state.peers,
@@ -980,6 +1720,14 @@ impl App {
 
                     self.sort_and_filter_torrent_list();
                     self.app_state.ui_needs_redraw = true;
+                    if just_completed && self.client_configs.bell_on_complete {
+                        self.ring_bell();
+                    }
+                    if let Some(action) = seed_goal_action {
+                        self.seed_goals_reached(&info_hash, action).await;
+                    } else if self.app_state.seed_goal_deadlines.remove(&info_hash).is_some() {
+                        self.close_seed_goal_prompt(&info_hash);
+                    }
 
                         }
                         Err(broadcast::error::RecvError::Lagged(n)) => {
@@ -994,19 +1742,21 @@ impl App {
                     match command {
                         AppCommand::AddTorrentFromFile(path) => {
                             // All state mutation happens here, in the main task.
-                            if let Some(download_path) = &self.client_configs.default_download_folder {
+                            if let Some(download_path) = self.client_configs.download_folder_for(&path) {
 
-                                self.add_torrent_from_file(
+                                let added = self.add_torrent_from_file(
                                     path.to_path_buf(),
-                                    download_path.to_path_buf(),
+                                    download_path,
                                     false,
                                     TorrentControlState::Running
                                 ).await;
+                                self.decline_duplicate_sources();
+                                self.set_watch_folder_category(&path, added);
 
                                 // Move or rename file for it not to reprocess.
-                                let move_successful = if let Some(watch_folder) = &self.client_configs.watch_folder {
+                                let move_successful = if let Some(rule) = self.client_configs.watch_folder_rule_for(&path) {
                                     (|| {
-                                        let parent_dir = watch_folder.parent()?;
+                                        let parent_dir = rule.path.parent()?;
                                         let processed_folder = parent_dir.join("processed_torrents");
                                         fs::create_dir_all(&processed_folder).ok()?;
 
@@ -1032,35 +1782,16 @@ impl App {
 
                             } else {
                                 self.app_state.pending_torrent_path = Some(path.clone());
-                                if let Ok(mut explorer) = FileExplorer::new() {
-                                    let initial_path = self
-                                        .find_most_common_download_path()
-                                        .or_else(|| UserDirs::new().map(|ud| ud.home_dir().to_path_buf()));
-                                    if let Some(common_path) = initial_path {
-                                        explorer.set_cwd(common_path).ok();
-                                    }
+                                if let Ok(explorer) = self.new_file_explorer(Theme::default(), None) {
+                                    self.app_state.mode = AppMode::DownloadPathPicker(explorer);
                                 }
                             }
                         }
                         AppCommand::AddTorrentFromPathFile(path) => {
                             if let Some((_, processed_path)) = get_watch_path() {
                                 match fs::read_to_string(&path) {
-                                    Ok(torrent_file_path_str) => {
-                                        let torrent_file_path = PathBuf::from(torrent_file_path_str.trim());
-                                        if let Some(download_path) = self.client_configs.default_download_folder.clone() {
-                                            self.add_torrent_from_file(torrent_file_path, download_path, false, TorrentControlState::Running).await;
-                                        } else {
-                                            self.app_state.pending_torrent_path = Some(torrent_file_path);
-                                            if let Ok(mut explorer) = FileExplorer::new() {
-                                                let initial_path = self
-                                                    .find_most_common_download_path()
-                                                    .or_else(|| UserDirs::new().map(|ud| ud.home_dir().to_path_buf()));
-                                                if let Some(common_path) = initial_path {
-                                                    explorer.set_cwd(common_path).ok();
-                                                }
-                                                self.app_state.mode = AppMode::DownloadPathPicker(explorer);
-                                            }
-                                        }
+                                    Ok(content) => {
+                                        self.add_from_watch_file(&path, &content).await;
                                     }
                                     Err(e) => {
                                         tracing_event!(Level::ERROR, "Failed to read torrent path from file {:?}: {}", &path, e);
@@ -1080,19 +1811,10 @@ impl App {
                             // This now uses the consolidated processed_path
                             if let Some((_, processed_path)) = get_watch_path() {
                                 match fs::read_to_string(&path) {
-                                                                         Ok(magnet_link) => {
-                                                                            if let Some(download_path) = self.client_configs.default_download_folder.clone() {
-                                                                                self.add_magnet_torrent("Fetching name...".to_string(), magnet_link.trim().to_string(), download_path, false, TorrentControlState::Running).await;
-                                                                            } else if let Ok(mut explorer) = FileExplorer::new() {
-                                                                                    let initial_path = self
-                                                                                        .find_most_common_download_path()
-                                                                                        .or_else(|| UserDirs::new().map(|ud| ud.home_dir().to_path_buf()));
-                                                                                    if let Some(common_path) = initial_path {
-                                                                                        explorer.set_cwd(common_path).ok();
-                                                                                    }
-                                                                                    self.app_state.mode = AppMode::DownloadPathPicker(explorer);
-                                                                            }
-                                                                        }                                    Err(e) => {
+                                    Ok(content) => {
+                                        self.add_from_watch_file(&path, &content).await;
+                                    }
+                                    Err(e) => {
                                         tracing_event!(Level::ERROR, "Failed to read magnet file {:?}: {}", &path, e);
                                     }
                                 }
@@ -1109,6 +1831,32 @@ impl App {
                                 tracing_event!(Level::ERROR, "Could not get system watch paths for magnet processing.");
                             }
                         }
+                        #[cfg(unix)]
+                        AppCommand::Control(command) => {
+                            self.handle_control_command(command).await;
+                        }
+                        #[cfg(unix)]
+                        AppCommand::ReloadSettings => {
+                            self.reload_settings(&mut watcher).await;
+                        }
+                        AppCommand::IpFilterLoaded(ip_filter) => {
+                            self.apply_ip_filter(ip_filter);
+                        }
+                        AppCommand::ExternalIpReported(ip, source) => {
+                            self.report_external_ip(ip, source).await;
+                        }
+                        AppCommand::PortChecked(port, result) => {
+                            self.finish_port_check(port, result);
+                        }
+                        AppCommand::TorrentCreated { torrent_path, seed_from, result } => {
+                            self.finish_torrent_creation(torrent_path, seed_from, result).await;
+                        }
+                        AppCommand::Stream(info_hash, command) => {
+                            // An unknown torrent drops the reply sender, which the server reads as a 404.
+                            if let Some(manager_tx) = self.torrent_manager_command_txs.get(&info_hash) {
+                                let _ = manager_tx.try_send(command);
+                            }
+                        }
                         AppCommand::ClientShutdown(path) => {
                             tracing_event!(Level::INFO, "Shutdown command received via command file.");
                             self.app_state.should_quit = true;
@@ -1130,12 +1878,14 @@ impl App {
                                             );
 
                                             // Attempt to bind to the new port
-                                            match tokio::net::TcpListener::bind(format!("0.0.0.0:{}", new_port)).await {
-                                                Ok(new_listener) => {
+                                            match bind_peer_listeners(&self.client_configs, new_port).await {
+                                                Ok((new_listener, new_listener_v6)) => {
                                                     // Success! Replace the old listener.
                                                     // The old one is dropped, closing the old socket.
                                                     self.listener = new_listener;
+                                                    self.listener_v6 = new_listener_v6;
                                                     self.client_configs.client_port = new_port;
+                                                    self.next_port_check_at = Some(Instant::now());
 
                                                     tracing_event!(Level::INFO, "Successfully bound to new port {}", new_port);
 
@@ -1143,23 +1893,16 @@ impl App {
                                                         let _ = manager_tx.try_send(ManagerCommand::UpdateListenPort(new_port));
                                                     }
 
+                                                    if self.client_configs.peer_transport.uses_utp() {
+                                                        self.rebind_utp(new_port).await;
+                                                    }
+
                                                     #[cfg(feature = "dht")]
                                                     {
                                                         tracing::event!(Level::INFO, "Rebinding DHT server to new port...");
-                                                        let bootstrap_nodes: Vec<&str> = self.client_configs
-                                                            .bootstrap_nodes
-                                                            .iter()
-                                                            .map(AsRef::as_ref)
-                                                            .collect();
-
-                                                        match Dht::builder()
-                                                            .bootstrap(&bootstrap_nodes)
-                                                            .port(new_port)
-                                                            .server_mode()
-                                                            .build()
-                                                        {
-                                                            Ok(new_dht_server) => {
-                                                                let new_dht_handle = new_dht_server.as_async();
+                                                        let known_nodes = self.distributed_hash_table.to_bootstrap().await;
+                                                        match build_dht(&self.client_configs, new_port, &known_nodes) {
+                                                            Ok(new_dht_handle) => {
                                                                 self.distributed_hash_table = new_dht_handle.clone();
 
                                                                 // 3. Tell all managers to use the new handle
@@ -1201,6 +1944,7 @@ impl App {
 
                 Some(event) = self.tui_event_rx.recv() => {
                     tui_events::handle_event(event, self).await;
+                    self.offer_duplicate_sources();
                 }
 
                 Some(result) = notify_rx.recv() => {
@@ -1267,6 +2011,53 @@ impl App {
 
                    self.app_state.throbber_holder.borrow_mut().torrent_sparkline.calc_next();
 
+                    let message_count = self.app_state.status_messages.len();
+                    self.app_state
+                        .status_messages
+                        .retain(|message| message.created_at.elapsed() < STATUS_MESSAGE_LIFETIME);
+                    if self.app_state.status_messages.len() != message_count {
+                        self.app_state.ui_needs_redraw = true;
+                    }
+
+                    self.retry_due_failed_torrents().await;
+                    self.apply_schedule().await;
+                    self.update_queue().await;
+
+                    let autosave_interval = self.client_configs.autosave_interval_secs;
+                    if autosave_interval > 0
+                        && self
+                            .app_state
+                            .last_saved_at
+                            .get_or_insert_with(Instant::now)
+                            .elapsed()
+                            >= Duration::from_secs(autosave_interval)
+                    {
+                        match self.save_state() {
+                            Ok(()) => tracing_event!(Level::DEBUG, "Autosaved settings."),
+                            Err(e) => tracing_event!(Level::WARN, "Autosave failed: {}", e),
+                        }
+                    }
+
+                    self.app_state.banned_peers = self.ban_list.active_count();
+
+                    if self.next_port_check_at.is_some_and(|at| Instant::now() >= at) {
+                        if let Err(e) = self.start_port_check() {
+                            tracing_event!(Level::DEBUG, "Port check postponed: {}", e);
+                        }
+                    }
+
+                    #[cfg(feature = "dht")]
+                    if self.app_state.run_time.is_multiple_of(DHT_STATUS_REFRESH_SECS) {
+                        self.refresh_dht_status().await;
+                    }
+
+                    if self.app_state.system_error != self.app_state.last_bell_error {
+                        self.app_state.last_bell_error = self.app_state.system_error.clone();
+                        if self.app_state.system_error.is_some() && self.client_configs.bell_on_error {
+                            self.ring_bell();
+                        }
+                    }
+
                     if matches!(self.app_state.mode, AppMode::PowerSaving) && !self.app_state.run_time.is_multiple_of(5) {
                         self.app_state.run_time += 1;
                         continue;
@@ -1280,6 +2071,22 @@ impl App {
                         }
                     };
 
+                    let watched_processes = &self.client_configs.pause_when_process_running;
+                    if (!watched_processes.is_empty() || self.app_state.process_pause.is_some())
+                        && self.app_state.run_time.is_multiple_of(5)
+                    {
+                        sys.refresh_processes(sysinfo::ProcessesToUpdate::All, true);
+                        let running = watched_processes
+                            .iter()
+                            .find(|wanted| {
+                                sys.processes()
+                                    .values()
+                                    .any(|process| process_name_matches(process.name(), wanted))
+                            })
+                            .cloned();
+                        self.apply_process_pause(running).await;
+                    }
+
                     sys.refresh_cpu_usage();
                     sys.refresh_memory();
                     sys.refresh_processes(sysinfo::ProcessesToUpdate::Some(&[pid]), true);
@@ -1291,6 +2098,9 @@ impl App {
                         self.app_state.ram_usage_percent = (process.memory() as f32 / sys.total_memory() as f32) * 100.0;
                         self.app_state.run_time = process.run_time();
                     }
+                    // Hashing runs on the blocking pool, so it is already part of the process CPU above.
+                    self.app_state.hash_threads = self.hash_pool.threads();
+                    self.app_state.hash_threads_busy = self.hash_pool.busy_threads();
 
 
                     // --- Calculate all thrash scores ---
@@ -1478,7 +2288,8 @@ impl App {
                         relevant_history.iter().sum::<u64>() / relevant_history.len() as u64
                     };
                     let current_scpb = self.app_state.global_disk_thrash_score;
-                    let scpb_max = self.app_state.adaptive_max_scpb;
+                    let tuning = TuningProfile::for_profile(self.client_configs.performance_profile);
+                    let scpb_max = self.app_state.adaptive_max_scpb * tuning.thrash_tolerance;
                     let penalty_factor = (current_scpb / scpb_max - 1.0).max(0.0);
                     let new_score = (new_raw_score as f64 / (1.0 + penalty_factor)) as u64;
                     self.app_state.current_tuning_score = new_score;
@@ -1518,7 +2329,7 @@ impl App {
                             .await;
                     }
 
-                    let (next_limits, desc) = make_random_adjustment(self.app_state.limits.clone());
+                    let (next_limits, desc) = make_random_adjustment(self.app_state.limits.clone(), &tuning);
                     self.app_state.limits = next_limits;
 
                     tracing_event!(Level::DEBUG, "Self-Tune: Trying next change... {}", desc);
@@ -1529,59 +2340,30 @@ impl App {
 
                 _ = draw_interval.tick() => {
                     if self.app_state.ui_needs_redraw {
-                        terminal.draw(|f| {
-                            tui::draw(f, &self.app_state, &self.client_configs);
-                        })?;
+                        if let Some(terminal) = terminal.as_mut() {
+                            terminal.draw(|f| {
+                                tui::draw(f, &self.app_state, &self.client_configs);
+                            })?;
+                        }
                         self.app_state.ui_needs_redraw = false;
                     }
                 }
             }
         }
 
-        let _ = self.shutdown_tx.send(());
+        self.client_configs = self.settings_snapshot();
+        save_settings(&self.client_configs)?;
 
-        self.client_configs.lifetime_downloaded += self.app_state.session_total_downloaded;
-        self.client_configs.lifetime_uploaded += self.app_state.session_total_uploaded;
-        self.client_configs.torrent_sort_column = self.app_state.torrent_sort.0;
-        self.client_configs.torrent_sort_direction = self.app_state.torrent_sort.1;
-        self.client_configs.peer_sort_column = self.app_state.peer_sort.0;
-        self.client_configs.peer_sort_direction = self.app_state.peer_sort.1;
-
-        let old_validation_statuses: HashMap<String, bool> = self
-            .client_configs
-            .torrents
-            .iter()
-            .map(|cfg| (cfg.torrent_or_magnet.clone(), cfg.validation_status))
-            .collect();
-
-        self.client_configs.torrents = self
-            .app_state
-            .torrents
-            .values()
-            .map(|torrent| {
-                let torrent_state = &torrent.latest_state;
-
-                let is_complete = torrent_state.number_of_pieces_total > 0
-                    && torrent_state.number_of_pieces_total
-                        == torrent_state.number_of_pieces_completed;
-
-                let old_status = old_validation_statuses
-                    .get(&torrent_state.torrent_or_magnet)
-                    .cloned()
-                    .unwrap_or(false);
-
-                let final_validation_status = if is_complete { true } else { old_status };
-
-                TorrentSettings {
-                    torrent_or_magnet: torrent_state.torrent_or_magnet.clone(),
-                    name: torrent_state.torrent_name.clone(),
-                    validation_status: final_validation_status,
-                    download_path: torrent_state.download_path.clone(),
-                    torrent_control_state: torrent_state.torrent_control_state.clone(),
-                }
-            })
-            .collect();
-        save_settings(&self.client_configs)?;
+        #[cfg(feature = "dht")]
+        if self.client_configs.dht_mode != DhtMode::Off {
+            // An empty table (no network this run) shouldn't replace the saved one.
+            let nodes = self.distributed_hash_table.to_bootstrap().await;
+            if !nodes.is_empty() {
+                if let Err(e) = save_dht_nodes(&nodes) {
+                    tracing_event!(Level::WARN, "Failed to save DHT nodes: {}", e);
+                }
+            }
+        }
 
         let total_managers_to_shut_down = self.torrent_manager_command_txs.len();
         let mut managers_shut_down = 0;
@@ -1591,10 +2373,11 @@ impl App {
         }
 
         if total_managers_to_shut_down == 0 {
+            let _ = self.shutdown_tx.send(());
             return Ok(());
         }
 
-        let shutdown_timeout = time::sleep(Duration::from_secs(5));
+        let shutdown_timeout = time::sleep(SHUTDOWN_TIMEOUT);
         let mut draw_interval = time::interval(Duration::from_millis(100));
         tokio::pin!(shutdown_timeout);
 
@@ -1607,9 +2390,11 @@ impl App {
         loop {
             self.app_state.shutdown_progress =
                 managers_shut_down as f64 / total_managers_to_shut_down as f64;
-            terminal.draw(|f| {
-                tui::draw(f, &self.app_state, &self.client_configs);
-            })?;
+            if let Some(terminal) = terminal.as_mut() {
+                terminal.draw(|f| {
+                    tui::draw(f, &self.app_state, &self.client_configs);
+                })?;
+            }
 
             tokio::select! {
                 Some(event) = self.manager_event_rx.recv() => {
@@ -1635,16 +2420,29 @@ impl App {
             }
         }
 
+        // The resource manager stays up until now so pending piece writes can still get
+        // disk permits while the torrents shut down.
+        let _ = self.shutdown_tx.send(());
+
         self.app_state.shutdown_progress = 1.0;
-        terminal.draw(|f| {
-            tui::draw(f, &self.app_state, &self.client_configs);
-        })?;
+        if let Some(terminal) = terminal.as_mut() {
+            terminal.draw(|f| {
+                tui::draw(f, &self.app_state, &self.client_configs);
+            })?;
+        }
 
         Ok(())
     }
 
     pub fn sort_and_filter_torrent_list(&mut self) {
         let torrents_map = &self.app_state.torrents;
+        let queue_positions: HashMap<&Vec<u8>, usize> = self
+            .app_state
+            .torrent_queue
+            .iter()
+            .enumerate()
+            .map(|(position, info_hash)| (info_hash, position))
+            .collect();
         let (sort_by, sort_direction) = self.app_state.torrent_sort;
         let search_query = &self.app_state.search_query;
 
@@ -1656,12 +2454,21 @@ impl App {
             torrent_list.retain(|info_hash| {
                 let torrent_name = torrents_map
                     .get(info_hash)
-                    .map_or("", |t| &t.latest_state.torrent_name);
+                    .map_or("", |t| t.latest_state.name());
 
                 matcher.fuzzy_match(torrent_name, search_query).is_some()
             });
         }
 
+        if let Some(category) = &self.app_state.category_filter {
+            torrent_list.retain(|info_hash| {
+                torrents_map
+                    .get(info_hash)
+                    .and_then(|t| t.latest_state.category.as_ref())
+                    == Some(category)
+            });
+        }
+
         torrent_list.sort_by(|a_info_hash, b_info_hash| {
             let Some(a_torrent) = torrents_map.get(a_info_hash) else {
                 return std::cmp::Ordering::Equal;
@@ -1671,51 +2478,2208 @@ impl App {
             };
 
             let ordering = match sort_by {
+                // Front of the queue first, like the fastest torrent first for speeds
+                TorrentSortColumn::Queue => {
+                    let a_position = queue_positions.get(a_info_hash).copied();
+                    let b_position = queue_positions.get(b_info_hash).copied();
+                    a_position
+                        .unwrap_or(usize::MAX)
+                        .cmp(&b_position.unwrap_or(usize::MAX))
+                }
                 TorrentSortColumn::Name => a_torrent
                     .latest_state
-                    .torrent_name
-                    .cmp(&b_torrent.latest_state.torrent_name),
+                    .name()
+                    .cmp(b_torrent.latest_state.name()),
+                TorrentSortColumn::Category => a_torrent
+                    .latest_state
+                    .category
+                    .cmp(&b_torrent.latest_state.category),
                 TorrentSortColumn::Down => b_torrent
                     .smoothed_download_speed_bps
                     .cmp(&a_torrent.smoothed_download_speed_bps),
                 TorrentSortColumn::Up => b_torrent
                     .smoothed_upload_speed_bps
                     .cmp(&a_torrent.smoothed_upload_speed_bps),
+                TorrentSortColumn::Ratio => {
+                    let ratio = |torrent: &TorrentDisplayState| {
+                        share_ratio(
+                            torrent.latest_state.total_uploaded,
+                            torrent.latest_state.total_downloaded,
+                        )
+                        .unwrap_or(-1.0)
+                    };
+                    ratio(b_torrent).total_cmp(&ratio(a_torrent))
+                }
+            };
+
+            let default_direction = match sort_by {
+                TorrentSortColumn::Name => SortDirection::Ascending,
+                _ => SortDirection::Descending,
             };
 
-            let default_direction = match sort_by {
-                TorrentSortColumn::Name => SortDirection::Ascending,
-                _ => SortDirection::Descending,
-            };
+            let ordering = if sort_direction != default_direction {
+                ordering.reverse()
+            } else {
+                ordering
+            };
+            // Ties go to whichever torrent moved data most recently.
+            ordering.then_with(|| b_torrent.last_active_at.cmp(&a_torrent.last_active_at))
+        });
+
+        self.app_state.torrent_list_order = torrent_list;
+
+        if self.app_state.selected_torrent_index >= self.app_state.torrent_list_order.len() {
+            self.app_state.selected_torrent_index =
+                self.app_state.torrent_list_order.len().saturating_sub(1);
+        }
+    }
+
+    pub fn find_most_common_download_path(&mut self) -> Option<PathBuf> {
+        let mut counts: HashMap<PathBuf, usize> = HashMap::new();
+
+        for state in self.app_state.torrents.values() {
+            if let Some(parent_path) = state.latest_state.download_path.parent() {
+                *counts.entry(parent_path.to_path_buf()).or_insert(0) += 1;
+            }
+        }
+
+        counts
+            .into_iter()
+            .max_by_key(|&(_, count)| count)
+            .map(|(path, _)| path)
+    }
+
+    /// Tells the torrent's manager to shut down (deleting its files if asked) and marks
+    /// it as deleting until the manager reports back.
+    pub fn delete_torrent(&mut self, info_hash: &[u8], with_files: bool) {
+        let command = if with_files {
+            ManagerCommand::DeleteFile
+        } else {
+            ManagerCommand::Shutdown
+        };
+        if let Some(manager_tx) = self.torrent_manager_command_txs.get(info_hash) {
+            let manager_tx_clone = manager_tx.clone();
+            tokio::spawn(async move {
+                let _ = manager_tx_clone.send(command).await;
+            });
+        } else {
+            // Torrents that failed to load have no manager; just drop the entry.
+            let manager_event_tx = self.manager_event_tx.clone();
+            let info_hash = info_hash.to_vec();
+            tokio::spawn(async move {
+                let _ = manager_event_tx
+                    .send(ManagerEvent::DeletionComplete(info_hash, Ok(())))
+                    .await;
+            });
+        }
+        if let Some(torrent) = self.app_state.torrents.get_mut(info_hash) {
+            torrent.latest_state.torrent_control_state = TorrentControlState::Deleting;
+        }
+    }
+
+    /// Re-reads settings.toml after a SIGHUP. Limits, the schedule, queue slots, seed
+    /// goals, watch folders, the download folder, the log level, watched processes and
+    /// display options apply at once; anything that needs a restart is logged and left
+    /// as it is. Torrents and lifetime stats stay as they are in memory, since they are
+    /// only written back on exit.
+    #[cfg(unix)]
+    async fn reload_settings(&mut self, watcher: &mut RecommendedWatcher) {
+        tracing_event!(Level::INFO, "SIGHUP received. Reloading settings.");
+        // A broken settings.toml must not reset everything to the defaults
+        let new = match try_load_settings() {
+            Ok(new) => new,
+            Err(e) => {
+                tracing_event!(Level::ERROR, "Could not reload settings: {}", e);
+                self.app_state.system_error = Some(format!(
+                    "Could not reload settings.toml, keeping the current settings: {}",
+                    e
+                ));
+                return;
+            }
+        };
+        let old = &mut self.client_configs;
+
+        let limits_changed = new.rate_limits(RateProfile::Normal)
+            != old.rate_limits(RateProfile::Normal)
+            || new.rate_limits(RateProfile::Alternative)
+                != old.rate_limits(RateProfile::Alternative);
+        old.global_download_limit_bps = new.global_download_limit_bps;
+        old.global_upload_limit_bps = new.global_upload_limit_bps;
+        old.alt_download_limit_bps = new.alt_download_limit_bps;
+        old.alt_upload_limit_bps = new.alt_upload_limit_bps;
+        // A changed schedule takes effect on the next stats tick
+        old.schedule = new.schedule.clone();
+        old.schedule_utc_offset_minutes = new.schedule_utc_offset_minutes;
+        if limits_changed {
+            let (dl_limit, ul_limit) = new.rate_limits(self.app_state.rate_profile);
+            self.global_dl_bucket.lock().await.set_rate(dl_limit as f64);
+            self.global_ul_bucket.lock().await.set_rate(ul_limit as f64);
+        }
+
+        if new.connection_attempt_permits != old.connection_attempt_permits {
+            old.connection_attempt_permits = new.connection_attempt_permits;
+            let permits = new.connection_attempt_permits.max(1);
+            // Self-tune reverts to last_tuning_limits, which must not bring back the old cap
+            self.app_state.limits.connection_attempt_permits = permits;
+            self.app_state.last_tuning_limits.connection_attempt_permits = permits;
+            let _ = self
+                .resource_manager
+                .update_limits(HashMap::from([(ResourceType::ConnectionAttempt, permits)]))
+                .await;
+        }
+
+        if new.watch_folders != old.watch_folders {
+            for rule in &old.watch_folders {
+                let _ = watcher.unwatch(&rule.path);
+            }
+            for rule in &new.watch_folders {
+                match watcher.watch(&rule.path, RecursiveMode::NonRecursive) {
+                    Ok(()) => {
+                        tracing_event!(Level::INFO, "Watching user path: {:?}", rule.path)
+                    }
+                    Err(e) => tracing_event!(
+                        Level::ERROR,
+                        "Failed to watch user path {:?}: {}",
+                        rule.path,
+                        e
+                    ),
+                }
+            }
+            old.watch_folders = new.watch_folders.clone();
+        }
+
+        if new.log_level != old.log_level {
+            old.log_level = new.log_level;
+            logging::set_level(new.log_level);
+        }
+
+        let folder_changed = new.default_download_folder != old.default_download_folder;
+        old.default_download_folder = new.default_download_folder.clone();
+        old.dim_inactive_torrents = new.dim_inactive_torrents;
+        old.inactive_torrent_threshold_secs = new.inactive_torrent_threshold_secs;
+        old.confirm_delete = new.confirm_delete;
+        old.confirm_delete_with_files = new.confirm_delete_with_files;
+        old.confirm_quit = new.confirm_quit;
+        old.show_clock = new.show_clock;
+        old.bell_on_complete = new.bell_on_complete;
+        old.bell_on_error = new.bell_on_error;
+        old.autosave_interval_secs = new.autosave_interval_secs;
+        old.max_active_downloads = new.max_active_downloads;
+        old.max_active_seeds = new.max_active_seeds;
+        old.seed_goals = new.seed_goals.clone();
+        old.seed_goal_grace_secs = new.seed_goal_grace_secs;
+        old.torrent_columns = new.torrent_columns.clone();
+        old.peer_columns = new.peer_columns.clone();
+        old.pause_when_process_running = new.pause_when_process_running.clone();
+
+        let mut needs_restart = Vec::new();
+        if new.client_port != old.client_port {
+            needs_restart.push("client_port");
+        }
+        if new.listen_interface != old.listen_interface {
+            needs_restart.push("listen_interface");
+        }
+        if new.listen_port_range != old.listen_port_range
+            || new.extra_listen_ports != old.extra_listen_ports
+        {
+            needs_restart.push("listen ports");
+        }
+        if new.control_socket != old.control_socket {
+            needs_restart.push("control_socket");
+        }
+        if new.dht_mode != old.dht_mode || new.bootstrap_nodes != old.bootstrap_nodes {
+            needs_restart.push("DHT settings");
+        }
+        if new.hash_threads != old.hash_threads {
+            needs_restart.push("hash_threads");
+        }
+        if new.streaming_port != old.streaming_port {
+            needs_restart.push("streaming_port");
+        }
+        if new.ip_filter != old.ip_filter
+            || new.ip_filter_refresh_hours != old.ip_filter_refresh_hours
+        {
+            needs_restart.push("ip_filter");
+        }
+        if !needs_restart.is_empty() {
+            tracing_event!(
+                Level::WARN,
+                "Settings changed that need a restart to take effect: {}",
+                needs_restart.join(", ")
+            );
+        }
+
+        if folder_changed {
+            self.check_default_download_folder();
+        }
+        self.push_status_message(if needs_restart.is_empty() {
+            "Settings reloaded.".to_string()
+        } else {
+            format!(
+                "Settings reloaded. Restart to apply: {}",
+                needs_restart.join(", ")
+            )
+        });
+    }
+
+    /// Applies a command that arrived on the control socket.
+    #[cfg(unix)]
+    async fn handle_control_command(&mut self, command: ControlCommand) {
+        match command {
+            ControlCommand::Add(input, options) => {
+                let download_path = self.client_configs.default_download_folder.clone();
+                self.add_with_options(input, options, download_path).await;
+                self.decline_duplicate_sources();
+            }
+            ControlCommand::Pause(info_hash) => self.set_torrent_paused(&info_hash, true).await,
+            ControlCommand::Resume(info_hash) => self.set_torrent_paused(&info_hash, false).await,
+            ControlCommand::PauseAll => self.pause_all().await,
+            ControlCommand::ResumeAll => self.resume_all().await,
+            ControlCommand::Delete(info_hash) => {
+                if self.app_state.torrents.contains_key(&info_hash) {
+                    self.delete_torrent(&info_hash, false);
+                } else {
+                    tracing_event!(
+                        Level::WARN,
+                        "Control command for unknown torrent {}",
+                        hex::encode(&info_hash)
+                    );
+                }
+            }
+            ControlCommand::Cap(info_hash, max_download_bytes) => {
+                self.set_download_cap(&info_hash, max_download_bytes);
+            }
+            ControlCommand::SetFilePriority(info_hash, ranges, priority) => {
+                self.set_file_priority(&info_hash, ranges, priority);
+            }
+            ControlCommand::SeedGoals(info_hash, seed_goals) => {
+                self.set_seed_goals(&info_hash, seed_goals);
+            }
+            ControlCommand::Category(info_hash, category) => {
+                self.set_torrent_category(&info_hash, category);
+            }
+            ControlCommand::Priority(info_hash, priority) => {
+                self.set_bandwidth_priority(&info_hash, priority);
+            }
+            ControlCommand::Dht(info_hash, enabled) => {
+                self.set_peer_discovery(&info_hash, true, enabled);
+            }
+            ControlCommand::Pex(info_hash, enabled) => {
+                self.set_peer_discovery(&info_hash, false, enabled);
+            }
+            ControlCommand::Seed(torrent_path, download_path) => {
+                self.add_torrent_from_file(
+                    torrent_path,
+                    download_path,
+                    false,
+                    TorrentControlState::Running,
+                )
+                .await;
+                self.decline_duplicate_sources();
+            }
+            ControlCommand::Shutdown => {
+                tracing_event!(Level::INFO, "Shutdown command received via control socket.");
+                self.app_state.should_quit = true;
+            }
+        }
+        self.app_state.ui_needs_redraw = true;
+    }
+
+    /// Pauses a torrent, or puts it back in the queue, which resumes it straight away
+    /// if a slot is free.
+    pub async fn set_torrent_paused(&mut self, info_hash: &[u8], paused: bool) {
+        if let (Some(torrent), Some(manager_tx)) = (
+            self.app_state.torrents.get_mut(info_hash),
+            self.torrent_manager_command_txs.get(info_hash),
+        ) {
+            match (&torrent.latest_state.torrent_control_state, paused) {
+                (TorrentControlState::Running, true) => {
+                    torrent.latest_state.torrent_control_state = TorrentControlState::Paused;
+                    let _ = manager_tx.send(ManagerCommand::Pause).await;
+                }
+                // Already paused in the manager
+                (TorrentControlState::Queued, true) => {
+                    torrent.latest_state.torrent_control_state = TorrentControlState::Paused;
+                }
+                (TorrentControlState::Paused, false) => {
+                    torrent.latest_state.torrent_control_state = TorrentControlState::Queued;
+                    self.update_queue().await;
+                }
+                _ => {}
+            }
+            self.app_state.ui_needs_redraw = true;
+        } else {
+            tracing_event!(
+                Level::WARN,
+                "Control command for unknown torrent {}",
+                hex::encode(info_hash)
+            );
+        }
+    }
+
+    /// Shows a torrent under `name`, or with `None` under the name from its metadata
+    /// again. Files on disk keep their names.
+    pub fn rename_torrent(&mut self, info_hash: &[u8], name: Option<String>) {
+        let Some(torrent) = self.app_state.torrents.get_mut(info_hash) else {
+            return;
+        };
+        torrent.latest_state.display_name =
+            name.filter(|name| *name != torrent.latest_state.torrent_name);
+        self.sort_and_filter_torrent_list();
+    }
+
+    /// Moves file `index` (as numbered in the metadata) to `path`, relative to the
+    /// torrent's download folder. The manager reports back with `FileRenamed`.
+    pub fn rename_file(&mut self, info_hash: &[u8], index: usize, path: PathBuf) {
+        if let Some(manager_tx) = self.torrent_manager_command_txs.get(info_hash) {
+            let _ = manager_tx.try_send(ManagerCommand::RenameFile { index, path });
+        }
+    }
+
+    /// Forgets which pieces a torrent has and hashes its files again, e.g. after a disk
+    /// error or after its files were changed by hand.
+    pub async fn force_recheck(&mut self, info_hash: &[u8]) {
+        let (Some(torrent), Some(manager_tx)) = (
+            self.app_state.torrents.get(info_hash),
+            self.torrent_manager_command_txs.get(info_hash),
+        ) else {
+            return;
+        };
+        let torrent_name = torrent.latest_state.torrent_name.clone();
+        let _ = manager_tx.send(ManagerCommand::ForceRecheck).await;
+        self.push_status_message(format!("Rechecking '{}'", torrent_name));
+    }
+
+    /// Puts a torrent's files under `path`, moving them there or, without `move_data`,
+    /// using a copy that is already there. `recheck` hashes them once in place.
+    pub async fn set_location(
+        &mut self,
+        info_hash: &[u8],
+        path: PathBuf,
+        move_data: bool,
+        recheck: bool,
+    ) {
+        let (Some(torrent), Some(manager_tx)) = (
+            self.app_state.torrents.get(info_hash),
+            self.torrent_manager_command_txs.get(info_hash),
+        ) else {
+            return;
+        };
+        let torrent_name = torrent.latest_state.name().to_string();
+        if torrent.latest_state.download_path == path {
+            self.push_status_message(format!("'{}' is already there", torrent_name));
+            return;
+        }
+        let message = if move_data {
+            format!("Moving '{}' to {}", torrent_name, path.display())
+        } else {
+            format!(
+                "Using the files of '{}' in {}",
+                torrent_name,
+                path.display()
+            )
+        };
+        let _ = manager_tx
+            .send(ManagerCommand::SetLocation {
+                path,
+                move_data,
+                recheck,
+            })
+            .await;
+        self.push_status_message(message);
+    }
+
+    /// Announces a running torrent to its trackers and the DHT right away, e.g. once
+    /// a network problem is fixed.
+    pub async fn force_reannounce(&mut self, info_hash: &[u8]) {
+        let (Some(torrent), Some(manager_tx)) = (
+            self.app_state.torrents.get(info_hash),
+            self.torrent_manager_command_txs.get(info_hash),
+        ) else {
+            return;
+        };
+        let torrent_name = torrent.latest_state.torrent_name.clone();
+        if torrent.latest_state.torrent_control_state != TorrentControlState::Running {
+            self.push_status_message(format!(
+                "'{}' isn't running, so it has nothing to announce",
+                torrent_name
+            ));
+            return;
+        }
+        let _ = manager_tx.send(ManagerCommand::ForceReannounce).await;
+        self.push_status_message(format!("Re-announcing '{}'", torrent_name));
+    }
+
+    /// Acts on a torrent that reached its seed goals once `seed_goal_grace_secs` have
+    /// passed. Until then the UI offers to act at once or keep seeding.
+    async fn seed_goals_reached(&mut self, info_hash: &[u8], action: SeedGoalAction) {
+        let now = Instant::now();
+        let deadline = match self.app_state.seed_goal_deadlines.get(info_hash) {
+            Some(deadline) => *deadline,
+            None => {
+                let deadline = now + Duration::from_secs(self.client_configs.seed_goal_grace_secs);
+                self.app_state
+                    .seed_goal_deadlines
+                    .insert(info_hash.to_vec(), deadline);
+                if deadline > now {
+                    if matches!(self.app_state.mode, AppMode::Normal) {
+                        self.app_state.mode = AppMode::SeedGoalConfirm {
+                            info_hash: info_hash.to_vec(),
+                            action,
+                        };
+                    }
+                    if let Some(torrent) = self.app_state.torrents.get(info_hash) {
+                        let message = format!(
+                            "'{}' reached its seed goals and will be {} in {}s",
+                            torrent.latest_state.name(),
+                            action.describe(),
+                            self.client_configs.seed_goal_grace_secs
+                        );
+                        self.push_status_message(message);
+                    }
+                }
+                deadline
+            }
+        };
+        if now >= deadline {
+            self.finish_seeding(info_hash, action).await;
+        }
+    }
+
+    /// Closes the seed goal dialog if it is open for this torrent.
+    fn close_seed_goal_prompt(&mut self, info_hash: &[u8]) {
+        if let AppMode::SeedGoalConfirm {
+            info_hash: shown, ..
+        } = &self.app_state.mode
+        {
+            if shown == info_hash {
+                self.app_state.mode = AppMode::Normal;
+            }
+        }
+    }
+
+    /// Turns off the seed goals of a torrent that reached them, so it seeds on.
+    pub fn keep_seeding(&mut self, info_hash: &[u8]) {
+        self.app_state.seed_goal_deadlines.remove(info_hash);
+        self.close_seed_goal_prompt(info_hash);
+        let Some(torrent) = self.app_state.torrents.get_mut(info_hash) else {
+            return;
+        };
+        torrent.latest_state.seed_goals = Some(SeedGoals::default());
+        let message = format!(
+            "'{}' keeps seeding with its seed goals turned off",
+            torrent.latest_state.name()
+        );
+        self.push_status_message(message);
+    }
+
+    /// Pauses or removes, with or without its files, a torrent that has reached its
+    /// seed goals.
+    pub async fn finish_seeding(&mut self, info_hash: &[u8], action: SeedGoalAction) {
+        self.app_state.seed_goal_deadlines.remove(info_hash);
+        // A torrent resumed after a pause still meets its goals; don't pause it again
+        self.app_state.seed_goals_fired.insert(info_hash.to_vec());
+        self.close_seed_goal_prompt(info_hash);
+        let Some(torrent) = self.app_state.torrents.get(info_hash) else {
+            return;
+        };
+        let torrent_name = torrent.latest_state.torrent_name.clone();
+        tracing_event!(
+            Level::INFO,
+            "'{}' reached its seed goals: {:?}",
+            torrent_name,
+            action
+        );
+        match action {
+            SeedGoalAction::Pause => {
+                self.set_torrent_paused(info_hash, true).await;
+                self.push_status_message(format!(
+                    "'{}' reached its seed goals and was paused",
+                    torrent_name
+                ));
+            }
+            SeedGoalAction::Remove | SeedGoalAction::RemoveWithData => {
+                self.delete_torrent(info_hash, action == SeedGoalAction::RemoveWithData);
+                self.push_status_message(format!(
+                    "'{}' reached its seed goals and was {}",
+                    torrent_name,
+                    action.describe()
+                ));
+            }
+        }
+    }
+
+    /// The selected torrent's peers in peer table order.
+    pub fn selected_torrent_peers(&self) -> Vec<PeerInfo> {
+        self.app_state
+            .torrent_list_order
+            .get(self.app_state.selected_torrent_index)
+            .and_then(|info_hash| self.app_state.torrents.get(info_hash))
+            .map(|torrent| displayed_peers(&torrent.latest_state, self.app_state.peer_sort))
+            .unwrap_or_default()
+    }
+
+    /// Bans the IP of the peer selected in the peer table for `peer_ban_duration_secs`
+    /// and has every torrent drop its connections to it.
+    pub fn ban_selected_peer(&mut self) {
+        let peers = self.selected_torrent_peers();
+        let Some(peer) = peers.get(
+            self.app_state
+                .selected_peer_index
+                .min(peers.len().saturating_sub(1)),
+        ) else {
+            return;
+        };
+        let Some(ip) = peer_ip(&peer.address) else {
+            tracing_event!(Level::WARN, address = %peer.address, "Could not read peer IP to ban.");
+            return;
+        };
+
+        let duration = Duration::from_secs(self.client_configs.peer_ban_duration_secs);
+        self.ban_list.ban(ip, duration);
+        for manager_tx in self.torrent_manager_command_txs.values() {
+            let _ = manager_tx.try_send(ManagerCommand::DisconnectBannedPeers);
+        }
+        self.app_state.banned_peers = self.ban_list.active_count();
+
+        tracing_event!(Level::INFO, %ip, "Banned peer for {:?}.", duration);
+        self.push_status_message(format!(
+            "Banned {} for {}",
+            ip,
+            format_time(duration.as_secs())
+        ));
+    }
+
+    pub fn unban_all_peers(&mut self) {
+        let count = self.ban_list.clear();
+        self.app_state.banned_peers = 0;
+        if count > 0 {
+            tracing_event!(Level::INFO, "Lifted {} peer bans.", count);
+            self.push_status_message(format!("Unbanned {} peers", count));
+        }
+    }
+
+    /// Swaps in a newly loaded IP filter and drops peers it now blocks.
+    fn apply_ip_filter(&mut self, ip_filter: IpFilter) {
+        let ranges = ip_filter.len();
+        self.ban_list.set_ip_filter(ip_filter);
+        for manager_tx in self.torrent_manager_command_txs.values() {
+            let _ = manager_tx.try_send(ManagerCommand::DisconnectBannedPeers);
+        }
+
+        tracing_event!(
+            Level::INFO,
+            "Loaded IP filter with {} blocked ranges.",
+            ranges
+        );
+        self.push_status_message(format!("IP filter: {} blocked ranges", ranges));
+    }
+
+    /// Takes a report of our external address. Once it changes, every torrent
+    /// re-announces and the listeners move to the interface's new address.
+    async fn report_external_ip(&mut self, ip: IpAddr, source: IpSource) {
+        match self.external_ip.report(ip, source) {
+            IpChange::Unchanged => {}
+            IpChange::Learned(ip) => {
+                tracing_event!(Level::INFO, %ip, "Learned external IP.");
+            }
+            IpChange::Changed { old, new } => {
+                tracing_event!(Level::INFO, %old, %new, "External IP changed.");
+                self.push_status_message(format!("External IP changed: {} -> {}", old, new));
+                // Unknown until a peer reaches us at the new address
+                self.app_state.externally_accessable_port = false;
+                self.next_port_check_at = Some(Instant::now());
+                for manager_tx in self.torrent_manager_command_txs.values() {
+                    let _ = manager_tx.try_send(ManagerCommand::ExternalIpChanged(new));
+                }
+                self.rebind_interface_listeners().await;
+            }
+        }
+    }
+
+    /// Tests whether peers can reach `client_port`, through `port_check_url` or by
+    /// connecting to our external IP. Does nothing while a check is running.
+    pub fn start_port_check(&mut self) -> Result<(), PortCheckError> {
+        if self.app_state.port_check_running {
+            return Ok(());
+        }
+        let checker = match (
+            &self.client_configs.port_check_url,
+            self.external_ip.current(),
+        ) {
+            (Some(url), _) => PortChecker::Url(url.clone()),
+            (None, Some(ip)) => PortChecker::SelfConnect(ip),
+            (None, None) => {
+                self.next_port_check_at = Some(Instant::now() + PORT_CHECK_RETRY);
+                return Err(PortCheckError::NoExternalIp);
+            }
+        };
+        self.app_state.port_check_running = true;
+        self.port_check_self_connect = matches!(checker, PortChecker::SelfConnect(_));
+        self.next_port_check_at = None;
+        port_check::spawn_check(
+            checker,
+            self.client_configs.client_port,
+            self.app_command_tx.clone(),
+        );
+        Ok(())
+    }
+
+    fn finish_port_check(&mut self, port: u16, result: Result<bool, PortCheckError>) {
+        self.app_state.port_check_running = false;
+        self.next_port_check_at = self
+            .client_configs
+            .port_check_interval()
+            .map(|interval| Instant::now() + interval);
+        if port != self.client_configs.client_port {
+            // The port changed while this one was checked.
+            self.next_port_check_at = Some(Instant::now());
+            return;
+        }
+        let incoming_peer_seen = std::mem::take(&mut self.incoming_peer_since_port_check);
+        match result {
+            // Connecting to our own address also fails behind routers without hairpin
+            // NAT, so it can't overrule a peer that reached us.
+            Ok(false) if self.port_check_self_connect && incoming_peer_seen => {
+                tracing_event!(
+                    Level::INFO,
+                    port,
+                    "Port check could not connect, but peers have reached us. Keeping the port open."
+                );
+                self.app_state.port_checked_at = Some(Instant::now());
+            }
+            Ok(open) => {
+                tracing_event!(Level::INFO, port, open, "Port check finished.");
+                self.app_state.externally_accessable_port = open;
+                self.app_state.port_checked_at = Some(Instant::now());
+            }
+            Err(e) => {
+                tracing_event!(Level::WARN, port, error = %e, "Port check failed.");
+                self.push_status_message(format!("Port check failed: {}", e));
+            }
+        }
+    }
+
+    /// Makes a torrent in the background and writes it next to the file or folder it
+    /// shares. With `seed` set it is added from there once written.
+    pub fn start_torrent_creation(&mut self, options: CreateOptions, seed: bool) {
+        let Some(name) = options.path.file_name() else {
+            return;
+        };
+        let torrent_path = options
+            .path
+            .with_file_name(format!("{}.torrent", name.to_string_lossy()));
+        let seed_from = options
+            .path
+            .parent()
+            .filter(|_| seed)
+            .map(Path::to_path_buf);
+        self.push_status_message(format!("Creating {}...", torrent_path.display()));
+
+        let app_command_tx = self.app_command_tx.clone();
+        tokio::spawn(async move {
+            let output = torrent_path.clone();
+            let created = tokio::task::spawn_blocking(move || {
+                let bytes = create::create_torrent(&options)?;
+                create::write_torrent(&output, &bytes)
+            })
+            .await;
+            // A panic while hashing is reported too, or the user never hears how
+            // the creation ended.
+            let result = created.unwrap_or_else(|e| Err(e.into()));
+            let _ = app_command_tx
+                .send(AppCommand::TorrentCreated {
+                    torrent_path,
+                    seed_from,
+                    result,
+                })
+                .await;
+        });
+    }
+
+    async fn finish_torrent_creation(
+        &mut self,
+        torrent_path: PathBuf,
+        seed_from: Option<PathBuf>,
+        result: Result<(), CreateTorrentError>,
+    ) {
+        if let Err(e) = result {
+            tracing_event!(Level::ERROR, "Failed to create {:?}: {}", torrent_path, e);
+            self.app_state.system_error = Some(format!("Failed to create torrent: {}", e));
+            return;
+        }
+        tracing_event!(Level::INFO, "Created {:?}", torrent_path);
+        self.push_status_message(format!("Created {}", torrent_path.display()));
+        if let Some(download_path) = seed_from {
+            self.add_torrent_from_file(
+                torrent_path,
+                download_path,
+                false,
+                TorrentControlState::Running,
+            )
+            .await;
+        }
+    }
+
+    /// With `listen_interface` set the peer listeners are bound to the interface's
+    /// addresses, which a VPN reconnect or new DHCP lease can take away. Binds them
+    /// again when the address they are on is gone.
+    async fn rebind_interface_listeners(&mut self) {
+        let Some(listen_interface) = self.client_configs.listen_interface.clone() else {
+            return;
+        };
+        let addresses = interface_addresses(&listen_interface).unwrap_or_default();
+        if self
+            .listener
+            .local_addr()
+            .is_ok_and(|bound| addresses.contains(&bound.ip()))
+        {
+            return;
+        }
+
+        let port = self.client_configs.client_port;
+        match bind_peer_listeners(&self.client_configs, port).await {
+            Ok((listener, listener_v6)) => {
+                self.listener = listener;
+                self.listener_v6 = listener_v6;
+                self.extra_listeners = bind_extra_listeners(&self.client_configs).await;
+                tracing_event!(
+                    Level::INFO,
+                    "Rebound peer listeners on {}.",
+                    listen_interface
+                );
+            }
+            Err(e) => {
+                tracing_event!(
+                    Level::WARN,
+                    "Failed to rebind peer listeners on {}: {}",
+                    listen_interface,
+                    e
+                );
+                return;
+            }
+        }
+        if self.client_configs.peer_transport.uses_utp() {
+            self.rebind_utp(port).await;
+        }
+    }
+
+    /// Binds uTP on `port` again and hands the new socket to every torrent.
+    async fn rebind_utp(&mut self, port: u16) {
+        let (utp_socket, utp_incoming_rx) = bind_utp(&self.client_configs, port).await.unzip();
+        self.utp_incoming_rx = utp_incoming_rx;
+        for manager_tx in self.torrent_manager_command_txs.values() {
+            let _ = manager_tx.try_send(ManagerCommand::UpdateUtpSocket(utp_socket.clone()));
+        }
+        self.utp_socket = utp_socket;
+    }
+
+    /// Reads the handshake from a peer that connected to us, over TCP or uTP, and
+    /// hands the connection to the manager of the torrent it asks for.
+    fn route_incoming_peer(&mut self, mut stream: BoxedPeerStream, addr: SocketAddr) {
+        // IPv4 peers reaching a dual-stack socket show up as `::ffff:a.b.c.d`.
+        let addr = SocketAddr::new(addr.ip().to_canonical(), addr.port());
+        if !self.app_state.externally_accessable_port {
+            self.app_state.externally_accessable_port = true;
+        }
+        self.app_state.port_checked_at = Some(Instant::now());
+        self.incoming_peer_since_port_check = true;
+        if self.ban_list.is_banned(addr.ip()) {
+            tracing_event!(Level::DEBUG, peer = %addr, "Refusing incoming connection from banned peer.");
+            return;
+        }
+
+        let torrent_manager_incoming_peer_txs_clone =
+            self.torrent_manager_incoming_peer_txs.clone();
+        let resource_manager_clone = self.resource_manager.clone();
+        let mut permit_shutdown_rx = self.shutdown_tx.subscribe();
+        let handshake_timeout = self.client_configs.peer_handshake_timeout();
+        tokio::spawn(async move {
+            let _session_permit = tokio::select! {
+                permit_result = resource_manager_clone.acquire_peer_connection() => {
+                    match permit_result {
+                        Ok(permit) => Some(permit),
+                        Err(_) => {
+                            tracing_event!(Level::DEBUG, "Failed to acquire permit. Manager shut down?");
+                            None
+                        }
+                    }
+                }
+                _ = permit_shutdown_rx.recv() => {
+                    None
+                }
+            };
+            let mut buffer = vec![0u8; 68];
+            let handshake = tokio::time::timeout(handshake_timeout, stream.read_exact(&mut buffer));
+            if matches!(handshake.await, Ok(Ok(_))) {
+                let peer_info_hash = &buffer[28..48];
+                if let Some(torrent_manager_tx) =
+                    torrent_manager_incoming_peer_txs_clone.get(peer_info_hash)
+                {
+                    let torrent_manager_tx_clone = torrent_manager_tx.clone();
+                    let _ = torrent_manager_tx_clone.send((stream, addr, buffer)).await;
+                }
+            }
+        });
+    }
+
+    /// Rings the terminal bell, at most once per `BELL_DEBOUNCE` so several torrents
+    /// finishing together make one sound.
+    fn ring_bell(&mut self) {
+        if self
+            .app_state
+            .last_bell_at
+            .is_some_and(|at| at.elapsed() < BELL_DEBOUNCE)
+        {
+            return;
+        }
+        self.app_state.last_bell_at = Some(Instant::now());
+        let mut stdout = std::io::stdout();
+        if let Err(e) = stdout.write_all(b"\x07").and_then(|_| stdout.flush()) {
+            tracing_event!(Level::DEBUG, "Could not ring the terminal bell: {}", e);
+        }
+    }
+
+    /// Pauses every running torrent when `running` names a watched process that just
+    /// appeared, and resumes the same torrents once no watched process is left.
+    /// Torrents the user paused, resumed or removed in between are left alone.
+    async fn apply_process_pause(&mut self, running: Option<String>) {
+        match (running, self.app_state.process_pause.take()) {
+            (Some(process), None) => {
+                let paused = self.pause_all_torrents().await;
+
+                let entered_power_saving = matches!(self.app_state.mode, AppMode::Normal);
+                if entered_power_saving {
+                    self.app_state.mode = AppMode::PowerSaving;
+                }
+                tracing_event!(
+                    Level::INFO,
+                    "{} is running. Paused {} torrents.",
+                    process,
+                    paused.len()
+                );
+                self.push_status_message(format!(
+                    "Paused {} torrents while {} is running",
+                    paused.len(),
+                    process
+                ));
+                self.app_state.process_pause = Some(ProcessPause {
+                    process,
+                    torrents: paused,
+                    entered_power_saving,
+                });
+            }
+            (Some(_), Some(process_pause)) => {
+                self.app_state.process_pause = Some(process_pause);
+            }
+            (None, Some(mut process_pause)) => {
+                self.release_paused_torrents(std::mem::take(&mut process_pause.torrents))
+                    .await;
+
+                if process_pause.entered_power_saving
+                    && matches!(self.app_state.mode, AppMode::PowerSaving)
+                {
+                    self.app_state.mode = AppMode::Normal;
+                }
+                tracing_event!(
+                    Level::INFO,
+                    "{} exited. Resuming torrents.",
+                    process_pause.process
+                );
+                self.push_status_message(format!(
+                    "{} exited, resumed torrents",
+                    process_pause.process
+                ));
+            }
+            (None, None) => {}
+        }
+    }
+
+    /// Switches between the normal and alternative global limits, and pauses or
+    /// resumes every torrent, as the `schedule` rules call for right now.
+    async fn apply_schedule(&mut self) {
+        let profile = scheduler::scheduled_profile(
+            &self.client_configs.schedule,
+            self.client_configs.schedule_utc_offset_minutes,
+            SystemTime::now(),
+        );
+        let previous = self.app_state.rate_profile;
+        if profile == previous {
+            return;
+        }
+        self.app_state.rate_profile = profile;
+        self.apply_global_rate_limits().await;
+
+        if profile == RateProfile::Paused {
+            self.app_state.schedule_paused = self.pause_all_torrents().await;
+        } else if previous == RateProfile::Paused {
+            let torrents = std::mem::take(&mut self.app_state.schedule_paused);
+            self.release_paused_torrents(torrents).await;
+        }
+
+        let message = match profile {
+            RateProfile::Normal => "Schedule: back to the normal rate limits",
+            RateProfile::Alternative => "Schedule: using the alternative rate limits",
+            RateProfile::Paused => "Schedule: paused all torrents",
+        };
+        tracing_event!(Level::INFO, "{}", message);
+        self.push_status_message(message);
+    }
+
+    /// Sets the global token buckets to the limits of the active rate profile.
+    async fn apply_global_rate_limits(&self) {
+        let (dl_limit, ul_limit) = self.client_configs.rate_limits(self.app_state.rate_profile);
+        self.global_dl_bucket.lock().await.set_rate(dl_limit as f64);
+        self.global_ul_bucket.lock().await.set_rate(ul_limit as f64);
+    }
+
+    /// Pauses every running or queued torrent, returning the ones it paused.
+    async fn pause_all_torrents(&mut self) -> Vec<Vec<u8>> {
+        let mut paused = Vec::new();
+        for (info_hash, torrent) in self.app_state.torrents.iter_mut() {
+            let was_running = match torrent.latest_state.torrent_control_state {
+                TorrentControlState::Running => true,
+                TorrentControlState::Queued => false,
+                _ => continue,
+            };
+            if let Some(manager_tx) = self.torrent_manager_command_txs.get(info_hash) {
+                torrent.latest_state.torrent_control_state = TorrentControlState::Paused;
+                if was_running {
+                    let _ = manager_tx.send(ManagerCommand::Pause).await;
+                }
+                paused.push(info_hash.clone());
+            }
+        }
+        paused
+    }
+
+    /// Pauses every running or queued torrent until `resume_all`, e.g. before a backup.
+    pub async fn pause_all(&mut self) {
+        if self.app_state.all_paused.is_some() {
+            return;
+        }
+        let paused = self.pause_all_torrents().await;
+        tracing_event!(Level::INFO, "Paused all {} torrents.", paused.len());
+        self.push_status_message(format!("Paused all torrents ({})", paused.len()));
+        self.app_state.all_paused = Some(paused);
+        self.app_state.ui_needs_redraw = true;
+    }
+
+    /// Resumes the torrents `pause_all` paused. Without one (e.g. after a restart),
+    /// every paused torrent is resumed.
+    pub async fn resume_all(&mut self) {
+        let torrents = match self.app_state.all_paused.take() {
+            Some(torrents) => torrents,
+            None => self
+                .app_state
+                .torrents
+                .iter()
+                .filter(|(_, torrent)| {
+                    torrent.latest_state.torrent_control_state == TorrentControlState::Paused
+                })
+                .map(|(info_hash, _)| info_hash.clone())
+                .collect(),
+        };
+        tracing_event!(Level::INFO, "Resuming all {} torrents.", torrents.len());
+        self.push_status_message(format!("Resumed all torrents ({})", torrents.len()));
+        self.release_paused_torrents(torrents).await;
+        self.app_state.ui_needs_redraw = true;
+    }
+
+    /// Resumes torrents one kind of pause is done with. If the schedule, a watched
+    /// process or pause all still holds everything paused, they're handed over to it.
+    async fn release_paused_torrents(&mut self, torrents: Vec<Vec<u8>>) {
+        if self.app_state.rate_profile == RateProfile::Paused {
+            self.app_state.schedule_paused.extend(torrents);
+        } else if let Some(process_pause) = self.app_state.process_pause.as_mut() {
+            process_pause.torrents.extend(torrents);
+        } else if let Some(all_paused) = self.app_state.all_paused.as_mut() {
+            all_paused.extend(torrents);
+        } else {
+            self.requeue_torrents(&torrents).await;
+        }
+    }
+
+    /// Puts torrents paused by `pause_all_torrents` back into the queue, which resumes
+    /// as many as there are slots for. Ones the user has since touched stay as they are.
+    async fn requeue_torrents(&mut self, torrents: &[Vec<u8>]) {
+        for info_hash in torrents {
+            if let Some(torrent) = self.app_state.torrents.get_mut(info_hash) {
+                if torrent.latest_state.torrent_control_state == TorrentControlState::Paused {
+                    torrent.latest_state.torrent_control_state = TorrentControlState::Queued;
+                }
+            }
+        }
+        self.update_queue().await;
+    }
+
+    /// Per-torrent switches saved for `torrent_or_magnet`, so a restored torrent comes
+    /// back the way it was left. New torrents get the defaults.
+    fn saved_torrent_settings(&self, torrent_or_magnet: &str) -> TorrentSettings {
+        self.client_configs
+            .torrents
+            .iter()
+            .find(|cfg| cfg.torrent_or_magnet == torrent_or_magnet)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Limits a torrent to its first `max_download_bytes`, or lifts the limit with
+    /// `None`. The torrent pauses itself once everything under the cap is done.
+    #[cfg(unix)]
+    pub fn set_download_cap(&mut self, info_hash: &[u8], max_download_bytes: Option<u64>) {
+        let Some(torrent) = self.app_state.torrents.get_mut(info_hash) else {
+            tracing_event!(
+                Level::WARN,
+                "Download cap for unknown torrent {}",
+                hex::encode(info_hash)
+            );
+            return;
+        };
+        torrent.latest_state.max_download_bytes = max_download_bytes;
+        let torrent_name = torrent.latest_state.torrent_name.clone();
+
+        if let Some(manager_tx) = self.torrent_manager_command_txs.get(info_hash) {
+            let _ = manager_tx.try_send(ManagerCommand::SetMaxDownloadBytes(max_download_bytes));
+        }
+        self.push_status_message(match max_download_bytes {
+            Some(bytes) => format!(
+                "Downloading only the first {} of '{}'",
+                format_bytes(bytes),
+                torrent_name
+            ),
+            None => format!("Removed the download cap from '{}'", torrent_name),
+        });
+    }
+
+    /// Gives a torrent its own seed goals, or with `None` has it follow the global
+    /// ones again.
+    #[cfg(unix)]
+    pub fn set_seed_goals(&mut self, info_hash: &[u8], seed_goals: Option<SeedGoals>) {
+        let Some(torrent) = self.app_state.torrents.get_mut(info_hash) else {
+            tracing_event!(
+                Level::WARN,
+                "Seed goals for unknown torrent {}",
+                hex::encode(info_hash)
+            );
+            return;
+        };
+        let message = match &seed_goals {
+            Some(_) => format!("Set seed goals for '{}'", torrent.latest_state.torrent_name),
+            None => format!(
+                "'{}' now follows the global seed goals",
+                torrent.latest_state.torrent_name
+            ),
+        };
+        torrent.latest_state.seed_goals = seed_goals;
+        self.push_status_message(message);
+    }
+
+    /// Files a torrent under `category`, or with `None` takes it out of its category.
+    /// Finished torrents stay where they are; one still downloading moves to the
+    /// category's completed folder, if it has one, when it finishes.
+    pub fn set_torrent_category(&mut self, info_hash: &[u8], category: Option<String>) {
+        let Some(torrent) = self.app_state.torrents.get_mut(info_hash) else {
+            tracing_event!(
+                Level::WARN,
+                "Category for unknown torrent {}",
+                hex::encode(info_hash)
+            );
+            return;
+        };
+        let message = match &category {
+            Some(category) => format!(
+                "Filed '{}' under {}",
+                torrent.latest_state.torrent_name, category
+            ),
+            None => format!(
+                "Removed '{}' from its category",
+                torrent.latest_state.torrent_name
+            ),
+        };
+        torrent.latest_state.category = category;
+        self.update_completed_dir(info_hash);
+        self.push_status_message(message);
+        self.sort_and_filter_torrent_list();
+    }
+
+    /// Adds a magnet link or torrent file the way `options` say. It downloads to the
+    /// folder in `options`, else its category's, else `fallback_folder`; with none of
+    /// them the add dialog opens so one can be picked.
+    pub async fn add_with_options(
+        &mut self,
+        input: String,
+        options: AddOptions,
+        fallback_folder: Option<PathBuf>,
+    ) -> Option<Vec<u8>> {
+        let category_folder = options
+            .category
+            .as_deref()
+            .and_then(|name| self.client_configs.category_download_folder(name));
+        let Some(download_path) = options
+            .download_path
+            .clone()
+            .or(category_folder)
+            .or(fallback_folder)
+        else {
+            tracing_event!(Level::WARN, "No download folder for {}", input);
+            self.push_status_message("Pick a download folder for the new torrent");
+            self.app_state.mode = AppMode::AddTorrent {
+                input,
+                options,
+                selected_index: 0,
+            };
+            return None;
+        };
+
+        let torrent_control_state = if options.paused {
+            TorrentControlState::Paused
+        } else {
+            TorrentControlState::Running
+        };
+        let added = if input.starts_with("magnet:") {
+            self.add_magnet_torrent(
+                "Fetching name...".to_string(),
+                input,
+                download_path,
+                options.skip_check,
+                torrent_control_state,
+            )
+            .await
+        } else {
+            self.add_torrent_from_file(
+                PathBuf::from(input),
+                download_path,
+                options.skip_check,
+                torrent_control_state,
+            )
+            .await
+        };
+        if options.merge_sources {
+            if let Some(sources) = self.app_state.duplicate_sources.take() {
+                self.merge_duplicate_sources(sources);
+            }
+        }
+        let info_hash = added?;
+
+        if let Some(torrent) = self.app_state.torrents.get_mut(&info_hash) {
+            if options.category.is_some() {
+                torrent.latest_state.category = options.category;
+                self.update_completed_dir(&info_hash);
+            }
+        }
+        if options.sequential {
+            self.set_sequential(&info_hash, true);
+        }
+        Some(info_hash)
+    }
+
+    /// Switches a torrent between downloading pieces in order and rarest first.
+    pub fn set_sequential(&mut self, info_hash: &[u8], sequential: bool) {
+        let Some(torrent) = self.app_state.torrents.get_mut(info_hash) else {
+            return;
+        };
+        torrent.latest_state.sequential = sequential;
+        if let Some(manager_tx) = self.torrent_manager_command_txs.get(info_hash) {
+            let _ = manager_tx.try_send(ManagerCommand::SetSequential(sequential));
+        }
+    }
+
+    /// Gives a torrent a bigger or smaller share of the global speed limits.
+    pub fn set_bandwidth_priority(&mut self, info_hash: &[u8], priority: BandwidthPriority) {
+        let Some(torrent) = self.app_state.torrents.get_mut(info_hash) else {
+            tracing_event!(
+                Level::WARN,
+                "Bandwidth priority for unknown torrent {}",
+                hex::encode(info_hash)
+            );
+            return;
+        };
+        torrent.latest_state.bandwidth_priority = priority;
+        let message = format!(
+            "'{}' now has {} bandwidth priority",
+            torrent.latest_state.torrent_name,
+            priority.label()
+        );
+        if let Some(manager_tx) = self.torrent_manager_command_txs.get(info_hash) {
+            let _ = manager_tx.try_send(ManagerCommand::SetBandwidthPriority(priority));
+        }
+        self.push_status_message(message);
+    }
+
+    /// Holds on to the trackers and web seeds of a torrent added a second time until
+    /// the user, or the `merge_sources` add option, says to merge them. A private
+    /// torrent keeps the trackers it has.
+    fn hold_duplicate_sources(
+        &mut self,
+        info_hash: &[u8],
+        trackers: Vec<String>,
+        web_seeds: Vec<String>,
+        is_private: bool,
+    ) {
+        let Some(torrent) = self.app_state.torrents.get(info_hash) else {
+            return;
+        };
+        let torrent_name = torrent.latest_state.name().to_string();
+        if is_private || torrent.latest_state.is_private {
+            self.push_status_message(format!(
+                "'{}' is already added; it's private, so its trackers stay as they are",
+                torrent_name
+            ));
+        } else if trackers.is_empty() && web_seeds.is_empty() {
+            self.push_status_message(format!("'{}' is already added", torrent_name));
+        } else {
+            self.app_state.duplicate_sources = Some(DuplicateSources {
+                info_hash: info_hash.to_vec(),
+                trackers,
+                web_seeds,
+            });
+        }
+    }
+
+    /// Hands the sources of a duplicate to the torrent already running; the manager
+    /// answers with the ones it didn't know.
+    pub fn merge_duplicate_sources(&mut self, sources: DuplicateSources) {
+        if let Some(manager_tx) = self.torrent_manager_command_txs.get(&sources.info_hash) {
+            let _ = manager_tx.try_send(ManagerCommand::AddSources {
+                trackers: sources.trackers,
+                web_seeds: sources.web_seeds,
+            });
+        }
+    }
+
+    /// Asks whether to merge the sources of a torrent added again from the UI, once
+    /// no other dialog is open.
+    fn offer_duplicate_sources(&mut self) {
+        if matches!(self.app_state.mode, AppMode::Normal) {
+            if let Some(sources) = self.app_state.duplicate_sources.take() {
+                self.app_state.mode = AppMode::MergeSourcesConfirm(sources);
+            }
+        }
+    }
+
+    /// Drops the sources of a duplicate added from outside the UI without the
+    /// `merge_sources` option.
+    fn decline_duplicate_sources(&mut self) {
+        let Some(sources) = self.app_state.duplicate_sources.take() else {
+            return;
+        };
+        if let Some(torrent) = self.app_state.torrents.get(&sources.info_hash) {
+            let message = format!(
+                "'{}' is already added; add it with merge_sources to take its new trackers",
+                torrent.latest_state.name()
+            );
+            self.push_status_message(message);
+        }
+    }
+
+    /// Lets one torrent find peers through the DHT (or PEX), or stops it. Private
+    /// torrents never use either, whatever is set here.
+    pub fn set_peer_discovery(&mut self, info_hash: &[u8], dht: bool, enabled: bool) {
+        let Some(torrent) = self.app_state.torrents.get_mut(info_hash) else {
+            tracing_event!(
+                Level::WARN,
+                "Peer discovery setting for unknown torrent {}",
+                hex::encode(info_hash)
+            );
+            return;
+        };
+        let state = &mut torrent.latest_state;
+        let (source, command) = if dht {
+            state.dht_disabled = !enabled;
+            ("DHT", ManagerCommand::SetDhtDisabled(!enabled))
+        } else {
+            state.pex_disabled = !enabled;
+            ("PEX", ManagerCommand::SetPexDisabled(!enabled))
+        };
+        let message = if state.is_private {
+            format!(
+                "'{}' is private and never uses {}",
+                state.torrent_name, source
+            )
+        } else {
+            format!(
+                "{} {} for '{}'",
+                source,
+                if enabled { "enabled" } else { "disabled" },
+                state.torrent_name
+            )
+        };
+        if let Some(manager_tx) = self.torrent_manager_command_txs.get(info_hash) {
+            let _ = manager_tx.try_send(command);
+        }
+        self.push_status_message(message);
+    }
+
+    /// Adds the magnet link or torrent path on the first line of a `.magnet` or `.path`
+    /// watch file, with the add options on the lines after it. The watch folder's
+    /// download folder and label apply unless the file gives others.
+    async fn add_from_watch_file(&mut self, watch_file: &Path, content: &str) {
+        let mut lines = content.lines();
+        let input = lines.next().unwrap_or_default().trim().to_string();
+        let mut options = AddOptions::parse(lines).unwrap_or_else(|e| {
+            tracing_event!(
+                Level::WARN,
+                "Ignoring the options in {:?}: {}",
+                watch_file,
+                e
+            );
+            AddOptions::default()
+        });
+        if options.category.is_none() {
+            options.category = self
+                .client_configs
+                .watch_folder_rule_for(watch_file)
+                .and_then(|rule| rule.label.clone());
+        }
+        let fallback_folder = self.client_configs.download_folder_for(watch_file);
+        self.add_with_options(input, options, fallback_folder).await;
+        self.decline_duplicate_sources();
+    }
+
+    /// Files a torrent just added from a watch folder under that folder's label.
+    fn set_watch_folder_category(&mut self, watched_path: &Path, info_hash: Option<Vec<u8>>) {
+        let label = self
+            .client_configs
+            .watch_folder_rule_for(watched_path)
+            .and_then(|rule| rule.label.clone());
+        if let (Some(info_hash), Some(label)) = (info_hash, label) {
+            if let Some(torrent) = self.app_state.torrents.get_mut(&info_hash) {
+                torrent.latest_state.category = Some(label);
+                self.update_completed_dir(&info_hash);
+            }
+        }
+    }
+
+    /// The configured categories, then any others torrents are filed under.
+    pub fn category_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .client_configs
+            .categories
+            .iter()
+            .map(|category| category.name.clone())
+            .collect();
+        let mut others: Vec<String> = self
+            .app_state
+            .torrents
+            .values()
+            .filter_map(|torrent| torrent.latest_state.category.clone())
+            .filter(|name| !names.contains(name))
+            .collect();
+        others.sort();
+        others.dedup();
+        names.extend(others);
+        names
+    }
+
+    /// Gives some of a torrent's files a new priority, or skips them. The manager
+    /// keeps the priorities and reports them back, so they're saved with the torrent.
+    pub fn set_file_priority(
+        &mut self,
+        info_hash: &[u8],
+        ranges: Vec<RangeInclusive<usize>>,
+        priority: FilePriority,
+    ) {
+        let Some(torrent) = self.app_state.torrents.get_mut(info_hash) else {
+            tracing_event!(
+                Level::WARN,
+                "File selection for unknown torrent {}",
+                hex::encode(info_hash)
+            );
+            return;
+        };
+        let torrent_name = torrent.latest_state.torrent_name.clone();
+        if torrent.latest_state.files.is_empty() {
+            self.push_status_message(format!(
+                "'{}' has no metadata yet; set file priorities once it arrives",
+                torrent_name
+            ));
+            return;
+        }
+        // Shown right away; the manager's next report confirms it.
+        for file in &mut torrent.latest_state.files {
+            if ranges.iter().any(|range| range.contains(&file.index)) {
+                file.priority = priority;
+            }
+        }
+
+        if let Some(manager_tx) = self.torrent_manager_command_txs.get(info_hash) {
+            let _ = manager_tx.try_send(ManagerCommand::SetFilePriority(ranges, priority));
+        }
+        self.push_status_message(match priority {
+            FilePriority::Skip => format!("Skipping the selected files of '{}'", torrent_name),
+            _ => format!(
+                "Selected files of '{}' set to {} priority",
+                torrent_name,
+                priority.label()
+            ),
+        });
+    }
+
+    /// Turns uploads (or downloads) off for one torrent, or back on.
+    pub fn toggle_transfer_disabled(&mut self, info_hash: &[u8], upload: bool) {
+        let Some(torrent) = self.app_state.torrents.get_mut(info_hash) else {
+            return;
+        };
+        let state = &mut torrent.latest_state;
+        let (direction, disabled, command) = if upload {
+            state.upload_disabled = !state.upload_disabled;
+            let disabled = state.upload_disabled;
+            (
+                "Uploads",
+                disabled,
+                ManagerCommand::SetUploadDisabled(disabled),
+            )
+        } else {
+            state.download_disabled = !state.download_disabled;
+            let disabled = state.download_disabled;
+            (
+                "Downloads",
+                disabled,
+                ManagerCommand::SetDownloadDisabled(disabled),
+            )
+        };
+        let torrent_name = state.torrent_name.clone();
+
+        if let Some(manager_tx) = self.torrent_manager_command_txs.get(info_hash) {
+            let _ = manager_tx.try_send(command);
+        }
+        self.push_status_message(format!(
+            "{} {} for '{}'",
+            direction,
+            if disabled { "disabled" } else { "enabled" },
+            torrent_name
+        ));
+    }
+
+    /// Makes room for one more torrent under `max_torrents`, removing the longest-loaded
+    /// finished torrents (keeping their files) if the policy allows it. Returns false
+    /// when the new torrent should be turned away.
+    fn make_room_for_torrent(&mut self, torrent_name: &str) -> bool {
+        let max_torrents = self.client_configs.max_torrents;
+        if max_torrents == 0 {
+            return true;
+        }
+        let loaded: Vec<(&Vec<u8>, &TorrentDisplayState)> = self
+            .app_state
+            .torrents
+            .iter()
+            .filter(|(_, torrent)| {
+                torrent.latest_state.torrent_control_state != TorrentControlState::Deleting
+            })
+            .collect();
+        if loaded.len() < max_torrents {
+            return true;
+        }
+        let excess = loaded.len() + 1 - max_torrents;
+
+        if self.client_configs.torrent_limit_policy == TorrentLimitPolicy::RemoveOldestComplete {
+            let mut complete: Vec<(&Vec<u8>, &TorrentDisplayState)> = loaded
+                .into_iter()
+                .filter(|(_, torrent)| {
+                    let state = &torrent.latest_state;
+                    state.number_of_pieces_total > 0
+                        && state.number_of_pieces_completed == state.number_of_pieces_total
+                })
+                .collect();
+
+            if complete.len() >= excess {
+                complete.sort_by_key(|(_, torrent)| torrent.added_at);
+                let evicted: Vec<(Vec<u8>, String)> = complete
+                    .into_iter()
+                    .take(excess)
+                    .map(|(info_hash, torrent)| {
+                        (info_hash.clone(), torrent.latest_state.torrent_name.clone())
+                    })
+                    .collect();
+                for (info_hash, evicted_name) in evicted {
+                    tracing_event!(
+                        Level::INFO,
+                        "Torrent limit of {} reached. Removing finished torrent '{}' to make room.",
+                        max_torrents,
+                        evicted_name
+                    );
+                    self.delete_torrent(&info_hash, false);
+                    self.push_status_message(format!(
+                        "Removed finished '{}' to make room",
+                        evicted_name
+                    ));
+                }
+                return true;
+            }
+        }
+
+        tracing_event!(
+            Level::WARN,
+            "Rejected '{}': the limit of {} torrents is reached.",
+            torrent_name,
+            max_torrents
+        );
+        self.push_status_message(format!(
+            "'{}' not added: the limit of {} torrents is reached",
+            torrent_name, max_torrents
+        ));
+        false
+    }
+
+    /// Sends a new torrent to the incomplete folder, when one is set, returning where
+    /// it downloads and the folder it moves to once finished. Restored torrents keep
+    /// their saved paths.
+    fn incomplete_download_path(
+        &self,
+        torrent_or_magnet: &str,
+        download_path: PathBuf,
+        is_validated: bool,
+    ) -> (PathBuf, Option<PathBuf>) {
+        if let Some(saved) = self
+            .client_configs
+            .torrents
+            .iter()
+            .find(|cfg| cfg.torrent_or_magnet == torrent_or_magnet)
+        {
+            return (download_path, saved.completed_path.clone());
+        }
+        match &self.client_configs.incomplete_download_folder {
+            Some(incomplete) if !is_validated && *incomplete != download_path => {
+                (incomplete.clone(), Some(download_path))
+            }
+            _ => (download_path, None),
+        }
+    }
+
+    /// Where a finished torrent's files move: its category's completed folder, the
+    /// global one, or the folder it was added with while it downloads elsewhere.
+    fn completed_dir_for(
+        &self,
+        category: Option<&str>,
+        completed_path: Option<&PathBuf>,
+    ) -> Option<PathBuf> {
+        self.client_configs
+            .completed_folder_for(category)
+            .or_else(|| completed_path.cloned())
+    }
+
+    /// Like `completed_dir_for`, but a torrent that was already finished stays where
+    /// it is unless it never left the incomplete folder.
+    fn completed_dir_for_new_torrent(
+        &self,
+        category: Option<&str>,
+        completed_path: Option<&PathBuf>,
+        is_validated: bool,
+    ) -> Option<PathBuf> {
+        if is_validated && completed_path.is_none() {
+            return None;
+        }
+        self.completed_dir_for(category, completed_path)
+    }
+
+    /// Tells a torrent's manager where to move it once finished, after its category
+    /// changed.
+    fn update_completed_dir(&self, info_hash: &[u8]) {
+        let Some(torrent) = self.app_state.torrents.get(info_hash) else {
+            return;
+        };
+        let completed_dir = self.completed_dir_for(
+            torrent.latest_state.category.as_deref(),
+            torrent.latest_state.completed_path.as_ref(),
+        );
+        if let Some(manager_tx) = self.torrent_manager_command_txs.get(info_hash) {
+            let _ = manager_tx.try_send(ManagerCommand::SetCompletedDir(completed_dir));
+        }
+    }
+
+    /// Keeps a new torrent paused when its download folder can't be written to, so
+    /// it doesn't stall halfway through on an unmounted or read-only drive.
+    fn control_state_for_download_path(
+        &mut self,
+        download_path: &Path,
+        torrent_control_state: TorrentControlState,
+    ) -> TorrentControlState {
+        if !self.client_configs.check_download_folder_writable
+            || torrent_control_state != TorrentControlState::Running
+        {
+            return torrent_control_state;
+        }
+
+        match check_writable(download_path) {
+            Ok(()) => torrent_control_state,
+            Err(e) => {
+                tracing_event!(Level::WARN, path = ?download_path, error = %e, "Download folder is not writable. Starting torrent paused.");
+                self.app_state.system_error = Some(format!(
+                    "Download folder {} is not writable ({}). The torrent was paused.",
+                    download_path.display(),
+                    e
+                ));
+                TorrentControlState::Paused
+            }
+        }
+    }
+
+    /// A new torrent counts as a download until it reports having every piece, so it
+    /// starts queued when every download slot is taken.
+    fn control_state_for_queue(
+        &self,
+        torrent_control_state: TorrentControlState,
+    ) -> TorrentControlState {
+        let downloads = self
+            .app_state
+            .torrents
+            .values()
+            .filter(|torrent| {
+                torrent.latest_state.torrent_control_state == TorrentControlState::Running
+                    && !torrent.latest_state.is_complete()
+            })
+            .count();
+        new_torrent_control_state(
+            torrent_control_state,
+            downloads,
+            self.client_configs.max_active_downloads,
+        )
+    }
+
+    /// Adds a torrent to the end of the queue, unless it already has a place there
+    /// from before, e.g. when one that failed to start is tried again.
+    fn enqueue_torrent(&mut self, info_hash: &[u8]) {
+        if !self
+            .app_state
+            .torrent_queue
+            .iter()
+            .any(|ih| ih == info_hash)
+        {
+            self.app_state.torrent_queue.push(info_hash.to_vec());
+        }
+    }
+
+    /// Hands out download and seed slots in queue order, up to `max_active_downloads`
+    /// and `max_active_seeds`, resuming queued torrents that get one and queueing
+    /// running ones that don't. Torrents the user paused don't take a slot.
+    async fn update_queue(&mut self) {
+        let contenders: Vec<(&Vec<u8>, &TorrentState)> = self
+            .app_state
+            .torrent_queue
+            .iter()
+            .filter_map(|info_hash| {
+                let torrent = self.app_state.torrents.get(info_hash)?;
+                let state = &torrent.latest_state;
+                (torrent.load_error.is_none()
+                    && matches!(
+                        state.torrent_control_state,
+                        TorrentControlState::Running | TorrentControlState::Queued
+                    ))
+                .then_some((info_hash, state))
+            })
+            .collect();
+        let slots = queue_slots(
+            contenders.iter().map(|(_, state)| state.is_complete()),
+            self.client_configs.max_active_downloads,
+            self.client_configs.max_active_seeds,
+        );
+        let changes: Vec<(Vec<u8>, TorrentControlState)> = contenders
+            .into_iter()
+            .zip(slots)
+            .filter(|((_, state), new_state)| *new_state != state.torrent_control_state)
+            .map(|((info_hash, _), new_state)| (info_hash.clone(), new_state))
+            .collect();
+
+        for (info_hash, new_state) in changes {
+            let (Some(torrent), Some(manager_tx)) = (
+                self.app_state.torrents.get_mut(&info_hash),
+                self.torrent_manager_command_txs.get(&info_hash),
+            ) else {
+                continue;
+            };
+            let command = if new_state == TorrentControlState::Running {
+                ManagerCommand::Resume
+            } else {
+                ManagerCommand::Pause
+            };
+            tracing_event!(
+                Level::DEBUG,
+                "Queue: '{}' is now {:?}",
+                torrent.latest_state.torrent_name,
+                new_state
+            );
+            torrent.latest_state.torrent_control_state = new_state;
+            let _ = manager_tx.send(command).await;
+            self.app_state.ui_needs_redraw = true;
+        }
+    }
+
+    /// Moves a torrent one place up (towards the front) or down the queue.
+    pub async fn move_in_queue(&mut self, info_hash: &[u8], up: bool) {
+        let Some(new_position) = shift_in_queue(&mut self.app_state.torrent_queue, info_hash, up)
+        else {
+            return;
+        };
+
+        if let Some(torrent) = self.app_state.torrents.get(info_hash) {
+            let message = format!(
+                "'{}' is now #{} in the queue",
+                torrent.latest_state.torrent_name,
+                new_position + 1
+            );
+            self.push_status_message(message);
+        }
+        self.update_queue().await;
+        self.sort_and_filter_torrent_list();
+        if let Some(index) = self
+            .app_state
+            .torrent_list_order
+            .iter()
+            .position(|ih| ih == info_hash)
+        {
+            self.app_state.selected_torrent_index = index;
+        }
+    }
+
+    /// Reports an unwritable default download folder before any torrent is added to it.
+    pub fn check_default_download_folder(&mut self) {
+        if !self.client_configs.check_download_folder_writable {
+            return;
+        }
+        let Some(folder) = self.client_configs.default_download_folder.clone() else {
+            return;
+        };
+
+        if let Err(e) = check_writable(&folder) {
+            tracing_event!(Level::WARN, path = ?folder, error = %e, "Default download folder is not writable.");
+            self.app_state.system_error = Some(format!(
+                "Default download folder {} is not writable ({}). Check that the drive is mounted.",
+                folder.display(),
+                e
+            ));
+        }
+    }
+
+    /// Re-runs the checks shown on the welcome screen for the usual reasons a new
+    /// setup adds a torrent and then nothing happens.
+    pub fn refresh_setup_checks(&mut self) {
+        let file_handles = match &self.app_state.system_warning {
+            Some(warning) => Err(warning.clone()),
+            None => Ok("Limit is high enough".to_string()),
+        };
+
+        let download_folder = match &self.client_configs.default_download_folder {
+            Some(folder) => match check_writable(folder) {
+                Ok(()) => Ok(format!("{}", folder.display())),
+                Err(e) => Err(format!(
+                    "{} is not writable ({}). Press [f] to pick another.",
+                    folder.display(),
+                    e
+                )),
+            },
+            None => Err(
+                "Not set. Press [f] to choose one; the CLI and watch folders need it.".to_string(),
+            ),
+        };
+
+        let utp_port = self
+            .utp_socket
+            .as_ref()
+            .and_then(|utp_socket| utp_socket.local_addr().ok())
+            .map(|addr| format!(" (TCP) and {} (UDP, uTP)", addr.port()))
+            .unwrap_or_default();
+        let port = match self.listener.local_addr() {
+            Ok(addr) => Ok(format!(
+                "Listening on {}{}{}. Forward it on your router for incoming peers.",
+                addr.port(),
+                utp_port,
+                if addr.is_ipv6() || self.listener_v6.is_some() {
+                    " over IPv4 and IPv6"
+                } else {
+                    ""
+                }
+            )),
+            Err(e) => Err(format!("Listener is not usable ({}).", e)),
+        };
+
+        self.app_state.setup_checks = vec![
+            SetupCheck {
+                label: "File handles",
+                result: file_handles,
+            },
+            SetupCheck {
+                label: "Download folder",
+                result: download_folder,
+            },
+            SetupCheck {
+                label: "Peer port",
+                result: port,
+            },
+        ];
+    }
+
+    /// Queues a message for the status area. Only the most recent few are kept, and each
+    /// one disappears after a few seconds.
+    pub fn push_status_message(&mut self, text: impl Into<String>) {
+        self.app_state.status_messages.push_back(StatusMessage {
+            text: text.into(),
+            created_at: Instant::now(),
+        });
+        while self.app_state.status_messages.len() > STATUS_MESSAGE_HISTORY {
+            self.app_state.status_messages.pop_front();
+        }
+        self.app_state.ui_needs_redraw = true;
+    }
+
+    /// The settings as they should be on disk right now: what was loaded plus this
+    /// session's transfer totals, sort order and torrent list. `client_configs` is
+    /// left alone, so saving again later doesn't count the session twice.
+    fn settings_snapshot(&self) -> Settings {
+        let mut settings = self.client_configs.clone();
+        settings.lifetime_downloaded += self.app_state.session_total_downloaded;
+        settings.lifetime_uploaded += self.app_state.session_total_uploaded;
+        settings.torrent_sort_column = self.app_state.torrent_sort.0;
+        settings.torrent_sort_direction = self.app_state.torrent_sort.1;
+        settings.peer_sort_column = self.app_state.peer_sort.0;
+        settings.peer_sort_direction = self.app_state.peer_sort.1;
+
+        // Torrents paused only for a watched process or by the schedule start again
+        // next time.
+        let process_paused: &[Vec<u8>] = self
+            .app_state
+            .process_pause
+            .as_ref()
+            .map_or(&[], |process_pause| &process_pause.torrents);
+
+        let old_validation_statuses: HashMap<&str, bool> = self
+            .client_configs
+            .torrents
+            .iter()
+            .map(|cfg| (cfg.torrent_or_magnet.as_str(), cfg.validation_status))
+            .collect();
+
+        settings.torrents = self
+            .app_state
+            .torrent_queue
+            .iter()
+            .filter_map(|info_hash| Some((info_hash, self.app_state.torrents.get(info_hash)?)))
+            .map(|(info_hash, torrent)| {
+                let torrent_state = &torrent.latest_state;
+
+                let is_complete = torrent_state.number_of_pieces_total > 0
+                    && torrent_state.number_of_pieces_total
+                        == torrent_state.number_of_pieces_completed;
+
+                let old_status = old_validation_statuses
+                    .get(torrent_state.torrent_or_magnet.as_str())
+                    .cloned()
+                    .unwrap_or(false);
+
+                let final_validation_status = if is_complete { true } else { old_status };
+
+                let torrent_control_state = if torrent_state.torrent_control_state
+                    == TorrentControlState::Paused
+                    && (process_paused.contains(info_hash)
+                        || self.app_state.schedule_paused.contains(info_hash))
+                {
+                    TorrentControlState::Running
+                } else {
+                    torrent_state.torrent_control_state.clone()
+                };
+
+                TorrentSettings {
+                    torrent_or_magnet: torrent_state.torrent_or_magnet.clone(),
+                    name: torrent_state.torrent_name.clone(),
+                    validation_status: final_validation_status,
+                    download_path: torrent_state.download_path.clone(),
+                    torrent_control_state,
+                    upload_disabled: torrent_state.upload_disabled,
+                    download_disabled: torrent_state.download_disabled,
+                    max_download_bytes: torrent_state.max_download_bytes,
+                    file_priorities: torrent_state.file_priorities.clone(),
+                    total_downloaded: torrent_state.total_downloaded,
+                    total_uploaded: torrent_state.total_uploaded,
+                    seeding_secs: torrent_state.seeding_time.as_secs(),
+                    seed_goals: torrent_state.seed_goals.clone(),
+                    category: torrent_state.category.clone(),
+                    completed_path: torrent_state.completed_path.clone(),
+                    file_renames: torrent_state.file_renames.clone(),
+                    display_name: torrent_state.display_name.clone(),
+                    sequential: torrent_state.sequential,
+                    bandwidth_priority: torrent_state.bandwidth_priority,
+                    dht_disabled: torrent_state.dht_disabled,
+                    pex_disabled: torrent_state.pex_disabled,
+                    extra_trackers: torrent_state.extra_trackers.clone(),
+                    extra_web_seeds: torrent_state.extra_web_seeds.clone(),
+                }
+            })
+            .collect();
+        settings
+    }
+
+    /// Writes the current state to settings.toml without waiting for shutdown, so a
+    /// crash or power loss keeps the torrent list and transfer totals.
+    pub fn save_state(&mut self) -> std::io::Result<()> {
+        self.app_state.last_saved_at = Some(Instant::now());
+        save_settings(&self.settings_snapshot())
+    }
+
+    /// Saves the state on request from the user and reports how it went.
+    pub fn save_state_now(&mut self) {
+        match self.save_state() {
+            Ok(()) => self.push_status_message("Saved settings and torrent list"),
+            Err(e) => {
+                tracing_event!(Level::ERROR, "Failed to save settings: {}", e);
+                self.push_status_message(format!("Failed to save settings: {}", e));
+            }
+        }
+    }
+
+    /// Keeps a saved torrent in the list when its stored .torrent file failed to load,
+    /// so it doesn't silently disappear. The entry has no manager; it can be removed,
+    /// or replaced by adding the same torrent again.
+    fn keep_unloaded_torrent(&mut self, torrent_config: &TorrentSettings) {
+        let info_hash = Path::new(&torrent_config.torrent_or_magnet)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| hex::decode(s).ok())
+            .unwrap_or_else(|| torrent_config.torrent_or_magnet.as_bytes().to_vec());
+        if self.app_state.torrents.contains_key(&info_hash) {
+            return;
+        }
+
+        tracing_event!(
+            Level::WARN,
+            "Keeping '{}' in the list after it failed to load from {}",
+            torrent_config.name,
+            torrent_config.torrent_or_magnet
+        );
+        let error_state = TorrentDisplayState {
+            latest_state: TorrentState {
+                torrent_control_state: torrent_config.torrent_control_state.clone(),
+                info_hash: info_hash.clone(),
+                torrent_or_magnet: torrent_config.torrent_or_magnet.clone(),
+                torrent_name: torrent_config.name.clone(),
+                download_path: torrent_config.download_path.clone(),
+                upload_disabled: torrent_config.upload_disabled,
+                download_disabled: torrent_config.download_disabled,
+                max_download_bytes: torrent_config.max_download_bytes,
+                file_priorities: torrent_config.file_priorities.clone(),
+                total_downloaded: torrent_config.total_downloaded,
+                total_uploaded: torrent_config.total_uploaded,
+                seeding_time: Duration::from_secs(torrent_config.seeding_secs),
+                seed_goals: torrent_config.seed_goals.clone(),
+                category: torrent_config.category.clone(),
+                completed_path: torrent_config.completed_path.clone(),
+                file_renames: torrent_config.file_renames.clone(),
+                display_name: torrent_config.display_name.clone(),
+                sequential: torrent_config.sequential,
+                bandwidth_priority: torrent_config.bandwidth_priority,
+                dht_disabled: torrent_config.dht_disabled,
+                pex_disabled: torrent_config.pex_disabled,
+                extra_trackers: torrent_config.extra_trackers.clone(),
+                extra_web_seeds: torrent_config.extra_web_seeds.clone(),
+                activity_message: "Error: failed to load".to_string(),
+                ..Default::default()
+            },
+            load_error: Some(format!(
+                "Could not load {}. Remove it or add the torrent again.",
+                torrent_config.torrent_or_magnet
+            )),
+            added_at: Some(Instant::now()),
+            ..Default::default()
+        };
+        self.app_state
+            .torrents
+            .insert(info_hash.clone(), error_state);
+        self.enqueue_torrent(&info_hash);
+        self.app_state.torrent_list_order.push(info_hash);
+    }
+
+    /// Leaves a torrent whose manager failed to start in the list as failed, rather than
+    /// dropping it, so it can be retried. Storage failures are also retried on their
+    /// own after a backoff.
+    fn keep_failed_torrent(&mut self, info_hash: &[u8], error: &ManagerInitError) {
+        self.torrent_manager_command_txs.remove(info_hash);
+        self.torrent_manager_incoming_peer_txs.remove(info_hash);
+
+        let transient = error.is_transient();
+        let next_retry_at = (transient && self.client_configs.failed_torrent_retry_attempts > 0)
+            .then(|| Instant::now() + self.client_configs.failed_torrent_retry_delay(0));
+        let Some(torrent) = self.app_state.torrents.get_mut(info_hash) else {
+            return;
+        };
+        torrent.latest_state.activity_message = "Error: failed to start".to_string();
+        torrent.load_error = Some(if transient {
+            format!("{}. Press [r] to retry.", error)
+        } else {
+            format!("{}. Remove it or add the torrent again.", error)
+        });
+        torrent.init_failure = Some(InitFailure {
+            transient,
+            attempts: 0,
+            next_retry_at,
+        });
+        let torrent_name = torrent.latest_state.torrent_name.clone();
+        self.push_status_message(format!("'{}' failed to start", torrent_name));
+    }
+
+    /// Tries again to start a torrent that failed to load or whose manager failed to
+    /// start. If it fails again, the next automatic retry waits twice as long.
+    pub async fn retry_failed_torrent(&mut self, info_hash: &[u8]) {
+        let Some(torrent) = self.app_state.torrents.get(info_hash) else {
+            return;
+        };
+        if torrent.load_error.is_none()
+            || torrent.latest_state.torrent_control_state == TorrentControlState::Deleting
+        {
+            return;
+        }
+        let state = torrent.latest_state.clone();
+        let attempts = torrent
+            .init_failure
+            .as_ref()
+            .map_or(0, |failure| failure.attempts)
+            + 1;
+
+        tracing_event!(
+            Level::INFO,
+            "Retrying '{}' (attempt {})",
+            state.torrent_name,
+            attempts
+        );
+        if state.torrent_or_magnet.starts_with("magnet:") {
+            self.add_magnet_torrent(
+                state.torrent_name,
+                state.torrent_or_magnet,
+                state.download_path,
+                false,
+                state.torrent_control_state,
+            )
+            .await;
+        } else {
+            self.add_torrent_from_file(
+                PathBuf::from(&state.torrent_or_magnet),
+                state.download_path,
+                false,
+                state.torrent_control_state,
+            )
+            .await;
+        }
 
-            if sort_direction != default_direction {
-                ordering.reverse()
-            } else {
-                ordering
-            }
+        let Some(failure) = self
+            .app_state
+            .torrents
+            .get_mut(info_hash)
+            .and_then(|torrent| torrent.init_failure.as_mut())
+        else {
+            return;
+        };
+        failure.attempts = attempts;
+        failure.next_retry_at = (failure.transient
+            && attempts < self.client_configs.failed_torrent_retry_attempts)
+            .then(|| Instant::now() + self.client_configs.failed_torrent_retry_delay(attempts));
+    }
+
+    /// Reads the routing table and network view of the DHT node for the status panel.
+    #[cfg(feature = "dht")]
+    async fn refresh_dht_status(&mut self) {
+        if self.client_configs.dht_mode == DhtMode::Off {
+            self.app_state.dht_status = None;
+            return;
+        }
+        let info = self.distributed_hash_table.info().await;
+        if let Some(public_address) = info.public_address() {
+            self.report_external_ip(IpAddr::V4(*public_address.ip()), IpSource::Dht)
+                .await;
+        }
+        let routing_table_nodes = self.distributed_hash_table.to_bootstrap().await.len();
+        let queries_received = logging::dht_queries_received();
+        let now = Instant::now();
+        let queries_per_sec = self
+            .app_state
+            .dht_status
+            .as_ref()
+            .and_then(|previous| {
+                let elapsed = now.duration_since(previous.refreshed_at?).as_secs_f64();
+                (elapsed > 0.0).then(|| {
+                    queries_received.saturating_sub(previous.queries_received) as f64 / elapsed
+                })
+            })
+            .unwrap_or(0.0);
+        self.app_state.dht_status = Some(DhtStatus {
+            routing_table_nodes,
+            public_address: info.public_address().map(SocketAddr::V4),
+            firewalled: info.firewalled(),
+            server_mode: info.server_mode(),
+            local_port: info.local_addr().port(),
+            network_size_estimate: info.dht_size_estimate().0,
+            queries_per_sec,
+            queries_received,
+            refreshed_at: Some(now),
         });
+    }
 
-        self.app_state.torrent_list_order = torrent_list;
+    /// Retries every torrent whose automatic retry is due.
+    async fn retry_due_failed_torrents(&mut self) {
+        let now = Instant::now();
+        let due: Vec<Vec<u8>> = self
+            .app_state
+            .torrents
+            .iter()
+            .filter(|(_, torrent)| {
+                torrent
+                    .init_failure
+                    .as_ref()
+                    .and_then(|failure| failure.next_retry_at)
+                    .is_some_and(|retry_at| retry_at <= now)
+            })
+            .map(|(info_hash, _)| info_hash.clone())
+            .collect();
 
-        if self.app_state.selected_torrent_index >= self.app_state.torrent_list_order.len() {
-            self.app_state.selected_torrent_index =
-                self.app_state.torrent_list_order.len().saturating_sub(1);
+        for info_hash in due {
+            self.retry_failed_torrent(&info_hash).await;
         }
     }
 
-    pub fn find_most_common_download_path(&mut self) -> Option<PathBuf> {
-        let mut counts: HashMap<PathBuf, usize> = HashMap::new();
+    /// Switches the self-tuner to `profile`: recalculates the starting limits from the
+    /// profile's proportions, pushes them to the resource manager and forgets the old
+    /// best score so tuning starts over within the new bounds.
+    pub async fn apply_performance_profile(&mut self, profile: PerformanceProfile) {
+        self.client_configs.performance_profile = profile;
+        let (limits, _) = calculate_adaptive_limits(&self.client_configs);
+        tracing_event!(
+            Level::INFO,
+            "Self-Tune: Switched to {:?} profile. max_peers={}, disk_reads={}, disk_writes={}",
+            profile,
+            limits.max_connected_peers,
+            limits.disk_read_permits,
+            limits.disk_write_permits
+        );
 
-        for state in self.app_state.torrents.values() {
-            if let Some(parent_path) = state.latest_state.download_path.parent() {
-                *counts.entry(parent_path.to_path_buf()).or_insert(0) += 1;
+        self.app_state.limits = limits.clone();
+        self.app_state.last_tuning_limits = limits.clone();
+        self.app_state.last_tuning_score = 0;
+        self.app_state.current_tuning_score = 0;
+        self.app_state.baseline_speed_ema = 0.0;
+        self.app_state.tuning_countdown = 90;
+
+        let _ = self.resource_manager.update_limits(limits.into_map()).await;
+    }
+
+    /// Creates a file explorer that starts in the first accessible directory out of
+    /// `preferred`, the most common download path and the home directory, so a stale
+    /// or deleted path doesn't stop the picker from opening.
+    pub fn new_file_explorer(
+        &mut self,
+        theme: Theme,
+        preferred: Option<PathBuf>,
+    ) -> std::io::Result<FileExplorer> {
+        let mut file_explorer = FileExplorer::with_theme(theme)?;
+
+        let candidates = preferred
+            .into_iter()
+            .chain(self.find_most_common_download_path())
+            .chain(UserDirs::new().map(|ud| ud.home_dir().to_path_buf()));
+        for candidate in candidates {
+            if candidate.is_dir() && file_explorer.set_cwd(candidate).is_ok() {
+                break;
             }
         }
 
-        counts
-            .into_iter()
-            .max_by_key(|&(_, count)| count)
-            .map(|(path, _)| path)
+        self.app_state.file_picker_error = None;
+        Ok(file_explorer)
     }
 
     pub async fn add_torrent_from_file(
@@ -1724,7 +4688,7 @@ impl App {
         download_path: PathBuf,
         is_validated: bool,
         torrent_control_state: TorrentControlState,
-    ) {
+    ) -> Option<Vec<u8>> {
         let buffer = match fs::read(&path) {
             Ok(buf) => buf,
             Err(e) => {
@@ -1734,7 +4698,7 @@ impl App {
                     &path,
                     e
                 );
-                return;
+                return None;
             }
         };
 
@@ -1747,37 +4711,40 @@ impl App {
                     &path,
                     e
                 );
-                return;
+                return None;
             }
         };
 
-        #[cfg(all(feature = "dht", feature = "pex"))]
+        let info_hash = torrent.info_hash();
+
+        // Adding a torrent again replaces an entry that failed to load on startup.
+        if self
+            .app_state
+            .torrents
+            .get(&info_hash)
+            .is_some_and(|t| t.load_error.is_some())
         {
-            if torrent.info.private == Some(1) {
-                tracing_event!(
-                    Level::ERROR,
-                    "Rejected private torrent '{}' in normal build.",
-                    torrent.info.name
-                );
-                self.app_state.system_error = Some(format!(
-                    "Private Torrent Rejected:'{}' This build (with DHT/PEX) is not safe for private trackers. Please use private builds for this torrent.",
-                    torrent.info.name
-                ));
-                return;
-            }
+            self.app_state.torrents.remove(&info_hash);
+            self.app_state
+                .torrent_list_order
+                .retain(|ih| *ih != info_hash);
         }
 
-        let mut hasher = sha1::Sha1::new();
-        hasher.update(&torrent.info_dict_bencode);
-        let info_hash = hasher.finalize().to_vec();
-
         if self.app_state.torrents.contains_key(&info_hash) {
             tracing_event!(
                 Level::INFO,
-                "Ignoring already present torrent: {}",
+                "Torrent already present, merging its trackers: {}",
                 torrent.info.name
             );
-            return;
+            let trackers = torrent.announce_tiers().into_iter().flatten().collect();
+            let web_seeds = torrent.httpseeds.clone().unwrap_or_default();
+            let is_private = torrent.info.private == Some(1);
+            self.hold_duplicate_sources(&info_hash, trackers, web_seeds, is_private);
+            return None;
+        }
+
+        if !self.app_state.restoring_torrents && !self.make_room_for_torrent(&torrent.info.name) {
+            return None;
         }
 
         let torrent_files_dir = match get_app_paths() {
@@ -1787,7 +4754,7 @@ impl App {
                     Level::ERROR,
                     "Could not determine application data directory."
                 );
-                return;
+                return None;
             }
         };
         if let Err(e) = fs::create_dir_all(&torrent_files_dir) {
@@ -1796,37 +4763,76 @@ impl App {
                 "Could not create torrents data directory: {}",
                 e
             );
-            return;
+            return None;
         }
         let permanent_torrent_path =
             torrent_files_dir.join(format!("{}.torrent", hex::encode(&info_hash)));
-        if let Err(e) = fs::copy(&path, &permanent_torrent_path) {
-            tracing_event!(
-                Level::ERROR,
-                "Failed to copy torrent to data directory: {}",
-                e
-            );
-            return;
+        // Restored and retried torrents are read from the stored copy itself, and
+        // copying a file onto itself truncates it.
+        if path != permanent_torrent_path {
+            if let Err(e) = fs::copy(&path, &permanent_torrent_path) {
+                tracing_event!(
+                    Level::ERROR,
+                    "Failed to copy torrent to data directory: {}",
+                    e
+                );
+                return None;
+            }
         }
 
+        let torrent_or_magnet = permanent_torrent_path.to_string_lossy().to_string();
+        let saved_settings = self.saved_torrent_settings(&torrent_or_magnet);
+        let (download_path, completed_path) =
+            self.incomplete_download_path(&torrent_or_magnet, download_path, is_validated);
+        let completed_dir = self.completed_dir_for_new_torrent(
+            saved_settings.category.as_deref(),
+            completed_path.as_ref(),
+            is_validated,
+        );
+
+        let torrent_control_state =
+            self.control_state_for_download_path(&download_path, torrent_control_state);
+        let torrent_control_state = self.control_state_for_queue(torrent_control_state);
+
         let placeholder_state = TorrentDisplayState {
             latest_state: TorrentState {
                 torrent_control_state: torrent_control_state.clone(),
                 info_hash: info_hash.clone(),
-                torrent_or_magnet: permanent_torrent_path.to_string_lossy().to_string(),
+                torrent_or_magnet,
                 torrent_name: torrent.info.name.clone(),
                 download_path: download_path.clone(),
-                number_of_pieces_total: (torrent.info.pieces.len() / 20) as u32,
+                upload_disabled: saved_settings.upload_disabled,
+                download_disabled: saved_settings.download_disabled,
+                max_download_bytes: saved_settings.max_download_bytes,
+                file_priorities: saved_settings.file_priorities.clone(),
+                total_downloaded: saved_settings.total_downloaded,
+                total_uploaded: saved_settings.total_uploaded,
+                seeding_time: Duration::from_secs(saved_settings.seeding_secs),
+                seed_goals: saved_settings.seed_goals.clone(),
+                category: saved_settings.category.clone(),
+                completed_path,
+                file_renames: saved_settings.file_renames.clone(),
+                display_name: saved_settings.display_name.clone(),
+                sequential: saved_settings.sequential,
+                bandwidth_priority: saved_settings.bandwidth_priority,
+                dht_disabled: saved_settings.dht_disabled,
+                pex_disabled: saved_settings.pex_disabled,
+                extra_trackers: saved_settings.extra_trackers.clone(),
+                extra_web_seeds: saved_settings.extra_web_seeds.clone(),
+                is_private: torrent.info.private == Some(1),
+                number_of_pieces_total: u32::try_from(torrent.piece_count()).unwrap_or(u32::MAX),
                 ..Default::default()
             },
+            added_at: Some(Instant::now()),
             ..Default::default()
         };
         self.app_state
             .torrents
             .insert(info_hash.clone(), placeholder_state);
         self.app_state.torrent_list_order.push(info_hash.clone());
+        self.enqueue_torrent(&info_hash);
 
-        let (incoming_peer_tx, incoming_peer_rx) = mpsc::channel::<(TcpStream, Vec<u8>)>(100);
+        let (incoming_peer_tx, incoming_peer_rx) = mpsc::channel::<IncomingPeer>(100);
         self.torrent_manager_incoming_peer_txs
             .insert(info_hash.clone(), incoming_peer_tx);
         let (manager_command_tx, manager_command_rx) = mpsc::channel::<ManagerCommand>(100);
@@ -1843,28 +4849,52 @@ impl App {
         let dht_clone = self.distributed_hash_table.clone();
         #[cfg(not(feature = "dht"))]
         let dht_clone = ();
+        #[cfg(feature = "dht")]
+        let (dht4_clone, dht6_clone) = (self.dht4.clone(), self.dht6.clone());
+        #[cfg(not(feature = "dht"))]
+        let (dht4_clone, dht6_clone) = (None, None);
 
         let torrent_params = TorrentParameters {
             dht_handle: dht_clone,
+            dht4: dht4_clone,
+            dht6: dht6_clone,
             incoming_peer_rx,
             metrics_tx: torrent_tx_clone,
             torrent_validation_status: is_validated,
             download_dir: download_path,
+            completed_dir,
             manager_command_rx,
             manager_event_tx: manager_event_tx_clone,
             settings: Arc::clone(&Arc::new(self.client_configs.clone())),
             resource_manager: resource_manager_clone,
+            hash_pool: self.hash_pool.clone(),
+            validation_permits: self.validation_permits.clone(),
             global_dl_bucket: global_dl_bucket_clone,
             global_ul_bucket: global_ul_bucket_clone,
+            upload_disabled: saved_settings.upload_disabled,
+            download_disabled: saved_settings.download_disabled,
+            max_download_bytes: saved_settings.max_download_bytes,
+            file_priorities: saved_settings.file_priorities,
+            file_renames: saved_settings.file_renames,
+            sequential: saved_settings.sequential,
+            bandwidth_priority: saved_settings.bandwidth_priority,
+            dht_disabled: saved_settings.dht_disabled,
+            pex_disabled: saved_settings.pex_disabled,
+            extra_trackers: saved_settings.extra_trackers,
+            extra_web_seeds: saved_settings.extra_web_seeds,
+            ban_list: self.ban_list.clone(),
+            utp_socket: self.utp_socket.clone(),
         };
 
+        let torrent_name = torrent.info.name.clone();
         match TorrentManager::from_torrent(torrent_params, torrent) {
             Ok(torrent_manager) => {
                 tokio::spawn(async move {
                     let _ = torrent_manager
-                        .run(torrent_control_state == TorrentControlState::Paused)
+                        .run(torrent_control_state != TorrentControlState::Running)
                         .await;
                 });
+                self.push_status_message(format!("Added '{}'", torrent_name));
             }
             Err(e) => {
                 tracing_event!(
@@ -1872,12 +4902,10 @@ impl App {
                     "Failed to create torrent manager from file: {:?}",
                     e
                 );
-                self.app_state.torrents.remove(&info_hash);
-                self.app_state
-                    .torrent_list_order
-                    .retain(|ih| *ih != info_hash);
+                self.keep_failed_torrent(&info_hash, &e);
             }
         }
+        Some(info_hash)
     }
 
     pub async fn add_magnet_torrent(
@@ -1887,12 +4915,12 @@ impl App {
         download_path: PathBuf,
         is_validated: bool,
         torrent_control_state: TorrentControlState,
-    ) {
+    ) -> Option<Vec<u8>> {
         let magnet = match Magnet::new(&magnet_link) {
             Ok(m) => m,
             Err(e) => {
                 tracing_event!(Level::ERROR, "Could not parse invalid magnet: {:?}", e);
-                return;
+                return None;
             }
         };
 
@@ -1900,7 +4928,7 @@ impl App {
             Some(hash) => hash,
             None => {
                 tracing_event!(Level::ERROR, "Magnet link is missing info_hash");
-                return;
+                return None;
             }
         };
 
@@ -1908,15 +4936,49 @@ impl App {
             Ok(hash) => hash,
             Err(e) => {
                 tracing_event!(Level::ERROR, "Failed to decode info_hash: {}", e);
-                return;
+                return None;
             }
         };
 
+        // Adding a magnet again replaces an entry whose manager failed to start.
+        if self
+            .app_state
+            .torrents
+            .get(&info_hash)
+            .is_some_and(|t| t.load_error.is_some())
+        {
+            self.app_state.torrents.remove(&info_hash);
+            self.app_state
+                .torrent_list_order
+                .retain(|ih| *ih != info_hash);
+        }
+
         if self.app_state.torrents.contains_key(&info_hash) {
-            tracing_event!(Level::INFO, "Ignoring already present torrent from magnet");
-            return;
+            tracing_event!(
+                Level::INFO,
+                "Torrent from magnet already present, merging its trackers"
+            );
+            self.hold_duplicate_sources(&info_hash, magnet_trackers(&magnet), Vec::new(), false);
+            return None;
+        }
+
+        if !self.app_state.restoring_torrents && !self.make_room_for_torrent(&torrent_name) {
+            return None;
         }
 
+        let saved_settings = self.saved_torrent_settings(&magnet_link);
+        let (download_path, completed_path) =
+            self.incomplete_download_path(&magnet_link, download_path, is_validated);
+        let completed_dir = self.completed_dir_for_new_torrent(
+            saved_settings.category.as_deref(),
+            completed_path.as_ref(),
+            is_validated,
+        );
+
+        let torrent_control_state =
+            self.control_state_for_download_path(&download_path, torrent_control_state);
+        let torrent_control_state = self.control_state_for_queue(torrent_control_state);
+
         let placeholder_state = TorrentDisplayState {
             latest_state: TorrentState {
                 torrent_control_state: torrent_control_state.clone(),
@@ -1924,16 +4986,36 @@ impl App {
                 torrent_or_magnet: magnet_link.clone(),
                 torrent_name,
                 download_path: download_path.clone(),
+                upload_disabled: saved_settings.upload_disabled,
+                download_disabled: saved_settings.download_disabled,
+                max_download_bytes: saved_settings.max_download_bytes,
+                file_priorities: saved_settings.file_priorities.clone(),
+                total_downloaded: saved_settings.total_downloaded,
+                total_uploaded: saved_settings.total_uploaded,
+                seeding_time: Duration::from_secs(saved_settings.seeding_secs),
+                seed_goals: saved_settings.seed_goals.clone(),
+                category: saved_settings.category.clone(),
+                completed_path,
+                file_renames: saved_settings.file_renames.clone(),
+                display_name: saved_settings.display_name.clone(),
+                sequential: saved_settings.sequential,
+                bandwidth_priority: saved_settings.bandwidth_priority,
+                dht_disabled: saved_settings.dht_disabled,
+                pex_disabled: saved_settings.pex_disabled,
+                extra_trackers: saved_settings.extra_trackers.clone(),
+                extra_web_seeds: saved_settings.extra_web_seeds.clone(),
                 ..Default::default()
             },
+            added_at: Some(Instant::now()),
             ..Default::default()
         };
         self.app_state
             .torrents
             .insert(info_hash.clone(), placeholder_state);
         self.app_state.torrent_list_order.push(info_hash.clone());
+        self.enqueue_torrent(&info_hash);
 
-        let (incoming_peer_tx, incoming_peer_rx) = mpsc::channel::<(TcpStream, Vec<u8>)>(100);
+        let (incoming_peer_tx, incoming_peer_rx) = mpsc::channel::<IncomingPeer>(100);
         self.torrent_manager_incoming_peer_txs
             .insert(info_hash.clone(), incoming_peer_tx);
         let (manager_command_tx, manager_command_rx) = mpsc::channel::<ManagerCommand>(100);
@@ -1941,6 +5023,10 @@ impl App {
             .insert(info_hash.clone(), manager_command_tx);
 
         let dht_clone = self.distributed_hash_table.clone();
+        #[cfg(feature = "dht")]
+        let (dht4_clone, dht6_clone) = (self.dht4.clone(), self.dht6.clone());
+        #[cfg(not(feature = "dht"))]
+        let (dht4_clone, dht6_clone) = (None, None);
         let torrent_tx_clone = self.torrent_tx.clone();
         let manager_event_tx_clone = self.manager_event_tx.clone();
         let resource_manager_clone = self.resource_manager.clone();
@@ -1948,25 +5034,46 @@ impl App {
         let global_ul_bucket_clone = self.global_ul_bucket.clone();
         let torrent_params = TorrentParameters {
             dht_handle: dht_clone,
+            dht4: dht4_clone,
+            dht6: dht6_clone,
             incoming_peer_rx,
             metrics_tx: torrent_tx_clone,
             torrent_validation_status: is_validated,
             download_dir: download_path,
+            completed_dir,
             manager_command_rx,
             manager_event_tx: manager_event_tx_clone,
             settings: Arc::clone(&Arc::new(self.client_configs.clone())),
             resource_manager: resource_manager_clone,
+            hash_pool: self.hash_pool.clone(),
+            validation_permits: self.validation_permits.clone(),
             global_dl_bucket: global_dl_bucket_clone,
             global_ul_bucket: global_ul_bucket_clone,
+            upload_disabled: saved_settings.upload_disabled,
+            download_disabled: saved_settings.download_disabled,
+            max_download_bytes: saved_settings.max_download_bytes,
+            file_priorities: saved_settings.file_priorities,
+            file_renames: saved_settings.file_renames,
+            sequential: saved_settings.sequential,
+            bandwidth_priority: saved_settings.bandwidth_priority,
+            dht_disabled: saved_settings.dht_disabled,
+            pex_disabled: saved_settings.pex_disabled,
+            extra_trackers: saved_settings.extra_trackers,
+            extra_web_seeds: saved_settings.extra_web_seeds,
+            ban_list: self.ban_list.clone(),
+            utp_socket: self.utp_socket.clone(),
         };
 
+        let file_selection = magnet_select_only(&magnet_link);
         match TorrentManager::from_magnet(torrent_params, magnet) {
             Ok(torrent_manager) => {
+                let torrent_manager = torrent_manager.with_magnet_file_selection(file_selection);
                 tokio::spawn(async move {
                     let _ = torrent_manager
-                        .run(torrent_control_state == TorrentControlState::Paused)
+                        .run(torrent_control_state != TorrentControlState::Running)
                         .await;
                 });
+                self.push_status_message("Added magnet link, fetching metadata");
             }
             Err(e) => {
                 tracing_event!(
@@ -1974,12 +5081,10 @@ impl App {
                     "Failed to create new torrent manager from magnet: {:?}",
                     e
                 );
-                self.app_state.torrents.remove(&info_hash);
-                self.app_state
-                    .torrent_list_order
-                    .retain(|ih| *ih != info_hash);
+                self.keep_failed_torrent(&info_hash, &e);
             }
         }
+        Some(info_hash)
     }
 
     async fn process_pending_commands(&mut self) {
@@ -2057,6 +5162,311 @@ fn calculate_thrash_score_seek_cost_f64(history_log: &VecDeque<DiskIoOperation>)
     total_seek_distance as f64 / total_bytes_transferred as f64
 }
 
+/// The peers shown in the peer table, in table order. Peers still connecting are
+/// hidden once any connection is established.
+pub fn displayed_peers(
+    state: &TorrentState,
+    peer_sort: (PeerSortColumn, SortDirection),
+) -> Vec<PeerInfo> {
+    let has_established_peers = state.peers.iter().any(|p| p.last_action != "Connecting...");
+
+    let mut peers_to_display: Vec<PeerInfo> = if has_established_peers {
+        state
+            .peers
+            .iter()
+            .filter(|p| p.last_action != "Connecting...")
+            .cloned()
+            .collect()
+    } else {
+        state.peers.clone()
+    };
+
+    let (sort_by, sort_direction) = peer_sort;
+    peers_to_display.sort_by(|a, b| {
+        let ordering = match sort_by {
+            PeerSortColumn::Flags => {
+                let mut a_score = 0;
+                if !a.peer_choking {
+                    a_score += 2;
+                }
+                if !a.am_choking {
+                    a_score += 1;
+                }
+                let mut b_score = 0;
+                if !b.peer_choking {
+                    b_score += 2;
+                }
+                if !b.am_choking {
+                    b_score += 1;
+                }
+                b_score.cmp(&a_score)
+            }
+            PeerSortColumn::Completed => {
+                let total_pieces = state.number_of_pieces_total as usize;
+                if total_pieces == 0 {
+                    return std::cmp::Ordering::Equal;
+                }
+                let a_completed = a.bitfield.iter().take(total_pieces).filter(|&h| h).count();
+                let a_percent = a_completed as f64 / total_pieces as f64;
+                let b_completed = b.bitfield.iter().take(total_pieces).filter(|&h| h).count();
+                let b_percent = b_completed as f64 / total_pieces as f64;
+                b_percent.total_cmp(&a_percent)
+            }
+            // Numeric order, IPv4 before IPv6
+            PeerSortColumn::Address => a
+                .address
+                .parse::<SocketAddr>()
+                .ok()
+                .cmp(&b.address.parse::<SocketAddr>().ok())
+                .then_with(|| a.address.cmp(&b.address)),
+            PeerSortColumn::Client => a.peer_id.cmp(&b.peer_id),
+            PeerSortColumn::Action => a.last_action.cmp(&b.last_action),
+            PeerSortColumn::DL => a.download_speed_bps.cmp(&b.download_speed_bps),
+            PeerSortColumn::UL => a.upload_speed_bps.cmp(&b.upload_speed_bps),
+            PeerSortColumn::TotalDL => a.total_downloaded.cmp(&b.total_downloaded),
+            PeerSortColumn::TotalUL => a.total_uploaded.cmp(&b.total_uploaded),
+            PeerSortColumn::Country => a.country.cmp(&b.country),
+            PeerSortColumn::Source => a.source.cmp(&b.source),
+        };
+
+        if sort_direction == SortDirection::Ascending {
+            ordering
+        } else {
+            ordering.reverse()
+        }
+    });
+
+    peers_to_display
+}
+
+/// Binds the uTP socket on UDP `port` if `peer_transport` uses uTP, on the IPv4
+/// address of `listen_interface` when one is set. A failure is logged and leaves
+/// peers on TCP.
+async fn bind_utp(
+    settings: &Settings,
+    port: u16,
+) -> Option<(UtpSocket, mpsc::Receiver<(UtpStream, SocketAddr)>)> {
+    if !settings.peer_transport.uses_utp() {
+        return None;
+    }
+    let ip = match settings.listen_interface.as_deref() {
+        Some(listen_interface) => {
+            let ipv4 = interface_addresses(listen_interface)
+                .ok()
+                .and_then(|addresses| addresses.into_iter().find(IpAddr::is_ipv4));
+            let Some(ipv4) = ipv4 else {
+                tracing_event!(
+                    Level::WARN,
+                    "{} has no IPv4 address for uTP. Peers will use TCP.",
+                    listen_interface
+                );
+                return None;
+            };
+            ipv4
+        }
+        None => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+    };
+    match UtpSocket::bind(SocketAddr::new(ip, port)).await {
+        Ok(bound) => Some(bound),
+        Err(e) => {
+            tracing_event!(
+                Level::WARN,
+                "Failed to bind uTP to UDP port {}: {}. Peers will use TCP.",
+                port,
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Binds the TCP peer listeners on `port`. The IPv6 socket goes first: where it is
+/// dual-stack the IPv4 bind then fails with `AddrInUse` and the IPv6 socket serves
+/// both; elsewhere each family gets its own. Without IPv6 only IPv4 is bound.
+async fn bind_peer_listeners(
+    settings: &Settings,
+    port: u16,
+) -> std::io::Result<(tokio::net::TcpListener, Option<tokio::net::TcpListener>)> {
+    if let Some(listen_interface) = settings.listen_interface.as_deref() {
+        return bind_interface_listeners(listen_interface, port).await;
+    }
+
+    let listener_v6 = tokio::net::TcpListener::bind((Ipv6Addr::UNSPECIFIED, port))
+        .await
+        .ok();
+    // Port 0 picks a free port; keep both families on the same one.
+    let port = listener_v6
+        .as_ref()
+        .and_then(|l| l.local_addr().ok())
+        .map_or(port, |addr| addr.port());
+
+    match (
+        tokio::net::TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).await,
+        listener_v6,
+    ) {
+        (Ok(listener), listener_v6) => Ok((listener, listener_v6)),
+        (Err(e), Some(listener_v6)) if e.kind() == std::io::ErrorKind::AddrInUse => {
+            Ok((listener_v6, None))
+        }
+        (Err(e), _) => Err(e),
+    }
+}
+
+/// Binds the TCP peer listeners on `port` at the addresses of `listen_interface`
+/// only: its first IPv4 address and its first IPv6 one, when it has both. Fails if
+/// the interface is missing, so peers can't reach us over another route.
+async fn bind_interface_listeners(
+    listen_interface: &str,
+    port: u16,
+) -> std::io::Result<(tokio::net::TcpListener, Option<tokio::net::TcpListener>)> {
+    let addresses = interface_addresses(listen_interface)?;
+    let (first, second) = match addresses.iter().copied().find(IpAddr::is_ipv4) {
+        Some(ipv4) => (ipv4, addresses.iter().copied().find(IpAddr::is_ipv6)),
+        // IPv6 only
+        None => (addresses[0], None),
+    };
+
+    let listener = tokio::net::TcpListener::bind((first, port)).await?;
+    // Port 0 picks a free port; keep both families on the same one.
+    let port = listener.local_addr()?.port();
+    let listener_v6 = match second {
+        Some(ip) => tokio::net::TcpListener::bind((ip, port)).await.ok(),
+        None => None,
+    };
+    Ok((listener, listener_v6))
+}
+
+/// Binds a TCP peer listener on each of `extra_listen_ports`, the same way as the
+/// one on `client_port`. Ports that can't be bound are logged and skipped.
+async fn bind_extra_listeners(settings: &Settings) -> Vec<tokio::net::TcpListener> {
+    let mut listeners = Vec::new();
+    for &port in &settings.extra_listen_ports {
+        if port == 0 || port == settings.client_port {
+            continue;
+        }
+        match bind_peer_listeners(settings, port).await {
+            Ok((listener, listener_v6)) => {
+                listeners.push(listener);
+                listeners.extend(listener_v6);
+            }
+            Err(e) => {
+                tracing_event!(
+                    Level::WARN,
+                    "Failed to bind extra listen port {}: {}",
+                    port,
+                    e
+                );
+            }
+        }
+    }
+    listeners
+}
+
+/// Accepts on whichever of `listeners` has a peer waiting. Never resolves when
+/// there are none.
+async fn accept_any(
+    listeners: &[tokio::net::TcpListener],
+) -> std::io::Result<(tokio::net::TcpStream, SocketAddr)> {
+    std::future::poll_fn(|cx| {
+        for listener in listeners {
+            if let Poll::Ready(result) = listener.poll_accept(cx) {
+                return Poll::Ready(result);
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+/// Accepts on `listener`. Never resolves when there is none.
+async fn accept_if_bound(
+    listener: Option<&tokio::net::TcpListener>,
+) -> std::io::Result<(tokio::net::TcpStream, SocketAddr)> {
+    match listener {
+        Some(listener) => listener.accept().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// The next peer connecting over uTP. Never resolves while uTP is off.
+async fn next_utp_connection(
+    utp_incoming_rx: &mut Option<mpsc::Receiver<(UtpStream, SocketAddr)>>,
+) -> (UtpStream, SocketAddr) {
+    if let Some(receiver) = utp_incoming_rx {
+        if let Some(connection) = receiver.recv().await {
+            return connection;
+        }
+        *utp_incoming_rx = None;
+    }
+    std::future::pending().await
+}
+
+/// Builds the DHT node for `settings.dht_mode`. `ReadOnly` only leaves out forced
+/// server mode: the node starts out as a read-only client (BEP 43), but mainline's
+/// adaptive mode still switches it to a server once it finds it isn't firewalled.
+/// `Off` still needs a handle for the managers, so it gets a node with no bootstrap
+/// nodes that never joins the network; managers don't query it. `known_nodes` (the
+/// routing table of an earlier node) are tried before the configured bootstrap nodes.
+#[cfg(feature = "dht")]
+fn build_dht(settings: &Settings, port: u16, known_nodes: &[String]) -> std::io::Result<AsyncDht> {
+    // uTP listens on UDP `port`, so the DHT takes the port above it. Announces carry
+    // `client_port` explicitly, so peers still find us there.
+    let port = if settings.peer_transport.uses_utp() {
+        port.checked_add(1).unwrap_or(0)
+    } else {
+        port
+    };
+    let bootstrap_nodes: Vec<&str> = known_nodes
+        .iter()
+        .chain(&settings.bootstrap_nodes)
+        .map(AsRef::as_ref)
+        .collect();
+
+    // Node ID security (BEP 42) is handled by mainline: once other nodes report our
+    // public IP it derives our ID from it, and it checks the IDs of nodes it adds to
+    // the routing table against their IPs.
+    let dht = match settings.dht_mode {
+        DhtMode::Server => Dht::builder()
+            .bootstrap(&bootstrap_nodes)
+            .port(port)
+            .server_mode()
+            .build()?,
+        DhtMode::ReadOnly => Dht::builder()
+            .bootstrap(&bootstrap_nodes)
+            .port(port)
+            .build()?,
+        DhtMode::Off => {
+            let no_bootstrap: [&str; 0] = [];
+            Dht::builder().bootstrap(&no_bootstrap).port(0).build()?
+        }
+    };
+    Ok(dht.as_async())
+}
+
+/// Lookup-only clients for IPv4 and IPv6, for what mainline can't do: scrapes,
+/// and anything over IPv6.
+#[cfg(feature = "dht")]
+async fn build_dht_clients(settings: &Settings) -> (Option<DhtClient>, Option<DhtClient>) {
+    if settings.dht_mode == DhtMode::Off {
+        return (None, None);
+    }
+    let bootstrap_nodes = dht_client::resolve_bootstrap_nodes(&settings.bootstrap_nodes).await;
+    let bootstrap_nodes = &bootstrap_nodes;
+    let bind = move |ip: IpAddr| async move {
+        match DhtClient::bind(ip, bootstrap_nodes).await {
+            Ok(client) => Some(client),
+            Err(e) => {
+                tracing_event!(Level::INFO, "DHT client on {} unavailable: {}", ip, e);
+                None
+            }
+        }
+    };
+    tokio::join!(
+        bind(Ipv4Addr::UNSPECIFIED.into()),
+        bind(Ipv6Addr::UNSPECIFIED.into())
+    )
+}
+
 fn calculate_adaptive_limits(client_configs: &Settings) -> (CalculatedLimits, Option<String>) {
     let effective_limit;
     let mut system_warning = None;
@@ -2101,15 +5511,17 @@ fn calculate_adaptive_limits(client_configs: &Settings) -> (CalculatedLimits, Op
 
     let available_budget_after_reservation = effective_limit.saturating_sub(FILE_HANDLE_MINIMUM);
     let safe_budget = available_budget_after_reservation as f64 * SAFE_BUDGET_PERCENTAGE;
-    const PEER_PROPORTION: f64 = 0.70;
-    const DISK_READ_PROPORTION: f64 = 0.15;
-    const DISK_WRITE_PROPORTION: f64 = 0.15;
+    let tuning = TuningProfile::for_profile(client_configs.performance_profile);
+    let disk_permits = |proportion: f64| {
+        ((safe_budget * proportion).max(4.0) as usize).clamp(tuning.min_disk, tuning.max_disk)
+    };
 
     let limits = CalculatedLimits {
         reserve_permits: 0,
-        max_connected_peers: (safe_budget * PEER_PROPORTION).max(10.0) as usize,
-        disk_read_permits: (safe_budget * DISK_READ_PROPORTION).max(4.0) as usize,
-        disk_write_permits: (safe_budget * DISK_WRITE_PROPORTION).max(4.0) as usize,
+        max_connected_peers: (safe_budget * tuning.peer_proportion).max(10.0) as usize,
+        disk_read_permits: disk_permits(tuning.disk_read_proportion),
+        disk_write_permits: disk_permits(tuning.disk_write_proportion),
+        connection_attempt_permits: client_configs.connection_attempt_permits.max(1),
     };
 
     (limits, system_warning)
@@ -2118,11 +5530,56 @@ fn calculate_adaptive_limits(client_configs: &Settings) -> (CalculatedLimits, Op
 const MIN_STEP_RATE: f64 = 0.01;
 const MAX_STEP_RATE: f64 = 0.10;
 
-// --- Define Min/Max bounds for all resource types ---
-const MIN_PEERS: usize = 20;
-const MIN_DISK: usize = 2;
 const MIN_RESERVE: usize = 0;
 
+/// Self-tuner parameters behind each `PerformanceProfile`.
+struct TuningProfile {
+    // Share of the file handle budget each resource starts with
+    peer_proportion: f64,
+    disk_read_proportion: f64,
+    disk_write_proportion: f64,
+    // Bounds the tuner may trade within
+    min_peers: usize,
+    min_disk: usize,
+    max_disk: usize,
+    // Multiplier on the learned seek cost before a score is penalised for thrashing
+    thrash_tolerance: f64,
+}
+
+impl TuningProfile {
+    fn for_profile(profile: PerformanceProfile) -> Self {
+        match profile {
+            PerformanceProfile::MaxSpeed => Self {
+                peer_proportion: 0.75,
+                disk_read_proportion: 0.125,
+                disk_write_proportion: 0.125,
+                min_peers: 50,
+                min_disk: 4,
+                max_disk: usize::MAX,
+                thrash_tolerance: 2.0,
+            },
+            PerformanceProfile::Balanced => Self {
+                peer_proportion: 0.70,
+                disk_read_proportion: 0.15,
+                disk_write_proportion: 0.15,
+                min_peers: 20,
+                min_disk: 2,
+                max_disk: usize::MAX,
+                thrash_tolerance: 1.0,
+            },
+            PerformanceProfile::QuietDisk => Self {
+                peer_proportion: 0.80,
+                disk_read_proportion: 0.10,
+                disk_write_proportion: 0.10,
+                min_peers: 20,
+                min_disk: 2,
+                max_disk: 16,
+                thrash_tolerance: 0.5,
+            },
+        }
+    }
+}
+
 // --- Maximum attempts to find a valid trade per cycle ---
 const MAX_TRADE_ATTEMPTS: usize = 5;
 
@@ -2132,6 +5589,7 @@ fn get_limit(limits: &CalculatedLimits, resource: ResourceType) -> usize {
         ResourceType::DiskRead => limits.disk_read_permits,
         ResourceType::DiskWrite => limits.disk_write_permits,
         ResourceType::Reserve => limits.reserve_permits,
+        ResourceType::ConnectionAttempt => limits.connection_attempt_permits,
     }
 }
 
@@ -2141,12 +5599,16 @@ fn set_limit(limits: &mut CalculatedLimits, resource: ResourceType, value: usize
         ResourceType::DiskRead => limits.disk_read_permits = value,
         ResourceType::DiskWrite => limits.disk_write_permits = value,
         ResourceType::Reserve => limits.reserve_permits = value,
+        ResourceType::ConnectionAttempt => limits.connection_attempt_permits = value,
     }
 }
 
 /// Makes a random, proportional trade, retrying a few times if the first is blocked.
 /// This version is refactored to support any number of resources, including Reserve.
-fn make_random_adjustment(mut limits: CalculatedLimits) -> (CalculatedLimits, String) {
+fn make_random_adjustment(
+    mut limits: CalculatedLimits,
+    tuning: &TuningProfile,
+) -> (CalculatedLimits, String) {
     let mut rng = rand::rng();
     let mut parameters = [
         ResourceType::PeerConnection,
@@ -2166,10 +5628,16 @@ fn make_random_adjustment(mut limits: CalculatedLimits) -> (CalculatedLimits, St
         let dest_val = get_limit(&limits, dest_param);
 
         let source_min = match source_param {
-            ResourceType::PeerConnection => MIN_PEERS,
-            ResourceType::DiskRead => MIN_DISK,
-            ResourceType::DiskWrite => MIN_DISK,
+            ResourceType::PeerConnection => tuning.min_peers,
+            ResourceType::DiskRead => tuning.min_disk,
+            ResourceType::DiskWrite => tuning.min_disk,
             ResourceType::Reserve => MIN_RESERVE,
+            // Not in the trading pool
+            ResourceType::ConnectionAttempt => usize::MAX,
+        };
+        let dest_max = match dest_param {
+            ResourceType::DiskRead | ResourceType::DiskWrite => tuning.max_disk,
+            _ => usize::MAX,
         };
 
         // 3. Calculate random step rate and amount to trade
@@ -2178,8 +5646,9 @@ fn make_random_adjustment(mut limits: CalculatedLimits) -> (CalculatedLimits, St
 
         // 4. Check if this specific trade is possible
         let can_give = source_val >= source_min.saturating_add(amount_to_trade);
+        let can_take = dest_val.saturating_add(amount_to_trade) <= dest_max;
 
-        if can_give {
+        if can_give && can_take {
             // --- VALID TRADE FOUND ---
             // 5. Perform the 1-for-1 trade
             set_limit(
@@ -2215,6 +5684,19 @@ fn make_random_adjustment(mut limits: CalculatedLimits) -> (CalculatedLimits, St
     (limits, description)
 }
 
+/// Whether a running process is the one named in `pause_when_process_running`,
+/// ignoring case and a Windows ".exe" suffix on either side.
+fn process_name_matches(process_name: &std::ffi::OsStr, wanted: &str) -> bool {
+    let strip = |name: &str| {
+        let name = name.trim().to_ascii_lowercase();
+        name.strip_suffix(".exe")
+            .map(str::to_string)
+            .unwrap_or(name)
+    };
+    let wanted = strip(wanted);
+    !wanted.is_empty() && strip(&process_name.to_string_lossy()) == wanted
+}
+
 pub fn decode_info_hash(hash_string: &str) -> Result<Vec<u8>, String> {
     if hash_string.len() == 40 {
         // It's Hex encoded
@@ -2229,6 +5711,66 @@ pub fn decode_info_hash(hash_string: &str) -> Result<Vec<u8>, String> {
     }
 }
 
+/// The state for a torrent being added: `Queued` instead of `Running` when
+/// `downloads` already fill all `max_downloads` slots (0 for no limit).
+fn new_torrent_control_state(
+    torrent_control_state: TorrentControlState,
+    downloads: usize,
+    max_downloads: usize,
+) -> TorrentControlState {
+    if torrent_control_state == TorrentControlState::Running
+        && max_downloads > 0
+        && downloads >= max_downloads
+    {
+        TorrentControlState::Queued
+    } else {
+        torrent_control_state
+    }
+}
+
+/// Whether each torrent in queue order, given by whether it is complete, gets a
+/// slot (`Running`) or waits (`Queued`). Complete torrents take seed slots, the
+/// rest download slots; a limit of 0 means no limit.
+fn queue_slots(
+    is_complete: impl IntoIterator<Item = bool>,
+    max_downloads: usize,
+    max_seeds: usize,
+) -> Vec<TorrentControlState> {
+    let (mut downloads, mut seeds) = (0, 0);
+    is_complete
+        .into_iter()
+        .map(|is_complete| {
+            let (active, limit) = if is_complete {
+                (&mut seeds, max_seeds)
+            } else {
+                (&mut downloads, max_downloads)
+            };
+            if limit == 0 || *active < limit {
+                *active += 1;
+                TorrentControlState::Running
+            } else {
+                TorrentControlState::Queued
+            }
+        })
+        .collect()
+}
+
+/// Swaps `info_hash` with its neighbour towards the front (`up`) or back of the
+/// queue. Returns its new position, or `None` if it didn't move.
+fn shift_in_queue(queue: &mut [Vec<u8>], info_hash: &[u8], up: bool) -> Option<usize> {
+    let position = queue.iter().position(|ih| ih == info_hash)?;
+    let new_position = if up {
+        position.checked_sub(1)?
+    } else {
+        position + 1
+    };
+    if new_position >= queue.len() {
+        return None;
+    }
+    queue.swap(position, new_position);
+    Some(new_position)
+}
+
 fn aggregate_peers_to_availability(peers: &[PeerInfo], total_pieces: usize) -> Vec<u32> {
     if total_pieces == 0 {
         return Vec::new();
@@ -2236,10 +5778,113 @@ fn aggregate_peers_to_availability(peers: &[PeerInfo], total_pieces: usize) -> V
     let mut availability: Vec<u32> = vec![0; total_pieces];
     for peer in peers {
         for (i, has_piece) in peer.bitfield.iter().enumerate().take(total_pieces) {
-            if *has_piece {
+            if has_piece {
                 availability[i] += 1;
             }
         }
     }
     availability
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TorrentControlState::{Paused, Queued, Running};
+
+    #[test]
+    fn test_queue_slots_follow_download_and_seed_limits() {
+        // Downloads and seeds are counted apart, in queue order.
+        let is_complete = [false, true, false, true, false, true];
+        assert_eq!(
+            queue_slots(is_complete, 2, 1),
+            [Running, Running, Running, Queued, Queued, Queued]
+        );
+        assert_eq!(
+            queue_slots(is_complete, 1, 2),
+            [Running, Running, Queued, Running, Queued, Queued]
+        );
+    }
+
+    #[test]
+    fn test_queue_slots_zero_means_no_limit() {
+        assert_eq!(queue_slots([false; 3], 0, 1), [Running, Running, Running]);
+        assert_eq!(queue_slots([true; 3], 1, 0), [Running, Running, Running]);
+    }
+
+    #[test]
+    fn test_new_torrent_is_queued_when_download_slots_are_full() {
+        assert_eq!(new_torrent_control_state(Running, 1, 2), Running);
+        assert_eq!(new_torrent_control_state(Running, 2, 2), Queued);
+        assert_eq!(new_torrent_control_state(Running, 5, 0), Running);
+        // Paused torrents stay paused whatever the slots.
+        assert_eq!(new_torrent_control_state(Paused, 2, 2), Paused);
+    }
+
+    #[test]
+    fn test_seed_goal_eta_takes_the_nearest_goal() {
+        let state = TorrentState {
+            total_size: 1000,
+            total_downloaded: 1000,
+            total_uploaded: 500,
+            seeding_time: Duration::from_secs(600),
+            ..Default::default()
+        };
+        let goals = |ratio, seed_time_secs| SeedGoals {
+            ratio,
+            seed_time_secs,
+            ..Default::default()
+        };
+
+        // 1500 bytes to go at 10 B/s, or 900 seconds more of seeding.
+        assert_eq!(
+            state.seed_goal_eta(&goals(Some(2.0), None), 10),
+            Some(Duration::from_secs(150))
+        );
+        assert_eq!(
+            state.seed_goal_eta(&goals(None, Some(1500)), 10),
+            Some(Duration::from_secs(900))
+        );
+        assert_eq!(
+            state.seed_goal_eta(&goals(Some(2.0), Some(1500)), 1),
+            Some(Duration::from_secs(900))
+        );
+        assert_eq!(
+            state.seed_goal_eta(&goals(Some(0.5), None), 0),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_seed_goal_eta_is_unknown_without_goal_or_upload() {
+        let state = TorrentState {
+            total_size: 1000,
+            ..Default::default()
+        };
+        assert_eq!(state.seed_goal_eta(&SeedGoals::default(), 10), None);
+        let ratio_only = SeedGoals {
+            ratio: Some(1.0),
+            ..Default::default()
+        };
+        assert_eq!(state.seed_goal_eta(&ratio_only, 0), None);
+    }
+
+    #[test]
+    fn test_shift_in_queue_swaps_with_neighbour() {
+        let mut queue = vec![vec![1], vec![2], vec![3]];
+
+        assert_eq!(shift_in_queue(&mut queue, &[3], true), Some(1));
+        assert_eq!(queue, [vec![1], vec![3], vec![2]]);
+        assert_eq!(shift_in_queue(&mut queue, &[1], false), Some(1));
+        assert_eq!(queue, [vec![3], vec![1], vec![2]]);
+    }
+
+    #[test]
+    fn test_shift_in_queue_stops_at_the_ends() {
+        let mut queue = vec![vec![1], vec![2]];
+
+        assert_eq!(shift_in_queue(&mut queue, &[1], true), None);
+        assert_eq!(shift_in_queue(&mut queue, &[2], false), None);
+        assert_eq!(shift_in_queue(&mut queue, &[9], true), None);
+        assert_eq!(queue, [vec![1], vec![2]]);
+    }
+}
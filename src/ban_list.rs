@@ -0,0 +1,136 @@
+// SPDX-FileCopyrightText: 2025 The superseedr Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::ip_filter::IpFilter;
+
+/// Peers banned by IP until a deadline, plus the ranges blocked by the IP filter.
+/// The app checks it before accepting an incoming connection and every torrent
+/// manager checks it before dialing out.
+#[derive(Clone, Debug, Default)]
+pub struct BanList {
+    bans: Arc<Mutex<HashMap<IpAddr, Instant>>>,
+    ip_filter: Arc<RwLock<IpFilter>>,
+}
+
+impl BanList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bans `ip` for `duration`, replacing any earlier ban.
+    pub fn ban(&self, ip: IpAddr, duration: Duration) {
+        if let Ok(mut bans) = self.bans.lock() {
+            bans.insert(ip, Instant::now() + duration);
+        }
+    }
+
+    /// Lifts every ban and returns how many there were.
+    pub fn clear(&self) -> usize {
+        self.bans
+            .lock()
+            .map(|mut bans| bans.drain().count())
+            .unwrap_or(0)
+    }
+
+    /// Replaces the blocked ranges with a freshly loaded filter.
+    pub fn set_ip_filter(&self, ip_filter: IpFilter) {
+        if let Ok(mut current) = self.ip_filter.write() {
+            *current = ip_filter;
+        }
+    }
+
+    pub fn is_banned(&self, ip: IpAddr) -> bool {
+        if self
+            .ip_filter
+            .read()
+            .is_ok_and(|ip_filter| ip_filter.contains(ip))
+        {
+            return true;
+        }
+        self.bans
+            .lock()
+            .is_ok_and(|bans| bans.get(&ip).is_some_and(|until| *until > Instant::now()))
+    }
+
+    /// Whether the peer at `address` (`ip:port`) is banned.
+    pub fn is_peer_banned(&self, address: &str) -> bool {
+        peer_ip(address).is_some_and(|ip| self.is_banned(ip))
+    }
+
+    /// Drops expired bans and returns how many are left.
+    pub fn active_count(&self) -> usize {
+        self.bans
+            .lock()
+            .map(|mut bans| {
+                let now = Instant::now();
+                bans.retain(|_, until| *until > now);
+                bans.len()
+            })
+            .unwrap_or(0)
+    }
+}
+
+/// The IP part of a peer address. Peers are keyed by socket address (`[::1]:6881`);
+/// a bare `ip:port` is understood too, even for IPv6.
+pub fn peer_ip(address: &str) -> Option<IpAddr> {
+    if let Ok(socket_addr) = address.parse::<SocketAddr>() {
+        return Some(socket_addr.ip());
+    }
+    let (ip, _port) = address.rsplit_once(':')?;
+    ip.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_ip_formats() {
+        let v4: IpAddr = "10.0.0.7".parse().unwrap();
+        let v6: IpAddr = "2001:db8::1".parse().unwrap();
+
+        assert_eq!(peer_ip("10.0.0.7:6881"), Some(v4));
+        assert_eq!(peer_ip("[2001:db8::1]:6881"), Some(v6));
+        assert_eq!(peer_ip("2001:db8::1:6881"), Some(v6));
+        assert_eq!(peer_ip("not an address"), None);
+    }
+
+    #[test]
+    fn test_ban_expires_and_clears() {
+        let ban_list = BanList::new();
+        let ip: IpAddr = "10.0.0.7".parse().unwrap();
+
+        ban_list.ban(ip, Duration::from_secs(60));
+        assert!(ban_list.is_banned(ip));
+        assert!(ban_list.is_peer_banned("10.0.0.7:51413"));
+        assert!(!ban_list.is_peer_banned("10.0.0.8:51413"));
+
+        ban_list.ban("10.0.0.8".parse().unwrap(), Duration::ZERO);
+        assert_eq!(ban_list.active_count(), 1);
+
+        assert_eq!(ban_list.clear(), 1);
+        assert!(!ban_list.is_banned(ip));
+    }
+
+    #[test]
+    fn test_ip_filter_blocks_ranges() {
+        let ban_list = BanList::new();
+        assert!(!ban_list.is_peer_banned("1.2.3.4:6881"));
+
+        ban_list.set_ip_filter(IpFilter::parse("1.2.3.0/24\n"));
+        assert!(ban_list.is_peer_banned("1.2.3.4:6881"));
+        assert!(!ban_list.is_peer_banned("1.2.4.4:6881"));
+
+        // Clearing manual bans leaves the filter in place
+        ban_list.clear();
+        assert!(ban_list.is_peer_banned("1.2.3.4:6881"));
+    }
+}
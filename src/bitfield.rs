@@ -0,0 +1,212 @@
+// SPDX-FileCopyrightText: 2025 The superseedr Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+/// A fixed-length set of piece flags stored one bit per piece, most significant bit
+/// first, which is also the layout of the BitTorrent `bitfield` message. Peers of
+/// torrents with millions of pieces would otherwise cost a byte per piece each.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Bitfield {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl Bitfield {
+    /// Reads a wire-format bitfield as `len` pieces. Missing bytes count as cleared
+    /// bits and spare bits past `len` are dropped.
+    pub fn from_bytes(bytes: &[u8], len: usize) -> Self {
+        let mut bitfield = Self {
+            bytes: bytes.to_vec(),
+            len: bytes.len() * 8,
+        };
+        bitfield.resize(len, false);
+        bitfield
+    }
+
+    /// The bitfield in wire format, with any spare bits in the last byte cleared.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the bit for `index`, or `None` if it is out of range.
+    pub fn get(&self, index: usize) -> Option<bool> {
+        if index >= self.len {
+            return None;
+        }
+        Some(self.bytes[index / 8] & Self::mask(index) != 0)
+    }
+
+    /// Whether `index` is in range and set.
+    pub fn has(&self, index: usize) -> bool {
+        self.get(index) == Some(true)
+    }
+
+    /// Sets the bit for `index`. Out of range indexes are ignored.
+    pub fn set(&mut self, index: usize, value: bool) {
+        if index >= self.len {
+            return;
+        }
+        if value {
+            self.bytes[index / 8] |= Self::mask(index);
+        } else {
+            self.bytes[index / 8] &= !Self::mask(index);
+        }
+    }
+
+    /// Grows or shrinks the bitfield to `len` pieces, filling new bits with `value`.
+    pub fn resize(&mut self, len: usize, value: bool) {
+        let old_len = self.len;
+        self.bytes.resize(len.div_ceil(8), 0);
+        self.len = len;
+
+        if len < old_len {
+            // Clear the spare bits so equality and `as_bytes` stay well defined.
+            let last_byte_mask = self.last_byte_mask();
+            if let Some(last) = self.bytes.last_mut() {
+                *last &= last_byte_mask;
+            }
+        } else if value {
+            for index in old_len..len {
+                self.set(index, true);
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len).map(move |index| self.bytes[index / 8] & Self::mask(index) != 0)
+    }
+
+    pub fn count_ones(&self) -> usize {
+        let Some((last, full)) = self.bytes.split_last() else {
+            return 0;
+        };
+        let full_ones: usize = full.iter().map(|byte| byte.count_ones() as usize).sum();
+        full_ones + (last & self.last_byte_mask()).count_ones() as usize
+    }
+
+    /// Whether every bit is set. An empty bitfield counts as complete.
+    pub fn all(&self) -> bool {
+        let Some((last, full)) = self.bytes.split_last() else {
+            return true;
+        };
+        let last_byte_mask = self.last_byte_mask();
+        full.iter().all(|&byte| byte == 0xFF) && last & last_byte_mask == last_byte_mask
+    }
+
+    fn mask(index: usize) -> u8 {
+        1 << (7 - index % 8)
+    }
+
+    /// The bits of the last byte that are in range.
+    fn last_byte_mask(&self) -> u8 {
+        match self.len % 8 {
+            0 => 0xFF,
+            spare => 0xFF << (8 - spare),
+        }
+    }
+}
+
+impl FromIterator<bool> for Bitfield {
+    fn from_iter<I: IntoIterator<Item = bool>>(iter: I) -> Self {
+        let mut bitfield = Self::default();
+        for has in iter {
+            let index = bitfield.len;
+            bitfield.resize(index + 1, false);
+            bitfield.set(index, has);
+        }
+        bitfield
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_bytes_matches_wire_layout() {
+        let bitfield = Bitfield::from_bytes(&[0b1010_0000, 0b0000_0001], 16);
+
+        assert_eq!(bitfield.len(), 16);
+        assert!(bitfield.has(0));
+        assert!(!bitfield.has(1));
+        assert!(bitfield.has(2));
+        assert!(bitfield.has(15));
+        assert_eq!(bitfield.get(16), None);
+        assert_eq!(bitfield.count_ones(), 3);
+        assert_eq!(bitfield.as_bytes(), &[0b1010_0000, 0b0000_0001]);
+    }
+
+    #[test]
+    fn test_resize_clears_spare_bits() {
+        // A peer may send spare bits set; they must not leak into piece counts.
+        let mut bitfield = Bitfield::from_bytes(&[0xFF, 0xFF], 10);
+        assert_eq!(bitfield.count_ones(), 10);
+        assert_eq!(bitfield.as_bytes(), &[0xFF, 0b1100_0000]);
+
+        bitfield.resize(12, false);
+        assert_eq!(bitfield.count_ones(), 10);
+        assert!(!bitfield.has(11));
+
+        bitfield.resize(20, true);
+        assert_eq!(bitfield.count_ones(), 18);
+        assert!(bitfield.has(19));
+    }
+
+    #[test]
+    fn test_set_and_all() {
+        let mut bitfield = Bitfield::from_bytes(&[], 3);
+        assert!(!bitfield.all());
+
+        bitfield.set(0, true);
+        bitfield.set(1, true);
+        bitfield.set(2, true);
+        bitfield.set(3, true); // Out of range, ignored
+        assert!(bitfield.all());
+        assert_eq!(bitfield.len(), 3);
+
+        bitfield.set(1, false);
+        assert_eq!(bitfield.iter().collect::<Vec<_>>(), vec![true, false, true]);
+    }
+
+    #[test]
+    fn test_count_ones_and_all_across_bytes() {
+        let mut bitfield = Bitfield::from_bytes(&[], 1_003);
+        assert_eq!(bitfield.count_ones(), 0);
+        assert!(!bitfield.all());
+
+        bitfield.resize(2_011, true);
+        assert_eq!(bitfield.count_ones(), 1_008);
+        assert!(!bitfield.all());
+
+        for index in 0..1_003 {
+            bitfield.set(index, true);
+        }
+        assert_eq!(bitfield.count_ones(), 2_011);
+        assert!(bitfield.all());
+
+        bitfield.set(2_010, false);
+        assert_eq!(bitfield.count_ones(), 2_010);
+        assert!(!bitfield.all());
+        bitfield.set(2_010, true);
+        bitfield.set(7, false);
+        assert_eq!(bitfield.count_ones(), 2_010);
+        assert!(!bitfield.all());
+
+        assert!(Bitfield::default().all());
+        assert_eq!(Bitfield::default().count_ones(), 0);
+    }
+
+    #[test]
+    fn test_collect_from_bools() {
+        let bitfield: Bitfield = [true, false, false, true].into_iter().collect();
+        assert_eq!(bitfield.len(), 4);
+        assert_eq!(bitfield.as_bytes(), &[0b1001_0000]);
+    }
+}
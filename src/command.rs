@@ -2,17 +2,27 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
 
+use crate::bitfield::Bitfield;
 use crate::torrent_file::Torrent;
 
-use crate::tracker::TrackerResponse;
+use crate::tracker::{SwarmCounts, TrackerResponse};
 
+use crate::networking::protocol::{HolepunchMessage, PexPeer};
 use crate::networking::BlockInfo;
 
+use crate::errors::PieceVerifyError;
+use crate::errors::WebSeedError;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum TorrentCommand {
     SuccessfullyConnected(String),
     PeerId(String, Vec<u8>),
+    // Our IP as a peer sees it, from its extended handshake
+    PeerReportedIp(String, IpAddr),
 
     Choke(String),
     Unchoke(String),
@@ -24,35 +34,74 @@ pub enum TorrentCommand {
     Have(String, u32),
 
     NotInterested,
+    // Partial seed (BEP 21): whether we now only upload
+    UploadOnly(bool),
 
     ClientInterested,
     PeerInterested(String),
 
-    ClientBitfield(Vec<u8>, Option<i64>),
+    ClientBitfield(Bitfield, Option<Arc<Vec<u8>>>),
     PeerBitfield(String, Vec<u8>),
 
-    RequestDownload(u32, i64, i64),
+    // Fast extension (BEP 6)
+    // The peer supports it; carries the pieces we allow it to fetch while choked
+    PeerFastExtension(String, Vec<u32>),
+    PeerHaveAll(String),
+    SuggestPiece(String, u32),
+    AllowedFast(String, u32),
+    // The peer refused a request for this piece
+    RequestRejected(String, u32),
+    // Tell the peer we won't serve this block
+    RejectUpload(u32, u32, u32),
+
+    // Piece index, piece length, torrent size and the offsets of blocks we already
+    // hold, which aren't requested again
+    RequestDownload(u32, i64, i64, Vec<u32>),
 
     RequestUpload(String, u32, u32, u32),
     Upload(u32, u32, Vec<u8>),
 
     CancelUpload(String, u32, u32, u32),
     Cancel(u32),
+    // Endgame: another peer delivered this block first
+    CancelBlock(u32, u32, u32),
 
     Disconnect(String),
 
-    AddPexPeers(String, Vec<(String, u16)>),
-    SendPexPeers(Vec<String>),
-
-    DhtTorrent(Torrent, i64),
+    AddPexPeers(String, Vec<PexPeer>),
+    // Peers the sender no longer sees in the swarm
+    DropPexPeers(String, Vec<SocketAddr>),
+    // Every peer we're connected to; the session sends the changes (BEP 11)
+    SendPexPeers(Vec<PexPeer>),
+
+    // Holepunch extension (BEP 55)
+    PeerHolepunchExtension(String),
+    // The peer asks us to introduce it to the peer at this address
+    HolepunchRendezvous(String, SocketAddr),
+    // A relay asks us to connect to this address
+    HolepunchConnect(SocketAddr),
+    SendHolepunch(HolepunchMessage),
+
+    // Metadata fetched from a peer (BEP 9), still to be checked against the info hash
+    DhtTorrent(String, Box<Torrent>),
+    // The peer sent metadata that couldn't be decoded
+    MetadataRejected(String),
 
     AnnounceResponse(String, TrackerResponse),
     AnnounceFailed(String, String),
+    ScrapeResponse(String, SwarmCounts),
+    ScrapeFailed(String, String),
 
     PieceVerified {
         piece_index: u32,
         peer_id: String,
-        verification_result: Result<Vec<u8>, ()>,
+        verification_result: Result<Vec<u8>, PieceVerifyError>,
+    },
+    // A piece fetched from an HTTP seed (BEP 17), still to be hashed
+    HttpSeedPiece {
+        url: String,
+        piece_index: u32,
+        result: Result<Vec<u8>, WebSeedError>,
     },
 
     UploadTaskCompleted {
@@ -69,6 +118,12 @@ pub enum TorrentCommand {
     },
 
     UnresponsivePeer(String),
+
+    FinalVerificationComplete {
+        bad_pieces: Vec<u32>,
+    },
+    // The finished download's new root folder, once its files have moved there
+    FilesMoved(Result<PathBuf, String>),
 }
 
 pub struct TorrentCommandSummary<'a>(pub &'a TorrentCommand);
@@ -84,12 +139,12 @@ impl fmt::Debug for TorrentCommandSummary<'_> {
                     data.len()
                 )
             }
-            TorrentCommand::ClientBitfield(bitfield, torrent_metadata_length) => {
+            TorrentCommand::ClientBitfield(bitfield, torrent_metadata) => {
                 write!(
                     f,
                     "CLIENT_BITFIELD(bitfield: {}, len: {:?})",
                     bitfield.len(),
-                    torrent_metadata_length
+                    torrent_metadata.as_ref().map(|metadata| metadata.len())
                 )
             }
             TorrentCommand::PeerBitfield(peer_id, bitfield) => {
@@ -101,6 +156,20 @@ impl fmt::Debug for TorrentCommandSummary<'_> {
                 )
             }
 
+            TorrentCommand::HttpSeedPiece {
+                url,
+                piece_index,
+                result,
+            } => {
+                write!(
+                    f,
+                    "HTTP_SEED_PIECE(seed: {}, index: {}, result: {:?})",
+                    url,
+                    piece_index,
+                    result.as_ref().map(Vec::len)
+                )
+            }
+
             TorrentCommand::Upload(index, begin, data) => {
                 write!(
                     f,
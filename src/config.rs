@@ -5,19 +5,31 @@ use figment::providers::{Env, Format};
 use figment::{providers::Toml, Figment};
 
 use directories::ProjectDirs;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io;
-use std::path::PathBuf;
+#[cfg(feature = "dht")]
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use crate::app::TorrentControlState;
+use crate::scheduler::{RateProfile, ScheduleRule};
+use crate::storage::FileRename;
+use crate::token_bucket::BandwidthPriority;
+use crate::torrent_manager::files::FilePriority;
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
 pub enum TorrentSortColumn {
+    // Position in the download/seed queue
+    Queue,
     Name,
+    Category,
     Down,
     #[default]
     Up,
+    Ratio,
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
@@ -32,6 +44,10 @@ pub enum PeerSortColumn {
     UL,
     TotalDL,
     TotalUL,
+    // Country, or network with an ASN database; empty without `geoip_database_path`
+    Country,
+    // Where we heard of the peer: tracker, DHT, PEX, incoming...
+    Source,
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
@@ -41,6 +57,286 @@ pub enum SortDirection {
     Descending,
 }
 
+/// A column of the torrent or peer table and its share of the table's width, in percent.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct TableColumn<C> {
+    pub column: C,
+    pub width: u16,
+}
+
+/// Controls whether a torrent's content is wrapped in a folder named after the torrent.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum CreateSubfolder {
+    Always,
+    #[default]
+    Never,
+    MultiFileOnly,
+}
+
+/// What to do when a torrent won't fit in the free space of its download folder.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum InsufficientSpaceAction {
+    Refuse,
+    #[default]
+    StartPaused,
+    Ignore,
+}
+
+/// How much detail goes to the log file.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+/// What to do with a new torrent when `max_torrents` are already loaded.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum TorrentLimitPolicy {
+    #[default]
+    RejectNew,
+    // Remove the longest-loaded finished torrent (keeping its files) to make room
+    RemoveOldestComplete,
+}
+
+/// High-level preset for the self-tuner: how much of the resource budget goes to
+/// peers versus disk, and how much disk seeking it tolerates.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum PerformanceProfile {
+    MaxSpeed,
+    #[default]
+    Balanced,
+    QuietDisk,
+}
+
+/// How much this client takes part in the DHT.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum DhtMode {
+    // Finds peers and serves the routing table to other nodes
+    #[default]
+    Server,
+    // Finds peers and starts out not answering other nodes' queries. mainline has no
+    // way to stay a client for good, so it may still become a server once it finds it
+    // isn't firewalled.
+    ReadOnly,
+    Off,
+}
+
+/// Which transports peer connections use.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum PeerTransport {
+    #[default]
+    Tcp,
+    // uTP (BEP 29) over UDP, which backs off when other traffic needs the link
+    Utp,
+    // Accept both; connect over uTP first and fall back to TCP
+    Both,
+}
+
+impl PeerTransport {
+    pub fn uses_utp(self) -> bool {
+        matches!(self, Self::Utp | Self::Both)
+    }
+
+    pub fn uses_tcp(self) -> bool {
+        matches!(self, Self::Tcp | Self::Both)
+    }
+}
+
+/// Inclusive range of TCP/UDP ports to listen on, e.g. `{ start = 50000, end = 60000 }`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct PortRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl PortRange {
+    /// A port picked at random from the range, in either order. Port 0 is never picked.
+    pub fn random_port(self) -> u16 {
+        let low = self.start.min(self.end).max(1);
+        let high = self.start.max(self.end).max(1);
+        rand::rng().random_range(low..=high)
+    }
+}
+
+// Windows has long throttled half-open connections (to 10 on desktop editions),
+// and stalls everything else on the machine when the limit is hit.
+#[cfg(windows)]
+const DEFAULT_CONNECTION_ATTEMPT_PERMITS: usize = 8;
+#[cfg(not(windows))]
+const DEFAULT_CONNECTION_ATTEMPT_PERMITS: usize = 50;
+
+#[cfg(feature = "dht")]
+pub const MIN_DHT_GET_PEERS_INTERVAL_SECS: u64 = 60;
+#[cfg(feature = "dht")]
+pub const MIN_DHT_ANNOUNCE_INTERVAL_SECS: u64 = 300;
+pub const MIN_TRACKER_SCRAPE_INTERVAL_SECS: u64 = 300;
+pub const MAX_FAILED_TORRENT_RETRY_DELAY_SECS: u64 = 3600;
+
+/// A drop folder whose torrents are added to their own download location.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct WatchFolderRule {
+    pub path: PathBuf,
+    // Falls back to the label's category folder, then `default_download_folder`.
+    pub download_folder: Option<PathBuf>,
+    // Category given to torrents added from this folder
+    pub label: Option<String>,
+}
+
+/// Reads `watch_folders`, or the bare path of the old `watch_folder` key as a rule
+/// without a download folder or label.
+fn deserialize_watch_folders<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<WatchFolderRule>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum WatchFolders {
+        Rules(Vec<WatchFolderRule>),
+        Path(PathBuf),
+    }
+
+    Ok(match WatchFolders::deserialize(deserializer)? {
+        WatchFolders::Rules(rules) => rules,
+        WatchFolders::Path(path) => vec![WatchFolderRule {
+            path,
+            ..Default::default()
+        }],
+    })
+}
+
+/// A category torrents can be filed under, e.g. "movies" or "tv".
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Category {
+    pub name: String,
+    // New torrents in this category download here instead of `default_download_folder`
+    pub download_folder: Option<PathBuf>,
+    // Finished torrents in this category move here instead of `completed_download_folder`
+    pub completed_folder: Option<PathBuf>,
+}
+
+/// When a finished torrent has seeded enough. Reaching either target counts.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct SeedGoals {
+    // Uploaded divided by the larger of downloaded and the torrent's size
+    pub ratio: Option<f64>,
+    // Time spent seeding, counted only while the torrent is running
+    pub seed_time_secs: Option<u64>,
+    pub action: SeedGoalAction,
+}
+
+/// How to add a torrent, given along with its magnet link or path: on the command
+/// line, in a `.magnet` or `.path` watch file, or in the add dialog.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AddOptions {
+    // Add it paused instead of starting it
+    pub paused: bool,
+    pub category: Option<String>,
+    // Used instead of the category's or the default download folder
+    pub download_path: Option<PathBuf>,
+    // Download pieces in order instead of rarest first
+    pub sequential: bool,
+    // Trust the files already in the folder and mark the torrent complete without
+    // hashing them, e.g. for data moved over from another client
+    pub skip_check: bool,
+    // If the torrent is already added, merge its new trackers and web seeds into it
+    // without asking
+    pub merge_sources: bool,
+}
+
+impl AddOptions {
+    /// Reads options written as `paused`, `sequential`, `skip_check`,
+    /// `merge_sources`, `category=<name>` and `download_dir=<path>`, one per item.
+    /// Blank items are skipped.
+    pub fn parse<'a>(items: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        let mut options = Self::default();
+        for item in items
+            .into_iter()
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+        {
+            match item
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+            {
+                None if item.eq_ignore_ascii_case("paused") => options.paused = true,
+                None if item.eq_ignore_ascii_case("sequential") => options.sequential = true,
+                None if item.eq_ignore_ascii_case("skip_check") => options.skip_check = true,
+                None if item.eq_ignore_ascii_case("merge_sources") => options.merge_sources = true,
+                Some(("category", name)) if !name.is_empty() => {
+                    options.category = Some(name.to_string())
+                }
+                Some(("download_dir", path)) if !path.is_empty() => {
+                    options.download_path = Some(PathBuf::from(path))
+                }
+                _ => return Err(format!("'{}' is not an add option", item)),
+            }
+        }
+        Ok(options)
+    }
+
+    /// The options the way `parse` reads them.
+    pub fn to_items(&self) -> Vec<String> {
+        let mut items = Vec::new();
+        if self.paused {
+            items.push("paused".to_string());
+        }
+        if self.sequential {
+            items.push("sequential".to_string());
+        }
+        if self.skip_check {
+            items.push("skip_check".to_string());
+        }
+        if self.merge_sources {
+            items.push("merge_sources".to_string());
+        }
+        if let Some(category) = &self.category {
+            items.push(format!("category={}", category));
+        }
+        if let Some(download_path) = &self.download_path {
+            items.push(format!("download_dir={}", download_path.display()));
+        }
+        items
+    }
+}
+
+impl SeedGoalAction {
+    /// What happens, as in "will be ...".
+    pub fn describe(self) -> &'static str {
+        match self {
+            SeedGoalAction::Pause => "paused",
+            SeedGoalAction::Remove => "removed",
+            SeedGoalAction::RemoveWithData => "removed with its files",
+        }
+    }
+}
+
+impl SeedGoals {
+    /// Whether a torrent with this share ratio that has seeded this long is done.
+    pub fn reached(&self, ratio: f64, seeding_secs: u64) -> bool {
+        self.ratio.is_some_and(|target| ratio >= target)
+            || self
+                .seed_time_secs
+                .is_some_and(|target| seeding_secs >= target)
+    }
+}
+
+/// What happens to a torrent once it reaches its seeding goals.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum SeedGoalAction {
+    #[default]
+    Pause,
+    // Removes the torrent from the list; its files stay on disk
+    Remove,
+    // Removes the torrent and deletes its files
+    RemoveWithData,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(default)]
 pub struct Settings {
@@ -49,37 +345,183 @@ pub struct Settings {
     pub torrents: Vec<TorrentSettings>,
     pub lifetime_downloaded: u64,
     pub lifetime_uploaded: u64,
+    // Stop seeding finished torrents at a share ratio or after a time; torrents
+    // can set their own
+    pub seed_goals: SeedGoals,
+    // How long the UI offers to act now or keep seeding before a torrent that
+    // reached its seed goals is paused or removed; 0 acts at once
+    pub seed_goal_grace_secs: u64,
 
     pub private_client: bool,
+    // Accept commands from the CLI on a Unix socket in the data directory (Unix only)
+    pub control_socket: bool,
+    // Detail written to logs/app.log; reloaded on SIGHUP along with the limits
+    pub log_level: LogLevel,
 
     // UI
     pub torrent_sort_column: TorrentSortColumn,
     pub torrent_sort_direction: SortDirection,
     pub peer_sort_column: PeerSortColumn,
     pub peer_sort_direction: SortDirection,
+    // Table columns in display order with their widths in percent; leave one out to
+    // hide it. An empty list restores the default layout.
+    pub torrent_columns: Vec<TableColumn<TorrentSortColumn>>,
+    pub peer_columns: Vec<TableColumn<PeerSortColumn>>,
+    // Grey out torrents that have moved no data for this long
+    pub dim_inactive_torrents: bool,
+    pub inactive_torrent_threshold_secs: u64,
+    // Ask before removing a torrent (with or without its files) or quitting
+    pub confirm_delete: bool,
+    pub confirm_delete_with_files: bool,
+    pub confirm_quit: bool,
+    // Show the current time and when this session started (UTC) in the stats panel
+    pub show_clock: bool,
+    // Ring the terminal bell when a download finishes or an error is shown
+    pub bell_on_complete: bool,
+    pub bell_on_error: bool,
 
     // Disk
-    pub watch_folder: Option<PathBuf>,
+    // Older versions saved a single `watch_folder` path, which is read as one rule
+    #[serde(alias = "watch_folder", deserialize_with = "deserialize_watch_folders")]
+    pub watch_folders: Vec<WatchFolderRule>,
+    // Categories offered in the UI, with their download folders. A torrent can also
+    // carry a category that isn't listed here.
+    pub categories: Vec<Category>,
     pub default_download_folder: Option<PathBuf>,
+    // New torrents download here while incomplete, then move to the folder they were
+    // added with, or to `completed_download_folder`.
+    pub incomplete_download_folder: Option<PathBuf>,
+    // Finished downloads are moved here
+    pub completed_download_folder: Option<PathBuf>,
+    // Changing this moves where existing torrents are looked for on disk.
+    pub create_subfolder: CreateSubfolder,
+    pub on_insufficient_space: InsufficientSpaceAction,
+    // Re-hash every piece once a download finishes, before it starts seeding
+    pub verify_before_seeding: bool,
+    // Check each piece's length as well as its hash before accepting it
+    pub verify_piece_length: bool,
+    // Start new torrents paused when their download folder is missing or can't be
+    // written to
+    pub check_download_folder_writable: bool,
+    // Write settings.toml (torrent list and transfer totals) this often while running,
+    // not just on exit; 0 turns it off
+    pub autosave_interval_secs: u64,
+    // Optional MaxMind-format country or ASN database (.mmdb)
+    pub geoip_database_path: Option<PathBuf>,
 
     // Networking
     pub max_connected_peers: usize,
     pub bootstrap_nodes: Vec<String>,
+    pub dht_mode: DhtMode,
+    // uTP listens on UDP `client_port`, moving the DHT to the port above it
+    pub peer_transport: PeerTransport,
+    // Interface name (e.g. "tun0") or local IP that peer connections must use, so
+    // they stop rather than leak out another route if a VPN drops. Read at startup
+    // and again when our external IP changes.
+    pub listen_interface: Option<String>,
+    // Pick `client_port` at random from this range on every launch, for ISPs that
+    // throttle well-known ports. A forwarded port from the port file still wins.
+    pub listen_port_range: Option<PortRange>,
+    // More TCP ports to accept peers on besides `client_port`. Trackers and the DHT
+    // only hear about `client_port`.
+    pub extra_listen_ports: Vec<u16>,
+    // Serve torrent files over HTTP on 127.0.0.1 at this port for media players:
+    // /<hex info hash> is an M3U playlist and /<hex info hash>/<file index> a file.
+    // Streamed pieces are downloaded first.
+    pub streaming_port: Option<u16>,
     pub global_download_limit_bps: u64,
     pub global_upload_limit_bps: u64,
+    // Limits used instead of the two above while a `schedule` rule is in effect;
+    // 0 means no limit
+    pub alt_download_limit_bps: u64,
+    pub alt_upload_limit_bps: u64,
+    // Weekday/hour ranges that switch to the alternative limits, or pause every
+    // torrent. Hours are UTC shifted by `schedule_utc_offset_minutes`.
+    pub schedule: Vec<ScheduleRule>,
+    pub schedule_utc_offset_minutes: i32,
+    // How long a peer banned from the peer table stays banned
+    pub peer_ban_duration_secs: u64,
+    // Ban a peer IP for `peer_ban_duration_secs` once it has sent blocks of this many
+    // pieces that fail the hash check; 0 never bans
+    pub hash_fail_ban_threshold: u32,
+    // Blocklist of peer IP ranges: a file path or http(s) URL to an eMule .dat,
+    // PeerGuardian plaintext or CIDR list. Matching peers are refused both ways.
+    pub ip_filter: Option<String>,
+    // Reload `ip_filter` this often; 0 loads it once at startup
+    pub ip_filter_refresh_hours: u64,
+    // URL that answers with our external IP as plain text (e.g.
+    // "https://api.ipify.org"). Trackers, peers and the DHT report it too; this
+    // catches a change sooner.
+    pub external_ip_check_url: Option<String>,
+    pub external_ip_check_interval_secs: u64,
+    // URL that tests whether peers can reach us, with "{port}" standing for
+    // `client_port`; it answers "open" or "closed". Without it we connect to our own
+    // external IP, which needs a router that supports hairpin NAT.
+    pub port_check_url: Option<String>,
+    // Check the port again this often; 0 checks only at startup and on request
+    pub port_check_interval_secs: u64,
+    // Per torrent, drop peers that have moved no data either way for this long, but
+    // never below `min_connected_peers` or a peer we need for a piece; 0 turns it off
+    pub peer_idle_disconnect_secs: u64,
+    pub min_connected_peers: usize,
+    // How long a peer gets to send its handshake
+    pub peer_handshake_timeout_secs: u64,
+    // A keep-alive goes to every peer this often
+    pub peer_keepalive_interval_secs: u64,
+    // Drop a peer that has sent nothing at all, not even a keep-alive, for this long
+    pub peer_inactivity_timeout_secs: u64,
+    // A peer that sends none of the blocks we asked for in this long is snubbed: its
+    // pieces go to other peers and it only gets one at a time until a block arrives
+    pub peer_snub_timeout_secs: u64,
+    // Leave a few pieces out of the bitfield sent on connect and announce them with
+    // spaced HAVE messages instead, so a seed doesn't stand out as one
+    pub lazy_bitfield: bool,
 
     // Performance
+    pub performance_profile: PerformanceProfile,
+    // How many torrents may hash their files at the same time
     pub max_concurrent_validations: usize,
+    // Outgoing connections being set up at once, across all torrents. Consumer
+    // routers can fall over when hundreds of SYNs go out together.
     pub connection_attempt_permits: usize,
     pub resource_limit_override: Option<usize>,
+    // Threads used for piece hashing; 0 picks half of the available cores
+    pub hash_threads: usize,
+    // Most torrents loaded at once; 0 means no limit. Saved torrents always load.
+    pub max_torrents: usize,
+    pub torrent_limit_policy: TorrentLimitPolicy,
+    // Torrents downloading / seeding at once; the rest wait in the queue, in order,
+    // for a slot. 0 means no limit.
+    pub max_active_downloads: usize,
+    pub max_active_seeds: usize,
+    // Pause running torrents while a process with one of these names is open,
+    // e.g. a game; they resume when it exits. Names match without case or ".exe".
+    pub pause_when_process_running: Vec<String>,
 
     // Throttling / Choking
+    // Peers unchoked for reciprocating best, plus one optimistic unchoke
     pub upload_slots: usize,
+    // How often the unchoked peers are re-ranked
+    pub choke_interval_secs: u64,
+    // How long a randomly picked peer keeps the optimistic unchoke
+    pub optimistic_unchoke_interval_secs: u64,
     pub peer_upload_in_flight_limit: usize,
+    // Stop sending to / requesting from peers for every torrent
+    pub upload_disabled: bool,
+    pub download_disabled: bool,
 
     // Timings
     pub tracker_fallback_interval_secs: u64,
     pub client_leeching_fallback_interval_secs: u64,
+    // How often to ask each tracker for swarm seed/leech counts; 0 turns it off
+    pub tracker_scrape_interval_secs: u64,
+    // Per torrent; clamped so busy swarms don't get us rate-limited by other nodes
+    pub dht_get_peers_interval_secs: u64,
+    pub dht_announce_interval_secs: u64,
+    // Retry torrents that failed to start because of storage trouble, waiting twice as
+    // long after each attempt; 0 attempts turns it off
+    pub failed_torrent_retry_attempts: u32,
+    pub failed_torrent_retry_base_secs: u64,
 }
 
 impl Default for Settings {
@@ -88,17 +530,60 @@ impl Default for Settings {
             client_id: String::new(),
             client_port: 6681,
             torrents: Vec::new(),
-            watch_folder: None,
+            watch_folders: Vec::new(),
+            categories: Vec::new(),
             default_download_folder: None,
+            incomplete_download_folder: None,
+            completed_download_folder: None,
+            create_subfolder: CreateSubfolder::default(),
+            on_insufficient_space: InsufficientSpaceAction::default(),
+            verify_before_seeding: false,
+            verify_piece_length: true,
+            check_download_folder_writable: true,
+            autosave_interval_secs: 300,
+            geoip_database_path: None,
             lifetime_downloaded: 0,
             lifetime_uploaded: 0,
+            seed_goals: SeedGoals::default(),
+            seed_goal_grace_secs: 60,
             private_client: false,
+            control_socket: true,
+            log_level: LogLevel::default(),
             global_download_limit_bps: 0,
             global_upload_limit_bps: 0,
+            alt_download_limit_bps: 0,
+            alt_upload_limit_bps: 0,
+            schedule: Vec::new(),
+            schedule_utc_offset_minutes: 0,
+            peer_ban_duration_secs: 3600,
+            hash_fail_ban_threshold: 3,
+            ip_filter: None,
+            ip_filter_refresh_hours: 24,
+            external_ip_check_url: None,
+            external_ip_check_interval_secs: 300,
+            port_check_url: None,
+            port_check_interval_secs: 1800,
+            peer_idle_disconnect_secs: 600,
+            min_connected_peers: 20,
+            peer_handshake_timeout_secs: 10,
+            peer_keepalive_interval_secs: 60,
+            peer_inactivity_timeout_secs: 120,
+            peer_snub_timeout_secs: 60,
+            lazy_bitfield: true,
             torrent_sort_column: TorrentSortColumn::default(),
             torrent_sort_direction: SortDirection::default(),
             peer_sort_column: PeerSortColumn::default(),
             peer_sort_direction: SortDirection::default(),
+            torrent_columns: default_torrent_columns(),
+            peer_columns: default_peer_columns(),
+            dim_inactive_torrents: true,
+            inactive_torrent_threshold_secs: 300,
+            confirm_delete: true,
+            confirm_delete_with_files: true,
+            confirm_quit: false,
+            show_clock: false,
+            bell_on_complete: false,
+            bell_on_error: false,
             max_connected_peers: 2000,
             bootstrap_nodes: vec![
                 "router.utorrent.com:6881".to_string(),
@@ -107,17 +592,72 @@ impl Default for Settings {
                 "dht.libtorrent.org:25401".to_string(),
                 "router.cococorp.de:6881".to_string(),
             ],
+            performance_profile: PerformanceProfile::default(),
+            dht_mode: DhtMode::default(),
+            peer_transport: PeerTransport::default(),
+            listen_interface: None,
+            listen_port_range: None,
+            extra_listen_ports: Vec::new(),
+            streaming_port: None,
             max_concurrent_validations: 64,
             resource_limit_override: None,
-            connection_attempt_permits: 50,
+            hash_threads: 0,
+            max_torrents: 0,
+            torrent_limit_policy: TorrentLimitPolicy::default(),
+            max_active_downloads: 5,
+            max_active_seeds: 0,
+            pause_when_process_running: Vec::new(),
+            connection_attempt_permits: DEFAULT_CONNECTION_ATTEMPT_PERMITS,
             upload_slots: 8,
+            choke_interval_secs: 10,
+            optimistic_unchoke_interval_secs: 30,
             peer_upload_in_flight_limit: 4,
+            upload_disabled: false,
+            download_disabled: false,
             tracker_fallback_interval_secs: 1800,
             client_leeching_fallback_interval_secs: 60,
+            tracker_scrape_interval_secs: 900,
+            dht_get_peers_interval_secs: 300,
+            dht_announce_interval_secs: 1800,
+            failed_torrent_retry_attempts: 5,
+            failed_torrent_retry_base_secs: 30,
         }
     }
 }
 
+fn default_torrent_columns() -> Vec<TableColumn<TorrentSortColumn>> {
+    [
+        (TorrentSortColumn::Queue, 4),
+        (TorrentSortColumn::Name, 50),
+        (TorrentSortColumn::Category, 10),
+        (TorrentSortColumn::Down, 15),
+        (TorrentSortColumn::Up, 15),
+        (TorrentSortColumn::Ratio, 6),
+    ]
+    .into_iter()
+    .map(|(column, width)| TableColumn { column, width })
+    .collect()
+}
+
+fn default_peer_columns() -> Vec<TableColumn<PeerSortColumn>> {
+    [
+        (PeerSortColumn::Flags, 6),
+        (PeerSortColumn::Address, 14),
+        (PeerSortColumn::Country, 5),
+        (PeerSortColumn::Source, 5),
+        (PeerSortColumn::Client, 14),
+        (PeerSortColumn::Action, 10),
+        (PeerSortColumn::Completed, 7),
+        (PeerSortColumn::DL, 10),
+        (PeerSortColumn::UL, 10),
+        (PeerSortColumn::TotalDL, 9),
+        (PeerSortColumn::TotalUL, 10),
+    ]
+    .into_iter()
+    .map(|(column, width)| TableColumn { column, width })
+    .collect()
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct TorrentSettings {
@@ -126,6 +666,191 @@ pub struct TorrentSettings {
     pub validation_status: bool,
     pub download_path: PathBuf,
     pub torrent_control_state: TorrentControlState,
+    pub upload_disabled: bool,
+    pub download_disabled: bool,
+    // Download only the first this many bytes, then pause
+    pub max_download_bytes: Option<u64>,
+    // Priority of each file, by index. None until one is set, so a magnet link's so=
+    // still applies once its metadata arrives.
+    pub file_priorities: Option<Vec<FilePriority>>,
+    // Files the user renamed or moved within the download folder
+    pub file_renames: Vec<FileRename>,
+    // Shown instead of the name from the metadata
+    pub display_name: Option<String>,
+    // Bytes moved over the torrent's whole life, for its share ratio
+    pub total_downloaded: u64,
+    pub total_uploaded: u64,
+    // Time spent seeding, towards the seed time goal
+    pub seeding_secs: u64,
+    // Replaces the global `seed_goals` for this torrent when set
+    pub seed_goals: Option<SeedGoals>,
+    pub category: Option<String>,
+    // Where the torrent moves once finished, while it downloads to the incomplete folder
+    pub completed_path: Option<PathBuf>,
+    // Pieces are downloaded in order, e.g. to watch a video while it downloads
+    pub sequential: bool,
+    // Its share of the global speed limits while other torrents want them too
+    pub bandwidth_priority: BandwidthPriority,
+    // Peer discovery switched off for this torrent; private torrents never use it
+    pub dht_disabled: bool,
+    pub pex_disabled: bool,
+    // Trackers and web seeds merged in from duplicates of the torrent added later
+    pub extra_trackers: Vec<String>,
+    pub extra_web_seeds: Vec<String>,
+}
+
+impl Settings {
+    /// Returns the watch folder rule for a file dropped directly into one of the watch folders.
+    pub fn watch_folder_rule_for(&self, file_path: &Path) -> Option<&WatchFolderRule> {
+        let parent = file_path.parent()?;
+        self.watch_folders.iter().find(|rule| rule.path == parent)
+    }
+
+    /// Global (download, upload) limits in bytes per second under `profile`.
+    pub fn rate_limits(&self, profile: RateProfile) -> (u64, u64) {
+        match profile {
+            RateProfile::Normal => (self.global_download_limit_bps, self.global_upload_limit_bps),
+            RateProfile::Alternative | RateProfile::Paused => {
+                (self.alt_download_limit_bps, self.alt_upload_limit_bps)
+            }
+        }
+    }
+
+    /// Number of threads used for piece hashing, resolving `hash_threads = 0` to half
+    /// of the available cores.
+    pub fn hash_thread_count(&self) -> usize {
+        if self.hash_threads > 0 {
+            return self.hash_threads;
+        }
+        std::thread::available_parallelism()
+            .map(|cores| (cores.get() / 2).max(1))
+            .unwrap_or(1)
+    }
+
+    /// Columns of the torrent list, falling back to the default layout when none are set.
+    pub fn torrent_table_columns(&self) -> Vec<TableColumn<TorrentSortColumn>> {
+        if self.torrent_columns.is_empty() {
+            default_torrent_columns()
+        } else {
+            self.torrent_columns.clone()
+        }
+    }
+
+    /// Columns of the peer table, falling back to the default layout when none are set.
+    pub fn peer_table_columns(&self) -> Vec<TableColumn<PeerSortColumn>> {
+        if self.peer_columns.is_empty() {
+            default_peer_columns()
+        } else {
+            self.peer_columns.clone()
+        }
+    }
+
+    /// How often each torrent asks the DHT for peers.
+    #[cfg(feature = "dht")]
+    pub fn dht_get_peers_interval(&self) -> Duration {
+        Duration::from_secs(
+            self.dht_get_peers_interval_secs
+                .max(MIN_DHT_GET_PEERS_INTERVAL_SECS),
+        )
+    }
+
+    /// How often each torrent announces itself to the DHT.
+    #[cfg(feature = "dht")]
+    pub fn dht_announce_interval(&self) -> Duration {
+        Duration::from_secs(
+            self.dht_announce_interval_secs
+                .max(MIN_DHT_ANNOUNCE_INTERVAL_SECS),
+        )
+    }
+
+    /// How often each tracker is scraped, or `None` when scraping is off.
+    pub fn tracker_scrape_interval(&self) -> Option<Duration> {
+        (self.tracker_scrape_interval_secs > 0).then(|| {
+            Duration::from_secs(
+                self.tracker_scrape_interval_secs
+                    .max(MIN_TRACKER_SCRAPE_INTERVAL_SECS),
+            )
+        })
+    }
+
+    /// How often the unchoked peers are re-ranked, at least once a second.
+    pub fn choke_interval(&self) -> Duration {
+        Duration::from_secs(self.choke_interval_secs.max(1))
+    }
+
+    /// How long the optimistic unchoke stays with one peer.
+    pub fn optimistic_unchoke_interval(&self) -> Duration {
+        Duration::from_secs(self.optimistic_unchoke_interval_secs)
+    }
+
+    pub fn peer_handshake_timeout(&self) -> Duration {
+        Duration::from_secs(self.peer_handshake_timeout_secs.max(1))
+    }
+
+    pub fn peer_keepalive_interval(&self) -> Duration {
+        Duration::from_secs(self.peer_keepalive_interval_secs.max(1))
+    }
+
+    /// Never shorter than the keep-alive interval, so quiet but healthy peers stay.
+    pub fn peer_inactivity_timeout(&self) -> Duration {
+        Duration::from_secs(self.peer_inactivity_timeout_secs).max(self.peer_keepalive_interval())
+    }
+
+    pub fn peer_snub_timeout(&self) -> Duration {
+        Duration::from_secs(self.peer_snub_timeout_secs.max(1))
+    }
+
+    pub fn external_ip_check_interval(&self) -> Duration {
+        Duration::from_secs(self.external_ip_check_interval_secs.max(30))
+    }
+
+    /// How often the port is checked again, or `None` to check it only once.
+    pub fn port_check_interval(&self) -> Option<Duration> {
+        (self.port_check_interval_secs > 0)
+            .then(|| Duration::from_secs(self.port_check_interval_secs.max(60)))
+    }
+
+    /// How often the IP filter is reloaded, or `None` to load it only once.
+    pub fn ip_filter_refresh_interval(&self) -> Option<Duration> {
+        (self.ip_filter_refresh_hours > 0)
+            .then(|| Duration::from_secs(self.ip_filter_refresh_hours.saturating_mul(3600)))
+    }
+
+    /// How long to wait before retry `attempt` (counting from 0) of a torrent that
+    /// failed to start. Doubles each time, up to an hour.
+    pub fn failed_torrent_retry_delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .failed_torrent_retry_base_secs
+            .saturating_mul(1 << attempt.min(16));
+        Duration::from_secs(delay.min(MAX_FAILED_TORRENT_RETRY_DELAY_SECS))
+    }
+
+    /// The download folder for a file picked up from a watch folder.
+    pub fn download_folder_for(&self, file_path: &Path) -> Option<PathBuf> {
+        let rule = self.watch_folder_rule_for(file_path);
+        rule.and_then(|rule| rule.download_folder.clone())
+            .or_else(|| {
+                rule.and_then(|rule| rule.label.as_deref())
+                    .and_then(|label| self.category_download_folder(label))
+            })
+            .or_else(|| self.default_download_folder.clone())
+    }
+
+    /// The download folder configured for the category `name`, if any.
+    pub fn category_download_folder(&self, name: &str) -> Option<PathBuf> {
+        self.categories
+            .iter()
+            .find(|category| category.name == name)
+            .and_then(|category| category.download_folder.clone())
+    }
+
+    /// The folder a torrent in `category` moves to when it finishes, if one is set.
+    pub fn completed_folder_for(&self, category: Option<&str>) -> Option<PathBuf> {
+        category
+            .and_then(|name| self.categories.iter().find(|c| c.name == name))
+            .and_then(|category| category.completed_folder.clone())
+            .or_else(|| self.completed_download_folder.clone())
+    }
 }
 
 /// This is now the single source of truth for app directories.
@@ -164,6 +889,12 @@ pub fn create_watch_directories() -> io::Result<()> {
 }
 
 pub fn load_settings() -> Settings {
+    try_load_settings().unwrap_or_default()
+}
+
+/// Like `load_settings`, but reports a settings.toml that can't be read instead of
+/// falling back to the defaults.
+pub fn try_load_settings() -> Result<Settings, Box<figment::Error>> {
     if let Some((config_dir, _)) = get_app_paths() {
         let config_file_path = config_dir.join("settings.toml");
 
@@ -171,11 +902,11 @@ pub fn load_settings() -> Settings {
             .merge(Toml::file(config_file_path))
             .merge(Env::prefixed("SUPERSEEDR_"))
             .extract()
-            .unwrap_or_default();
+            .map_err(Box::new);
     }
 
     // Fallback if we can't even determine the application paths.
-    Settings::default()
+    Ok(Settings::default())
 }
 
 /// Saves the provided settings to the config file.
@@ -190,6 +921,54 @@ pub fn save_settings(settings: &Settings) -> io::Result<()> {
     Ok(())
 }
 
+/// Where a torrent's partly downloaded pieces are kept between runs.
+pub fn partial_pieces_path(info_hash: &[u8]) -> Option<PathBuf> {
+    get_app_paths().map(|(_, data_dir)| {
+        data_dir
+            .join("partial_pieces")
+            .join(format!("{}.bin", hex::encode(info_hash)))
+    })
+}
+
+// Routing table nodes kept between runs, so the DHT doesn't bootstrap from scratch
+#[cfg(feature = "dht")]
+const DHT_NODES_FILE: &str = "dht_nodes.txt";
+#[cfg(feature = "dht")]
+const MAX_SAVED_DHT_NODES: usize = 256;
+
+/// DHT nodes saved by the last run, for bootstrapping before the configured nodes.
+#[cfg(feature = "dht")]
+pub fn load_dht_nodes() -> Vec<String> {
+    get_app_paths()
+        .and_then(|(_, data_dir)| fs::read_to_string(data_dir.join(DHT_NODES_FILE)).ok())
+        .map(|content| parse_dht_nodes(&content))
+        .unwrap_or_default()
+}
+
+/// Saves the DHT routing table, one `ip:port` per line.
+#[cfg(feature = "dht")]
+pub fn save_dht_nodes(nodes: &[String]) -> io::Result<()> {
+    if let Some((_, data_dir)) = get_app_paths() {
+        let nodes_file_path = data_dir.join(DHT_NODES_FILE);
+        let temp_file_path = data_dir.join(format!("{}.tmp", DHT_NODES_FILE));
+        let mut content = parse_dht_nodes(&nodes.join("\n")).join("\n");
+        content.push('\n');
+        fs::write(&temp_file_path, content)?;
+        fs::rename(&temp_file_path, &nodes_file_path)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "dht")]
+fn parse_dht_nodes(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter_map(|line| line.trim().parse::<SocketAddr>().ok())
+        .map(|addr| addr.to_string())
+        .take(MAX_SAVED_DHT_NODES)
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*; // Import everything from the parent module (your config.rs code)
@@ -209,29 +988,104 @@ mod tests {
             torrent_sort_direction = "Descending"
             peer_sort_column = "Address"
             peer_sort_direction = "Ascending"
+            dim_inactive_torrents = false
+            inactive_torrent_threshold_secs = 60
+            confirm_delete = false
+            confirm_quit = true
+            show_clock = true
 
-            watch_folder = "/path/to/watch"
             default_download_folder = "/path/to/download"
+            incomplete_download_folder = "/path/to/incomplete"
+            create_subfolder = "MultiFileOnly"
+            on_insufficient_space = "Refuse"
+            verify_before_seeding = true
+            verify_piece_length = false
+            check_download_folder_writable = false
+            control_socket = false
+            log_level = "Debug"
 
             max_connected_peers = 500
             global_download_limit_bps = 102400
             global_upload_limit_bps = 51200
+            alt_download_limit_bps = 10240
+            alt_upload_limit_bps = 5120
+            schedule_utc_offset_minutes = -300
+            peer_ban_duration_secs = 600
+            hash_fail_ban_threshold = 5
+            lazy_bitfield = false
+            peer_handshake_timeout_secs = 5
+            peer_keepalive_interval_secs = 90
+            peer_inactivity_timeout_secs = 30
+            peer_snub_timeout_secs = 0
+            ip_filter = "https://example.com/level1.p2p"
+            ip_filter_refresh_hours = 0
+            external_ip_check_url = "https://api.ipify.org"
+            external_ip_check_interval_secs = 10
+            port_check_url = "https://example.com/port/{port}"
+            port_check_interval_secs = 0
 
+            dht_mode = "ReadOnly"
+            peer_transport = "Both"
+            listen_interface = "tun0"
+            listen_port_range = { start = 50000, end = 50100 }
+            extra_listen_ports = [443, 8080]
+            streaming_port = 8089
+            performance_profile = "QuietDisk"
             max_concurrent_validations = 32
             connection_attempt_permits = 25
             resource_limit_override = 1024
+            hash_threads = 6
+            max_torrents = 50
+            torrent_limit_policy = "RemoveOldestComplete"
+            max_active_downloads = 3
+            max_active_seeds = 10
+            pause_when_process_running = ["steam", "Resolve.exe"]
 
             upload_slots = 10
+            choke_interval_secs = 15
+            optimistic_unchoke_interval_secs = 45
             peer_upload_in_flight_limit = 2
+            download_disabled = true
 
             tracker_fallback_interval_secs = 3600
             client_leeching_fallback_interval_secs = 120
+            tracker_scrape_interval_secs = 0
+            dht_get_peers_interval_secs = 600
+            dht_announce_interval_secs = 3600
+            failed_torrent_retry_attempts = 0
 
             bootstrap_nodes = [
                 "node1.com:1234",
                 "node2.com:5678"
             ]
 
+            [[watch_folders]]
+            path = "/path/to/watch"
+
+            [seed_goals]
+            ratio = 2.5
+            action = "RemoveWithData"
+
+            [[schedule]]
+            days = ["Mon", "Tue", "Wed", "Thu", "Fri"]
+            start_hour = 9
+            end_hour = 17
+
+            [[schedule]]
+            start_hour = 23
+            end_hour = 7
+            pause = true
+
+            [[categories]]
+            name = "tv"
+            download_folder = "/downloads/tv"
+            completed_folder = "/media/tv"
+
+            [[watch_folders]]
+            path = "/path/to/watch/movies"
+            download_folder = "/path/to/movies"
+            label = "movies"
+
             [[torrents]]
             torrent_or_magnet = "magnet:?xt=urn:btih:..."
             name = "My Test Torrent"
@@ -245,6 +1099,26 @@ mod tests {
             validation_status = false
             download_path = "/downloads/another"
             torrent_control_state = "Paused"
+            upload_disabled = true
+            max_download_bytes = 1073741824
+            file_priorities = ["Normal", "Skip", "High"]
+            display_name = "Renamed"
+            total_downloaded = 1000
+            total_uploaded = 2500
+            seeding_secs = 7200
+            category = "tv"
+            completed_path = "/downloads"
+            sequential = true
+            bandwidth_priority = "Low"
+            dht_disabled = true
+
+            [torrents.seed_goals]
+            seed_time_secs = 86400
+            action = "Remove"
+
+            [[torrents.file_renames]]
+            index = 2
+            path = "another/Extras/notes.txt"
         "#;
 
         // Parse the string using Figment, just like load_settings would
@@ -257,12 +1131,136 @@ mod tests {
         assert_eq!(settings.client_id, "test-client-id-123");
         assert_eq!(settings.client_port, 12345);
         assert_eq!(settings.lifetime_downloaded, 1000);
+        assert_eq!(settings.seed_goals.ratio, Some(2.5));
+        assert_eq!(settings.seed_goals.action, SeedGoalAction::RemoveWithData);
+        assert_eq!(settings.seed_goal_grace_secs, 60);
         assert_eq!(settings.global_upload_limit_bps, 51200);
+        assert_eq!(
+            settings.rate_limits(RateProfile::Alternative),
+            (10240, 5120)
+        );
+        assert_eq!(settings.rate_limits(RateProfile::Normal), (102400, 51200));
+        assert_eq!(settings.schedule_utc_offset_minutes, -300);
+        assert_eq!(settings.schedule.len(), 2);
+        assert_eq!(settings.schedule[0].days.len(), 5);
+        assert!(!settings.schedule[0].pause);
+        assert_eq!(
+            settings.schedule[1],
+            ScheduleRule {
+                days: Vec::new(),
+                start_hour: 23,
+                end_hour: 7,
+                pause: true,
+            }
+        );
+        assert_eq!(settings.peer_ban_duration_secs, 600);
+        assert_eq!(settings.hash_fail_ban_threshold, 5);
+        assert!(!settings.lazy_bitfield);
+        assert_eq!(settings.peer_handshake_timeout(), Duration::from_secs(5));
+        assert_eq!(settings.peer_keepalive_interval(), Duration::from_secs(90));
+        // Raised to the keep-alive interval
+        assert_eq!(settings.peer_inactivity_timeout(), Duration::from_secs(90));
+        assert_eq!(settings.peer_snub_timeout(), Duration::from_secs(1));
+        assert_eq!(
+            settings.ip_filter.as_deref(),
+            Some("https://example.com/level1.p2p")
+        );
+        assert_eq!(settings.ip_filter_refresh_interval(), None);
+        assert_eq!(
+            settings.external_ip_check_url.as_deref(),
+            Some("https://api.ipify.org")
+        );
+        // Raised to the 30 second minimum
+        assert_eq!(
+            settings.external_ip_check_interval(),
+            Duration::from_secs(30)
+        );
+        assert_eq!(
+            settings.port_check_url.as_deref(),
+            Some("https://example.com/port/{port}")
+        );
+        assert_eq!(settings.port_check_interval(), None);
         assert_eq!(settings.torrent_sort_column, TorrentSortColumn::Name);
         assert_eq!(settings.torrent_sort_direction, SortDirection::Descending);
         assert_eq!(settings.peer_sort_column, PeerSortColumn::Address);
-        assert_eq!(settings.watch_folder, Some(PathBuf::from("/path/to/watch")));
+        assert_eq!(settings.watch_folders.len(), 2);
+        assert_eq!(
+            settings.watch_folders[0].path,
+            PathBuf::from("/path/to/watch")
+        );
+        assert!(settings.watch_folders[0].download_folder.is_none());
+        assert_eq!(
+            settings.watch_folders[1].download_folder,
+            Some(PathBuf::from("/path/to/movies"))
+        );
+        assert_eq!(settings.watch_folders[1].label.as_deref(), Some("movies"));
+        assert_eq!(
+            settings.incomplete_download_folder,
+            Some(PathBuf::from("/path/to/incomplete"))
+        );
+        assert_eq!(settings.completed_download_folder, None);
+        assert_eq!(
+            settings.completed_folder_for(Some("tv")),
+            Some(PathBuf::from("/media/tv"))
+        );
+        assert_eq!(settings.completed_folder_for(None), None);
+        assert_eq!(settings.create_subfolder, CreateSubfolder::MultiFileOnly);
+        assert_eq!(
+            settings.on_insufficient_space,
+            InsufficientSpaceAction::Refuse
+        );
+        assert!(settings.verify_before_seeding);
+        assert!(!settings.verify_piece_length);
+        assert!(!settings.check_download_folder_writable);
+        assert!(!settings.control_socket);
+        assert_eq!(settings.log_level, LogLevel::Debug);
+        assert!(!settings.upload_disabled);
+        assert!(settings.download_disabled);
         assert_eq!(settings.resource_limit_override, Some(1024));
+        assert_eq!(settings.hash_threads, 6);
+        assert_eq!(settings.max_torrents, 50);
+        assert_eq!(settings.max_active_downloads, 3);
+        assert_eq!(settings.max_active_seeds, 10);
+        assert_eq!(
+            settings.torrent_limit_policy,
+            TorrentLimitPolicy::RemoveOldestComplete
+        );
+        assert_eq!(
+            settings.pause_when_process_running,
+            vec!["steam".to_string(), "Resolve.exe".to_string()]
+        );
+        assert_eq!(settings.performance_profile, PerformanceProfile::QuietDisk);
+        assert_eq!(settings.dht_mode, DhtMode::ReadOnly);
+        assert_eq!(settings.peer_transport, PeerTransport::Both);
+        assert_eq!(settings.listen_interface.as_deref(), Some("tun0"));
+        assert_eq!(
+            settings.listen_port_range,
+            Some(PortRange {
+                start: 50000,
+                end: 50100
+            })
+        );
+        assert_eq!(settings.extra_listen_ports, vec![443, 8080]);
+        assert_eq!(settings.streaming_port, Some(8089));
+        assert!(settings.peer_transport.uses_utp() && settings.peer_transport.uses_tcp());
+        assert_eq!(settings.dht_get_peers_interval_secs, 600);
+        assert_eq!(settings.dht_announce_interval_secs, 3600);
+        assert_eq!(settings.tracker_scrape_interval(), None);
+        assert_eq!(settings.failed_torrent_retry_attempts, 0);
+        assert_eq!(settings.failed_torrent_retry_base_secs, 30);
+        assert!(!settings.dim_inactive_torrents);
+        assert_eq!(settings.inactive_torrent_threshold_secs, 60);
+        assert!(!settings.confirm_delete);
+        assert!(settings.confirm_delete_with_files);
+        assert!(settings.confirm_quit);
+        assert!(settings.show_clock);
+        assert_eq!(settings.hash_thread_count(), 6);
+        assert_eq!(settings.upload_slots, 10);
+        assert_eq!(settings.choke_interval(), Duration::from_secs(15));
+        assert_eq!(
+            settings.optimistic_unchoke_interval(),
+            Duration::from_secs(45)
+        );
         assert_eq!(
             settings.bootstrap_nodes,
             vec!["node1.com:1234", "node2.com:5678"]
@@ -282,6 +1280,87 @@ mod tests {
             settings.torrents[1].torrent_control_state,
             TorrentControlState::Paused
         );
+        assert!(!settings.torrents[0].upload_disabled);
+        assert!(settings.torrents[1].upload_disabled);
+        assert!(!settings.torrents[1].download_disabled);
+        assert_eq!(settings.torrents[0].max_download_bytes, None);
+        assert_eq!(settings.torrents[1].max_download_bytes, Some(1_073_741_824));
+        assert_eq!(settings.torrents[0].file_priorities, None);
+        assert_eq!(
+            settings.torrents[1].file_priorities,
+            Some(vec![
+                FilePriority::Normal,
+                FilePriority::Skip,
+                FilePriority::High
+            ])
+        );
+        assert_eq!(settings.torrents[0].total_uploaded, 0);
+        assert_eq!(settings.torrents[1].total_downloaded, 1000);
+        assert_eq!(settings.torrents[1].total_uploaded, 2500);
+        assert_eq!(settings.torrents[1].seeding_secs, 7200);
+        assert_eq!(settings.torrents[0].seed_goals, None);
+        assert_eq!(
+            settings.torrents[1].seed_goals,
+            Some(SeedGoals {
+                ratio: None,
+                seed_time_secs: Some(86400),
+                action: SeedGoalAction::Remove,
+            })
+        );
+        assert_eq!(settings.torrents[0].category, None);
+        assert_eq!(settings.torrents[1].category.as_deref(), Some("tv"));
+        assert_eq!(settings.torrents[0].completed_path, None);
+        assert_eq!(settings.torrents[0].display_name, None);
+        assert_eq!(
+            settings.torrents[1].display_name.as_deref(),
+            Some("Renamed")
+        );
+        assert!(settings.torrents[0].file_renames.is_empty());
+        assert_eq!(
+            settings.torrents[1].file_renames,
+            vec![FileRename {
+                index: 2,
+                path: PathBuf::from("another/Extras/notes.txt"),
+                borrowed: false,
+            }]
+        );
+        assert_eq!(
+            settings.torrents[1].completed_path,
+            Some(PathBuf::from("/downloads"))
+        );
+        assert!(!settings.torrents[0].sequential);
+        assert!(settings.torrents[1].sequential);
+        assert_eq!(
+            settings.torrents[0].bandwidth_priority,
+            BandwidthPriority::Normal
+        );
+        assert_eq!(
+            settings.torrents[1].bandwidth_priority,
+            BandwidthPriority::Low
+        );
+        assert!(!settings.torrents[0].dht_disabled);
+        assert!(settings.torrents[1].dht_disabled);
+        assert!(!settings.torrents[1].pex_disabled);
+        assert_eq!(
+            settings.category_download_folder("tv"),
+            Some(PathBuf::from("/downloads/tv"))
+        );
+        assert_eq!(settings.category_download_folder("music"), None);
+    }
+
+    #[test]
+    fn test_seed_goals_reached() {
+        let goals = SeedGoals {
+            ratio: Some(2.0),
+            seed_time_secs: Some(3600),
+            action: SeedGoalAction::Pause,
+        };
+        assert!(!goals.reached(1.5, 600));
+        assert!(goals.reached(2.0, 600));
+        assert!(goals.reached(0.1, 3600));
+
+        // No targets, no end to seeding
+        assert!(!SeedGoals::default().reached(f64::INFINITY, u64::MAX));
     }
 
     #[test]
@@ -351,9 +1430,58 @@ mod tests {
         assert_eq!(settings.client_port, 6681);
         assert_eq!(settings.lifetime_downloaded, 0);
         assert_eq!(settings.global_upload_limit_bps, 0);
+        assert_eq!(settings.peer_ban_duration_secs, 3600);
+        assert_eq!(settings.hash_fail_ban_threshold, 3);
+        assert_eq!(
+            settings.ip_filter_refresh_interval(),
+            Some(Duration::from_secs(24 * 3600))
+        );
+        assert!(settings.external_ip_check_url.is_none());
+        assert_eq!(
+            settings.port_check_interval(),
+            Some(Duration::from_secs(1800))
+        );
+        assert_eq!(settings.peer_idle_disconnect_secs, 600);
+        assert_eq!(settings.min_connected_peers, 20);
+        assert!(settings.lazy_bitfield);
+        assert_eq!(settings.peer_inactivity_timeout(), Duration::from_secs(120));
         assert_eq!(settings.torrent_sort_column, TorrentSortColumn::Up);
         assert_eq!(settings.peer_sort_direction, SortDirection::Ascending);
-        assert!(settings.watch_folder.is_none());
+        assert!(settings.watch_folders.is_empty());
+        assert_eq!(settings.create_subfolder, CreateSubfolder::Never);
+        assert_eq!(
+            settings.on_insufficient_space,
+            InsufficientSpaceAction::StartPaused
+        );
+        assert!(!settings.verify_before_seeding);
+        assert!(settings.verify_piece_length);
+        assert!(settings.check_download_folder_writable);
+        assert_eq!(settings.autosave_interval_secs, 300);
+        assert!(settings.control_socket);
+        assert_eq!(settings.log_level, LogLevel::Info);
+        assert!(!settings.show_clock);
+        assert!(!settings.bell_on_complete);
+        assert!(!settings.bell_on_error);
+        assert_eq!(settings.failed_torrent_retry_attempts, 5);
+        assert_eq!(
+            settings.tracker_scrape_interval(),
+            Some(Duration::from_secs(900))
+        );
+        assert_eq!(settings.hash_threads, 0);
+        assert_eq!(settings.max_torrents, 0);
+        assert_eq!(settings.torrent_limit_policy, TorrentLimitPolicy::RejectNew);
+        assert_eq!(settings.max_active_downloads, 5);
+        assert_eq!(settings.max_active_seeds, 0);
+        assert!(settings.schedule.is_empty());
+        assert_eq!(settings.rate_limits(RateProfile::Paused), (0, 0));
+        assert!(settings.pause_when_process_running.is_empty());
+        assert_eq!(settings.torrent_columns.len(), 6);
+        assert_eq!(settings.peer_columns.len(), 11);
+        assert_eq!(settings.performance_profile, PerformanceProfile::Balanced);
+        assert_eq!(settings.dht_mode, DhtMode::Server);
+        assert_eq!(settings.peer_transport, PeerTransport::Tcp);
+        assert!(!settings.peer_transport.uses_utp());
+        assert!(settings.hash_thread_count() >= 1);
         assert_eq!(settings.max_connected_peers, 2000);
         assert_eq!(settings.bootstrap_nodes, default_settings.bootstrap_nodes);
         assert!(settings.torrents.is_empty());
@@ -392,4 +1520,283 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_old_watch_folder_key_becomes_a_rule() {
+        let settings: Settings = Figment::new()
+            .merge(Toml::string(r#"watch_folder = "/path/to/watch""#))
+            .extract()
+            .expect("Failed to parse old watch_folder key");
+        assert_eq!(
+            settings.watch_folders,
+            vec![WatchFolderRule {
+                path: PathBuf::from("/path/to/watch"),
+                ..Default::default()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_download_folder_for_watch_folder_rules() {
+        let settings = Settings {
+            default_download_folder: Some(PathBuf::from("/downloads")),
+            watch_folders: vec![
+                WatchFolderRule {
+                    path: PathBuf::from("/watch/movies"),
+                    download_folder: Some(PathBuf::from("/downloads/movies")),
+                    label: Some("movies".to_string()),
+                },
+                WatchFolderRule {
+                    path: PathBuf::from("/watch/misc"),
+                    download_folder: None,
+                    label: None,
+                },
+                WatchFolderRule {
+                    path: PathBuf::from("/watch/tv"),
+                    download_folder: None,
+                    label: Some("tv".to_string()),
+                },
+            ],
+            categories: vec![
+                Category {
+                    name: "tv".to_string(),
+                    download_folder: Some(PathBuf::from("/downloads/tv")),
+                    completed_folder: None,
+                },
+                Category {
+                    name: "movies".to_string(),
+                    download_folder: Some(PathBuf::from("/downloads/films")),
+                    completed_folder: Some(PathBuf::from("/media/films")),
+                },
+            ],
+            ..Default::default()
+        };
+
+        let movie = PathBuf::from("/watch/movies/film.torrent");
+        assert_eq!(
+            settings
+                .watch_folder_rule_for(&movie)
+                .and_then(|rule| rule.label.as_deref()),
+            Some("movies")
+        );
+        assert_eq!(
+            settings.download_folder_for(&movie),
+            Some(PathBuf::from("/downloads/movies"))
+        );
+
+        // A rule without its own download folder uses its label's category folder,
+        // then the default one
+        assert_eq!(
+            settings.download_folder_for(Path::new("/watch/tv/show.torrent")),
+            Some(PathBuf::from("/downloads/tv"))
+        );
+        assert_eq!(
+            settings.download_folder_for(Path::new("/watch/misc/a.torrent")),
+            Some(PathBuf::from("/downloads"))
+        );
+
+        // Files in nested or unknown folders don't match any rule
+        assert!(settings
+            .watch_folder_rule_for(Path::new("/watch/movies/sub/b.torrent"))
+            .is_none());
+        assert_eq!(
+            settings.download_folder_for(Path::new("/elsewhere/c.torrent")),
+            Some(PathBuf::from("/downloads"))
+        );
+
+        // Only categories with a completed folder of their own move finished torrents
+        // when there's no global completed folder
+        assert_eq!(
+            settings.completed_folder_for(Some("movies")),
+            Some(PathBuf::from("/media/films"))
+        );
+        assert_eq!(settings.completed_folder_for(Some("tv")), None);
+        let settings = Settings {
+            completed_download_folder: Some(PathBuf::from("/media")),
+            ..settings
+        };
+        assert_eq!(
+            settings.completed_folder_for(Some("tv")),
+            Some(PathBuf::from("/media"))
+        );
+        assert_eq!(
+            settings.completed_folder_for(Some("movies")),
+            Some(PathBuf::from("/media/films"))
+        );
+    }
+
+    #[test]
+    fn test_table_columns_parsing() {
+        let toml_str = r#"
+            peer_columns = []
+
+            [[torrent_columns]]
+            column = "Ratio"
+            width = 10
+
+            [[torrent_columns]]
+            column = "Name"
+            width = 90
+        "#;
+
+        let settings: Settings = Figment::new()
+            .merge(Toml::string(toml_str))
+            .extract()
+            .expect("Failed to parse table columns");
+
+        assert_eq!(
+            settings.torrent_table_columns(),
+            vec![
+                TableColumn {
+                    column: TorrentSortColumn::Ratio,
+                    width: 10
+                },
+                TableColumn {
+                    column: TorrentSortColumn::Name,
+                    width: 90
+                },
+            ]
+        );
+        // An empty list falls back to the default layout instead of an empty table.
+        assert_eq!(settings.peer_table_columns(), default_peer_columns());
+        assert_eq!(
+            default_torrent_columns()
+                .iter()
+                .map(|c| c.width)
+                .sum::<u16>(),
+            100
+        );
+        assert_eq!(
+            default_peer_columns().iter().map(|c| c.width).sum::<u16>(),
+            100
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "dht")]
+    fn test_dht_intervals_are_clamped_to_minimums() {
+        let settings = Settings {
+            dht_get_peers_interval_secs: 5,
+            dht_announce_interval_secs: 0,
+            ..Default::default()
+        };
+        assert_eq!(
+            settings.dht_get_peers_interval(),
+            Duration::from_secs(MIN_DHT_GET_PEERS_INTERVAL_SECS)
+        );
+        assert_eq!(
+            settings.dht_announce_interval(),
+            Duration::from_secs(MIN_DHT_ANNOUNCE_INTERVAL_SECS)
+        );
+
+        let defaults = Settings::default();
+        assert_eq!(defaults.dht_get_peers_interval(), Duration::from_secs(300));
+        assert_eq!(defaults.dht_announce_interval(), Duration::from_secs(1800));
+    }
+
+    #[test]
+    fn test_tracker_scrape_interval_is_clamped_or_off() {
+        let settings = Settings {
+            tracker_scrape_interval_secs: 10,
+            ..Default::default()
+        };
+        assert_eq!(
+            settings.tracker_scrape_interval(),
+            Some(Duration::from_secs(MIN_TRACKER_SCRAPE_INTERVAL_SECS))
+        );
+
+        let settings = Settings {
+            tracker_scrape_interval_secs: 0,
+            ..Default::default()
+        };
+        assert_eq!(settings.tracker_scrape_interval(), None);
+    }
+
+    #[test]
+    fn test_failed_torrent_retry_delay_backs_off() {
+        let settings = Settings::default();
+        assert_eq!(
+            settings.failed_torrent_retry_delay(0),
+            Duration::from_secs(30)
+        );
+        assert_eq!(
+            settings.failed_torrent_retry_delay(1),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            settings.failed_torrent_retry_delay(3),
+            Duration::from_secs(240)
+        );
+        assert_eq!(
+            settings.failed_torrent_retry_delay(40),
+            Duration::from_secs(MAX_FAILED_TORRENT_RETRY_DELAY_SECS)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "dht")]
+    fn test_parse_dht_nodes_skips_bad_lines() {
+        let content =
+            "1.2.3.4:6881\n\n  [2001:db8::1]:6881  \nrouter.example.com:6881\nnot a node\n";
+        assert_eq!(
+            parse_dht_nodes(content),
+            vec!["1.2.3.4:6881".to_string(), "[2001:db8::1]:6881".to_string()]
+        );
+
+        let many: String = (0..1000)
+            .map(|i| format!("10.0.{}.{}:6881\n", i / 256, i % 256))
+            .collect();
+        assert_eq!(parse_dht_nodes(&many).len(), MAX_SAVED_DHT_NODES);
+    }
+
+    #[test]
+    fn test_add_options_parse() {
+        let options = AddOptions::parse([
+            "paused",
+            " category = tv ",
+            "",
+            "download_dir=/media/tv shows",
+            "skip_check",
+            "merge_sources",
+        ])
+        .unwrap();
+        assert_eq!(
+            options,
+            AddOptions {
+                paused: true,
+                category: Some("tv".to_string()),
+                download_path: Some(PathBuf::from("/media/tv shows")),
+                sequential: false,
+                skip_check: true,
+                merge_sources: true,
+            }
+        );
+        assert_eq!(
+            AddOptions::parse(options.to_items().iter().map(String::as_str)),
+            Ok(options)
+        );
+        assert_eq!(AddOptions::parse([""]), Ok(AddOptions::default()));
+        assert_eq!(
+            AddOptions::parse(["SEQUENTIAL"]).map(|options| options.sequential),
+            Ok(true)
+        );
+
+        assert!(AddOptions::parse(["fast"]).is_err());
+        assert!(AddOptions::parse(["category="]).is_err());
+        assert!(AddOptions::parse(["paused=yes"]).is_err());
+    }
+
+    #[test]
+    fn test_port_range_random_port_stays_in_range() {
+        let range = PortRange {
+            start: 50100,
+            end: 50000,
+        };
+        for _ in 0..100 {
+            assert!((50000..=50100).contains(&range.random_port()));
+        }
+
+        let zero = PortRange { start: 0, end: 0 };
+        assert_eq!(zero.random_port(), 1);
+    }
 }
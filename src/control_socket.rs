@@ -0,0 +1,561 @@
+// SPDX-FileCopyrightText: 2025 The superseedr Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use crate::app::AppCommand;
+use crate::config::{get_app_paths, AddOptions, SeedGoalAction, SeedGoals};
+use crate::token_bucket::BandwidthPriority;
+use crate::torrent_manager::files::{parse_file_ranges, FilePriority};
+
+use data_encoding::BASE32;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::ops::RangeInclusive;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::net::UnixListener;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{event as tracing_event, Level};
+
+/// A command sent to the running instance over the control socket. Each command is
+/// one line: `add <magnet or path>[\t<option>...]`, `pause <hash>`, `resume <hash>`, `pause-all`,
+/// `resume-all`, `delete <hash>`,
+/// `cap <hash> <size or off>`, `skip <hash> <files>`, `unskip <hash> <files>`,
+/// `goal <hash> <goals>`, `category <hash> <name or none>`,
+/// `priority <hash> <low, normal or high>`, `dht <hash> <on or off>`, `pex <hash> <on or off>`,
+/// `seed <torrent path>\t<folder>` or `shutdown`. Info hashes can be hex or base32;
+/// files are indexes and ranges such as `0,2,4-6`. Add options are those read by
+/// `AddOptions::parse`, each after a tab.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlCommand {
+    Add(String, AddOptions),
+    Pause(Vec<u8>),
+    Resume(Vec<u8>),
+    // Pause every torrent, and undo that
+    PauseAll,
+    ResumeAll,
+    Delete(Vec<u8>),
+    // Download only the first this many bytes; None lifts the cap
+    Cap(Vec<u8>, Option<u64>),
+    // Give the files in these index ranges a new priority; skip and unskip set Skip
+    // and Normal
+    SetFilePriority(Vec<u8>, Vec<RangeInclusive<usize>>, FilePriority),
+    // The torrent's own seed goals; None goes back to the global ones
+    SeedGoals(Vec<u8>, Option<SeedGoals>),
+    // File the torrent under a category; None takes it out of its category
+    Category(Vec<u8>, Option<String>),
+    // The torrent's share of the global speed limits
+    Priority(Vec<u8>, BandwidthPriority),
+    // Let the torrent find peers through the DHT or PEX, or not; private torrents
+    // never do
+    Dht(Vec<u8>, bool),
+    Pex(Vec<u8>, bool),
+    // Add a torrent whose files are already in the folder, e.g. one just created;
+    // the two paths are separated by a tab since either may hold spaces
+    Seed(PathBuf, PathBuf),
+    Shutdown,
+}
+
+impl ControlCommand {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (verb, arg) = match line.split_once(char::is_whitespace) {
+            Some((verb, arg)) => (verb, arg.trim()),
+            None => (line, ""),
+        };
+
+        match verb.to_ascii_lowercase().as_str() {
+            "add" if !arg.is_empty() => {
+                let mut parts = arg.split('\t');
+                let input = parts.next().unwrap_or_default().trim();
+                Ok(Self::Add(input.to_string(), AddOptions::parse(parts)?))
+            }
+            "add" => Err("add needs a magnet link or torrent path".to_string()),
+            "pause" => parse_info_hash(arg).map(Self::Pause),
+            "resume" => parse_info_hash(arg).map(Self::Resume),
+            "pause-all" => Ok(Self::PauseAll),
+            "resume-all" => Ok(Self::ResumeAll),
+            "delete" => parse_info_hash(arg).map(Self::Delete),
+            "cap" => {
+                let (info_hash, size) = arg
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| "cap needs an info hash and a size (or 'off')".to_string())?;
+                Ok(Self::Cap(
+                    parse_info_hash(info_hash)?,
+                    parse_size(size.trim())?,
+                ))
+            }
+            "skip" | "unskip" => {
+                let (info_hash, files) = arg
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| format!("{} needs an info hash and file indexes", verb))?;
+                let ranges = parse_file_ranges(files)
+                    .ok_or_else(|| format!("'{}' is not a list of file indexes", files.trim()))?;
+                let priority = if verb.eq_ignore_ascii_case("skip") {
+                    FilePriority::Skip
+                } else {
+                    FilePriority::Normal
+                };
+                Ok(Self::SetFilePriority(
+                    parse_info_hash(info_hash)?,
+                    ranges,
+                    priority,
+                ))
+            }
+            "goal" => {
+                let (info_hash, goals) = arg
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| "goal needs an info hash and goals".to_string())?;
+                Ok(Self::SeedGoals(
+                    parse_info_hash(info_hash)?,
+                    parse_seed_goals(goals)?,
+                ))
+            }
+            "category" => {
+                let (info_hash, category) = arg
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| "category needs an info hash and a name".to_string())?;
+                let category = category.trim();
+                Ok(Self::Category(
+                    parse_info_hash(info_hash)?,
+                    (!category.eq_ignore_ascii_case("none")).then(|| category.to_string()),
+                ))
+            }
+            "priority" => {
+                let (info_hash, priority) =
+                    arg.split_once(char::is_whitespace).ok_or_else(|| {
+                        "priority needs an info hash and low, normal or high".to_string()
+                    })?;
+                let priority = BandwidthPriority::parse(priority.trim())
+                    .ok_or_else(|| format!("'{}' is not low, normal or high", priority.trim()))?;
+                Ok(Self::Priority(parse_info_hash(info_hash)?, priority))
+            }
+            "dht" | "pex" => {
+                let (info_hash, switch) = arg
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| format!("{} needs an info hash and on or off", verb))?;
+                let enabled = match switch.trim().to_ascii_lowercase().as_str() {
+                    "on" => true,
+                    "off" => false,
+                    other => return Err(format!("'{}' is not on or off", other)),
+                };
+                let info_hash = parse_info_hash(info_hash)?;
+                if verb.eq_ignore_ascii_case("dht") {
+                    Ok(Self::Dht(info_hash, enabled))
+                } else {
+                    Ok(Self::Pex(info_hash, enabled))
+                }
+            }
+            "seed" => {
+                let (torrent_path, folder) = arg
+                    .split_once('\t')
+                    .ok_or_else(|| "seed needs a torrent path and a folder".to_string())?;
+                Ok(Self::Seed(
+                    PathBuf::from(torrent_path.trim()),
+                    PathBuf::from(folder.trim()),
+                ))
+            }
+            "shutdown" => Ok(Self::Shutdown),
+            _ => Err(format!("unknown command '{}'", verb)),
+        }
+    }
+}
+
+fn parse_info_hash(arg: &str) -> Result<Vec<u8>, String> {
+    let info_hash = match arg.len() {
+        40 => hex::decode(arg).ok(),
+        32 => BASE32.decode(arg.to_ascii_uppercase().as_bytes()).ok(),
+        _ => None,
+    };
+    info_hash.ok_or_else(|| format!("'{}' is not a hex or base32 info hash", arg))
+}
+
+/// Reads seed goals such as `2.0 3d remove`: a bare number is a share ratio, one
+/// ending in s, m, h or d a seed time, and `pause`, `remove` or `remove-data` (which
+/// also deletes the files) what to do once either is reached. `never` seeds forever
+/// and `default` follows the global goals.
+fn parse_seed_goals(arg: &str) -> Result<Option<SeedGoals>, String> {
+    let arg = arg.trim();
+    if arg.eq_ignore_ascii_case("default") {
+        return Ok(None);
+    }
+
+    let mut goals = SeedGoals::default();
+    if arg.eq_ignore_ascii_case("never") {
+        return Ok(Some(goals));
+    }
+    for word in arg.split_whitespace() {
+        let lower = word.to_ascii_lowercase();
+        let multiplier = match lower.chars().last() {
+            Some('s') => Some(1),
+            Some('m') => Some(60),
+            Some('h') => Some(3600),
+            Some('d') => Some(86400),
+            _ => None,
+        };
+        match (lower.as_str(), multiplier) {
+            ("pause", _) => goals.action = SeedGoalAction::Pause,
+            ("remove", _) => goals.action = SeedGoalAction::Remove,
+            ("remove-data", _) => goals.action = SeedGoalAction::RemoveWithData,
+            (_, Some(multiplier)) => {
+                let time = lower[..lower.len() - 1]
+                    .parse::<u64>()
+                    .map_err(|_| format!("'{}' is not a seed time like 12h or 3d", word))?;
+                goals.seed_time_secs = Some(time.saturating_mul(multiplier));
+            }
+            (_, None) => match lower.parse::<f64>() {
+                Ok(ratio) if ratio.is_finite() && ratio >= 0.0 => goals.ratio = Some(ratio),
+                _ => return Err(format!("'{}' is not a ratio, seed time or action", word)),
+            },
+        }
+    }
+    if goals.ratio.is_none() && goals.seed_time_secs.is_none() {
+        return Err("goal needs a ratio or a seed time, 'never' or 'default'".to_string());
+    }
+    Ok(Some(goals))
+}
+
+/// Reads a size such as `1073741824`, `500M` or `1.5G` (binary units), or `off`.
+fn parse_size(arg: &str) -> Result<Option<u64>, String> {
+    if arg.eq_ignore_ascii_case("off") {
+        return Ok(None);
+    }
+
+    let upper = arg.to_ascii_uppercase();
+    let digits = upper.trim_end_matches(['B', 'I']);
+    let (number, multiplier) = match digits.chars().last() {
+        Some('K') => (&digits[..digits.len() - 1], 1u64 << 10),
+        Some('M') => (&digits[..digits.len() - 1], 1 << 20),
+        Some('G') => (&digits[..digits.len() - 1], 1 << 30),
+        Some('T') => (&digits[..digits.len() - 1], 1 << 40),
+        _ => (digits, 1),
+    };
+
+    match number.trim().parse::<f64>() {
+        Ok(value) if value.is_finite() && value >= 0.0 => {
+            Ok(Some((value * multiplier as f64) as u64))
+        }
+        _ => Err(format!("'{}' is not a size like 500M or 2G", arg)),
+    }
+}
+
+/// The socket sits in its own directory so only our user can reach it, even in the
+/// moment between binding it and tightening its permissions.
+pub fn socket_path() -> Option<PathBuf> {
+    get_app_paths().map(|(_, data_dir)| data_dir.join("control").join("superseedr.sock"))
+}
+
+/// Sends one command line to the running instance and returns its reply. Fails if
+/// no instance is listening, so callers can fall back to the watch folder.
+pub fn send_command(line: &str) -> io::Result<String> {
+    let path = socket_path().ok_or_else(|| io::Error::other("no data directory"))?;
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(2)))?;
+
+    writeln!(stream, "{}", line)?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    Ok(reply.trim().to_string())
+}
+
+/// Listens on the control socket until shutdown, forwarding each command to the app.
+/// We hold the instance lock, so any socket file already at `path` is stale.
+pub fn spawn_listener(
+    path: PathBuf,
+    app_command_tx: mpsc::Sender<AppCommand>,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) -> io::Result<()> {
+    // Anyone who can connect can delete torrents, so keep it to our own user.
+    if let Some(dir) = path.parent() {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    tracing_event!(Level::INFO, "Control socket listening on {:?}", path);
+
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = shutdown_rx.recv() => break,
+                accept_result = listener.accept() => match accept_result {
+                    Ok((stream, _)) => {
+                        tokio::spawn(handle_connection(stream, app_command_tx.clone()));
+                    }
+                    Err(e) => {
+                        tracing_event!(Level::WARN, "Control socket accept failed: {}", e);
+                    }
+                }
+            }
+        }
+        let _ = std::fs::remove_file(&path);
+    });
+
+    Ok(())
+}
+
+async fn handle_connection(
+    stream: tokio::net::UnixStream,
+    app_command_tx: mpsc::Sender<AppCommand>,
+) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = tokio::io::BufReader::new(reader).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let reply = match ControlCommand::parse(&line) {
+            Ok(command) => {
+                tracing_event!(Level::INFO, ?command, "Control socket command received.");
+                match app_command_tx.send(AppCommand::Control(command)).await {
+                    // The app applies it on its next loop turn, so this only says it
+                    // was accepted
+                    Ok(()) => "queued".to_string(),
+                    Err(_) => "error: client is shutting down".to_string(),
+                }
+            }
+            Err(e) => format!("error: {}", e),
+        };
+
+        if writer
+            .write_all(format!("{}\n", reply).as_bytes())
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INFO_HASH_HEX: &str = "c9e15763f722f23e98a29decdfae341b98d53056";
+
+    #[test]
+    fn test_parse_commands() {
+        let info_hash = hex::decode(INFO_HASH_HEX).unwrap();
+
+        assert_eq!(
+            ControlCommand::parse("add magnet:?xt=urn:btih:abc"),
+            Ok(ControlCommand::Add(
+                "magnet:?xt=urn:btih:abc".to_string(),
+                AddOptions::default()
+            ))
+        );
+        assert_eq!(
+            ControlCommand::parse("add /tmp/my file.torrent\n"),
+            Ok(ControlCommand::Add(
+                "/tmp/my file.torrent".to_string(),
+                AddOptions::default()
+            ))
+        );
+        assert_eq!(
+            ControlCommand::parse("add magnet:?xt=urn:btih:abc\tpaused\tcategory=tv"),
+            Ok(ControlCommand::Add(
+                "magnet:?xt=urn:btih:abc".to_string(),
+                AddOptions {
+                    paused: true,
+                    category: Some("tv".to_string()),
+                    ..Default::default()
+                }
+            ))
+        );
+        assert!(ControlCommand::parse("add magnet:?xt=urn:btih:abc\tfast").is_err());
+        assert_eq!(
+            ControlCommand::parse(&format!("PAUSE {}", INFO_HASH_HEX)),
+            Ok(ControlCommand::Pause(info_hash.clone()))
+        );
+        assert_eq!(
+            ControlCommand::parse(&format!("delete {}", BASE32.encode(&info_hash))),
+            Ok(ControlCommand::Delete(info_hash))
+        );
+        assert_eq!(
+            ControlCommand::parse("pause-all"),
+            Ok(ControlCommand::PauseAll)
+        );
+        assert_eq!(
+            ControlCommand::parse("Resume-All\n"),
+            Ok(ControlCommand::ResumeAll)
+        );
+        assert_eq!(
+            ControlCommand::parse("shutdown"),
+            Ok(ControlCommand::Shutdown)
+        );
+    }
+
+    #[test]
+    fn test_parse_cap() {
+        let info_hash = hex::decode(INFO_HASH_HEX).unwrap();
+
+        assert_eq!(
+            ControlCommand::parse(&format!("cap {} 2G", INFO_HASH_HEX)),
+            Ok(ControlCommand::Cap(info_hash.clone(), Some(2 << 30)))
+        );
+        assert_eq!(
+            ControlCommand::parse(&format!("cap {} 1.5MiB", INFO_HASH_HEX)),
+            Ok(ControlCommand::Cap(info_hash.clone(), Some(1_572_864)))
+        );
+        assert_eq!(
+            ControlCommand::parse(&format!("cap {} 1000", INFO_HASH_HEX)),
+            Ok(ControlCommand::Cap(info_hash.clone(), Some(1000)))
+        );
+        assert_eq!(
+            ControlCommand::parse(&format!("cap {} off", INFO_HASH_HEX)),
+            Ok(ControlCommand::Cap(info_hash, None))
+        );
+        assert!(ControlCommand::parse(&format!("cap {}", INFO_HASH_HEX)).is_err());
+        assert!(ControlCommand::parse(&format!("cap {} lots", INFO_HASH_HEX)).is_err());
+    }
+
+    #[test]
+    fn test_parse_skip() {
+        let info_hash = hex::decode(INFO_HASH_HEX).unwrap();
+        assert_eq!(
+            ControlCommand::parse(&format!("skip {} 0,2-3", INFO_HASH_HEX)),
+            Ok(ControlCommand::SetFilePriority(
+                info_hash.clone(),
+                vec![0..=0, 2..=3],
+                FilePriority::Skip
+            ))
+        );
+        assert_eq!(
+            ControlCommand::parse(&format!("unskip {} 1", INFO_HASH_HEX)),
+            Ok(ControlCommand::SetFilePriority(
+                info_hash,
+                vec![1..=1],
+                FilePriority::Normal
+            ))
+        );
+        assert!(ControlCommand::parse(&format!("skip {}", INFO_HASH_HEX)).is_err());
+        assert!(ControlCommand::parse(&format!("skip {} all", INFO_HASH_HEX)).is_err());
+    }
+
+    #[test]
+    fn test_parse_seed_goals() {
+        let info_hash = hex::decode(INFO_HASH_HEX).unwrap();
+
+        assert_eq!(
+            ControlCommand::parse(&format!("goal {} 2.5 3d remove", INFO_HASH_HEX)),
+            Ok(ControlCommand::SeedGoals(
+                info_hash.clone(),
+                Some(SeedGoals {
+                    ratio: Some(2.5),
+                    seed_time_secs: Some(3 * 86400),
+                    action: SeedGoalAction::Remove,
+                })
+            ))
+        );
+        assert_eq!(
+            ControlCommand::parse(&format!("goal {} 5 remove-data", INFO_HASH_HEX)),
+            Ok(ControlCommand::SeedGoals(
+                info_hash.clone(),
+                Some(SeedGoals {
+                    ratio: Some(5.0),
+                    action: SeedGoalAction::RemoveWithData,
+                    ..Default::default()
+                })
+            ))
+        );
+        assert_eq!(
+            ControlCommand::parse(&format!("goal {} 90m", INFO_HASH_HEX)),
+            Ok(ControlCommand::SeedGoals(
+                info_hash.clone(),
+                Some(SeedGoals {
+                    seed_time_secs: Some(5400),
+                    ..Default::default()
+                })
+            ))
+        );
+        assert_eq!(
+            ControlCommand::parse(&format!("goal {} never", INFO_HASH_HEX)),
+            Ok(ControlCommand::SeedGoals(
+                info_hash.clone(),
+                Some(SeedGoals::default())
+            ))
+        );
+        assert_eq!(
+            ControlCommand::parse(&format!("goal {} default", INFO_HASH_HEX)),
+            Ok(ControlCommand::SeedGoals(info_hash, None))
+        );
+        assert!(ControlCommand::parse(&format!("goal {}", INFO_HASH_HEX)).is_err());
+        assert!(ControlCommand::parse(&format!("goal {} remove", INFO_HASH_HEX)).is_err());
+        assert!(ControlCommand::parse(&format!("goal {} 2x", INFO_HASH_HEX)).is_err());
+        assert!(ControlCommand::parse(&format!("goal {} 1.5h", INFO_HASH_HEX)).is_err());
+    }
+
+    #[test]
+    fn test_parse_category() {
+        let info_hash = hex::decode(INFO_HASH_HEX).unwrap();
+        assert_eq!(
+            ControlCommand::parse(&format!("category {} TV Shows", INFO_HASH_HEX)),
+            Ok(ControlCommand::Category(
+                info_hash.clone(),
+                Some("TV Shows".to_string())
+            ))
+        );
+        assert_eq!(
+            ControlCommand::parse(&format!("category {} none", INFO_HASH_HEX)),
+            Ok(ControlCommand::Category(info_hash, None))
+        );
+        assert!(ControlCommand::parse(&format!("category {}", INFO_HASH_HEX)).is_err());
+    }
+
+    #[test]
+    fn test_parse_priority() {
+        let info_hash = hex::decode(INFO_HASH_HEX).unwrap();
+        assert_eq!(
+            ControlCommand::parse(&format!("priority {} High", INFO_HASH_HEX)),
+            Ok(ControlCommand::Priority(info_hash, BandwidthPriority::High))
+        );
+        assert!(ControlCommand::parse(&format!("priority {}", INFO_HASH_HEX)).is_err());
+        assert!(ControlCommand::parse(&format!("priority {} urgent", INFO_HASH_HEX)).is_err());
+    }
+
+    #[test]
+    fn test_parse_peer_discovery_switches() {
+        let info_hash = hex::decode(INFO_HASH_HEX).unwrap();
+        assert_eq!(
+            ControlCommand::parse(&format!("dht {} off", INFO_HASH_HEX)),
+            Ok(ControlCommand::Dht(info_hash.clone(), false))
+        );
+        assert_eq!(
+            ControlCommand::parse(&format!("PEX {} On", INFO_HASH_HEX)),
+            Ok(ControlCommand::Pex(info_hash, true))
+        );
+        assert!(ControlCommand::parse(&format!("dht {}", INFO_HASH_HEX)).is_err());
+        assert!(ControlCommand::parse(&format!("pex {} maybe", INFO_HASH_HEX)).is_err());
+    }
+
+    #[test]
+    fn test_parse_seed() {
+        assert_eq!(
+            ControlCommand::parse("seed /home/me/My Album.torrent\t/home/me/music"),
+            Ok(ControlCommand::Seed(
+                PathBuf::from("/home/me/My Album.torrent"),
+                PathBuf::from("/home/me/music")
+            ))
+        );
+        assert!(ControlCommand::parse("seed /home/me/My Album.torrent").is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_input() {
+        assert!(ControlCommand::parse("add").is_err());
+        assert!(ControlCommand::parse("resume not-a-hash").is_err());
+        assert!(ControlCommand::parse("resume").is_err());
+        assert!(ControlCommand::parse("restart").is_err());
+    }
+}
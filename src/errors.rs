@@ -1,6 +1,9 @@
 // SPDX-FileCopyrightText: 2025 The superseedr Contributors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+use std::path::PathBuf;
+use std::time::Duration;
+
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -13,6 +16,123 @@ pub enum TrackerError {
 
     #[error("Tracker returned a failure reason: {0}")]
     Tracker(String),
+
+    #[error("UDP tracker request failed: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Tracker did not answer in time")]
+    Timeout,
+
+    #[error("Tracker sent a malformed response")]
+    InvalidResponse,
+
+    #[error("Tracker does not support scraping")]
+    ScrapeUnsupported,
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum PieceVerifyError {
+    #[error("Piece is {actual} bytes but should be {expected} bytes")]
+    LengthMismatch { expected: usize, actual: usize },
+
+    #[error("Piece does not match its hash")]
+    HashMismatch,
+
+    #[error("Torrent has no hash for this piece")]
+    MissingHash,
+
+    #[error("Piece hash could not be computed")]
+    HashFailed,
+}
+
+/// Why a piece could not be fetched from an HTTP seed.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum WebSeedError {
+    #[error("Request to web seed failed: {0}")]
+    Request(String),
+
+    #[error("Web seed answered with HTTP status {0}")]
+    Status(u16),
+
+    #[error("Web seed is busy; try again in {0:?}")]
+    Busy(Duration),
+
+    #[error("Web seed sent {actual} bytes but the piece is {expected} bytes")]
+    LengthMismatch { expected: usize, actual: usize },
+}
+
+impl WebSeedError {
+    /// How long the seed asked us to wait before the next request, if it did.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::Busy(delay) => Some(*delay),
+            _ => None,
+        }
+    }
+}
+
+/// Why an IP filter list could not be loaded.
+#[derive(Error, Debug)]
+pub enum IpFilterError {
+    #[error("Failed to download IP filter: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("Failed to read IP filter: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("IP filter download is too large")]
+    TooLarge,
+}
+
+/// Why the external IP check URL gave no address.
+#[derive(Error, Debug)]
+pub enum ExternalIpError {
+    #[error("Failed to reach external IP check: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("External IP check did not answer with an address")]
+    InvalidResponse,
+}
+
+/// Why the listening port could not be checked.
+#[derive(Error, Debug)]
+pub enum PortCheckError {
+    #[error("Failed to reach port check: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("Port check did not answer open or closed")]
+    InvalidResponse,
+
+    #[error("External IP not known yet")]
+    NoExternalIp,
+}
+
+/// Why a torrent could not be made from a file or folder.
+#[derive(Error, Debug)]
+pub enum CreateTorrentError {
+    #[error("Failed to read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("{0} has a name that isn't UTF-8")]
+    InvalidName(PathBuf),
+
+    #[error("Found no files with any data to make a torrent of")]
+    NoFiles,
+
+    #[error("{0} already exists")]
+    AlreadyExists(PathBuf),
+
+    #[error("Piece size {0} must be a power of two of at least 16 KiB")]
+    InvalidPieceLength(u64),
+
+    #[error("Failed to encode the torrent: {0}")]
+    Encode(#[from] serde_bencode::Error),
+
+    #[error("Torrent creation stopped unexpectedly: {0}")]
+    Aborted(#[from] tokio::task::JoinError),
 }
 
 #[derive(Error, Debug)]
@@ -20,3 +140,21 @@ pub enum StorageError {
     #[error("An I/O error occurred")]
     Io(#[from] std::io::Error),
 }
+
+/// Why a torrent manager could not be created. Bad metadata will fail the same way
+/// every time; storage failures may go away once the disk is back.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ManagerInitError {
+    #[error("Invalid torrent metadata: {0}")]
+    InvalidMetadata(String),
+
+    #[error("Storage is not ready: {0}")]
+    Storage(String),
+}
+
+impl ManagerInitError {
+    /// Whether trying again later might succeed.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Storage(_))
+    }
+}
@@ -0,0 +1,269 @@
+// SPDX-FileCopyrightText: 2025 The superseedr Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::time::Duration;
+
+use tokio::sync::{broadcast, mpsc};
+use tracing::{event, Level};
+
+use crate::app::AppCommand;
+use crate::errors::ExternalIpError;
+
+// Trackers and peers can be wrong or lie; a new address needs this many of them to agree
+const REPORTS_TO_CHANGE: usize = 3;
+// A check URL answers with an address, not a web page
+const MAX_CHECK_RESPONSE_BYTES: usize = 256;
+
+/// Where a report of our external address came from.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum IpSource {
+    // The "external ip" key of an announce response (BEP 24), by tracker URL
+    Tracker(String),
+    // The "yourip" key of an extended handshake (BEP 10), by the peer's address
+    Peer(IpAddr),
+    // The DHT node's public address, which it only reports once nodes agree on it
+    #[cfg(feature = "dht")]
+    Dht,
+    // The `external_ip_check_url` setting
+    CheckUrl,
+}
+
+impl IpSource {
+    fn is_trusted(&self) -> bool {
+        match self {
+            #[cfg(feature = "dht")]
+            IpSource::Dht => true,
+            IpSource::CheckUrl => true,
+            IpSource::Tracker(_) | IpSource::Peer(_) => false,
+        }
+    }
+}
+
+/// Our external address as the rest of the swarm sees it, kept apart for IPv4 and
+/// IPv6.
+#[derive(Debug, Default)]
+pub struct ExternalIp {
+    v4: Family,
+    v6: Family,
+}
+
+#[derive(Debug, Default)]
+struct Family {
+    current: Option<IpAddr>,
+    // Addresses other than `current` and who reported them
+    candidates: HashMap<IpAddr, HashSet<IpSource>>,
+}
+
+/// What a report changed.
+#[derive(Debug, PartialEq)]
+pub enum IpChange {
+    Unchanged,
+    // The first address we learned for this family
+    Learned(IpAddr),
+    Changed { old: IpAddr, new: IpAddr },
+}
+
+impl ExternalIp {
+    /// Takes a report of our address. The first address is taken as is; a different
+    /// one replaces it once the DHT or the check URL reports it, or enough trackers
+    /// and peers do. Private and loopback addresses are ignored.
+    pub fn report(&mut self, ip: IpAddr, source: IpSource) -> IpChange {
+        let ip = ip.to_canonical();
+        if !is_public(ip) {
+            return IpChange::Unchanged;
+        }
+        let family = if ip.is_ipv4() {
+            &mut self.v4
+        } else {
+            &mut self.v6
+        };
+
+        let Some(old) = family.current else {
+            family.current = Some(ip);
+            return IpChange::Learned(ip);
+        };
+        if old == ip {
+            // A report of the current address outvotes the same source's old one.
+            for reporters in family.candidates.values_mut() {
+                reporters.remove(&source);
+            }
+            family
+                .candidates
+                .retain(|_, reporters| !reporters.is_empty());
+            return IpChange::Unchanged;
+        }
+
+        let reporters = family.candidates.entry(ip).or_default();
+        reporters.insert(source.clone());
+        if !source.is_trusted() && reporters.len() < REPORTS_TO_CHANGE {
+            return IpChange::Unchanged;
+        }
+        family.current = Some(ip);
+        family.candidates.clear();
+        IpChange::Changed { old, new: ip }
+    }
+
+    /// Our address, IPv4 when we know it.
+    pub fn current(&self) -> Option<IpAddr> {
+        self.v4.current.or(self.v6.current)
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            !(v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_documentation())
+        }
+        IpAddr::V6(v6) => {
+            let first = v6.segments()[0];
+            // Unique local (fc00::/7) and link-local (fe80::/10)
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80)
+        }
+    }
+}
+
+/// Asks `url` for our address: the response body is the address as text.
+pub async fn check(url: &str) -> Result<IpAddr, ExternalIpError> {
+    let response = reqwest::get(url).await?.error_for_status()?;
+    let bytes = response.bytes().await?;
+    if bytes.len() > MAX_CHECK_RESPONSE_BYTES {
+        return Err(ExternalIpError::InvalidResponse);
+    }
+    String::from_utf8_lossy(&bytes)
+        .trim()
+        .parse()
+        .map_err(|_| ExternalIpError::InvalidResponse)
+}
+
+/// Checks `url` now and again every `interval`, handing each answer to the app.
+pub fn spawn_check(
+    url: String,
+    interval: Duration,
+    app_command_tx: mpsc::Sender<AppCommand>,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) {
+    tokio::spawn(async move {
+        loop {
+            match check(&url).await {
+                Ok(ip) => {
+                    if app_command_tx
+                        .send(AppCommand::ExternalIpReported(ip, IpSource::CheckUrl))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    event!(Level::DEBUG, %url, error = %e, "External IP check failed.");
+                }
+            }
+
+            tokio::select! {
+                _ = shutdown_rx.recv() => break,
+                _ = tokio::time::sleep(interval) => {}
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    fn tracker(url: &str) -> IpSource {
+        IpSource::Tracker(url.to_string())
+    }
+
+    #[test]
+    fn test_first_address_is_learned_and_private_ones_ignored() {
+        let mut external_ip = ExternalIp::default();
+        assert_eq!(
+            external_ip.report(ip("192.168.1.5"), tracker("http://a")),
+            IpChange::Unchanged
+        );
+        assert_eq!(
+            external_ip.report(ip("fe80::1"), tracker("http://a")),
+            IpChange::Unchanged
+        );
+        assert_eq!(
+            external_ip.report(ip("::ffff:81.2.69.160"), tracker("http://a")),
+            IpChange::Learned(ip("81.2.69.160"))
+        );
+        assert_eq!(
+            external_ip.report(ip("2a00:1450::1"), IpSource::Peer(ip("81.2.69.1"))),
+            IpChange::Learned(ip("2a00:1450::1"))
+        );
+    }
+
+    #[test]
+    fn test_change_needs_agreeing_reports() {
+        let mut external_ip = ExternalIp::default();
+        external_ip.report(ip("81.2.69.160"), tracker("http://a"));
+
+        let new = ip("81.2.69.200");
+        assert_eq!(
+            external_ip.report(new, tracker("http://a")),
+            IpChange::Unchanged
+        );
+        // The same source again doesn't count twice.
+        assert_eq!(
+            external_ip.report(new, tracker("http://a")),
+            IpChange::Unchanged
+        );
+        assert_eq!(
+            external_ip.report(new, IpSource::Peer(ip("1.1.1.1"))),
+            IpChange::Unchanged
+        );
+        assert_eq!(
+            external_ip.report(new, IpSource::Peer(ip("1.0.0.1"))),
+            IpChange::Changed {
+                old: ip("81.2.69.160"),
+                new
+            }
+        );
+    }
+
+    #[test]
+    fn test_source_reporting_current_address_withdraws_its_vote() {
+        let mut external_ip = ExternalIp::default();
+        let old = ip("81.2.69.160");
+        let new = ip("81.2.69.200");
+        external_ip.report(old, tracker("http://a"));
+
+        external_ip.report(new, tracker("http://a"));
+        external_ip.report(new, tracker("http://b"));
+        external_ip.report(old, tracker("http://a"));
+        assert_eq!(
+            external_ip.report(new, tracker("http://c")),
+            IpChange::Unchanged
+        );
+    }
+
+    #[test]
+    fn test_trusted_source_changes_address_at_once() {
+        let mut external_ip = ExternalIp::default();
+        external_ip.report(ip("81.2.69.160"), tracker("http://a"));
+        assert_eq!(
+            external_ip.report(ip("81.2.69.200"), IpSource::CheckUrl),
+            IpChange::Changed {
+                old: ip("81.2.69.160"),
+                new: ip("81.2.69.200")
+            }
+        );
+    }
+}
@@ -0,0 +1,68 @@
+// SPDX-FileCopyrightText: 2025 The superseedr Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+
+use crate::app::PeerInfo;
+
+/// Offline country/ASN lookups backed by a MaxMind-format (.mmdb) database.
+pub struct GeoIpDatabase {
+    reader: Reader<Vec<u8>>,
+    is_asn: bool,
+}
+
+impl GeoIpDatabase {
+    pub fn open(path: &Path) -> Result<Self, MaxMindDBError> {
+        let reader = Reader::open_readfile(path)?;
+        let is_asn = reader.metadata.database_type.contains("ASN");
+        Ok(Self { reader, is_asn })
+    }
+
+    pub fn is_asn(&self) -> bool {
+        self.is_asn
+    }
+
+    /// Returns an ISO country code, or "AS<number>" when backed by an ASN database.
+    pub fn lookup_label(&self, ip: IpAddr) -> Option<String> {
+        if self.is_asn {
+            let asn: geoip2::Asn = self.reader.lookup(ip).ok()?;
+            asn.autonomous_system_number.map(|n| format!("AS{}", n))
+        } else {
+            let country: geoip2::Country = self.reader.lookup(ip).ok()?;
+            country.country?.iso_code.map(str::to_string)
+        }
+    }
+}
+
+/// Peers sharing one country or network label and their combined speeds.
+#[derive(Debug, Clone, Default)]
+pub struct LabelStats {
+    pub label: String,
+    pub peers: usize,
+    pub download_speed_bps: u64,
+    pub upload_speed_bps: u64,
+}
+
+/// Groups peers by their looked-up label, most peers first. Peers with no label
+/// count as "??".
+pub fn top_labels(peers: &[PeerInfo], top_n: usize) -> Vec<LabelStats> {
+    let mut stats: HashMap<&str, LabelStats> = HashMap::new();
+    for peer in peers {
+        let label = peer.country.as_deref().unwrap_or("??");
+        let entry = stats.entry(label).or_insert_with(|| LabelStats {
+            label: label.to_string(),
+            ..Default::default()
+        });
+        entry.peers += 1;
+        entry.download_speed_bps += peer.download_speed_bps;
+        entry.upload_speed_bps += peer.upload_speed_bps;
+    }
+
+    let mut sorted: Vec<LabelStats> = stats.into_values().collect();
+    sorted.sort_by(|a, b| b.peers.cmp(&a.peers).then_with(|| a.label.cmp(&b.label)));
+    sorted.truncate(top_n);
+    sorted
+}
@@ -0,0 +1,175 @@
+// SPDX-FileCopyrightText: 2025 The superseedr Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use crate::errors::PieceVerifyError;
+use crate::torrent_file::PieceHash;
+
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// Caps how many piece hashes run at the same time. The hashing itself runs on
+/// tokio's blocking pool, so it never stalls the async runtime; the semaphore just
+/// keeps it to the configured number of threads. Shared by all torrents.
+#[derive(Clone, Debug)]
+pub struct HashPool {
+    permits: Arc<Semaphore>,
+    threads: usize,
+}
+
+impl HashPool {
+    pub fn new(threads: usize) -> Self {
+        let threads = threads.max(1);
+        Self {
+            permits: Arc::new(Semaphore::new(threads)),
+            threads,
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Number of hashes currently running.
+    pub fn busy_threads(&self) -> usize {
+        self.threads - self.permits.available_permits()
+    }
+
+    /// Hashes `data` once a thread is free and returns it back if it matches
+    /// `expected_hash`. When `expected_len` is given the length is checked first, so
+    /// a short or padded piece is reported as such rather than as a bad hash.
+    pub async fn verify(
+        &self,
+        data: Vec<u8>,
+        expected_hash: Option<PieceHash>,
+        expected_len: Option<usize>,
+    ) -> Result<Vec<u8>, PieceVerifyError> {
+        if let Some(expected) = expected_len {
+            if data.len() != expected {
+                return Err(PieceVerifyError::LengthMismatch {
+                    expected,
+                    actual: data.len(),
+                });
+            }
+        }
+
+        let expected_hash = expected_hash.ok_or(PieceVerifyError::MissingHash)?;
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|_| PieceVerifyError::HashFailed)?;
+
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            if expected_hash.matches(&data) {
+                Ok(data)
+            } else {
+                Err(PieceVerifyError::HashMismatch)
+            }
+        })
+        .await
+        .map_err(|_| PieceVerifyError::HashFailed)?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::torrent_file::v2::{merkle_root, MerklePiece, MERKLE_BLOCK_SIZE};
+    use sha1::{Digest, Sha1};
+
+    fn sha1_hash(data: &[u8]) -> Option<PieceHash> {
+        Some(PieceHash::Sha1(Sha1::digest(data).to_vec()))
+    }
+
+    #[tokio::test]
+    async fn test_verify_matching_and_mismatching_hash() {
+        let pool = HashPool::new(2);
+        let data = b"piece data".to_vec();
+        let expected = sha1_hash(&data);
+
+        assert_eq!(
+            pool.verify(data.clone(), expected, None).await,
+            Ok(data.clone())
+        );
+        assert_eq!(
+            pool.verify(data.clone(), Some(PieceHash::Sha1(vec![0; 20])), None)
+                .await,
+            Err(PieceVerifyError::HashMismatch)
+        );
+        assert_eq!(
+            pool.verify(data, None, None).await,
+            Err(PieceVerifyError::MissingHash)
+        );
+        assert_eq!(pool.busy_threads(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_verify_short_last_piece_length() {
+        // 2.5 pieces of 16 bytes: the last piece is 8 bytes, not a full piece.
+        let pool = HashPool::new(1);
+        let last_piece = vec![7u8; 8];
+        let expected = sha1_hash(&last_piece);
+
+        assert_eq!(
+            pool.verify(last_piece.clone(), expected.clone(), Some(8))
+                .await,
+            Ok(last_piece.clone())
+        );
+
+        // Padding the last piece out to the full piece length is a length error.
+        let mut padded = last_piece.clone();
+        padded.resize(16, 0);
+        assert_eq!(
+            pool.verify(padded, expected.clone(), Some(8)).await,
+            Err(PieceVerifyError::LengthMismatch {
+                expected: 8,
+                actual: 16
+            })
+        );
+
+        // So is a piece that came back one byte short, even before hashing.
+        assert_eq!(
+            pool.verify(last_piece[..7].to_vec(), expected, Some(8))
+                .await,
+            Err(PieceVerifyError::LengthMismatch {
+                expected: 8,
+                actual: 7
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_merkle_piece_ignores_trailing_padding() {
+        // A v2 file ends one and a half blocks into a two block piece.
+        let pool = HashPool::new(1);
+        let data_len = MERKLE_BLOCK_SIZE + MERKLE_BLOCK_SIZE / 2;
+        let file_data = vec![3u8; data_len];
+        let expected = Some(PieceHash::Merkle(MerklePiece {
+            root: merkle_root(&file_data, 2),
+            leaf_count: 2,
+            data_len,
+        }));
+
+        let mut piece = file_data.clone();
+        piece.resize(2 * MERKLE_BLOCK_SIZE, 0);
+        assert_eq!(
+            pool.verify(piece.clone(), expected.clone(), None).await,
+            Ok(piece.clone())
+        );
+
+        piece[0] = 4;
+        assert_eq!(
+            pool.verify(piece, expected, None).await,
+            Err(PieceVerifyError::HashMismatch)
+        );
+    }
+
+    #[test]
+    fn test_zero_threads_still_allows_hashing() {
+        let pool = HashPool::new(0);
+        assert_eq!(pool.threads(), 1);
+        assert_eq!(pool.busy_threads(), 0);
+    }
+}
@@ -0,0 +1,281 @@
+// SPDX-FileCopyrightText: 2025 The superseedr Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+use tokio::sync::{broadcast, mpsc};
+use tracing::{event, Level};
+
+use crate::app::AppCommand;
+use crate::errors::IpFilterError;
+
+// eMule .dat entries at or above this access level are allowed, not blocked
+const EMULE_ALLOW_LEVEL: u32 = 128;
+// Lists fetched from a URL over this size are refused
+const MAX_DOWNLOAD_BYTES: usize = 64 * 1024 * 1024;
+
+/// Blocked IP ranges, loaded from an eMule .dat, PeerGuardian (P2P) plaintext or
+/// CIDR list. Each line may use any of the formats:
+///
+/// ```text
+/// 001.002.004.000 - 001.002.004.255 , 000 , Some organisation
+/// Some organisation:1.2.4.0-1.2.4.255
+/// 1.2.4.0/24
+/// 2001:db8::/32
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct IpFilter {
+    // Sorted, non-overlapping inclusive ranges
+    v4: Vec<(u32, u32)>,
+    v6: Vec<(u128, u128)>,
+}
+
+impl IpFilter {
+    /// Reads every line it understands; comments and lines it can't read are skipped.
+    pub fn parse(content: &str) -> Self {
+        let mut filter = Self::default();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+                continue;
+            }
+            match parse_line(line) {
+                Some(IpRange::V4(start, end)) => filter.v4.push((start, end)),
+                Some(IpRange::V6(start, end)) => filter.v6.push((start, end)),
+                None => {}
+            }
+        }
+        merge_ranges(&mut filter.v4);
+        merge_ranges(&mut filter.v6);
+        filter
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match ip.to_canonical() {
+            IpAddr::V4(v4) => range_contains(&self.v4, u32::from(v4)),
+            IpAddr::V6(v6) => range_contains(&self.v6, u128::from(v6)),
+        }
+    }
+
+    /// Number of blocked ranges after merging overlaps.
+    pub fn len(&self) -> usize {
+        self.v4.len() + self.v6.len()
+    }
+}
+
+/// Loads a list from `source`, an http(s) URL or a file path.
+pub async fn load(source: &str) -> Result<IpFilter, IpFilterError> {
+    let content = if source.starts_with("http://") || source.starts_with("https://") {
+        let response = reqwest::get(source).await?.error_for_status()?;
+        if response
+            .content_length()
+            .is_some_and(|length| length as usize > MAX_DOWNLOAD_BYTES)
+        {
+            return Err(IpFilterError::TooLarge);
+        }
+        let bytes = response.bytes().await?;
+        if bytes.len() > MAX_DOWNLOAD_BYTES {
+            return Err(IpFilterError::TooLarge);
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+    } else {
+        let bytes = tokio::fs::read(source).await?;
+        String::from_utf8_lossy(&bytes).into_owned()
+    };
+    Ok(IpFilter::parse(&content))
+}
+
+/// Loads the list now and again every `refresh` (never, when `None`), handing each
+/// loaded copy to the app. A failed load keeps the last good list.
+pub fn spawn_refresh(
+    source: String,
+    refresh: Option<Duration>,
+    app_command_tx: mpsc::Sender<AppCommand>,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) {
+    tokio::spawn(async move {
+        loop {
+            match load(&source).await {
+                Ok(ip_filter) => {
+                    if app_command_tx
+                        .send(AppCommand::IpFilterLoaded(ip_filter))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    event!(Level::WARN, %source, error = %e, "Failed to load IP filter.");
+                }
+            }
+
+            let Some(refresh) = refresh else {
+                break;
+            };
+            tokio::select! {
+                _ = shutdown_rx.recv() => break,
+                _ = tokio::time::sleep(refresh) => {}
+            }
+        }
+    });
+}
+
+#[derive(Debug, PartialEq)]
+enum IpRange {
+    V4(u32, u32),
+    V6(u128, u128),
+}
+
+fn parse_line(line: &str) -> Option<IpRange> {
+    // eMule: "start - end , access level , description". Only a numeric level marks
+    // the line as eMule, since P2P descriptions may contain commas too.
+    if let Some((range, rest)) = line.split_once(',') {
+        if let Ok(level) = rest.split(',').next().unwrap_or("").trim().parse::<u32>() {
+            if level >= EMULE_ALLOW_LEVEL {
+                return None;
+            }
+            return parse_range(range.trim());
+        }
+    }
+    // P2P: "description:start-end". IPv6 lists use the bare formats.
+    if let Some((_description, range)) = line.rsplit_once(':') {
+        if let Some(range @ IpRange::V4(..)) = parse_range(range.trim()) {
+            return Some(range);
+        }
+    }
+    parse_range(line)
+}
+
+/// A single address, a CIDR block or a `start-end` range.
+fn parse_range(text: &str) -> Option<IpRange> {
+    if let Some((ip, prefix)) = text.split_once('/') {
+        let prefix: u32 = prefix.trim().parse().ok()?;
+        return match parse_ip(ip.trim())? {
+            IpAddr::V4(ip) if prefix <= 32 => {
+                let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+                let start = u32::from(ip) & mask;
+                Some(IpRange::V4(start, start | !mask))
+            }
+            IpAddr::V6(ip) if prefix <= 128 => {
+                let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+                let start = u128::from(ip) & mask;
+                Some(IpRange::V6(start, start | !mask))
+            }
+            _ => None,
+        };
+    }
+
+    let (start, end) = match text.split_once('-') {
+        Some((start, end)) => (parse_ip(start.trim())?, parse_ip(end.trim())?),
+        None => {
+            let ip = parse_ip(text)?;
+            (ip, ip)
+        }
+    };
+    match (start, end) {
+        (IpAddr::V4(start), IpAddr::V4(end)) if start <= end => {
+            Some(IpRange::V4(start.into(), end.into()))
+        }
+        (IpAddr::V6(start), IpAddr::V6(end)) if start <= end => {
+            Some(IpRange::V6(start.into(), end.into()))
+        }
+        _ => None,
+    }
+}
+
+/// Like `IpAddr::from_str`, but also takes the zero-padded IPv4 octets eMule lists
+/// use ("001.002.004.000").
+fn parse_ip(text: &str) -> Option<IpAddr> {
+    if let Ok(ip) = text.parse::<Ipv6Addr>() {
+        return Some(IpAddr::V6(ip));
+    }
+    let mut octets = [0u8; 4];
+    let mut parts = text.split('.');
+    for octet in &mut octets {
+        *octet = parts.next()?.parse().ok()?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(IpAddr::V4(Ipv4Addr::from(octets)))
+}
+
+fn merge_ranges<T: Ord + Copy>(ranges: &mut Vec<(T, T)>) {
+    ranges.sort_unstable();
+    let mut merged: Vec<(T, T)> = Vec::with_capacity(ranges.len());
+    for &(start, end) in ranges.iter() {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    *ranges = merged;
+}
+
+fn range_contains<T: Ord + Copy>(ranges: &[(T, T)], ip: T) -> bool {
+    let after = ranges.partition_point(|&(start, _)| start <= ip);
+    after > 0 && ranges[after - 1].1 >= ip
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_formats() {
+        let filter = IpFilter::parse(
+            "# comment\n\
+             001.002.004.000 - 001.002.004.255 , 000 , Blocked org\n\
+             005.006.007.000 - 005.006.007.255 , 200 , Allowed org\n\
+             Some org: with colon:10.0.0.0-10.0.0.9\n\
+             192.168.1.0/24\n\
+             2001:db8::/32\n\
+             8.8.8.8\n\
+             not a range\n",
+        );
+
+        assert!(filter.contains(ip("1.2.4.0")));
+        assert!(filter.contains(ip("1.2.4.255")));
+        assert!(!filter.contains(ip("1.2.5.0")));
+        assert!(!filter.contains(ip("5.6.7.8")));
+        assert!(filter.contains(ip("10.0.0.9")));
+        assert!(!filter.contains(ip("10.0.0.10")));
+        assert!(filter.contains(ip("192.168.1.77")));
+        assert!(filter.contains(ip("2001:db8:ffff::1")));
+        assert!(!filter.contains(ip("2001:db9::1")));
+        assert!(filter.contains(ip("8.8.8.8")));
+        assert!(!filter.contains(ip("8.8.4.4")));
+        assert_eq!(filter.len(), 5);
+    }
+
+    #[test]
+    fn test_overlapping_ranges_merge() {
+        let filter = IpFilter::parse("10.0.0.0-10.0.0.100\n10.0.0.50-10.0.1.0\n10.0.0.0/8\n");
+        assert_eq!(filter.len(), 1);
+        assert!(filter.contains(ip("10.255.255.255")));
+        assert!(!filter.contains(ip("11.0.0.0")));
+    }
+
+    #[test]
+    fn test_mapped_ipv4_is_checked_as_ipv4() {
+        let filter = IpFilter::parse("1.2.3.0/24\n");
+        assert!(filter.contains(ip("::ffff:1.2.3.4")));
+        assert!(IpFilter::parse("0.0.0.0/0\n").contains(ip("255.255.255.255")));
+    }
+
+    #[test]
+    fn test_p2p_description_with_comma() {
+        let filter = IpFilter::parse(
+            "Acme, Inc.:3.3.3.0-3.3.3.255
+",
+        );
+        assert!(filter.contains(ip("3.3.3.3")));
+        assert_eq!(filter.len(), 1);
+    }
+}
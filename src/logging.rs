@@ -0,0 +1,192 @@
+// SPDX-FileCopyrightText: 2025 The superseedr Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use crate::config::LogLevel;
+
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+#[cfg(any(feature = "dht", test))]
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+    sync::Mutex,
+};
+
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling;
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::{fmt, prelude::*, reload, Registry};
+
+// Set once the log file layer is installed, so the level can change while running.
+static FILTER_HANDLE: OnceLock<reload::Handle<Targets, Registry>> = OnceLock::new();
+
+// mainline traces every message its socket receives, but keeps no count of them
+#[cfg(any(feature = "dht", test))]
+const DHT_SOCKET_TARGET: &str = "mainline::rpc::socket";
+#[cfg(any(feature = "dht", test))]
+const DHT_REQUEST_RECEIVED: &str = "Received request message";
+
+/// Queries other DHT nodes have sent us since startup.
+#[cfg(feature = "dht")]
+pub fn dht_queries_received() -> u64 {
+    DHT_QUERIES.count()
+}
+
+#[cfg(feature = "dht")]
+static DHT_QUERIES: DhtQueryCounter = DhtQueryCounter::new();
+
+/// Counts the DHT socket's "Received request message" traces, whatever level the
+/// log file is at.
+#[cfg(any(feature = "dht", test))]
+struct DhtQueryCounter {
+    received: AtomicU64,
+    // Whether each trace call site in the DHT socket is the one for requests, so
+    // the message is only formatted the first time a call site fires
+    request_callsites: Mutex<Option<HashMap<tracing::callsite::Identifier, bool>>>,
+}
+
+#[cfg(any(feature = "dht", test))]
+impl DhtQueryCounter {
+    const fn new() -> Self {
+        Self {
+            received: AtomicU64::new(0),
+            request_callsites: Mutex::new(None),
+        }
+    }
+
+    fn count(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    fn record(&self, event: &tracing::Event<'_>) {
+        let mut callsites = self.request_callsites.lock().unwrap();
+        let is_request = *callsites
+            .get_or_insert_with(HashMap::new)
+            .entry(event.metadata().callsite())
+            .or_insert_with(|| {
+                let mut visitor = MessageVisitor(false);
+                event.record(&mut visitor);
+                visitor.0
+            });
+        if is_request {
+            self.received.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Looks for the request trace's message among an event's fields.
+#[cfg(any(feature = "dht", test))]
+struct MessageVisitor(bool);
+
+#[cfg(any(feature = "dht", test))]
+impl tracing::field::Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" && format!("{:?}", value) == DHT_REQUEST_RECEIVED {
+            self.0 = true;
+        }
+    }
+}
+
+#[cfg(any(feature = "dht", test))]
+struct DhtQueryLayer(&'static DhtQueryCounter);
+
+#[cfg(any(feature = "dht", test))]
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for DhtQueryLayer {
+    fn on_event(
+        &self,
+        event: &tracing::Event<'_>,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        self.0.record(event);
+    }
+}
+
+#[cfg(any(feature = "dht", test))]
+fn dht_query_layer<S>(counter: &'static DhtQueryCounter) -> impl tracing_subscriber::Layer<S>
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    DhtQueryLayer(counter)
+        .with_filter(Targets::new().with_target(DHT_SOCKET_TARGET, LevelFilter::TRACE))
+}
+
+fn filter_for(level: LogLevel) -> Targets {
+    let level = match level {
+        LogLevel::Error => LevelFilter::ERROR,
+        LogLevel::Warn => LevelFilter::WARN,
+        LogLevel::Info => LevelFilter::INFO,
+        LogLevel::Debug => LevelFilter::DEBUG,
+        LogLevel::Trace => LevelFilter::TRACE,
+    };
+    Targets::new()
+        .with_default(level)
+        .with_target("mainline::rpc::socket", LevelFilter::ERROR)
+}
+
+/// Sends logs to `app.log` in `log_dir`. The returned guard flushes the writer when
+/// dropped, so keep it alive for the whole run.
+pub fn init(log_dir: &Path, level: LogLevel) -> WorkerGuard {
+    let general_log = rolling::never(log_dir, "app.log");
+    let (non_blocking_general, guard) = tracing_appender::non_blocking(general_log);
+
+    if fs::create_dir_all(log_dir).is_ok() {
+        let (filter, handle) = reload::Layer::new(filter_for(level));
+        let general_layer = fmt::layer()
+            .with_writer(non_blocking_general)
+            .with_filter(filter);
+
+        let subscriber = tracing_subscriber::registry().with(general_layer);
+        #[cfg(feature = "dht")]
+        let subscriber = subscriber.with(dht_query_layer(&DHT_QUERIES));
+        if subscriber.try_init().is_ok() {
+            let _ = FILTER_HANDLE.set(handle);
+        }
+    } else {
+        let subscriber = tracing_subscriber::registry();
+        #[cfg(feature = "dht")]
+        let subscriber = subscriber.with(dht_query_layer(&DHT_QUERIES));
+        let _ = subscriber.try_init();
+    }
+
+    guard
+}
+
+/// Changes the log file level. Does nothing if logging was never set up.
+pub fn set_level(level: LogLevel) {
+    if let Some(handle) = FILTER_HANDLE.get() {
+        if let Err(e) = handle.reload(filter_for(level)) {
+            tracing::warn!("Could not change log level: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dht_query_counter_counts_received_requests_only() {
+        static COUNTER: DhtQueryCounter = DhtQueryCounter::new();
+        let subscriber = tracing_subscriber::registry().with(dht_query_layer(&COUNTER));
+        tracing::subscriber::with_default(subscriber, || {
+            for _ in 0..3 {
+                // The shape of mainline's traces, which name the parsed message `message` too
+                tracing::trace!(
+                    target: "mainline::rpc::socket",
+                    context = "socket_message_receiving",
+                    message = ?"get_peers",
+                    "Received request message"
+                );
+                tracing::trace!(
+                    target: "mainline::rpc::socket",
+                    context = "socket_message_receiving",
+                    message = ?"nodes",
+                    "Received response message"
+                );
+            }
+            tracing::trace!("Received request message");
+        });
+        assert_eq!(COUNTER.count(), 3);
+    }
+}
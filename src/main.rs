@@ -2,12 +2,24 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 mod app;
+mod ban_list;
+mod bitfield;
 mod command;
 mod config;
+#[cfg(unix)]
+mod control_socket;
 mod errors;
+mod external_ip;
+mod geoip;
+mod hash_pool;
+mod ip_filter;
+mod logging;
 mod networking;
+mod port_check;
 mod resource_manager;
+mod scheduler;
 mod storage;
+mod streaming;
 mod theme;
 mod token_bucket;
 mod torrent_file;
@@ -30,17 +42,16 @@ use std::path::Path;
 use std::path::PathBuf;
 
 use crate::config::load_settings;
+use crate::config::AddOptions;
+use crate::config::LogLevel;
 use crate::config::Settings;
-
-use tracing_appender::rolling;
+use crate::errors::CreateTorrentError;
+use crate::torrent_file::create::{self, CreateOptions, TorrentVersion};
 
 use ratatui::{backend::CrosstermBackend, Terminal};
 use std::env;
 use std::io::stdout;
 
-use tracing_subscriber::filter::Targets;
-use tracing_subscriber::{filter::LevelFilter, fmt, prelude::*};
-
 use crossterm::{
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
@@ -55,24 +66,263 @@ use crossterm::event::{
 
 use clap::{Parser, Subcommand};
 
-const DEFAULT_LOG_FILTER: LevelFilter = LevelFilter::INFO;
-
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     input: Option<String>,
 
+    /// Run without the terminal UI, e.g. as a background service. Control it with
+    /// the other subcommands, the watch folder or the control socket.
+    #[arg(long)]
+    daemon: bool,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    Add { input: String },
+    #[command(flatten)]
+    Instance(InstanceCommand),
+    // Make a .torrent of a file or folder
+    Create(CreateArgs),
+}
+
+// Commands carried out by the running instance, or left in the watch folder for it
+#[derive(Subcommand, Debug)]
+enum InstanceCommand {
+    Add(AddArgs),
     StopClient,
+    // These need the control socket of a running instance (Unix only)
+    Pause { info_hash: String },
+    Resume { info_hash: String },
+    // Pause every torrent, e.g. before a backup, and resume them again
+    PauseAll,
+    ResumeAll,
+    Delete { info_hash: String },
+    // Download only the first SIZE (e.g. 500M, 2G) of a torrent, or "off"
+    Cap { info_hash: String, size: String },
+    // Stop downloading some of a torrent's files, by index (e.g. 0,2,4-6)
+    Skip { info_hash: String, files: String },
+    // Download skipped files again
+    Unskip { info_hash: String, files: String },
+    // Stop seeding a torrent at a share ratio or after a time
+    Goal(GoalArgs),
+    // File a torrent under a category, or "none" to take it out of one
+    Category { info_hash: String, category: String },
+    // Give a torrent a low, normal or high share of the global speed limits
+    Priority { info_hash: String, priority: String },
+    // Turn finding peers through the DHT on or off for a torrent
+    Dht { info_hash: String, state: String },
+    // Turn peer exchange on or off for a torrent
+    Pex { info_hash: String, state: String },
+}
+
+#[derive(clap::Args, Debug)]
+struct AddArgs {
+    /// Magnet link or path to a .torrent file
+    input: String,
+
+    /// Add the torrent paused instead of starting it
+    #[arg(long)]
+    paused: bool,
+
+    /// File the torrent under this category
+    #[arg(long)]
+    category: Option<String>,
+
+    /// Download into this folder [default: the category's or the default folder]
+    #[arg(long)]
+    download_dir: Option<PathBuf>,
+
+    /// Download pieces in order, e.g. to play a file while it downloads
+    #[arg(long)]
+    sequential: bool,
+
+    /// Trust the files already in the download folder and seed them without a hash
+    /// check, e.g. after moving data over from another client
+    #[arg(long)]
+    skip_check: bool,
+
+    /// If the torrent is already added, merge its new trackers and web seeds into it
+    #[arg(long)]
+    merge_sources: bool,
+}
+
+impl AddArgs {
+    fn options(&self) -> AddOptions {
+        AddOptions {
+            paused: self.paused,
+            category: self.category.clone(),
+            // Made absolute since the running instance has its own working directory
+            download_path: self
+                .download_dir
+                .as_ref()
+                .map(|path| fs::canonicalize(path).unwrap_or_else(|_| path.clone())),
+            sequential: self.sequential,
+            skip_check: self.skip_check,
+            merge_sources: self.merge_sources,
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct GoalArgs {
+    info_hash: String,
+
+    /// A ratio (2.0) and/or a seed time (12h, 3d), then pause, remove or remove-data
+    /// (also deleting the files) once either is reached; "never" seeds forever and
+    /// "default" follows the global goals
+    #[arg(required = true)]
+    goals: Vec<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct CreateArgs {
+    /// The file or folder to share
+    path: PathBuf,
+
+    /// Where to write the .torrent [default: NAME.torrent in the current folder]
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Tracker announce URL; repeat for more trackers, each in its own tier
+    #[arg(short, long = "tracker")]
+    trackers: Vec<String>,
+
+    /// URL the files can also be downloaded from (BEP 19); repeat for more
+    #[arg(short, long = "web-seed")]
+    web_seeds: Vec<String>,
+
+    /// Piece size such as 256K or 4M [default: picked from the total size]
+    #[arg(long)]
+    piece_size: Option<String>,
+
+    /// Mark the torrent private, for private trackers (no DHT or PEX)
+    #[arg(long)]
+    private: bool,
+
+    /// Which hashes to include: v1, v2 or hybrid
+    #[arg(long, default_value_t = TorrentVersion::Hybrid)]
+    version: TorrentVersion,
+
+    /// Free-form comment stored in the torrent
+    #[arg(long)]
+    comment: Option<String>,
+
+    /// Seed the new torrent in the running instance
+    #[arg(long)]
+    seed: bool,
+}
+
+/// Makes a torrent of `args.path` and writes it out. With `--seed`, the running
+/// instance is asked to seed it from where the files already are.
+fn create_command(args: CreateArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let piece_length = match &args.piece_size {
+        Some(size) => Some(
+            create::parse_piece_length(size)
+                .ok_or_else(|| format!("'{}' is not a piece size like 256K or 4M", size))?,
+        ),
+        None => None,
+    };
+    let options = CreateOptions {
+        path: args.path.clone(),
+        piece_length,
+        trackers: args.trackers,
+        web_seeds: args.web_seeds,
+        private: args.private,
+        version: args.version,
+        comment: args.comment,
+    };
+
+    let bytes = create::create_torrent(&options)?;
+    let torrent = torrent_file::parser::from_bytes(&bytes)?;
+    let output = args
+        .output
+        .unwrap_or_else(|| PathBuf::from(format!("{}.torrent", torrent.info.name)));
+    match create::write_torrent(&output, &bytes) {
+        Err(CreateTorrentError::AlreadyExists(path)) => {
+            return Err(
+                format!("{} already exists; choose another --output", path.display()).into(),
+            )
+        }
+        result => result?,
+    }
+    println!(
+        "Created {} ({} pieces, info hash {})",
+        output.display(),
+        torrent.piece_count(),
+        hex::encode(torrent.info_hash())
+    );
+
+    if args.seed {
+        let torrent_path = fs::canonicalize(&output)?;
+        let data_folder = fs::canonicalize(&args.path)?
+            .parent()
+            .map(Path::to_path_buf)
+            .ok_or("the shared path has no parent folder")?;
+        let line = format!("seed {}\t{}", torrent_path.display(), data_folder.display());
+        if !send_to_running_instance(&line) {
+            eprintln!("superseedr is not running, or its control socket is disabled.");
+        }
+    }
+    Ok(())
+}
+
+/// Builds the control socket line that adds `input` with `options`, each option after
+/// a tab. Paths are made absolute since the running instance has its own working
+/// directory.
+fn add_command_line(input_str: &str, options: &AddOptions) -> String {
+    let input = if input_str.starts_with("magnet:") {
+        input_str.to_string()
+    } else {
+        fs::canonicalize(input_str)
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_else(|_| input_str.to_string())
+    };
+    let mut line = format!("add {}", input);
+    for item in options.to_items() {
+        line.push('\t');
+        line.push_str(&item);
+    }
+    line
 }
 
-fn process_input(input_str: &str, watch_path: &Path) {
+/// The contents of a `.magnet` or `.path` watch file: the magnet link or torrent
+/// path, then one add option per line.
+fn watch_file_content(input: &str, options: &AddOptions) -> String {
+    let mut lines = vec![input.to_string()];
+    lines.extend(options.to_items());
+    lines.join("\n")
+}
+
+/// Sends `line` to a running instance over its control socket. Returns false when
+/// nothing is listening, so the caller can fall back to the watch folder.
+fn send_to_running_instance(line: &str) -> bool {
+    #[cfg(unix)]
+    {
+        match control_socket::send_command(line) {
+            Ok(reply) => {
+                tracing::info!("Control socket replied: {}", reply);
+                if reply != "queued" {
+                    eprintln!("{}", reply);
+                }
+                true
+            }
+            Err(e) => {
+                tracing::info!("Control socket unavailable ({}). Using watch folder.", e);
+                false
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = line;
+        false
+    }
+}
+
+fn process_input(input_str: &str, options: &AddOptions, watch_path: &Path) {
     if input_str.starts_with("magnet:") {
         let hash_bytes = Sha1::digest(input_str.as_bytes());
         let file_hash_hex = hex::encode(hash_bytes);
@@ -89,7 +339,7 @@ fn process_input(input_str: &str, watch_path: &Path) {
         );
 
         // 1. Write the content to the temporary file
-        match fs::write(&temp_path, input_str.as_bytes()) {
+        match fs::write(&temp_path, watch_file_content(input_str, options)) {
             Ok(_) => {
                 tracing::info!(
                     "Atomically renaming magnet file to final path: {:?}",
@@ -119,8 +369,7 @@ fn process_input(input_str: &str, watch_path: &Path) {
                 let temp_filename = format!("{}.path.tmp", file_hash_hex);
                 let temp_dest_path = watch_path.join(temp_filename);
 
-                let absolute_path_cow = absolute_path.to_string_lossy();
-                let content = absolute_path_cow.as_bytes(); // The content reference is now valid!
+                let content = watch_file_content(&absolute_path.to_string_lossy(), options);
 
                 tracing::info!(
                     "Attempting to write torrent path to temporary path: {:?}",
@@ -168,25 +417,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .map(|(_, data_dir)| data_dir)
         .unwrap_or_else(|| env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
     let log_dir = base_data_dir.join("logs");
-    let general_log = rolling::never(&log_dir, "app.log");
-    let (non_blocking_general, _guard_general) = tracing_appender::non_blocking(general_log);
-    let _subscriber_result = {
-        if fs::create_dir_all(&log_dir).is_ok() {
-            let quiet_filter = Targets::new()
-                .with_default(DEFAULT_LOG_FILTER)
-                .with_target("mainline::rpc::socket", LevelFilter::ERROR);
-
-            let general_layer = fmt::layer()
-                .with_writer(non_blocking_general)
-                .with_filter(quiet_filter);
-
-            tracing_subscriber::registry()
-                .with(general_layer)
-                .try_init()
-        } else {
-            tracing_subscriber::registry().try_init()
-        }
-    };
+    let _guard_general = logging::init(&log_dir, LogLevel::default());
 
     tracing::info!("STARTING SUPERSEEDR");
 
@@ -195,30 +426,83 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     let cli = Cli::parse();
+    let command = match cli.command {
+        Some(Commands::Create(args)) => return create_command(args),
+        Some(Commands::Instance(command)) => Some(command),
+        None => None,
+    };
     let mut command_processed = false;
 
     if let Some(direct_input) = cli.input {
-        if let Some((watch_path, _)) = config::get_watch_path() {
+        let options = AddOptions::default();
+        if send_to_running_instance(&add_command_line(&direct_input, &options)) {
+            command_processed = true;
+        } else if let Some((watch_path, _)) = config::get_watch_path() {
             tracing::info!("Processing direct input: {}", direct_input);
-            process_input(&direct_input, &watch_path);
+            process_input(&direct_input, &options, &watch_path);
             command_processed = true;
         } else {
             tracing::error!("Could not get watch path to process direct input.");
         }
-    } else if let Some(command) = cli.command {
-        if let Some((watch_path, _)) = config::get_watch_path() {
+    } else if let Some(command) = command {
+        let control_line = match &command {
+            InstanceCommand::Add(args) => add_command_line(&args.input, &args.options()),
+            InstanceCommand::StopClient => "shutdown".to_string(),
+            InstanceCommand::Pause { info_hash } => format!("pause {}", info_hash),
+            InstanceCommand::Resume { info_hash } => format!("resume {}", info_hash),
+            InstanceCommand::PauseAll => "pause-all".to_string(),
+            InstanceCommand::ResumeAll => "resume-all".to_string(),
+            InstanceCommand::Delete { info_hash } => format!("delete {}", info_hash),
+            InstanceCommand::Cap { info_hash, size } => format!("cap {} {}", info_hash, size),
+            InstanceCommand::Skip { info_hash, files } => format!("skip {} {}", info_hash, files),
+            InstanceCommand::Unskip { info_hash, files } => {
+                format!("unskip {} {}", info_hash, files)
+            }
+            InstanceCommand::Goal(args) => {
+                format!("goal {} {}", args.info_hash, args.goals.join(" "))
+            }
+            InstanceCommand::Category {
+                info_hash,
+                category,
+            } => format!("category {} {}", info_hash, category),
+            InstanceCommand::Priority {
+                info_hash,
+                priority,
+            } => format!("priority {} {}", info_hash, priority),
+            InstanceCommand::Dht { info_hash, state } => format!("dht {} {}", info_hash, state),
+            InstanceCommand::Pex { info_hash, state } => format!("pex {} {}", info_hash, state),
+        };
+
+        if send_to_running_instance(&control_line) {
+            command_processed = true;
+        } else if let Some((watch_path, _)) = config::get_watch_path() {
             command_processed = true;
             match command {
-                Commands::StopClient => {
+                InstanceCommand::Pause { .. }
+                | InstanceCommand::Resume { .. }
+                | InstanceCommand::PauseAll
+                | InstanceCommand::ResumeAll
+                | InstanceCommand::Delete { .. }
+                | InstanceCommand::Cap { .. }
+                | InstanceCommand::Skip { .. }
+                | InstanceCommand::Unskip { .. }
+                | InstanceCommand::Goal(_)
+                | InstanceCommand::Category { .. }
+                | InstanceCommand::Priority { .. }
+                | InstanceCommand::Dht { .. }
+                | InstanceCommand::Pex { .. } => {
+                    eprintln!("superseedr is not running, or its control socket is disabled.");
+                }
+                InstanceCommand::StopClient => {
                     tracing::info!("Processing StopClient command.");
                     let file_path = watch_path.join("shutdown.cmd");
                     if let Err(e) = fs::write(&file_path, "STOP") {
                         tracing::error!("Failed to write stop command file: {}", e);
                     }
                 }
-                Commands::Add { input } => {
-                    tracing::info!("Processing Add subcommand input: {}", input);
-                    process_input(&input, &watch_path);
+                InstanceCommand::Add(args) => {
+                    tracing::info!("Processing Add subcommand input: {}", args.input);
+                    process_input(&args.input, &args.options(), &watch_path);
                 }
             }
         } else {
@@ -245,6 +529,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
     if proceed_to_app {
         let mut client_configs = load_settings();
+        logging::set_level(client_configs.log_level);
 
         #[cfg(all(feature = "dht", feature = "pex"))]
         {
@@ -318,6 +603,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     );
                 }
             }
+        } else if let Some(port_range) = client_configs.listen_port_range {
+            client_configs.client_port = port_range.random_port();
+            tracing::info!(
+                "Dynamic file not found. Using random port {} from {}-{}.",
+                client_configs.client_port,
+                port_range.start,
+                port_range.end
+            );
         } else {
             tracing::info!(
                 "Dynamic file not found. Using port {} from settings.",
@@ -332,6 +625,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
 
+        if cli.daemon {
+            tracing::info!("Running headless (--daemon).");
+            let mut app = App::new(client_configs).await?;
+            if let Err(e) = app.run(None).await {
+                tracing::error!("Application failed: {}", e);
+                eprintln!("[Error] Application failed: {}", e);
+            }
+            return Ok(());
+        }
+
         let original_hook = std::panic::take_hook();
         std::panic::set_hook(Box::new(move |panic_info| {
             let _ = cleanup_terminal();
@@ -355,7 +658,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let mut terminal = Terminal::new(backend)?;
 
         let mut app = App::new(client_configs).await?;
-        if let Err(e) = app.run(&mut terminal).await {
+        if let Err(e) = app.run(Some(&mut terminal)).await {
             eprintln!("[Error] Application failed: {}", e);
         }
 
@@ -0,0 +1,690 @@
+// SPDX-FileCopyrightText: 2025 The superseedr Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! A lookup-only DHT client for either address family. mainline only runs BEP 5
+//! over IPv4 and can't scrape, so one of these finds and announces to IPv6 peers
+//! (BEP 32), and both ask the nodes closest to an info hash for their swarm bloom
+//! filters (BEP 33). It never answers queries and says so in every message it
+//! sends (BEP 43), so it keeps no routing table beyond the nodes it has heard from.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::net::{IpAddr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rand::Rng;
+use serde_bencode::value::Value;
+use tokio::net::UdpSocket;
+use tokio::sync::oneshot;
+use tokio::task::{JoinHandle, JoinSet};
+
+use crate::tracker::SwarmCounts;
+
+// A lookup keeps going until this many of the closest nodes have answered
+const K: usize = 8;
+// Queries a lookup has in flight at once
+const ALPHA: usize = 4;
+const MAX_QUERIES_PER_LOOKUP: usize = 128;
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+// Nodes remembered to start later lookups from
+const MAX_KNOWN_NODES: usize = 128;
+const COMPACT_NODE_LEN: usize = 26;
+const COMPACT_NODE6_LEN: usize = 38;
+// BEP 33 filters are 2048 bits with two of them set for each peer's IP
+const BLOOM_FILTER_LEN: usize = 256;
+const BLOOM_FILTER_BITS: usize = BLOOM_FILTER_LEN * 8;
+
+type NodeId = [u8; 20];
+type BloomFilter = [u8; BLOOM_FILTER_LEN];
+type PendingQueries = Mutex<HashMap<u16, (SocketAddr, oneshot::Sender<Response>)>>;
+
+/// What a node answered to a query. Error replies come back empty.
+#[derive(Debug, Default, PartialEq)]
+struct Response {
+    id: Option<NodeId>,
+    token: Option<Vec<u8>>,
+    nodes: Vec<(NodeId, SocketAddr)>,
+    peers: Vec<SocketAddr>,
+    // BEP 33 scrape: the seeds, and the peers still downloading, announced to it
+    seeds: Option<BloomFilter>,
+    leechers: Option<BloomFilter>,
+}
+
+/// The result of looking up an info hash: the peers found, the closest nodes that
+/// answered along with the token they gave for announcing to them, and the union
+/// of those nodes' scrape filters.
+#[derive(Debug, Default)]
+pub struct Lookup {
+    pub peers: Vec<SocketAddr>,
+    closest: Vec<(SocketAddr, Vec<u8>)>,
+    seeds: Option<BloomFilter>,
+    leechers: Option<BloomFilter>,
+}
+
+#[derive(Clone)]
+pub struct DhtClient {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    socket: Arc<UdpSocket>,
+    id: NodeId,
+    ipv6: bool,
+    bootstrap_nodes: Vec<SocketAddr>,
+    next_transaction: AtomicU16,
+    pending: Arc<PendingQueries>,
+    known_nodes: Mutex<Vec<(NodeId, SocketAddr)>>,
+    receiver: JoinHandle<()>,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        self.receiver.abort();
+    }
+}
+
+impl DhtClient {
+    /// Binds a UDP socket on `ip`. Lookups start from the `bootstrap_nodes` of the
+    /// same address family until nodes closer to their targets are known.
+    pub async fn bind(ip: IpAddr, bootstrap_nodes: &[SocketAddr]) -> io::Result<Self> {
+        let socket = Arc::new(UdpSocket::bind((ip, 0)).await?);
+        let pending = Arc::new(PendingQueries::default());
+        let receiver = tokio::spawn(receive_responses(socket.clone(), pending.clone()));
+        Ok(Self {
+            inner: Arc::new(Inner {
+                socket,
+                id: rand::rng().random(),
+                ipv6: ip.is_ipv6(),
+                bootstrap_nodes: bootstrap_nodes
+                    .iter()
+                    .filter(|address| address.is_ipv6() == ip.is_ipv6())
+                    .copied()
+                    .collect(),
+                next_transaction: AtomicU16::new(0),
+                pending,
+                known_nodes: Mutex::new(Vec::new()),
+                receiver,
+            }),
+        })
+    }
+
+    /// Asks the nodes closest to `info_hash` for its peers and swarm size.
+    pub async fn lookup(&self, info_hash: NodeId) -> Lookup {
+        let mut candidates: BTreeMap<NodeId, SocketAddr> = self
+            .inner
+            .known_nodes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, address)| (distance(id, &info_hash), *address))
+            .collect();
+        let mut bootstrap_nodes = if candidates.len() < K {
+            self.inner.bootstrap_nodes.clone()
+        } else {
+            Vec::new()
+        };
+        let mut queried = HashSet::new();
+        let mut answered: BTreeMap<NodeId, (SocketAddr, Response)> = BTreeMap::new();
+        let mut peers = HashSet::new();
+        let mut in_flight = JoinSet::new();
+
+        loop {
+            while in_flight.len() < ALPHA && queried.len() < MAX_QUERIES_PER_LOOKUP {
+                // Only nodes closer than the K closest that answered can still help
+                let bound = answered.keys().nth(K - 1).copied();
+                let next = bootstrap_nodes.pop().or_else(|| {
+                    let (key, address) = candidates
+                        .iter()
+                        .find(|(key, address)| {
+                            !queried.contains(*address) && bound.is_none_or(|bound| **key < bound)
+                        })
+                        .map(|(key, address)| (*key, *address))?;
+                    candidates.remove(&key);
+                    Some(address)
+                });
+                let Some(address) = next else {
+                    break;
+                };
+                if !queried.insert(address) {
+                    continue;
+                }
+                let dht = self.clone();
+                let args = get_peers_args(info_hash, self.inner.ipv6);
+                in_flight.spawn(async move {
+                    let response = dht.query(address, "get_peers", args).await;
+                    (address, response)
+                });
+            }
+
+            let Some(result) = in_flight.join_next().await else {
+                break;
+            };
+            let Ok((address, Some(mut response))) = result else {
+                continue;
+            };
+            peers.extend(response.peers.drain(..));
+            for (id, node) in response.nodes.drain(..) {
+                if node.is_ipv6() == self.inner.ipv6 && !queried.contains(&node) {
+                    candidates.insert(distance(&id, &info_hash), node);
+                }
+            }
+            if let Some(id) = response.id {
+                answered.insert(distance(&id, &info_hash), (address, response));
+                self.remember(id, address);
+            }
+        }
+
+        // Only the closest nodes are responsible for the info hash, so only their
+        // filters cover the swarm.
+        let closest: Vec<_> = answered.into_values().take(K).collect();
+        Lookup {
+            peers: peers.into_iter().collect(),
+            seeds: union_filters(closest.iter().filter_map(|(_, r)| r.seeds.as_ref())),
+            leechers: union_filters(closest.iter().filter_map(|(_, r)| r.leechers.as_ref())),
+            closest: closest
+                .into_iter()
+                .filter_map(|(address, response)| Some((address, response.token?)))
+                .collect(),
+        }
+    }
+
+    /// Announces that we have `info_hash` on `port` to the closest nodes `lookup`
+    /// found.
+    pub async fn announce(&self, info_hash: NodeId, lookup: &Lookup, port: u16) {
+        let mut announces = JoinSet::new();
+        for (address, token) in lookup.closest.iter().cloned() {
+            let dht = self.clone();
+            announces.spawn(async move {
+                dht.query(
+                    address,
+                    "announce_peer",
+                    announce_peer_args(info_hash, port, token),
+                )
+                .await
+            });
+        }
+        announces.join_all().await;
+    }
+
+    async fn query(
+        &self,
+        address: SocketAddr,
+        method: &str,
+        mut args: Vec<(&str, Value)>,
+    ) -> Option<Response> {
+        let transaction = self.inner.next_transaction.fetch_add(1, Ordering::Relaxed);
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.inner
+            .pending
+            .lock()
+            .unwrap()
+            .insert(transaction, (address, reply_tx));
+
+        args.push(("id", Value::Bytes(self.inner.id.to_vec())));
+        let message = encode_query(transaction, method, args);
+        let response = match self.inner.socket.send_to(&message, address).await {
+            Ok(_) => tokio::time::timeout(QUERY_TIMEOUT, reply_rx)
+                .await
+                .ok()
+                .and_then(Result::ok),
+            Err(_) => None,
+        };
+        self.inner.pending.lock().unwrap().remove(&transaction);
+        response
+    }
+
+    fn remember(&self, id: NodeId, address: SocketAddr) {
+        let mut known_nodes = self.inner.known_nodes.lock().unwrap();
+        known_nodes.retain(|(_, known)| *known != address);
+        known_nodes.push((id, address));
+        if known_nodes.len() > MAX_KNOWN_NODES {
+            known_nodes.remove(0);
+        }
+    }
+}
+
+/// Seeds and leechers counted by the scrape filters of `lookups`, or None if no
+/// node they reached supports BEP 33.
+pub fn estimate_swarm<'a>(lookups: impl IntoIterator<Item = &'a Lookup>) -> Option<SwarmCounts> {
+    let lookups: Vec<&Lookup> = lookups.into_iter().collect();
+    let seeds = union_filters(lookups.iter().filter_map(|lookup| lookup.seeds.as_ref()));
+    let leechers = union_filters(lookups.iter().filter_map(|lookup| lookup.leechers.as_ref()));
+    if seeds.is_none() && leechers.is_none() {
+        return None;
+    }
+    Some(SwarmCounts {
+        seeders: seeds.as_ref().map_or(0, estimate_filter_size),
+        leechers: leechers.as_ref().map_or(0, estimate_filter_size),
+        completed: 0,
+    })
+}
+
+/// The addresses of `nodes`, given as `host:port`.
+#[cfg(feature = "dht")]
+pub async fn resolve_bootstrap_nodes(nodes: &[String]) -> Vec<SocketAddr> {
+    let mut addresses = Vec::new();
+    for node in nodes {
+        if let Ok(resolved) = tokio::net::lookup_host(node.as_str()).await {
+            addresses.extend(resolved);
+        }
+    }
+    addresses
+}
+
+/// Hands each response to the query waiting for it, if it came from the node
+/// that query went to.
+async fn receive_responses(socket: Arc<UdpSocket>, pending: Arc<PendingQueries>) {
+    let mut buffer = vec![0; 65536];
+    loop {
+        // Errors here are ICMP replies to earlier queries; those time out anyway.
+        let Ok((len, from)) = socket.recv_from(&mut buffer).await else {
+            continue;
+        };
+        let Some((transaction, response)) = parse_response(&buffer[..len]) else {
+            continue;
+        };
+        let mut pending = pending.lock().unwrap();
+        if pending
+            .get(&transaction)
+            .is_some_and(|(address, _)| *address == from)
+        {
+            if let Some((_, reply_tx)) = pending.remove(&transaction) {
+                let _ = reply_tx.send(response);
+            }
+        }
+    }
+}
+
+fn distance(a: &NodeId, b: &NodeId) -> NodeId {
+    std::array::from_fn(|i| a[i] ^ b[i])
+}
+
+fn union_filters<'a>(filters: impl Iterator<Item = &'a BloomFilter>) -> Option<BloomFilter> {
+    filters.fold(None, |union, filter| {
+        let mut union = union.unwrap_or([0; BLOOM_FILTER_LEN]);
+        for (bits, filter_bits) in union.iter_mut().zip(filter) {
+            *bits |= filter_bits;
+        }
+        Some(union)
+    })
+}
+
+/// How many distinct IPs went into `filter`, by BEP 33's estimate.
+fn estimate_filter_size(filter: &BloomFilter) -> u32 {
+    let zeros: u32 = filter.iter().map(|bits| bits.count_zeros()).sum();
+    let m = BLOOM_FILTER_BITS as f64;
+    let c = f64::from(zeros.max(1));
+    ((c / m).ln() / (2.0 * (1.0 - 1.0 / m).ln())).round() as u32
+}
+
+fn get_peers_args(info_hash: NodeId, ipv6: bool) -> Vec<(&'static str, Value)> {
+    let want: &[u8] = if ipv6 { b"n6" } else { b"n4" };
+    vec![
+        ("info_hash", Value::Bytes(info_hash.to_vec())),
+        ("want", Value::List(vec![Value::Bytes(want.to_vec())])),
+        ("scrape", Value::Int(1)),
+    ]
+}
+
+fn announce_peer_args(info_hash: NodeId, port: u16, token: Vec<u8>) -> Vec<(&'static str, Value)> {
+    vec![
+        ("info_hash", Value::Bytes(info_hash.to_vec())),
+        ("port", Value::Int(port.into())),
+        ("implied_port", Value::Int(0)),
+        ("token", Value::Bytes(token)),
+    ]
+}
+
+fn encode_query(transaction: u16, method: &str, args: Vec<(&str, Value)>) -> Vec<u8> {
+    let args = args
+        .into_iter()
+        .map(|(key, value)| (key.as_bytes().to_vec(), value))
+        .collect();
+    let message = Value::Dict(HashMap::from([
+        (
+            b"t".to_vec(),
+            Value::Bytes(transaction.to_be_bytes().to_vec()),
+        ),
+        (b"y".to_vec(), Value::Bytes(b"q".to_vec())),
+        (b"q".to_vec(), Value::Bytes(method.as_bytes().to_vec())),
+        (b"a".to_vec(), Value::Dict(args)),
+        (b"ro".to_vec(), Value::Int(1)),
+    ]));
+    serde_bencode::to_bytes(&message).unwrap_or_default()
+}
+
+fn parse_response(bytes: &[u8]) -> Option<(u16, Response)> {
+    let Ok(Value::Dict(message)) = serde_bencode::from_bytes::<Value>(bytes) else {
+        return None;
+    };
+    let Some(Value::Bytes(transaction)) = message.get(b"t".as_slice()) else {
+        return None;
+    };
+    let transaction = u16::from_be_bytes(transaction.as_slice().try_into().ok()?);
+    match message.get(b"y".as_slice()) {
+        Some(Value::Bytes(kind)) if kind == b"e" => {
+            return Some((transaction, Response::default()))
+        }
+        Some(Value::Bytes(kind)) if kind == b"r" => {}
+        _ => return None,
+    }
+    let Some(Value::Dict(reply)) = message.get(b"r".as_slice()) else {
+        return None;
+    };
+
+    let bytes_of = |key: &[u8]| match reply.get(key) {
+        Some(Value::Bytes(bytes)) => Some(bytes.clone()),
+        _ => None,
+    };
+    let mut nodes = bytes_of(b"nodes")
+        .map(|nodes| parse_compact_nodes(&nodes))
+        .unwrap_or_default();
+    nodes.extend(
+        bytes_of(b"nodes6")
+            .map(|nodes| parse_compact_nodes6(&nodes))
+            .unwrap_or_default(),
+    );
+    let filter_of = |key: &[u8]| bytes_of(key).and_then(|bits| bits.try_into().ok());
+    let peers = match reply.get(b"values".as_slice()) {
+        Some(Value::List(values)) => values
+            .iter()
+            .filter_map(|value| match value {
+                Value::Bytes(peer) => parse_compact_peer(peer),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    Some((
+        transaction,
+        Response {
+            id: bytes_of(b"id").and_then(|id| id.try_into().ok()),
+            token: bytes_of(b"token"),
+            nodes,
+            peers,
+            seeds: filter_of(b"BFsd"),
+            leechers: filter_of(b"BFpe"),
+        },
+    ))
+}
+
+/// Nodes in BEP 5's compact form: a 20 byte ID, a 4 byte address and a port.
+fn parse_compact_nodes(bytes: &[u8]) -> Vec<(NodeId, SocketAddr)> {
+    bytes
+        .chunks_exact(COMPACT_NODE_LEN)
+        .filter_map(|node| {
+            let id: NodeId = node[..20].try_into().ok()?;
+            let ip: [u8; 4] = node[20..24].try_into().ok()?;
+            let port = u16::from_be_bytes([node[24], node[25]]);
+            (port != 0).then(|| (id, SocketAddr::V4(SocketAddrV4::new(ip.into(), port))))
+        })
+        .collect()
+}
+
+/// Nodes in BEP 32's compact form: a 20 byte ID, a 16 byte address and a port.
+fn parse_compact_nodes6(bytes: &[u8]) -> Vec<(NodeId, SocketAddr)> {
+    bytes
+        .chunks_exact(COMPACT_NODE6_LEN)
+        .filter_map(|node| {
+            let id: NodeId = node[..20].try_into().ok()?;
+            let ip: [u8; 16] = node[20..36].try_into().ok()?;
+            let port = u16::from_be_bytes([node[36], node[37]]);
+            (port != 0).then(|| (id, SocketAddr::V6(SocketAddrV6::new(ip.into(), port, 0, 0))))
+        })
+        .collect()
+}
+
+/// A peer in compact form: an IPv4 or IPv6 address and a port.
+fn parse_compact_peer(bytes: &[u8]) -> Option<SocketAddr> {
+    let (ip, port) = bytes.split_at_checked(bytes.len().checked_sub(2)?)?;
+    let port = u16::from_be_bytes([port[0], port[1]]);
+    let address = match ip.len() {
+        4 => SocketAddr::V4(SocketAddrV4::new(
+            <[u8; 4]>::try_from(ip).ok()?.into(),
+            port,
+        )),
+        16 => SocketAddr::V6(SocketAddrV6::new(
+            <[u8; 16]>::try_from(ip).ok()?.into(),
+            port,
+            0,
+            0,
+        )),
+        _ => return None,
+    };
+    (port != 0).then_some(address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha1::{Digest, Sha1};
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    /// Adds `ip` to `filter` the way BEP 33 nodes do.
+    fn insert_into_filter(filter: &mut BloomFilter, ip: IpAddr) {
+        let hash = match ip {
+            IpAddr::V4(ip) => Sha1::digest(ip.octets()),
+            IpAddr::V6(ip) => Sha1::digest(ip.octets()),
+        };
+        for index in [
+            u16::from_le_bytes([hash[0], hash[1]]),
+            u16::from_le_bytes([hash[2], hash[3]]),
+        ] {
+            let index = usize::from(index) % BLOOM_FILTER_BITS;
+            filter[index / 8] |= 1 << (index % 8);
+        }
+    }
+
+    #[test]
+    fn test_get_peers_query_asks_for_nodes_of_its_family_and_a_scrape() {
+        let message = encode_query(0x0102, "get_peers", get_peers_args([7; 20], true));
+        let expected = [
+            b"d1:ad9:info_hash20:".as_slice(),
+            &[7; 20],
+            b"6:scrapei1e4:wantl2:n6ee1:q9:get_peers2:roi1e1:t2:\x01\x021:y1:qe",
+        ]
+        .concat();
+        assert_eq!(message, expected);
+
+        let message = encode_query(0x0102, "get_peers", get_peers_args([7; 20], false));
+        assert!(message
+            .windows(b"4:wantl2:n4e".len())
+            .any(|window| window == b"4:wantl2:n4e"));
+    }
+
+    #[test]
+    fn test_estimate_filter_size_matches_bep_33() {
+        // BEP 33's example: 192.0.2.0 to 192.0.2.255 and 2001:db8:: to 2001:db8::3e7
+        let mut filter = [0; BLOOM_FILTER_LEN];
+        for last in 0..=255 {
+            insert_into_filter(&mut filter, Ipv4Addr::new(192, 0, 2, last).into());
+        }
+        for last in 0..=0x3e7 {
+            insert_into_filter(
+                &mut filter,
+                Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, last).into(),
+            );
+        }
+        assert_eq!(estimate_filter_size(&filter), 1225);
+
+        assert_eq!(estimate_filter_size(&[0; BLOOM_FILTER_LEN]), 0);
+        // A full filter can't say how full; it saturates instead of overflowing.
+        assert!(estimate_filter_size(&[0xff; BLOOM_FILTER_LEN]) > 7000);
+    }
+
+    #[test]
+    fn test_estimate_swarm_unions_filters_across_lookups() {
+        let mut seeds_a = [0; BLOOM_FILTER_LEN];
+        let mut seeds_b = [0; BLOOM_FILTER_LEN];
+        for last in 0..20 {
+            insert_into_filter(&mut seeds_a, Ipv4Addr::new(10, 0, 0, last).into());
+            // Half of these are the same seeds again
+            insert_into_filter(&mut seeds_b, Ipv4Addr::new(10, 0, 0, last + 10).into());
+        }
+        let mut leechers = [0; BLOOM_FILTER_LEN];
+        insert_into_filter(&mut leechers, Ipv4Addr::new(10, 0, 1, 1).into());
+
+        let a = Lookup {
+            seeds: Some(seeds_a),
+            ..Lookup::default()
+        };
+        let b = Lookup {
+            seeds: Some(seeds_b),
+            leechers: Some(leechers),
+            ..Lookup::default()
+        };
+        let swarm = estimate_swarm([&a, &b]).unwrap();
+        assert!((28..=32).contains(&swarm.seeders), "{:?}", swarm);
+        assert_eq!(swarm.leechers, 1);
+
+        assert_eq!(estimate_swarm([&Lookup::default()]), None);
+    }
+
+    #[test]
+    fn test_parse_response_with_nodes6_and_values() {
+        let node_ip = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+        let peer_ip = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2);
+        let nodes6 = [
+            [1; 20].as_slice(),
+            &node_ip.octets(),
+            &6881u16.to_be_bytes(),
+        ]
+        .concat();
+        let nodes = [[4; 20].as_slice(), &[10, 0, 0, 2], &6882u16.to_be_bytes()].concat();
+        let peer6 = [peer_ip.octets().as_slice(), &51413u16.to_be_bytes()].concat();
+        let message = Value::Dict(HashMap::from([
+            (b"t".to_vec(), Value::Bytes(vec![0, 9])),
+            (b"y".to_vec(), Value::Bytes(b"r".to_vec())),
+            (
+                b"r".to_vec(),
+                Value::Dict(HashMap::from([
+                    (b"id".to_vec(), Value::Bytes(vec![2; 20])),
+                    (b"token".to_vec(), Value::Bytes(b"tk".to_vec())),
+                    (b"nodes".to_vec(), Value::Bytes(nodes)),
+                    (b"nodes6".to_vec(), Value::Bytes(nodes6)),
+                    (b"BFsd".to_vec(), Value::Bytes(vec![1; BLOOM_FILTER_LEN])),
+                    // Not a filter BEP 33 nodes send, so it's ignored
+                    (b"BFpe".to_vec(), Value::Bytes(vec![1; 3])),
+                    (
+                        b"values".to_vec(),
+                        Value::List(vec![
+                            Value::Bytes(peer6),
+                            Value::Bytes(vec![10, 0, 0, 1, 0x1a, 0xe1]),
+                        ]),
+                    ),
+                ])),
+            ),
+        ]));
+
+        let (transaction, response) =
+            parse_response(&serde_bencode::to_bytes(&message).unwrap()).unwrap();
+        assert_eq!(transaction, 9);
+        assert_eq!(
+            response,
+            Response {
+                id: Some([2; 20]),
+                token: Some(b"tk".to_vec()),
+                nodes: vec![
+                    ([4; 20], "10.0.0.2:6882".parse().unwrap()),
+                    ([1; 20], SocketAddrV6::new(node_ip, 6881, 0, 0).into()),
+                ],
+                peers: vec![
+                    SocketAddr::V6(SocketAddrV6::new(peer_ip, 51413, 0, 0)),
+                    "10.0.0.1:6881".parse().unwrap(),
+                ],
+                seeds: Some([1; BLOOM_FILTER_LEN]),
+                leechers: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_response_ignores_queries_and_bad_transactions() {
+        assert!(parse_response(b"d1:t2:aa1:y1:qe").is_none());
+        assert!(parse_response(b"d1:t1:a1:y1:re").is_none());
+        assert!(parse_response(b"not bencode").is_none());
+        assert_eq!(
+            parse_response(b"d1:eli201e5:Errore1:t2:\x00\x051:y1:ee"),
+            Some((5, Response::default()))
+        );
+    }
+
+    /// Answers get_peers with `peer`, a token and a scrape that counts `peer` as a
+    /// seed, and reports the port and token of announces.
+    async fn fake_node(
+        peer: SocketAddr,
+    ) -> (SocketAddr, tokio::sync::mpsc::Receiver<(i64, Vec<u8>)>) {
+        let socket = UdpSocket::bind((Ipv6Addr::LOCALHOST, 0)).await.unwrap();
+        let address = socket.local_addr().unwrap();
+        let (announce_tx, announce_rx) = tokio::sync::mpsc::channel(1);
+        tokio::spawn(async move {
+            let mut buffer = vec![0; 2048];
+            loop {
+                let (len, from) = socket.recv_from(&mut buffer).await.unwrap();
+                let Ok(Value::Dict(query)) = serde_bencode::from_bytes::<Value>(&buffer[..len])
+                else {
+                    continue;
+                };
+                let (Some(Value::Bytes(method)), Some(Value::Dict(args))) =
+                    (query.get(b"q".as_slice()), query.get(b"a".as_slice()))
+                else {
+                    continue;
+                };
+                let mut reply = HashMap::from([(b"id".to_vec(), Value::Bytes(vec![3; 20]))]);
+                if method == b"get_peers" {
+                    let SocketAddr::V6(peer) = peer else {
+                        unreachable!()
+                    };
+                    let value =
+                        [peer.ip().octets().as_slice(), &peer.port().to_be_bytes()].concat();
+                    reply.insert(b"token".to_vec(), Value::Bytes(b"tk".to_vec()));
+                    reply.insert(b"values".to_vec(), Value::List(vec![Value::Bytes(value)]));
+                    let mut seeds = [0; BLOOM_FILTER_LEN];
+                    insert_into_filter(&mut seeds, (*peer.ip()).into());
+                    reply.insert(b"BFsd".to_vec(), Value::Bytes(seeds.to_vec()));
+                } else if let (Some(Value::Int(port)), Some(Value::Bytes(token))) =
+                    (args.get(b"port".as_slice()), args.get(b"token".as_slice()))
+                {
+                    let _ = announce_tx.send((*port, token.clone())).await;
+                }
+                let response = Value::Dict(HashMap::from([
+                    (b"t".to_vec(), query[b"t".as_slice()].clone()),
+                    (b"y".to_vec(), Value::Bytes(b"r".to_vec())),
+                    (b"r".to_vec(), Value::Dict(reply)),
+                ]));
+                let bytes = serde_bencode::to_bytes(&response).unwrap();
+                socket.send_to(&bytes, from).await.unwrap();
+            }
+        });
+        (address, announce_rx)
+    }
+
+    #[tokio::test]
+    async fn test_lookup_and_announce_over_ipv6() {
+        let peer: SocketAddr = "[2001:db8::7]:51413".parse().unwrap();
+        let (node, mut announce_rx) = fake_node(peer).await;
+        let dht = DhtClient::bind(Ipv6Addr::LOCALHOST.into(), &[node])
+            .await
+            .unwrap();
+
+        let lookup = dht.lookup([5; 20]).await;
+        assert_eq!(lookup.peers, vec![peer]);
+        assert_eq!(
+            estimate_swarm([&lookup]),
+            Some(SwarmCounts {
+                seeders: 1,
+                leechers: 0,
+                completed: 0,
+            })
+        );
+
+        dht.announce([5; 20], &lookup, 6881).await;
+        assert_eq!(announce_rx.recv().await, Some((6881, b"tk".to_vec())));
+
+        // The node that answered is where the next lookup starts
+        let known = dht.inner.known_nodes.lock().unwrap().clone();
+        assert_eq!(known, vec![([3; 20], node)]);
+    }
+}
@@ -1,9 +1,93 @@
 // SPDX-FileCopyrightText: 2025 The superseedr Contributors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+#[cfg(any(feature = "dht", test))]
+pub mod dht_client;
 pub mod protocol;
 pub mod session;
+pub mod utp;
+pub mod web_seed;
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+use sysinfo::Networks;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpSocket, TcpStream};
 
 // Re-export key types for easier access.
 pub use protocol::BlockInfo;
 pub use session::{ConnectionType, PeerSession};
+
+/// A connection to a peer, over TCP or uTP.
+pub trait PeerStream: AsyncRead + AsyncWrite + Send + Unpin {}
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> PeerStream for T {}
+
+pub type BoxedPeerStream = Box<dyn PeerStream>;
+
+/// An incoming peer routed to its torrent: the stream, the peer's address and the
+/// handshake already read from the stream.
+pub type IncomingPeer = (BoxedPeerStream, SocketAddr, Vec<u8>);
+
+/// The local addresses `listen_interface` stands for: the address itself when it
+/// is an IP, otherwise those of the network interface with that name. A missing
+/// interface is an error rather than an empty list, so callers never fall back to
+/// binding every interface.
+pub fn interface_addresses(listen_interface: &str) -> io::Result<Vec<IpAddr>> {
+    if let Ok(ip) = listen_interface.parse::<IpAddr>() {
+        return Ok(vec![ip]);
+    }
+
+    let networks = Networks::new_with_refreshed_list();
+    let addresses: Vec<IpAddr> = networks
+        .get(listen_interface)
+        .into_iter()
+        .flat_map(|network| network.ip_networks())
+        .map(|ip_network| ip_network.addr)
+        // Link-local IPv6 addresses can't be bound without a scope id
+        .filter(|ip| match ip {
+            IpAddr::V4(_) => true,
+            IpAddr::V6(v6) => v6.segments()[0] & 0xffc0 != 0xfe80,
+        })
+        .collect();
+
+    if addresses.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            format!(
+                "network interface {} is missing or has no usable address",
+                listen_interface
+            ),
+        ));
+    }
+    Ok(addresses)
+}
+
+/// Opens a TCP connection to `peer_addr` from an address of `listen_interface`.
+/// Fails when the interface has no address of the peer's family instead of
+/// leaving through another route.
+pub async fn connect_from_interface(
+    listen_interface: &str,
+    peer_addr: SocketAddr,
+) -> io::Result<TcpStream> {
+    let local_ip = interface_addresses(listen_interface)?
+        .into_iter()
+        .find(|ip| ip.is_ipv4() == peer_addr.is_ipv4())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                format!(
+                    "network interface {} has no address to reach {}",
+                    listen_interface, peer_addr
+                ),
+            )
+        })?;
+
+    let socket = if peer_addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    socket.bind(SocketAddr::new(local_ip, 0))?;
+    socket.connect(peer_addr).await
+}
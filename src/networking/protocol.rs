@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use crate::token_bucket::consume_tokens;
-use crate::token_bucket::TokenBucket;
+use crate::token_bucket::{BandwidthPriority, TokenBucket};
 
 use tokio::sync::Mutex;
 
@@ -10,16 +10,18 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::error::Error as StdError;
 use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::sync::Arc;
 
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
-use tokio::net::tcp::OwnedWriteHalf;
 use tokio::sync::broadcast;
 use tokio::sync::mpsc::Receiver;
 use tokio::sync::oneshot;
+use tokio::sync::watch;
 
 use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
 
 use std::fmt;
 use tracing::{event, Level};
@@ -29,6 +31,23 @@ use strum_macros::EnumIter;
 
 const STANDARD_BLOCK_SIZE: u32 = 16384;
 
+// Handshake reserved bits: byte 5 for the extension protocol (BEP 10), byte 7 for
+// the fast extension (BEP 6)
+pub const EXTENSION_PROTOCOL_FLAG: u8 = 0x10;
+pub const FAST_EXTENSION_FLAG: u8 = 0x04;
+
+// Pieces granted to each fast extension peer to download while choked
+pub const ALLOWED_FAST_SET_SIZE: usize = 10;
+
+// ut_metadata (BEP 9) message types and the size metadata is sliced into
+pub const METADATA_REQUEST: u8 = 0;
+pub const METADATA_DATA: u8 = 1;
+pub const METADATA_REJECT: u8 = 2;
+pub const METADATA_PIECE_SIZE: usize = 16384;
+
+// Deepest nesting accepted when measuring a bencoded ut_metadata header
+const MAX_BENCODE_DEPTH: usize = 8;
+
 #[derive(Debug)]
 pub enum MessageGenerationError {
     PayloadTooLarge(String),
@@ -51,6 +70,7 @@ pub enum ClientExtendedId {
     #[cfg(feature = "pex")]
     UtPex = 1,
     UtMetadata = 2,
+    UtHolepunch = 3,
 }
 impl ClientExtendedId {
     /// Returns the integer ID for the extension message.
@@ -65,19 +85,135 @@ impl ClientExtendedId {
             #[cfg(feature = "pex")]
             ClientExtendedId::UtPex => "ut_pex",
             ClientExtendedId::UtMetadata => "ut_metadata",
+            ClientExtendedId::UtHolepunch => "ut_holepunch",
+        }
+    }
+
+    /// Whether the extension passes peers around outside the tracker, which a
+    /// private torrent must not do.
+    pub fn is_peer_exchange(&self) -> bool {
+        match self {
+            #[cfg(feature = "pex")]
+            ClientExtendedId::UtPex => true,
+            ClientExtendedId::UtHolepunch => true,
+            _ => false,
         }
     }
 }
 
+// ut_pex (BEP 11) peer flags
+pub const PEX_SEED: u8 = 0x02;
+pub const PEX_SUPPORTS_HOLEPUNCH: u8 = 0x08;
+pub const PEX_CONNECTABLE: u8 = 0x10;
+
+// Most added or dropped peers a single ut_pex message carries
+pub const MAX_PEX_PEERS_PER_MESSAGE: usize = 50;
+
+/// A peer passed on over PEX, with the BEP 11 flags known for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PexPeer {
+    pub addr: SocketAddr,
+    pub flags: u8,
+}
+
 #[derive(Serialize, Deserialize, Debug, Default)]
 #[cfg(feature = "pex")]
 pub struct PexMessage {
     #[serde(with = "serde_bytes", default)]
     pub added: Vec<u8>,
-    #[serde(default)]
+    // One flags byte per peer in `added`
+    #[serde(rename = "added.f", with = "serde_bytes", default)]
     pub added_f: Vec<u8>,
+    // Compact IPv6 peers, 18 bytes each
+    #[serde(with = "serde_bytes", default)]
+    pub added6: Vec<u8>,
+    #[serde(rename = "added6.f", with = "serde_bytes", default)]
+    pub added6_f: Vec<u8>,
     #[serde(with = "serde_bytes", default)]
     pub dropped: Vec<u8>,
+    #[serde(with = "serde_bytes", default)]
+    pub dropped6: Vec<u8>,
+}
+
+#[cfg(feature = "pex")]
+impl PexMessage {
+    pub fn new(added: &[PexPeer], dropped: &[SocketAddr]) -> Self {
+        let mut message = PexMessage::default();
+        for peer in added {
+            if peer.addr.is_ipv4() {
+                push_compact_addr(&mut message.added, peer.addr);
+                message.added_f.push(peer.flags);
+            } else {
+                push_compact_addr(&mut message.added6, peer.addr);
+                message.added6_f.push(peer.flags);
+            }
+        }
+        for &addr in dropped {
+            if addr.is_ipv4() {
+                push_compact_addr(&mut message.dropped, addr);
+            } else {
+                push_compact_addr(&mut message.dropped6, addr);
+            }
+        }
+        message
+    }
+
+    /// Added peers of both families. A missing flags byte reads as no flags.
+    pub fn added_peers(&self) -> Vec<PexPeer> {
+        let with_flags = |addrs: Vec<SocketAddr>, flags: &[u8]| {
+            addrs
+                .into_iter()
+                .enumerate()
+                .map(|(i, addr)| PexPeer {
+                    addr,
+                    flags: flags.get(i).copied().unwrap_or(0),
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut peers = with_flags(compact_addrs(&self.added, 4), &self.added_f);
+        peers.extend(with_flags(compact_addrs(&self.added6, 16), &self.added6_f));
+        peers
+    }
+
+    pub fn dropped_peers(&self) -> Vec<SocketAddr> {
+        let mut peers = compact_addrs(&self.dropped, 4);
+        peers.extend(compact_addrs(&self.dropped6, 16));
+        peers
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.added6.is_empty()
+            && self.dropped.is_empty()
+            && self.dropped6.is_empty()
+    }
+}
+
+#[cfg(feature = "pex")]
+fn push_compact_addr(out: &mut Vec<u8>, addr: SocketAddr) {
+    match addr.ip().to_canonical() {
+        IpAddr::V4(ip) => out.extend_from_slice(&ip.octets()),
+        IpAddr::V6(ip) => out.extend_from_slice(&ip.octets()),
+    }
+    out.extend_from_slice(&addr.port().to_be_bytes());
+}
+
+/// Compact peers (BEP 23), `ip_len` address bytes plus a port each.
+#[cfg(feature = "pex")]
+fn compact_addrs(data: &[u8], ip_len: usize) -> Vec<SocketAddr> {
+    data.chunks_exact(ip_len + 2)
+        .map(|chunk| {
+            let ip = match ip_len {
+                4 => IpAddr::V4(Ipv4Addr::new(chunk[0], chunk[1], chunk[2], chunk[3])),
+                _ => {
+                    let mut octets = [0u8; 16];
+                    octets.copy_from_slice(&chunk[..16]);
+                    IpAddr::V6(Ipv6Addr::from(octets))
+                }
+            };
+            SocketAddr::new(ip, u16::from_be_bytes([chunk[ip_len], chunk[ip_len + 1]]))
+        })
+        .collect()
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -100,6 +236,109 @@ pub struct ExtendedHandshakePayload {
 
     #[serde(default)]
     pub metadata_size: Option<i64>,
+
+    // Partial seed (BEP 21): 1 when the sender won't download anything more
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_only: Option<u8>,
+
+    // Our IP as the sender sees it (BEP 10), 4 or 16 bytes
+    #[serde(default, with = "serde_bytes", skip_serializing_if = "Option::is_none")]
+    pub yourip: Option<Vec<u8>>,
+}
+
+impl ExtendedHandshakePayload {
+    /// The `yourip` field as an address, if it is one.
+    pub fn your_ip(&self) -> Option<IpAddr> {
+        match self.yourip.as_deref()? {
+            &[a, b, c, d] => Some(IpAddr::from([a, b, c, d])),
+            bytes => <[u8; 16]>::try_from(bytes).ok().map(IpAddr::from),
+        }
+    }
+}
+
+/// Why a relay couldn't pass on a holepunch rendezvous (BEP 55).
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HolepunchError {
+    NoSuchPeer = 1,
+    NotConnected = 2,
+    NoSupport = 3,
+    NoSelf = 4,
+}
+
+impl HolepunchError {
+    fn from_code(code: u32) -> Option<Self> {
+        match code {
+            1 => Some(Self::NoSuchPeer),
+            2 => Some(Self::NotConnected),
+            3 => Some(Self::NoSupport),
+            4 => Some(Self::NoSelf),
+            _ => None,
+        }
+    }
+}
+
+/// A ut_holepunch (BEP 55) message. A rendezvous asks the relay to introduce us to
+/// the peer at that address; the relay answers with a connect to both sides, or an
+/// error to us.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HolepunchMessage {
+    Rendezvous(SocketAddr),
+    Connect(SocketAddr),
+    Error(SocketAddr, HolepunchError),
+}
+
+impl HolepunchMessage {
+    pub fn to_bytes(self) -> Vec<u8> {
+        let (msg_type, addr, err_code) = match self {
+            HolepunchMessage::Rendezvous(addr) => (0u8, addr, 0u32),
+            HolepunchMessage::Connect(addr) => (1, addr, 0),
+            HolepunchMessage::Error(addr, error) => (2, addr, error as u32),
+        };
+        let mut bytes = vec![msg_type];
+        match addr.ip().to_canonical() {
+            IpAddr::V4(ip) => {
+                bytes.push(0);
+                bytes.extend(ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                bytes.push(1);
+                bytes.extend(ip.octets());
+            }
+        }
+        bytes.extend(addr.port().to_be_bytes());
+        bytes.extend(err_code.to_be_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(payload: &[u8]) -> Option<Self> {
+        let (&msg_type, rest) = payload.split_first()?;
+        let (&addr_type, rest) = rest.split_first()?;
+        let (ip, rest) = match addr_type {
+            0 => {
+                let octets: [u8; 4] = rest.get(..4)?.try_into().ok()?;
+                (IpAddr::V4(Ipv4Addr::from(octets)), &rest[4..])
+            }
+            1 => {
+                let octets: [u8; 16] = rest.get(..16)?.try_into().ok()?;
+                (IpAddr::V6(Ipv6Addr::from(octets)), &rest[16..])
+            }
+            _ => return None,
+        };
+        let port = u16::from_be_bytes(rest.get(..2)?.try_into().ok()?);
+        let err_code = u32::from_be_bytes(rest.get(2..6)?.try_into().ok()?);
+        let addr = SocketAddr::new(ip, port);
+
+        match msg_type {
+            0 => Some(HolepunchMessage::Rendezvous(addr)),
+            1 => Some(HolepunchMessage::Connect(addr)),
+            2 => Some(HolepunchMessage::Error(
+                addr,
+                HolepunchError::from_code(err_code)?,
+            )),
+            _ => None,
+        }
+    }
 }
 
 pub struct MessageSummary<'a>(pub &'a Message);
@@ -140,7 +379,16 @@ pub enum Message {
     Cancel(u32, u32, u32),
     Port(u32),
 
-    ExtendedHandshake(Option<i64>),
+    // Fast extension (BEP 6)
+    SuggestPiece(u32),
+    HaveAll,
+    HaveNone,
+    RejectRequest(u32, u32, u32),
+    AllowedFast(u32),
+
+    // Metadata size, whether we only upload (BEP 21), and whether ut_pex and
+    // ut_holepunch are offered; private torrents leave them out (BEP 27)
+    ExtendedHandshake(Option<i64>, bool, bool),
     Extended(u8, Vec<u8>),
 }
 
@@ -170,11 +418,154 @@ pub fn calculate_blocks_for_piece(piece_index: u32, piece_size: u32) -> HashSet<
     blocks
 }
 
+/// Splits a ut_metadata message into its bencoded header and the metadata bytes that
+/// follow it in data messages.
+pub fn split_metadata_message(payload: &[u8]) -> Option<(MetadataMessage, &[u8])> {
+    let header_len = bencode_len(payload, 0)?;
+    let header = serde_bencode::from_bytes(&payload[..header_len]).ok()?;
+    Some((header, &payload[header_len..]))
+}
+
+/// Length of the bencoded value at the start of `data`, or `None` if it is cut short,
+/// malformed or nested too deep.
+fn bencode_len(data: &[u8], depth: usize) -> Option<usize> {
+    match *data.first()? {
+        b'i' => Some(data.iter().position(|&b| b == b'e')? + 1),
+        b'l' | b'd' if depth < MAX_BENCODE_DEPTH => {
+            let mut pos = 1;
+            while *data.get(pos)? != b'e' {
+                pos += bencode_len(&data[pos..], depth + 1)?;
+            }
+            Some(pos + 1)
+        }
+        b'0'..=b'9' => {
+            let colon = data.iter().position(|&b| b == b':')?;
+            let len: usize = std::str::from_utf8(&data[..colon]).ok()?.parse().ok()?;
+            let end = colon.checked_add(1)?.checked_add(len)?;
+            (end <= data.len()).then_some(end)
+        }
+        _ => None,
+    }
+}
+
+/// The ut_metadata answer to a request for `piece`: a data message carrying that
+/// slice of the info dict, or a reject when we don't have it.
+pub fn metadata_reply(
+    metadata: Option<&[u8]>,
+    piece: usize,
+) -> Result<Vec<u8>, serde_bencode::Error> {
+    let slice = metadata.and_then(|metadata| {
+        let start = piece.checked_mul(METADATA_PIECE_SIZE)?;
+        let end = start
+            .saturating_add(METADATA_PIECE_SIZE)
+            .min(metadata.len());
+        Some((
+            metadata.get(start..end).filter(|s| !s.is_empty())?,
+            metadata.len(),
+        ))
+    });
+
+    let Some((data, total_size)) = slice else {
+        return serde_bencode::to_bytes(&MetadataMessage {
+            msg_type: METADATA_REJECT,
+            piece,
+            total_size: None,
+        });
+    };
+    let mut reply = serde_bencode::to_bytes(&MetadataMessage {
+        msg_type: METADATA_DATA,
+        piece,
+        total_size: Some(total_size),
+    })?;
+    reply.extend_from_slice(data);
+    Ok(reply)
+}
+
+/// The pieces a peer at `ip` may download while choked (BEP 6). The set only depends
+/// on the peer's /24 and the info hash, so both sides can compute it. IPv6 has no
+/// canonical set, so those peers get none.
+pub fn allowed_fast_set(info_hash: &[u8], ip: Ipv4Addr, num_pieces: u32, count: usize) -> Vec<u32> {
+    let count = count.min(num_pieces as usize);
+    let mut allowed = Vec::with_capacity(count);
+
+    let masked_ip = u32::from(ip) & 0xFFFF_FF00;
+    let mut x = masked_ip.to_be_bytes().to_vec();
+    x.extend_from_slice(info_hash);
+
+    while allowed.len() < count {
+        x = Sha1::digest(&x).to_vec();
+        for chunk in x.chunks_exact(4) {
+            if allowed.len() >= count {
+                break;
+            }
+            let y = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            let index = y % num_pieces;
+            if !allowed.contains(&index) {
+                allowed.push(index);
+            }
+        }
+    }
+    allowed
+}
+
+/// Canonical priority (BEP 40) of a connection between `a` and `b`. It is the same
+/// from both ends, so when every client prefers the highest-priority connections the
+/// swarm converges on one well-connected graph. `None` for an IPv4/IPv6 pair.
+pub fn canonical_peer_priority(a: SocketAddr, b: SocketAddr) -> Option<u32> {
+    let (a_ip, b_ip) = (a.ip().to_canonical(), b.ip().to_canonical());
+    if a_ip == b_ip {
+        let (low, high) = (a.port().min(b.port()), a.port().max(b.port()));
+        return Some(crc32c(&[low.to_be_bytes(), high.to_be_bytes()].concat()));
+    }
+
+    // The leading bytes kept whole: the /16 for IPv4 and the /48 for IPv6, one byte
+    // more when both share it, and all of them when they share that too. The rest
+    // are masked with 0x55.
+    let (mut a_bytes, mut b_bytes, prefix_len) = match (a_ip, b_ip) {
+        (IpAddr::V4(a), IpAddr::V4(b)) => (a.octets().to_vec(), b.octets().to_vec(), 2),
+        (IpAddr::V6(a), IpAddr::V6(b)) => (a.octets().to_vec(), b.octets().to_vec(), 6),
+        _ => return None,
+    };
+    let kept = if a_bytes[..prefix_len] != b_bytes[..prefix_len] {
+        prefix_len
+    } else if a_bytes[prefix_len] != b_bytes[prefix_len] {
+        prefix_len + 1
+    } else {
+        a_bytes.len()
+    };
+    for bytes in [&mut a_bytes, &mut b_bytes] {
+        for byte in &mut bytes[kept..] {
+            *byte &= 0x55;
+        }
+    }
+    if a_bytes > b_bytes {
+        std::mem::swap(&mut a_bytes, &mut b_bytes);
+    }
+    Some(crc32c(&[a_bytes, b_bytes].concat()))
+}
+
+/// CRC-32C (Castagnoli), the checksum BEP 40 uses.
+fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x82F6_3B78
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
 pub async fn writer_task(
-    mut stream_write_half: OwnedWriteHalf,
+    mut stream_write_half: impl AsyncWrite + Unpin,
     mut write_rx: Receiver<Message>,
     error_tx: oneshot::Sender<Box<dyn StdError + Send + Sync>>,
     global_ul_bucket: Arc<Mutex<TokenBucket>>,
+    bandwidth_priority: watch::Receiver<BandwidthPriority>,
     mut shutdown_rx: broadcast::Receiver<()>,
 ) {
     loop {
@@ -182,8 +573,9 @@ pub async fn writer_task(
         tokio::select! {            Some(message) = write_rx.recv() => {
                 if let Message::Piece(_, _, data) = &message {
                     if !data.is_empty() {
+                        let priority = *bandwidth_priority.borrow();
                         tokio::select! {
-                            _ = consume_tokens(&global_ul_bucket, data.len() as f64) => {},
+                            _ = consume_tokens(&global_ul_bucket, data.len() as f64, priority) => {},
                             _ = shutdown_rx.recv() => {
                                 event!(Level::TRACE, "writer task shutting down during token wait.");
                                 break;
@@ -239,7 +631,8 @@ pub fn generate_message(message: Message) -> Result<Vec<u8>, MessageGenerationEr
             let protocol_str = "BitTorrent protocol";
             let pstrlen = [19u8];
             let mut reserved = [0u8; 8];
-            reserved[5] |= 0x10;
+            reserved[5] |= EXTENSION_PROTOCOL_FLAG;
+            reserved[7] |= FAST_EXTENSION_FLAG;
 
             handshake.extend_from_slice(&pstrlen);
             handshake.extend_from_slice(protocol_str.as_bytes());
@@ -308,12 +701,43 @@ pub fn generate_message(message: Message) -> Result<Vec<u8>, MessageGenerationEr
             message_bytes.extend(port.to_be_bytes());
             Ok(message_bytes)
         }
-        Message::ExtendedHandshake(metadata_size) => {
+        Message::SuggestPiece(index) => {
+            let mut message_bytes = Vec::new();
+            message_bytes.extend([0, 0, 0, 5]);
+            message_bytes.extend([13]);
+            message_bytes.extend(index.to_be_bytes());
+            Ok(message_bytes)
+        }
+        Message::HaveAll => Ok([0, 0, 0, 1, 14].to_vec()),
+        Message::HaveNone => Ok([0, 0, 0, 1, 15].to_vec()),
+        Message::RejectRequest(index, begin, length) => {
+            let mut message_bytes = Vec::new();
+            message_bytes.extend([0, 0, 0, 13]);
+            message_bytes.extend([16]);
+            message_bytes.extend(index.to_be_bytes());
+            message_bytes.extend(begin.to_be_bytes());
+            message_bytes.extend(length.to_be_bytes());
+            Ok(message_bytes)
+        }
+        Message::AllowedFast(index) => {
+            let mut message_bytes = Vec::new();
+            message_bytes.extend([0, 0, 0, 5]);
+            message_bytes.extend([17]);
+            message_bytes.extend(index.to_be_bytes());
+            Ok(message_bytes)
+        }
+        Message::ExtendedHandshake(metadata_size, upload_only, pex_allowed) => {
             let m: HashMap<String, u8> = ClientExtendedId::iter()
                 .filter(|&variant| variant != ClientExtendedId::Handshake) // Exclude the special handshake ID
+                .filter(|&variant| pex_allowed || !variant.is_peer_exchange())
                 .map(|variant| (variant.as_str().to_string(), variant.id()))
                 .collect();
-            let payload = ExtendedHandshakePayload { m, metadata_size };
+            let payload = ExtendedHandshakePayload {
+                m,
+                metadata_size,
+                upload_only: upload_only.then_some(1),
+                yourip: None,
+            };
             let bencoded_payload =
                 serde_bencode::to_bytes(&payload).map_err(MessageGenerationError::BencodeError)?;
 
@@ -424,6 +848,37 @@ pub async fn parse_message(
             let _ = socket.read_exact(&mut buffer_message_port).await?;
             Ok(Message::Port(u32::from_be_bytes(buffer_message_port)))
         }
+        13 => {
+            let mut buffer_message_index = [0u8; 4];
+            let _ = socket.read_exact(&mut buffer_message_index).await?;
+            Ok(Message::SuggestPiece(u32::from_be_bytes(
+                buffer_message_index,
+            )))
+        }
+        14 => Ok(Message::HaveAll),
+        15 => Ok(Message::HaveNone),
+        16 => {
+            let mut buffer_message_index = [0u8; 4];
+            let mut buffer_message_begin = [0u8; 4];
+            let mut buffer_message_len = [0u8; 4];
+
+            let _ = socket.read_exact(&mut buffer_message_index).await?;
+            let _ = socket.read_exact(&mut buffer_message_begin).await?;
+            let _ = socket.read_exact(&mut buffer_message_len).await?;
+
+            Ok(Message::RejectRequest(
+                u32::from_be_bytes(buffer_message_index),
+                u32::from_be_bytes(buffer_message_begin),
+                u32::from_be_bytes(buffer_message_len),
+            ))
+        }
+        17 => {
+            let mut buffer_message_index = [0u8; 4];
+            let _ = socket.read_exact(&mut buffer_message_index).await?;
+            Ok(Message::AllowedFast(u32::from_be_bytes(
+                buffer_message_index,
+            )))
+        }
         20 => {
             let mut extended_id_buf = [0u8; 1];
             socket.read_exact(&mut extended_id_buf).await?;
@@ -461,6 +916,7 @@ mod tests {
 
         let mut expected_reserved = [0u8; 8];
         expected_reserved[5] |= 0x10; // This matches your implementation
+        expected_reserved[7] |= 0x04; // Fast extension
 
         assert_eq!(actual_result.len(), 68);
         assert_eq!(actual_result[0], 19); // Pstrlen should be 19
@@ -503,6 +959,7 @@ mod tests {
 
         let mut expected_reserved = [0u8; 8];
         expected_reserved[5] |= 0x10;
+        expected_reserved[7] |= 0x04;
 
         assert_eq!(buffer[0], 19);
         assert_eq!(&buffer[1..20], b"BitTorrent protocol");
@@ -643,6 +1100,199 @@ mod tests {
         assert_message_roundtrip(Message::Cancel(1, 16384, 16384)).await;
         assert_message_roundtrip(Message::Port(6881)).await;
         assert_message_roundtrip(Message::Extended(1, vec![10, 20, 30])).await;
+        assert_message_roundtrip(Message::SuggestPiece(42)).await;
+        assert_message_roundtrip(Message::HaveAll).await;
+        assert_message_roundtrip(Message::HaveNone).await;
+        assert_message_roundtrip(Message::RejectRequest(1, 16384, 16384)).await;
+        assert_message_roundtrip(Message::AllowedFast(7)).await;
+    }
+
+    #[cfg(feature = "pex")]
+    #[test]
+    fn test_pex_message_roundtrip_with_flags_and_dropped() {
+        let seed = PexPeer {
+            addr: "10.0.0.1:6881".parse().unwrap(),
+            flags: PEX_SEED | PEX_CONNECTABLE,
+        };
+        let v6_peer = PexPeer {
+            addr: "[2001:db8::2]:51413".parse().unwrap(),
+            flags: PEX_SUPPORTS_HOLEPUNCH,
+        };
+        let dropped: Vec<SocketAddr> = vec![
+            "10.0.0.3:6881".parse().unwrap(),
+            "[2001:db8::4]:6881".parse().unwrap(),
+        ];
+
+        let message = PexMessage::new(&[seed, v6_peer], &dropped);
+        let bytes = serde_bencode::to_bytes(&message).unwrap();
+        let text = String::from_utf8_lossy(&bytes);
+        assert!(text.contains("7:added.f"));
+        assert!(text.contains("8:added6.f"));
+        assert!(text.contains("8:dropped6"));
+
+        let parsed: PexMessage = serde_bencode::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.added_peers(), vec![seed, v6_peer]);
+        assert_eq!(parsed.dropped_peers(), dropped);
+        assert!(!parsed.is_empty());
+    }
+
+    #[cfg(feature = "pex")]
+    #[test]
+    fn test_pex_message_without_flags() {
+        // Some clients leave out added.f entirely.
+        let parsed: PexMessage =
+            serde_bencode::from_bytes(b"d5:added6:\x0a\x00\x00\x01\x1a\xe1e").unwrap();
+        assert_eq!(
+            parsed.added_peers(),
+            vec![PexPeer {
+                addr: "10.0.0.1:6881".parse().unwrap(),
+                flags: 0,
+            }]
+        );
+        assert!(parsed.dropped_peers().is_empty());
+        assert!(PexMessage::new(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn test_holepunch_message_roundtrip() {
+        let v4: SocketAddr = "10.1.2.3:6881".parse().unwrap();
+        let v6: SocketAddr = "[2001:db8::1]:51413".parse().unwrap();
+
+        let rendezvous = HolepunchMessage::Rendezvous(v4).to_bytes();
+        assert_eq!(rendezvous, vec![0, 0, 10, 1, 2, 3, 0x1a, 0xe1, 0, 0, 0, 0]);
+
+        for message in [
+            HolepunchMessage::Rendezvous(v4),
+            HolepunchMessage::Connect(v6),
+            HolepunchMessage::Error(v4, HolepunchError::NotConnected),
+        ] {
+            assert_eq!(
+                HolepunchMessage::from_bytes(&message.to_bytes()),
+                Some(message)
+            );
+        }
+    }
+
+    #[test]
+    fn test_holepunch_message_rejects_bad_payloads() {
+        let v4: SocketAddr = "10.1.2.3:6881".parse().unwrap();
+        let bytes = HolepunchMessage::Connect(v4).to_bytes();
+
+        assert_eq!(
+            HolepunchMessage::from_bytes(&bytes[..bytes.len() - 1]),
+            None
+        );
+
+        let mut bad_addr_type = bytes.clone();
+        bad_addr_type[1] = 2;
+        assert_eq!(HolepunchMessage::from_bytes(&bad_addr_type), None);
+
+        let mut unknown_error = HolepunchMessage::Error(v4, HolepunchError::NoSelf).to_bytes();
+        *unknown_error.last_mut().unwrap() = 9;
+        assert_eq!(HolepunchMessage::from_bytes(&unknown_error), None);
+    }
+
+    #[test]
+    fn test_metadata_reply_slices_info_dict() {
+        let metadata: Vec<u8> = (0..20000u32).map(|i| i as u8).collect();
+
+        let reply = metadata_reply(Some(&metadata), 1).unwrap();
+        let (header, data) = split_metadata_message(&reply).unwrap();
+        assert_eq!(
+            header,
+            MetadataMessage {
+                msg_type: METADATA_DATA,
+                piece: 1,
+                total_size: Some(20000),
+            }
+        );
+        assert_eq!(data, &metadata[METADATA_PIECE_SIZE..]);
+
+        let reply = metadata_reply(Some(&metadata), 0).unwrap();
+        let (_, data) = split_metadata_message(&reply).unwrap();
+        assert_eq!(data, &metadata[..METADATA_PIECE_SIZE]);
+
+        for reply in [
+            metadata_reply(Some(&metadata), 2).unwrap(),
+            metadata_reply(None, 0).unwrap(),
+        ] {
+            let (header, data) = split_metadata_message(&reply).unwrap();
+            assert_eq!(header.msg_type, METADATA_REJECT);
+            assert!(data.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_split_metadata_message_rejects_bad_headers() {
+        assert!(split_metadata_message(b"d8:msg_typei0e5:piecei0e").is_none());
+        assert!(split_metadata_message(b"d8:msg_typei0e5:piece").is_none());
+        assert!(split_metadata_message(b"99:short").is_none());
+        assert!(split_metadata_message(&[b'l'; 64]).is_none());
+
+        let (header, rest) = split_metadata_message(b"d8:msg_typei0e5:piecei3eeextra").unwrap();
+        assert_eq!(header.msg_type, METADATA_REQUEST);
+        assert_eq!(header.piece, 3);
+        assert_eq!(rest, b"extra");
+    }
+
+    #[test]
+    fn test_allowed_fast_set_matches_bep6_vectors() {
+        let info_hash = [0xaa; 20];
+        let ip = Ipv4Addr::new(80, 4, 4, 200);
+
+        assert_eq!(
+            allowed_fast_set(&info_hash, ip, 1313, 7),
+            [1059, 431, 808, 1217, 287, 376, 1188]
+        );
+        assert_eq!(
+            allowed_fast_set(&info_hash, ip, 1313, 9),
+            [1059, 431, 808, 1217, 287, 376, 1188, 353, 508]
+        );
+        // Only the /24 matters.
+        assert_eq!(
+            allowed_fast_set(&info_hash, Ipv4Addr::new(80, 4, 4, 1), 1313, 7),
+            allowed_fast_set(&info_hash, ip, 1313, 7)
+        );
+        // Small torrents can't grant more pieces than they have.
+        assert_eq!(allowed_fast_set(&info_hash, ip, 3, 10).len(), 3);
+    }
+
+    #[test]
+    fn test_canonical_peer_priority_matches_bep40_vectors() {
+        let addr = |text: &str| text.parse::<SocketAddr>().unwrap();
+
+        assert_eq!(
+            canonical_peer_priority(addr("123.213.32.10:0"), addr("98.76.54.32:0")),
+            Some(0xec2d7224)
+        );
+        assert_eq!(
+            canonical_peer_priority(addr("123.213.32.10:0"), addr("123.213.32.234:0")),
+            Some(0x99568189)
+        );
+        // Both ends compute the same value.
+        assert_eq!(
+            canonical_peer_priority(addr("98.76.54.32:0"), addr("123.213.32.10:0")),
+            Some(0xec2d7224)
+        );
+        assert_eq!(
+            canonical_peer_priority(addr("[2001:db8::1]:6881"), addr("[2001:db9::1]:6881")),
+            canonical_peer_priority(addr("[2001:db9::1]:6881"), addr("[2001:db8::1]:6881"))
+        );
+        assert_eq!(
+            canonical_peer_priority(addr("1.2.3.4:6881"), addr("1.2.3.4:6882")),
+            canonical_peer_priority(addr("1.2.3.4:6882"), addr("1.2.3.4:6881"))
+        );
+        assert_eq!(
+            canonical_peer_priority(addr("1.2.3.4:6881"), addr("[2001:db8::1]:6881")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_extended_handshake_your_ip() {
+        let payload: ExtendedHandshakePayload =
+            serde_bencode::from_bytes(b"d1:mde6:yourip4:\x01\x02\x03\x04e").unwrap();
+        assert_eq!(payload.your_ip(), Some(IpAddr::from([1, 2, 3, 4])));
     }
 
     /// Special test for the ExtendedHandshake
@@ -650,7 +1300,7 @@ mod tests {
     async fn test_extended_handshake_parsing() {
         // 1. Generate the ExtendedHandshake message
         let metadata_size = 12345;
-        let msg = Message::ExtendedHandshake(Some(metadata_size));
+        let msg = Message::ExtendedHandshake(Some(metadata_size), true, true);
         let generated_bytes = generate_message(msg).unwrap();
 
         // 2. Parse it back using our generic parser
@@ -666,10 +1316,27 @@ mod tests {
                 serde_bencode::from_bytes(&payload_bytes).unwrap();
 
             assert_eq!(payload.metadata_size, Some(metadata_size as i64));
+            assert_eq!(payload.upload_only, Some(1));
             assert!(payload.m.contains_key("ut_pex"));
             assert!(payload.m.contains_key("ut_metadata"));
+            assert!(payload.m.contains_key("ut_holepunch"));
         } else {
             panic!("ExtendedHandshake did not parse back as Message::Extended");
         }
     }
+
+    #[tokio::test]
+    async fn test_extended_handshake_without_peer_exchange() {
+        let generated_bytes =
+            generate_message(Message::ExtendedHandshake(None, false, false)).unwrap();
+        let mut reader = &generated_bytes[..];
+        let Message::Extended(_, payload_bytes) = parse_message(&mut reader).await.unwrap() else {
+            panic!("ExtendedHandshake did not parse back as Message::Extended");
+        };
+
+        let payload: ExtendedHandshakePayload = serde_bencode::from_bytes(&payload_bytes).unwrap();
+        assert!(payload.m.contains_key("ut_metadata"));
+        assert!(!payload.m.contains_key("ut_pex"));
+        assert!(!payload.m.contains_key("ut_holepunch"));
+    }
 }
@@ -1,19 +1,25 @@
 // SPDX-FileCopyrightText: 2025 The superseedr Contributors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+use crate::bitfield::Bitfield;
 use crate::torrent_file::Info;
 use crate::torrent_file::Torrent;
 
 use super::protocol::{
-    calculate_blocks_for_piece, parse_message, writer_task, BlockInfo, ClientExtendedId,
-    ExtendedHandshakePayload, Message, MessageSummary, MetadataMessage,
+    allowed_fast_set, calculate_blocks_for_piece, parse_message, writer_task, BlockInfo,
+    ClientExtendedId, ExtendedHandshakePayload, Message, MessageSummary, MetadataMessage,
+    ALLOWED_FAST_SET_SIZE, EXTENSION_PROTOCOL_FLAG, FAST_EXTENSION_FLAG,
+};
+use super::protocol::{
+    metadata_reply, split_metadata_message, HolepunchMessage, METADATA_DATA, METADATA_PIECE_SIZE,
+    METADATA_REJECT, METADATA_REQUEST,
 };
 
 #[cfg(feature = "pex")]
-use super::protocol::PexMessage;
+use super::protocol::{PexMessage, PexPeer, MAX_PEX_PEERS_PER_MESSAGE};
 
 use crate::token_bucket::consume_tokens;
-use crate::token_bucket::TokenBucket;
+use crate::token_bucket::{BandwidthPriority, TokenBucket};
 
 use crate::command::TorrentCommand;
 
@@ -21,16 +27,18 @@ use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::error::Error as StdError;
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 
+use super::BoxedPeerStream;
+
 use tokio::io::AsyncReadExt;
 
-use tokio::net::TcpStream;
 use tokio::sync::broadcast;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::oneshot;
+use tokio::sync::watch;
 use tokio::sync::Mutex;
 use tokio::sync::Semaphore;
 use tokio::task::JoinHandle;
@@ -39,9 +47,16 @@ use tokio::time::timeout;
 use tokio::time::Duration;
 use tokio::time::Instant;
 
+use rand::seq::IndexedRandom;
+use rand::Rng;
+
 use tracing::{event, instrument, Level};
 
 const PEER_BLOCK_IN_FLIGHT_LIMIT: usize = 5;
+// Lazy bitfield: at most this many pieces are left out of the bitfield and sent
+// afterwards as HAVE messages, each this many milliseconds after the last
+const LAZY_BITFIELD_HELD_PIECES: usize = 8;
+const LAZY_HAVE_SPACING_MS: std::ops::RangeInclusive<u64> = 200..=1500;
 
 struct DisconnectGuard {
     peer_ip_port: String,
@@ -69,9 +84,17 @@ pub enum ConnectionType {
     Incoming,
 }
 
+/// How long a peer session waits on the other side; see the matching settings.
+#[derive(Debug, Clone, Copy)]
+pub struct PeerTimeouts {
+    pub handshake: Duration,
+    pub keepalive: Duration,
+    pub inactivity: Duration,
+}
+
 pub struct PeerSessionParameters {
     pub info_hash: Vec<u8>,
-    pub torrent_metadata_length: Option<i64>,
+    pub torrent_metadata: Option<Arc<Vec<u8>>>,
     pub connection_type: ConnectionType,
     pub torrent_manager_rx: Receiver<TorrentCommand>,
     pub torrent_manager_tx: Sender<TorrentCommand>,
@@ -79,20 +102,36 @@ pub struct PeerSessionParameters {
     pub client_id: Vec<u8>,
     pub global_dl_bucket: Arc<Mutex<TokenBucket>>,
     pub global_ul_bucket: Arc<Mutex<TokenBucket>>,
+    // The torrent's share of the global limits, changed by the manager
+    pub bandwidth_priority: watch::Receiver<BandwidthPriority>,
     pub shutdown_tx: broadcast::Sender<()>,
+    pub lazy_bitfield: bool,
+    pub upload_only: bool,
+    // False for private torrents, which never swap peers outside the tracker
+    pub pex_allowed: bool,
+    pub timeouts: PeerTimeouts,
 }
 
 pub struct PeerSession {
     info_hash: Vec<u8>,
     peer_session_established: bool,
-    torrent_metadata_length: Option<i64>,
+    torrent_metadata: Option<Arc<Vec<u8>>>,
     connection_type: ConnectionType,
     torrent_manager_rx: Receiver<TorrentCommand>,
     torrent_manager_tx: Sender<TorrentCommand>,
     client_id: Vec<u8>,
     peer_ip_port: String,
-
-    writer_rx: Receiver<Message>,
+    peer_supports_fast: bool,
+    lazy_bitfield: bool,
+    // Sent in the extended handshake when we are a partial seed (BEP 21)
+    upload_only: bool,
+    pex_allowed: bool,
+    timeouts: PeerTimeouts,
+    // Sends the HAVEs held back from a lazy bitfield
+    lazy_have_task: Option<AbortOnDrop>,
+
+    // Handed to the writer task when the session starts
+    writer_rx: Option<Receiver<Message>>,
     writer_tx: Sender<Message>,
 
     block_tracker: HashMap<u32, HashSet<BlockInfo>>,
@@ -106,9 +145,13 @@ pub struct PeerSession {
     peer_extended_handshake_payload: Option<ExtendedHandshakePayload>,
     peer_torrent_metadata_piece_count: usize,
     peer_torrent_metadata_pieces: Vec<u8>,
+    // Peers this peer has heard about from us over PEX (BEP 11)
+    #[cfg(feature = "pex")]
+    pex_sent: HashSet<SocketAddr>,
 
     global_dl_bucket: Arc<Mutex<TokenBucket>>,
     global_ul_bucket: Arc<Mutex<TokenBucket>>,
+    bandwidth_priority: watch::Receiver<BandwidthPriority>,
 
     shutdown_tx: broadcast::Sender<()>,
 }
@@ -120,13 +163,19 @@ impl PeerSession {
         Self {
             info_hash: params.info_hash,
             peer_session_established: false,
-            torrent_metadata_length: params.torrent_metadata_length,
+            torrent_metadata: params.torrent_metadata,
             connection_type: params.connection_type,
             torrent_manager_rx: params.torrent_manager_rx,
             torrent_manager_tx: params.torrent_manager_tx,
             client_id: params.client_id,
             peer_ip_port: params.peer_ip_port,
-            writer_rx,
+            peer_supports_fast: false,
+            lazy_bitfield: params.lazy_bitfield,
+            upload_only: params.upload_only,
+            pex_allowed: params.pex_allowed,
+            timeouts: params.timeouts,
+            lazy_have_task: None,
+            writer_rx: Some(writer_rx),
             writer_tx,
             block_tracker: HashMap::new(),
             block_request_buffer: Vec::new(),
@@ -138,34 +187,42 @@ impl PeerSession {
             peer_extended_handshake_payload: None,
             peer_torrent_metadata_piece_count: 0,
             peer_torrent_metadata_pieces: Vec::new(),
+            #[cfg(feature = "pex")]
+            pex_sent: HashSet::new(),
             global_dl_bucket: params.global_dl_bucket,
             global_ul_bucket: params.global_ul_bucket,
+            bandwidth_priority: params.bandwidth_priority,
             shutdown_tx: params.shutdown_tx,
         }
     }
 
-    #[instrument(skip(self, current_bitfield))]
+    #[instrument(skip(self, stream, current_bitfield))]
     pub async fn run(
         mut self,
-        stream: TcpStream,
+        stream: BoxedPeerStream,
         handshake_response: Vec<u8>,
-        current_bitfield: Option<Vec<u8>>,
+        current_bitfield: Option<Bitfield>,
     ) -> Result<(), Box<dyn StdError + Send + Sync>> {
         let _guard = DisconnectGuard {
             peer_ip_port: self.peer_ip_port.clone(),
             manager_tx: self.torrent_manager_tx.clone(),
         };
 
-        let (mut stream_read_half, stream_write_half) = stream.into_split();
+        let writer_rx = self
+            .writer_rx
+            .take()
+            .ok_or("Peer session was already started")?;
+        let (mut stream_read_half, stream_write_half) = tokio::io::split(stream);
         let (error_tx, mut error_rx) = oneshot::channel();
 
         let global_ul_bucket_clone = self.global_ul_bucket.clone();
         let writer_shutdown_rx = self.shutdown_tx.subscribe();
         let writer_handle = tokio::spawn(writer_task(
             stream_write_half,
-            self.writer_rx,
+            writer_rx,
             error_tx,
             global_ul_bucket_clone,
+            self.bandwidth_priority.clone(),
             writer_shutdown_rx,
         ));
         let _writer_abort_guard = AbortOnDrop(writer_handle);
@@ -178,7 +235,12 @@ impl PeerSession {
                 ));
 
                 let mut buffer = vec![0u8; 68];
-                stream_read_half.read_exact(&mut buffer).await?;
+                timeout(
+                    self.timeouts.handshake,
+                    stream_read_half.read_exact(&mut buffer),
+                )
+                .await
+                .map_err(|_| "Peer handshake timed out")??;
                 buffer
             }
             ConnectionType::Incoming => {
@@ -208,21 +270,20 @@ impl PeerSession {
             .try_send(TorrentCommand::PeerId(self.peer_ip_port.clone(), peer_id));
 
         let reserved_bytes = &handshake_response[20..28];
-        const EXTENSION_FLAG: u8 = 0x10;
-        let peer_supports_extended = (reserved_bytes[5] & EXTENSION_FLAG) != 0;
+        let peer_supports_extended = (reserved_bytes[5] & EXTENSION_PROTOCOL_FLAG) != 0;
+        self.peer_supports_fast = (reserved_bytes[7] & FAST_EXTENSION_FLAG) != 0;
         if peer_supports_extended {
-            let mut torrent_metadata_len = None;
-            if let Some(torrent_metadata_length) = self.torrent_metadata_length {
-                torrent_metadata_len = Some(torrent_metadata_length);
-            }
-            let _ = self
-                .writer_tx
-                .try_send(Message::ExtendedHandshake(torrent_metadata_len));
+            let metadata_size = self.torrent_metadata.as_ref().map(|m| m.len() as i64);
+            let _ = self.writer_tx.try_send(Message::ExtendedHandshake(
+                metadata_size,
+                self.upload_only,
+                self.pex_allowed,
+            ));
         }
 
         if let Some(bitfield) = current_bitfield {
             self.peer_session_established = true;
-            let _ = self.writer_tx.try_send(Message::Bitfield(bitfield.clone()));
+            self.send_bitfield(bitfield);
             let _ = self
                 .torrent_manager_tx
                 .try_send(TorrentCommand::SuccessfullyConnected(
@@ -230,9 +291,9 @@ impl PeerSession {
                 ));
         }
 
-        let mut keep_alive_timer = tokio::time::interval(Duration::from_secs(60));
+        let mut keep_alive_timer = tokio::time::interval(self.timeouts.keepalive);
 
-        let inactivity_timeout = tokio::time::sleep(Duration::from_secs(120));
+        let inactivity_timeout = tokio::time::sleep(self.timeouts.inactivity);
         tokio::pin!(inactivity_timeout);
 
         let _result: Result<(), Box<dyn StdError + Send + Sync>> = 'session: loop {
@@ -253,7 +314,7 @@ impl PeerSession {
 
                 Ok(message_from_peer) = timeout(READ_TIMEOUT, parse_message(&mut stream_read_half)) => {
                     if let Ok(ref message) = message_from_peer {
-                        inactivity_timeout.as_mut().reset(Instant::now() + Duration::from_secs(120));
+                        inactivity_timeout.as_mut().reset(Instant::now() + self.timeouts.inactivity);
                         match message {
                             Message::KeepAlive => {
                                 event!(Level::TRACE, ?message);
@@ -270,6 +331,45 @@ impl PeerSession {
                             let _ = self.torrent_manager_tx
                                 .try_send(TorrentCommand::PeerBitfield(self.peer_ip_port.clone(), value));
                         }
+                        Ok(Message::HaveAll) => {
+                            let _ = self.torrent_manager_tx
+                                .try_send(TorrentCommand::PeerHaveAll(self.peer_ip_port.clone()));
+                        }
+                        Ok(Message::HaveNone) => {
+                            let _ = self.torrent_manager_tx
+                                .try_send(TorrentCommand::PeerBitfield(self.peer_ip_port.clone(), Vec::new()));
+                        }
+                        Ok(Message::SuggestPiece(piece_index)) => {
+                            let _ = self.torrent_manager_tx
+                                .try_send(TorrentCommand::SuggestPiece(self.peer_ip_port.clone(), piece_index));
+                        }
+                        Ok(Message::AllowedFast(piece_index)) => {
+                            let _ = self.torrent_manager_tx
+                                .try_send(TorrentCommand::AllowedFast(self.peer_ip_port.clone(), piece_index));
+                        }
+                        Ok(Message::RejectRequest(piece_index, block_offset, block_length)) => {
+                            // Blocks we cancelled are no longer tracked, so their rejects, which
+                            // BEP 6 peers send for every cancel, are ignored.
+                            let block = BlockInfo {
+                                piece_index,
+                                offset: block_offset,
+                                length: block_length,
+                            };
+                            if let Entry::Occupied(mut entry) = self.block_tracker.entry(piece_index) {
+                                if entry.get_mut().remove(&block) {
+                                    if self.block_request_limit_semaphore.available_permits() < PEER_BLOCK_IN_FLIGHT_LIMIT {
+                                        self.block_request_limit_semaphore.add_permits(1);
+                                    }
+                                    // This peer can't finish the piece, so the manager passes it on.
+                                    // Blocks of it still on their way are kept.
+                                    let _ = self.torrent_manager_tx
+                                        .try_send(TorrentCommand::RequestRejected(self.peer_ip_port.clone(), piece_index));
+                                }
+                                if entry.get().is_empty() {
+                                    entry.remove();
+                                }
+                            }
+                        }
                         Ok(Message::NotInterested) => {}
                         Ok(Message::Interested) => {
                                 let _ =
@@ -308,8 +408,9 @@ impl PeerSession {
                             let torrent_manager_tx_clone = self.torrent_manager_tx.clone();
                             let _block_request_buffer_clone = self.block_request_buffer.clone();
                             let global_dl_bucket_clone = self.global_dl_bucket.clone();
+                            let priority = *self.bandwidth_priority.borrow();
                             self.block_request_joinset.spawn(async move {
-                                consume_tokens(&global_dl_bucket_clone, block_data.len() as f64).await;
+                                consume_tokens(&global_dl_bucket_clone, block_data.len() as f64, priority).await;
                                 let _ = torrent_manager_tx_clone
                                     .send(TorrentCommand::Block(peer_ip_port_clone, piece_index, block_offset, block_data))
                                     .await;
@@ -333,13 +434,21 @@ impl PeerSession {
                                 if let Ok(handshake_data) = serde_bencode::from_bytes::<ExtendedHandshakePayload>(&payload) {
 
                                     self.peer_extended_id_mappings = handshake_data.m.clone();
+                                    if let Some(our_ip) = handshake_data.your_ip() {
+                                        let _ = self.torrent_manager_tx
+                                            .try_send(TorrentCommand::PeerReportedIp(self.peer_ip_port.clone(), our_ip));
+                                    }
+                                    if self.peer_extended_id(ClientExtendedId::UtHolepunch).is_some() {
+                                        let _ = self.torrent_manager_tx
+                                            .try_send(TorrentCommand::PeerHolepunchExtension(self.peer_ip_port.clone()));
+                                    }
 
                                     if !handshake_data.m.is_empty() {
                                         self.peer_extended_handshake_payload = Some(handshake_data.clone());
                                         if !self.peer_session_established {
                                             if let Some(_torrent_metadata_len) = handshake_data.metadata_size {
                                                 let request = MetadataMessage {
-                                                    msg_type: 0,
+                                                    msg_type: METADATA_REQUEST,
                                                     piece: 0,
                                                     total_size: None,
                                                 };
@@ -362,29 +471,62 @@ impl PeerSession {
                             {
                                 if extended_id == ClientExtendedId::UtPex.id() {
                                     if let Ok(pex_data) = serde_bencode::from_bytes::<PexMessage>(&payload) {
-                                        let mut new_peers = Vec::new();
-                                        for chunk in pex_data.added.chunks_exact(6) {
-                                            let ip = Ipv4Addr::new(chunk[0], chunk[1], chunk[2], chunk[3]);
-                                            let port = u16::from_be_bytes([chunk[4], chunk[5]]);
-                                            new_peers.push((ip.to_string(), port));
-                                        }
+                                        let new_peers = pex_data.added_peers();
                                         if !new_peers.is_empty() {
                                                 let _ = self.torrent_manager_tx
                                                     .try_send(TorrentCommand::AddPexPeers(self.peer_ip_port.clone(), new_peers));
                                         }
+                                        let dropped_peers = pex_data.dropped_peers();
+                                        if !dropped_peers.is_empty() {
+                                                let _ = self.torrent_manager_tx
+                                                    .try_send(TorrentCommand::DropPexPeers(self.peer_ip_port.clone(), dropped_peers));
+                                        }
                                     }
                                 }
                             }
-                            if extended_id == ClientExtendedId::UtMetadata.id()
-                                && !self.peer_session_established {
-                                    if let Some(ref handshake_data) = self.peer_extended_handshake_payload {
-                                        if let Some(torrent_metadata_len) = handshake_data.metadata_size {
+                            if extended_id == ClientExtendedId::UtHolepunch.id() {
+                                match HolepunchMessage::from_bytes(&payload) {
+                                    Some(HolepunchMessage::Rendezvous(target)) => {
+                                        let _ = self.torrent_manager_tx
+                                            .try_send(TorrentCommand::HolepunchRendezvous(self.peer_ip_port.clone(), target));
+                                    }
+                                    Some(HolepunchMessage::Connect(peer_addr)) => {
+                                        let _ = self.torrent_manager_tx
+                                            .try_send(TorrentCommand::HolepunchConnect(peer_addr));
+                                    }
+                                    Some(HolepunchMessage::Error(target, error)) => {
+                                        event!(Level::DEBUG, peer = %self.peer_ip_port, %target, ?error, "Holepunch relay failed.");
+                                    }
+                                    None => {
+                                        event!(Level::DEBUG, peer = %self.peer_ip_port, "Ignoring malformed holepunch message.");
+                                    }
+                                }
+                            }
+                            if extended_id == ClientExtendedId::UtMetadata.id() {
+                                let Some((header, metadata_binary)) = split_metadata_message(&payload) else {
+                                    break 'session Err("Peer sent a malformed metadata message".into());
+                                };
+                                match header.msg_type {
+                                    METADATA_REQUEST => self.answer_metadata_request(header.piece),
+                                    METADATA_REJECT if !self.peer_session_established => {
+                                        let _ = self.torrent_manager_tx.try_send(TorrentCommand::MetadataRejected(self.peer_ip_port.clone()));
+                                        break 'session Err("Peer rejected our metadata request".into());
+                                    }
+                                    METADATA_DATA if !self.peer_session_established => {
+                                        if let Some(torrent_metadata_len) = self.peer_extended_handshake_payload.as_ref().and_then(|h| h.metadata_size) {
                                             let torrent_metadata_len_usize = torrent_metadata_len as usize;
 
-                                            let current_offset = self.peer_torrent_metadata_piece_count * 16384;
-                                            let expected_data_len = std::cmp::min(16384, torrent_metadata_len_usize - current_offset);
-                                            let header_len = payload.len() - expected_data_len;
-                                            let metadata_binary = &payload[header_len..];
+                                            let current_offset = self.peer_torrent_metadata_piece_count * METADATA_PIECE_SIZE;
+                                            // A piece that doesn't fit the advertised size is bad data, not a reason to panic.
+                                            let expected_data_len = torrent_metadata_len_usize
+                                                .checked_sub(current_offset)
+                                                .map(|remaining| remaining.min(METADATA_PIECE_SIZE));
+                                            if header.piece != self.peer_torrent_metadata_piece_count
+                                                || expected_data_len != Some(metadata_binary.len())
+                                            {
+                                                let _ = self.torrent_manager_tx.try_send(TorrentCommand::MetadataRejected(self.peer_ip_port.clone()));
+                                                break 'session Err("Peer sent a malformed metadata piece".into());
+                                            }
                                             self.peer_torrent_metadata_pieces.extend(metadata_binary);
 
                                             if torrent_metadata_len_usize == self.peer_torrent_metadata_pieces.len() {
@@ -395,7 +537,8 @@ impl PeerSession {
                                                     Ok(dht_info) => {
                                                             let _ = self.torrent_manager_tx
                                                                 .try_send(TorrentCommand::DhtTorrent(
-                                                                    Torrent {
+                                                                    self.peer_ip_port.clone(),
+                                                                    Box::new(Torrent {
                                                                         info_dict_bencode: self.peer_torrent_metadata_pieces.clone(),
                                                                         info: dht_info,
                                                                         announce: None,
@@ -403,13 +546,16 @@ impl PeerSession {
                                                                         creation_date: None,
                                                                         comment: None,
                                                                         created_by: None,
-                                                                        encoding: None
-                                                                    },
-                                                                    torrent_metadata_len
+                                                                        encoding: None,
+                                                                        httpseeds: None,
+                                                                        piece_layers: None,
+                                                                        v2_pieces: Vec::new()
+                                                                    }),
                                                                 ));
                                                     }
                                                     Err(e) => {
                                                         event!(Level::WARN, "Failed to decode torrent metadata from peer: {}", e);
+                                                        let _ = self.torrent_manager_tx.try_send(TorrentCommand::MetadataRejected(self.peer_ip_port.clone()));
                                                         return Err("Peer sent invalid torrent metadata".into());
                                                     }
                                                 }
@@ -417,7 +563,7 @@ impl PeerSession {
                                             } else {
                                                 self.peer_torrent_metadata_piece_count += 1;
                                                 let request = MetadataMessage {
-                                                    msg_type: 0,
+                                                    msg_type: METADATA_REQUEST,
                                                     piece: self.peer_torrent_metadata_piece_count,
                                                     total_size: None,
                                                 };
@@ -434,7 +580,9 @@ impl PeerSession {
                                             }
                                         }
                                     }
+                                    _ => {}
                                 }
+                            }
                         }
                         Ok(Message::KeepAlive) => {
                             event!(Level::TRACE, "PEER KEEP SENT KEEP ALIVE.");
@@ -452,49 +600,33 @@ impl PeerSession {
                 Some(command) = self.torrent_manager_rx.recv() => {
                     event!(Level::TRACE, ?command);
                     match command {
-                        TorrentCommand::ClientBitfield(bitfield, torrent_metadata_length) => {
+                        TorrentCommand::ClientBitfield(bitfield, torrent_metadata) => {
                             self.peer_session_established = true;
-                            self.torrent_metadata_length = torrent_metadata_length;
+                            self.torrent_metadata = torrent_metadata;
 
-                                let _ = self.writer_tx.try_send(Message::Bitfield(bitfield));
+                                self.send_bitfield(bitfield);
                                 let _ = self.torrent_manager_tx
                                     .try_send(TorrentCommand::SuccessfullyConnected(self.peer_ip_port.clone()));
                         }
                         #[cfg(feature = "pex")]
                         TorrentCommand::SendPexPeers(peers_list) => {
-                            if let Some(pex_id) = self.peer_extended_id_mappings.get(ClientExtendedId::UtPex.as_str()).copied() {
-                                let pex_list_for_this_peer: Vec<u8> = peers_list.iter()
-                                    .filter(|&peer_ip| *peer_ip != self.peer_ip_port)
-                                    .filter_map(|ip_port| ip_port.parse::<std::net::SocketAddr>().ok())
-                                    .filter_map(|addr| {
-                                        if let std::net::SocketAddr::V4(v4_addr) = addr {
-                                            let mut peer_bytes = Vec::with_capacity(6);
-                                            peer_bytes.extend_from_slice(&v4_addr.ip().octets());
-                                            peer_bytes.extend_from_slice(&v4_addr.port().to_be_bytes());
-                                            Some(peer_bytes)
-                                        } else {
-                                            None
-                                        }
-                                    })
-                                    .flatten()
-                                    .collect();
-
-                                if pex_list_for_this_peer.is_empty() {
+                            if let Some(pex_id) = self.peer_extended_id(ClientExtendedId::UtPex) {
+                                let pex_message = self.next_pex_message(peers_list);
+                                if pex_message.is_empty() {
                                     continue;
                                 }
-
-                                let pex_message = PexMessage {
-                                    added: pex_list_for_this_peer,
-                                    ..Default::default()
-                                };
-
                                 if let Ok(bencoded_payload) = serde_bencode::to_bytes(&pex_message) {
                                         let _ = self.writer_tx.try_send(
                                             Message::Extended(pex_id, bencoded_payload)
                                         );
                                 }
                             }
-
+                        }
+                        TorrentCommand::SendHolepunch(message) => {
+                            if let Some(holepunch_id) = self.peer_extended_id(ClientExtendedId::UtHolepunch) {
+                                let _ = self.writer_tx
+                                    .try_send(Message::Extended(holepunch_id, message.to_bytes()));
+                            }
                         }
                         TorrentCommand::PeerUnchoke => {
                                 let _ = self.writer_tx
@@ -515,6 +647,15 @@ impl PeerSession {
                                 let _ = self.writer_tx
                                     .try_send(Message::NotInterested);
                         }
+                        TorrentCommand::UploadOnly(upload_only) => {
+                            // BEP 21 lets the extended handshake be sent again to update it
+                            if self.upload_only != upload_only && self.peer_extended_handshake_payload.is_some() {
+                                let metadata_size = self.torrent_metadata.as_ref().map(|m| m.len() as i64);
+                                let _ = self.writer_tx
+                                    .try_send(Message::ExtendedHandshake(metadata_size, upload_only, self.pex_allowed));
+                            }
+                            self.upload_only = upload_only;
+                        }
                         TorrentCommand::Cancel(piece_index) => {
                             if let Some(blocks) = self.block_tracker.remove(&piece_index) {
                                 for block in blocks {
@@ -539,17 +680,37 @@ impl PeerSession {
                                 let _ = self.writer_tx
                                     .try_send(Message::Have(piece_index));
                         }
-                        TorrentCommand::RequestDownload(piece_index, piece_length, torrent_size) => {
+                        TorrentCommand::CancelBlock(piece_index, block_offset, block_length) => {
+                            let block = BlockInfo {
+                                piece_index,
+                                offset: block_offset,
+                                length: block_length,
+                            };
+                            if let Entry::Occupied(mut entry) = self.block_tracker.entry(piece_index) {
+                                if entry.get_mut().remove(&block) {
+                                    if self.block_request_limit_semaphore.available_permits() < PEER_BLOCK_IN_FLIGHT_LIMIT {
+                                        self.block_request_limit_semaphore.add_permits(1);
+                                    }
+                                    let _ = self.writer_tx
+                                        .try_send(Message::Cancel(piece_index, block_offset, block_length));
+                                }
+                                if entry.get().is_empty() {
+                                    entry.remove();
+                                }
+                            }
+                        }
+                        TorrentCommand::RequestDownload(piece_index, piece_length, torrent_size, held_offsets) => {
                             let piece_start = piece_index as u64 * piece_length as u64;
                             let remaining_bytes = (torrent_size as u64).saturating_sub(piece_start);
                             let piece_size = std::cmp::min(piece_length as u64, remaining_bytes) as u32;
 
                             self.block_request_buffer = vec![0; piece_size as usize];
 
-                            let blocks = calculate_blocks_for_piece(
+                            let mut blocks = calculate_blocks_for_piece(
                                 piece_index,
                                 piece_size
                             );
+                            blocks.retain(|block| !held_offsets.contains(&block.offset));
                             self.block_tracker.insert(piece_index, blocks.clone());
                             self.block_requests_remaining = blocks.len();
                             for block in blocks.into_iter() {
@@ -581,6 +742,12 @@ impl PeerSession {
                                 });
                             }
                         }
+                        TorrentCommand::RejectUpload(piece_index, block_offset, block_length) => {
+                            if self.peer_supports_fast {
+                                let _ = self.writer_tx
+                                    .try_send(Message::RejectRequest(piece_index, block_offset, block_length));
+                            }
+                        }
                         TorrentCommand::Upload(piece_index, block_offset, block_data) => {
                             let writer_tx_clone = self.writer_tx.clone();
                             let _semaphore_clone = self.block_upload_limit_semaphore.clone();
@@ -617,4 +784,135 @@ impl PeerSession {
 
         Ok(())
     }
+
+    /// Sends the pieces we have. Fast extension peers get have-all or have-none when
+    /// that says the same thing, then the pieces they may fetch while we choke them.
+    /// With `lazy_bitfield` a few pieces are left out and trickled in as HAVEs, and
+    /// have-all isn't used, so a seed's first message looks like a leecher's.
+    fn send_bitfield(&mut self, bitfield: Bitfield) {
+        let held_pieces = if self.lazy_bitfield {
+            let have: Vec<usize> = (0..bitfield.len()).filter(|&i| bitfield.has(i)).collect();
+            have.choose_multiple(&mut rand::rng(), LAZY_BITFIELD_HELD_PIECES)
+                .copied()
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let message = if self.peer_supports_fast && bitfield.count_ones() == 0 {
+            Message::HaveNone
+        } else if self.peer_supports_fast && bitfield.all() && held_pieces.is_empty() {
+            Message::HaveAll
+        } else {
+            let mut sent_bitfield = bitfield.clone();
+            for &piece_index in &held_pieces {
+                sent_bitfield.set(piece_index, false);
+            }
+            Message::Bitfield(sent_bitfield.as_bytes().to_vec())
+        };
+        let _ = self.writer_tx.try_send(message);
+
+        if !held_pieces.is_empty() {
+            let mut rng = rand::rng();
+            let spaced_haves: Vec<(u32, Duration)> = held_pieces
+                .iter()
+                .map(|&piece_index| {
+                    let spacing = rng.random_range(LAZY_HAVE_SPACING_MS);
+                    (piece_index as u32, Duration::from_millis(spacing))
+                })
+                .collect();
+            let writer_tx = self.writer_tx.clone();
+            self.lazy_have_task = Some(AbortOnDrop(tokio::spawn(async move {
+                for (piece_index, spacing) in spaced_haves {
+                    tokio::time::sleep(spacing).await;
+                    if writer_tx.send(Message::Have(piece_index)).await.is_err() {
+                        break;
+                    }
+                }
+            })));
+        }
+
+        if !self.peer_supports_fast {
+            return;
+        }
+        let peer_ip = self
+            .peer_ip_port
+            .parse::<SocketAddr>()
+            .map(|addr| addr.ip().to_canonical());
+        let allowed_fast = match peer_ip {
+            Ok(IpAddr::V4(ip)) => allowed_fast_set(
+                &self.info_hash,
+                ip,
+                bitfield.len() as u32,
+                ALLOWED_FAST_SET_SIZE,
+            ),
+            _ => Vec::new(),
+        };
+        for &piece_index in &allowed_fast {
+            let _ = self.writer_tx.try_send(Message::AllowedFast(piece_index));
+        }
+        let _ = self
+            .torrent_manager_tx
+            .try_send(TorrentCommand::PeerFastExtension(
+                self.peer_ip_port.clone(),
+                allowed_fast,
+            ));
+    }
+
+    /// The changes since the last ut_pex message to this peer: peers it hasn't heard
+    /// about from us yet, and ones we told it about that are gone.
+    #[cfg(feature = "pex")]
+    fn next_pex_message(&mut self, peers: Vec<PexPeer>) -> PexMessage {
+        let current: HashMap<SocketAddr, PexPeer> = peers
+            .into_iter()
+            .filter(|peer| peer.addr.to_string() != self.peer_ip_port)
+            .map(|peer| (peer.addr, peer))
+            .collect();
+
+        let added: Vec<PexPeer> = current
+            .values()
+            .filter(|peer| !self.pex_sent.contains(&peer.addr))
+            .take(MAX_PEX_PEERS_PER_MESSAGE)
+            .copied()
+            .collect();
+        let dropped: Vec<SocketAddr> = self
+            .pex_sent
+            .iter()
+            .filter(|addr| !current.contains_key(addr))
+            .take(MAX_PEX_PEERS_PER_MESSAGE)
+            .copied()
+            .collect();
+
+        self.pex_sent.extend(added.iter().map(|peer| peer.addr));
+        for addr in &dropped {
+            self.pex_sent.remove(addr);
+        }
+        PexMessage::new(&added, &dropped)
+    }
+
+    /// The id the peer wants `extension` messages sent with, if it supports it.
+    fn peer_extended_id(&self, extension: ClientExtendedId) -> Option<u8> {
+        self.peer_extended_id_mappings
+            .get(extension.as_str())
+            .copied()
+            .filter(|&id| id != 0)
+    }
+
+    /// Answers a ut_metadata request with that slice of the info dict, or a reject
+    /// while we are still fetching the metadata ourselves.
+    fn answer_metadata_request(&self, piece: usize) {
+        let Some(ut_metadata_id) = self.peer_extended_id(ClientExtendedId::UtMetadata) else {
+            return;
+        };
+        match metadata_reply(self.torrent_metadata.as_deref().map(Vec::as_slice), piece) {
+            Ok(reply) => {
+                let _ = self
+                    .writer_tx
+                    .try_send(Message::Extended(ut_metadata_id, reply));
+            }
+            Err(e) => {
+                event!(Level::ERROR, "Failed to serialize metadata reply: {}", e);
+            }
+        }
+    }
 }
@@ -0,0 +1,913 @@
+// SPDX-FileCopyrightText: 2025 The superseedr Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep_until, timeout, Duration, Instant};
+
+use tracing::{event, Level};
+
+/// A uTP connection as seen by a peer session: a byte stream like a TCP socket.
+/// A task per connection moves bytes between it and the UDP socket.
+pub type UtpStream = DuplexStream;
+
+const HEADER_LEN: usize = 20;
+const VERSION: u8 = 1;
+// Payload bytes per packet, small enough to avoid IP fragmentation on most paths
+const MAX_PAYLOAD: usize = 1200;
+// Bytes buffered each way between a connection task and its peer session
+const STREAM_BUFFER: usize = 256 * 1024;
+// Most received bytes held for the session before the peer has to wait
+const RECEIVE_WINDOW: usize = 1024 * 1024;
+// Out-of-order packets further ahead than this are dropped
+const MAX_REORDER_PACKETS: u16 = 1024;
+const PACKET_QUEUE: usize = 1024;
+
+// LEDBAT congestion control: grow the send window while our packets spend less
+// than the target time queued on the path, and shrink it when they spend more.
+const TARGET_DELAY_MICROS: f64 = 100_000.0;
+const MAX_WINDOW_GAIN: f64 = 3000.0;
+const MIN_WINDOW: f64 = (2 * MAX_PAYLOAD) as f64;
+const MAX_WINDOW: f64 = RECEIVE_WINDOW as f64;
+// The lowest delay seen over this long is taken as the path's base delay
+const BASE_DELAY_PERIOD: Duration = Duration::from_secs(60);
+
+const SYN_TIMEOUT: Duration = Duration::from_secs(1);
+const SYN_ATTEMPTS: u32 = 3;
+const MIN_TIMEOUT: Duration = Duration::from_millis(500);
+const MAX_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_TRANSMISSIONS: u32 = 6;
+const DUPLICATE_ACKS_BEFORE_RESEND: u32 = 3;
+const IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PacketType {
+    Data,
+    Fin,
+    State,
+    Reset,
+    Syn,
+}
+
+impl PacketType {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Data),
+            1 => Some(Self::Fin),
+            2 => Some(Self::State),
+            3 => Some(Self::Reset),
+            4 => Some(Self::Syn),
+            _ => None,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            Self::Data => 0,
+            Self::Fin => 1,
+            Self::State => 2,
+            Self::Reset => 3,
+            Self::Syn => 4,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Packet {
+    packet_type: PacketType,
+    connection_id: u16,
+    timestamp_micros: u32,
+    timestamp_difference_micros: u32,
+    window_size: u32,
+    seq_nr: u16,
+    ack_nr: u16,
+    payload: Vec<u8>,
+}
+
+impl Packet {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.payload.len());
+        bytes.push((self.packet_type.as_u8() << 4) | VERSION);
+        bytes.push(0); // No extensions
+        bytes.extend_from_slice(&self.connection_id.to_be_bytes());
+        bytes.extend_from_slice(&self.timestamp_micros.to_be_bytes());
+        bytes.extend_from_slice(&self.timestamp_difference_micros.to_be_bytes());
+        bytes.extend_from_slice(&self.window_size.to_be_bytes());
+        bytes.extend_from_slice(&self.seq_nr.to_be_bytes());
+        bytes.extend_from_slice(&self.ack_nr.to_be_bytes());
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < HEADER_LEN || bytes[0] & 0x0f != VERSION {
+            return None;
+        }
+        let packet_type = PacketType::from_u8(bytes[0] >> 4)?;
+
+        // Skip extensions such as selective acks; only cumulative acks are used.
+        let mut extension = bytes[1];
+        let mut offset = HEADER_LEN;
+        while extension != 0 {
+            let header = bytes.get(offset..offset + 2)?;
+            extension = header[0];
+            offset += 2 + header[1] as usize;
+        }
+        let payload = bytes.get(offset..)?.to_vec();
+
+        let u16_at = |i: usize| u16::from_be_bytes([bytes[i], bytes[i + 1]]);
+        let u32_at =
+            |i: usize| u32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        Some(Self {
+            packet_type,
+            connection_id: u16_at(2),
+            timestamp_micros: u32_at(4),
+            timestamp_difference_micros: u32_at(8),
+            window_size: u32_at(12),
+            seq_nr: u16_at(16),
+            ack_nr: u16_at(18),
+            payload,
+        })
+    }
+}
+
+/// Microseconds on a clock shared by every connection. Only differences between
+/// readings matter, so it wraps freely.
+fn now_micros() -> u32 {
+    static EPOCH: OnceLock<std::time::Instant> = OnceLock::new();
+    EPOCH
+        .get_or_init(std::time::Instant::now)
+        .elapsed()
+        .as_micros() as u32
+}
+
+/// Whether sequence number `a` is `b` or comes before it, allowing for wrap-around.
+fn seq_le(a: u16, b: u16) -> bool {
+    b.wrapping_sub(a) < 0x8000
+}
+
+type ConnectionMap = Arc<Mutex<HashMap<(SocketAddr, u16), mpsc::Sender<Packet>>>>;
+
+/// A UDP socket carrying uTP (BEP 29) connections, both the ones we open and the
+/// ones peers open to us. Clones share the socket, which closes with the last one.
+#[derive(Clone, Debug)]
+pub struct UtpSocket {
+    inner: Arc<SocketInner>,
+}
+
+#[derive(Debug)]
+struct SocketInner {
+    socket: Arc<UdpSocket>,
+    connections: ConnectionMap,
+    receive_task: JoinHandle<()>,
+}
+
+impl Drop for SocketInner {
+    fn drop(&mut self) {
+        self.receive_task.abort();
+    }
+}
+
+impl UtpSocket {
+    /// Binds `addr`. Connections peers open to us arrive on the returned receiver.
+    pub async fn bind(
+        addr: SocketAddr,
+    ) -> io::Result<(Self, mpsc::Receiver<(UtpStream, SocketAddr)>)> {
+        let socket = Arc::new(UdpSocket::bind(addr).await?);
+        let connections = ConnectionMap::default();
+        let (incoming_tx, incoming_rx) = mpsc::channel(64);
+        let receive_task = tokio::spawn(receive_loop(
+            socket.clone(),
+            connections.clone(),
+            incoming_tx,
+        ));
+
+        let inner = SocketInner {
+            socket,
+            connections,
+            receive_task,
+        };
+        Ok((
+            Self {
+                inner: Arc::new(inner),
+            },
+            incoming_rx,
+        ))
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.socket.local_addr()
+    }
+
+    /// Opens a connection to `addr`, failing if the peer doesn't answer.
+    pub async fn connect(&self, addr: SocketAddr) -> io::Result<UtpStream> {
+        let (packet_tx, packet_rx) = mpsc::channel(PACKET_QUEUE);
+        let recv_id = {
+            let mut connections = self
+                .inner
+                .connections
+                .lock()
+                .map_err(|_| io::Error::other("uTP connection table poisoned"))?;
+            let mut recv_id = rand::random::<u16>();
+            while connections.contains_key(&(addr, recv_id)) {
+                recv_id = rand::random();
+            }
+            connections.insert((addr, recv_id), packet_tx);
+            recv_id
+        };
+
+        let (stream, connection_side) = tokio::io::duplex(STREAM_BUFFER);
+        let (connected_tx, connected_rx) = oneshot::channel();
+        let connection = Connection::new(
+            self.inner.socket.clone(),
+            self.inner.connections.clone(),
+            addr,
+            recv_id,
+            recv_id.wrapping_add(1),
+        );
+        tokio::spawn(connection.run_outgoing(connection_side, packet_rx, connected_tx));
+
+        match connected_rx.await {
+            Ok(Ok(())) => Ok(stream),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(io::ErrorKind::ConnectionAborted.into()),
+        }
+    }
+}
+
+/// Reads every datagram on the socket and hands it to its connection, starting a
+/// new connection for each SYN.
+async fn receive_loop(
+    socket: Arc<UdpSocket>,
+    connections: ConnectionMap,
+    incoming_tx: mpsc::Sender<(UtpStream, SocketAddr)>,
+) {
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let (len, addr) = match socket.recv_from(&mut buffer).await {
+            Ok(received) => received,
+            Err(e) => {
+                // Windows reports ICMP port unreachable replies as receive errors.
+                event!(Level::TRACE, "uTP receive error: {}", e);
+                continue;
+            }
+        };
+        let Some(packet) = Packet::decode(&buffer[..len]) else {
+            continue;
+        };
+
+        // The connection ID we receive on: the SYN's plus one, as the initiator
+        // sends everything after the SYN with that.
+        let recv_id = if packet.packet_type == PacketType::Syn {
+            packet.connection_id.wrapping_add(1)
+        } else {
+            packet.connection_id
+        };
+        let Ok(mut connections_guard) = connections.lock() else {
+            return;
+        };
+        if let Some(packet_tx) = connections_guard.get(&(addr, recv_id)) {
+            // A full queue drops the packet like a congested link would.
+            let _ = packet_tx.try_send(packet);
+            continue;
+        }
+        if packet.packet_type != PacketType::Syn || incoming_tx.is_closed() {
+            continue;
+        }
+
+        let (packet_tx, packet_rx) = mpsc::channel(PACKET_QUEUE);
+        connections_guard.insert((addr, recv_id), packet_tx);
+        drop(connections_guard);
+
+        let (stream, connection_side) = tokio::io::duplex(STREAM_BUFFER);
+        let connection = Connection::new(
+            socket.clone(),
+            connections.clone(),
+            addr,
+            recv_id,
+            packet.connection_id,
+        );
+        tokio::spawn(connection.run_incoming(connection_side, packet_rx, packet));
+        // If nobody takes the stream, dropping it closes the connection.
+        let _ = incoming_tx.try_send((stream, addr));
+    }
+}
+
+struct SentPacket {
+    packet: Packet,
+    sent_at: Instant,
+    transmissions: u32,
+}
+
+/// The lowest one-way delay seen recently, which the delay of each packet is
+/// measured against. Kept per minute so a route change is picked up.
+struct BaseDelay {
+    minimums: [u32; 2],
+    period_started: Instant,
+}
+
+impl BaseDelay {
+    fn new() -> Self {
+        Self {
+            minimums: [u32::MAX; 2],
+            period_started: Instant::now(),
+        }
+    }
+
+    /// Records `sample` and returns how far above the base delay it is.
+    fn queuing_delay(&mut self, sample: u32) -> u32 {
+        if self.period_started.elapsed() >= BASE_DELAY_PERIOD {
+            self.minimums = [self.minimums[1], u32::MAX];
+            self.period_started = Instant::now();
+        }
+        self.minimums[1] = self.minimums[1].min(sample);
+        let base = self.minimums[0].min(self.minimums[1]);
+        sample.saturating_sub(base)
+    }
+}
+
+struct Connection {
+    socket: Arc<UdpSocket>,
+    connections: ConnectionMap,
+    peer: SocketAddr,
+    recv_id: u16,
+    send_id: u16,
+
+    // Next sequence number we send, and the last one received in order
+    seq_nr: u16,
+    ack_nr: u16,
+    ack_pending: bool,
+    last_ack_received: u16,
+    duplicate_acks: u32,
+
+    in_flight: VecDeque<SentPacket>,
+    bytes_in_flight: usize,
+    window: f64,
+    peer_window: usize,
+    base_delay: BaseDelay,
+    // Our measure of the peer's last packet delay, echoed back to it
+    reply_micros: u32,
+    rtt: Option<(Duration, Duration)>,
+    timeout: Duration,
+
+    reordered: HashMap<u16, Vec<u8>>,
+    reordered_bytes: usize,
+    to_session: VecDeque<u8>,
+    peer_fin: Option<u16>,
+    // Everything up to the peer's FIN has arrived
+    peer_finished: bool,
+    fin_sent: bool,
+    // The session stopped reading, so received data is thrown away
+    session_gone: bool,
+    last_received: Instant,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if let Ok(mut connections) = self.connections.lock() {
+            connections.remove(&(self.peer, self.recv_id));
+        }
+    }
+}
+
+impl Connection {
+    fn new(
+        socket: Arc<UdpSocket>,
+        connections: ConnectionMap,
+        peer: SocketAddr,
+        recv_id: u16,
+        send_id: u16,
+    ) -> Self {
+        Self {
+            socket,
+            connections,
+            peer,
+            recv_id,
+            send_id,
+            seq_nr: 1,
+            ack_nr: 0,
+            ack_pending: false,
+            last_ack_received: 0,
+            duplicate_acks: 0,
+            in_flight: VecDeque::new(),
+            bytes_in_flight: 0,
+            window: MIN_WINDOW,
+            peer_window: RECEIVE_WINDOW,
+            base_delay: BaseDelay::new(),
+            reply_micros: 0,
+            rtt: None,
+            timeout: SYN_TIMEOUT,
+            reordered: HashMap::new(),
+            reordered_bytes: 0,
+            to_session: VecDeque::new(),
+            peer_fin: None,
+            peer_finished: false,
+            fin_sent: false,
+            session_gone: false,
+            last_received: Instant::now(),
+        }
+    }
+
+    async fn run_outgoing(
+        mut self,
+        stream: DuplexStream,
+        mut packet_rx: mpsc::Receiver<Packet>,
+        connected_tx: oneshot::Sender<io::Result<()>>,
+    ) {
+        let mut syn = Packet {
+            packet_type: PacketType::Syn,
+            connection_id: self.recv_id,
+            timestamp_micros: 0,
+            timestamp_difference_micros: 0,
+            window_size: RECEIVE_WINDOW as u32,
+            seq_nr: self.seq_nr,
+            ack_nr: 0,
+            payload: Vec::new(),
+        };
+        self.seq_nr = self.seq_nr.wrapping_add(1);
+
+        let mut state = None;
+        'attempts: for _ in 0..SYN_ATTEMPTS {
+            self.transmit(&mut syn).await;
+            let deadline = Instant::now() + SYN_TIMEOUT;
+            while let Ok(Some(packet)) = timeout(
+                deadline.saturating_duration_since(Instant::now()),
+                packet_rx.recv(),
+            )
+            .await
+            {
+                match packet.packet_type {
+                    PacketType::State if packet.ack_nr == syn.seq_nr => {
+                        state = Some(packet);
+                        break 'attempts;
+                    }
+                    PacketType::Reset => break 'attempts,
+                    _ => {}
+                }
+            }
+        }
+
+        let Some(state) = state else {
+            let _ = connected_tx.send(Err(io::ErrorKind::TimedOut.into()));
+            return;
+        };
+        // The acceptor's first data packet carries the sequence number of its STATE.
+        self.ack_nr = state.seq_nr.wrapping_sub(1);
+        self.last_ack_received = state.ack_nr;
+        self.note_received(&state);
+
+        if connected_tx.send(Ok(())).is_err() {
+            // Whoever asked for the connection gave up waiting.
+            self.send_reset().await;
+            return;
+        }
+        self.run(stream, packet_rx).await;
+    }
+
+    async fn run_incoming(
+        mut self,
+        stream: DuplexStream,
+        packet_rx: mpsc::Receiver<Packet>,
+        syn: Packet,
+    ) {
+        self.seq_nr = rand::random();
+        self.ack_nr = syn.seq_nr;
+        self.last_ack_received = self.seq_nr.wrapping_sub(1);
+        self.note_received(&syn);
+        self.ack_pending = true;
+        self.flush_ack().await;
+        self.run(stream, packet_rx).await;
+    }
+
+    async fn run(mut self, stream: DuplexStream, mut packet_rx: mpsc::Receiver<Packet>) {
+        let (mut session_read, mut session_write) = tokio::io::split(stream);
+        let mut read_buffer = vec![0u8; MAX_PAYLOAD];
+        let mut eof_delivered = false;
+
+        let result: io::Result<()> = loop {
+            let can_send = !self.fin_sent && self.window_open();
+            let can_deliver = !self.to_session.is_empty() && !self.session_gone;
+            let retransmit_at = self.retransmit_deadline();
+
+            tokio::select! {
+                packet = packet_rx.recv() => {
+                    let Some(packet) = packet else {
+                        break Err(io::ErrorKind::ConnectionAborted.into());
+                    };
+                    if let Err(e) = self.handle_packet(packet).await {
+                        break Err(e);
+                    }
+                    // Take everything already queued before answering with one ack.
+                    let mut queued_result = Ok(());
+                    while let Ok(packet) = packet_rx.try_recv() {
+                        queued_result = self.handle_packet(packet).await;
+                        if queued_result.is_err() {
+                            break;
+                        }
+                    }
+                    if let Err(e) = queued_result {
+                        break Err(e);
+                    }
+                    self.flush_ack().await;
+                }
+                read_result = session_read.read(&mut read_buffer), if can_send => {
+                    match read_result {
+                        Ok(0) | Err(_) => {
+                            self.send_new(PacketType::Fin, Vec::new()).await;
+                            self.fin_sent = true;
+                        }
+                        Ok(n) => self.send_new(PacketType::Data, read_buffer[..n].to_vec()).await,
+                    }
+                }
+                write_result = session_write.write(self.to_session.as_slices().0), if can_deliver => {
+                    match write_result {
+                        Ok(n) => {
+                            self.to_session.drain(..n);
+                        }
+                        Err(_) => {
+                            self.session_gone = true;
+                            self.to_session.clear();
+                        }
+                    }
+                }
+                _ = sleep_until(retransmit_at.unwrap_or_else(Instant::now)), if retransmit_at.is_some() => {
+                    if let Err(e) = self.resend_expired().await {
+                        break Err(e);
+                    }
+                }
+                _ = sleep_until(self.last_received + IDLE_TIMEOUT) => {
+                    break Err(io::ErrorKind::TimedOut.into());
+                }
+            }
+
+            if self.peer_finished && self.to_session.is_empty() && !eof_delivered {
+                let _ = session_write.shutdown().await;
+                eof_delivered = true;
+            }
+            let delivered = self.to_session.is_empty() || self.session_gone;
+            if self.fin_sent
+                && self.in_flight.is_empty()
+                && delivered
+                && (self.peer_finished || self.session_gone)
+            {
+                break Ok(());
+            }
+        };
+
+        if let Err(e) = result {
+            event!(Level::DEBUG, peer = %self.peer, "uTP connection closed: {}", e);
+            if e.kind() != io::ErrorKind::ConnectionReset {
+                self.send_reset().await;
+            }
+        }
+    }
+
+    fn window_open(&self) -> bool {
+        let limit = (self.window as usize).min(self.peer_window);
+        self.in_flight.is_empty() || self.bytes_in_flight + MAX_PAYLOAD <= limit
+    }
+
+    fn retransmit_deadline(&self) -> Option<Instant> {
+        self.in_flight
+            .iter()
+            .map(|sent| sent.sent_at + self.timeout)
+            .min()
+    }
+
+    fn receive_window(&self) -> u32 {
+        RECEIVE_WINDOW.saturating_sub(self.to_session.len() + self.reordered_bytes) as u32
+    }
+
+    fn note_received(&mut self, packet: &Packet) {
+        self.last_received = Instant::now();
+        self.reply_micros = now_micros().wrapping_sub(packet.timestamp_micros);
+        self.peer_window = packet.window_size as usize;
+    }
+
+    async fn handle_packet(&mut self, packet: Packet) -> io::Result<()> {
+        self.note_received(&packet);
+        match packet.packet_type {
+            PacketType::Reset => return Err(io::ErrorKind::ConnectionReset.into()),
+            // A repeated SYN means our STATE reply was lost.
+            PacketType::Syn => {
+                self.ack_pending = true;
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        self.handle_ack(&packet).await;
+        match packet.packet_type {
+            PacketType::Data => {
+                self.receive_data(packet.seq_nr, packet.payload);
+                self.ack_pending = true;
+            }
+            PacketType::Fin => {
+                self.peer_fin = Some(packet.seq_nr);
+                self.advance_past_fin();
+                self.ack_pending = true;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    async fn handle_ack(&mut self, packet: &Packet) {
+        let now = Instant::now();
+        let mut acked_bytes = 0;
+        while let Some(sent) = self.in_flight.front() {
+            if !seq_le(sent.packet.seq_nr, packet.ack_nr) {
+                break;
+            }
+            let sent = self.in_flight.pop_front().expect("front exists");
+            acked_bytes += sent.packet.payload.len();
+            // Only packets sent once give an unambiguous round trip time.
+            if sent.transmissions == 1 {
+                self.update_rtt(now - sent.sent_at);
+            }
+        }
+        self.bytes_in_flight -= acked_bytes;
+
+        if packet.timestamp_difference_micros != 0 {
+            let queuing_delay = self
+                .base_delay
+                .queuing_delay(packet.timestamp_difference_micros);
+            if acked_bytes > 0 {
+                let off_target = ((TARGET_DELAY_MICROS - queuing_delay as f64)
+                    / TARGET_DELAY_MICROS)
+                    .clamp(-1.0, 1.0);
+                self.window += MAX_WINDOW_GAIN * off_target * acked_bytes as f64 / self.window;
+                self.window = self.window.clamp(MIN_WINDOW, MAX_WINDOW);
+            }
+        }
+
+        if packet.ack_nr != self.last_ack_received {
+            self.last_ack_received = packet.ack_nr;
+            self.duplicate_acks = 0;
+        } else if packet.packet_type == PacketType::State && !self.in_flight.is_empty() {
+            self.duplicate_acks += 1;
+            if self.duplicate_acks == DUPLICATE_ACKS_BEFORE_RESEND {
+                // The packet after the repeated ack was most likely lost.
+                self.window = (self.window / 2.0).max(MIN_WINDOW);
+                self.resend(0).await;
+            }
+        }
+    }
+
+    fn update_rtt(&mut self, sample: Duration) {
+        let (rtt, rtt_var) = match self.rtt {
+            None => (sample, sample / 2),
+            Some((rtt, rtt_var)) => {
+                let deviation = rtt.abs_diff(sample);
+                ((rtt * 7 + sample) / 8, (rtt_var * 3 + deviation) / 4)
+            }
+        };
+        self.rtt = Some((rtt, rtt_var));
+        self.timeout = (rtt + rtt_var * 4).clamp(MIN_TIMEOUT, MAX_TIMEOUT);
+    }
+
+    fn receive_data(&mut self, seq_nr: u16, payload: Vec<u8>) {
+        if seq_nr == self.ack_nr.wrapping_add(1) {
+            self.deliver(payload);
+            self.ack_nr = seq_nr;
+            while let Some(next) = self.reordered.remove(&self.ack_nr.wrapping_add(1)) {
+                self.reordered_bytes -= next.len();
+                self.deliver(next);
+                self.ack_nr = self.ack_nr.wrapping_add(1);
+            }
+            self.advance_past_fin();
+        } else if !seq_le(seq_nr, self.ack_nr)
+            && seq_nr.wrapping_sub(self.ack_nr) <= MAX_REORDER_PACKETS
+            && !self.reordered.contains_key(&seq_nr)
+        {
+            self.reordered_bytes += payload.len();
+            self.reordered.insert(seq_nr, payload);
+        }
+    }
+
+    fn deliver(&mut self, payload: Vec<u8>) {
+        if !self.session_gone {
+            self.to_session.extend(payload);
+        }
+    }
+
+    fn advance_past_fin(&mut self) {
+        if self.peer_fin == Some(self.ack_nr.wrapping_add(1)) {
+            self.ack_nr = self.ack_nr.wrapping_add(1);
+            self.peer_finished = true;
+        }
+    }
+
+    fn packet(&self, packet_type: PacketType, seq_nr: u16, payload: Vec<u8>) -> Packet {
+        Packet {
+            packet_type,
+            connection_id: self.send_id,
+            timestamp_micros: 0,
+            timestamp_difference_micros: 0,
+            window_size: 0,
+            seq_nr,
+            ack_nr: 0,
+            payload,
+        }
+    }
+
+    /// Stamps `packet` with the current time, ack and window, then sends it.
+    async fn transmit(&mut self, packet: &mut Packet) {
+        packet.timestamp_micros = now_micros();
+        packet.timestamp_difference_micros = self.reply_micros;
+        packet.window_size = self.receive_window();
+        if packet.packet_type != PacketType::Syn {
+            packet.ack_nr = self.ack_nr;
+        }
+        if let Err(e) = self.socket.send_to(&packet.encode(), self.peer).await {
+            event!(Level::TRACE, peer = %self.peer, "uTP send failed: {}", e);
+        }
+    }
+
+    async fn send_new(&mut self, packet_type: PacketType, payload: Vec<u8>) {
+        let mut packet = self.packet(packet_type, self.seq_nr, payload);
+        self.seq_nr = self.seq_nr.wrapping_add(1);
+        self.transmit(&mut packet).await;
+        // Data packets carry the ack too.
+        self.ack_pending = false;
+        self.bytes_in_flight += packet.payload.len();
+        self.in_flight.push_back(SentPacket {
+            packet,
+            sent_at: Instant::now(),
+            transmissions: 1,
+        });
+    }
+
+    async fn resend(&mut self, index: usize) {
+        let mut packet = self.in_flight[index].packet.clone();
+        self.transmit(&mut packet).await;
+        let sent = &mut self.in_flight[index];
+        sent.packet = packet;
+        sent.sent_at = Instant::now();
+        sent.transmissions += 1;
+    }
+
+    /// Resends every packet that went unacknowledged for a full timeout, backing
+    /// off as TCP does. Gives up on the connection after too many tries.
+    async fn resend_expired(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        let timeout = self.timeout;
+        let expired: Vec<usize> = (0..self.in_flight.len())
+            .filter(|&i| self.in_flight[i].sent_at + timeout <= now)
+            .collect();
+        if expired.is_empty() {
+            return Ok(());
+        }
+        if expired
+            .iter()
+            .any(|&i| self.in_flight[i].transmissions >= MAX_TRANSMISSIONS)
+        {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        self.window = MIN_WINDOW;
+        self.timeout = (timeout * 2).min(MAX_TIMEOUT);
+        for i in expired {
+            self.resend(i).await;
+        }
+        Ok(())
+    }
+
+    async fn flush_ack(&mut self) {
+        if self.ack_pending {
+            self.ack_pending = false;
+            let mut state = self.packet(PacketType::State, self.seq_nr, Vec::new());
+            self.transmit(&mut state).await;
+        }
+    }
+
+    async fn send_reset(&mut self) {
+        let mut reset = self.packet(PacketType::Reset, self.seq_nr, Vec::new());
+        self.transmit(&mut reset).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_round_trip() {
+        let packet = Packet {
+            packet_type: PacketType::Data,
+            connection_id: 0xbeef,
+            timestamp_micros: 123_456,
+            timestamp_difference_micros: 789,
+            window_size: 65_536,
+            seq_nr: 65_535,
+            ack_nr: 7,
+            payload: b"hello".to_vec(),
+        };
+        let bytes = packet.encode();
+        assert_eq!(bytes.len(), HEADER_LEN + 5);
+        assert_eq!(bytes[0], 0x01);
+        assert_eq!(Packet::decode(&bytes), Some(packet));
+    }
+
+    #[test]
+    fn test_decode_skips_extensions_and_rejects_junk() {
+        let mut bytes = Packet {
+            packet_type: PacketType::State,
+            connection_id: 1,
+            timestamp_micros: 0,
+            timestamp_difference_micros: 0,
+            window_size: 0,
+            seq_nr: 1,
+            ack_nr: 1,
+            payload: Vec::new(),
+        }
+        .encode();
+        // A selective ack extension with a 4-byte bitmask, then no more.
+        bytes[1] = 1;
+        bytes.extend_from_slice(&[0, 4, 0xff, 0, 0, 0]);
+        let packet = Packet::decode(&bytes).unwrap();
+        assert_eq!(packet.packet_type, PacketType::State);
+        assert!(packet.payload.is_empty());
+
+        // Truncated extension, wrong version, DHT traffic.
+        assert_eq!(Packet::decode(&bytes[..HEADER_LEN + 3]), None);
+        bytes[0] = 0x22;
+        assert_eq!(Packet::decode(&bytes), None);
+        assert_eq!(Packet::decode(b"d1:ad2:id20:"), None);
+    }
+
+    #[test]
+    fn test_sequence_numbers_wrap() {
+        assert!(seq_le(1, 2));
+        assert!(seq_le(2, 2));
+        assert!(!seq_le(3, 2));
+        assert!(seq_le(65_535, 0));
+        assert!(seq_le(65_000, 10));
+        assert!(!seq_le(10, 65_000));
+    }
+
+    #[tokio::test]
+    async fn test_transfer_both_ways_and_close() {
+        let (server, mut incoming_rx) = UtpSocket::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let (client, _client_incoming_rx) = UtpSocket::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+
+        let to_server: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        let to_client: Vec<u8> = (0..50_000u32).map(|i| (i % 241) as u8).collect();
+
+        let server_task = {
+            let to_server = to_server.clone();
+            let to_client = to_client.clone();
+            tokio::spawn(async move {
+                let (mut stream, _) = incoming_rx.recv().await.unwrap();
+                let mut received = vec![0u8; to_server.len()];
+                stream.read_exact(&mut received).await.unwrap();
+                assert_eq!(received, to_server);
+                stream.write_all(&to_client).await.unwrap();
+                stream.shutdown().await.unwrap();
+                // The client closes after reading, which ends our stream too.
+                let mut rest = Vec::new();
+                stream.read_to_end(&mut rest).await.unwrap();
+                assert!(rest.is_empty());
+            })
+        };
+
+        let mut stream = client.connect(server.local_addr().unwrap()).await.unwrap();
+        stream.write_all(&to_server).await.unwrap();
+        let mut received = Vec::new();
+        timeout(Duration::from_secs(20), stream.read_to_end(&mut received))
+            .await
+            .expect("transfer timed out")
+            .unwrap();
+        assert_eq!(received, to_client);
+        drop(stream);
+
+        timeout(Duration::from_secs(20), server_task)
+            .await
+            .expect("server side did not finish")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connect_to_silent_address_fails() {
+        let (client, _incoming_rx) = UtpSocket::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        // Bound but never answers.
+        let silent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        let result = client.connect(silent.local_addr().unwrap()).await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert!(client.inner.connections.lock().unwrap().is_empty());
+    }
+}
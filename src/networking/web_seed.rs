@@ -0,0 +1,138 @@
+// SPDX-FileCopyrightText: 2025 The superseedr Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use crate::errors::WebSeedError;
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use reqwest::header;
+use reqwest::{Client, StatusCode};
+
+static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+// How long to leave a busy seed alone when it doesn't say
+const DEFAULT_BUSY_SECS: u64 = 60;
+const MAX_BUSY_SECS: u64 = 3600;
+
+/// The HTTP client shared by every web seed request.
+fn client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        Client::builder()
+            .user_agent(APP_USER_AGENT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_else(|_| Client::new())
+    })
+}
+
+/// URL of piece `piece_index` on an HTTP seed (BEP 17): the seed's base URL with
+/// the info hash and piece index as query parameters.
+pub fn http_seed_piece_url(base_url: &str, info_hash: &[u8], piece_index: u32) -> String {
+    let separator = if base_url.contains('?') { '&' } else { '?' };
+    format!(
+        "{}{}info_hash={}&piece={}",
+        base_url,
+        separator,
+        urlencoding::encode_binary(info_hash),
+        piece_index
+    )
+}
+
+/// Downloads a whole piece from an HTTP seed. The data still has to be checked
+/// against the piece hash.
+pub async fn fetch_http_seed_piece(
+    base_url: &str,
+    info_hash: &[u8],
+    piece_index: u32,
+    piece_len: usize,
+) -> Result<Vec<u8>, WebSeedError> {
+    let url = http_seed_piece_url(base_url, info_hash, piece_index);
+    get_exact(&url, piece_len).await
+}
+
+/// GETs `url` and returns the body if it is exactly `expected_len` bytes. A busy
+/// server (503) comes back as `WebSeedError::Busy` with how long it asked us to wait.
+async fn get_exact(url: &str, expected_len: usize) -> Result<Vec<u8>, WebSeedError> {
+    let response = client().get(url).send().await.map_err(request_error)?;
+
+    let status = response.status();
+    if status == StatusCode::SERVICE_UNAVAILABLE {
+        let retry_after_header = response
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let body = response.text().await.unwrap_or_default();
+        let secs = busy_retry_secs(retry_after_header.as_deref(), &body);
+        return Err(WebSeedError::Busy(Duration::from_secs(secs)));
+    }
+    if !status.is_success() {
+        return Err(WebSeedError::Status(status.as_u16()));
+    }
+
+    // Don't download a body we already know is the wrong size.
+    if let Some(content_length) = response.content_length() {
+        if content_length != expected_len as u64 {
+            return Err(WebSeedError::LengthMismatch {
+                expected: expected_len,
+                actual: content_length as usize,
+            });
+        }
+    }
+
+    let body = response.bytes().await.map_err(request_error)?;
+    if body.len() != expected_len {
+        return Err(WebSeedError::LengthMismatch {
+            expected: expected_len,
+            actual: body.len(),
+        });
+    }
+    Ok(body.to_vec())
+}
+
+fn request_error(e: reqwest::Error) -> WebSeedError {
+    WebSeedError::Request(e.to_string())
+}
+
+/// Seconds a busy seed asked us to wait. HTTP seeds put the number in the body of
+/// the 503; other servers use a `Retry-After` header.
+fn busy_retry_secs(retry_after_header: Option<&str>, body: &str) -> u64 {
+    retry_after_header
+        .and_then(|value| value.trim().parse().ok())
+        .or_else(|| body.trim().parse().ok())
+        .unwrap_or(DEFAULT_BUSY_SECS)
+        .min(MAX_BUSY_SECS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_seed_piece_url() {
+        let info_hash = [0x12, 0xab, b'a', b' ', 0xff];
+
+        assert_eq!(
+            http_seed_piece_url("http://seed.example/seed.php", &info_hash, 7),
+            "http://seed.example/seed.php?info_hash=%12%ABa%20%FF&piece=7"
+        );
+        assert_eq!(
+            http_seed_piece_url("http://seed.example/seed?id=3", &info_hash, 0),
+            "http://seed.example/seed?id=3&info_hash=%12%ABa%20%FF&piece=0"
+        );
+    }
+
+    #[test]
+    fn test_busy_retry_secs() {
+        assert_eq!(busy_retry_secs(None, "120\n"), 120);
+        assert_eq!(busy_retry_secs(Some("30"), "120"), 30);
+        assert_eq!(
+            busy_retry_secs(None, "<html>busy</html>"),
+            DEFAULT_BUSY_SECS
+        );
+        assert_eq!(busy_retry_secs(None, "999999"), MAX_BUSY_SECS);
+    }
+}
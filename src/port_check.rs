@@ -0,0 +1,115 @@
+// SPDX-FileCopyrightText: 2025 The superseedr Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+
+use crate::app::AppCommand;
+use crate::errors::PortCheckError;
+
+// How long the listening port gets to answer before it counts as closed
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const URL_TIMEOUT: Duration = Duration::from_secs(15);
+// Check URLs answer with a word, not a web page
+const MAX_RESPONSE_BYTES: usize = 256;
+
+/// How we find out whether peers can reach our listening port.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PortChecker {
+    // Connect to our own external address. Needs a router that loops connections
+    // to its public address back inside (hairpin NAT).
+    SelfConnect(IpAddr),
+    // Ask an outside service; see `Settings::port_check_url`
+    Url(String),
+}
+
+impl PortChecker {
+    /// Whether `port` can be reached from outside.
+    pub async fn check(&self, port: u16) -> Result<bool, PortCheckError> {
+        match self {
+            PortChecker::SelfConnect(ip) => {
+                let connect = TcpStream::connect(SocketAddr::new(*ip, port));
+                Ok(matches!(
+                    tokio::time::timeout(CONNECT_TIMEOUT, connect).await,
+                    Ok(Ok(_))
+                ))
+            }
+            PortChecker::Url(template) => {
+                let url = check_url(template, port);
+                let response = reqwest::Client::new()
+                    .get(url)
+                    .timeout(URL_TIMEOUT)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+                let bytes = response.bytes().await?;
+                if bytes.len() > MAX_RESPONSE_BYTES {
+                    return Err(PortCheckError::InvalidResponse);
+                }
+                parse_response(&String::from_utf8_lossy(&bytes))
+            }
+        }
+    }
+}
+
+/// Runs one check in the background and hands the result to the app.
+pub fn spawn_check(checker: PortChecker, port: u16, app_command_tx: mpsc::Sender<AppCommand>) {
+    tokio::spawn(async move {
+        let result = checker.check(port).await;
+        let _ = app_command_tx
+            .send(AppCommand::PortChecked(port, result))
+            .await;
+    });
+}
+
+fn check_url(template: &str, port: u16) -> String {
+    template.replace("{port}", &port.to_string())
+}
+
+fn parse_response(body: &str) -> Result<bool, PortCheckError> {
+    match body.trim().to_ascii_lowercase().as_str() {
+        "open" | "true" | "yes" | "1" => Ok(true),
+        "closed" | "false" | "no" | "0" => Ok(false),
+        _ => Err(PortCheckError::InvalidResponse),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_url_fills_in_port() {
+        assert_eq!(
+            check_url("https://example.com/check?port={port}", 6681),
+            "https://example.com/check?port=6681"
+        );
+        assert_eq!(
+            check_url("https://example.com/check", 6681),
+            "https://example.com/check"
+        );
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert!(parse_response("Open\n").unwrap());
+        assert!(parse_response("1").unwrap());
+        assert!(!parse_response(" closed ").unwrap());
+        assert!(!parse_response("false").unwrap());
+        assert!(parse_response("<html>").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_self_connect_sees_listening_port() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let checker = PortChecker::SelfConnect("127.0.0.1".parse().unwrap());
+        assert!(checker.check(port).await.unwrap());
+
+        drop(listener);
+        assert!(!checker.check(port).await.unwrap());
+    }
+}
@@ -29,6 +29,8 @@ pub enum ResourceType {
     PeerConnection,
     DiskRead,
     DiskWrite,
+    // Outgoing connections still being set up (half-open), across all torrents
+    ConnectionAttempt,
 }
 
 #[derive(Error, Debug, Clone)]
@@ -55,6 +57,9 @@ impl ResourceManagerClient {
     pub async fn acquire_disk_write(&self) -> Result<PermitGuard, ResourceManagerError> {
         self.acquire(ResourceType::DiskWrite).await
     }
+    pub async fn acquire_connection_attempt(&self) -> Result<PermitGuard, ResourceManagerError> {
+        self.acquire(ResourceType::ConnectionAttempt).await
+    }
 
     pub async fn update_limits(
         &self,
@@ -168,6 +173,10 @@ impl ResourceManager {
             .unwrap();
         let mut read_rx = self.acquire_rxs.remove(&ResourceType::DiskRead).unwrap();
         let mut write_rx = self.acquire_rxs.remove(&ResourceType::DiskWrite).unwrap();
+        let mut attempt_rx = self
+            .acquire_rxs
+            .remove(&ResourceType::ConnectionAttempt)
+            .unwrap();
         let mut shutdown_rx = self.shutdown_tx.subscribe();
 
         loop {
@@ -176,6 +185,7 @@ impl ResourceManager {
                 Some(cmd) = peer_rx.recv() => self.handle_acquire(ResourceType::PeerConnection, cmd.respond_to),
                 Some(cmd) = read_rx.recv() => self.handle_acquire(ResourceType::DiskRead, cmd.respond_to),
                 Some(cmd) = write_rx.recv() => self.handle_acquire(ResourceType::DiskWrite, cmd.respond_to),
+                Some(cmd) = attempt_rx.recv() => self.handle_acquire(ResourceType::ConnectionAttempt, cmd.respond_to),
 
                 Some(cmd) = self.control_rx.recv() => {
                     match cmd {
@@ -274,6 +284,7 @@ mod tests {
         limits.insert(ResourceType::PeerConnection, peer);
         limits.insert(ResourceType::DiskRead, read);
         limits.insert(ResourceType::DiskWrite, write);
+        limits.insert(ResourceType::ConnectionAttempt, (1, 1));
         limits
     }
 
@@ -549,4 +560,31 @@ mod tests {
             assert!(join_res.unwrap().is_ok(), "Task {} acquire failed", i);
         }
     }
+
+    #[tokio::test]
+    async fn test_connection_attempts_are_capped_separately() {
+        // Plenty of peer connections, but one attempt at a time
+        let limits = create_limits((10, 10), (0, 0), (0, 0));
+        let (client, _handle) = setup_manager(limits);
+
+        let _peer_guard = client.acquire_peer_connection().await.unwrap();
+        let attempt_guard = client.acquire_connection_attempt().await.unwrap();
+
+        let client_clone = client.clone();
+        let attempt_task =
+            tokio::spawn(async move { client_clone.acquire_connection_attempt().await });
+        sleep(Duration::from_millis(50)).await;
+        assert!(
+            !attempt_task.is_finished(),
+            "Second connection attempt did not wait"
+        );
+
+        // Peer connections are still available while attempts are capped
+        assert!(client.acquire_peer_connection().await.is_ok());
+
+        drop(attempt_guard);
+        let result = timeout(Duration::from_millis(100), attempt_task).await;
+        assert!(result.is_ok(), "Waiting attempt was not woken");
+        assert!(result.unwrap().unwrap().is_ok(), "Acquire task failed");
+    }
 }
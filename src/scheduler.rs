@@ -0,0 +1,196 @@
+// SPDX-FileCopyrightText: 2025 The superseedr Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Weekday {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+impl Weekday {
+    const ALL: [Weekday; 7] = [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ];
+
+    fn previous(self) -> Weekday {
+        Self::ALL[(self as usize + 6) % 7]
+    }
+}
+
+/// Part of the week during which the alternative global limits apply, e.g. work
+/// hours when the connection is needed for other things.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ScheduleRule {
+    // Days the rule starts on; empty means every day
+    pub days: Vec<Weekday>,
+    // Hours of the day, 0 to 24. A start after the end runs past midnight into the
+    // next day, so 22 to 6 on Fri covers Friday night.
+    pub start_hour: u8,
+    pub end_hour: u8,
+    // Pause every torrent instead of only slowing them down
+    pub pause: bool,
+}
+
+impl Default for ScheduleRule {
+    fn default() -> Self {
+        Self {
+            days: Vec::new(),
+            start_hour: 0,
+            end_hour: 24,
+            pause: false,
+        }
+    }
+}
+
+impl ScheduleRule {
+    fn starts_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    /// Whether the rule is in effect at `hour` (0-23) on `day`.
+    fn covers(&self, day: Weekday, hour: u8) -> bool {
+        if self.start_hour <= self.end_hour {
+            self.starts_on(day) && (self.start_hour..self.end_hour).contains(&hour)
+        } else {
+            (self.starts_on(day) && hour >= self.start_hour)
+                || (self.starts_on(day.previous()) && hour < self.end_hour)
+        }
+    }
+}
+
+/// Which global limits are in force.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum RateProfile {
+    #[default]
+    Normal,
+    Alternative,
+    // Alternative limits, and every torrent paused
+    Paused,
+}
+
+/// The profile `rules` call for at `now`, with the rules' hours in UTC shifted by
+/// `utc_offset_minutes`. A rule that pauses wins over one that only slows down.
+pub fn scheduled_profile(
+    rules: &[ScheduleRule],
+    utc_offset_minutes: i32,
+    now: SystemTime,
+) -> RateProfile {
+    let secs = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64
+        + i64::from(utc_offset_minutes) * 60;
+    let days = secs.div_euclid(86_400);
+    let hour = (secs.rem_euclid(86_400) / 3600) as u8;
+    // 1970-01-01 was a Thursday
+    let day = Weekday::ALL[(days + 3).rem_euclid(7) as usize];
+
+    let matching = rules.iter().filter(|rule| rule.covers(day, hour));
+    let mut profile = RateProfile::Normal;
+    for rule in matching {
+        if rule.pause {
+            return RateProfile::Paused;
+        }
+        profile = RateProfile::Alternative;
+    }
+    profile
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    // Monday 2025-01-06 00:00 UTC
+    const MONDAY: u64 = 1_736_121_600;
+
+    fn at(day: u64, hour: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(MONDAY + day * 86_400 + hour * 3600)
+    }
+
+    #[test]
+    fn test_daytime_rule() {
+        let rules = [ScheduleRule {
+            days: vec![Weekday::Mon, Weekday::Tue],
+            start_hour: 9,
+            end_hour: 17,
+            pause: false,
+        }];
+        assert_eq!(scheduled_profile(&rules, 0, at(0, 8)), RateProfile::Normal);
+        assert_eq!(
+            scheduled_profile(&rules, 0, at(0, 9)),
+            RateProfile::Alternative
+        );
+        assert_eq!(
+            scheduled_profile(&rules, 0, at(1, 16)),
+            RateProfile::Alternative
+        );
+        assert_eq!(scheduled_profile(&rules, 0, at(1, 17)), RateProfile::Normal);
+        assert_eq!(scheduled_profile(&rules, 0, at(2, 12)), RateProfile::Normal);
+    }
+
+    #[test]
+    fn test_overnight_rule() {
+        // Friday 22:00 until Saturday 06:00
+        let rules = [ScheduleRule {
+            days: vec![Weekday::Fri],
+            start_hour: 22,
+            end_hour: 6,
+            pause: true,
+        }];
+        assert_eq!(scheduled_profile(&rules, 0, at(4, 21)), RateProfile::Normal);
+        assert_eq!(scheduled_profile(&rules, 0, at(4, 23)), RateProfile::Paused);
+        assert_eq!(scheduled_profile(&rules, 0, at(5, 5)), RateProfile::Paused);
+        assert_eq!(scheduled_profile(&rules, 0, at(5, 6)), RateProfile::Normal);
+        // Thursday night isn't covered
+        assert_eq!(scheduled_profile(&rules, 0, at(4, 2)), RateProfile::Normal);
+    }
+
+    #[test]
+    fn test_every_day_and_offset() {
+        let rules = [
+            ScheduleRule {
+                start_hour: 18,
+                end_hour: 23,
+                ..Default::default()
+            },
+            ScheduleRule {
+                days: vec![Weekday::Sun],
+                start_hour: 20,
+                end_hour: 21,
+                pause: true,
+            },
+        ];
+        // 17:00 UTC is 18:00 at UTC+1
+        assert_eq!(scheduled_profile(&rules, 0, at(2, 17)), RateProfile::Normal);
+        assert_eq!(
+            scheduled_profile(&rules, 60, at(2, 17)),
+            RateProfile::Alternative
+        );
+        // The pausing rule wins where both apply
+        assert_eq!(scheduled_profile(&rules, 0, at(6, 20)), RateProfile::Paused);
+        // A negative offset can move the time back into the previous day
+        assert_eq!(
+            scheduled_profile(&rules, -120, at(1, 0)),
+            RateProfile::Alternative
+        );
+
+        assert_eq!(
+            scheduled_profile(&[ScheduleRule::default()], 0, at(3, 3)),
+            RateProfile::Alternative
+        );
+        assert_eq!(scheduled_profile(&[], 0, at(3, 3)), RateProfile::Normal);
+    }
+}
@@ -1,10 +1,16 @@
 // SPDX-FileCopyrightText: 2025 The superseedr Contributors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+use crate::config::CreateSubfolder;
 use crate::errors::StorageError;
-use std::path::{Path, PathBuf};
+use crate::hash_pool::HashPool;
+use crate::torrent_file::PieceHash;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
 use tokio::fs::{self, try_exists, File, OpenOptions};
 use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, SeekFrom};
+use tracing::{event, Level};
 
 use crate::torrent_file::InfoFile;
 
@@ -13,6 +19,20 @@ pub struct FileInfo {
     pub path: PathBuf,            // The full path to the file on the disk.
     pub length: u64,              // The length of the file in bytes.
     pub global_start_offset: u64, // The starting offset of this file within the torrent's complete data stream.
+    pub is_padding: bool,         // BEP 47 pad file: reads as zeros and is never created on disk.
+    pub is_borrowed: bool,        // Another torrent's file, used in place: never moved or deleted.
+}
+
+/// A torrent file kept under another path than its metadata gives, relative to the
+/// torrent's download folder. Borrowed files belong to other data on disk and keep
+/// their absolute path.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileRename {
+    // Index of the file in the metadata, pad files included
+    pub index: usize,
+    pub path: PathBuf,
+    #[serde(default)]
+    pub borrowed: bool,
 }
 
 /// Manages the file layout for a torrent, abstracting away the difference
@@ -47,6 +67,8 @@ impl MultiFileInfo {
                     path: full_path,
                     length: f.length as u64,
                     global_start_offset: current_offset,
+                    is_padding: f.is_padding(),
+                    is_borrowed: false,
                 });
 
                 current_offset += f.length as u64;
@@ -62,6 +84,8 @@ impl MultiFileInfo {
                 path: file_path,
                 length: total_size,
                 global_start_offset: 0,
+                is_padding: false,
+                is_borrowed: false,
             };
             Ok(Self {
                 files: vec![single_file],
@@ -69,6 +93,215 @@ impl MultiFileInfo {
             })
         }
     }
+
+    /// Points renamed files at their paths under `root`, and borrowed ones at their
+    /// own path. Renames of pad files, of files that don't exist or to paths outside
+    /// `root` are ignored.
+    pub fn apply_renames(&mut self, root: &Path, renames: &[FileRename]) {
+        let usable =
+            |r: &&FileRename| is_plain_relative(&r.path) || (r.borrowed && r.path.is_absolute());
+        for rename in renames.iter().filter(usable) {
+            if let Some(file_info) = self.files.get_mut(rename.index) {
+                if !file_info.is_padding {
+                    file_info.path = root.join(&rename.path);
+                    file_info.is_borrowed = rename.borrowed;
+                }
+            }
+        }
+    }
+}
+
+/// Whether `path` names something inside the folder it's joined to: relative, and
+/// without `..`.
+fn is_plain_relative(path: &Path) -> bool {
+    path.components().next().is_some()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Resolves the directory a torrent's content is placed in, wrapping it in a
+/// folder named after the torrent when the subfolder policy asks for one.
+pub fn content_root_dir(
+    root_dir: &Path,
+    torrent_name: &str,
+    is_multi_file: bool,
+    policy: CreateSubfolder,
+) -> PathBuf {
+    let wrap = match policy {
+        CreateSubfolder::Always => true,
+        CreateSubfolder::Never => false,
+        CreateSubfolder::MultiFileOnly => is_multi_file,
+    };
+    if wrap {
+        root_dir.join(torrent_name)
+    } else {
+        root_dir.to_path_buf()
+    }
+}
+
+/// Finds the torrent's files that are missing from `content_root` but exist under
+/// another root folder in `download_dir`, e.g. the same data saved from another
+/// tracker's torrent under a different name. Files have to match in path and size
+/// below their root, and only a root holding every missing file is used. Returns
+/// borrowed renames pointing the torrent at them; check them with
+/// `files_match_pieces` before use, since a size says little about the contents.
+pub fn find_existing_files(
+    download_dir: &Path,
+    content_root: &Path,
+    multi_file_info: &MultiFileInfo,
+) -> Vec<FileRename> {
+    let missing: Vec<(usize, &Path, u64)> = multi_file_info
+        .files
+        .iter()
+        .enumerate()
+        .filter(|(_, file_info)| !file_info.is_padding && !file_info.path.exists())
+        .filter_map(|(index, file_info)| {
+            let relative = file_info.path.strip_prefix(content_root).ok()?;
+            Some((index, relative, file_info.length))
+        })
+        .collect();
+    if missing.is_empty() {
+        return Vec::new();
+    }
+
+    let mut roots = vec![download_dir.to_path_buf()];
+    if let Ok(entries) = std::fs::read_dir(download_dir) {
+        roots.extend(
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir()),
+        );
+    }
+    roots.retain(|root| root != content_root);
+    roots.sort();
+
+    let has_length = |path: &Path, length: u64| {
+        std::fs::metadata(path).is_ok_and(|m| m.is_file() && m.len() == length)
+    };
+    let Some(root) = roots.iter().find(|root| {
+        missing
+            .iter()
+            .all(|&(_, relative, length)| has_length(&root.join(relative), length))
+    }) else {
+        return Vec::new();
+    };
+
+    missing
+        .iter()
+        .map(|&(index, relative, _)| FileRename {
+            index,
+            path: root.join(relative),
+            borrowed: true,
+        })
+        .collect()
+}
+
+/// Whether every piece holding part of the files at `indexes` reads back from disk
+/// with the hash `piece_hash` gives for it.
+pub async fn files_match_pieces(
+    multi_file_info: &MultiFileInfo,
+    indexes: &[usize],
+    piece_length: u64,
+    piece_hash: impl Fn(usize) -> Option<PieceHash>,
+    hash_pool: &HashPool,
+) -> bool {
+    let mut pieces = BTreeSet::new();
+    for file_info in indexes
+        .iter()
+        .filter_map(|&index| multi_file_info.files.get(index))
+        .filter(|file_info| file_info.length > 0)
+    {
+        let first = file_info.global_start_offset / piece_length;
+        let last = (file_info.global_start_offset + file_info.length - 1) / piece_length;
+        pieces.extend(first..=last);
+    }
+
+    for piece_index in pieces {
+        let start = piece_index * piece_length;
+        let len = piece_length.min(multi_file_info.total_size - start) as usize;
+        let Ok(data) = read_data_from_disk(multi_file_info, start, len).await else {
+            return false;
+        };
+        if hash_pool
+            .verify(data, piece_hash(piece_index as usize), Some(len))
+            .await
+            .is_err()
+        {
+            return false;
+        }
+    }
+    true
+}
+
+/// Whether every file that belongs on disk is there at its full length, the least a
+/// torrent trusted as complete without hashing needs. Pad files and those `skipped`
+/// picks out, which were never downloaded, don't count.
+pub async fn files_present(
+    multi_file_info: &MultiFileInfo,
+    skipped: impl Fn(usize) -> bool,
+) -> bool {
+    for (index, file_info) in multi_file_info.files.iter().enumerate() {
+        if file_info.is_padding || skipped(index) {
+            continue;
+        }
+        let present = fs::metadata(&file_info.path)
+            .await
+            .is_ok_and(|m| m.is_file() && m.len() == file_info.length);
+        if !present {
+            return false;
+        }
+    }
+    true
+}
+
+/// Returns how many bytes still have to be allocated on disk for this torrent.
+/// Files that already exist count towards the total, so resuming a partially
+/// allocated torrent only asks for the difference.
+pub fn remaining_allocation_bytes(multi_file_info: &MultiFileInfo) -> u64 {
+    multi_file_info
+        .files
+        .iter()
+        .filter(|file_info| !file_info.is_padding)
+        .map(|file_info| {
+            let existing_len = std::fs::metadata(&file_info.path)
+                .map(|m| m.len())
+                .unwrap_or(0);
+            file_info.length.saturating_sub(existing_len)
+        })
+        .sum()
+}
+
+/// Returns the free space on the filesystem that will hold `path`.
+/// The download folder may not exist yet, so the nearest existing ancestor is queried.
+pub fn available_space(path: &Path) -> std::io::Result<u64> {
+    let mut candidate = Some(path);
+    while let Some(dir) = candidate {
+        if dir.exists() {
+            return fs2::available_space(dir);
+        }
+        candidate = dir.parent();
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("No existing ancestor for {:?}", path),
+    ))
+}
+
+/// Checks that files can be created in `dir` by writing and removing a small probe
+/// file. A missing folder fails rather than being created, since on an unmounted
+/// drive that would put the downloads on whatever filesystem holds the mountpoint.
+pub fn check_writable(dir: &Path) -> std::io::Result<()> {
+    if !dir.is_dir() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "the folder does not exist",
+        ));
+    }
+    let probe = dir.join(".superseedr-write-test");
+    std::fs::write(&probe, b"")?;
+    std::fs::remove_file(&probe)
 }
 
 /// Creates all necessary directories and pre-allocates all files for a torrent.
@@ -76,7 +309,7 @@ impl MultiFileInfo {
 pub async fn create_and_allocate_files(
     multi_file_info: &MultiFileInfo,
 ) -> Result<(), StorageError> {
-    for file_info in &multi_file_info.files {
+    for file_info in multi_file_info.files.iter().filter(|f| !f.is_padding) {
         // Ensure the parent directory for the file exists.
         if let Some(parent_dir) = file_info.path.parent() {
             if !try_exists(parent_dir).await? {
@@ -118,7 +351,10 @@ pub async fn read_data_from_disk(
                 file_info.length - local_offset,
             ) as usize;
 
-            if bytes_to_read_in_this_file > 0 {
+            if bytes_to_read_in_this_file > 0 && file_info.is_padding {
+                buffer.resize(buffer.len() + bytes_to_read_in_this_file, 0);
+                bytes_read += bytes_to_read_in_this_file;
+            } else if bytes_to_read_in_this_file > 0 {
                 let mut file = File::open(&file_info.path).await?;
                 file.seek(SeekFrom::Start(local_offset)).await?;
 
@@ -161,7 +397,10 @@ pub async fn write_data_to_disk(
                 file_info.length - local_offset,
             ) as usize;
 
-            if bytes_to_write_in_this_file > 0 {
+            if bytes_to_write_in_this_file > 0 && file_info.is_padding {
+                // Padding is all zeros by definition; nothing to store.
+                bytes_written += bytes_to_write_in_this_file;
+            } else if bytes_to_write_in_this_file > 0 {
                 let mut file = OpenOptions::new().write(true).open(&file_info.path).await?;
                 file.seek(SeekFrom::Start(local_offset)).await?;
 
@@ -184,12 +423,191 @@ pub async fn write_data_to_disk(
     )))
 }
 
+/// Renames file `index` to `new_path`, relative to `root`, on disk and in the layout.
+/// A file that isn't on disk yet is only renamed in the layout and gets created
+/// under the new name.
+pub async fn rename_file(
+    multi_file_info: &mut MultiFileInfo,
+    root: &Path,
+    index: usize,
+    new_path: &Path,
+) -> Result<(), StorageError> {
+    if !is_plain_relative(new_path) {
+        return Err(StorageError::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{:?} must be a relative path without '..'", new_path),
+        )));
+    }
+    let new_path = root.join(new_path);
+    let old_path = match multi_file_info.files.get(index) {
+        Some(file_info) if file_info.is_borrowed => {
+            return Err(StorageError::Io(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!("{:?} belongs to other data on disk", file_info.path),
+            )))
+        }
+        Some(file_info) if !file_info.is_padding => file_info.path.clone(),
+        _ => {
+            return Err(StorageError::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("The torrent has no file {}", index),
+            )))
+        }
+    };
+    if old_path == new_path {
+        return Ok(());
+    }
+    let taken = multi_file_info.files.iter().any(|file_info| {
+        file_info.path.starts_with(&new_path) || new_path.starts_with(&file_info.path)
+    });
+    if taken || try_exists(&new_path).await? {
+        return Err(StorageError::Io(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{:?} already exists", new_path),
+        )));
+    }
+
+    if try_exists(&old_path).await? {
+        if let Some(parent_dir) = new_path.parent() {
+            fs::create_dir_all(parent_dir).await?;
+        }
+        fs::rename(&old_path, &new_path).await?;
+    }
+    multi_file_info.files[index].path = new_path;
+    Ok(())
+}
+
+/// Moves a torrent's files from under `old_root` to the same places under `new_root`
+/// and returns the new layout. Each file is renamed, or where that fails (e.g. across
+/// filesystems) copied under a temporary name, compared with the original and then
+/// renamed into place, so an interrupted copy never blocks a retry. Originals are
+/// only removed once every file is in place; a failure before that puts back what
+/// was already moved. Files never created on disk, like skipped ones, and borrowed
+/// files are left out.
+pub async fn move_files(
+    multi_file_info: &MultiFileInfo,
+    old_root: &Path,
+    new_root: &Path,
+) -> Result<MultiFileInfo, StorageError> {
+    let mut moved_info = multi_file_info.clone();
+    let mut moves = Vec::new();
+    for (old, new) in multi_file_info.files.iter().zip(&mut moved_info.files) {
+        if old.is_borrowed {
+            continue;
+        }
+        let relative = old.path.strip_prefix(old_root).map_err(|_| {
+            std::io::Error::other(format!("{:?} is not under {:?}", old.path, old_root))
+        })?;
+        new.path = new_root.join(relative);
+        if !old.is_padding && try_exists(&old.path).await? {
+            if try_exists(&new.path).await? {
+                return Err(StorageError::Io(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!("{:?} already exists", new.path),
+                )));
+            }
+            moves.push((old.path.clone(), new.path.clone()));
+        }
+    }
+
+    let mut renamed = Vec::new();
+    let mut copied = Vec::new();
+    let result = async {
+        for (from, to) in &moves {
+            if let Some(parent_dir) = to.parent() {
+                fs::create_dir_all(parent_dir).await?;
+            }
+            if fs::rename(from, to).await.is_ok() {
+                renamed.push((from, to));
+                continue;
+            }
+            let partial = partial_copy_path(to);
+            let copy_result = async {
+                fs::copy(from, &partial).await?;
+                if !same_contents(from, &partial).await? {
+                    return Err(std::io::Error::other(format!(
+                        "the copy of {:?} doesn't match the original",
+                        from
+                    )));
+                }
+                fs::rename(&partial, to).await
+            }
+            .await;
+            if copy_result.is_err() {
+                let _ = fs::remove_file(&partial).await;
+            }
+            copy_result?;
+            copied.push((from, to));
+        }
+        Ok(())
+    }
+    .await;
+
+    if let Err(e) = result {
+        for (from, to) in renamed.into_iter().rev() {
+            let _ = fs::rename(to, from).await;
+        }
+        for (_, to) in copied {
+            let _ = fs::remove_file(to).await;
+        }
+        return Err(StorageError::Io(e));
+    }
+
+    // Every file is in place by now, so an original that can't be removed only
+    // wastes space.
+    for (from, _) in copied {
+        if let Err(e) = fs::remove_file(from).await {
+            event!(Level::WARN, file = ?from, error = %e, "Could not remove a moved file's original.");
+        }
+    }
+    // Folders left empty, up to but not including the old root
+    for (from, _) in &moves {
+        let mut dir = from.parent();
+        while let Some(current) = dir.filter(|dir| *dir != old_root) {
+            if fs::remove_dir(current).await.is_err() {
+                break;
+            }
+            dir = current.parent();
+        }
+    }
+    Ok(moved_info)
+}
+
+/// Where a file is copied to before it's known to be complete, next to `path`.
+fn partial_copy_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".superseedr-part");
+    path.with_file_name(name)
+}
+
+/// Whether two files hold the same bytes.
+async fn same_contents(a: &Path, b: &Path) -> std::io::Result<bool> {
+    let mut a = File::open(a).await?;
+    let mut b = File::open(b).await?;
+    if a.metadata().await?.len() != b.metadata().await?.len() {
+        return Ok(false);
+    }
+    let mut buf_a = vec![0; 1 << 20];
+    let mut buf_b = vec![0; 1 << 20];
+    loop {
+        let n = a.read(&mut buf_a).await?;
+        if n == 0 {
+            return Ok(true);
+        }
+        b.read_exact(&mut buf_b[..n]).await?;
+        if buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*; // Our module's functions
     use crate::errors::StorageError; // As used in your file
     use crate::torrent_file::InfoFile; // As used in your file
 
+    use sha1::{Digest, Sha1};
     use std::path::PathBuf;
     use tempfile::tempdir;
     use tokio::fs::{self, File};
@@ -215,11 +633,13 @@ mod tests {
                 path: vec!["file_a.txt".to_string()],
                 length: 50, // Ends at 49
                 md5sum: None,
+                attr: None,
             },
             InfoFile {
                 path: vec!["subdir".to_string(), "file_b.txt".to_string()],
                 length: 70, // Starts at 50
                 md5sum: None,
+                attr: None,
             },
         ];
         // Total size 120
@@ -257,6 +677,77 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_content_root_dir_policies() {
+        let root = PathBuf::from("/downloads");
+
+        assert_eq!(
+            content_root_dir(&root, "name", false, CreateSubfolder::Never),
+            root
+        );
+        assert_eq!(
+            content_root_dir(&root, "name", true, CreateSubfolder::Never),
+            root
+        );
+        assert_eq!(
+            content_root_dir(&root, "name", false, CreateSubfolder::Always),
+            root.join("name")
+        );
+        assert_eq!(
+            content_root_dir(&root, "name", false, CreateSubfolder::MultiFileOnly),
+            root
+        );
+        assert_eq!(
+            content_root_dir(&root, "name", true, CreateSubfolder::MultiFileOnly),
+            root.join("name")
+        );
+    }
+
+    #[test]
+    fn test_multi_file_info_single_file_in_subfolder() {
+        let root = PathBuf::from("/downloads");
+        let content_root = content_root_dir(&root, "movie.mkv", false, CreateSubfolder::Always);
+        let mfi = MultiFileInfo::new(&content_root, "movie.mkv", None, Some(10)).unwrap();
+        assert_eq!(mfi.files[0].path, root.join("movie.mkv").join("movie.mkv"));
+    }
+
+    #[tokio::test]
+    async fn test_remaining_allocation_bytes() {
+        let (_dir, mfi) = setup_multi_file();
+        assert_eq!(remaining_allocation_bytes(&mfi), 120);
+
+        // Once the first file is allocated only the second one is outstanding.
+        fs::write(&mfi.files[0].path, vec![0u8; 50]).await.unwrap();
+        assert_eq!(remaining_allocation_bytes(&mfi), 70);
+
+        create_and_allocate_files(&mfi).await.unwrap();
+        assert_eq!(remaining_allocation_bytes(&mfi), 0);
+    }
+
+    #[test]
+    fn test_available_space_uses_existing_ancestor() {
+        let dir = tempdir().unwrap();
+        let missing = dir.path().join("not").join("created").join("yet");
+        assert!(available_space(&missing).is_ok());
+    }
+
+    #[test]
+    fn test_check_writable() {
+        let dir = tempdir().unwrap();
+        assert!(check_writable(dir.path()).is_ok());
+        assert!(!dir.path().join(".superseedr-write-test").exists());
+
+        // A missing folder, such as an unmounted drive, isn't created.
+        let missing = dir.path().join("downloads");
+        assert!(check_writable(&missing).is_err());
+        assert!(!missing.exists());
+
+        // A file where the folder should be can't be written into.
+        let file_path = dir.path().join("not_a_folder");
+        std::fs::write(&file_path, b"x").unwrap();
+        assert!(check_writable(&file_path).is_err());
+    }
+
     #[tokio::test]
     async fn test_create_and_allocate_files_single() {
         let (_dir, mfi) = setup_single_file();
@@ -391,4 +882,299 @@ mod tests {
         let read_back = read_data_from_disk(&mfi, 90, 10).await.unwrap();
         assert_eq!(read_back, data);
     }
+
+    #[tokio::test]
+    async fn test_padding_files_are_virtual() {
+        let dir = tempdir().unwrap();
+        let files = vec![
+            InfoFile {
+                path: vec!["a.bin".to_string()],
+                length: 10,
+                md5sum: None,
+                attr: None,
+            },
+            InfoFile {
+                path: vec![".pad".to_string(), "6".to_string()],
+                length: 6,
+                md5sum: None,
+                attr: Some("p".to_string()),
+            },
+            InfoFile {
+                path: vec!["b.bin".to_string()],
+                length: 4,
+                md5sum: None,
+                attr: None,
+            },
+        ];
+        let mfi = MultiFileInfo::new(dir.path(), "padded", Some(&files), None).unwrap();
+        assert_eq!(remaining_allocation_bytes(&mfi), 14);
+
+        create_and_allocate_files(&mfi).await.unwrap();
+        assert!(!tokio::fs::try_exists(&mfi.files[1].path).await.unwrap());
+
+        let data: Vec<u8> = (1..=20).collect();
+        write_data_to_disk(&mfi, 0, &data).await.unwrap();
+
+        let mut expected = data.clone();
+        expected[10..16].fill(0);
+        assert_eq!(read_data_from_disk(&mfi, 0, 20).await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_move_files() {
+        let (dir, mfi) = setup_multi_file();
+        create_and_allocate_files(&mfi).await.unwrap();
+        let data: Vec<u8> = (0..120).collect();
+        write_data_to_disk(&mfi, 0, &data).await.unwrap();
+
+        let completed = tempdir().unwrap();
+        let new_root = completed.path().join("done");
+        let moved = move_files(&mfi, dir.path(), &new_root).await.unwrap();
+
+        assert_eq!(moved.files[0].path, new_root.join("file_a.txt"));
+        assert_eq!(
+            moved.files[1].path,
+            new_root.join("subdir").join("file_b.txt")
+        );
+        assert_eq!(read_data_from_disk(&moved, 0, 120).await.unwrap(), data);
+        for file_info in &mfi.files {
+            assert!(!fs::try_exists(&file_info.path).await.unwrap());
+        }
+        // The emptied subfolder goes too, but never the old root
+        assert!(!fs::try_exists(dir.path().join("subdir")).await.unwrap());
+        assert!(fs::try_exists(dir.path()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_move_files_keeps_existing_files() {
+        let (dir, mfi) = setup_multi_file();
+        create_and_allocate_files(&mfi).await.unwrap();
+
+        let new_root = tempdir().unwrap();
+        fs::create_dir_all(new_root.path().join("subdir"))
+            .await
+            .unwrap();
+        fs::write(new_root.path().join("subdir").join("file_b.txt"), b"mine")
+            .await
+            .unwrap();
+
+        let result = move_files(&mfi, dir.path(), new_root.path()).await;
+        assert!(matches!(
+            result,
+            Err(StorageError::Io(e)) if e.kind() == std::io::ErrorKind::AlreadyExists
+        ));
+        // Nothing moved, nothing overwritten
+        for file_info in &mfi.files {
+            assert!(fs::try_exists(&file_info.path).await.unwrap());
+        }
+        assert!(!fs::try_exists(new_root.path().join("file_a.txt"))
+            .await
+            .unwrap());
+        assert_eq!(
+            fs::read(new_root.path().join("subdir").join("file_b.txt"))
+                .await
+                .unwrap(),
+            b"mine"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_same_contents() {
+        let dir = tempdir().unwrap();
+        let (a, b, c) = (
+            dir.path().join("a"),
+            dir.path().join("b"),
+            dir.path().join("c"),
+        );
+        fs::write(&a, vec![7u8; 3 << 20]).await.unwrap();
+        fs::write(&b, vec![7u8; 3 << 20]).await.unwrap();
+        let mut different = vec![7u8; 3 << 20];
+        different[(2 << 20) + 5] = 8;
+        fs::write(&c, different).await.unwrap();
+
+        assert!(same_contents(&a, &b).await.unwrap());
+        assert!(!same_contents(&a, &c).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_rename_file() {
+        let (dir, mut mfi) = setup_multi_file();
+        create_and_allocate_files(&mfi).await.unwrap();
+        let data: Vec<u8> = (0..120).collect();
+        write_data_to_disk(&mfi, 0, &data).await.unwrap();
+
+        rename_file(&mut mfi, dir.path(), 1, Path::new("other/b.bin"))
+            .await
+            .unwrap();
+        assert_eq!(mfi.files[1].path, dir.path().join("other").join("b.bin"));
+        assert!(
+            !fs::try_exists(dir.path().join("subdir").join("file_b.txt"))
+                .await
+                .unwrap()
+        );
+        assert_eq!(read_data_from_disk(&mfi, 0, 120).await.unwrap(), data);
+
+        // Paths outside the folder and paths of other files are refused
+        for bad_path in ["../escape.txt", "/tmp/abs.txt", "file_a.txt", "other"] {
+            assert!(rename_file(&mut mfi, dir.path(), 1, Path::new(bad_path))
+                .await
+                .is_err());
+        }
+        assert!(rename_file(&mut mfi, dir.path(), 5, Path::new("x.txt"))
+            .await
+            .is_err());
+        assert_eq!(mfi.files[1].path, dir.path().join("other").join("b.bin"));
+    }
+
+    #[tokio::test]
+    async fn test_apply_renames() {
+        let (dir, mut mfi) = setup_multi_file();
+        mfi.apply_renames(
+            dir.path(),
+            &[
+                FileRename {
+                    index: 0,
+                    path: PathBuf::from("renamed.txt"),
+                    borrowed: false,
+                },
+                FileRename {
+                    index: 1,
+                    path: PathBuf::from("../outside.txt"),
+                    borrowed: false,
+                },
+                FileRename {
+                    index: 9,
+                    path: PathBuf::from("missing.txt"),
+                    borrowed: false,
+                },
+            ],
+        );
+        assert_eq!(mfi.files[0].path, dir.path().join("renamed.txt"));
+        assert_eq!(
+            mfi.files[1].path,
+            dir.path().join("subdir").join("file_b.txt")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_find_existing_files_under_other_root() {
+        let dir = tempdir().unwrap();
+        let content_root = dir.path().join("multi_file_torrent");
+        let files = vec![
+            InfoFile {
+                path: vec!["file_a.txt".to_string()],
+                length: 50,
+                md5sum: None,
+                attr: None,
+            },
+            InfoFile {
+                path: vec!["subdir".to_string(), "file_b.txt".to_string()],
+                length: 70,
+                md5sum: None,
+                attr: None,
+            },
+        ];
+        let mfi =
+            MultiFileInfo::new(&content_root, "multi_file_torrent", Some(&files), None).unwrap();
+
+        // Nothing there yet
+        assert!(find_existing_files(dir.path(), &content_root, &mfi).is_empty());
+
+        // A folder holding only a same-named file of the wrong size, and a full copy
+        // under another name
+        let other = dir.path().join("Multi File Torrent [other tracker]");
+        fs::create_dir_all(other.join("subdir")).await.unwrap();
+        fs::write(other.join("file_a.txt"), vec![1u8; 50])
+            .await
+            .unwrap();
+        fs::write(other.join("subdir").join("file_b.txt"), vec![2u8; 70])
+            .await
+            .unwrap();
+        let stale = dir.path().join("stale");
+        fs::create_dir_all(&stale).await.unwrap();
+        fs::write(stale.join("file_a.txt"), vec![1u8; 10])
+            .await
+            .unwrap();
+
+        let renames = find_existing_files(dir.path(), &content_root, &mfi);
+        assert_eq!(
+            renames,
+            vec![
+                FileRename {
+                    index: 0,
+                    path: other.join("file_a.txt"),
+                    borrowed: true,
+                },
+                FileRename {
+                    index: 1,
+                    path: other.join("subdir").join("file_b.txt"),
+                    borrowed: true,
+                },
+            ]
+        );
+
+        // Files already where the metadata puts them are left alone
+        fs::create_dir_all(&content_root).await.unwrap();
+        fs::write(content_root.join("file_a.txt"), vec![1u8; 50])
+            .await
+            .unwrap();
+        let renames = find_existing_files(dir.path(), &content_root, &mfi);
+        assert_eq!(renames.len(), 1);
+        assert_eq!(renames[0].index, 1);
+    }
+
+    #[tokio::test]
+    async fn test_existing_files_have_to_match_the_hashes() {
+        let dir = tempdir().unwrap();
+        let content_root = dir.path().join("torrent");
+        let files = vec![
+            InfoFile {
+                path: vec!["file_a.txt".to_string()],
+                length: 50,
+                md5sum: None,
+                attr: None,
+            },
+            InfoFile {
+                path: vec!["file_b.txt".to_string()],
+                length: 70,
+                md5sum: None,
+                attr: None,
+            },
+        ];
+        let mfi = MultiFileInfo::new(&content_root, "torrent", Some(&files), None).unwrap();
+        let data: Vec<u8> = (0..120).collect();
+        let hashes: Vec<PieceHash> = data
+            .chunks(32)
+            .map(|piece| PieceHash::Sha1(Sha1::digest(piece).to_vec()))
+            .collect();
+        let piece_hash = |index: usize| hashes.get(index).cloned();
+        let hash_pool = HashPool::new(1);
+
+        // Same names and sizes, but file_b holds other data
+        let other = dir.path().join("other");
+        fs::create_dir_all(&other).await.unwrap();
+        fs::write(other.join("file_a.txt"), &data[..50])
+            .await
+            .unwrap();
+        fs::write(other.join("file_b.txt"), vec![0u8; 70])
+            .await
+            .unwrap();
+        let renames = find_existing_files(dir.path(), &content_root, &mfi);
+        let mut adopted = mfi.clone();
+        adopted.apply_renames(dir.path(), &renames);
+        assert!(adopted.files.iter().all(|file_info| file_info.is_borrowed));
+        assert!(!files_match_pieces(&adopted, &[0, 1], 32, &piece_hash, &hash_pool).await);
+
+        fs::write(other.join("file_b.txt"), &data[50..])
+            .await
+            .unwrap();
+        assert!(files_match_pieces(&adopted, &[0, 1], 32, &piece_hash, &hash_pool).await);
+
+        // Borrowed files stay where they are when the torrent moves
+        let moved = move_files(&adopted, dir.path(), &dir.path().join("moved"))
+            .await
+            .unwrap();
+        assert_eq!(moved.files[0].path, other.join("file_a.txt"));
+        assert!(other.join("file_a.txt").exists());
+    }
 }
@@ -0,0 +1,451 @@
+// SPDX-FileCopyrightText: 2025 The superseedr Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use crate::app::AppCommand;
+use crate::errors::StorageError;
+use crate::torrent_manager::ManagerCommand;
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc};
+use tracing::{event as tracing_event, Level};
+
+// Bytes asked of the torrent manager at a time while streaming a file
+const STREAM_CHUNK_BYTES: u64 = 1024 * 1024;
+// Request heads longer than this are refused
+const MAX_REQUEST_HEAD_BYTES: usize = 8 * 1024;
+
+// Tells the torrent manager which reads belong to the same connection
+static NEXT_STREAM_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Tells the torrent manager the stream is over when the connection ends, however
+/// it ends.
+struct StreamEndGuard {
+    info_hash: Vec<u8>,
+    stream_id: u64,
+    app_command_tx: mpsc::Sender<AppCommand>,
+}
+
+impl Drop for StreamEndGuard {
+    fn drop(&mut self) {
+        let _ = self.app_command_tx.try_send(AppCommand::Stream(
+            self.info_hash.clone(),
+            ManagerCommand::StreamEnded {
+                stream_id: self.stream_id,
+            },
+        ));
+    }
+}
+
+/// A file of a torrent as the streaming server lists it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamFile {
+    pub name: String,
+    pub size: u64,
+    // BEP 47 pad files are left out of playlists and never served
+    pub is_padding: bool,
+}
+
+/// The `Range` header of a request, before it is checked against the file size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ByteRange {
+    // `bytes=start-` or `bytes=start-end`
+    From(u64, Option<u64>),
+    // `bytes=-length`: the last `length` bytes
+    Suffix(u64),
+}
+
+impl ByteRange {
+    /// Reads a single `bytes=` range. Anything else, multiple ranges included, is
+    /// ignored and the whole file is sent.
+    fn parse(value: &str) -> Option<Self> {
+        let spec = value.trim().strip_prefix("bytes=")?;
+        if spec.contains(',') {
+            return None;
+        }
+        let (start, end) = spec.split_once('-')?;
+        let (start, end) = (start.trim(), end.trim());
+        if start.is_empty() {
+            return end.parse().ok().map(Self::Suffix);
+        }
+        let start = start.parse().ok()?;
+        let end = if end.is_empty() {
+            None
+        } else {
+            Some(end.parse().ok()?)
+        };
+        Some(Self::From(start, end))
+    }
+
+    /// The inclusive byte range this asks for in a file of `size` bytes, or `None`
+    /// when it can't be satisfied.
+    fn resolve(self, size: u64) -> Option<(u64, u64)> {
+        let (start, end) = match self {
+            Self::From(start, end) => (start, end.unwrap_or(u64::MAX).min(size.checked_sub(1)?)),
+            Self::Suffix(0) => return None,
+            Self::Suffix(length) => (size.saturating_sub(length), size.checked_sub(1)?),
+        };
+        (start <= end).then_some((start, end))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct StreamRequest {
+    head_only: bool,
+    info_hash: Vec<u8>,
+    // None asks for a playlist of the torrent's files
+    file_index: Option<usize>,
+    range: Option<ByteRange>,
+}
+
+impl StreamRequest {
+    /// Reads `GET /<hex info hash>[/<file index>]` and its `Range` header.
+    fn parse(head: &str) -> Result<Self, &'static str> {
+        let mut lines = head.lines();
+        let mut request_line = lines.next().unwrap_or("").split_whitespace();
+        let head_only = match request_line.next() {
+            Some("GET") => false,
+            Some("HEAD") => true,
+            _ => return Err("405 Method Not Allowed"),
+        };
+
+        let path = request_line.next().unwrap_or("");
+        let path = path.split(['?', '#']).next().unwrap_or("");
+        let mut segments = path.trim_matches('/').split('/');
+        let info_hash = segments
+            .next()
+            .filter(|segment| segment.len() == 40)
+            .and_then(|segment| hex::decode(segment).ok())
+            .ok_or("404 Not Found")?;
+        let file_index = match segments.next() {
+            Some(segment) => Some(segment.parse().map_err(|_| "404 Not Found")?),
+            None => None,
+        };
+
+        let range = lines
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("range"))
+            .and_then(|(_, value)| ByteRange::parse(value));
+
+        Ok(Self {
+            head_only,
+            info_hash,
+            file_index,
+            range,
+        })
+    }
+}
+
+/// Serves torrent files over HTTP on localhost so a player such as mpv or VLC can
+/// play them while they download. `/<info hash>/<file index>` is a file with Range
+/// support and `/<info hash>` an M3U playlist of every file. Reads wait for their
+/// pieces, which the torrent fetches ahead of everything else.
+pub async fn spawn_server(
+    port: u16,
+    app_command_tx: mpsc::Sender<AppCommand>,
+    mut shutdown_rx: broadcast::Receiver<()>,
+) -> io::Result<SocketAddr> {
+    // Anyone who can connect can read every torrent, so stay on loopback.
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await?;
+    let local_addr = listener.local_addr()?;
+    tracing_event!(
+        Level::INFO,
+        "Streaming server listening on http://{}",
+        local_addr
+    );
+
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = shutdown_rx.recv() => break,
+                accept_result = listener.accept() => match accept_result {
+                    Ok((stream, _)) => {
+                        let app_command_tx = app_command_tx.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = handle_connection(stream, local_addr, app_command_tx).await {
+                                tracing_event!(Level::DEBUG, "Streaming connection ended: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        tracing_event!(Level::WARN, "Streaming server accept failed: {}", e);
+                    }
+                }
+            }
+        }
+    });
+
+    Ok(local_addr)
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    local_addr: SocketAddr,
+    app_command_tx: mpsc::Sender<AppCommand>,
+) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    let mut head = String::new();
+    loop {
+        // Read at most one byte past the limit, so a line without an end can't
+        // grow the head unbounded.
+        let budget = (MAX_REQUEST_HEAD_BYTES + 1 - head.len()) as u64;
+        let read = (&mut reader).take(budget).read_line(&mut head).await?;
+        if head.len() > MAX_REQUEST_HEAD_BYTES {
+            return write_status(&mut writer, "431 Request Header Fields Too Large").await;
+        }
+        if read == 0 || head.ends_with("\r\n\r\n") || head.ends_with("\n\n") {
+            break;
+        }
+    }
+
+    let request = match StreamRequest::parse(&head) {
+        Ok(request) => request,
+        Err(status) => return write_status(&mut writer, status).await,
+    };
+
+    let Some(files) = list_files(&app_command_tx, &request.info_hash).await else {
+        return write_status(&mut writer, "404 Not Found").await;
+    };
+
+    let Some(file_index) = request.file_index else {
+        let playlist = playlist(&files, local_addr, &request.info_hash);
+        let header = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: audio/x-mpegurl\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            playlist.len()
+        );
+        writer.write_all(header.as_bytes()).await?;
+        if !request.head_only {
+            writer.write_all(playlist.as_bytes()).await?;
+        }
+        return writer.flush().await;
+    };
+
+    let Some(file) = files.get(file_index).filter(|file| !file.is_padding) else {
+        return write_status(&mut writer, "404 Not Found").await;
+    };
+
+    let (start, end, status) = match request.range {
+        Some(range) => match range.resolve(file.size) {
+            Some((start, end)) => (start, end, "206 Partial Content"),
+            None => {
+                let header = format!(
+                    "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    file.size
+                );
+                return writer.write_all(header.as_bytes()).await;
+            }
+        },
+        None => (0, file.size.saturating_sub(1), "200 OK"),
+    };
+    let content_length = if file.size == 0 { 0 } else { end - start + 1 };
+
+    let mut header = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\nConnection: close\r\n",
+        status,
+        content_type(&file.name),
+        content_length
+    );
+    if request.range.is_some() {
+        header.push_str(&format!(
+            "Content-Range: bytes {}-{}/{}\r\n",
+            start, end, file.size
+        ));
+    }
+    header.push_str("\r\n");
+    writer.write_all(header.as_bytes()).await?;
+    if request.head_only || content_length == 0 {
+        return writer.flush().await;
+    }
+
+    let stream = StreamEndGuard {
+        info_hash: request.info_hash.clone(),
+        stream_id: NEXT_STREAM_ID.fetch_add(1, Ordering::Relaxed),
+        app_command_tx: app_command_tx.clone(),
+    };
+    let mut offset = start;
+    while offset <= end {
+        let length = STREAM_CHUNK_BYTES.min(end - offset + 1) as usize;
+        let data = read_file(
+            &app_command_tx,
+            &request.info_hash,
+            stream.stream_id,
+            file_index,
+            offset,
+            length,
+        )
+        .await
+        .map_err(|e| io::Error::other(e.to_string()))?;
+        writer.write_all(&data).await?;
+        offset += length as u64;
+    }
+    writer.flush().await
+}
+
+/// The torrent's files, or `None` if it isn't loaded.
+async fn list_files(
+    app_command_tx: &mpsc::Sender<AppCommand>,
+    info_hash: &[u8],
+) -> Option<Vec<StreamFile>> {
+    let (reply_tx, mut reply_rx) = mpsc::channel(1);
+    app_command_tx
+        .send(AppCommand::Stream(
+            info_hash.to_vec(),
+            ManagerCommand::StreamFiles(reply_tx),
+        ))
+        .await
+        .ok()?;
+    reply_rx.recv().await.filter(|files| !files.is_empty())
+}
+
+/// Waits until the torrent has the requested bytes on disk and returns them.
+async fn read_file(
+    app_command_tx: &mpsc::Sender<AppCommand>,
+    info_hash: &[u8],
+    stream_id: u64,
+    file_index: usize,
+    offset: u64,
+    length: usize,
+) -> Result<Vec<u8>, StorageError> {
+    let torrent_gone = || StorageError::Io(io::Error::other("torrent is no longer loaded"));
+    let (reply_tx, mut reply_rx) = mpsc::channel(1);
+    app_command_tx
+        .send(AppCommand::Stream(
+            info_hash.to_vec(),
+            ManagerCommand::StreamRead {
+                stream_id,
+                file_index,
+                offset,
+                length,
+                reply_tx,
+            },
+        ))
+        .await
+        .map_err(|_| torrent_gone())?;
+    reply_rx.recv().await.ok_or_else(torrent_gone)?
+}
+
+fn playlist(files: &[StreamFile], local_addr: SocketAddr, info_hash: &[u8]) -> String {
+    let mut playlist = String::from("#EXTM3U\n");
+    for (index, file) in files.iter().enumerate() {
+        if file.is_padding {
+            continue;
+        }
+        playlist.push_str(&format!(
+            "#EXTINF:-1,{}\nhttp://{}/{}/{}\n",
+            file.name,
+            local_addr,
+            hex::encode(info_hash),
+            index
+        ));
+    }
+    playlist
+}
+
+fn content_type(name: &str) -> &'static str {
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    match extension.as_str() {
+        "mp4" | "m4v" => "video/mp4",
+        "mkv" => "video/x-matroska",
+        "webm" => "video/webm",
+        "avi" => "video/x-msvideo",
+        "mov" => "video/quicktime",
+        "ts" => "video/mp2t",
+        "mp3" => "audio/mpeg",
+        "m4a" => "audio/mp4",
+        "flac" => "audio/flac",
+        "ogg" | "oga" => "audio/ogg",
+        "opus" => "audio/opus",
+        "wav" => "audio/wav",
+        "srt" => "application/x-subrip",
+        "vtt" => "text/vtt",
+        _ => "application/octet-stream",
+    }
+}
+
+async fn write_status(
+    writer: &mut tokio::net::tcp::OwnedWriteHalf,
+    status: &str,
+) -> io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        status
+    );
+    writer.write_all(response.as_bytes()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INFO_HASH_HEX: &str = "c9e15763f722f23e98a29decdfae341b98d53056";
+
+    #[test]
+    fn test_parse_byte_ranges() {
+        assert_eq!(ByteRange::parse("bytes=0-"), Some(ByteRange::From(0, None)));
+        assert_eq!(
+            ByteRange::parse(" bytes=100-199"),
+            Some(ByteRange::From(100, Some(199)))
+        );
+        assert_eq!(ByteRange::parse("bytes=-500"), Some(ByteRange::Suffix(500)));
+        assert_eq!(ByteRange::parse("bytes=0-1,5-6"), None);
+        assert_eq!(ByteRange::parse("items=0-1"), None);
+    }
+
+    #[test]
+    fn test_resolve_byte_ranges() {
+        assert_eq!(ByteRange::From(0, None).resolve(1000), Some((0, 999)));
+        assert_eq!(
+            ByteRange::From(10, Some(5000)).resolve(1000),
+            Some((10, 999))
+        );
+        assert_eq!(ByteRange::From(1000, None).resolve(1000), None);
+        assert_eq!(ByteRange::Suffix(100).resolve(1000), Some((900, 999)));
+        assert_eq!(ByteRange::Suffix(5000).resolve(1000), Some((0, 999)));
+        assert_eq!(ByteRange::Suffix(0).resolve(1000), None);
+        assert_eq!(ByteRange::From(0, None).resolve(0), None);
+    }
+
+    #[test]
+    fn test_parse_requests() {
+        let info_hash = hex::decode(INFO_HASH_HEX).unwrap();
+
+        let request = StreamRequest::parse(&format!(
+            "GET /{}/2 HTTP/1.1\r\nHost: localhost\r\nRange: bytes=1024-\r\n\r\n",
+            INFO_HASH_HEX
+        ))
+        .unwrap();
+        assert_eq!(
+            request,
+            StreamRequest {
+                head_only: false,
+                info_hash: info_hash.clone(),
+                file_index: Some(2),
+                range: Some(ByteRange::From(1024, None)),
+            }
+        );
+
+        let request =
+            StreamRequest::parse(&format!("HEAD /{}?x=1 HTTP/1.1\r\n\r\n", INFO_HASH_HEX)).unwrap();
+        assert!(request.head_only);
+        assert_eq!(request.file_index, None);
+
+        assert_eq!(
+            StreamRequest::parse("POST / HTTP/1.1\r\n\r\n"),
+            Err("405 Method Not Allowed")
+        );
+        assert_eq!(
+            StreamRequest::parse("GET /nothex/0 HTTP/1.1\r\n\r\n"),
+            Err("404 Not Found")
+        );
+    }
+}
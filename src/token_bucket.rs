@@ -1,15 +1,84 @@
 // SPDX-FileCopyrightText: 2025 The superseedr Contributors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 
+/// How much of the global limits a torrent gets while others want them too. While
+/// several priorities are waiting for tokens, the refill is split between them by
+/// weight, so High gets four times what Low does; a torrent on its own still gets
+/// all of it.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BandwidthPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl BandwidthPriority {
+    pub fn label(self) -> &'static str {
+        match self {
+            BandwidthPriority::Low => "Low",
+            BandwidthPriority::Normal => "Normal",
+            BandwidthPriority::High => "High",
+        }
+    }
+
+    /// Low, Normal, High, then back to Low.
+    pub fn next(self) -> Self {
+        match self {
+            BandwidthPriority::Low => BandwidthPriority::Normal,
+            BandwidthPriority::Normal => BandwidthPriority::High,
+            BandwidthPriority::High => BandwidthPriority::Low,
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "low" => Some(BandwidthPriority::Low),
+            "normal" => Some(BandwidthPriority::Normal),
+            "high" => Some(BandwidthPriority::High),
+            _ => None,
+        }
+    }
+
+    /// Share of the refill, relative to the other priorities waiting.
+    pub fn weight(self) -> f64 {
+        match self {
+            BandwidthPriority::Low => 1.0,
+            BandwidthPriority::Normal => 2.0,
+            BandwidthPriority::High => 4.0,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+const PRIORITIES: [BandwidthPriority; 3] = [
+    BandwidthPriority::Low,
+    BandwidthPriority::Normal,
+    BandwidthPriority::High,
+];
+
+// A priority counts as waiting until this long after its consumers meant to check back
+const WAIT_LEASE: Duration = Duration::from_millis(50);
+// Longest a consumer sleeps before checking again, in case its share grew
+const MAX_WAIT: Duration = Duration::from_millis(250);
+
 pub struct TokenBucket {
     last_refill_time: Instant,
     tokens: f64,
     fill_rate: f64, // tokens per second
     capacity: f64,
+    // Per priority, tokens set aside for its waiting consumers and until when it is
+    // waiting. Nobody else can take them.
+    reserved: [f64; 3],
+    waiting_until: [Option<Instant>; 3],
 }
 
 impl TokenBucket {
@@ -27,6 +96,8 @@ impl TokenBucket {
             tokens: initial_tokens,
             fill_rate: sane_fill_rate,
             capacity: initial_capacity,
+            reserved: [0.0; 3],
+            waiting_until: [None; 3],
         }
     }
 
@@ -41,9 +112,19 @@ impl TokenBucket {
             self.capacity = rate; // Assuming capacity matches rate
             self.tokens = rate;
         }
+        self.reserved = [0.0; 3];
         self.last_refill_time = Instant::now();
     }
 
+    /// Total weight of the priorities with consumers waiting.
+    fn waiting_weight(&self) -> f64 {
+        PRIORITIES
+            .iter()
+            .filter(|priority| self.waiting_until[priority.index()].is_some())
+            .map(|priority| priority.weight())
+            .sum()
+    }
+
     fn refill(&mut self) {
         if self.capacity.is_infinite() {
             self.tokens = f64::INFINITY;
@@ -53,14 +134,67 @@ impl TokenBucket {
         let now = Instant::now();
         let elapsed = now.saturating_duration_since(self.last_refill_time);
         self.last_refill_time = now;
+
+        // Priorities nobody is waiting on any more give back what was set aside
+        for index in 0..PRIORITIES.len() {
+            if self.waiting_until[index].is_some_and(|until| until <= now) {
+                self.waiting_until[index] = None;
+                self.tokens += std::mem::take(&mut self.reserved[index]);
+            }
+        }
+        self.tokens = self.tokens.min(self.capacity);
+
         if self.fill_rate > 0.0 && self.fill_rate.is_finite() {
             let tokens_to_add = elapsed.as_secs_f64() * self.fill_rate;
-            self.tokens = (self.tokens + tokens_to_add).min(self.capacity);
+            let waiting_weight = self.waiting_weight();
+            if waiting_weight == 0.0 {
+                self.tokens = (self.tokens + tokens_to_add).min(self.capacity);
+            } else {
+                for priority in PRIORITIES {
+                    let index = priority.index();
+                    if self.waiting_until[index].is_some() {
+                        let share = tokens_to_add * priority.weight() / waiting_weight;
+                        self.reserved[index] = (self.reserved[index] + share).min(self.capacity);
+                    }
+                }
+            }
         }
     }
+
+    /// Takes `amount` tokens for a consumer of `priority`, from its reserve first. If
+    /// there aren't enough, marks the priority as waiting and returns how long until
+    /// its share of the refill should cover the rest.
+    fn take(&mut self, amount: f64, priority: BandwidthPriority) -> Option<Duration> {
+        self.refill();
+        let index = priority.index();
+        let available = self.tokens + self.reserved[index];
+        if available >= amount {
+            let from_reserve = amount.min(self.reserved[index]);
+            self.reserved[index] -= from_reserve;
+            self.tokens -= amount - from_reserve;
+            return None;
+        }
+
+        let mut waiting_weight = self.waiting_weight();
+        if self.waiting_until[index].is_none() {
+            waiting_weight += priority.weight();
+        }
+        let share_rate = self.fill_rate * priority.weight() / waiting_weight;
+        let wait =
+            Duration::from_secs_f64(((amount - available) / share_rate).max(0.001)).min(MAX_WAIT);
+        let until = Instant::now() + wait + WAIT_LEASE;
+        self.waiting_until[index] = Some(self.waiting_until[index].map_or(until, |u| u.max(until)));
+        Some(wait)
+    }
 }
 
-pub async fn consume_tokens(bucket_arc: &Arc<Mutex<TokenBucket>>, amount_tokens: f64) {
+/// Waits until `amount_tokens` can be taken from the bucket. While the bucket is short
+/// each waiting `priority` gets its weighted share of the refill.
+pub async fn consume_tokens(
+    bucket_arc: &Arc<Mutex<TokenBucket>>,
+    amount_tokens: f64,
+    priority: BandwidthPriority,
+) {
     if amount_tokens < 0.0 || !amount_tokens.is_finite() {
         return;
     }
@@ -88,18 +222,11 @@ pub async fn consume_tokens(bucket_arc: &Arc<Mutex<TokenBucket>>, amount_tokens:
         }
 
         loop {
-            let wait_time = {
-                let mut bucket = bucket_arc.lock().await;
-                bucket.refill();
-                if bucket.tokens >= amount_tokens {
-                    bucket.tokens -= amount_tokens;
-                    break;
-                }
-                let tokens_needed = amount_tokens - bucket.tokens;
-                let wait_duration_secs = tokens_needed / current_fill_rate;
-                Duration::from_secs_f64(wait_duration_secs.max(0.001))
-            };
-            tokio::time::sleep(wait_time).await;
+            let wait_time = bucket_arc.lock().await.take(amount_tokens, priority);
+            match wait_time {
+                Some(wait_time) => tokio::time::sleep(wait_time).await,
+                None => break,
+            }
         }
     }
 }
@@ -225,7 +352,7 @@ mod tests {
     async fn test_consume_tokens_unlimited_zero_rate_direct() {
         let bucket = Arc::new(Mutex::new(TokenBucket::new(100.0, 0.0)));
         let start = Instant::now();
-        consume_tokens(&bucket, 1_000_000.0).await;
+        consume_tokens(&bucket, 1_000_000.0, BandwidthPriority::Normal).await;
         let elapsed = start.elapsed();
         assert!(elapsed < Duration::from_millis(50));
         let locked_bucket = bucket.lock().await;
@@ -238,7 +365,7 @@ mod tests {
         let bucket = Arc::new(Mutex::new(TokenBucket::new(100.0, 10.0)));
         bucket.lock().await.set_rate(0.0);
         let start = Instant::now();
-        consume_tokens(&bucket, 1_000_000.0).await;
+        consume_tokens(&bucket, 1_000_000.0, BandwidthPriority::Normal).await;
         let elapsed = start.elapsed();
         assert!(elapsed < Duration::from_millis(50));
         let locked_bucket = bucket.lock().await;
@@ -249,7 +376,7 @@ mod tests {
     #[tokio::test]
     async fn test_consume_tokens_immediate_success_direct() {
         let bucket = Arc::new(Mutex::new(TokenBucket::new(1000.0, 100.0)));
-        consume_tokens(&bucket, 500.0).await;
+        consume_tokens(&bucket, 500.0, BandwidthPriority::Normal).await;
         assert!((bucket.lock().await.tokens - 500.0).abs() < TOLERANCE);
     }
 
@@ -260,7 +387,7 @@ mod tests {
         assert!(bucket.lock().await.tokens.abs() < TOLERANCE);
 
         let start = Instant::now();
-        consume_tokens(&bucket, 500.0).await; // Needs 0.5s
+        consume_tokens(&bucket, 500.0, BandwidthPriority::Normal).await; // Needs 0.5s
         let elapsed = start.elapsed();
 
         let target_wait = 0.5;
@@ -282,6 +409,95 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_consume_tokens_priority_goes_first() {
+        let bucket = Arc::new(Mutex::new(TokenBucket::new(1000.0, 1000.0)));
+        bucket.lock().await.tokens = 0.0;
+
+        let low_bucket = Arc::clone(&bucket);
+        let high_bucket = Arc::clone(&bucket);
+        let start = Instant::now();
+        let low = tokio::spawn(async move {
+            consume_tokens(&low_bucket, 300.0, BandwidthPriority::Low).await;
+            start.elapsed()
+        });
+        let high = tokio::spawn(async move {
+            consume_tokens(&high_bucket, 300.0, BandwidthPriority::High).await;
+            start.elapsed()
+        });
+        let (low, high) = (low.await.unwrap(), high.await.unwrap());
+
+        // Both need 0.3s of tokens; high gets 4/5 of the refill while both wait.
+        assert!(
+            high.as_secs_f64() < 0.3 + TIMING_TOLERANCE,
+            "Expected the high priority consumer done after ~0.3s, got {:?}",
+            high
+        );
+        assert!(
+            low > high,
+            "Expected the low priority consumer after the high one, got {:?} and {:?}",
+            low,
+            high
+        );
+    }
+
+    #[tokio::test]
+    async fn test_consume_tokens_low_priority_alone_gets_full_rate() {
+        let bucket = Arc::new(Mutex::new(TokenBucket::new(1000.0, 1000.0)));
+        bucket.lock().await.tokens = 0.0;
+
+        let start = Instant::now();
+        consume_tokens(&bucket, 500.0, BandwidthPriority::Low).await; // Needs 0.5s
+        let elapsed = start.elapsed();
+        assert!(
+            (elapsed.as_secs_f64() - 0.5).abs() < TIMING_TOLERANCE,
+            "Expected ~0.5s on its own, got {:?}",
+            elapsed
+        );
+    }
+
+    #[tokio::test]
+    async fn test_consume_tokens_splits_rate_by_priority() {
+        let bucket = Arc::new(Mutex::new(TokenBucket::new(1000.0, 1000.0)));
+        bucket.lock().await.tokens = 0.0;
+
+        let consumer = |priority| {
+            let bucket = Arc::clone(&bucket);
+            tokio::spawn(async move {
+                let mut consumed = 0.0;
+                let deadline = Instant::now() + Duration::from_secs(2);
+                while Instant::now() < deadline {
+                    consume_tokens(&bucket, 20.0, priority).await;
+                    consumed += 20.0;
+                }
+                consumed
+            })
+        };
+        let low = consumer(BandwidthPriority::Low);
+        let high = consumer(BandwidthPriority::High);
+        let (low, high) = (low.await.unwrap(), high.await.unwrap());
+
+        // 1000 tokens/s split 1:4 is about 400 and 1600 over the 2s
+        let ratio = high / low;
+        assert!(
+            (3.0..=5.5).contains(&ratio),
+            "Expected high to get ~4x low, got {} and {}",
+            high,
+            low
+        );
+    }
+
+    #[test]
+    fn test_bandwidth_priority_parse_and_cycle() {
+        assert_eq!(
+            BandwidthPriority::parse("HIGH"),
+            Some(BandwidthPriority::High)
+        );
+        assert_eq!(BandwidthPriority::parse("urgent"), None);
+        assert_eq!(BandwidthPriority::High.next(), BandwidthPriority::Low);
+        assert!(BandwidthPriority::High.weight() > BandwidthPriority::Low.weight());
+    }
+
     #[tokio::test]
     async fn test_consume_tokens_large_request_direct() {
         let bucket = Arc::new(Mutex::new(TokenBucket::new(100.0, 1000.0)));
@@ -289,7 +505,7 @@ mod tests {
         assert!((initial_tokens - 100.0).abs() < TOLERANCE);
 
         let start = Instant::now();
-        consume_tokens(&bucket, 500.0).await; // Needs 0.5s sleep
+        consume_tokens(&bucket, 500.0, BandwidthPriority::Normal).await; // Needs 0.5s sleep
         let elapsed = start.elapsed();
 
         let target_wait = 0.5;
@@ -320,10 +536,10 @@ mod tests {
         let start = Instant::now();
 
         let task_1 = tokio::spawn(async move {
-            consume_tokens(&bucket_1, 500.0).await;
+            consume_tokens(&bucket_1, 500.0, BandwidthPriority::Normal).await;
         }); // Needs 0.5s
         let task_2 = tokio::spawn(async move {
-            consume_tokens(&bucket_2, 1000.0).await;
+            consume_tokens(&bucket_2, 1000.0, BandwidthPriority::Normal).await;
         }); // Needs 1.0s
 
         let (res1, res2) = tokio::join!(task_1, task_2);
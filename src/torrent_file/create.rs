@@ -0,0 +1,611 @@
+// SPDX-FileCopyrightText: 2025 The superseedr Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Making a torrent out of a file or folder: hashing its pieces and encoding the
+//! metainfo for BitTorrent v1, v2 (BEP 52) or both.
+
+use crate::errors::CreateTorrentError;
+use crate::torrent_file::v2::{self, MERKLE_BLOCK_SIZE};
+
+use serde_bencode::value::Value;
+use sha1::{Digest, Sha1};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Automatic piece sizes aim for about this many pieces
+const TARGET_PIECE_COUNT: u64 = 1500;
+// v2 pieces are whole merkle subtrees, so no smaller than one 16 KiB block
+pub const MIN_PIECE_LENGTH: u64 = MERKLE_BLOCK_SIZE as u64;
+pub const MAX_PIECE_LENGTH: u64 = 16 << 20;
+
+/// Which hashes a new torrent carries.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TorrentVersion {
+    V1,
+    V2,
+    // v1 and v2 hashes side by side, so clients of either kind can join (BEP 52)
+    #[default]
+    Hybrid,
+}
+
+impl TorrentVersion {
+    fn has_v1(self) -> bool {
+        self != TorrentVersion::V2
+    }
+
+    fn has_v2(self) -> bool {
+        self != TorrentVersion::V1
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            TorrentVersion::V1 => TorrentVersion::V2,
+            TorrentVersion::V2 => TorrentVersion::Hybrid,
+            TorrentVersion::Hybrid => TorrentVersion::V1,
+        }
+    }
+}
+
+impl fmt::Display for TorrentVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TorrentVersion::V1 => "v1",
+            TorrentVersion::V2 => "v2",
+            TorrentVersion::Hybrid => "hybrid",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for TorrentVersion {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "v1" | "1" => Ok(TorrentVersion::V1),
+            "v2" | "2" => Ok(TorrentVersion::V2),
+            "hybrid" => Ok(TorrentVersion::Hybrid),
+            _ => Err(format!("'{}' is not v1, v2 or hybrid", value)),
+        }
+    }
+}
+
+/// What goes into a new torrent.
+#[derive(Clone, Debug, Default)]
+pub struct CreateOptions {
+    // The file or folder to share; its name becomes the torrent's name
+    pub path: PathBuf,
+    // None picks one from the total size, see `auto_piece_length`
+    pub piece_length: Option<u64>,
+    // Announce URLs, each in a tier of its own
+    pub trackers: Vec<String>,
+    // Web seeds (BEP 19): URLs the same files can be fetched from over HTTP
+    pub web_seeds: Vec<String>,
+    pub private: bool,
+    pub version: TorrentVersion,
+    pub comment: Option<String>,
+}
+
+/// A piece size for `total_size` bytes: the power of two that gives about
+/// `TARGET_PIECE_COUNT` pieces, kept between 16 KiB and 16 MiB.
+pub fn auto_piece_length(total_size: u64) -> u64 {
+    (total_size / TARGET_PIECE_COUNT)
+        .next_power_of_two()
+        .clamp(MIN_PIECE_LENGTH, MAX_PIECE_LENGTH)
+}
+
+/// Reads a piece size such as `16384`, `256K` or `4M` (binary units).
+pub fn parse_piece_length(value: &str) -> Option<u64> {
+    let upper = value.trim().to_ascii_uppercase();
+    let digits = upper.trim_end_matches(['B', 'I']);
+    let (number, multiplier) = if let Some(number) = digits.strip_suffix('K') {
+        (number, 1 << 10)
+    } else if let Some(number) = digits.strip_suffix('M') {
+        (number, 1 << 20)
+    } else {
+        (digits, 1)
+    };
+    number.trim().parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// Hashes the file or folder at `options.path` and returns the encoded .torrent.
+/// Every byte is read, so async callers should run this on a blocking thread.
+pub fn create_torrent(options: &CreateOptions) -> Result<Vec<u8>, CreateTorrentError> {
+    let root = fs::canonicalize(&options.path).map_err(|e| io_error(&options.path, e))?;
+    let name = root
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| CreateTorrentError::InvalidName(root.clone()))?
+        .to_string();
+
+    let files = collect_files(&root)?;
+    let total_size: u64 = files.iter().map(|file| file.length).sum();
+    if total_size == 0 {
+        return Err(CreateTorrentError::NoFiles);
+    }
+    let piece_length = match options.piece_length {
+        Some(length) if length < MIN_PIECE_LENGTH || !length.is_power_of_two() => {
+            return Err(CreateTorrentError::InvalidPieceLength(length))
+        }
+        Some(length) => length,
+        None => auto_piece_length(total_size),
+    };
+
+    // A lone file is a single-file torrent, which v2 lists under the torrent's name.
+    let single_file = files.len() == 1 && files[0].path.is_empty();
+    let mut hashes = Hashes::new(piece_length as usize, options.version);
+    for file in &files {
+        let tree_path = if single_file {
+            std::slice::from_ref(&name)
+        } else {
+            file.path.as_slice()
+        };
+        hashes.add_file(file, tree_path)?;
+    }
+    hashes.finish();
+
+    let mut info = HashMap::from([
+        (b"name".to_vec(), Value::Bytes(name.into_bytes())),
+        (b"piece length".to_vec(), Value::Int(piece_length as i64)),
+    ]);
+    if options.private {
+        info.insert(b"private".to_vec(), Value::Int(1));
+    }
+    if options.version.has_v1() {
+        info.insert(b"pieces".to_vec(), Value::Bytes(hashes.v1_pieces));
+        if single_file {
+            info.insert(b"length".to_vec(), Value::Int(total_size as i64));
+        } else {
+            info.insert(b"files".to_vec(), Value::List(hashes.v1_files));
+        }
+    }
+    if options.version.has_v2() {
+        info.insert(b"meta version".to_vec(), Value::Int(2));
+        info.insert(b"file tree".to_vec(), Value::Dict(hashes.file_tree));
+    }
+
+    let creation_date = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs() as i64)
+        .unwrap_or(0);
+    let mut torrent = HashMap::from([
+        (b"info".to_vec(), Value::Dict(info)),
+        (
+            b"created by".to_vec(),
+            Value::Bytes(format!("superseedr/{}", env!("CARGO_PKG_VERSION")).into_bytes()),
+        ),
+        (b"creation date".to_vec(), Value::Int(creation_date)),
+    ]);
+    if let Some(tracker) = options.trackers.first() {
+        torrent.insert(b"announce".to_vec(), string_value(tracker));
+    }
+    if options.trackers.len() > 1 {
+        let tiers = options
+            .trackers
+            .iter()
+            .map(|tracker| Value::List(vec![string_value(tracker)]))
+            .collect();
+        torrent.insert(b"announce-list".to_vec(), Value::List(tiers));
+    }
+    if !options.web_seeds.is_empty() {
+        let web_seeds = options
+            .web_seeds
+            .iter()
+            .map(|url| string_value(url))
+            .collect();
+        torrent.insert(b"url-list".to_vec(), Value::List(web_seeds));
+    }
+    if let Some(comment) = options.comment.as_deref().filter(|c| !c.is_empty()) {
+        torrent.insert(b"comment".to_vec(), string_value(comment));
+    }
+    if options.version.has_v2() {
+        torrent.insert(b"piece layers".to_vec(), Value::Dict(hashes.piece_layers));
+    }
+
+    Ok(serde_bencode::to_bytes(&Value::Dict(torrent))?)
+}
+
+/// A file to hash.
+struct SourceFile {
+    disk_path: PathBuf,
+    // Path inside the torrent, below its name; empty for a single-file torrent
+    path: Vec<String>,
+    length: u64,
+}
+
+/// Writes a .torrent made by `create_torrent` to `path`, which must not exist yet.
+pub fn write_torrent(path: &Path, bytes: &[u8]) -> Result<(), CreateTorrentError> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(|e| match e.kind() {
+            ErrorKind::AlreadyExists => CreateTorrentError::AlreadyExists(path.to_path_buf()),
+            _ => io_error(path, e),
+        })?;
+    file.write_all(bytes).map_err(|e| io_error(path, e))
+}
+
+/// Lists the file at `root`, or every file under it in the order the v2 file tree
+/// will have them: by path, one component at a time.
+fn collect_files(root: &Path) -> Result<Vec<SourceFile>, CreateTorrentError> {
+    let metadata = fs::metadata(root).map_err(|e| io_error(root, e))?;
+    if metadata.is_file() {
+        return Ok(vec![SourceFile {
+            disk_path: root.to_path_buf(),
+            path: Vec::new(),
+            length: metadata.len(),
+        }]);
+    }
+
+    let mut files = Vec::new();
+    walk_dir(root, &mut Vec::new(), &mut files)?;
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+fn walk_dir(
+    dir: &Path,
+    path: &mut Vec<String>,
+    files: &mut Vec<SourceFile>,
+) -> Result<(), CreateTorrentError> {
+    for entry in fs::read_dir(dir).map_err(|e| io_error(dir, e))? {
+        let entry = entry.map_err(|e| io_error(dir, e))?;
+        let disk_path = entry.path();
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|_| CreateTorrentError::InvalidName(disk_path.clone()))?;
+        let file_type = entry.file_type().map_err(|e| io_error(&disk_path, e))?;
+
+        path.push(name);
+        if file_type.is_dir() {
+            walk_dir(&disk_path, path, files)?;
+        } else {
+            // Links to files are followed; links to folders are not, so a link loop
+            // can't make the walk endless.
+            let metadata = fs::metadata(&disk_path).map_err(|e| io_error(&disk_path, e))?;
+            if metadata.is_file() {
+                files.push(SourceFile {
+                    disk_path,
+                    path: path.clone(),
+                    length: metadata.len(),
+                });
+            }
+        }
+        path.pop();
+    }
+    Ok(())
+}
+
+/// Piece hashes, collected as the files are read in order.
+struct Hashes {
+    piece_length: usize,
+    version: TorrentVersion,
+    // v1: SHA-1 of each piece; pieces run across file boundaries
+    v1_pieces: Vec<u8>,
+    v1_piece: Vec<u8>,
+    // v1 file list, with the pad files a hybrid torrent needs
+    v1_files: Vec<Value>,
+    // v2: the file tree and the piece layers of files longer than a piece
+    file_tree: HashMap<Vec<u8>, Value>,
+    piece_layers: HashMap<Vec<u8>, Value>,
+}
+
+impl Hashes {
+    fn new(piece_length: usize, version: TorrentVersion) -> Self {
+        Self {
+            piece_length,
+            version,
+            v1_pieces: Vec::new(),
+            v1_piece: Vec::with_capacity(if version.has_v1() { piece_length } else { 0 }),
+            v1_files: Vec::new(),
+            file_tree: HashMap::new(),
+            piece_layers: HashMap::new(),
+        }
+    }
+
+    fn add_file(
+        &mut self,
+        file: &SourceFile,
+        tree_path: &[String],
+    ) -> Result<(), CreateTorrentError> {
+        if self.version.has_v1() {
+            // In a hybrid torrent every file starts on a piece boundary, so the v1
+            // and v2 pieces are the same bytes.
+            if self.version.has_v2() && !self.v1_piece.is_empty() && file.length > 0 {
+                let pad_len = self.piece_length - self.v1_piece.len();
+                self.v1_piece.resize(self.piece_length, 0);
+                self.finish_v1_piece();
+                self.v1_files.push(v1_file_entry(
+                    pad_len as u64,
+                    vec![".pad".to_string(), pad_len.to_string()],
+                    true,
+                ));
+            }
+            self.v1_files
+                .push(v1_file_entry(file.length, file.path.clone(), false));
+        }
+
+        let mut reader = File::open(&file.disk_path).map_err(|e| io_error(&file.disk_path, e))?;
+        let mut buffer = vec![0; self.piece_length.min(file.length as usize)];
+        let leaves_per_piece = self.piece_length / MERKLE_BLOCK_SIZE;
+        let mut piece_roots = Vec::new();
+        let mut remaining = file.length;
+        while remaining > 0 {
+            let len = remaining.min(self.piece_length as u64) as usize;
+            reader
+                .read_exact(&mut buffer[..len])
+                .map_err(|e| io_error(&file.disk_path, e))?;
+            if self.version.has_v1() {
+                self.push_v1_data(&buffer[..len]);
+            }
+            if self.version.has_v2() && file.length > self.piece_length as u64 {
+                piece_roots.push(v2::merkle_root(&buffer[..len], leaves_per_piece));
+            }
+            remaining -= len as u64;
+        }
+
+        if self.version.has_v2() {
+            let mut entry = HashMap::from([(b"length".to_vec(), Value::Int(file.length as i64))]);
+            if file.length > 0 {
+                // A file of one piece spans only as many leaves as it has blocks.
+                let pieces_root = if piece_roots.is_empty() {
+                    v2::merkle_root(&buffer, 1)
+                } else {
+                    let root = v2::file_pieces_root(&piece_roots, leaves_per_piece);
+                    self.piece_layers
+                        .insert(root.to_vec(), Value::Bytes(piece_roots.concat()));
+                    root
+                };
+                entry.insert(b"pieces root".to_vec(), Value::Bytes(pieces_root.to_vec()));
+            }
+            insert_file(&mut self.file_tree, tree_path, Value::Dict(entry));
+        }
+        Ok(())
+    }
+
+    fn push_v1_data(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let take = (self.piece_length - self.v1_piece.len()).min(data.len());
+            self.v1_piece.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.v1_piece.len() == self.piece_length {
+                self.finish_v1_piece();
+            }
+        }
+    }
+
+    fn finish_v1_piece(&mut self) {
+        self.v1_pieces
+            .extend_from_slice(&Sha1::digest(&self.v1_piece));
+        self.v1_piece.clear();
+    }
+
+    /// Hashes the short last piece.
+    fn finish(&mut self) {
+        if !self.v1_piece.is_empty() {
+            self.finish_v1_piece();
+        }
+    }
+}
+
+fn v1_file_entry(length: u64, path: Vec<String>, is_padding: bool) -> Value {
+    let path = path
+        .into_iter()
+        .map(|part| Value::Bytes(part.into_bytes()))
+        .collect();
+    let mut entry = HashMap::from([
+        (b"length".to_vec(), Value::Int(length as i64)),
+        (b"path".to_vec(), Value::List(path)),
+    ]);
+    if is_padding {
+        entry.insert(b"attr".to_vec(), Value::Bytes(b"p".to_vec()));
+    }
+    Value::Dict(entry)
+}
+
+/// Puts a file's entry into a v2 file tree, adding the folders on its path.
+fn insert_file(tree: &mut HashMap<Vec<u8>, Value>, path: &[String], entry: Value) {
+    let Some((name, rest)) = path.split_first() else {
+        return;
+    };
+    let key = name.as_bytes().to_vec();
+    if rest.is_empty() {
+        tree.insert(key, Value::Dict(HashMap::from([(Vec::new(), entry)])));
+    } else if let Value::Dict(dir) = tree
+        .entry(key)
+        .or_insert_with(|| Value::Dict(HashMap::new()))
+    {
+        insert_file(dir, rest, entry);
+    }
+}
+
+fn string_value(value: &str) -> Value {
+    Value::Bytes(value.as_bytes().to_vec())
+}
+
+fn io_error(path: &Path, source: std::io::Error) -> CreateTorrentError {
+    CreateTorrentError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::torrent_file::parser::from_bytes;
+    use tempfile::tempdir;
+
+    const PIECE: u64 = MIN_PIECE_LENGTH;
+
+    fn data(len: usize, seed: u8) -> Vec<u8> {
+        (0..len)
+            .map(|i| (i as u8).wrapping_mul(31) ^ seed)
+            .collect()
+    }
+
+    // a.txt (40000 bytes), empty.txt and sub/b.bin (30000 bytes) in a folder "pack"
+    fn make_folder(dir: &Path) -> PathBuf {
+        let root = dir.join("pack");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a.txt"), data(40000, 1)).unwrap();
+        fs::write(root.join("empty.txt"), b"").unwrap();
+        fs::write(root.join("sub").join("b.bin"), data(30000, 2)).unwrap();
+        root
+    }
+
+    fn options(path: PathBuf, version: TorrentVersion) -> CreateOptions {
+        CreateOptions {
+            path,
+            piece_length: Some(PIECE),
+            version,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_auto_piece_length() {
+        assert_eq!(auto_piece_length(0), MIN_PIECE_LENGTH);
+        assert_eq!(auto_piece_length(1_500_000_000), 1 << 20);
+        assert_eq!(auto_piece_length(u64::MAX / 2), MAX_PIECE_LENGTH);
+    }
+
+    #[test]
+    fn test_parse_piece_length() {
+        assert_eq!(parse_piece_length("16384"), Some(16384));
+        assert_eq!(parse_piece_length("256K"), Some(256 << 10));
+        assert_eq!(parse_piece_length("4MiB"), Some(4 << 20));
+        assert_eq!(parse_piece_length("big"), None);
+    }
+
+    #[test]
+    fn test_version_from_str() {
+        assert_eq!("V2".parse(), Ok(TorrentVersion::V2));
+        assert_eq!(
+            TorrentVersion::Hybrid.to_string().parse(),
+            Ok(TorrentVersion::Hybrid)
+        );
+        assert!("v3".parse::<TorrentVersion>().is_err());
+    }
+
+    #[test]
+    fn test_v1_folder_round_trips_through_parser() {
+        let dir = tempdir().unwrap();
+        let root = make_folder(dir.path());
+        let mut options = options(root, TorrentVersion::V1);
+        options.trackers = vec!["http://a/announce".into(), "udp://b:80".into()];
+        options.web_seeds = vec!["http://seed/pack/".into()];
+
+        let torrent = from_bytes(&create_torrent(&options).unwrap()).unwrap();
+        assert_eq!(torrent.info.name, "pack");
+        assert_eq!(torrent.info.meta_version, None);
+        let paths: Vec<String> = torrent
+            .info
+            .files
+            .iter()
+            .map(|f| f.path.join("/"))
+            .collect();
+        assert_eq!(paths, ["a.txt", "empty.txt", "sub/b.bin"]);
+        assert_eq!(
+            torrent.announce_tiers(),
+            vec![
+                vec!["http://a/announce".to_string()],
+                vec!["udp://b:80".to_string()]
+            ]
+        );
+
+        // Pieces run across the file boundary.
+        let all = [data(40000, 1), data(30000, 2)].concat();
+        assert_eq!(torrent.piece_count(), all.len().div_ceil(PIECE as usize));
+        for (index, piece) in all.chunks(PIECE as usize).enumerate() {
+            assert!(torrent.piece_hash(index).unwrap().matches(piece));
+        }
+    }
+
+    #[test]
+    fn test_hybrid_pads_files_and_agrees_with_v2_hashes() {
+        let dir = tempdir().unwrap();
+        let options = options(make_folder(dir.path()), TorrentVersion::Hybrid);
+
+        let torrent = from_bytes(&create_torrent(&options).unwrap()).unwrap();
+        assert!(!torrent.is_v2_only());
+        let lengths: Vec<i64> = torrent.info.files.iter().map(|f| f.length).collect();
+        let pad = 3 * PIECE as i64 - 40000;
+        assert_eq!(lengths, [40000, 0, pad, 30000]);
+        assert!(torrent.info.files[2].is_padding());
+
+        // The v2 side describes the same layout and the same pieces.
+        let v2_files = v2::parse_file_tree(torrent.info.file_tree.as_ref().unwrap()).unwrap();
+        let layout = v2::build_layout(&v2_files, PIECE, torrent.piece_layers.as_ref()).unwrap();
+        let v2_lengths: Vec<i64> = layout.files.iter().map(|f| f.length).collect();
+        assert_eq!(v2_lengths, lengths);
+        assert_eq!(layout.pieces.len(), torrent.piece_count());
+
+        let mut first = data(40000, 1);
+        first.resize(3 * PIECE as usize, 0);
+        let all = [first, data(30000, 2)].concat();
+        for (index, piece) in all.chunks(PIECE as usize).enumerate() {
+            assert!(torrent.piece_hash(index).unwrap().matches(piece));
+            assert!(layout.pieces[index].matches(piece));
+        }
+    }
+
+    #[test]
+    fn test_v2_single_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("movie.mkv");
+        let contents = data(50000, 3);
+        fs::write(&path, &contents).unwrap();
+        let mut options = options(path, TorrentVersion::V2);
+        options.private = true;
+
+        let torrent = from_bytes(&create_torrent(&options).unwrap()).unwrap();
+        assert!(torrent.is_v2_only());
+        assert_eq!(torrent.info.name, "movie.mkv");
+        assert_eq!(torrent.info.length, 50000);
+        assert_eq!(torrent.info.private, Some(1));
+        assert_eq!(torrent.piece_count(), 4);
+        for (index, piece) in contents.chunks(PIECE as usize).enumerate() {
+            assert!(torrent.piece_hash(index).unwrap().matches(piece));
+        }
+    }
+
+    #[test]
+    fn test_rejects_bad_piece_length_and_empty_folders() {
+        let dir = tempdir().unwrap();
+        let root = make_folder(dir.path());
+        let mut bad = options(root, TorrentVersion::V1);
+        bad.piece_length = Some(40000);
+        assert!(matches!(
+            create_torrent(&bad),
+            Err(CreateTorrentError::InvalidPieceLength(40000))
+        ));
+
+        let empty = dir.path().join("nothing");
+        fs::create_dir(&empty).unwrap();
+        assert!(matches!(
+            create_torrent(&options(empty, TorrentVersion::V1)),
+            Err(CreateTorrentError::NoFiles)
+        ));
+    }
+
+    #[test]
+    fn test_write_torrent_never_overwrites() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("a.torrent");
+        write_torrent(&path, b"first").unwrap();
+        assert!(matches!(
+            write_torrent(&path, b"second"),
+            Err(CreateTorrentError::AlreadyExists(existing)) if existing == path
+        ));
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+    }
+}
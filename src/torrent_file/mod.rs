@@ -1,10 +1,20 @@
 // SPDX-FileCopyrightText: 2025 The superseedr Contributors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+pub mod create;
 pub mod parser;
+pub mod v2;
 
 use serde::Deserialize;
 use serde::Serialize;
+use serde_bencode::value::Value;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use v2::MerklePiece;
+
+// Length of a v1 piece hash (SHA-1)
+pub const V1_HASH_LENGTH: usize = 20;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Torrent {
@@ -30,6 +40,86 @@ pub struct Torrent {
 
     #[serde(default)]
     pub encoding: Option<String>,
+
+    // HTTP seeds (BEP 17), asked for whole pieces when the swarm has gone quiet
+    #[serde(default)]
+    pub httpseeds: Option<Vec<String>>,
+
+    // v2 (BEP 52): per-file piece hashes, keyed by the file's pieces root
+    #[serde(rename = "piece layers", default)]
+    pub piece_layers: Option<Value>,
+
+    // Piece hashes of a v2-only torrent, filled in by the parser from the file
+    // tree and piece layers. Empty for v1 and hybrid torrents.
+    #[serde(skip)]
+    pub v2_pieces: Vec<MerklePiece>,
+}
+
+/// What a downloaded piece is checked against.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PieceHash {
+    // v1: SHA-1 of the whole piece
+    Sha1(Vec<u8>),
+    // v2: root of the SHA-256 merkle tree over the piece's blocks
+    Merkle(MerklePiece),
+}
+
+impl PieceHash {
+    pub fn matches(&self, data: &[u8]) -> bool {
+        match self {
+            PieceHash::Sha1(expected) => Sha1::digest(data).as_slice() == expected.as_slice(),
+            PieceHash::Merkle(piece) => piece.matches(data),
+        }
+    }
+}
+
+impl Torrent {
+    /// Tracker tiers to announce to. Per BEP 12 the announce-list replaces
+    /// `announce` when it is present.
+    pub fn announce_tiers(&self) -> Vec<Vec<String>> {
+        match &self.announce_list {
+            Some(list) if list.iter().any(|tier| !tier.is_empty()) => list.clone(),
+            _ => self.announce.iter().map(|url| vec![url.clone()]).collect(),
+        }
+    }
+
+    /// Whether pieces are checked with v2 merkle hashes only, with no v1 hashes.
+    pub fn is_v2_only(&self) -> bool {
+        self.info.meta_version == Some(2) && self.info.pieces.is_empty()
+    }
+
+    /// The 20 byte info hash used on the wire. v2-only torrents use the SHA-256
+    /// of the info dict cut to 20 bytes (BEP 52); everything else the SHA-1.
+    pub fn info_hash(&self) -> Vec<u8> {
+        if self.is_v2_only() {
+            Sha256::digest(&self.info_dict_bencode)[..V1_HASH_LENGTH].to_vec()
+        } else {
+            Sha1::digest(&self.info_dict_bencode).to_vec()
+        }
+    }
+
+    pub fn piece_count(&self) -> usize {
+        if self.is_v2_only() {
+            self.v2_pieces.len()
+        } else {
+            self.info.pieces.len() / V1_HASH_LENGTH
+        }
+    }
+
+    pub fn piece_hash(&self, piece_index: usize) -> Option<PieceHash> {
+        if self.is_v2_only() {
+            return self
+                .v2_pieces
+                .get(piece_index)
+                .cloned()
+                .map(PieceHash::Merkle);
+        }
+        let start = piece_index * V1_HASH_LENGTH;
+        self.info
+            .pieces
+            .get(start..start + V1_HASH_LENGTH)
+            .map(|hash| PieceHash::Sha1(hash.to_vec()))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -37,8 +127,9 @@ pub struct Info {
     #[serde(rename = "piece length")]
     pub piece_length: i64,
 
-    // Use serde_bytes to handle this as a raw byte vector
-    #[serde(with = "serde_bytes")]
+    // Use serde_bytes to handle this as a raw byte vector. v2-only torrents
+    // have no v1 piece hashes.
+    #[serde(with = "serde_bytes", default)]
     pub pieces: Vec<u8>,
 
     #[serde(default)]
@@ -56,6 +147,14 @@ pub struct Info {
 
     #[serde(default)]
     pub md5sum: Option<String>,
+
+    // 2 for v2 and hybrid torrents (BEP 52)
+    #[serde(rename = "meta version", default)]
+    pub meta_version: Option<i64>,
+
+    // v2 file layout, a nested dictionary of path components
+    #[serde(rename = "file tree", default)]
+    pub file_tree: Option<Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -65,4 +164,13 @@ pub struct InfoFile {
     pub md5sum: Option<String>,
     // The path is actually a list of strings
     pub path: Vec<String>,
+    // BEP 47 attributes; "p" marks padding that is never written to disk
+    #[serde(default)]
+    pub attr: Option<String>,
+}
+
+impl InfoFile {
+    pub fn is_padding(&self) -> bool {
+        self.attr.as_deref().is_some_and(|attr| attr.contains('p'))
+    }
 }
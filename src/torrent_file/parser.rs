@@ -1,6 +1,7 @@
 // SPDX-FileCopyrightText: 2025 The superseedr Contributors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+use crate::torrent_file::v2;
 use crate::torrent_file::Torrent;
 use serde_bencode::de;
 use serde_bencode::value::Value;
@@ -11,6 +12,7 @@ use std::fmt;
 pub enum ParseError {
     Bencode(serde_bencode::Error),
     MissingInfoDict,
+    InvalidV2(String),
 }
 
 impl fmt::Display for ParseError {
@@ -19,6 +21,7 @@ impl fmt::Display for ParseError {
             // For the Bencode variant, we now use the contained error `e`
             ParseError::Bencode(e) => write!(f, "Bencode parsing error: {}", e),
             ParseError::MissingInfoDict => write!(f, "Missing 'info' dictionary in torrent file"),
+            ParseError::InvalidV2(reason) => write!(f, "Invalid v2 torrent: {}", reason),
         }
     }
 }
@@ -55,5 +58,35 @@ pub fn from_bytes(bencode_data: &[u8]) -> Result<Torrent, ParseError> {
     // 5. Manually set the `info_dict_bencode` field we created.
     torrent.info_dict_bencode = info_dict_bencode;
 
+    // 6. v2-only torrents have no v1 file list or piece hashes; derive both from
+    //    the file tree and piece layers.
+    if torrent.is_v2_only() {
+        apply_v2_layout(&mut torrent)?;
+    }
+
     Ok(torrent)
 }
+
+fn apply_v2_layout(torrent: &mut Torrent) -> Result<(), ParseError> {
+    let tree = torrent
+        .info
+        .file_tree
+        .as_ref()
+        .ok_or_else(|| ParseError::InvalidV2("missing file tree".to_string()))?;
+    let files = v2::parse_file_tree(tree).map_err(ParseError::InvalidV2)?;
+    let piece_length = u64::try_from(torrent.info.piece_length)
+        .map_err(|_| ParseError::InvalidV2("negative piece length".to_string()))?;
+    let layout = v2::build_layout(&files, piece_length, torrent.piece_layers.as_ref())
+        .map_err(ParseError::InvalidV2)?;
+
+    // A lone file named after the torrent is a single-file torrent.
+    let single_file = files.len() == 1 && files[0].path == [torrent.info.name.clone()];
+    if single_file {
+        torrent.info.length = files[0].length as i64;
+        torrent.info.files = Vec::new();
+    } else {
+        torrent.info.files = layout.files;
+    }
+    torrent.v2_pieces = layout.pieces;
+    Ok(())
+}
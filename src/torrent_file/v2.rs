@@ -0,0 +1,365 @@
+// SPDX-FileCopyrightText: 2025 The superseedr Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! BitTorrent v2 metainfo (BEP 52): the file tree, piece layers and the SHA-256
+//! merkle trees pieces are checked against.
+
+use crate::torrent_file::InfoFile;
+
+use serde_bencode::value::Value;
+use sha2::{Digest, Sha256};
+
+// Leaves of the merkle trees are the hashes of 16 KiB blocks
+pub const MERKLE_BLOCK_SIZE: usize = 16384;
+const HASH_LEN: usize = 32;
+
+/// A file from a v2 file tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct V2File {
+    pub path: Vec<String>,
+    pub length: u64,
+    // Missing only for empty files
+    pub pieces_root: Option<[u8; HASH_LEN]>,
+}
+
+/// What one piece of a v2 torrent hashes to: the root of the merkle subtree over
+/// its blocks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerklePiece {
+    pub root: [u8; HASH_LEN],
+    // Leaves the subtree spans; blocks past the end of the file count as zero hashes
+    pub leaf_count: usize,
+    // File bytes in the piece; anything after is padding up to the next file
+    pub data_len: usize,
+}
+
+impl MerklePiece {
+    pub fn matches(&self, data: &[u8]) -> bool {
+        data.get(..self.data_len)
+            .is_some_and(|file_data| merkle_root(file_data, self.leaf_count) == self.root)
+    }
+}
+
+/// A v2 torrent laid out the way the rest of the client expects: files with padding
+/// between them so each starts on a piece boundary, and the hash of every piece.
+#[derive(Debug, Clone, PartialEq)]
+pub struct V2Layout {
+    pub files: Vec<InfoFile>,
+    pub pieces: Vec<MerklePiece>,
+}
+
+/// Walks a `file tree` dictionary. Keys are visited in byte order, which is also
+/// the order the files' pieces come in.
+pub fn parse_file_tree(tree: &Value) -> Result<Vec<V2File>, String> {
+    let mut files = Vec::new();
+    walk_file_tree(tree, &mut Vec::new(), &mut files)?;
+    Ok(files)
+}
+
+fn walk_file_tree(
+    node: &Value,
+    path: &mut Vec<String>,
+    files: &mut Vec<V2File>,
+) -> Result<(), String> {
+    let Value::Dict(entries) = node else {
+        return Err("file tree entry is not a dictionary".to_string());
+    };
+
+    // A file is a directory entry holding only the empty key.
+    if let Some(Value::Dict(file)) = entries.get(b"".as_slice()) {
+        if path.is_empty() {
+            return Err("file tree has a file without a name".to_string());
+        }
+        let length = match file.get(b"length".as_slice()) {
+            Some(Value::Int(length)) if *length >= 0 => *length as u64,
+            _ => return Err(format!("file {} has no valid length", path.join("/"))),
+        };
+        let pieces_root = match file.get(b"pieces root".as_slice()) {
+            Some(Value::Bytes(root)) => Some(
+                root.as_slice()
+                    .try_into()
+                    .map_err(|_| format!("file {} has a malformed pieces root", path.join("/")))?,
+            ),
+            _ => None,
+        };
+        if length > 0 && pieces_root.is_none() {
+            return Err(format!("file {} has no pieces root", path.join("/")));
+        }
+        files.push(V2File {
+            path: path.clone(),
+            length,
+            pieces_root,
+        });
+        return Ok(());
+    }
+
+    let mut names: Vec<&Vec<u8>> = entries.keys().collect();
+    names.sort();
+    for name in names {
+        let name_str = String::from_utf8(name.clone())
+            .map_err(|_| "file tree has a name that isn't UTF-8".to_string())?;
+        if name_str.is_empty() || name_str == "." || name_str == ".." || name_str.contains('/') {
+            return Err(format!("file tree has an unsafe name {:?}", name_str));
+        }
+        path.push(name_str);
+        walk_file_tree(&entries[name], path, files)?;
+        path.pop();
+    }
+    Ok(())
+}
+
+/// Lays out `files` on piece boundaries and collects every piece's hash. Files
+/// longer than a piece take their piece hashes from `piece_layers`, which is
+/// checked against the file's pieces root; shorter files are a single piece whose
+/// hash is the pieces root itself.
+pub fn build_layout(
+    files: &[V2File],
+    piece_length: u64,
+    piece_layers: Option<&Value>,
+) -> Result<V2Layout, String> {
+    let piece_len = usize::try_from(piece_length).unwrap_or(0);
+    if piece_len < MERKLE_BLOCK_SIZE || !piece_len.is_power_of_two() {
+        return Err(format!(
+            "piece length {} must be a power of two of at least 16 KiB",
+            piece_length
+        ));
+    }
+    let leaves_per_piece = piece_len / MERKLE_BLOCK_SIZE;
+    let layers = match piece_layers {
+        Some(Value::Dict(layers)) => Some(layers),
+        _ => None,
+    };
+
+    let mut layout = V2Layout {
+        files: Vec::new(),
+        pieces: Vec::new(),
+    };
+    let mut offset = 0u64;
+    let mut pad_count = 0;
+
+    for file in files {
+        // Pad the previous file out to a piece boundary.
+        let misalignment = offset % piece_length;
+        if misalignment != 0 && file.length > 0 {
+            let pad_len = piece_length - misalignment;
+            layout.files.push(InfoFile {
+                length: pad_len as i64,
+                md5sum: None,
+                path: vec![".pad".to_string(), pad_count.to_string()],
+                attr: Some("p".to_string()),
+            });
+            pad_count += 1;
+            offset += pad_len;
+        }
+
+        layout.files.push(InfoFile {
+            length: file.length as i64,
+            md5sum: None,
+            path: file.path.clone(),
+            attr: None,
+        });
+        offset += file.length;
+
+        let Some(pieces_root) = file.pieces_root else {
+            continue;
+        };
+        let piece_count = file.length.div_ceil(piece_length) as usize;
+        let data_len_of =
+            |piece: usize| (file.length - piece as u64 * piece_length).min(piece_length) as usize;
+
+        if piece_count == 1 {
+            let blocks = file.length.div_ceil(MERKLE_BLOCK_SIZE as u64) as usize;
+            layout.pieces.push(MerklePiece {
+                root: pieces_root,
+                leaf_count: blocks.next_power_of_two(),
+                data_len: data_len_of(0),
+            });
+            continue;
+        }
+
+        let layer = match layers.and_then(|layers| layers.get(pieces_root.as_slice())) {
+            Some(Value::Bytes(layer)) if layer.len() == piece_count * HASH_LEN => layer,
+            _ => {
+                return Err(format!(
+                    "piece layer for {} is missing or the wrong size",
+                    file.path.join("/")
+                ))
+            }
+        };
+        let piece_hashes: Vec<[u8; HASH_LEN]> = layer
+            .chunks_exact(HASH_LEN)
+            .map(|hash| hash.try_into().expect("chunks are HASH_LEN long"))
+            .collect();
+        if file_pieces_root(&piece_hashes, leaves_per_piece) != pieces_root {
+            return Err(format!(
+                "piece layer for {} doesn't match its pieces root",
+                file.path.join("/")
+            ));
+        }
+
+        for (piece, root) in piece_hashes.into_iter().enumerate() {
+            layout.pieces.push(MerklePiece {
+                root,
+                leaf_count: leaves_per_piece,
+                data_len: data_len_of(piece),
+            });
+        }
+    }
+    Ok(layout)
+}
+
+/// Root of the merkle tree over `data` cut into 16 KiB blocks, with zero hashes
+/// filling the leaves past the data up to `leaf_count`.
+pub fn merkle_root(data: &[u8], leaf_count: usize) -> [u8; HASH_LEN] {
+    let leaves: Vec<[u8; HASH_LEN]> = data
+        .chunks(MERKLE_BLOCK_SIZE)
+        .map(|block| Sha256::digest(block).into())
+        .collect();
+    let leaf_count = leaf_count.max(leaves.len()).next_power_of_two();
+
+    let mut layer = leaves;
+    layer.resize(leaf_count, [0; HASH_LEN]);
+    root_of_layer(layer, [0; HASH_LEN])
+}
+
+/// Root of a file's merkle tree from the roots of its pieces, each spanning
+/// `leaves_per_piece` leaves.
+pub fn file_pieces_root(piece_roots: &[[u8; HASH_LEN]], leaves_per_piece: usize) -> [u8; HASH_LEN] {
+    root_of_layer(piece_roots.to_vec(), zero_subtree_root(leaves_per_piece))
+}
+
+/// Hashes a layer up to its root. The layer is padded to a power of two with
+/// `pad_hash`, the root of an all-zero subtree the same height as its entries.
+fn root_of_layer(mut layer: Vec<[u8; HASH_LEN]>, pad_hash: [u8; HASH_LEN]) -> [u8; HASH_LEN] {
+    if layer.is_empty() {
+        return pad_hash;
+    }
+    layer.resize(layer.len().next_power_of_two(), pad_hash);
+    while layer.len() > 1 {
+        layer = layer
+            .chunks_exact(2)
+            .map(|pair| hash_pair(&pair[0], &pair[1]))
+            .collect();
+    }
+    layer[0]
+}
+
+/// Root of a subtree of `leaf_count` zero leaves.
+fn zero_subtree_root(leaf_count: usize) -> [u8; HASH_LEN] {
+    let mut hash = [0; HASH_LEN];
+    let mut width = 1;
+    while width < leaf_count {
+        hash = hash_pair(&hash, &hash);
+        width *= 2;
+    }
+    hash
+}
+
+fn hash_pair(left: &[u8; HASH_LEN], right: &[u8; HASH_LEN]) -> [u8; HASH_LEN] {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn file_entry(length: i64, pieces_root: Option<[u8; HASH_LEN]>) -> Value {
+        let mut file = HashMap::new();
+        file.insert(b"length".to_vec(), Value::Int(length));
+        if let Some(root) = pieces_root {
+            file.insert(b"pieces root".to_vec(), Value::Bytes(root.to_vec()));
+        }
+        Value::Dict(HashMap::from([(Vec::new(), Value::Dict(file))]))
+    }
+
+    #[test]
+    fn test_merkle_root_pads_with_zero_leaves() {
+        let data = vec![1u8; MERKLE_BLOCK_SIZE + 10];
+        let first: [u8; HASH_LEN] = Sha256::digest(&data[..MERKLE_BLOCK_SIZE]).into();
+        let second: [u8; HASH_LEN] = Sha256::digest(&data[MERKLE_BLOCK_SIZE..]).into();
+
+        assert_eq!(merkle_root(&data, 2), hash_pair(&first, &second));
+        assert_eq!(
+            merkle_root(&data, 4),
+            hash_pair(
+                &hash_pair(&first, &second),
+                &hash_pair(&[0; HASH_LEN], &[0; HASH_LEN])
+            )
+        );
+        assert_eq!(zero_subtree_root(4), merkle_root(&[], 4));
+    }
+
+    #[test]
+    fn test_parse_file_tree_orders_paths() {
+        let root = [7u8; HASH_LEN];
+        let tree = Value::Dict(HashMap::from([
+            (b"b.txt".to_vec(), file_entry(5, Some(root))),
+            (
+                b"dir".to_vec(),
+                Value::Dict(HashMap::from([(b"a.txt".to_vec(), file_entry(0, None))])),
+            ),
+            (b"a.txt".to_vec(), file_entry(3, Some(root))),
+        ]));
+
+        let files = parse_file_tree(&tree).unwrap();
+        let paths: Vec<String> = files.iter().map(|f| f.path.join("/")).collect();
+        assert_eq!(paths, ["a.txt", "b.txt", "dir/a.txt"]);
+        assert_eq!(files[2].pieces_root, None);
+
+        let unsafe_tree = Value::Dict(HashMap::from([(b"..".to_vec(), file_entry(0, None))]));
+        assert!(parse_file_tree(&unsafe_tree).is_err());
+    }
+
+    #[test]
+    fn test_build_layout_pads_and_checks_piece_layers() {
+        let piece_length = 2 * MERKLE_BLOCK_SIZE as u64;
+        let small = vec![3u8; 100];
+        let big = vec![9u8; 2 * piece_length as usize + 5];
+
+        let big_pieces: Vec<[u8; HASH_LEN]> = big
+            .chunks(piece_length as usize)
+            .map(|piece| merkle_root(piece, 2))
+            .collect();
+        let big_root = merkle_root(&big, 8);
+        let files = vec![
+            V2File {
+                path: vec!["a".to_string()],
+                length: small.len() as u64,
+                pieces_root: Some(merkle_root(&small, 1)),
+            },
+            V2File {
+                path: vec!["b".to_string()],
+                length: big.len() as u64,
+                pieces_root: Some(big_root),
+            },
+        ];
+        let layers = Value::Dict(HashMap::from([(
+            big_root.to_vec(),
+            Value::Bytes(big_pieces.concat()),
+        )]));
+
+        let layout = build_layout(&files, piece_length, Some(&layers)).unwrap();
+        let lengths: Vec<i64> = layout.files.iter().map(|f| f.length).collect();
+        assert_eq!(lengths, [100, piece_length as i64 - 100, big.len() as i64]);
+        assert!(layout.files[1].is_padding());
+        assert_eq!(layout.pieces.len(), 4);
+
+        // The first piece is the small file and its padding.
+        let mut first_piece = small.clone();
+        first_piece.resize(piece_length as usize, 0);
+        assert!(layout.pieces[0].matches(&first_piece));
+        assert!(layout.pieces[3].matches(&big[2 * piece_length as usize..]));
+        assert!(!layout.pieces[1].matches(&big[2 * piece_length as usize..]));
+
+        let wrong_layers = Value::Dict(HashMap::from([(
+            big_root.to_vec(),
+            Value::Bytes(vec![0; 3 * HASH_LEN]),
+        )]));
+        assert!(build_layout(&files, piece_length, Some(&wrong_layers)).is_err());
+        assert!(build_layout(&files, piece_length, None).is_err());
+    }
+}
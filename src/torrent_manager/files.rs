@@ -0,0 +1,319 @@
+// SPDX-FileCopyrightText: 2025 The superseedr Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use crate::storage::MultiFileInfo;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+
+/// How much the user wants a file. Pieces of higher priority files are picked
+/// first; pieces only skipped files have data in are never picked.
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub enum FilePriority {
+    Skip,
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl FilePriority {
+    pub fn label(self) -> &'static str {
+        match self {
+            FilePriority::Skip => "Skip",
+            FilePriority::Low => "Low",
+            FilePriority::Normal => "Normal",
+            FilePriority::High => "High",
+        }
+    }
+
+    /// One step up, stopping at High.
+    pub fn raise(self) -> Self {
+        match self {
+            FilePriority::Skip => FilePriority::Low,
+            FilePriority::Low => FilePriority::Normal,
+            FilePriority::Normal | FilePriority::High => FilePriority::High,
+        }
+    }
+
+    /// One step down, stopping at Skip.
+    pub fn lower(self) -> Self {
+        match self {
+            FilePriority::High => FilePriority::Normal,
+            FilePriority::Normal => FilePriority::Low,
+            FilePriority::Low | FilePriority::Skip => FilePriority::Skip,
+        }
+    }
+}
+
+/// The torrent's files as the piece picker sees them: which pieces each one covers
+/// and how much the user wants it. Indexes count pad files, as in the metadata.
+#[derive(Debug)]
+pub struct Files {
+    files: Vec<FileSpan>,
+    piece_length: u64,
+}
+
+#[derive(Debug, Clone)]
+struct FileSpan {
+    start: u64,
+    length: u64,
+    // BEP 47 pad files hold no data, so no piece is fetched for them
+    is_padding: bool,
+    priority: FilePriority,
+}
+
+impl Files {
+    pub fn new(multi_file_info: &MultiFileInfo, piece_length: u64) -> Self {
+        Self {
+            files: multi_file_info
+                .files
+                .iter()
+                .map(|file| FileSpan {
+                    start: file.global_start_offset,
+                    length: file.length,
+                    is_padding: file.is_padding,
+                    priority: FilePriority::Normal,
+                })
+                .collect(),
+            piece_length: piece_length.max(1),
+        }
+    }
+
+    /// Gives the files in `ranges` a new priority. Indexes past the last file are
+    /// ignored. Returns whether anything changed.
+    pub fn set_priority(
+        &mut self,
+        ranges: &[RangeInclusive<usize>],
+        priority: FilePriority,
+    ) -> bool {
+        let mut changed = false;
+        for (index, file) in self.files.iter_mut().enumerate() {
+            if file.priority != priority && ranges.iter().any(|range| range.contains(&index)) {
+                file.priority = priority;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Restores saved priorities, by index. Files past the end of `priorities` are
+    /// left normal.
+    pub fn set_priorities(&mut self, priorities: &[FilePriority]) {
+        for (file, priority) in self.files.iter_mut().zip(priorities) {
+            file.priority = *priority;
+        }
+    }
+
+    /// Every file's priority, in order.
+    pub fn priorities(&self) -> Vec<FilePriority> {
+        self.files.iter().map(|file| file.priority).collect()
+    }
+
+    /// The pieces file `index` has data in. None for empty and pad files.
+    pub fn pieces(&self, index: usize) -> Option<RangeInclusive<u32>> {
+        let file = self.files.get(index)?;
+        if file.is_padding || file.length == 0 {
+            return None;
+        }
+        let first = file.start / self.piece_length;
+        let last = (file.start + file.length - 1) / self.piece_length;
+        Some(first as u32..=last as u32)
+    }
+
+    /// Bytes of file `index` that lie in pieces `is_done` says are done.
+    pub fn bytes_done(&self, index: usize, is_done: impl Fn(u32) -> bool) -> u64 {
+        let (Some(file), Some(pieces)) = (self.files.get(index), self.pieces(index)) else {
+            return 0;
+        };
+        let end = file.start + file.length;
+        pieces
+            .filter(|&piece| is_done(piece))
+            .map(|piece| {
+                let piece_start = piece as u64 * self.piece_length;
+                let piece_end = piece_start + self.piece_length;
+                piece_end.min(end) - piece_start.max(file.start)
+            })
+            .sum()
+    }
+
+    /// The pieces worth fetching: every piece a wanted file has data in. A piece a
+    /// skipped file shares with a wanted one is still fetched, since the wanted file
+    /// can't be finished without it. None when no file is skipped.
+    pub fn wanted_pieces(&self) -> Option<HashSet<u32>> {
+        if !self
+            .files
+            .iter()
+            .any(|file| file.priority == FilePriority::Skip)
+        {
+            return None;
+        }
+        Some(
+            (0..self.files.len())
+                .filter(|&index| self.files[index].priority != FilePriority::Skip)
+                .filter_map(|index| self.pieces(index))
+                .flatten()
+                .collect(),
+        )
+    }
+
+    /// Pieces whose priority isn't normal. A piece shared by several files takes the
+    /// highest priority among the wanted ones.
+    pub fn piece_priorities(&self) -> HashMap<u32, FilePriority> {
+        let mut piece_priorities: HashMap<u32, FilePriority> = HashMap::new();
+        for (index, file) in self.files.iter().enumerate() {
+            if file.priority == FilePriority::Skip {
+                continue;
+            }
+            for piece in self.pieces(index).into_iter().flatten() {
+                let priority = piece_priorities.entry(piece).or_insert(file.priority);
+                *priority = (*priority).max(file.priority);
+            }
+        }
+        piece_priorities.retain(|_, priority| *priority != FilePriority::Normal);
+        piece_priorities
+    }
+}
+
+/// Reads a list of file indexes and index ranges, e.g. `0,2,4-6`, as a magnet
+/// link's `so=` (BEP 53) or the control socket give them. Parts that can't be read
+/// are left out; `None` when nothing is left.
+pub fn parse_file_ranges(value: &str) -> Option<Vec<RangeInclusive<usize>>> {
+    let ranges: Vec<RangeInclusive<usize>> = value
+        .split(',')
+        .filter_map(|part| match part.trim().split_once('-') {
+            Some((start, end)) => Some(start.trim().parse().ok()?..=end.trim().parse().ok()?),
+            None => {
+                let index = part.trim().parse().ok()?;
+                Some(index..=index)
+            }
+        })
+        .filter(|range| !range.is_empty())
+        .collect();
+    (!ranges.is_empty()).then_some(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::FileInfo;
+    use std::path::PathBuf;
+
+    // Pieces of 10 bytes: file 0 is 0..15, a pad file 15..20, file 2 is 20..35 and
+    // file 3 is 35..40, sharing piece 3 with file 2.
+    fn files() -> Files {
+        let file = |start, length, is_padding| FileInfo {
+            path: PathBuf::from("file"),
+            length,
+            global_start_offset: start,
+            is_padding,
+            is_borrowed: false,
+        };
+        let multi_file_info = MultiFileInfo {
+            files: vec![
+                file(0, 15, false),
+                file(15, 5, true),
+                file(20, 15, false),
+                file(35, 5, false),
+            ],
+            total_size: 40,
+        };
+        Files::new(&multi_file_info, 10)
+    }
+
+    #[test]
+    fn test_pieces_of_each_file() {
+        let files = files();
+        assert_eq!(files.pieces(0), Some(0..=1));
+        assert_eq!(files.pieces(1), None);
+        assert_eq!(files.pieces(2), Some(2..=3));
+        assert_eq!(files.pieces(3), Some(3..=3));
+        assert_eq!(files.pieces(4), None);
+    }
+
+    #[test]
+    fn test_skipped_files_exclusive_pieces_are_not_wanted() {
+        let mut files = files();
+        assert_eq!(files.wanted_pieces(), None);
+
+        assert!(files.set_priority(&[2..=2], FilePriority::Skip));
+        assert!(!files.set_priority(&[2..=2, 9..=9], FilePriority::Skip));
+        // Piece 3 is shared with file 3, which is still wanted.
+        assert_eq!(files.wanted_pieces(), Some(HashSet::from([0, 1, 3])));
+
+        assert!(files.set_priority(&[0..=3], FilePriority::Skip));
+        assert_eq!(files.wanted_pieces(), Some(HashSet::new()));
+
+        assert!(files.set_priority(&[0..=usize::MAX], FilePriority::Normal));
+        assert_eq!(files.wanted_pieces(), None);
+    }
+
+    #[test]
+    fn test_shared_pieces_take_the_highest_wanted_priority() {
+        let mut files = files();
+        assert!(files.piece_priorities().is_empty());
+
+        files.set_priorities(&[FilePriority::Low, FilePriority::Normal, FilePriority::High]);
+        assert_eq!(
+            files.priorities(),
+            vec![
+                FilePriority::Low,
+                FilePriority::Normal,
+                FilePriority::High,
+                FilePriority::Normal
+            ]
+        );
+        assert_eq!(
+            files.piece_priorities(),
+            HashMap::from([
+                (0, FilePriority::Low),
+                (1, FilePriority::Low),
+                (2, FilePriority::High),
+                (3, FilePriority::High),
+            ])
+        );
+
+        // A skipped file doesn't lift the pieces it shares.
+        files.set_priorities(&[
+            FilePriority::Normal,
+            FilePriority::Normal,
+            FilePriority::Skip,
+        ]);
+        files.set_priority(&[3..=3], FilePriority::Low);
+        assert_eq!(
+            files.piece_priorities(),
+            HashMap::from([(3, FilePriority::Low)])
+        );
+    }
+
+    #[test]
+    fn test_bytes_done_counts_the_file_part_of_done_pieces() {
+        let files = files();
+        assert_eq!(files.bytes_done(0, |piece| piece == 1), 5);
+        assert_eq!(files.bytes_done(2, |piece| piece == 3), 5);
+        assert_eq!(files.bytes_done(3, |_| true), 5);
+        assert_eq!(files.bytes_done(1, |_| true), 0);
+    }
+
+    #[test]
+    fn test_priority_steps() {
+        assert_eq!(FilePriority::Skip.raise(), FilePriority::Low);
+        assert_eq!(FilePriority::High.raise(), FilePriority::High);
+        assert_eq!(FilePriority::Normal.lower(), FilePriority::Low);
+        assert_eq!(FilePriority::Skip.lower(), FilePriority::Skip);
+    }
+
+    #[test]
+    fn test_parse_file_ranges() {
+        assert_eq!(
+            parse_file_ranges("0, 2,4-6"),
+            Some(vec![0..=0, 2..=2, 4..=6])
+        );
+        assert_eq!(parse_file_ranges("5-2,x"), None);
+        assert_eq!(parse_file_ranges(""), None);
+    }
+}
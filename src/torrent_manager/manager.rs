@@ -2,21 +2,39 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use crate::app::PeerInfo;
+use crate::app::TorrentFileInfo;
 use crate::app::TorrentState;
+use crate::app::TrackerInfo;
 
 use crate::resource_manager::ResourceManagerClient;
 use crate::resource_manager::ResourceManagerError;
 
+use crate::hash_pool::HashPool;
+
 use crate::networking::ConnectionType;
 
-use crate::token_bucket::TokenBucket;
+use crate::token_bucket::consume_tokens;
+use crate::token_bucket::{BandwidthPriority, TokenBucket};
 
 use crate::torrent_manager::DiskIoOperation;
 
+use crate::ban_list::{peer_ip, BanList};
+use crate::bitfield::Bitfield;
+use crate::config::partial_pieces_path;
+#[cfg(feature = "dht")]
+use crate::config::DhtMode;
+use crate::config::InsufficientSpaceAction;
+use crate::config::PeerTransport;
 use crate::config::Settings;
 
-use crate::torrent_manager::piece_manager::PieceStatus;
+use crate::external_ip::IpSource;
+
+use crate::torrent_manager::files::{parse_file_ranges, FilePriority, Files};
+use crate::torrent_manager::piece_manager::{PieceStatus, BLOCK_SIZE};
 use crate::torrent_manager::state::ChokeStatus;
+use crate::torrent_manager::state::HttpSeedState;
+use crate::torrent_manager::state::PeerCandidate;
+use crate::torrent_manager::state::PeerSource;
 use crate::torrent_manager::state::PeerState;
 use crate::torrent_manager::state::TorrentActivity;
 
@@ -24,24 +42,47 @@ use crate::torrent_manager::state::TorrentStatus;
 use crate::torrent_manager::state::TrackerState;
 use crate::torrent_manager::ManagerCommand;
 use crate::torrent_manager::ManagerEvent;
+use crate::tracker::tiers::TrackerTiers;
+use crate::tracker::SwarmCounts;
 
 use crate::torrent_manager::piece_manager::PieceManager;
 
+use crate::errors::ManagerInitError;
+use crate::errors::PieceVerifyError;
 use crate::errors::StorageError;
+use crate::storage::available_space;
+use crate::storage::content_root_dir;
 use crate::storage::create_and_allocate_files;
+use crate::storage::files_match_pieces;
+use crate::storage::files_present;
+use crate::storage::find_existing_files;
+use crate::storage::move_files;
 use crate::storage::read_data_from_disk;
+use crate::storage::remaining_allocation_bytes;
+use crate::storage::rename_file;
 use crate::storage::write_data_to_disk;
+use crate::storage::FileRename;
 use crate::storage::MultiFileInfo;
+use crate::streaming::StreamFile;
 
 use crate::command::TorrentCommand;
 use crate::command::TorrentCommandSummary;
 
-use crate::networking::session::PeerSessionParameters;
+use crate::networking::connect_from_interface;
+use crate::networking::protocol::{
+    canonical_peer_priority, HolepunchError, HolepunchMessage, PexPeer, PEX_CONNECTABLE, PEX_SEED,
+    PEX_SUPPORTS_HOLEPUNCH,
+};
+use crate::networking::session::{PeerSessionParameters, PeerTimeouts};
+use crate::networking::utp::UtpSocket;
+use crate::networking::web_seed::fetch_http_seed_piece;
 use crate::networking::BlockInfo;
 use crate::networking::PeerSession;
+use crate::networking::{BoxedPeerStream, IncomingPeer};
 
 use crate::tracker::client::{
-    announce_completed, announce_periodic, announce_started, announce_stopped,
+    announce_completed, announce_paused, announce_periodic, announce_started, announce_stopped,
+    scrape, AnnounceClient,
 };
 
 use rand::prelude::IndexedRandom;
@@ -59,6 +100,10 @@ use mainline::async_dht::AsyncDht;
 use mainline::Id;
 #[cfg(not(feature = "dht"))]
 type AsyncDht = ();
+#[cfg(feature = "dht")]
+use crate::networking::dht_client::{self, DhtClient, Lookup};
+#[cfg(not(feature = "dht"))]
+type DhtClient = ();
 
 use std::time::Duration;
 use std::time::Instant;
@@ -67,9 +112,10 @@ use magnet_url::Magnet;
 
 use urlencoding::decode;
 
+use std::ops::RangeInclusive;
+
 use data_encoding::BASE32;
 
-use sha1::{Digest, Sha1};
 use tokio::fs;
 use tokio::net::TcpStream;
 use tokio::signal;
@@ -78,49 +124,288 @@ use tokio::sync::mpsc;
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::watch;
 use tokio::sync::Mutex;
+use tokio::sync::Semaphore;
 use tokio::task::JoinHandle;
 use tokio::task::JoinSet;
 use tokio::time::timeout;
 use tokio_stream::StreamExt;
 
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::net::SocketAddrV4;
+use std::net::IpAddr;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
 
 use crate::torrent_manager::TorrentParameters;
 
-const HASH_LENGTH: usize = 20;
+/// What the last DHT lookup saw of the swarm.
+#[cfg(feature = "dht")]
+#[derive(Debug, Clone, Copy)]
+struct DhtSwarmEstimate {
+    // Distinct peers the lookup returned
+    peers: usize,
+    // Seeds and leechers by BEP 33 scrape, if any of the closest nodes support it
+    scrape: Option<SwarmCounts>,
+}
+
+/// Asks `client`, if there is one, for the peers of `info_hash` and passes them on.
+#[cfg(feature = "dht")]
+async fn dht_client_lookup(
+    client: Option<&DhtClient>,
+    info_hash: [u8; 20],
+    dht_tx: &Sender<Vec<SocketAddr>>,
+) -> Option<Lookup> {
+    let lookup = client?.lookup(info_hash).await;
+    if !lookup.peers.is_empty() {
+        let _ = dht_tx.send(lookup.peers.clone()).await;
+    }
+    Some(lookup)
+}
+
+/// Number of pieces in `torrent`. Piece indexes are u32 on the wire, so a torrent
+/// with more pieces than that can't be downloaded.
+fn piece_count(torrent: &Torrent) -> Result<u32, String> {
+    let num_pieces = torrent.piece_count();
+    u32::try_from(num_pieces).map_err(|_| format!("Torrent has too many pieces ({})", num_pieces))
+}
+
+/// Number of pieces that start inside the first `max_download_bytes` of the torrent,
+/// so a cap always downloads a contiguous prefix rather than scattered pieces.
+fn cap_piece_limit(max_download_bytes: Option<u64>, piece_length: u64) -> Option<u32> {
+    max_download_bytes
+        .map(|cap| u32::try_from(cap.div_ceil(piece_length.max(1))).unwrap_or(u32::MAX))
+}
+
+/// File index ranges from a magnet link's `so=` parameter (BEP 53), e.g.
+/// `so=0,2,4-6`. `None` without one, or when nothing in it can be read.
+pub fn magnet_select_only(magnet_link: &str) -> Option<Vec<RangeInclusive<usize>>> {
+    let query = magnet_link.split_once('?')?.1;
+    let value = query
+        .split('&')
+        .find_map(|param| param.strip_prefix("so="))?;
+    parse_file_ranges(&decode(value).ok()?)
+}
+
+/// The HTTP trackers of a magnet link, decoded. Magnet links have no tiers, so each
+/// gets its own.
+pub fn magnet_trackers(magnet: &Magnet) -> Vec<String> {
+    magnet
+        .trackers()
+        .iter()
+        .filter(|t| t.starts_with("http"))
+        .filter_map(|t| match decode(t) {
+            Ok(decoded_url) => Some(decoded_url.into_owned()),
+            Err(e) => {
+                event!(Level::DEBUG, tracker_url = %t, error = %e, "Failed to decode tracker URL from magnet link, skipping.");
+                None
+            }
+        })
+        .collect()
+}
+
+/// Length of piece `piece_index`. Every piece is `piece_length` long except the
+/// last, which holds whatever is left of `total_size`.
+fn expected_piece_size(piece_index: u32, piece_length: u64, total_size: u64) -> usize {
+    let start_offset = piece_index as u64 * piece_length;
+    let bytes_remaining = total_size.saturating_sub(start_offset);
+
+    std::cmp::min(piece_length, bytes_remaining) as usize
+}
+
+/// The picker's priority list for every open stream: their windows taken a piece
+/// at a time in turn, so one stream can't starve another.
+fn stream_priority(windows: &BTreeMap<u64, Vec<u32>>) -> Vec<u32> {
+    let longest = windows.values().map(Vec::len).max().unwrap_or(0);
+    (0..longest)
+        .flat_map(|i| {
+            windows
+                .values()
+                .filter_map(move |window| window.get(i).copied())
+        })
+        .collect()
+}
+
+/// Connects to `peer_ip_port` over the transports `peer_transport` allows, trying uTP
+/// before TCP. Without a uTP socket, TCP is used whatever the setting. With a
+/// `listen_interface`, TCP goes out from that interface or not at all; the uTP
+/// socket is already bound to it.
+async fn connect_peer_stream(
+    peer_ip_port: &str,
+    peer_transport: PeerTransport,
+    utp_socket: Option<&UtpSocket>,
+    listen_interface: Option<&str>,
+) -> Option<BoxedPeerStream> {
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+    if let (true, Some(utp_socket), Ok(addr)) = (
+        peer_transport.uses_utp(),
+        utp_socket,
+        peer_ip_port.parse::<SocketAddr>(),
+    ) {
+        if let Ok(Ok(stream)) = timeout(CONNECT_TIMEOUT, utp_socket.connect(addr)).await {
+            return Some(Box::new(stream));
+        }
+    }
+
+    if peer_transport.uses_tcp() || utp_socket.is_none() {
+        let connect = async {
+            match listen_interface {
+                Some(listen_interface) => {
+                    let addr = peer_ip_port
+                        .parse::<SocketAddr>()
+                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+                    connect_from_interface(listen_interface, addr).await
+                }
+                None => TcpStream::connect(peer_ip_port).await,
+            }
+        };
+        if let Ok(Ok(stream)) = timeout(CONNECT_TIMEOUT, connect).await {
+            return Some(Box::new(stream));
+        }
+    }
+    None
+}
+
+/// The key a peer is stored under: the same form as incoming peers, so IPv6
+/// addresses get brackets.
+fn peer_ip_port(peer_ip: &str, peer_port: u16) -> String {
+    match peer_ip.parse::<IpAddr>() {
+        Ok(ip) => SocketAddr::new(ip.to_canonical(), peer_port).to_string(),
+        Err(_) => format!("{}:{}", peer_ip, peer_port),
+    }
+}
+
+/// Unchoke order once seeding: peers that drained the most from us last round
+/// first, newest connections breaking ties, and anyone unchoked for longer than
+/// `SEED_UNCHOKE_ROTATION` rotated to the back.
+fn sort_for_seeding(peers: &mut [&PeerState]) {
+    peers.sort_by_key(|p| {
+        let rotated_out = p
+            .unchoked_since
+            .is_some_and(|since| since.elapsed() >= SEED_UNCHOKE_ROTATION);
+        (
+            rotated_out,
+            std::cmp::Reverse(p.bytes_uploaded_to_peer),
+            std::cmp::Reverse(p.connected_at),
+        )
+    });
+}
+
+/// Dial order for peer candidates: by source, then the most recently listed. Once
+/// a peer has told us our address (`us`), ties go in canonical priority order (BEP
+/// 40), so both ends of the best-ranked connections try them first.
+fn rank_peer_candidates(candidates: &mut [&PeerCandidate], us: Option<SocketAddr>) {
+    candidates.sort_by_cached_key(|candidate| {
+        let priority = us.and_then(|us| {
+            let ip = candidate.ip.parse::<IpAddr>().ok()?;
+            canonical_peer_priority(us, SocketAddr::new(ip, candidate.port))
+        });
+        (
+            candidate.source,
+            std::cmp::Reverse(candidate.discovered_at),
+            std::cmp::Reverse(priority),
+        )
+    });
+}
+
+/// How `peer` is passed on to other peers over PEX, with the BEP 11 flags we know.
+fn pex_peer(peer: &PeerState) -> Option<PexPeer> {
+    let mut flags = 0;
+    if peer.has_all || (!peer.bitfield.is_empty() && peer.bitfield.all()) {
+        flags |= PEX_SEED;
+    }
+    if peer.supports_holepunch {
+        flags |= PEX_SUPPORTS_HOLEPUNCH;
+    }
+    // Only a dial that got through the handshake shows the peer accepts connections
+    if peer.outgoing && !peer.peer_id.is_empty() {
+        flags |= PEX_CONNECTABLE;
+    }
+    let addr = peer.ip_port.parse().ok()?;
+    Some(PexPeer { addr, flags })
+}
+
 const MAX_BLOCK_SIZE: u32 = 131_072;
+// Cap on the allowed fast and suggested pieces kept per peer (BEP 6)
+const MAX_PEER_PIECE_HINTS: usize = 64;
 const CLIENT_LEECHING_FALLBACK_INTERVAL: u64 = 60;
 const FALLBACK_ANNOUNCE_INTERVAL: u64 = 1800;
 
 const BASE_COOLDOWN_SECS: u64 = 15;
 const MAX_COOLDOWN_SECS: u64 = 1800;
-const MAX_TIMEOUT_COUNT: u32 = 10;
+
+// Outgoing connections still handshaking at once; other candidates wait in the pool
+const MAX_CONNECTING_PEERS: usize = 32;
+// Candidates kept per torrent, and how long one stays without being listed again
+const MAX_PEER_CANDIDATES: usize = 2000;
+const PEER_CANDIDATE_TTL: Duration = Duration::from_secs(30 * 60);
 
 const MAX_UPLOAD_REQUEST_ATTEMPTS: u32 = 7;
 const MAX_PIECE_WRITE_ATTEMPTS: u32 = 12;
 const MAX_VALIDATION_ATTEMPTS: u32 = MAX_PIECE_WRITE_ATTEMPTS;
 
+// How long a shutting down torrent waits for piece writes that are already running
+const SHUTDOWN_WRITE_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Once fewer blocks than this are missing, they're requested from several peers at
+// once and the slower copies cancelled as the first arrives
+const ENDGAME_BLOCK_THRESHOLD: usize = 64;
+
+// PEX peers remembered as holepunch targets through the peer that sent them (BEP 55)
+const MAX_HOLEPUNCH_CANDIDATES: usize = 512;
+
+// Pieces past a streaming read that are fetched ahead of everything else, so
+// playback doesn't stall on the next read
+const STREAM_READAHEAD_PIECES: u32 = 8;
+
+// Peers connected for less than this are three times as likely to get the
+// optimistic unchoke, so newcomers with nothing to trade can get started
+const NEW_PEER_OPTIMISTIC_WINDOW: Duration = Duration::from_secs(60);
+
+// While seeding, a peer unchoked for this long goes behind everyone still
+// waiting, so one fast leecher can't hold a slot for good
+const SEED_UNCHOKE_ROTATION: Duration = Duration::from_secs(60);
+
+// HTTP seeds are only used once no peer has sent a block for this long
+const HTTP_SEED_FALLBACK_AFTER: Duration = Duration::from_secs(30);
+
 const BASE_BACKOFF_MS: u64 = 1000;
 const JITTER_MS: u64 = 100;
 
 const BITS_PER_BYTE: u64 = 8;
 const SMOOTHING_PERIOD_MS: f64 = 5000.0;
 
+/// A streaming server read waiting for its pieces to reach the disk.
+struct PendingStreamRead {
+    global_offset: u64,
+    length: usize,
+    reply_tx: Sender<Result<Vec<u8>, StorageError>>,
+}
+
 pub struct TorrentManager {
     info_hash: Vec<u8>,
-    torrent_metadata_length: Option<i64>,
+    // The bencoded info dict, served to peers over ut_metadata (BEP 9)
+    torrent_metadata: Option<Arc<Vec<u8>>>,
     torrent: Option<Torrent>,
 
     root_download_path: PathBuf,
     multi_file_info: Option<MultiFileInfo>,
+    // Where the files move once the download finishes; uploads wait while they do
+    completed_dir: Option<PathBuf>,
+    files_moving: bool,
+    // Set while the user moves the files elsewhere: peers were dropped for the move and
+    // are rejoined after it, with a recheck first if true
+    recheck_after_move: Option<bool>,
 
     is_paused: bool,
 
     trackers: HashMap<String, TrackerState>,
+    // Announce order; `trackers` holds the state of every tracker in it
+    tracker_tiers: TrackerTiers,
+    // Sent as `key` on every announce for as long as the torrent is loaded
+    announce_key: u32,
 
     torrent_status: TorrentStatus,
 
@@ -128,15 +413,21 @@ pub struct TorrentManager {
     torrent_validation_status: bool,
 
     dht_handle: AsyncDht,
+    // Scrape the swarm over IPv4 and find IPv6 peers, which `dht_handle` can't
+    dht4: Option<DhtClient>,
+    dht6: Option<DhtClient>,
 
     last_known_peers: HashSet<String>,
 
     peers_map: HashMap<String, PeerState>,
     timed_out_peers: HashMap<String, (u32, Instant)>,
+    // Peers heard of but not dialed yet, by address
+    peer_candidates: HashMap<String, PeerCandidate>,
     torrent_manager_tx: Sender<TorrentCommand>,
 
     #[cfg(feature = "dht")]
-    dht_tx: Sender<Vec<SocketAddrV4>>,
+    // DHT peers of either address family: IPv4 mostly from mainline, IPv6 from `dht6`
+    dht_tx: Sender<Vec<SocketAddr>>,
     #[cfg(not(feature = "dht"))]
     dht_tx: Sender<()>,
 
@@ -147,15 +438,40 @@ pub struct TorrentManager {
     torrent_manager_rx: Receiver<TorrentCommand>,
 
     #[cfg(feature = "dht")]
-    dht_rx: Receiver<Vec<SocketAddrV4>>,
+    dht_rx: Receiver<Vec<SocketAddr>>,
     #[cfg(not(feature = "dht"))]
     dht_rx: Receiver<()>,
 
-    incoming_peer_rx: Receiver<(TcpStream, Vec<u8>)>,
+    incoming_peer_rx: Receiver<IncomingPeer>,
     manager_command_rx: Receiver<ManagerCommand>,
 
     session_total_uploaded: u64,
     session_total_downloaded: u64,
+    // Downloaded bytes thrown away because their piece failed the hash check
+    wasted_bytes: u64,
+    // Peers whose metadata didn't decode or match the info hash. Skipped until
+    // another peer supplies good metadata.
+    metadata_rejected_peers: HashSet<String>,
+    // Peers that sent blocks of each piece still being assembled, so a piece
+    // failing the hash check can be blamed on everyone who supplied it
+    piece_contributors: HashMap<u32, HashSet<String>>,
+    // Pieces failing the hash check that each IP contributed to this session
+    hash_fail_strikes: HashMap<IpAddr, u32>,
+    // PEX peers we haven't reached yet, keyed to the peer that told us about them.
+    // If we can't connect, that peer is asked to relay a holepunch (BEP 55).
+    holepunch_relays: HashMap<String, String>,
+    // Per torrent; the global settings can also switch either direction off
+    upload_disabled: bool,
+    download_disabled: bool,
+    max_download_bytes: Option<u64>,
+    // Switched off by the user for this torrent; private torrents never use either
+    dht_disabled: bool,
+    pex_disabled: bool,
+    // Every file's priority, kept in step with `files` and reported to the app. None
+    // until the user or a magnet's so= sets one.
+    file_priorities: Option<Vec<FilePriority>>,
+    // Files kept under other paths than the metadata gives, applied once it's known
+    file_renames: Vec<FileRename>,
     bytes_downloaded_in_interval: u64,
     bytes_uploaded_in_interval: u64,
     total_dl_prev_avg_ema: f64,
@@ -164,75 +480,133 @@ pub struct TorrentManager {
     piece_manager: PieceManager,
 
     optimistic_unchoke_timer: Instant,
+    // Holds the optimistic unchoke until `optimistic_unchoke_interval` is up
+    optimistic_unchoke_peer: Option<String>,
+    // Partial seed (BEP 21): every piece inside the download cap is done, so peers
+    // and trackers are told we only upload
+    partial_seed: bool,
+    // Our public IP as peers last reported it; orders new connections (BEP 40)
+    external_ip: Option<IpAddr>,
+    // File indexes from the magnet link's so= (BEP 53), applied once the metadata
+    // arrives
+    magnet_file_selection: Option<Vec<RangeInclusive<usize>>>,
+    // Which pieces each file covers and which files are skipped; None until the
+    // metadata is known
+    files: Option<Files>,
 
     has_made_first_connection: bool,
 
     in_flight_uploads: HashMap<String, HashMap<BlockInfo, JoinHandle<()>>>,
+    in_flight_writes: JoinSet<()>,
+    // Streaming server reads still waiting for their pieces
+    stream_reads: Vec<PendingStreamRead>,
+    // The pieces each open stream wants next, by stream id
+    stream_windows: BTreeMap<u64, Vec<u32>>,
 
     #[cfg(feature = "dht")]
     dht_trigger_tx: watch::Sender<()>,
     #[cfg(feature = "dht")]
     dht_task_handle: Option<JoinHandle<()>>,
+    // When the DHT lookup task will next ask for peers
+    #[cfg(feature = "dht")]
+    dht_next_refresh_rx: watch::Receiver<Instant>,
+    // Our only swarm size without trackers
+    #[cfg(feature = "dht")]
+    dht_peer_estimate_rx: watch::Receiver<Option<DhtSwarmEstimate>>,
 
     #[cfg(not(feature = "dht"))]
     dht_trigger_tx: (),
     #[cfg(not(feature = "dht"))]
     dht_task_handle: (),
+    #[cfg(not(feature = "dht"))]
+    dht_next_refresh_rx: (),
+    #[cfg(not(feature = "dht"))]
+    dht_peer_estimate_rx: (),
 
     settings: Arc<Settings>,
     resource_manager: ResourceManagerClient,
+    hash_pool: HashPool,
+    validation_permits: Arc<Semaphore>,
 
     last_activity: TorrentActivity,
+    final_verification_in_progress: bool,
 
     global_dl_bucket: Arc<Mutex<TokenBucket>>,
     global_ul_bucket: Arc<Mutex<TokenBucket>>,
+    // Peer sessions watch this for the torrent's share of the global limits
+    bandwidth_priority_tx: watch::Sender<BandwidthPriority>,
+    ban_list: BanList,
+    // Shared with the app; None when uTP is off or its port couldn't be bound
+    utp_socket: Option<UtpSocket>,
+
+    http_seeds: Vec<HttpSeedState>,
+    last_peer_block_at: Instant,
 }
 
 impl TorrentManager {
     pub fn from_torrent(
         torrent_parameters: TorrentParameters,
         torrent: Torrent,
-    ) -> Result<Self, String> {
+    ) -> Result<Self, ManagerInitError> {
         let TorrentParameters {
             dht_handle,
+            dht4,
+            dht6,
             incoming_peer_rx,
             metrics_tx,
             torrent_validation_status,
             download_dir,
+            completed_dir,
             manager_command_rx,
             manager_event_tx,
             settings,
             resource_manager,
+            hash_pool,
+            validation_permits,
             global_dl_bucket,
             global_ul_bucket,
+            upload_disabled,
+            download_disabled,
+            max_download_bytes,
+            file_priorities,
+            file_renames,
+            sequential,
+            bandwidth_priority,
+            dht_disabled,
+            pex_disabled,
+            extra_trackers,
+            extra_web_seeds,
+            ban_list,
+            utp_socket,
         } = torrent_parameters;
 
-        let bencoded_data = serde_bencode::to_bytes(&torrent)
-            .map_err(|e| format!("Failed to re-encode torrent struct: {}", e))?;
-
-        let torrent_length = bencoded_data.len();
+        let mut web_seeds: Vec<&String> = torrent.httpseeds.iter().flatten().collect();
+        for url in &extra_web_seeds {
+            if !web_seeds.contains(&url) {
+                web_seeds.push(url);
+            }
+        }
+        let http_seeds = web_seeds
+            .into_iter()
+            .map(|url| HttpSeedState::new(url.clone()))
+            .collect();
 
-        let mut trackers = HashMap::new();
-        if let Some(ref announce) = torrent.announce {
-            trackers.insert(
-                announce.clone(),
-                TrackerState {
-                    next_announce_time: Instant::now(),
-                    leeching_interval: None,
-                    seeding_interval: None,
-                },
-            );
+        let mut tracker_tiers = TrackerTiers::new(torrent.announce_tiers());
+        for url in &extra_trackers {
+            tracker_tiers.push_tier(url.clone());
         }
+        let trackers = tracker_tiers
+            .urls()
+            .map(|url| (url.clone(), TrackerState::new(url)))
+            .collect();
 
-        let mut info_dict_hasher = Sha1::new();
-        info_dict_hasher.update(&torrent.info_dict_bencode);
-        let info_hash = info_dict_hasher.finalize();
+        let info_hash = torrent.info_hash();
 
         let (torrent_manager_tx, torrent_manager_rx) = mpsc::channel::<TorrentCommand>(100);
         let (shutdown_tx, _) = broadcast::channel(1);
 
         #[cfg(feature = "dht")]
-        let (dht_tx, dht_rx) = mpsc::channel::<Vec<SocketAddrV4>>(10);
+        let (dht_tx, dht_rx) = mpsc::channel::<Vec<SocketAddr>>(10);
         #[cfg(not(feature = "dht"))]
         let (dht_tx, dht_rx) = mpsc::channel::<()>(1);
 
@@ -246,14 +620,32 @@ impl TorrentManager {
         #[cfg(not(feature = "dht"))]
         let dht_trigger_tx = ();
 
-        let pieces_len = torrent.info.pieces.len();
+        #[cfg(feature = "dht")]
+        let (_, dht_next_refresh_rx) = watch::channel(Instant::now());
+        #[cfg(not(feature = "dht"))]
+        let dht_next_refresh_rx = ();
+        #[cfg(feature = "dht")]
+        let (_, dht_peer_estimate_rx) = watch::channel(None);
+        #[cfg(not(feature = "dht"))]
+        let dht_peer_estimate_rx = ();
+
+        let num_pieces = piece_count(&torrent).map_err(ManagerInitError::InvalidMetadata)?;
 
         let mut piece_manager = PieceManager::new();
-        piece_manager.set_initial_fields(pieces_len / 20, torrent_validation_status);
+        piece_manager.set_initial_fields(num_pieces as usize, torrent_validation_status);
+        piece_manager.piece_limit =
+            cap_piece_limit(max_download_bytes, torrent.info.piece_length as u64);
+        piece_manager.sequential = sequential;
 
-        let multi_file_info = MultiFileInfo::new(
+        let content_root = content_root_dir(
             &download_dir,
             &torrent.info.name,
+            !torrent.info.files.is_empty(),
+            settings.create_subfolder,
+        );
+        let mut multi_file_info = MultiFileInfo::new(
+            &content_root,
+            &torrent.info.name,
             if torrent.info.files.is_empty() {
                 None
             } else {
@@ -265,23 +657,34 @@ impl TorrentManager {
                 None
             },
         )
-        .map_err(|e| format!("Failed to initialize file manager: {}", e))?;
+        .map_err(|e| {
+            ManagerInitError::Storage(format!("Failed to initialize file manager: {}", e))
+        })?;
+        multi_file_info.apply_renames(&download_dir, &file_renames);
 
-        Ok(Self {
+        let mut manager = Self {
+            torrent_metadata: Some(Arc::new(torrent.info_dict_bencode.clone())),
             torrent: Some(torrent),
-            torrent_metadata_length: Some(torrent_length as i64),
             root_download_path: download_dir,
+            completed_dir,
+            files_moving: false,
+            recheck_after_move: None,
             multi_file_info: Some(multi_file_info),
             is_paused: false,
-            info_hash: info_hash.to_vec(),
+            info_hash,
             peers_map: HashMap::new(),
             timed_out_peers: HashMap::new(),
+            peer_candidates: HashMap::new(),
             trackers,
+            tracker_tiers,
+            announce_key: rand::random(),
             torrent_status: TorrentStatus::Standard,
             torrent_manager_tx,
             torrent_manager_rx,
             number_of_successfully_connected_peers: 0,
             dht_handle,
+            dht4,
+            dht6,
             dht_tx,
             dht_rx,
             dht_task_handle,
@@ -291,6 +694,18 @@ impl TorrentManager {
             torrent_validation_status,
             session_total_uploaded: 0,
             session_total_downloaded: 0,
+            wasted_bytes: 0,
+            metadata_rejected_peers: HashSet::new(),
+            piece_contributors: HashMap::new(),
+            hash_fail_strikes: HashMap::new(),
+            holepunch_relays: HashMap::new(),
+            upload_disabled,
+            download_disabled,
+            max_download_bytes,
+            dht_disabled,
+            pex_disabled,
+            file_priorities,
+            file_renames,
             bytes_downloaded_in_interval: 0,
             bytes_uploaded_in_interval: 0,
             total_dl_prev_avg_ema: 0.0,
@@ -300,40 +715,78 @@ impl TorrentManager {
             last_known_peers: HashSet::new(),
             piece_manager,
             optimistic_unchoke_timer: Instant::now(),
+            optimistic_unchoke_peer: None,
+            partial_seed: false,
+            external_ip: None,
+            magnet_file_selection: None,
+            files: None,
             has_made_first_connection: false,
             in_flight_uploads: HashMap::new(),
+            in_flight_writes: JoinSet::new(),
+            stream_reads: Vec::new(),
+            stream_windows: BTreeMap::new(),
             dht_trigger_tx,
+            dht_next_refresh_rx,
+            dht_peer_estimate_rx,
             settings,
             resource_manager,
+            hash_pool,
+            validation_permits,
             last_activity: TorrentActivity::Initializing,
+            final_verification_in_progress: false,
             global_dl_bucket,
             global_ul_bucket,
-        })
+            bandwidth_priority_tx: watch::channel(bandwidth_priority).0,
+            ban_list,
+            utp_socket,
+            http_seeds,
+            last_peer_block_at: Instant::now(),
+        };
+        manager.init_files();
+        Ok(manager)
     }
 
     pub fn from_magnet(
         torrent_parameters: TorrentParameters,
         magnet: Magnet,
-    ) -> Result<Self, String> {
+    ) -> Result<Self, ManagerInitError> {
         assert_eq!(magnet.hash_type(), Some("btih"));
 
         let TorrentParameters {
             dht_handle,
+            dht4,
+            dht6,
             incoming_peer_rx,
             metrics_tx,
             torrent_validation_status,
             download_dir,
+            completed_dir,
             manager_command_rx,
             manager_event_tx,
             settings,
             resource_manager,
+            hash_pool,
+            validation_permits,
             global_dl_bucket,
             global_ul_bucket,
+            upload_disabled,
+            download_disabled,
+            max_download_bytes,
+            file_priorities,
+            file_renames,
+            sequential,
+            bandwidth_priority,
+            dht_disabled,
+            pex_disabled,
+            extra_trackers,
+            extra_web_seeds,
+            ban_list,
+            utp_socket,
         } = torrent_parameters;
 
-        let hash_string = magnet
-            .hash()
-            .ok_or_else(|| "Magnet link does not contain info hash".to_string())?;
+        let hash_string = magnet.hash().ok_or_else(|| {
+            ManagerInitError::InvalidMetadata("Magnet link does not contain info hash".into())
+        })?;
 
         let info_hash = if hash_string.len() == 40 {
             hex::decode(hash_string).map_err(|e| e.to_string())
@@ -343,40 +796,30 @@ impl TorrentManager {
                 .map_err(|e| e.to_string())
         } else {
             Err(format!("Invalid info_hash length: {}", hash_string.len()))
-        }?;
+        }
+        .map_err(ManagerInitError::InvalidMetadata)?;
         event!(Level::DEBUG, "INFO HASH {:?}", info_hash);
 
-        let trackers_set: HashSet<String> = magnet
-            .trackers()
-            .iter()
-            .filter(|t| t.starts_with("http"))
-            .filter_map(|t| {
-                match decode(t) {
-                    Ok(decoded_url) => Some(decoded_url.into_owned()),
-                    Err(e) => {
-                        event!(Level::DEBUG, tracker_url = %t, error = %e, "Failed to decode tracker URL from magnet link, skipping.");
-                        None
-                    }
-                }
-            })
-            .collect();
-        let mut trackers = HashMap::new();
-        for url in trackers_set {
-            trackers.insert(
-                url.clone(),
-                TrackerState {
-                    next_announce_time: Instant::now(),
-                    leeching_interval: None,
-                    seeding_interval: None,
-                },
-            );
+        // Magnet links have no tiers; each tracker gets its own, in link order.
+        let mut tracker_tiers = TrackerTiers::new(
+            magnet_trackers(&magnet)
+                .into_iter()
+                .map(|url| vec![url])
+                .collect(),
+        );
+        for url in &extra_trackers {
+            tracker_tiers.push_tier(url.clone());
         }
+        let trackers = tracker_tiers
+            .urls()
+            .map(|url| (url.clone(), TrackerState::new(url)))
+            .collect();
 
         let (torrent_manager_tx, torrent_manager_rx) = mpsc::channel::<TorrentCommand>(100);
         let (shutdown_tx, _) = broadcast::channel(1);
 
         #[cfg(feature = "dht")]
-        let (dht_tx, dht_rx) = mpsc::channel::<Vec<SocketAddrV4>>(10);
+        let (dht_tx, dht_rx) = mpsc::channel::<Vec<SocketAddr>>(10);
         #[cfg(not(feature = "dht"))]
         let (dht_tx, dht_rx) = mpsc::channel::<()>(1);
 
@@ -390,21 +833,38 @@ impl TorrentManager {
         #[cfg(not(feature = "dht"))]
         let dht_trigger_tx = ();
 
+        #[cfg(feature = "dht")]
+        let (_, dht_next_refresh_rx) = watch::channel(Instant::now());
+        #[cfg(not(feature = "dht"))]
+        let dht_next_refresh_rx = ();
+        #[cfg(feature = "dht")]
+        let (_, dht_peer_estimate_rx) = watch::channel(None);
+        #[cfg(not(feature = "dht"))]
+        let dht_peer_estimate_rx = ();
+
         Ok(Self {
             torrent: None,
-            torrent_metadata_length: None,
+            torrent_metadata: None,
             root_download_path: download_dir,
+            completed_dir,
+            files_moving: false,
+            recheck_after_move: None,
             multi_file_info: None,
             is_paused: false,
             info_hash,
             trackers,
+            tracker_tiers,
+            announce_key: rand::random(),
             peers_map: HashMap::new(),
             timed_out_peers: HashMap::new(),
+            peer_candidates: HashMap::new(),
             torrent_status: TorrentStatus::Standard,
             torrent_manager_tx,
             torrent_manager_rx,
             number_of_successfully_connected_peers: 0,
             dht_handle,
+            dht4,
+            dht6,
             dht_tx,
             dht_rx,
             dht_task_handle,
@@ -414,6 +874,18 @@ impl TorrentManager {
             torrent_validation_status,
             session_total_uploaded: 0,
             session_total_downloaded: 0,
+            wasted_bytes: 0,
+            metadata_rejected_peers: HashSet::new(),
+            piece_contributors: HashMap::new(),
+            hash_fail_strikes: HashMap::new(),
+            holepunch_relays: HashMap::new(),
+            upload_disabled,
+            download_disabled,
+            max_download_bytes,
+            dht_disabled,
+            pex_disabled,
+            file_priorities,
+            file_renames,
             bytes_downloaded_in_interval: 0,
             bytes_uploaded_in_interval: 0,
             total_dl_prev_avg_ema: 0.0,
@@ -421,16 +893,40 @@ impl TorrentManager {
             manager_command_rx,
             manager_event_tx,
             last_known_peers: HashSet::new(),
-            piece_manager: PieceManager::new(),
+            piece_manager: PieceManager {
+                sequential,
+                ..PieceManager::new()
+            },
             optimistic_unchoke_timer: Instant::now(),
+            optimistic_unchoke_peer: None,
+            partial_seed: false,
+            external_ip: None,
+            magnet_file_selection: None,
+            files: None,
             has_made_first_connection: false,
             in_flight_uploads: HashMap::new(),
+            in_flight_writes: JoinSet::new(),
+            stream_reads: Vec::new(),
+            stream_windows: BTreeMap::new(),
             dht_trigger_tx,
+            dht_next_refresh_rx,
+            dht_peer_estimate_rx,
             settings,
             resource_manager,
+            hash_pool,
+            validation_permits,
             last_activity: TorrentActivity::Initializing,
+            final_verification_in_progress: false,
             global_dl_bucket,
             global_ul_bucket,
+            bandwidth_priority_tx: watch::channel(bandwidth_priority).0,
+            ban_list,
+            utp_socket,
+            http_seeds: extra_web_seeds
+                .into_iter()
+                .map(HttpSeedState::new)
+                .collect(),
+            last_peer_block_at: Instant::now(),
         })
     }
 
@@ -439,38 +935,101 @@ impl TorrentManager {
         if let Some(handle) = self.dht_task_handle.take() {
             handle.abort();
         }
+        if self.settings.dht_mode == DhtMode::Off || !self.dht_allowed() {
+            return;
+        }
 
         let dht_tx_clone = self.dht_tx.clone();
         let dht_handle_clone = self.dht_handle.clone();
+        let dht4 = self.dht4.clone();
+        let dht6 = self.dht6.clone();
         let mut dht_trigger_rx = self.dht_trigger_tx.subscribe();
         let mut shutdown_rx = self.shutdown_tx.subscribe();
-
-        if let Ok(info_hash_id) = Id::from_bytes(self.info_hash.clone()) {
+        let get_peers_interval = self.settings.dht_get_peers_interval();
+        let announce_interval = self.settings.dht_announce_interval();
+        let announce_port = self.settings.client_port;
+        let (next_refresh_tx, next_refresh_rx) = watch::channel(Instant::now());
+        self.dht_next_refresh_rx = next_refresh_rx;
+        let (peer_estimate_tx, peer_estimate_rx) = watch::channel(None);
+        self.dht_peer_estimate_rx = peer_estimate_rx;
+
+        let info_hash_bytes = <[u8; 20]>::try_from(self.info_hash.as_slice());
+        if let (Ok(info_hash_id), Ok(info_hash_bytes)) =
+            (Id::from_bytes(self.info_hash.clone()), info_hash_bytes)
+        {
             let handle = tokio::spawn(async move {
+                let mut next_announce = Instant::now();
                 loop {
                     event!(Level::DEBUG, "DHT task loop running");
                     let mut peers_stream = dht_handle_clone.get_peers(info_hash_id);
-                    tokio::select! {
+                    // Without scrapes, the union of the answers of the nodes closest to
+                    // the info hash is the best guess at the swarm's size.
+                    let mut found_peers = HashSet::new();
+                    let mainline_lookup = async {
+                        while let Some(peers) = peers_stream.next().await {
+                            let peers: Vec<SocketAddr> =
+                                peers.into_iter().map(SocketAddr::V4).collect();
+                            found_peers.extend(peers.iter().copied());
+                            if dht_tx_clone.send(peers).await.is_err() {
+                                return;
+                            }
+                        }
+                    };
+                    let (lookup_v4, lookup_v6) = tokio::select! {
                         _ = shutdown_rx.recv() => {
                             event!(Level::DEBUG, "DHT task shutting down.");
                             break;
                         }
 
-                        _ = async {
-                            while let Some(peer) = peers_stream.next().await {
-                                if dht_tx_clone.send(peer).await.is_err() {
-                                    return;
+                        (_, lookup_v4, lookup_v6) = async {
+                            tokio::join!(
+                                mainline_lookup,
+                                dht_client_lookup(dht4.as_ref(), info_hash_bytes, &dht_tx_clone),
+                                dht_client_lookup(dht6.as_ref(), info_hash_bytes, &dht_tx_clone),
+                            )
+                        } => (lookup_v4, lookup_v6),
+                    };
+                    let lookups: Vec<&Lookup> = lookup_v4.iter().chain(&lookup_v6).collect();
+                    for lookup in &lookups {
+                        found_peers.extend(lookup.peers.iter().copied());
+                    }
+                    let _ = peer_estimate_tx.send(Some(DhtSwarmEstimate {
+                        peers: found_peers.len(),
+                        scrape: dht_client::estimate_swarm(lookups),
+                    }));
+
+                    if Instant::now() >= next_announce {
+                        let announce_v6 = async {
+                            if let (Some(dht6), Some(lookup)) = (&dht6, &lookup_v6) {
+                                dht6.announce(info_hash_bytes, lookup, announce_port).await;
+                            }
+                        };
+                        tokio::select! {
+                            _ = shutdown_rx.recv() => {
+                                event!(Level::DEBUG, "DHT task shutting down.");
+                                break;
+                            }
+                            (result, ()) = async {
+                                tokio::join!(
+                                    dht_handle_clone.announce_peer(info_hash_id, Some(announce_port)),
+                                    announce_v6,
+                                )
+                            } => {
+                                if let Err(e) = result {
+                                    event!(Level::DEBUG, "DHT announce failed: {:?}", e);
                                 }
                             }
-                        } => {}
+                        }
+                        next_announce = Instant::now() + announce_interval;
                     }
 
+                    let _ = next_refresh_tx.send(Instant::now() + get_peers_interval);
                     tokio::select! {
                         _ = shutdown_rx.recv() => {
                             event!(Level::DEBUG, "DHT task shutting down.");
                             break;
                         }
-                        _ = tokio::time::sleep(Duration::from_secs(300)) => {}
+                        _ = tokio::time::sleep(get_peers_interval) => {}
                         _ = dht_trigger_rx.changed() => {}
                     }
                 }
@@ -479,179 +1038,1475 @@ impl TorrentManager {
         }
     }
 
-    async fn sleep_with_shutdown(
-        duration: Duration,
-        shutdown_rx: &mut broadcast::Receiver<()>,
-    ) -> Result<(), ()> {
-        tokio::select! {
-            biased; // Prioritize shutdown
-            _ = shutdown_rx.recv() => Err(()),
-            _ = tokio::time::sleep(duration) => Ok(()),
-        }
+    /// Private torrents (BEP 27) only get peers from their trackers. A magnet's
+    /// privacy is unknown until its metadata arrives.
+    fn is_private(&self) -> bool {
+        self.torrent
+            .as_ref()
+            .is_some_and(|torrent| torrent.info.private == Some(1))
     }
 
-    fn recalculate_chokes(&mut self) {
-        // Implements BitTorrent's choking algorithm to manage upload slots.
-        // 1. Sort interested peers by their download rate (or upload rate if seeding).
-        // 2. Unchoke the top N peers (`upload_slots`).
-        // 3. Every 30 seconds, optimistically unchoke one additional random peer.
-        // 4. Choke all other interested peers.
-        let mut interested_peers: Vec<_> = self
-            .peers_map
-            .values_mut()
-            .filter(|p| p.peer_is_interested_in_us)
-            .collect();
+    #[cfg(feature = "dht")]
+    fn dht_allowed(&self) -> bool {
+        !self.dht_disabled && !self.is_private()
+    }
 
-        if self.torrent_status == TorrentStatus::Done {
-            interested_peers
-                .sort_by(|a, b| b.bytes_uploaded_to_peer.cmp(&a.bytes_uploaded_to_peer));
-        } else {
-            interested_peers.sort_by(|a, b| {
-                b.bytes_downloaded_from_peer
-                    .cmp(&a.bytes_downloaded_from_peer)
-            });
-        }
+    fn pex_allowed(&self) -> bool {
+        !self.pex_disabled && !self.is_private()
+    }
 
-        let mut unchoke_candidates: HashSet<String> = interested_peers
-            .iter()
-            .take(self.settings.upload_slots)
-            .map(|p| p.ip_port.clone())
-            .collect();
+    /// Stops every task spawned on behalf of this torrent: pending connection
+    /// attempts, peer sessions (including ones still fetching metadata), the
+    /// DHT lookup loop and in-flight uploads.
+    fn shutdown_background_tasks(&mut self) {
+        let _ = self.shutdown_tx.send(());
 
-        if self.optimistic_unchoke_timer.elapsed() > Duration::from_secs(30) {
-            let optimistic_candidates: Vec<_> = interested_peers
-                .iter()
-                .filter(|p| !unchoke_candidates.contains(&p.ip_port))
-                .collect();
+        #[cfg(feature = "dht")]
+        if let Some(handle) = self.dht_task_handle.take() {
+            handle.abort();
+        }
 
-            if let Some(optimistic_peer) = optimistic_candidates.choose(&mut rand::rng()) {
-                unchoke_candidates.insert(optimistic_peer.ip_port.clone());
+        event!(Level::DEBUG, "Aborting all in-flight upload tasks...");
+        for (_peer_id, handles_map) in self.in_flight_uploads.iter() {
+            for (block_info, handle) in handles_map.iter() {
+                event!(Level::TRACE, ?block_info, "Aborting task");
+                handle.abort();
             }
-            self.optimistic_unchoke_timer = Instant::now();
         }
+        self.in_flight_uploads.clear();
+        event!(Level::DEBUG, "All upload tasks aborted.");
 
-        for (peer_id, peer) in self.peers_map.iter_mut() {
-            if unchoke_candidates.contains(peer_id) {
-                if peer.am_choking == ChokeStatus::Choke {
-                    peer.am_choking = ChokeStatus::Unchoke;
-                    let peer_tx = peer.peer_tx.clone();
-                    let _ = peer_tx.try_send(TorrentCommand::PeerUnchoke);
-                }
-            } else if peer.am_choking == ChokeStatus::Unchoke {
-                peer.am_choking = ChokeStatus::Choke;
-                let peer_tx = peer.peer_tx.clone();
-                let _ = peer_tx.try_send(TorrentCommand::PeerChoke);
+        self.peers_map.clear();
+        self.last_known_peers.clear();
+    }
+
+    /// Saves the blocks of unfinished pieces so the next run doesn't download them again.
+    /// Without any, a file left by an earlier run stays until it's restored.
+    async fn save_partial_pieces(&self) {
+        if self.piece_manager.piece_assemblers.is_empty() {
+            return;
+        }
+        let Some(path) = partial_pieces_path(&self.info_hash) else {
+            return;
+        };
+        let bytes = self.piece_manager.encode_partial_pieces();
+        let temp_path = path.with_extension("tmp");
+        let result = async {
+            if let Some(parent_dir) = path.parent() {
+                tokio::fs::create_dir_all(parent_dir).await?;
             }
+            tokio::fs::write(&temp_path, &bytes).await?;
+            tokio::fs::rename(&temp_path, &path).await
+        }
+        .await;
+        match result {
+            Ok(()) => event!(
+                Level::INFO,
+                pieces = self.piece_manager.piece_assemblers.len(),
+                "Saved partly downloaded pieces."
+            ),
+            Err(e) => event!(Level::WARN, error = %e, "Could not save partly downloaded pieces."),
         }
+    }
 
-        for peer in self.peers_map.values_mut() {
-            peer.bytes_downloaded_from_peer = 0;
-            peer.bytes_uploaded_to_peer = 0;
+    /// Puts back the blocks saved by `save_partial_pieces` once validation knows which
+    /// pieces are still needed. The file is removed either way.
+    async fn restore_partial_pieces(&mut self) {
+        let Some(path) = partial_pieces_path(&self.info_hash) else {
+            return;
+        };
+        let Ok(bytes) = tokio::fs::read(&path).await else {
+            return;
+        };
+        let _ = tokio::fs::remove_file(&path).await;
+        let (Some(torrent), Some(multi_file_info)) = (&self.torrent, &self.multi_file_info) else {
+            return;
+        };
+        let piece_length = torrent.info.piece_length as u64;
+        let total_size = multi_file_info.total_size;
+        let restored = self
+            .piece_manager
+            .restore_partial_pieces(&bytes, |piece_index| {
+                expected_piece_size(piece_index, piece_length, total_size)
+            });
+        event!(
+            Level::INFO,
+            blocks = restored,
+            "Restored partly downloaded pieces."
+        );
+    }
+
+    /// Waits for piece writes that are already running so verified data isn't lost on
+    /// exit. Anything still running after `SHUTDOWN_WRITE_FLUSH_TIMEOUT` is aborted.
+    async fn flush_in_flight_writes(&mut self) {
+        if self.in_flight_writes.is_empty() {
+            return;
+        }
+
+        event!(
+            Level::DEBUG,
+            "Waiting for {} piece writes to finish...",
+            self.in_flight_writes.len()
+        );
+        let writes = &mut self.in_flight_writes;
+        if tokio::time::timeout(SHUTDOWN_WRITE_FLUSH_TIMEOUT, async {
+            while writes.join_next().await.is_some() {}
+        })
+        .await
+        .is_err()
+        {
+            event!(
+                Level::WARN,
+                "{} piece writes did not finish in time. Aborting them.",
+                self.in_flight_writes.len()
+            );
+            self.in_flight_writes.abort_all();
         }
     }
 
-    /// Generates a bitfield message that represents the pieces the client currently has.
-    /// This is sent to peers to inform them of what pieces they can request.
-    fn generate_bitfield(&mut self) -> Vec<u8> {
-        let num_pieces = self.piece_manager.bitfield.len();
-        let num_bytes = num_pieces.div_ceil(8);
-        let mut bitfield_bytes = vec![0u8; num_bytes];
-
-        for (piece_index, status) in self.piece_manager.bitfield.iter().enumerate() {
-            if *status == PieceStatus::Done {
-                let byte_index = piece_index / 8;
-                let bit_index_in_byte = piece_index % 8;
-                let mask = 1 << (7 - bit_index_in_byte);
-                bitfield_bytes[byte_index] |= mask;
-            }
+    /// Disconnects every peer, remembering them so they can be reconnected on resume.
+    fn disconnect_all_peers(&mut self) {
+        for peer in self.peers_map.values() {
+            let peer_tx = peer.peer_tx.clone();
+            let peer_ip_port = peer.ip_port.clone();
+            let _ = peer_tx.try_send(TorrentCommand::Disconnect(peer_ip_port));
         }
 
-        bitfield_bytes
+        self.last_known_peers = self.peers_map.keys().cloned().collect();
+        self.peers_map.clear();
     }
 
-    /// Checks if all pieces have been downloaded. If so, it transitions the torrent
-    /// to the 'Done' state, sends a 'completed' announcement to trackers, and updates
-    /// peer states to 'not interested'.
-    fn check_for_completion(&mut self) {
-        let _torrent = self.torrent.clone().expect("Torrent metadata not ready.");
+    /// Reconnects to the peers we had before disconnecting from all of them, and
+    /// announces to trackers and the DHT right away.
+    async fn rejoin_swarm(&mut self) {
+        #[cfg(feature = "dht")]
+        let _ = self.dht_trigger_tx.send(());
 
-        if self.torrent_status != TorrentStatus::Done
-            && self
-                .piece_manager
-                .bitfield
-                .iter()
-                .all(|status| *status == PieceStatus::Done)
-        {
-            self.torrent_status = TorrentStatus::Done;
+        let last_known_peers = std::mem::take(&mut self.last_known_peers)
+            .iter()
+            .filter_map(|peer_addr| peer_addr.parse::<SocketAddr>().ok())
+            .map(|socket_addr| (socket_addr.ip().to_string(), socket_addr.port()))
+            .collect();
+        self.connect_to_peers(last_known_peers, PeerSource::Saved)
+            .await;
+        for tracker_state in self.trackers.values_mut() {
+            tracker_state.next_announce_time = Instant::now();
+        }
+    }
 
-            for url in self.trackers.keys() {
-                let url_clone = url.clone();
-                let info_hash_clone = self.info_hash.clone();
-                let client_port_clone = self.settings.client_port;
-                let client_id_clone = self.settings.client_id.clone();
-                let session_total_uploaded_clone = self.session_total_uploaded as usize;
-                let session_total_downloaded_clone = self.session_total_downloaded as usize;
-                tokio::spawn(async move {
-                    let _ = announce_completed(
-                        url_clone,
-                        &info_hash_clone,
-                        client_id_clone,
-                        client_port_clone,
-                        session_total_uploaded_clone,
-                        session_total_downloaded_clone,
-                    )
-                    .await;
-                });
-            }
-            for tracker in self.trackers.values_mut() {
-                tracker.next_announce_time = Instant::now();
-            }
+    /// Whether `peer_ip_port` already sent bad metadata (while we still need it) or
+    /// too many corrupt pieces this session. The second outlasts the peer's ban.
+    fn is_rejected_source(&self, peer_ip_port: &str) -> bool {
+        let threshold = self.settings.hash_fail_ban_threshold;
+        (self.torrent.is_none() && self.metadata_rejected_peers.contains(peer_ip_port))
+            || (threshold > 0
+                && peer_ip(peer_ip_port)
+                    .and_then(|ip| self.hash_fail_strikes.get(&ip))
+                    .is_some_and(|strikes| *strikes >= threshold))
+    }
 
-            for peer in self.peers_map.values_mut() {
-                peer.am_interested = false;
-                let peer_tx_cloned = peer.peer_tx.clone();
-                let _ = peer_tx_cloned.try_send(TorrentCommand::NotInterested);
+    /// Gives a strike to every peer that sent blocks of `piece_index`, which failed
+    /// the hash check, and bans those reaching `hash_fail_ban_threshold`. A peer
+    /// that supplied the whole piece is certainly at fault and is disconnected
+    /// either way.
+    fn record_hash_failure(&mut self, piece_index: u32, contributors: HashSet<String>) {
+        let threshold = self.settings.hash_fail_ban_threshold;
+        let ban_duration = Duration::from_secs(self.settings.peer_ban_duration_secs);
+        let sole_source = contributors.len() == 1;
+        let mut banned_any = false;
+
+        for peer_ip_port in &contributors {
+            let Some(ip) = peer_ip(peer_ip_port) else {
+                continue;
+            };
+            let strikes = self.hash_fail_strikes.entry(ip).or_insert(0);
+            *strikes += 1;
+            let strikes = *strikes;
+
+            if threshold > 0 && strikes >= threshold {
+                event!(Level::WARN, peer = %peer_ip_port, piece = piece_index, strikes, "Banning peer for sending corrupt pieces.");
+                self.ban_list.ban(ip, ban_duration);
+                banned_any = true;
+            } else if sole_source {
+                if let Some(peer) = self.peers_map.get(peer_ip_port) {
+                    event!(Level::WARN, peer = %peer_ip_port, strikes, "Disconnecting from peer due to sending corrupt piece.");
+                    let _ = peer
+                        .peer_tx
+                        .try_send(TorrentCommand::Disconnect(peer_ip_port.clone()));
+                }
             }
         }
+
+        if banned_any {
+            self.disconnect_banned_peers();
+        }
     }
 
-    /// Identifies the rarest available piece that a peer has and assigns it to them for download.
-    /// This is the core of the piece selection strategy.
-    fn find_and_assign_work(&mut self, peer_id: String) {
-        if self.piece_manager.need_queue.is_empty() && self.piece_manager.pending_queue.is_empty() {
+    /// Maps the files onto pieces once the metadata is known, and applies the saved
+    /// file priorities or, without any, the files the magnet link's so= picked.
+    fn init_files(&mut self) {
+        let (Some(torrent), Some(multi_file_info)) = (&self.torrent, &self.multi_file_info) else {
             return;
+        };
+        let mut files = Files::new(multi_file_info, torrent.info.piece_length as u64);
+
+        if let Some(file_priorities) = &self.file_priorities {
+            files.set_priorities(file_priorities);
+        } else if let Some(ranges) = &self.magnet_file_selection {
+            files.set_priority(&[0..=usize::MAX], FilePriority::Skip);
+            files.set_priority(ranges, FilePriority::Normal);
+            if files
+                .wanted_pieces()
+                .is_some_and(|pieces| pieces.is_empty())
+            {
+                event!(
+                    Level::WARN,
+                    info_hash = %BASE32.encode(&self.info_hash),
+                    "No file matches the magnet link's file selection. Downloading everything."
+                );
+                files.set_priority(&[0..=usize::MAX], FilePriority::Normal);
+            } else {
+                self.file_priorities = Some(files.priorities());
+            }
         }
 
-        let torrent = self.torrent.clone().expect("Torrent metadata not ready.");
-        let multi_file_info = self.multi_file_info.as_ref().expect("File info not ready.");
+        self.files = Some(files);
+        self.apply_file_priorities();
+    }
 
-        if let Some(peer) = self.peers_map.get_mut(&peer_id) {
-            if peer.bitfield.is_empty() || peer.peer_choking == ChokeStatus::Choke {
-                if peer.peer_choking == ChokeStatus::Choke
-                    && !peer.am_interested
-                    && self
-                        .piece_manager
-                        .need_queue
-                        .iter()
-                        .any(|&p| peer.bitfield.get(p as usize) == Some(&true))
-                {
-                    peer.am_interested = true;
-                    peer.peer_choking = ChokeStatus::Pending;
-                    let peer_tx_cloned = peer.peer_tx.clone();
-                    let _ = peer_tx_cloned.try_send(TorrentCommand::ClientInterested);
-                }
-                return;
-            }
+    /// Limits the picker to the pieces wanted files touch, and has it fetch those of
+    /// higher priority files first.
+    fn apply_file_priorities(&mut self) {
+        let Some(files) = &self.files else {
+            return;
+        };
+        self.piece_manager.selected_pieces = files.wanted_pieces();
+        self.piece_manager.piece_priorities = files.piece_priorities();
+    }
 
-            let piece_to_assign = self.piece_manager.choose_piece_for_peer(
-                &peer.bitfield,
-                &peer.pending_requests,
-                &self.torrent_status,
+    /// Gives the files in `ranges` a new priority. Pieces that only skipped files have
+    /// data in are no longer requested; pieces already on the way still arrive.
+    fn set_file_priority(&mut self, ranges: &[RangeInclusive<usize>], priority: FilePriority) {
+        let Some(files) = self.files.as_mut() else {
+            event!(
+                Level::WARN,
+                info_hash = %BASE32.encode(&self.info_hash),
+                "Ignoring file priorities set before the metadata arrived."
             );
+            return;
+        };
+        if !files.set_priority(ranges, priority) {
+            return;
+        }
+        self.file_priorities = Some(files.priorities());
+        self.apply_file_priorities();
 
-            if let Some(piece_index) = piece_to_assign {
+        if !self.is_paused {
+            let peer_ids: Vec<String> = self.peers_map.keys().cloned().collect();
+            for peer_id in peer_ids {
+                self.find_and_assign_work(peer_id);
+            }
+        }
+    }
+
+    /// Keeps the magnet link's `so=` file selection (see `magnet_select_only`) for
+    /// when the metadata arrives.
+    pub fn with_magnet_file_selection(
+        mut self,
+        selection: Option<Vec<RangeInclusive<usize>>>,
+    ) -> Self {
+        self.magnet_file_selection = selection;
+        self
+    }
+
+    /// The torrent's files for the files view, with how much of each we have.
+    fn file_infos(&self) -> Vec<TorrentFileInfo> {
+        let (Some(files), Some(multi_file_info)) = (&self.files, &self.multi_file_info) else {
+            return Vec::new();
+        };
+        let priorities = files.priorities();
+        let is_done = |piece: u32| {
+            self.piece_manager.bitfield.get(piece as usize) == Some(&PieceStatus::Done)
+        };
+        multi_file_info
+            .files
+            .iter()
+            .enumerate()
+            .filter(|(_, file)| !file.is_padding)
+            .map(|(index, file)| TorrentFileInfo {
+                index,
+                path: file
+                    .path
+                    .strip_prefix(&self.root_download_path)
+                    .unwrap_or(&file.path)
+                    .to_string_lossy()
+                    .into_owned(),
+                size: file.length,
+                bytes_done: files.bytes_done(index, is_done),
+                priority: priorities[index],
+            })
+            .collect()
+    }
+
+    /// The torrent's files for the streaming server, in torrent order.
+    fn stream_files(&self) -> Vec<StreamFile> {
+        self.multi_file_info
+            .iter()
+            .flat_map(|multi_file_info| &multi_file_info.files)
+            .map(|file| StreamFile {
+                name: file
+                    .path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                size: file.length,
+                is_padding: file.is_padding,
+            })
+            .collect()
+    }
+
+    /// Queues a streaming read and moves the pieces it needs to the front of the
+    /// picker, along with some read-ahead and the file's first and last pieces,
+    /// where containers keep their headers and indexes. Reads outside the file are
+    /// dropped.
+    fn queue_stream_read(
+        &mut self,
+        stream_id: u64,
+        file_index: usize,
+        offset: u64,
+        length: usize,
+        reply_tx: Sender<Result<Vec<u8>, StorageError>>,
+    ) {
+        let Some(piece_length) = self.torrent.as_ref().map(|t| t.info.piece_length as u64) else {
+            return;
+        };
+        let Some(file) = self
+            .multi_file_info
+            .as_ref()
+            .and_then(|multi_file_info| multi_file_info.files.get(file_index))
+        else {
+            return;
+        };
+        if file.is_padding || length == 0 || offset.saturating_add(length as u64) > file.length {
+            return;
+        }
+
+        let global_offset = file.global_start_offset + offset;
+        let first_piece = (global_offset / piece_length) as u32;
+        let last_piece = ((global_offset + length as u64 - 1) / piece_length) as u32;
+        let file_first_piece = (file.global_start_offset / piece_length) as u32;
+        let file_last_piece = ((file.global_start_offset + file.length - 1) / piece_length) as u32;
+        let readahead_end = last_piece
+            .saturating_add(STREAM_READAHEAD_PIECES)
+            .min(file_last_piece);
+
+        // Skipped or capped pieces never arrive, so the read would wait forever.
+        let unwanted = (first_piece..=last_piece).any(|piece| {
+            self.piece_manager.bitfield.get(piece as usize) != Some(&PieceStatus::Done)
+                && !self.piece_manager.is_wanted(piece)
+        });
+        if unwanted {
+            let _ = reply_tx.try_send(Err(StorageError::Io(std::io::Error::other(
+                "the file is skipped or past the download cap",
+            ))));
+            return;
+        }
+
+        self.stream_windows.insert(
+            stream_id,
+            (first_piece..=readahead_end)
+                .chain([file_first_piece, file_last_piece])
+                .collect(),
+        );
+        self.piece_manager
+            .set_priority_pieces(stream_priority(&self.stream_windows));
+        self.stream_reads.push(PendingStreamRead {
+            global_offset,
+            length,
+            reply_tx,
+        });
+        self.serve_stream_reads();
+
+        // Idle peers start on the streamed pieces straight away.
+        if !self.is_paused {
+            let idle_peers: Vec<String> = self
+                .peers_map
+                .values()
+                .filter(|peer| peer.pending_requests.is_empty())
+                .map(|peer| peer.ip_port.clone())
+                .collect();
+            for peer_id in idle_peers {
+                self.find_and_assign_work(peer_id);
+            }
+        }
+    }
+
+    /// Forgets the pieces a closed stream wanted, so the picker goes back to the
+    /// user's order once no stream needs them.
+    fn end_stream(&mut self, stream_id: u64) {
+        if self.stream_windows.remove(&stream_id).is_some() {
+            self.piece_manager
+                .set_priority_pieces(stream_priority(&self.stream_windows));
+        }
+    }
+
+    /// Reads and sends every queued streaming read whose pieces are all on disk.
+    fn serve_stream_reads(&mut self) {
+        let (Some(torrent), Some(multi_file_info)) = (&self.torrent, &self.multi_file_info) else {
+            return;
+        };
+        let piece_length = torrent.info.piece_length as u64;
+        let bitfield = &self.piece_manager.bitfield;
+
+        let (ready, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.stream_reads)
+            .into_iter()
+            .filter(|read| !read.reply_tx.is_closed())
+            .partition(|read| {
+                let first_piece = read.global_offset / piece_length;
+                let last_piece = (read.global_offset + read.length as u64 - 1) / piece_length;
+                (first_piece..=last_piece)
+                    .all(|piece| bitfield.get(piece as usize) == Some(&PieceStatus::Done))
+            });
+        self.stream_reads = waiting;
+
+        for read in ready {
+            let multi_file_info = multi_file_info.clone();
+            tokio::spawn(async move {
+                let result =
+                    read_data_from_disk(&multi_file_info, read.global_offset, read.length).await;
+                let _ = read.reply_tx.send(result).await;
+            });
+        }
+    }
+
+    /// Whether so few blocks are missing that endgame should start before every
+    /// piece has been requested.
+    fn blocks_low_enough_for_endgame(&self) -> bool {
+        let pieces_remaining = self.piece_manager.pieces_remaining;
+        // Every missing piece is missing at least one block
+        if pieces_remaining == 0 || pieces_remaining >= ENDGAME_BLOCK_THRESHOLD {
+            return false;
+        }
+        let Some(torrent) = &self.torrent else {
+            return false;
+        };
+        let blocks_per_piece = (torrent.info.piece_length as usize).div_ceil(BLOCK_SIZE);
+        self.piece_manager.remaining_blocks(blocks_per_piece) < ENDGAME_BLOCK_THRESHOLD
+    }
+
+    /// Endgame: tells every other peer fetching `piece_index` to drop its request for
+    /// a block that just arrived.
+    fn cancel_duplicate_block(&self, from_peer: &str, piece_index: u32, offset: u32, length: u32) {
+        let Some(peer_ids) = self.piece_manager.pending_queue.get(&piece_index) else {
+            return;
+        };
+        for peer_id in peer_ids.iter().filter(|peer_id| *peer_id != from_peer) {
+            if let Some(peer) = self.peers_map.get(peer_id) {
+                let _ =
+                    peer.peer_tx
+                        .try_send(TorrentCommand::CancelBlock(piece_index, offset, length));
+            }
+        }
+    }
+
+    /// Dials a peer a relay introduced us to. Private torrents only take peers
+    /// from their tracker, so they ignore it.
+    async fn holepunch_connect(&mut self, peer_addr: SocketAddr) {
+        if !self.pex_allowed() {
+            return;
+        }
+        // A relay set this up, so the usual backoff doesn't apply.
+        let target_ip_port = peer_ip_port(&peer_addr.ip().to_string(), peer_addr.port());
+        self.timed_out_peers.remove(&target_ip_port);
+        self.connect_to_peer(
+            peer_addr.ip().to_string(),
+            peer_addr.port(),
+            PeerSource::Holepunch,
+        )
+        .await;
+    }
+
+    /// Relays a holepunch rendezvous (BEP 55): `from` and the peer at `target` are both
+    /// told to connect to each other, or `from` hears why they can't be.
+    fn relay_holepunch(&self, from: &str, target: SocketAddr) {
+        if !self.pex_allowed() {
+            return;
+        }
+        let (Some(from_peer), Ok(from_addr)) =
+            (self.peers_map.get(from), from.parse::<SocketAddr>())
+        else {
+            return;
+        };
+        let target_ip_port = peer_ip_port(&target.ip().to_string(), target.port());
+
+        let result = match self.peers_map.get(&target_ip_port) {
+            None => Err(HolepunchError::NoSuchPeer),
+            Some(_) if target_ip_port == from => Err(HolepunchError::NoSuchPeer),
+            Some(peer) if peer.peer_id.is_empty() => Err(HolepunchError::NotConnected),
+            Some(peer) if !peer.supports_holepunch => Err(HolepunchError::NoSupport),
+            Some(peer) => {
+                let _ = peer.peer_tx.try_send(TorrentCommand::SendHolepunch(
+                    HolepunchMessage::Connect(from_addr),
+                ));
+                Ok(())
+            }
+        };
+        let reply = match result {
+            Ok(()) => HolepunchMessage::Connect(target),
+            Err(error) => {
+                event!(Level::DEBUG, peer = %from, target = %target, ?error, "Can't relay holepunch.");
+                HolepunchMessage::Error(target, error)
+            }
+        };
+        let _ = from_peer
+            .peer_tx
+            .try_send(TorrentCommand::SendHolepunch(reply));
+    }
+
+    /// Asks the peer that told us about `peer_ip_port` to introduce us, now that a
+    /// direct connection failed. Each PEX peer is only tried once.
+    fn request_holepunch(&mut self, peer_ip_port: &str) {
+        let Some(relay_ip_port) = self.holepunch_relays.remove(peer_ip_port) else {
+            return;
+        };
+        let (Some(relay), Ok(target)) = (
+            self.peers_map.get(&relay_ip_port),
+            peer_ip_port.parse::<SocketAddr>(),
+        ) else {
+            return;
+        };
+        if relay.supports_holepunch {
+            event!(Level::DEBUG, peer = %peer_ip_port, relay = %relay_ip_port, "Requesting holepunch rendezvous.");
+            let _ = relay.peer_tx.try_send(TorrentCommand::SendHolepunch(
+                HolepunchMessage::Rendezvous(target),
+            ));
+        }
+    }
+
+    /// Drops a peer whose metadata was bad, leaving the other peers to supply it.
+    fn reject_metadata_source(&mut self, peer_id: &str) {
+        if self.metadata_rejected_peers.insert(peer_id.to_string()) {
+            event!(
+                Level::WARN,
+                peer = %peer_id,
+                rejected_peers = self.metadata_rejected_peers.len(),
+                "Rejected torrent metadata from peer."
+            );
+        }
+        if let Some(peer) = self.peers_map.get(peer_id) {
+            let _ = peer
+                .peer_tx
+                .try_send(TorrentCommand::Disconnect(peer_id.to_string()));
+        }
+
+        // Look for more peers in case every connected one is sending the same thing.
+        #[cfg(feature = "dht")]
+        let _ = self.dht_trigger_tx.send(());
+    }
+
+    /// Disconnects peers whose IP is on the ban list.
+    fn disconnect_banned_peers(&mut self) {
+        for peer in self.peers_map.values() {
+            if self.ban_list.is_peer_banned(&peer.ip_port) {
+                event!(Level::INFO, peer = %peer.ip_port, "Disconnecting banned peer.");
+                let _ = peer
+                    .peer_tx
+                    .try_send(TorrentCommand::Disconnect(peer.ip_port.clone()));
+            }
+        }
+    }
+
+    /// Gives each idle HTTP seed a needed piece, but only once no peer has sent us a
+    /// block for `HTTP_SEED_FALLBACK_AFTER`, so seeds carry a dead swarm without
+    /// competing with live peers.
+    fn assign_http_seed_work(&mut self) {
+        if self.http_seeds.is_empty()
+            || self.is_paused
+            || self.torrent.is_none()
+            || self.downloads_disabled()
+            || self.torrent_status == TorrentStatus::Done
+            || self.last_peer_block_at.elapsed() < HTTP_SEED_FALLBACK_AFTER
+        {
+            return;
+        }
+
+        let now = Instant::now();
+        for seed_index in 0..self.http_seeds.len() {
+            if !self.http_seeds[seed_index].is_ready(now) {
+                continue;
+            }
+            let Some(piece_index) = self
+                .piece_manager
+                .need_queue
+                .iter()
+                .copied()
+                .find(|&piece_index| self.piece_manager.is_wanted(piece_index))
+            else {
+                return;
+            };
+
+            let url = self.http_seeds[seed_index].url.clone();
+            self.http_seeds[seed_index].piece_in_flight = Some(piece_index);
+            self.piece_manager.mark_as_pending(piece_index, url.clone());
+            event!(Level::DEBUG, seed = %url, piece = piece_index, "Requesting piece from HTTP seed.");
+
+            let piece_size = self.get_piece_size(piece_index);
+            let info_hash = self.info_hash.clone();
+            let torrent_manager_tx = self.torrent_manager_tx.clone();
+            let global_dl_bucket = self.global_dl_bucket.clone();
+            let priority = *self.bandwidth_priority_tx.borrow();
+            let mut shutdown_rx = self.shutdown_tx.subscribe();
+            tokio::spawn(async move {
+                let result = tokio::select! {
+                    result = fetch_http_seed_piece(&url, &info_hash, piece_index, piece_size) => result,
+                    _ = shutdown_rx.recv() => return,
+                };
+                if let Ok(piece_data) = &result {
+                    consume_tokens(&global_dl_bucket, piece_data.len() as f64, priority).await;
+                }
+                let _ = torrent_manager_tx
+                    .send(TorrentCommand::HttpSeedPiece {
+                        url,
+                        piece_index,
+                        result,
+                    })
+                    .await;
+            });
+        }
+    }
+
+    /// Disconnects peers that have sent us nothing and taken nothing from us for
+    /// `peer_idle_disconnect_secs`, longest idle first. Keeps `min_connected_peers`
+    /// and any peer that is the only one with a piece we still need.
+    fn disconnect_idle_peers(&mut self) {
+        if self.settings.peer_idle_disconnect_secs == 0 {
+            return;
+        }
+        let idle_after = Duration::from_secs(self.settings.peer_idle_disconnect_secs);
+        let mut to_disconnect = self
+            .peers_map
+            .len()
+            .saturating_sub(self.settings.min_connected_peers);
+        if to_disconnect == 0 {
+            return;
+        }
+
+        let mut idle_peers: Vec<&PeerState> = self
+            .peers_map
+            .values()
+            .filter(|peer| peer.last_transfer.elapsed() >= idle_after)
+            .collect();
+        idle_peers.sort_by_key(|peer| peer.last_transfer);
+
+        for peer in idle_peers {
+            if to_disconnect == 0 {
+                break;
+            }
+            if self.is_only_source_of_needed_piece(peer) {
+                continue;
+            }
+            event!(Level::DEBUG, peer = %peer.ip_port, "Disconnecting idle peer.");
+            let _ = peer
+                .peer_tx
+                .try_send(TorrentCommand::Disconnect(peer.ip_port.clone()));
+            to_disconnect -= 1;
+        }
+    }
+
+    /// Marks peers that sent no block for the snub timeout while we had requests out
+    /// to them, and hands their pieces back so faster peers can pick them up.
+    fn detect_snubbed_peers(&mut self) {
+        let snub_after = self.settings.peer_snub_timeout();
+        for peer in self.peers_map.values_mut() {
+            if peer.snubbed
+                || peer.pending_requests.is_empty()
+                || peer.awaiting_block_since.elapsed() < snub_after
+            {
+                continue;
+            }
+            event!(Level::DEBUG, peer = %peer.ip_port, "Peer snubbed us, releasing its pieces.");
+            peer.snubbed = true;
+            for piece_index in peer.pending_requests.drain() {
+                self.piece_manager
+                    .release_pending(piece_index, &peer.ip_port);
+                let _ = peer.peer_tx.try_send(TorrentCommand::Cancel(piece_index));
+            }
+        }
+    }
+
+    /// Whether no other connected peer has one of the wanted pieces `peer` has, going
+    /// by the rarity counts from the last choke round.
+    fn is_only_source_of_needed_piece(&self, peer: &PeerState) -> bool {
+        if self.torrent_status == TorrentStatus::Done {
+            return false;
+        }
+        self.piece_manager
+            .piece_rarity
+            .iter()
+            .any(|(&piece_index, &count)| {
+                count == 1
+                    && self.piece_manager.is_wanted(piece_index)
+                    && self.piece_manager.bitfield.get(piece_index as usize)
+                        == Some(&PieceStatus::Need)
+                    && peer.bitfield.has(piece_index as usize)
+            })
+    }
+
+    /// Adds trackers and web seeds from another copy of the torrent, each tracker as a
+    /// new lowest tier. Returns the ones that weren't known yet.
+    fn add_sources(
+        &mut self,
+        trackers: Vec<String>,
+        web_seeds: Vec<String>,
+    ) -> (Vec<String>, Vec<String>) {
+        // A private torrent only announces where its metadata says.
+        if self.is_private() {
+            return (Vec::new(), Vec::new());
+        }
+        let mut new_trackers = Vec::new();
+        for url in trackers {
+            if url.is_empty() || self.tracker_tiers.contains(&url) {
+                continue;
+            }
+            self.tracker_tiers.push_tier(url.clone());
+            self.trackers.insert(url.clone(), TrackerState::new(&url));
+            new_trackers.push(url);
+        }
+
+        let mut new_web_seeds = Vec::new();
+        for url in web_seeds {
+            if url.is_empty() || self.http_seeds.iter().any(|seed| seed.url == url) {
+                continue;
+            }
+            self.http_seeds.push(HttpSeedState::new(url.clone()));
+            new_web_seeds.push(url);
+        }
+        (new_trackers, new_web_seeds)
+    }
+
+    /// Uses copies of missing files found under another root folder of the download
+    /// or completed folder, e.g. data already seeding for another tracker. They are
+    /// only used once every piece they hold matches its hash, and then as borrowed
+    /// files that are never written, moved or deleted. The app is told to save the
+    /// renames.
+    async fn map_existing_files(&mut self) {
+        let (Some(torrent), Some(multi_file_info)) = (self.torrent.clone(), &self.multi_file_info)
+        else {
+            return;
+        };
+        let content_root = content_root_dir(
+            &self.root_download_path,
+            &torrent.info.name,
+            !torrent.info.files.is_empty(),
+            self.settings.create_subfolder,
+        );
+        let mut search_dirs = vec![self.root_download_path.clone()];
+        search_dirs.extend(
+            self.completed_dir
+                .clone()
+                .filter(|dir| *dir != self.root_download_path),
+        );
+        let Some(renames) = search_dirs
+            .iter()
+            .map(|dir| find_existing_files(dir, &content_root, multi_file_info))
+            .find(|renames| !renames.is_empty())
+        else {
+            return;
+        };
+
+        let mut adopted = multi_file_info.clone();
+        adopted.apply_renames(&self.root_download_path, &renames);
+        let indexes: Vec<usize> = renames.iter().map(|rename| rename.index).collect();
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+        let matched = tokio::select! {
+            biased;
+            _ = shutdown_rx.recv() => return,
+            matched = files_match_pieces(
+                &adopted,
+                &indexes,
+                torrent.info.piece_length as u64,
+                |piece_index| torrent.piece_hash(piece_index),
+                &self.hash_pool,
+            ) => matched,
+        };
+        if !matched {
+            event!(
+                Level::INFO,
+                files = renames.len(),
+                "Files found under another folder don't match the torrent. Downloading into its own folder."
+            );
+            return;
+        }
+
+        event!(
+            Level::INFO,
+            files = renames.len(),
+            "Using existing files found under another folder."
+        );
+        self.multi_file_info = Some(adopted);
+        for rename in &renames {
+            self.file_renames.retain(|r| r.index != rename.index);
+            self.file_renames.push(rename.clone());
+        }
+        let _ = self
+            .manager_event_tx
+            .try_send(ManagerEvent::ExistingFilesFound {
+                info_hash: self.info_hash.clone(),
+                renames,
+            });
+    }
+
+    /// Checks that the download folder has room for the rest of the torrent.
+    /// Returns false and pauses the torrent when it doesn't, leaving the app to
+    /// decide whether to keep it paused or drop it. A torrent the user is
+    /// `resuming` is always kept.
+    fn check_disk_space(&mut self, resuming: bool) -> bool {
+        if self.settings.on_insufficient_space == InsufficientSpaceAction::Ignore {
+            return true;
+        }
+        let Some(multi_file_info) = &self.multi_file_info else {
+            return true;
+        };
+
+        let required_bytes = remaining_allocation_bytes(multi_file_info);
+        if required_bytes == 0 {
+            return true;
+        }
+
+        let available_bytes = match available_space(&self.root_download_path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                event!(Level::WARN, path = ?self.root_download_path, error = %e, "Could not determine free disk space. Skipping check.");
+                return true;
+            }
+        };
+
+        if required_bytes <= available_bytes {
+            return true;
+        }
+
+        event!(
+            Level::WARN,
+            info_hash = %BASE32.encode(&self.info_hash),
+            required_bytes,
+            available_bytes,
+            "Not enough disk space for torrent. Pausing."
+        );
+        self.is_paused = true;
+        self.last_activity = TorrentActivity::Paused;
+        let _ = self
+            .manager_event_tx
+            .try_send(ManagerEvent::InsufficientDiskSpace {
+                info_hash: self.info_hash.clone(),
+                required_bytes,
+                available_bytes,
+                resuming,
+            });
+        false
+    }
+
+    async fn sleep_with_shutdown(
+        duration: Duration,
+        shutdown_rx: &mut broadcast::Receiver<()>,
+    ) -> Result<(), ()> {
+        tokio::select! {
+            biased; // Prioritize shutdown
+            _ = shutdown_rx.recv() => Err(()),
+            _ = tokio::time::sleep(duration) => Ok(()),
+        }
+    }
+
+    fn uploads_disabled(&self) -> bool {
+        self.upload_disabled || self.settings.upload_disabled
+    }
+
+    fn downloads_disabled(&self) -> bool {
+        self.download_disabled || self.settings.download_disabled
+    }
+
+    /// Pauses the torrent once every piece inside its download cap is done, and lets
+    /// the app know so it shows the torrent as paused.
+    fn check_download_cap(&mut self) {
+        if self.is_paused || !self.piece_manager.cap_reached() {
+            return;
+        }
+
+        event!(
+            Level::INFO,
+            info_hash = %BASE32.encode(&self.info_hash),
+            max_download_bytes = ?self.max_download_bytes,
+            "Download cap reached. Pausing."
+        );
+        self.last_activity = TorrentActivity::Paused;
+        self.is_paused = true;
+        self.disconnect_all_peers();
+        self.announce_stopped_to_trackers().detach_all();
+        let _ = self
+            .manager_event_tx
+            .try_send(ManagerEvent::DownloadCapReached {
+                info_hash: self.info_hash.clone(),
+            });
+    }
+
+    /// Becomes a partial seed (BEP 21) once every wanted piece is done while the
+    /// torrent isn't: the files a magnet link selected, or everything inside a
+    /// download cap after the capped torrent is resumed. Stops being one when the cap
+    /// is raised or lifted.
+    fn update_partial_seed(&mut self) {
+        let partial_seed = self.piece_manager.wanted_pieces_done();
+        if partial_seed == self.partial_seed {
+            return;
+        }
+        self.partial_seed = partial_seed;
+        for peer in self.peers_map.values() {
+            let _ = peer
+                .peer_tx
+                .try_send(TorrentCommand::UploadOnly(partial_seed));
+        }
+    }
+
+    fn recalculate_chokes(&mut self) {
+        // Tit-for-tat, run every `choke_interval`:
+        // 1. Rank interested peers by the bytes they sent us since the last round.
+        //    Once seeding there's nothing to trade, so `sort_for_seeding` ranks them
+        //    by how fast they drain our upload and rotates long-held slots instead.
+        // 2. Unchoke the best `upload_slots`.
+        // 3. One more peer holds the optimistic unchoke for
+        //    `optimistic_unchoke_interval`, picked at random from the rest with new
+        //    peers favoured, so peers with nothing to trade yet get a chance.
+        // 4. Choke everyone else.
+        // With uploads disabled there are no slots, so everyone ends up choked.
+        let upload_slots = if self.uploads_disabled() {
+            0
+        } else {
+            self.settings.upload_slots
+        };
+        let mut interested_peers: Vec<&PeerState> = self
+            .peers_map
+            .values()
+            .filter(|p| p.peer_is_interested_in_us)
+            .collect();
+        if self.torrent_status == TorrentStatus::Done {
+            sort_for_seeding(&mut interested_peers);
+        } else {
+            interested_peers.sort_by_key(|p| std::cmp::Reverse(p.bytes_downloaded_from_peer));
+        }
+
+        let mut unchoke_candidates: HashSet<String> = interested_peers
+            .iter()
+            .take(upload_slots)
+            .map(|p| p.ip_port.clone())
+            .collect();
+
+        // A new optimistic peer is picked when the interval is up, or early if the
+        // current one left, lost interest or earned a regular slot.
+        let optimistic_peer_holds = self.optimistic_unchoke_peer.as_ref().is_some_and(|id| {
+            !unchoke_candidates.contains(id) && interested_peers.iter().any(|p| &p.ip_port == id)
+        });
+        if upload_slots == 0 {
+            self.optimistic_unchoke_peer = None;
+        } else if !optimistic_peer_holds
+            || self.optimistic_unchoke_timer.elapsed()
+                >= self.settings.optimistic_unchoke_interval()
+        {
+            let optimistic_candidates: Vec<&PeerState> = interested_peers
+                .iter()
+                .filter(|p| !unchoke_candidates.contains(&p.ip_port))
+                .copied()
+                .collect();
+            self.optimistic_unchoke_peer = optimistic_candidates
+                .choose_weighted(&mut rand::rng(), |p| {
+                    if p.connected_at.elapsed() < NEW_PEER_OPTIMISTIC_WINDOW {
+                        3
+                    } else {
+                        1
+                    }
+                })
+                .ok()
+                .map(|p| p.ip_port.clone());
+            self.optimistic_unchoke_timer = Instant::now();
+        }
+        if let Some(optimistic_peer) = &self.optimistic_unchoke_peer {
+            unchoke_candidates.insert(optimistic_peer.clone());
+        }
+
+        for (peer_id, peer) in self.peers_map.iter_mut() {
+            if unchoke_candidates.contains(peer_id) {
+                if peer.am_choking == ChokeStatus::Choke {
+                    peer.am_choking = ChokeStatus::Unchoke;
+                    peer.unchoked_since = Some(Instant::now());
+                    let peer_tx = peer.peer_tx.clone();
+                    let _ = peer_tx.try_send(TorrentCommand::PeerUnchoke);
+                }
+            } else if peer.am_choking == ChokeStatus::Unchoke {
+                peer.am_choking = ChokeStatus::Choke;
+                peer.unchoked_since = None;
+                let peer_tx = peer.peer_tx.clone();
+                let _ = peer_tx.try_send(TorrentCommand::PeerChoke);
+            }
+        }
+
+        for peer in self.peers_map.values_mut() {
+            peer.bytes_downloaded_from_peer = 0;
+            peer.bytes_uploaded_to_peer = 0;
+        }
+    }
+
+    /// Sends HAVE for a piece we just finished to every peer that doesn't already
+    /// advertise it; a peer that has the piece gains nothing from hearing about it.
+    fn announce_piece(&self, piece_index: u32) {
+        for peer in self.peers_map.values() {
+            if !peer.bitfield.has(piece_index as usize) {
+                let _ = peer
+                    .peer_tx
+                    .try_send(TorrentCommand::PieceAcquired(piece_index));
+            }
+        }
+    }
+
+    /// Generates a bitfield message that represents the pieces the client currently has.
+    /// This is sent to peers to inform them of what pieces they can request.
+    fn generate_bitfield(&mut self) -> Bitfield {
+        self.piece_manager
+            .bitfield
+            .iter()
+            .map(|status| *status == PieceStatus::Done)
+            .collect()
+    }
+
+    fn all_pieces_done(&self) -> bool {
+        self.piece_manager
+            .bitfield
+            .iter()
+            .all(|status| *status == PieceStatus::Done)
+    }
+
+    /// Checks if all pieces have been downloaded. If so, it either starts the final
+    /// verification pass (when `verify_before_seeding` is set) or completes the torrent.
+    fn check_for_completion(&mut self) {
+        if self.torrent_status == TorrentStatus::Done
+            || self.final_verification_in_progress
+            || !self.all_pieces_done()
+        {
+            return;
+        }
+
+        if self.settings.verify_before_seeding {
+            self.start_final_verification();
+        } else {
+            self.complete_download();
+        }
+    }
+
+    fn announce_client(&self) -> AnnounceClient {
+        AnnounceClient {
+            peer_id: self.settings.client_id.clone(),
+            port: self.settings.client_port,
+            key: self.announce_key,
+        }
+    }
+
+    /// Bytes still to download, as reported to trackers.
+    fn bytes_left(&self) -> u64 {
+        let (Some(torrent), Some(multi_file_info)) = (&self.torrent, &self.multi_file_info) else {
+            // The size isn't known until the metadata arrives; anything above
+            // zero keeps trackers from counting us as a seed.
+            return 1;
+        };
+        let bytes_completed = (torrent.info.piece_length as u64).saturating_mul(
+            self.piece_manager
+                .bitfield
+                .iter()
+                .filter(|&s| *s == PieceStatus::Done)
+                .count() as u64,
+        );
+        multi_file_info.total_size.saturating_sub(bytes_completed)
+    }
+
+    /// Tells every tracker we've sent 'started' to that we're leaving the swarm.
+    /// They get 'started' again on the next announce.
+    fn announce_stopped_to_trackers(&mut self) -> JoinSet<()> {
+        let bytes_left = self.bytes_left() as usize;
+        let announce_client = self.announce_client();
+        let mut announce_set = JoinSet::new();
+        for (url, tracker_state) in self.trackers.iter_mut().filter(|(_, t)| t.started) {
+            tracker_state.started = false;
+            let url_clone = url.clone();
+            let info_hash_clone = self.info_hash.clone();
+            let announce_client_clone = announce_client.clone();
+            let session_total_uploaded_clone = self.session_total_uploaded as usize;
+            let session_total_downloaded_clone = self.session_total_downloaded as usize;
+            let tracker_id_clone = tracker_state.tracker_id.clone();
+            announce_set.spawn(async move {
+                announce_stopped(
+                    url_clone,
+                    &info_hash_clone,
+                    announce_client_clone,
+                    session_total_uploaded_clone,
+                    session_total_downloaded_clone,
+                    bytes_left,
+                    tracker_id_clone,
+                )
+                .await;
+            });
+        }
+        announce_set
+    }
+
+    /// Sends 'stopped' before the manager exits, giving trackers a few seconds
+    /// to answer.
+    async fn wait_for_stopped_announces(&mut self) {
+        let mut announce_set = self.announce_stopped_to_trackers();
+        event!(
+            Level::DEBUG,
+            "Sending 'stopped' to {} trackers...",
+            announce_set.len()
+        );
+        if (tokio::time::timeout(Duration::from_secs(4), async {
+            while (announce_set.join_next().await).is_some() {}
+        })
+        .await)
+            .is_err()
+        {
+            event!(
+                Level::WARN,
+                "Tracker announce tasks timed out. Aborting remaining."
+            );
+            announce_set.abort_all();
+        } else {
+            event!(Level::DEBUG, "Tracker announces finished.");
+        }
+    }
+
+    /// Transitions the torrent to the 'Done' state, sends a 'completed' announcement
+    /// to trackers, and updates peer states to 'not interested'.
+    fn complete_download(&mut self) {
+        let _torrent = self.torrent.clone().expect("Torrent metadata not ready.");
+
+        if self.torrent_status != TorrentStatus::Done && self.all_pieces_done() {
+            self.torrent_status = TorrentStatus::Done;
+
+            // 'completed' goes out once, and only if this session downloaded
+            // something; a torrent that was already whole on disk just starts
+            // reporting left=0.
+            let announce_completed_event = self.session_total_downloaded > 0;
+            let now = Instant::now();
+            let announce_client = self.announce_client();
+            for (url, tracker_state) in self.trackers.iter_mut().filter(|(_, t)| t.started) {
+                if !announce_completed_event {
+                    tracker_state.schedule_announce(now);
+                    continue;
+                }
+
+                let url_clone = url.clone();
+                let info_hash_clone = self.info_hash.clone();
+                let announce_client_clone = announce_client.clone();
+                let session_total_uploaded_clone = self.session_total_uploaded as usize;
+                let session_total_downloaded_clone = self.session_total_downloaded as usize;
+                let tracker_id_clone = tracker_state.tracker_id.clone();
+                tokio::spawn(async move {
+                    let _ = announce_completed(
+                        url_clone,
+                        &info_hash_clone,
+                        announce_client_clone,
+                        session_total_uploaded_clone,
+                        session_total_downloaded_clone,
+                        tracker_id_clone,
+                    )
+                    .await;
+                });
+
+                // 'completed' stands in for the next regular announce.
+                tracker_state.last_announce_time = Some(now);
+                let seeding_interval = tracker_state
+                    .seeding_interval
+                    .unwrap_or(Duration::from_secs(FALLBACK_ANNOUNCE_INTERVAL));
+                tracker_state.schedule_announce(now + seeding_interval);
+            }
+
+            for peer in self.peers_map.values_mut() {
+                peer.am_interested = false;
+                let peer_tx_cloned = peer.peer_tx.clone();
+                let _ = peer_tx_cloned.try_send(TorrentCommand::NotInterested);
+            }
+
+            self.start_move_to_completed_dir();
+        }
+    }
+
+    /// Moves the finished download into `completed_dir` in a background task.
+    /// The outcome comes back as `TorrentCommand::FilesMoved`.
+    fn start_move_to_completed_dir(&mut self) {
+        let Some(completed_dir) = self.completed_dir.clone() else {
+            return;
+        };
+        if self.files_moving || completed_dir == self.root_download_path {
+            return;
+        }
+
+        event!(Level::INFO, to = ?completed_dir, "Moving the finished download.");
+        self.start_move(completed_dir);
+    }
+
+    /// Moves the files from `root_download_path` to `new_root` in a background task.
+    /// The outcome comes back as `TorrentCommand::FilesMoved`.
+    fn start_move(&mut self, new_root: PathBuf) {
+        let Some(multi_file_info) = self.multi_file_info.clone() else {
+            return;
+        };
+        self.files_moving = true;
+        let old_root = self.root_download_path.clone();
+        let torrent_manager_tx = self.torrent_manager_tx.clone();
+        tokio::spawn(async move {
+            let result = move_files(&multi_file_info, &old_root, &new_root)
+                .await
+                .map(|_| new_root)
+                .map_err(|e| e.to_string());
+            let _ = torrent_manager_tx
+                .send(TorrentCommand::FilesMoved(result))
+                .await;
+        });
+    }
+
+    /// Points the file layout at `new_root` in place of `root_download_path`. The
+    /// torrent stays there, so any pending move to the completed folder is dropped.
+    fn relocate(&mut self, new_root: PathBuf) {
+        if let Some(multi_file_info) = &mut self.multi_file_info {
+            for file_info in multi_file_info.files.iter_mut().filter(|f| !f.is_borrowed) {
+                if let Ok(relative) = file_info.path.strip_prefix(&self.root_download_path) {
+                    file_info.path = new_root.join(relative);
+                }
+            }
+        }
+        event!(Level::INFO, from = ?self.root_download_path, to = ?new_root, "Torrent relocated.");
+        self.root_download_path = new_root;
+        self.completed_dir = None;
+    }
+
+    /// Forgets which pieces we have and hashes the files on disk again, rejoining the
+    /// swarm afterwards unless paused.
+    async fn recheck_local_files(&mut self) {
+        // Peers were told about pieces we may turn out not to have.
+        self.disconnect_all_peers();
+        self.torrent_validation_status = false;
+        self.torrent_status = TorrentStatus::Standard;
+        self.piece_manager.forget_pieces();
+        if let Err(StorageError::Io(e)) = self.validate_local_file().await {
+            event!(Level::ERROR, error = %e, "Failed to recheck local files.");
+        }
+
+        if !self.is_paused {
+            self.rejoin_swarm().await;
+        }
+    }
+
+    /// Re-reads every piece from disk and checks it against its hash in a background task,
+    /// so a completed download is only reported once its data is known to be good.
+    /// The outcome comes back as `TorrentCommand::FinalVerificationComplete`.
+    fn start_final_verification(&mut self) {
+        let (Some(torrent), Some(multi_file_info)) =
+            (self.torrent.clone(), self.multi_file_info.clone())
+        else {
+            return;
+        };
+
+        event!(
+            Level::INFO,
+            "All pieces downloaded. Verifying before seeding."
+        );
+        self.final_verification_in_progress = true;
+
+        let piece_length = torrent.info.piece_length as u64;
+        let pieces: Vec<(u32, u64, usize)> = (0..self.piece_manager.bitfield.len() as u32)
+            .map(|piece_index| {
+                (
+                    piece_index,
+                    piece_index as u64 * piece_length,
+                    self.get_piece_size(piece_index),
+                )
+            })
+            .collect();
+        let piece_hashes: Vec<_> = pieces
+            .iter()
+            .map(|&(piece_index, _, _)| torrent.piece_hash(piece_index as usize))
+            .collect();
+        let verify_piece_length = self.settings.verify_piece_length;
+        let resource_manager = self.resource_manager.clone();
+        let hash_pool = self.hash_pool.clone();
+        let torrent_manager_tx = self.torrent_manager_tx.clone();
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+
+        tokio::spawn(async move {
+            let mut bad_pieces = Vec::new();
+
+            for ((piece_index, start_offset, len_this_piece), expected_hash) in
+                pieces.into_iter().zip(piece_hashes)
+            {
+                let mut attempt = 0;
+                let piece_data = loop {
+                    let disk_permit_result = tokio::select! {
+                        biased;
+                        _ = shutdown_rx.recv() => return,
+                        acquire_result = resource_manager.acquire_disk_read() => acquire_result
+                    };
+
+                    match disk_permit_result {
+                        Ok(_permit) => {
+                            break read_data_from_disk(
+                                &multi_file_info,
+                                start_offset,
+                                len_this_piece,
+                            )
+                            .await
+                            .ok();
+                        }
+                        Err(ResourceManagerError::QueueFull)
+                            if attempt < MAX_VALIDATION_ATTEMPTS =>
+                        {
+                            let backoff_duration_ms =
+                                BASE_BACKOFF_MS.saturating_mul(2u64.pow(attempt));
+                            attempt += 1;
+                            if Self::sleep_with_shutdown(
+                                Duration::from_millis(backoff_duration_ms),
+                                &mut shutdown_rx,
+                            )
+                            .await
+                            .is_err()
+                            {
+                                return;
+                            }
+                        }
+                        Err(ResourceManagerError::QueueFull) => break None,
+                        Err(ResourceManagerError::ManagerShutdown) => return,
+                    }
+                };
+
+                // A piece that can't be read back is treated like a corrupt one.
+                let is_valid = match piece_data {
+                    Some(data) => {
+                        let expected_len = verify_piece_length.then_some(len_this_piece);
+                        match hash_pool.verify(data, expected_hash, expected_len).await {
+                            Ok(_) => true,
+                            Err(e) => {
+                                event!(Level::WARN, piece = piece_index, error = %e, "Piece failed verification before seeding.");
+                                false
+                            }
+                        }
+                    }
+                    None => false,
+                };
+
+                if !is_valid {
+                    bad_pieces.push(piece_index);
+                }
+            }
+
+            let _ = torrent_manager_tx
+                .send(TorrentCommand::FinalVerificationComplete { bad_pieces })
+                .await;
+        });
+    }
+
+    /// Queues the pieces that failed the final verification for download again.
+    /// Peers without one of them were sent a HAVE for it, which can't be taken back,
+    /// so they're disconnected and redialed to get a bitfield without it.
+    fn redownload_unverified_pieces(&mut self, bad_pieces: &[u32]) {
+        for &piece_index in bad_pieces {
+            self.piece_manager.mark_as_needed(piece_index);
+        }
+
+        let now = Instant::now();
+        for peer in self.peers_map.values() {
+            if bad_pieces
+                .iter()
+                .all(|&piece_index| peer.bitfield.has(piece_index as usize))
+            {
+                continue;
+            }
+            let _ = peer
+                .peer_tx
+                .try_send(TorrentCommand::Disconnect(peer.ip_port.clone()));
+            if let Ok(address) = peer.ip_port.parse::<SocketAddr>() {
+                self.peer_candidates.insert(
+                    peer.ip_port.clone(),
+                    PeerCandidate {
+                        ip: address.ip().to_string(),
+                        port: address.port(),
+                        source: PeerSource::Saved,
+                        discovered_at: now,
+                    },
+                );
+            }
+        }
+
+        let peer_ids: Vec<String> = self.peers_map.keys().cloned().collect();
+        for peer_id in peer_ids {
+            self.find_and_assign_work(peer_id);
+        }
+    }
+
+    /// Identifies the rarest available piece that a peer has and assigns it to them for download.
+    /// This is the core of the piece selection strategy.
+    fn find_and_assign_work(&mut self, peer_id: String) {
+        if self.downloads_disabled() {
+            return;
+        }
+        if self.piece_manager.need_queue.is_empty() && self.piece_manager.pending_queue.is_empty() {
+            return;
+        }
+
+        let torrent = self.torrent.clone().expect("Torrent metadata not ready.");
+        let multi_file_info = self.multi_file_info.as_ref().expect("File info not ready.");
+
+        if let Some(peer) = self.peers_map.get_mut(&peer_id) {
+            if peer.snubbed && !peer.pending_requests.is_empty() {
+                return;
+            }
+            let choked = peer.bitfield.is_empty() || peer.peer_choking == ChokeStatus::Choke;
+            if choked
+                && peer.peer_choking == ChokeStatus::Choke
+                && !peer.am_interested
+                && self
+                    .piece_manager
+                    .need_queue
+                    .iter()
+                    .any(|&p| peer.bitfield.has(p as usize))
+            {
+                peer.am_interested = true;
+                peer.peer_choking = ChokeStatus::Pending;
+                let peer_tx_cloned = peer.peer_tx.clone();
+                let _ = peer_tx_cloned.try_send(TorrentCommand::ClientInterested);
+            }
+
+            let piece_to_assign = if choked {
+                // Only the peer's allowed fast pieces can be fetched while it chokes us.
+                self.piece_manager.choose_piece_from(
+                    &peer.allowed_fast,
+                    &peer.bitfield,
+                    &peer.pending_requests,
+                )
+            } else {
+                self.piece_manager
+                    .choose_piece_from(
+                        &peer.suggested_pieces,
+                        &peer.bitfield,
+                        &peer.pending_requests,
+                    )
+                    .or_else(|| {
+                        self.piece_manager.choose_piece_for_peer(
+                            &peer.bitfield,
+                            &peer.pending_requests,
+                            &self.torrent_status,
+                        )
+                    })
+            };
+
+            if let Some(piece_index) = piece_to_assign {
                 event!(Level::DEBUG, peer = %peer_id, piece = piece_index, "Assigning rarest piece.");
 
+                if peer.pending_requests.is_empty() {
+                    peer.awaiting_block_since = Instant::now();
+                }
                 peer.pending_requests.insert(piece_index);
                 self.piece_manager
                     .mark_as_pending(piece_index, peer_id.clone());
@@ -669,21 +2524,147 @@ impl TorrentManager {
                     piece_index,
                     torrent.info.piece_length,
                     torrent_size,
+                    self.piece_manager.received_block_offsets(piece_index),
                 ));
             }
         }
     }
 
+    fn peer_timeouts(&self) -> PeerTimeouts {
+        PeerTimeouts {
+            handshake: self.settings.peer_handshake_timeout(),
+            keepalive: self.settings.peer_keepalive_interval(),
+            inactivity: self.settings.peer_inactivity_timeout(),
+        }
+    }
+
+    /// Adds a batch of peers heard of from `source` to the candidate pool, then dials
+    /// the best candidates there are free slots for.
+    async fn connect_to_peers(&mut self, peers: Vec<(String, u16)>, source: PeerSource) {
+        let now = Instant::now();
+        for (ip, port) in peers {
+            let address = peer_ip_port(&ip, port);
+            if self.peers_map.contains_key(&address) {
+                continue;
+            }
+            self.peer_candidates
+                .entry(address)
+                .and_modify(|candidate| {
+                    candidate.source = candidate.source.min(source);
+                    candidate.discovered_at = now;
+                })
+                .or_insert(PeerCandidate {
+                    ip,
+                    port,
+                    source,
+                    discovered_at: now,
+                });
+        }
+
+        if self.peer_candidates.len() > MAX_PEER_CANDIDATES {
+            let mut ranked: Vec<&PeerCandidate> = self.peer_candidates.values().collect();
+            rank_peer_candidates(&mut ranked, self.our_address());
+            let dropped: Vec<String> = ranked[MAX_PEER_CANDIDATES..]
+                .iter()
+                .map(|candidate| peer_ip_port(&candidate.ip, candidate.port))
+                .collect();
+            for address in dropped {
+                self.peer_candidates.remove(&address);
+            }
+        }
+
+        self.dial_peer_candidates().await;
+    }
+
+    /// Dials the best candidates that aren't backing off after a failed attempt,
+    /// keeping at most `MAX_CONNECTING_PEERS` connections handshaking at once.
+    async fn dial_peer_candidates(&mut self) {
+        if self.is_paused {
+            return;
+        }
+        let connecting = self.peers_map.values().filter(|p| p.connecting).count();
+        let slots = MAX_CONNECTING_PEERS.saturating_sub(connecting);
+        if slots == 0 || self.peer_candidates.is_empty() {
+            return;
+        }
+
+        let now = Instant::now();
+        self.peer_candidates.retain(|_, candidate| {
+            now.duration_since(candidate.discovered_at) < PEER_CANDIDATE_TTL
+        });
+        let mut ready: Vec<&PeerCandidate> = self
+            .peer_candidates
+            .iter()
+            .filter(|(address, _)| {
+                self.timed_out_peers
+                    .get(*address)
+                    .is_none_or(|(_, next_attempt_time)| now >= *next_attempt_time)
+            })
+            .map(|(_, candidate)| candidate)
+            .collect();
+        rank_peer_candidates(&mut ready, self.our_address());
+        let chosen: Vec<PeerCandidate> = ready.into_iter().take(slots).cloned().collect();
+
+        for candidate in chosen {
+            self.peer_candidates
+                .remove(&peer_ip_port(&candidate.ip, candidate.port));
+            self.connect_to_peer(candidate.ip, candidate.port, candidate.source)
+                .await;
+        }
+    }
+
+    /// Our listening address as peers see it, once one has told us.
+    fn our_address(&self) -> Option<SocketAddr> {
+        self.external_ip
+            .map(|external_ip| SocketAddr::new(external_ip, self.settings.client_port))
+    }
+
+    /// Backs off from dialing a peer again after it couldn't be reached or didn't
+    /// finish the handshake, doubling the wait with each failure in a row.
+    fn record_dial_failure(&mut self, peer_ip_port: &str) {
+        let now = Instant::now();
+        let (failure_count, _) = self
+            .timed_out_peers
+            .get(peer_ip_port)
+            .cloned()
+            .unwrap_or((0, now));
+        let new_failure_count = (failure_count + 1).min(10);
+        let backoff_duration_secs =
+            (BASE_COOLDOWN_SECS * 2u64.pow(new_failure_count - 1)).min(MAX_COOLDOWN_SECS);
+        let next_attempt_time = now + Duration::from_secs(backoff_duration_secs);
+
+        event!(Level::DEBUG,
+            peer = %peer_ip_port,
+            failures = new_failure_count,
+            cooldown_secs = backoff_duration_secs,
+            "Peer unreachable. Applying exponential backoff."
+        );
+        self.timed_out_peers.insert(
+            peer_ip_port.to_string(),
+            (new_failure_count, next_attempt_time),
+        );
+    }
+
     /// Initiates a connection to a new peer. It handles peer session creation,
     /// exponential backoff for failed connections, and acquiring connection permits.
-    pub async fn connect_to_peer(&mut self, peer_ip: String, peer_port: u16) {
+    pub async fn connect_to_peer(&mut self, peer_ip: String, peer_port: u16, source: PeerSource) {
         let _ = self
             .manager_event_tx
             .try_send(ManagerEvent::PeerDiscovered {
                 info_hash: self.info_hash.clone(),
             });
 
-        let peer_ip_port = format!("{}:{}", peer_ip, peer_port);
+        let peer_ip_port = peer_ip_port(&peer_ip, peer_port);
+
+        if self.ban_list.is_peer_banned(&peer_ip_port) {
+            event!(Level::DEBUG, peer = %peer_ip_port, "Skipping banned peer.");
+            return;
+        }
+
+        if self.is_rejected_source(&peer_ip_port) {
+            event!(Level::DEBUG, peer = %peer_ip_port, "Skipping peer that sent bad data.");
+            return;
+        }
 
         if let Some((failure_count, next_attempt_time)) = self.timed_out_peers.get(&peer_ip_port) {
             if Instant::now() < *next_attempt_time {
@@ -705,8 +2686,9 @@ impl TorrentManager {
         let resource_manager_clone = self.resource_manager.clone();
         let global_dl_bucket_clone = self.global_dl_bucket.clone();
         let global_ul_bucket_clone = self.global_ul_bucket.clone();
+        let bandwidth_priority_rx = self.bandwidth_priority_tx.subscribe();
         let info_hash_clone = self.info_hash.clone();
-        let torrent_metadata_length_clone = self.torrent_metadata_length;
+        let torrent_metadata_clone = self.torrent_metadata.clone();
         let peer_ip_port_clone = peer_ip_port.clone();
 
         let mut shutdown_rx_permit = self.shutdown_tx.subscribe();
@@ -714,10 +2696,11 @@ impl TorrentManager {
         let shutdown_tx = self.shutdown_tx.clone();
 
         let (peer_session_tx, peer_session_rx) = mpsc::channel::<TorrentCommand>(10);
-        self.peers_map.insert(
-            peer_ip_port.clone(),
-            PeerState::new(peer_ip_port.clone(), peer_session_tx),
-        );
+        let mut peer_state = PeerState::new(peer_ip_port.clone(), peer_session_tx);
+        peer_state.outgoing = true;
+        peer_state.source = source;
+        peer_state.connecting = true;
+        self.peers_map.insert(peer_ip_port.clone(), peer_state);
 
         let bitfield = match self.torrent {
             None => None,
@@ -725,6 +2708,13 @@ impl TorrentManager {
         };
 
         let client_id_clone = self.settings.client_id.clone();
+        let lazy_bitfield = self.settings.lazy_bitfield;
+        let upload_only = self.partial_seed;
+        let pex_allowed = self.pex_allowed();
+        let timeouts = self.peer_timeouts();
+        let peer_transport = self.settings.peer_transport;
+        let listen_interface = self.settings.listen_interface.clone();
+        let utp_socket = self.utp_socket.clone();
         tokio::spawn(async move {
             let session_permit = tokio::select! {
                 permit_result = resource_manager_clone.acquire_peer_connection() => {
@@ -743,17 +2733,30 @@ impl TorrentManager {
             };
 
             if let Some(session_permit) = session_permit {
-                let connection_result = timeout(
-                    Duration::from_secs(2),
-                    TcpStream::connect(&peer_ip_port_clone),
-                )
-                .await;
+                // Half-open connections are capped across all torrents, so a swarm
+                // with thousands of candidates doesn't flood the router with SYNs.
+                let connection_result = tokio::select! {
+                    attempt_permit = resource_manager_clone.acquire_connection_attempt() => match attempt_permit {
+                        // Held until the connection is made or given up on
+                        Ok(_attempt_permit) => {
+                            connect_peer_stream(
+                                &peer_ip_port_clone,
+                                peer_transport,
+                                utp_socket.as_ref(),
+                                listen_interface.as_deref(),
+                            )
+                            .await
+                        }
+                        Err(_) => None,
+                    },
+                    _ = shutdown_rx_permit.recv() => None,
+                };
 
-                if let Ok(Ok(stream)) = connection_result {
+                if let Some(stream) = connection_result {
                     let _held_session_permit = session_permit;
                     let session = PeerSession::new(PeerSessionParameters {
                         info_hash: info_hash_clone,
-                        torrent_metadata_length: torrent_metadata_length_clone,
+                        torrent_metadata: torrent_metadata_clone,
                         connection_type: ConnectionType::Outgoing,
                         torrent_manager_rx: peer_session_rx,
                         torrent_manager_tx: torrent_manager_tx_clone.clone(),
@@ -761,7 +2764,12 @@ impl TorrentManager {
                         client_id: client_id_clone.into(),
                         global_dl_bucket: global_dl_bucket_clone,
                         global_ul_bucket: global_ul_bucket_clone,
+                        bandwidth_priority: bandwidth_priority_rx,
                         shutdown_tx,
+                        lazy_bitfield,
+                        upload_only,
+                        pex_allowed,
+                        timeouts,
                     });
 
                     tokio::select! {
@@ -806,15 +2814,14 @@ impl TorrentManager {
 
         let mut peers = HashSet::new();
 
-        for url in self.trackers.keys() {
+        // Walk the tiers until one tracker answers.
+        let urls: Vec<String> = self.tracker_tiers.urls().cloned().collect();
+        for url in urls {
             let info_hash_clone = self.info_hash.clone();
-            let client_port_clone = self.settings.client_port;
-            let client_id_clone = self.settings.client_id.clone();
             let tracker_response = announce_started(
                 url.to_string(),
                 &info_hash_clone,
-                client_id_clone,
-                client_port_clone,
+                self.announce_client(),
                 torrent_size_left,
             )
             .await;
@@ -824,16 +2831,24 @@ impl TorrentManager {
                     for peer in value.peers {
                         peers.insert((peer.ip, peer.port));
                     }
+                    if let Some(tracker_state) = self.trackers.get_mut(&url) {
+                        tracker_state.started = true;
+                        tracker_state.record_success();
+                    }
+                    self.tracker_tiers.record_success(&url);
+                    break;
                 }
                 Err(e) => {
                     event!(Level::DEBUG, ?e);
+                    if let Some(tracker_state) = self.trackers.get_mut(&url) {
+                        tracker_state.record_failure(e.to_string());
+                    }
                 }
             }
         }
 
-        for peer in peers {
-            self.connect_to_peer(peer.0, peer.1).await;
-        }
+        self.connect_to_peers(peers.into_iter().collect(), PeerSource::Tracker)
+            .await;
     }
 
     /// Verifies the integrity of the torrent's data on disk by checking each piece against the
@@ -846,6 +2861,26 @@ impl TorrentManager {
 
         let manager_event_tx_clone = self.manager_event_tx.clone();
 
+        // Data trusted without hashing, e.g. added with skip_check, still has to be there.
+        let skipped = |index: usize| {
+            self.file_priorities
+                .as_ref()
+                .and_then(|priorities| priorities.get(index))
+                == Some(&FilePriority::Skip)
+        };
+        let files_missing = match &self.multi_file_info {
+            Some(info) if self.torrent_validation_status => !files_present(info, skipped).await,
+            _ => false,
+        };
+        if files_missing {
+            event!(
+                Level::WARN,
+                "Files of a torrent trusted as complete are missing or the wrong size. Checking them instead."
+            );
+            self.torrent_validation_status = false;
+            self.piece_manager.forget_pieces();
+        }
+
         if self.torrent_validation_status {
             for piece_index in 0..self.piece_manager.bitfield.len() {
                 self.piece_manager.mark_as_complete(piece_index as u32);
@@ -856,6 +2891,25 @@ impl TorrentManager {
                 None => return Ok(()),
             };
 
+            // Only a few torrents hash their files at once; the rest wait their turn here.
+            let _validation_permit = match self.validation_permits.clone().try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_) => {
+                    self.send_validation_metrics(0, true);
+                    tokio::select! {
+                        biased;
+                        _ = shutdown_rx.recv() => {
+                            event!(Level::INFO, "Shutdown signal received while queued for validation.");
+                            return Ok(());
+                        }
+                        permit = self.validation_permits.clone().acquire_owned() => match permit {
+                            Ok(permit) => permit,
+                            Err(_) => return Ok(()),
+                        }
+                    }
+                }
+            };
+
             tokio::select! {
                 biased; // Prioritize shutdown
                 _ = shutdown_rx.recv() => {
@@ -867,6 +2921,7 @@ impl TorrentManager {
 
             let piece_length_u64 = torrent.info.piece_length as u64;
             let num_pieces = self.piece_manager.bitfield.len();
+            let mut hash_tasks = JoinSet::new();
 
             for piece_index in 0..num_pieces {
                 let start_offset = (piece_index as u64) * piece_length_u64;
@@ -876,13 +2931,7 @@ impl TorrentManager {
                     continue;
                 }
 
-                let start_hash_index = piece_index * HASH_LENGTH;
-                let end_hash_index = start_hash_index + HASH_LENGTH;
-                let expected_hash = torrent
-                    .info
-                    .pieces
-                    .get(start_hash_index..end_hash_index)
-                    .map(|s| s.to_vec());
+                let expected_hash = torrent.piece_hash(piece_index);
 
                 let mut attempt = 0;
 
@@ -963,68 +3012,110 @@ impl TorrentManager {
                     }
                 };
 
-                let mut validation_task = tokio::task::spawn_blocking(move || {
-                    if let Some(expected) = expected_hash {
-                        sha1::Sha1::digest(&piece_data).as_slice() == expected.as_slice()
-                    } else {
-                        false
+                // Hash in the background so the next piece can be read while this one is checked.
+                let hash_pool = self.hash_pool.clone();
+                let expected_len = self.settings.verify_piece_length.then_some(len_this_piece);
+                hash_tasks.spawn(async move {
+                    let result = hash_pool.verify(piece_data, expected_hash, expected_len).await;
+                    // Pieces we don't have yet fail the hash check; only a wrong length is unusual.
+                    if let Err(e @ PieceVerifyError::LengthMismatch { .. }) = &result {
+                        event!(Level::WARN, piece = piece_index, error = %e, "Piece read back with the wrong length during validation.");
                     }
+                    (piece_index, result.is_ok())
                 });
 
-                let validation_result = tokio::select! {
-                    biased;
-                    _ = shutdown_rx.recv() => {
-                        event!(Level::INFO, "Shutdown signal received during hash validation. Aborting validation.");
-                        validation_task.abort();
+                while hash_tasks.len() >= self.hash_pool.threads() {
+                    if !self
+                        .record_validation_hash(&mut hash_tasks, &mut shutdown_rx)
+                        .await
+                    {
                         return Ok(());
                     }
-                    join_handle_result = &mut validation_task => join_handle_result
-                };
+                }
 
-                if let Ok(is_valid) = validation_result {
-                    if is_valid {
-                        self.piece_manager.mark_as_complete(piece_index as u32);
-                    }
-                } else {
-                    event!(
-                        Level::WARN,
-                        "Hash validation task failed to complete. Aborting validation."
-                    );
+                if piece_index % 20 == 0 {
+                    self.send_validation_metrics((piece_index + 1) as u32, false);
+                }
+            }
+
+            while !hash_tasks.is_empty() {
+                if !self
+                    .record_validation_hash(&mut hash_tasks, &mut shutdown_rx)
+                    .await
+                {
                     return Ok(());
                 }
+            }
+        }
 
-                if piece_index % 20 == 0 {
-                    if let Some(ref torrent) = self.torrent {
-                        let metrics_tx_clone = self.metrics_tx.clone();
-                        let info_hash_clone = self.info_hash.clone();
-                        let torrent_name_clone = torrent.info.name.clone();
-                        let number_of_pieces_total = (torrent.info.pieces.len() / 20) as u32;
-                        let number_of_pieces_completed = (piece_index + 1) as u32;
-
-                        let torrent_state = TorrentState {
-                            info_hash: info_hash_clone,
-                            torrent_name: torrent_name_clone,
-                            number_of_pieces_total,
-                            number_of_pieces_completed,
-                            activity_message: "Validating local files...".to_string(),
-                            ..Default::default()
-                        };
+        self.restore_partial_pieces().await;
 
-                        if let Err(e) = metrics_tx_clone.send(torrent_state) {
-                            tracing::event!(
-                                Level::ERROR,
-                                "Failed to send validation metrics to TUI: {}",
-                                e
-                            );
-                        }
-                    }
-                }
+        // Every piece was just hashed (or trusted from a previous run), so there is
+        // no need for a second verification pass here.
+        self.complete_download();
+
+        Ok(())
+    }
+
+    fn send_validation_metrics(&self, number_of_pieces_completed: u32, is_queued: bool) {
+        if let Some(ref torrent) = self.torrent {
+            let activity_message = if is_queued {
+                "Queued for verification..."
+            } else {
+                "Validating local files..."
+            };
+            let torrent_state = TorrentState {
+                info_hash: self.info_hash.clone(),
+                torrent_name: torrent.info.name.clone(),
+                number_of_pieces_total: torrent.piece_count() as u32,
+                number_of_pieces_completed,
+                activity_message: activity_message.to_string(),
+                is_queued_for_validation: is_queued,
+                ..Default::default()
+            };
+
+            if let Err(e) = self.metrics_tx.send(torrent_state) {
+                tracing::event!(
+                    Level::ERROR,
+                    "Failed to send validation metrics to TUI: {}",
+                    e
+                );
             }
         }
+    }
 
-        self.check_for_completion();
+    /// Waits for the next validation hash to finish and marks its piece complete if it
+    /// matched. Returns `false` if validation should stop.
+    async fn record_validation_hash(
+        &mut self,
+        hash_tasks: &mut JoinSet<(usize, bool)>,
+        shutdown_rx: &mut broadcast::Receiver<()>,
+    ) -> bool {
+        let join_result = tokio::select! {
+            biased;
+            _ = shutdown_rx.recv() => {
+                event!(Level::INFO, "Shutdown signal received during hash validation. Aborting validation.");
+                return false;
+            }
+            join_result = hash_tasks.join_next() => join_result
+        };
 
-        Ok(())
+        match join_result {
+            Some(Ok((piece_index, is_valid))) => {
+                if is_valid {
+                    self.piece_manager.mark_as_complete(piece_index as u32);
+                }
+                true
+            }
+            Some(Err(_)) => {
+                event!(
+                    Level::WARN,
+                    "Hash validation task failed to complete. Aborting validation."
+                );
+                false
+            }
+            None => true,
+        }
     }
 
     /// Calculates the size of a specific piece. Most pieces have a fixed size, but the last
@@ -1033,18 +3124,45 @@ impl TorrentManager {
         let torrent = self.torrent.clone().expect("Torrent metadata not ready.");
         let multi_file_info = self.multi_file_info.as_ref().expect("File info not ready.");
 
-        let total_length_u64 = multi_file_info.total_size;
-        let piece_length_u64 = torrent.info.piece_length as u64;
-        let piece_index_u64 = piece_index as u64;
-        let start_offset = piece_index_u64 * piece_length_u64;
-        let bytes_remaining = total_length_u64.saturating_sub(start_offset);
-
-        std::cmp::min(piece_length_u64, bytes_remaining) as usize
+        expected_piece_size(
+            piece_index,
+            torrent.info.piece_length as u64,
+            multi_file_info.total_size,
+        )
     }
     /// Generates a human-readable status message for the UI based on the torrent's current state.
     fn generate_activity_message(&self, dl_speed: u64, ul_speed: u64) -> String {
         if self.is_paused {
-            return "Paused".to_string();
+            return if self.piece_manager.cap_reached() {
+                "Capped".to_string()
+            } else {
+                "Paused".to_string()
+            };
+        }
+
+        if self.final_verification_in_progress {
+            return "Verifying download...".to_string();
+        }
+
+        if self.torrent.is_none() && !self.metadata_rejected_peers.is_empty() {
+            return format!(
+                "Fetching metadata (rejected from {} peers)",
+                self.metadata_rejected_peers.len()
+            );
+        }
+
+        if self.downloads_disabled() && self.torrent_status != TorrentStatus::Done {
+            return if self.uploads_disabled() {
+                "Transfers disabled".to_string()
+            } else if ul_speed > 0 {
+                "Uploading (downloads off)".to_string()
+            } else {
+                "Downloads disabled".to_string()
+            };
+        }
+
+        if self.uploads_disabled() && self.torrent_status == TorrentStatus::Done {
+            return "Finished (uploads off)".to_string();
         }
 
         if self.torrent_status == TorrentStatus::Done {
@@ -1055,6 +3173,14 @@ impl TorrentManager {
             };
         }
 
+        if self.partial_seed {
+            return if ul_speed > 0 {
+                "Seeding (partial)".to_string()
+            } else {
+                "Finished (partial)".to_string()
+            };
+        }
+
         if dl_speed > 0 {
             return match &self.last_activity {
                 TorrentActivity::DownloadingPiece(p) => format!("Receiving piece #{}", p),
@@ -1095,6 +3221,48 @@ impl TorrentManager {
                     t.saturating_duration_since(Instant::now())
                 });
 
+            #[cfg(feature = "dht")]
+            let next_dht_refresh_in = self.dht_task_handle.as_ref().map(|_| {
+                self.dht_next_refresh_rx
+                    .borrow()
+                    .saturating_duration_since(Instant::now())
+            });
+            #[cfg(not(feature = "dht"))]
+            let next_dht_refresh_in = None;
+
+            #[cfg(feature = "dht")]
+            let dht_estimate = *self.dht_peer_estimate_rx.borrow();
+            #[cfg(feature = "dht")]
+            let (dht_peers, dht_swarm) = (
+                dht_estimate.map(|estimate| estimate.peers),
+                dht_estimate.and_then(|estimate| estimate.scrape),
+            );
+            #[cfg(not(feature = "dht"))]
+            let (dht_peers, dht_swarm) = (None, None);
+
+            let now = Instant::now();
+            let mut trackers: Vec<TrackerInfo> = self
+                .trackers
+                .iter()
+                .map(|(url, t)| TrackerInfo {
+                    url: url.clone(),
+                    next_announce_in: t.next_announce_time.saturating_duration_since(now),
+                    interval: if self.torrent_status == TorrentStatus::Done {
+                        t.seeding_interval
+                    } else {
+                        t.leeching_interval
+                    },
+                    swarm: t.swarm,
+                    failures: t.failures,
+                    last_error: t.last_error.clone(),
+                })
+                .collect();
+            trackers.sort_by(|a, b| a.url.cmp(&b.url));
+            let swarm = trackers
+                .iter()
+                .filter_map(|t| t.swarm)
+                .max_by_key(SwarmCounts::total);
+
             let bytes_downloaded_this_tick = self.bytes_downloaded_in_interval;
             let bytes_uploaded_this_tick = self.bytes_uploaded_in_interval;
             self.bytes_downloaded_in_interval = 0;
@@ -1127,7 +3295,7 @@ impl TorrentManager {
             let metrics_tx_clone = self.metrics_tx.clone();
             let info_hash_clone = self.info_hash.clone();
             let torrent_name_clone = torrent.info.name.clone();
-            let number_of_pieces_total = (torrent.info.pieces.len() / 20) as u32;
+            let number_of_pieces_total = torrent.piece_count() as u32;
             let number_of_pieces_completed =
                 number_of_pieces_total - self.piece_manager.pieces_remaining as u32;
             let number_of_successfully_connected_peers = self.peers_map.len();
@@ -1161,7 +3329,9 @@ impl TorrentManager {
                         TorrentCommand::SuccessfullyConnected(_) => {
                             "Exchanged Handshake".to_string()
                         }
-                        TorrentCommand::PeerBitfield(_, _) => "Exchanged Bitfield".to_string(),
+                        TorrentCommand::PeerBitfield(_, _) | TorrentCommand::PeerHaveAll(_) => {
+                            "Exchanged Bitfield".to_string()
+                        }
                         TorrentCommand::Choke(_) => "Choked Us".to_string(),
                         TorrentCommand::Unchoke(_) => "Unchoked Us".to_string(),
                         TorrentCommand::Disconnect(_) => "Disconnected".to_string(),
@@ -1194,6 +3364,8 @@ impl TorrentManager {
                         total_downloaded: p.total_bytes_downloaded,
                         total_uploaded: p.total_bytes_uploaded,
                         last_action: final_action_str,
+                        country: None,
+                        source: p.source,
                     }
                 })
                 .collect();
@@ -1219,8 +3391,17 @@ impl TorrentManager {
                 peers: peers_info,
                 activity_message,
                 next_announce_in,
+                next_dht_refresh_in,
+                trackers,
+                swarm,
+                dht_peers,
+                dht_swarm,
+                wasted_bytes: self.wasted_bytes,
+                is_private: torrent.info.private == Some(1),
                 total_size: total_size_bytes,
                 bytes_written,
+                file_priorities: self.file_priorities.clone(),
+                files: self.file_infos(),
                 ..Default::default()
             };
             tokio::spawn(async move {
@@ -1234,57 +3415,20 @@ impl TorrentManager {
     pub async fn run(mut self, is_paused: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
         self.is_paused = is_paused;
 
+        // Validation allocates the files, so it waits until there is room for them.
+        let mut validation_deferred = false;
         if self.torrent.is_some() {
-            if let Err(error) = self.validate_local_file().await {
-                match error {
-                    StorageError::Io(e) => {
-                        eprintln!("Error calling validate local file: {}", e);
-                    }
-                }
-            }
-        }
-
-        if !self.is_paused {
-            event!(
-                Level::DEBUG,
-                "Performing initial 'started' announce to trackers..."
-            );
-            let torrent_size_left = self
-                .multi_file_info
-                .as_ref()
-                .map_or(0, |mfi| mfi.total_size as usize);
-
-            for url in self.trackers.keys() {
-                let torrent_manager_tx_clone = self.torrent_manager_tx.clone();
-                let url_clone = url.clone();
-                let info_hash_clone = self.info_hash.clone();
-                let client_port_clone = self.settings.client_port;
-
-                let client_id_clone = self.settings.client_id.clone();
-
-                tokio::spawn(async move {
-                    let response = announce_started(
-                        url_clone.clone(),
-                        &info_hash_clone,
-                        client_id_clone,
-                        client_port_clone,
-                        torrent_size_left,
-                    )
-                    .await;
-
-                    match response {
-                        Ok(resp) => {
-                            let _ = torrent_manager_tx_clone
-                                .send(TorrentCommand::AnnounceResponse(url_clone, resp))
-                                .await;
-                        }
-                        Err(e) => {
-                            let _ = torrent_manager_tx_clone
-                                .send(TorrentCommand::AnnounceFailed(url_clone, e.to_string()))
-                                .await;
+            self.map_existing_files().await;
+            if self.check_disk_space(false) {
+                if let Err(error) = self.validate_local_file().await {
+                    match error {
+                        StorageError::Io(e) => {
+                            eprintln!("Error calling validate local file: {}", e);
                         }
                     }
-                });
+                }
+            } else {
+                validation_deferred = true;
             }
         }
 
@@ -1295,12 +3439,17 @@ impl TorrentManager {
         {}
 
         let mut data_rate_ms = 1000;
+        // A paused torrent sends no metrics until it resumes, so report what validation
+        // found; the app decides whether it queues as a download or a seed by this.
+        if self.is_paused && self.torrent.is_some() {
+            self.send_metrics(data_rate_ms);
+        }
         let mut tick = tokio::time::interval(Duration::from_millis(data_rate_ms));
         let mut last_tick_time = Instant::now();
 
         let mut cleanup_timer = tokio::time::interval(Duration::from_secs(3));
         let mut pex_timer = tokio::time::interval(Duration::from_secs(75));
-        let mut choke_timer = tokio::time::interval(Duration::from_secs(10));
+        let mut choke_timer = tokio::time::interval(self.settings.choke_interval());
         loop {
             tokio::select! {
                 _ = signal::ctrl_c() => {
@@ -1308,11 +3457,17 @@ impl TorrentManager {
                     break Ok(());
                 }
                 _ = cleanup_timer.tick(), if !self.is_paused => {
-                    self.timed_out_peers.retain(|_, (retry_count, _)| *retry_count < MAX_TIMEOUT_COUNT);
+                    while self.in_flight_writes.try_join_next().is_some() {}
+                    // A peer is forgotten once it has stayed unreachable a full cooldown past its last try.
+                    let now = Instant::now();
+                    self.timed_out_peers.retain(|_, (_, next_attempt_time)| now < *next_attempt_time + Duration::from_secs(MAX_COOLDOWN_SECS));
+                    self.dial_peer_candidates().await;
+                    self.update_partial_seed();
+                    self.detect_snubbed_peers();
 
                     if self.torrent_status == TorrentStatus::Done {
                         for peer in self.peers_map.values() {
-                            let peer_is_fully_seeded = peer.bitfield.iter().all(|&has| has);
+                            let peer_is_fully_seeded = peer.bitfield.all();
 
                             if peer_is_fully_seeded {
                                 let manager_tx_clone = self.torrent_manager_tx.clone();
@@ -1331,42 +3486,61 @@ impl TorrentManager {
                     last_tick_time = now;
                     let actual_ms = actual_duration.as_millis() as u64;
 
+                    // One tracker at a time, in tier order (BEP 12)
                     let mut trackers_to_announce = Vec::new();
 
-                    for (url, tracker_state) in &self.trackers {
-                        if now >= tracker_state.next_announce_time {
-                            trackers_to_announce.push(url.clone());
+                    if let Some(url) = self.tracker_tiers.current() {
+                        if self.trackers.get(url).is_some_and(|t| now >= t.next_announce_time) {
+                            trackers_to_announce.push(url.to_string());
                         }
                     }
 
                     if !trackers_to_announce.is_empty() {
-                        let mut torrent_size_left = 1;
-                        if let Some(torrent) = &self.torrent {
-                            torrent_size_left = torrent.info.length as usize;
-                            if !torrent.info.files.is_empty() {
-                                torrent_size_left = torrent.info.files.iter().map(|file| file.length as usize).sum();
-                            }
-                        }
+                        let torrent_size_left = self.bytes_left() as usize;
+                        let announce_client = self.announce_client();
                         for url in trackers_to_announce {
                             if let Some(tracker_state) = self.trackers.get_mut(&url) {
                                 tracker_state.next_announce_time = now + Duration::from_secs(2048 * 2);
+                                tracker_state.last_announce_time = Some(now);
+                                // 'started' until the tracker answers, then regular announces
+                                let started = tracker_state.started;
                                 let torrent_manager_tx_clone = self.torrent_manager_tx.clone();
                                 let url_clone = url.clone();
                                 let info_hash_clone = self.info_hash.clone();
-                                let client_port_clone = self.settings.client_port;
-                                let client_id_clone = self.settings.client_id.clone();
+                                let announce_client_clone = announce_client.clone();
                                 let session_total_uploaded_clone = self.session_total_uploaded as usize;
                                 let session_total_downloaded_clone = self.session_total_downloaded as usize;
+                                let tracker_id_clone = tracker_state.tracker_id.clone();
+                                let partial_seed = self.partial_seed;
                                 tokio::spawn(async move {
-                                    let tracker_response = announce_periodic(
-                                        url.to_string(),
-                                        &info_hash_clone,
-                                        client_id_clone,
-                                        client_port_clone,
-                                        session_total_uploaded_clone,
-                                        session_total_downloaded_clone,
-                                        torrent_size_left,
-                                    ).await;
+                                    let tracker_response = if started && partial_seed {
+                                        announce_paused(
+                                            url.to_string(),
+                                            &info_hash_clone,
+                                            announce_client_clone,
+                                            session_total_uploaded_clone,
+                                            session_total_downloaded_clone,
+                                            torrent_size_left,
+                                            tracker_id_clone,
+                                        ).await
+                                    } else if started {
+                                        announce_periodic(
+                                            url.to_string(),
+                                            &info_hash_clone,
+                                            announce_client_clone,
+                                            session_total_uploaded_clone,
+                                            session_total_downloaded_clone,
+                                            torrent_size_left,
+                                            tracker_id_clone,
+                                        ).await
+                                    } else {
+                                        announce_started(
+                                            url.to_string(),
+                                            &info_hash_clone,
+                                            announce_client_clone,
+                                            torrent_size_left,
+                                        ).await
+                                    };
 
                                     match tracker_response {
                                         Ok(response) => {
@@ -1381,7 +3555,30 @@ impl TorrentManager {
                         }
                     }
 
+                    if let Some(scrape_interval) = self.settings.tracker_scrape_interval() {
+                        for (url, tracker_state) in self.trackers.iter_mut() {
+                            if tracker_state.next_scrape_time.is_none_or(|at| now < at) {
+                                continue;
+                            }
+                            // A failed scrape just waits for the next interval
+                            tracker_state.next_scrape_time = Some(now + scrape_interval);
+                            let torrent_manager_tx_clone = self.torrent_manager_tx.clone();
+                            let url_clone = url.clone();
+                            let info_hash_clone = self.info_hash.clone();
+                            tokio::spawn(async move {
+                                let command = match scrape(&url_clone, &info_hash_clone).await {
+                                    Ok(counts) => TorrentCommand::ScrapeResponse(url_clone, counts),
+                                    Err(e) => TorrentCommand::ScrapeFailed(url_clone, e.to_string()),
+                                };
+                                let _ = torrent_manager_tx_clone.send(command).await;
+                            });
+                        }
+                    }
 
+                    if self.torrent_status == TorrentStatus::Standard && self.blocks_low_enough_for_endgame() {
+                        event!(Level::DEBUG, "Few blocks left, entering ENDGAME mode!");
+                        self.torrent_status = TorrentStatus::Endgame;
+                    }
                     if self.torrent_status == TorrentStatus::Endgame {
                         let peer_ids: Vec<String> = self.peers_map.keys().cloned().collect();
                         for peer_id in peer_ids {
@@ -1392,6 +3589,7 @@ impl TorrentManager {
                             }
                         }
                     }
+                    self.assign_http_seed_work();
 
                     let scaling_factor = if actual_ms > 0 {
                         1000.0 / actual_ms as f64
@@ -1427,18 +3625,19 @@ impl TorrentManager {
                         self.piece_manager.update_rarity(peer_bitfields);
                     }
                     self.recalculate_chokes();
+                    self.disconnect_idle_peers();
                 }
 
                 _ = pex_timer.tick(), if !self.is_paused => {
-                    if self.peers_map.len() < 2 {
+                    if self.peers_map.len() < 2 || !self.pex_allowed() {
                         continue;
                     }
 
-                    let all_peer_ips: Vec<String> = self.peers_map.keys().cloned().collect();
+                    let all_peers: Vec<PexPeer> = self.peers_map.values().filter_map(pex_peer).collect();
 
                     for peer_state in self.peers_map.values() {
                         let peer_tx = peer_state.peer_tx.clone();
-                        let peers_list = all_peer_ips.clone();
+                        let peers_list = all_peers.clone();
 
                         let _ = peer_tx.try_send(
                             TorrentCommand::SendPexPeers(peers_list)
@@ -1455,18 +3654,128 @@ impl TorrentManager {
                             tick.reset();
                             last_tick_time = Instant::now();
                         },
+                        ManagerCommand::SetUploadDisabled(disabled) => {
+                            self.upload_disabled = disabled;
+                            self.recalculate_chokes();
+                        },
+                        ManagerCommand::SetDownloadDisabled(disabled) => {
+                            self.download_disabled = disabled;
+                            // Requests already sent are left to finish; only new work stops.
+                            if !self.downloads_disabled() && self.torrent.is_some() {
+                                let peer_ids: Vec<String> = self.peers_map.keys().cloned().collect();
+                                for peer_id in peer_ids {
+                                    self.find_and_assign_work(peer_id);
+                                }
+                            }
+                        },
+                        ManagerCommand::SetCompletedDir(completed_dir) => {
+                            self.completed_dir = completed_dir;
+                        },
+                        ManagerCommand::SetLocation { path, move_data, recheck } => {
+                            if self.files_moving || self.final_verification_in_progress {
+                                let result = Err("The torrent is busy with its files".to_string());
+                                let _ = self.manager_event_tx.send(ManagerEvent::FilesMoved { info_hash: self.info_hash.clone(), result }).await;
+                                continue;
+                            }
+
+                            if move_data && self.multi_file_info.is_some() && path != self.root_download_path {
+                                event!(Level::INFO, to = ?path, "Moving the download.");
+                                // No new pieces get written or served while the files are on their way.
+                                self.disconnect_all_peers();
+                                self.flush_in_flight_writes().await;
+                                self.recheck_after_move = Some(recheck);
+                                self.start_move(path);
+                                continue;
+                            }
+
+                            // Nothing to move yet, or the files are already at `path`.
+                            self.flush_in_flight_writes().await;
+                            self.relocate(path.clone());
+                            let _ = self.manager_event_tx.send(ManagerEvent::FilesMoved { info_hash: self.info_hash.clone(), result: Ok(path) }).await;
+                            if recheck && self.torrent.is_some() {
+                                validation_deferred = false;
+                                self.map_existing_files().await;
+                                self.recheck_local_files().await;
+                            }
+                            self.send_metrics(data_rate_ms);
+                        },
+                        ManagerCommand::SetMaxDownloadBytes(max_download_bytes) => {
+                            self.max_download_bytes = max_download_bytes;
+                            // Magnets pick the cap up once their metadata arrives.
+                            if let Some(piece_length) = self.torrent.as_ref().map(|t| t.info.piece_length as u64) {
+                                self.piece_manager.piece_limit = cap_piece_limit(max_download_bytes, piece_length);
+                                self.check_download_cap();
+                                if !self.is_paused {
+                                    let peer_ids: Vec<String> = self.peers_map.keys().cloned().collect();
+                                    for peer_id in peer_ids {
+                                        self.find_and_assign_work(peer_id);
+                                    }
+                                }
+                            }
+                        },
+                        ManagerCommand::SetSequential(sequential) => {
+                            self.piece_manager.sequential = sequential;
+                        },
+                        ManagerCommand::SetBandwidthPriority(priority) => {
+                            self.bandwidth_priority_tx.send_replace(priority);
+                        },
+                        ManagerCommand::SetDhtDisabled(disabled) => {
+                            self.dht_disabled = disabled;
+                            #[cfg(feature = "dht")]
+                            self.spawn_dht_lookup_task();
+                        },
+                        ManagerCommand::SetPexDisabled(disabled) => {
+                            self.pex_disabled = disabled;
+                        },
+                        ManagerCommand::AddSources { trackers, web_seeds } => {
+                            let (trackers, web_seeds) = self.add_sources(trackers, web_seeds);
+                            event!(Level::INFO, trackers = trackers.len(), web_seeds = web_seeds.len(), "Merged sources from a duplicate.");
+                            let _ = self.manager_event_tx.send(ManagerEvent::SourcesAdded { info_hash: self.info_hash.clone(), trackers, web_seeds }).await;
+                            self.send_metrics(data_rate_ms);
+                        },
+                        ManagerCommand::SetFilePriority(ranges, priority) => {
+                            self.set_file_priority(&ranges, priority);
+                        },
+                        ManagerCommand::RenameFile { index, path } => {
+                            // Writes still on their way would recreate the file under its old name.
+                            self.flush_in_flight_writes().await;
+                            let result = match &mut self.multi_file_info {
+                                Some(_) if self.files_moving => Err("The files are being moved".to_string()),
+                                Some(multi_file_info) => rename_file(multi_file_info, &self.root_download_path, index, &path)
+                                    .await
+                                    .map_err(|e| e.to_string()),
+                                None => Err("The metadata isn't known yet".to_string()),
+                            };
+                            let result = result.map(|()| FileRename { index, path, borrowed: false });
+                            match &result {
+                                Ok(rename) => {
+                                    event!(Level::INFO, index, path = ?rename.path, "Renamed file.");
+                                    self.file_renames.retain(|r| r.index != index);
+                                    self.file_renames.push(rename.clone());
+                                }
+                                Err(e) => event!(Level::WARN, index, error = %e, "Could not rename file."),
+                            }
+                            let _ = self.manager_event_tx.send(ManagerEvent::FileRenamed { info_hash: self.info_hash.clone(), result }).await;
+                            self.send_metrics(data_rate_ms);
+                        },
+                        ManagerCommand::DisconnectBannedPeers => {
+                            self.disconnect_banned_peers();
+                        },
+                        ManagerCommand::StreamFiles(reply_tx) => {
+                            let _ = reply_tx.try_send(self.stream_files());
+                        },
+                        ManagerCommand::StreamRead { stream_id, file_index, offset, length, reply_tx } => {
+                            self.queue_stream_read(stream_id, file_index, offset, length, reply_tx);
+                        },
+                        ManagerCommand::StreamEnded { stream_id } => {
+                            self.end_stream(stream_id);
+                        },
                         ManagerCommand::Pause => {
                             self.last_activity = TorrentActivity::Paused;
                             self.is_paused = true;
 
-                            for peer in self.peers_map.values() {
-                                let peer_tx = peer.peer_tx.clone();
-                                let peer_ip_port = peer.ip_port.clone();
-                                let _ = peer_tx.try_send(TorrentCommand::Disconnect(peer_ip_port));
-                            }
-
-                            self.last_known_peers = self.peers_map.keys().cloned().collect();
-                            self.peers_map.clear();
+                            self.disconnect_all_peers();
+                            self.announce_stopped_to_trackers().detach_all();
 
                             self.bytes_downloaded_in_interval = 0;
                             self.bytes_uploaded_in_interval = 0;
@@ -1476,99 +3785,66 @@ impl TorrentManager {
 
                         },
                         ManagerCommand::Resume => {
+                            // The disk may have filled up while it was paused
+                            if !self.check_disk_space(true) {
+                                self.send_metrics(data_rate_ms);
+                                continue;
+                            }
                             self.last_activity = TorrentActivity::ConnectingToPeers;
                             self.is_paused = false;
                             event!(Level::INFO, info_hash = %BASE32.encode(&self.info_hash), "Torrent resumed. Re-announcing to trackers.");
 
-                            #[cfg(feature = "dht")]
-                            let _ = self.dht_trigger_tx.send(());
-
-                            for peer_addr in std::mem::take(&mut self.last_known_peers) {
-                                if let Ok(socket_addr) = peer_addr.parse::<std::net::SocketAddr>() {
-                                    self.connect_to_peer(socket_addr.ip().to_string(), socket_addr.port()).await;
+                            if validation_deferred {
+                                validation_deferred = false;
+                                if let Err(StorageError::Io(e)) = self.validate_local_file().await {
+                                    event!(Level::ERROR, error = %e, "Failed to validate local files after resume.");
                                 }
                             }
-                            for tracker_state in self.trackers.values_mut() {
-                                tracker_state.next_announce_time = Instant::now();
+
+                            self.rejoin_swarm().await;
+                        },
+                        ManagerCommand::ForceRecheck => {
+                            if self.torrent.is_none() || self.files_moving || self.final_verification_in_progress {
+                                event!(Level::WARN, info_hash = %BASE32.encode(&self.info_hash), "Torrent is busy with its files. Ignoring recheck.");
+                                continue;
                             }
+                            event!(Level::INFO, info_hash = %BASE32.encode(&self.info_hash), "Rechecking local files.");
+                            validation_deferred = false;
+                            self.recheck_local_files().await;
+                            self.send_metrics(data_rate_ms);
                         },
                         ManagerCommand::Shutdown => {
                             event!(Level::INFO, info_hash = %BASE32.encode(&self.info_hash), "Torrent shutting down.");
                             self.is_paused = true;
-                            let _ = self.shutdown_tx.send(());
+                            self.flush_in_flight_writes().await;
+                            self.save_partial_pieces().await;
+                            self.shutdown_background_tasks();
 
-                            event!(Level::DEBUG, "Aborting all in-flight upload tasks...");
-                            for (_peer_id, handles_map) in self.in_flight_uploads.iter() {
-                                for (block_info, handle) in handles_map.iter() {
-                                    event!(Level::TRACE, ?block_info, "Aborting task");
-                                    handle.abort();
-                                }
-                            }
-                            self.in_flight_uploads.clear();
-                            event!(Level::DEBUG, "All upload tasks aborted.");
-
-                            if let (Some(torrent), Some(multi_file_info)) = (&self.torrent, &self.multi_file_info) {
-                                let total_size_bytes = multi_file_info.total_size;
-                                let bytes_completed = (torrent.info.piece_length as u64).saturating_mul(
-                                    self.piece_manager
-                                        .bitfield
-                                        .iter()
-                                        .filter(|&s| *s == PieceStatus::Done)
-                                        .count() as u64,
-                                );
-                                let bytes_left = total_size_bytes.saturating_sub(bytes_completed);
-                                let mut announce_set = JoinSet::new();
-                                for url in self.trackers.keys() {
-                                    let url_clone = url.clone();
-                                    let info_hash_clone = self.info_hash.clone();
-                                    let client_port_clone = self.settings.client_port;
-                                    let client_id_clone = self.settings.client_id.clone();
-                                    let session_total_uploaded_clone = self.session_total_uploaded as usize;
-                                    let session_total_downloaded_clone = self.session_total_downloaded as usize;
-                                    announce_set.spawn(async move {
-                                        announce_stopped(
-                                            url_clone,
-                                            &info_hash_clone,
-                                            client_id_clone,
-                                            client_port_clone,
-                                            session_total_uploaded_clone,
-                                            session_total_downloaded_clone,
-                                            bytes_left as usize,
-                                        )
-                                        .await;
-                                    });
-                                }
-                                event!(Level::DEBUG, "Sending 'stopped' to {} trackers...", announce_set.len());
-                                if (tokio::time::timeout(Duration::from_secs(4), async {
-                                    while (announce_set.join_next().await).is_some() {
-                                    }
-                                }).await).is_err() {
-                                    event!(Level::WARN, "Tracker announce tasks timed out. Aborting remaining.");
-                                    announce_set.abort_all();
-                                } else {
-                                    event!(Level::DEBUG, "Tracker announces finished.");
-                                }
-                            }
+                            self.wait_for_stopped_announces().await;
 
-                            self.peers_map.clear();
                             let _ = self.manager_event_tx.try_send(ManagerEvent::DeletionComplete(self.info_hash.clone(), Ok(())));
                             break Ok(());
                         },
                         ManagerCommand::DeleteFile => {
+                            self.is_paused = true;
+                            self.shutdown_background_tasks();
+                            self.wait_for_stopped_announces().await;
+
                             let torrent = if let Some(t) = self.torrent.clone() {
                                 t
                             } else {
-                                let error_msg = "Cannot delete files: Torrent metadata has not been downloaded yet (magnet link?).".to_string();
-                                event!(Level::ERROR, "{}", error_msg);
-                                let _ = self.manager_event_tx.try_send(ManagerEvent::DeletionComplete(self.info_hash.clone(), Err(error_msg)));
+                                // Still fetching metadata for a magnet link, so nothing has been
+                                // allocated on disk yet. Tearing down the tasks is all that's needed.
+                                event!(Level::INFO, info_hash = %BASE32.encode(&self.info_hash), "Metadata fetch cancelled. No files to delete.");
+                                let _ = self.manager_event_tx.send(ManagerEvent::DeletionComplete(self.info_hash.clone(), Ok(()))).await;
                                 break Ok(());
                             };
 
-                            self.peers_map.clear();
                             let mut event_result = Ok(());
 
                             if let Some(multi_file_info) = &self.multi_file_info {
-                                for file_info in &multi_file_info.files {
+                                // Borrowed files are other data's, so they stay.
+                                for file_info in multi_file_info.files.iter().filter(|f| !f.is_borrowed) {
                                     if let Err(e) = fs::remove_file(&file_info.path).await {
                                         if e.kind() != std::io::ErrorKind::NotFound {
                                             let error_msg = format!("Failed to delete torrent file {:?}: {}", &file_info.path, e);
@@ -1578,8 +3854,13 @@ impl TorrentManager {
                                         }
                                     }
                                 }
-                                if event_result.is_ok() && multi_file_info.files.len() > 1 {
-                                    let content_dir = self.root_download_path.join(&torrent.info.name);
+                                let content_dir = content_root_dir(
+                                    &self.root_download_path,
+                                    &torrent.info.name,
+                                    !torrent.info.files.is_empty(),
+                                    self.settings.create_subfolder,
+                                );
+                                if event_result.is_ok() && content_dir != self.root_download_path {
                                     event!(Level::INFO, "Attempting to clean up directory: {:?}", &content_dir);
                                     let _ = fs::remove_dir(&content_dir).await.ok();
                                 }
@@ -1612,6 +3893,26 @@ impl TorrentManager {
                                 }
                             }
                         },
+                        ManagerCommand::ForceReannounce => {
+                            event!(Level::INFO, info_hash = %BASE32.encode(&self.info_hash), "Re-announcing to trackers and the DHT.");
+                            for tracker_state in self.trackers.values_mut() {
+                                tracker_state.next_announce_time = Instant::now();
+                            }
+                            #[cfg(feature = "dht")]
+                            let _ = self.dht_trigger_tx.send(());
+                        },
+                        ManagerCommand::ExternalIpChanged(ip) => {
+                            event!(Level::INFO, %ip, "External IP changed. Triggering re-announce.");
+                            self.external_ip = Some(ip);
+                            for tracker_state in self.trackers.values_mut() {
+                                tracker_state.next_announce_time = Instant::now();
+                            }
+                            #[cfg(feature = "dht")]
+                            let _ = self.dht_trigger_tx.send(());
+                        },
+                        ManagerCommand::UpdateUtpSocket(utp_socket) => {
+                            self.utp_socket = utp_socket;
+                        },
                         #[cfg(feature = "dht")]
                         ManagerCommand::UpdateDhtHandle(new_dht_handle) => {
                             event!(Level::INFO, "DHT handle updated. Restarting DHT lookup task.");
@@ -1634,81 +3935,99 @@ impl TorrentManager {
                     #[cfg(feature = "dht")]
                     {
                         if let Some(peers) = maybe_peers {
-                            self.last_activity = TorrentActivity::SearchingDht;
-                            for peer in peers {
-                                event!(Level::DEBUG, "PEER FROM DHT {}", peer);
-                                self.connect_to_peer(peer.ip().to_string(), peer.port()).await;
+                            // Results can still be queued from before the DHT was switched off
+                            if !self.dht_allowed() {
+                                continue;
                             }
+                            self.last_activity = TorrentActivity::SearchingDht;
+                            let dht_peers = peers
+                                .iter()
+                                .map(|peer| {
+                                    event!(Level::DEBUG, "PEER FROM DHT {}", peer);
+                                    (peer.ip().to_string(), peer.port())
+                                })
+                                .collect();
+                            self.connect_to_peers(dht_peers, PeerSource::Dht).await;
                         } else {
                             event!(Level::WARN, "DHT channel closed. No longer receiving DHT peers.");
                         }
                     }
                 }
 
-                Some((stream, handshake_response)) = self.incoming_peer_rx.recv(), if !self.is_paused => {
+                Some((stream, peer_addr, handshake_response)) = self.incoming_peer_rx.recv(), if !self.is_paused => {
                     let _ = self.manager_event_tx.try_send(ManagerEvent::PeerDiscovered { info_hash: self.info_hash.clone() });
-                    if let Ok(peer_addr) = stream.peer_addr() {
+                    let peer_ip_port = peer_addr.to_string();
+                    event!(Level::DEBUG, peer_addr = %peer_ip_port, "NEW INCOMING PEER CONNECTION");
+                    let torrent_manager_tx_clone = self.torrent_manager_tx.clone();
+                    let (peer_session_tx, peer_session_rx) = mpsc::channel::<TorrentCommand>(10);
 
-                        let peer_ip_port = peer_addr.to_string();
-                        event!(Level::DEBUG, peer_addr = %peer_ip_port, "NEW INCOMING PEER CONNECTION");
-                        let torrent_manager_tx_clone = self.torrent_manager_tx.clone();
-                        let (peer_session_tx, peer_session_rx) = mpsc::channel::<TorrentCommand>(10);
-
-                        if self.peers_map.contains_key(&peer_ip_port) {
-                            event!(Level::WARN, peer_ip = %peer_ip_port, "Already connected to this peer. Dropping incoming connection.");
-                            continue;
-                        }
+                    if self.peers_map.contains_key(&peer_ip_port) {
+                        event!(Level::WARN, peer_ip = %peer_ip_port, "Already connected to this peer. Dropping incoming connection.");
+                        continue;
+                    }
 
-                        self.peers_map.insert(
-                            peer_ip_port.clone(),
-                            PeerState::new(peer_ip_port.clone(), peer_session_tx),
-                        );
+                    if self.is_rejected_source(&peer_ip_port) {
+                        event!(Level::DEBUG, peer_ip = %peer_ip_port, "Dropping incoming connection from peer that sent bad data.");
+                        continue;
+                    }
 
-                        let bitfield = match self.torrent {
-                            None => None,
-                            _ => Some(self.generate_bitfield())
-                        };
-                        let info_hash_clone = self.info_hash.clone();
-                        let torrent_metadata_length_clone = self.torrent_metadata_length;
-                        let global_dl_bucket_clone = self.global_dl_bucket.clone();
-                        let global_ul_bucket_clone = self.global_ul_bucket.clone();
-                        let mut shutdown_rx_manager = self.shutdown_tx.subscribe();
-                        let shutdown_tx = self.shutdown_tx.clone();
-                        let client_id_clone = self.settings.client_id.clone();
+                    self.peers_map.insert(
+                        peer_ip_port.clone(),
+                        PeerState::new(peer_ip_port.clone(), peer_session_tx),
+                    );
 
-                        let _ = self.manager_event_tx.try_send(ManagerEvent::PeerConnected { info_hash: self.info_hash.clone() });
-                        tokio::spawn(async move {
-                            let session = PeerSession::new(PeerSessionParameters {
-                                info_hash: info_hash_clone,
-                                torrent_metadata_length: torrent_metadata_length_clone,
-                                connection_type: ConnectionType::Incoming,
-                                torrent_manager_rx: peer_session_rx,
-                                torrent_manager_tx: torrent_manager_tx_clone,
-                                peer_ip_port: peer_ip_port.clone(),
-                                client_id: client_id_clone.into(),
-                                global_dl_bucket: global_dl_bucket_clone,
-                                global_ul_bucket: global_ul_bucket_clone,
-                                shutdown_tx,
-                            });
+                    let bitfield = match self.torrent {
+                        None => None,
+                        _ => Some(self.generate_bitfield())
+                    };
+                    let info_hash_clone = self.info_hash.clone();
+                    let torrent_metadata_clone = self.torrent_metadata.clone();
+                    let global_dl_bucket_clone = self.global_dl_bucket.clone();
+                    let global_ul_bucket_clone = self.global_ul_bucket.clone();
+                    let bandwidth_priority_rx = self.bandwidth_priority_tx.subscribe();
+                    let mut shutdown_rx_manager = self.shutdown_tx.subscribe();
+                    let shutdown_tx = self.shutdown_tx.clone();
+                    let client_id_clone = self.settings.client_id.clone();
+                    let lazy_bitfield = self.settings.lazy_bitfield;
+                    let upload_only = self.partial_seed;
+                    let pex_allowed = self.pex_allowed();
+                    let timeouts = self.peer_timeouts();
+
+                    let _ = self.manager_event_tx.try_send(ManagerEvent::PeerConnected { info_hash: self.info_hash.clone() });
+                    tokio::spawn(async move {
+                        let session = PeerSession::new(PeerSessionParameters {
+                            info_hash: info_hash_clone,
+                            torrent_metadata: torrent_metadata_clone,
+                            connection_type: ConnectionType::Incoming,
+                            torrent_manager_rx: peer_session_rx,
+                            torrent_manager_tx: torrent_manager_tx_clone,
+                            peer_ip_port: peer_ip_port.clone(),
+                            client_id: client_id_clone.into(),
+                            global_dl_bucket: global_dl_bucket_clone,
+                            global_ul_bucket: global_ul_bucket_clone,
+                            bandwidth_priority: bandwidth_priority_rx,
+                            shutdown_tx,
+                            lazy_bitfield,
+                            upload_only,
+                            pex_allowed,
+                            timeouts,
+                        });
 
-                            tokio::select! {
-                                session_result = session.run(stream, handshake_response, bitfield) => {
-                                    if let Err(e) = session_result {
-                                        event!(Level::ERROR, peer_ip = %peer_ip_port, error = %e, "Incoming peer session ended with error.");
-                                    }
-                                }
-                                _ = shutdown_rx_manager.recv() => {
-                                    event!(
-                                        Level::DEBUG,
-                                        "INCOMING PEER SESSION {}: Shutting down due to manager signal.",
-                                        &peer_ip_port
-                                    );
+                        tokio::select! {
+                            session_result = session.run(stream, handshake_response, bitfield) => {
+                                if let Err(e) = session_result {
+                                    event!(Level::ERROR, peer_ip = %peer_ip_port, error = %e, "Incoming peer session ended with error.");
                                 }
                             }
-                        });
-                    } else {
-                        event!(Level::DEBUG, "ERROR GETTING PEER ADDRESS FROM STREAM");
-                    }
+                            _ = shutdown_rx_manager.recv() => {
+                                event!(
+                                    Level::DEBUG,
+                                    "INCOMING PEER SESSION {}: Shutting down due to manager signal.",
+                                    &peer_ip_port
+                                );
+                            }
+                        }
+                    });
                 }
 
                 Some(command) = self.torrent_manager_rx.recv() => {
@@ -1719,6 +4038,7 @@ impl TorrentManager {
                     let peer_id_for_action = match &command {
                         TorrentCommand::SuccessfullyConnected(id) => Some(id),
                         TorrentCommand::PeerBitfield(id, _) => Some(id),
+                        TorrentCommand::PeerHaveAll(id) => Some(id),
                         TorrentCommand::Choke(id) => Some(id),
                         TorrentCommand::Unchoke(id) => Some(id),
                         TorrentCommand::Have(id, _) => Some(id),
@@ -1746,6 +4066,11 @@ impl TorrentManager {
                             if self.timed_out_peers.remove(&peer_id).is_some() {
                                 event!(Level::DEBUG, peer = %peer_id, "Peer connected successfully, resetting backoff.");
                             }
+                            self.holepunch_relays.remove(&peer_id);
+                            if let Some(peer) = self.peers_map.get_mut(&peer_id) {
+                                peer.connecting = false;
+                            }
+                            self.dial_peer_candidates().await;
 
                             self.number_of_successfully_connected_peers += 1;
                             self.find_and_assign_work(peer_id);
@@ -1756,27 +4081,120 @@ impl TorrentManager {
                                 peer.peer_id = peer_id;
                             }
                         }
-                        TorrentCommand::AddPexPeers(_peer_id, new_peers) => {
-                            for peer_tuple in new_peers {
-                                self.connect_to_peer(peer_tuple.0, peer_tuple.1).await;
+                        TorrentCommand::PeerReportedIp(peer_ip_port, our_ip) => {
+                            self.external_ip = Some(our_ip.to_canonical());
+                            if let Some(reporter) = peer_ip(&peer_ip_port) {
+                                let _ = self.manager_event_tx.try_send(ManagerEvent::ExternalIpReported {
+                                    ip: our_ip,
+                                    source: IpSource::Peer(reporter),
+                                });
+                            }
+                        }
+                        TorrentCommand::AddPexPeers(peer_id, new_peers) => {
+                            if !self.pex_allowed() {
+                                continue;
+                            }
+                            let mut pex_candidates = Vec::new();
+                            for pex_peer in new_peers {
+                                // Two seeds have nothing to trade.
+                                if self.torrent_status == TorrentStatus::Done && pex_peer.flags & PEX_SEED != 0 {
+                                    continue;
+                                }
+                                let (ip, port) = (pex_peer.addr.ip().to_string(), pex_peer.addr.port());
+                                let new_peer_ip_port = peer_ip_port(&ip, port);
+                                if !self.peers_map.contains_key(&new_peer_ip_port)
+                                    && self.holepunch_relays.len() < MAX_HOLEPUNCH_CANDIDATES
+                                {
+                                    self.holepunch_relays.insert(new_peer_ip_port, peer_id.clone());
+                                }
+                                pex_candidates.push((ip, port));
+                            }
+                            self.connect_to_peers(pex_candidates, PeerSource::Pex).await;
+                        },
+                        TorrentCommand::DropPexPeers(peer_id, dropped_peers) => {
+                            for addr in dropped_peers {
+                                let dropped_ip_port = peer_ip_port(&addr.ip().to_string(), addr.port());
+                                if self.holepunch_relays.get(&dropped_ip_port) == Some(&peer_id) {
+                                    self.holepunch_relays.remove(&dropped_ip_port);
+                                }
+                            }
+                        }
+                        TorrentCommand::PeerHolepunchExtension(peer_id) => {
+                            if let Some(peer) = self.peers_map.get_mut(&peer_id) {
+                                peer.supports_holepunch = true;
+                            }
+                        }
+                        TorrentCommand::HolepunchRendezvous(peer_id, target) => {
+                            self.relay_holepunch(&peer_id, target);
+                        }
+                        TorrentCommand::HolepunchConnect(peer_addr) => {
+                            self.holepunch_connect(peer_addr).await;
+                        }
+                        TorrentCommand::PeerBitfield(peer_id, value) => {
+                            if let Some(peer) = self.peers_map.get_mut(&peer_id) {
+                                peer.bitfield = Bitfield::from_bytes(&value, value.len() * 8);
+                                if let Some(ref torrent) = self.torrent {
+                                    let total_pieces = torrent.piece_count();
+                                    peer.bitfield.resize(total_pieces, false);
+                                    self.find_and_assign_work(peer_id);
+                                } else {
+                                    event!(Level::DEBUG, peer_id = %peer_id, "Storing raw bitfield, metadata not yet available.");
+                                }
+                            }
+                        },
+                        TorrentCommand::PeerHaveAll(peer_id) => {
+                            if let Some(peer) = self.peers_map.get_mut(&peer_id) {
+                                peer.has_all = true;
+                                if let Some(ref torrent) = self.torrent {
+                                    peer.bitfield = (0..torrent.piece_count()).map(|_| true).collect();
+                                    self.find_and_assign_work(peer_id);
+                                }
+                            }
+                        }
+                        TorrentCommand::PeerFastExtension(peer_id, allowed_fast_for_peer) => {
+                            if let Some(peer) = self.peers_map.get_mut(&peer_id) {
+                                peer.supports_fast = true;
+                                peer.allowed_fast_for_peer = allowed_fast_for_peer.into_iter().collect();
                             }
-                        },
-                        TorrentCommand::PeerBitfield(peer_id, value) => {
+                        }
+                        TorrentCommand::SuggestPiece(peer_id, piece_index) => {
                             if let Some(peer) = self.peers_map.get_mut(&peer_id) {
-                                peer.bitfield = value.iter()
-                                    .flat_map(|&byte| {
-                                        (0..8).map(move |i| (byte >> (7 - i)) & 1 == 1)
-                                    })
-                                    .collect();
-                                if let Some(ref torrent) = self.torrent {
-                                    let total_pieces = torrent.info.pieces.len() / 20;
-                                    peer.bitfield.resize(total_pieces, false);
+                                if peer.suggested_pieces.len() < MAX_PEER_PIECE_HINTS {
+                                    peer.suggested_pieces.insert(piece_index);
+                                }
+                            }
+                        }
+                        TorrentCommand::AllowedFast(peer_id, piece_index) => {
+                            if let Some(peer) = self.peers_map.get_mut(&peer_id) {
+                                if peer.allowed_fast.len() < MAX_PEER_PIECE_HINTS {
+                                    peer.allowed_fast.insert(piece_index);
+                                }
+                                if self.torrent.is_some() {
                                     self.find_and_assign_work(peer_id);
-                                } else {
-                                    event!(Level::DEBUG, peer_id = %peer_id, "Storing raw bitfield, metadata not yet available.");
                                 }
                             }
-                        },
+                        }
+                        TorrentCommand::RequestRejected(peer_id, piece_index) => {
+                            let Some(peer) = self.peers_map.get_mut(&peer_id) else {
+                                continue;
+                            };
+                            if !peer.pending_requests.remove(&piece_index) {
+                                continue;
+                            }
+                            event!(Level::DEBUG, peer = %peer_id, piece = piece_index, "Peer rejected our request.");
+                            if self.piece_manager.bitfield.get(piece_index as usize) != Some(&PieceStatus::Done) {
+                                self.piece_manager.release_pending(piece_index, &peer_id);
+                            }
+
+                            // Hand the piece to a peer that has nothing to do.
+                            let idle_peers: Vec<String> = self.peers_map.iter()
+                                .filter(|(id, peer)| **id != peer_id && peer.pending_requests.is_empty())
+                                .map(|(id, _)| id.clone())
+                                .collect();
+                            for idle_peer in idle_peers {
+                                self.find_and_assign_work(idle_peer);
+                            }
+                        }
                         TorrentCommand::Choke(peer_id) => {
                             if let Some(peer) = self.peers_map.get_mut(&peer_id) {
                                 peer.peer_choking = ChokeStatus::Choke;
@@ -1788,7 +4206,11 @@ impl TorrentManager {
                             }
                         }
                         TorrentCommand::Disconnect(peer_id) => {
+                            self.holepunch_relays.retain(|_, relay| *relay != peer_id);
                             if let Some(removed_peer) = self.peers_map.remove(&peer_id) {
+                                if removed_peer.connecting {
+                                    self.record_dial_failure(&peer_id);
+                                }
                                 for piece_index in removed_peer.pending_requests {
                                     if self.piece_manager.bitfield[piece_index as usize] != PieceStatus::Done {
                                         event!(Level::DEBUG, piece = piece_index, peer = %peer_id, "Peer disconnected, requeueing abandoned piece.");
@@ -1801,6 +4223,7 @@ impl TorrentManager {
                                 };
                                 let _ = self.manager_event_tx.try_send(ManagerEvent::PeerDisconnected { info_hash: self.info_hash.clone() });
                             }
+                            self.dial_peer_candidates().await;
                         }
                         TorrentCommand::Unchoke(peer_id) => {
                             if self.torrent_status != TorrentStatus::Done {
@@ -1812,13 +4235,12 @@ impl TorrentManager {
                         }
                         TorrentCommand::Have(peer_id, piece_index) => {
                             if let Some(peer) = self.peers_map.get_mut(&peer_id) {
-                                if peer.bitfield.len() > piece_index as usize {
-                                    peer.bitfield[piece_index as usize] = true;
-                                }
+                                peer.bitfield.set(piece_index as usize, true);
                             }
                         },
                         TorrentCommand::Block(peer_id, piece_index, block_offset, block_data) => {
                             self.last_activity = TorrentActivity::DownloadingPiece(piece_index);
+                            self.last_peer_block_at = Instant::now();
                             let _ = self.manager_event_tx.try_send(ManagerEvent::BlockReceived {
                                 info_hash: self.info_hash.clone(),
                             });
@@ -1840,29 +4262,34 @@ impl TorrentManager {
                             if let Some(peer) = self.peers_map.get_mut(&peer_id) {
                                 peer.bytes_downloaded_from_peer += block_data.len() as u64;
                                 peer.bytes_downloaded_in_tick += block_data.len() as u64;
-                                peer.total_bytes_downloaded += block_data.len() as u64
+                                peer.total_bytes_downloaded += block_data.len() as u64;
+                                peer.last_transfer = Instant::now();
+                                peer.awaiting_block_since = Instant::now();
+                                peer.snubbed = false;
+                            }
+
+                            if self.torrent_status == TorrentStatus::Endgame {
+                                self.cancel_duplicate_block(&peer_id, piece_index, block_offset, block_data.len() as u32);
                             }
 
                             let piece_size = self.get_piece_size(piece_index);
+                            self.piece_contributors
+                                .entry(piece_index)
+                                .or_default()
+                                .insert(peer_id.clone());
 
                             if let Some(complete_piece_data) = self.piece_manager.handle_block(piece_index, block_offset, &block_data, piece_size) {
 
                                 let torrent = self.torrent.clone().expect("Torrent metadata not ready for verification.");
-                                let start_hash_index = piece_index as usize * HASH_LENGTH;
-                                let end_hash_index = start_hash_index + HASH_LENGTH;
-                                let expected_hash = torrent.info.pieces.get(start_hash_index..end_hash_index).map(|s| s.to_vec());
+                                let expected_hash = torrent.piece_hash(piece_index as usize);
                                 let torrent_manager_tx = self.torrent_manager_tx.clone();
                                 let peer_id_clone = peer_id.clone();
+                                let hash_pool = self.hash_pool.clone();
+                                let expected_len = self.settings.verify_piece_length.then_some(piece_size);
                                 tokio::spawn(async move {
-                                    let verification_result = tokio::task::spawn_blocking(move || {
-                                        if let Some(expected) = expected_hash {
-                                            let actual_hash = sha1::Sha1::digest(&complete_piece_data);
-                                            if actual_hash.as_slice() == expected.as_slice() {
-                                                return Ok(complete_piece_data);
-                                            }
-                                        }
-                                        Err(())
-                                    }).await.unwrap_or(Err(()));
+                                    let verification_result = hash_pool
+                                        .verify(complete_piece_data, expected_hash, expected_len)
+                                        .await;
 
                                     let _ = torrent_manager_tx.send(TorrentCommand::PieceVerified {
                                         piece_index,
@@ -1873,10 +4300,54 @@ impl TorrentManager {
                             }
 
                         },
+                        TorrentCommand::HttpSeedPiece { url, piece_index, result } => {
+                            let Some(seed) = self.http_seeds.iter_mut().find(|seed| seed.url == url) else {
+                                continue;
+                            };
+                            seed.piece_in_flight = None;
+                            let piece_done = self.piece_manager.bitfield.get(piece_index as usize) == Some(&PieceStatus::Done);
+
+                            match result {
+                                Ok(piece_data) => {
+                                    seed.record_success();
+                                    self.bytes_downloaded_in_interval += piece_data.len() as u64;
+                                    self.session_total_downloaded += piece_data.len() as u64;
+
+                                    if !piece_done {
+                                        self.last_activity = TorrentActivity::DownloadingPiece(piece_index);
+                                        let torrent = self.torrent.clone().expect("Torrent metadata not ready for verification.");
+                                        let expected_hash = torrent.piece_hash(piece_index as usize);
+                                        let expected_len = self.settings.verify_piece_length.then_some(self.get_piece_size(piece_index));
+                                        let torrent_manager_tx = self.torrent_manager_tx.clone();
+                                        let hash_pool = self.hash_pool.clone();
+                                        tokio::spawn(async move {
+                                            let verification_result = hash_pool
+                                                .verify(piece_data, expected_hash, expected_len)
+                                                .await;
+
+                                            let _ = torrent_manager_tx.send(TorrentCommand::PieceVerified {
+                                                piece_index,
+                                                peer_id: url,
+                                                verification_result,
+                                            }).await;
+                                        });
+                                    }
+                                }
+                                Err(e) => {
+                                    event!(Level::DEBUG, seed = %url, piece = piece_index, error = %e, "HTTP seed request failed.");
+                                    seed.record_failure(e.retry_after());
+                                    if !piece_done {
+                                        self.piece_manager.requeue_pending_to_need(piece_index);
+                                    }
+                                }
+                            }
+                            self.assign_http_seed_work();
+                        },
                         TorrentCommand::PieceVerified { piece_index, peer_id, verification_result } => {
                             self.last_activity = TorrentActivity::VerifyingPiece(piece_index);
 
                             let torrent = self.torrent.clone().expect("Torrent metadata not ready for verification.");
+                            let contributors = self.piece_contributors.remove(&piece_index);
                             match verification_result {
                                 Ok(verified_piece_data) => {
 
@@ -1908,19 +4379,10 @@ impl TorrentManager {
                                         peer.pending_requests.remove(&piece_index);
                                     }
 
-
-                                    let channels: Vec<Sender<TorrentCommand>> = self
-                                        .peers_map
-                                        .values()
-                                        .filter(|peer| {
-                                            peer.ip_port != peer_id &&
-                                            (piece_index as usize) < peer.bitfield.len() &&
-                                            !peer.bitfield[piece_index as usize]
-                                        })
-                                        .map(|p| p.peer_tx.clone())
-                                        .collect();
-                                    for tx in channels {
-                                        let _ = tx.try_send(TorrentCommand::PieceAcquired(piece_index));
+                                    if self.files_moving {
+                                        // The files are on their way to another folder; fetch the piece again later.
+                                        self.piece_manager.requeue_pending_to_need(piece_index);
+                                        continue;
                                     }
 
                                     let multi_file_info_clone = self
@@ -1938,7 +4400,7 @@ impl TorrentManager {
                                     let peer_id_clone = peer_id.clone();
                                     let mut shutdown_rx_for_write = self.shutdown_tx.subscribe();
 
-                                    tokio::spawn(async move {
+                                    self.in_flight_writes.spawn(async move {
                                         let operation = DiskIoOperation {
                                             piece_index,
                                             offset: global_offset,
@@ -2031,15 +4493,20 @@ impl TorrentManager {
                                     self.check_for_completion();
                                     self.find_and_assign_work(peer_id);
                                 },
-                                Err(_) => {
-                                    event!(Level::WARN, piece = piece_index, bad_peer = %peer_id, "Piece validation failed.");
+                                Err(e) => {
+                                    self.wasted_bytes += self.get_piece_size(piece_index) as u64;
+                                    event!(Level::WARN, piece = piece_index, bad_peer = %peer_id, error = %e, wasted_bytes = self.wasted_bytes, "Piece validation failed.");
                                     self.piece_manager.reset_piece_assembly(piece_index);
 
-                                    if let Some(peer) = self.peers_map.get_mut(&peer_id) {
-                                        event!(Level::WARN, peer = %peer_id, "Disconnecting from peer due to sending corrupt piece.");
-                                        let peer_tx = peer.peer_tx.clone();
-                                        let _ = peer_tx.try_send(TorrentCommand::Disconnect(peer_id));
+                                    if let Some(seed) = self.http_seeds.iter_mut().find(|seed| seed.url == peer_id) {
+                                        seed.record_failure(None);
+                                        self.piece_manager.requeue_pending_to_need(piece_index);
+                                        continue;
                                     }
+
+                                    // The piece goes back to the queue for another peer to supply.
+                                    let contributors = contributors.unwrap_or_else(|| HashSet::from([peer_id]));
+                                    self.record_hash_failure(piece_index, contributors);
                                 }
                             }
                         },
@@ -2062,12 +4529,46 @@ impl TorrentManager {
                                 }
                             }
 
-                            for peer in self.peers_map.values() {
-                                let peer_tx = peer.peer_tx.clone();
-                                let _ = peer_tx.try_send(TorrentCommand::PieceAcquired(piece_index));
-                            }
+                            self.announce_piece(piece_index);
 
                             self.check_for_completion();
+                            self.check_download_cap();
+                            self.serve_stream_reads();
+                        },
+                        TorrentCommand::FinalVerificationComplete { bad_pieces } => {
+                            self.final_verification_in_progress = false;
+
+                            if bad_pieces.is_empty() {
+                                event!(Level::INFO, "Final verification passed.");
+                                self.complete_download();
+                            } else {
+                                event!(
+                                    Level::WARN,
+                                    bad_pieces = bad_pieces.len(),
+                                    "Final verification found pieces that don't match their hash. Re-downloading them."
+                                );
+                                self.redownload_unverified_pieces(&bad_pieces);
+                            }
+                        },
+                        TorrentCommand::FilesMoved(result) => {
+                            self.files_moving = false;
+                            match &result {
+                                Ok(new_root) => self.relocate(new_root.clone()),
+                                Err(e) => {
+                                    event!(Level::ERROR, error = %e, "Could not move the download. It stays where it is.");
+                                }
+                            }
+                            let recheck_after_move = self.recheck_after_move.take();
+                            let _ = self.manager_event_tx.send(ManagerEvent::FilesMoved { info_hash: self.info_hash.clone(), result }).await;
+                            match recheck_after_move {
+                                Some(true) => {
+                                    validation_deferred = false;
+                                    self.recheck_local_files().await;
+                                    self.send_metrics(data_rate_ms);
+                                }
+                                Some(false) if !self.is_paused => self.rejoin_swarm().await,
+                                _ => {}
+                            }
                         },
                         TorrentCommand::PieceWriteFailed { piece_index } => {
                             event!(Level::WARN, piece = piece_index, "Re-queuing piece for download after disk write failure.");
@@ -2097,14 +4598,20 @@ impl TorrentManager {
                                 peer.bytes_uploaded_to_peer += block_length as u64;
                                 peer.bytes_uploaded_in_tick += block_length as u64;
                                 peer.total_bytes_uploaded += block_length as u64;
+                                peer.last_transfer = Instant::now();
 
-                                if peer.am_choking == ChokeStatus::Unchoke && (piece_index as usize) < self.piece_manager.bitfield.len() && self.piece_manager.bitfield[piece_index as usize] == PieceStatus::Done {
+                                let may_upload = !self.files_moving
+                                    && (peer.am_choking == ChokeStatus::Unchoke || peer.allowed_fast_for_peer.contains(&piece_index));
+                                if may_upload && (piece_index as usize) < self.piece_manager.bitfield.len() && self.piece_manager.bitfield[piece_index as usize] == PieceStatus::Done {
 
                                     let peer_semaphore = peer.upload_slots_semaphore.clone();
                                     let _peer_permit = match peer_semaphore.try_acquire_owned() {
                                         Ok(permit) => permit,
                                         Err(_) => {
                                             event!(Level::DEBUG, peer = %peer_id, "Peer is too aggressive (upload slots full), dropping request.");
+                                            if peer.supports_fast {
+                                                let _ = peer.peer_tx.try_send(TorrentCommand::RejectUpload(piece_index, block_offset, block_length));
+                                            }
                                             continue;
                                         }
                                     };
@@ -2251,6 +4758,9 @@ impl TorrentManager {
                                         .insert(block_info, handle);
 
 
+                                } else if peer.supports_fast {
+                                    // Fast extension peers are told instead of left waiting.
+                                    let _ = peer.peer_tx.try_send(TorrentCommand::RejectUpload(piece_index, block_offset, block_length));
                                 }
                             }
                         },
@@ -2260,6 +4770,10 @@ impl TorrentManager {
                                 if let Some(handle) = peer_uploads.remove(&block_to_cancel) {
                                     handle.abort();
                                     event!(Level::TRACE, peer = %peer_id, ?block_to_cancel, "Aborted in-flight upload task.");
+                                    // A cancelled request still needs an answer under the fast extension.
+                                    if let Some(peer) = self.peers_map.get(&peer_id).filter(|peer| peer.supports_fast) {
+                                        let _ = peer.peer_tx.try_send(TorrentCommand::RejectUpload(piece_index, block_offset, block_length));
+                                    }
                                 }
                             }
                         },
@@ -2268,72 +4782,124 @@ impl TorrentManager {
                                 peer_uploads.remove(&block_info);
                             }
                         },
-                        TorrentCommand::DhtTorrent(torrent, torrent_metadata_length) => {
+                        TorrentCommand::MetadataRejected(peer_id) => {
+                            if self.torrent.is_none() {
+                                self.reject_metadata_source(&peer_id);
+                            }
+                        }
+                        TorrentCommand::DhtTorrent(peer_id, torrent) => {
                             if self.torrent.is_none() {
-                                let mut info_dict_hasher = Sha1::new();
-                                info_dict_hasher.update(torrent.clone().info_dict_bencode);
-                                let dht_info_hash = info_dict_hasher.finalize();
+                                let dht_info_hash = torrent.info_hash();
 
-                                if *self.info_hash == *dht_info_hash {
+                                if self.info_hash == dht_info_hash {
 
-                                    #[cfg(all(feature = "dht", feature = "pex"))]
-                                    {
-                                        // Check if the 'private' key exists and is set to 1
-                                        if torrent.info.private == Some(1) {
-                                            event!(Level::ERROR, info_hash = %BASE32.encode(&self.info_hash), "Rejecting private torrent (from metadata) in normal build.");
+                                    // BEP 9 only carries the info dict. v2-only pieces are checked
+                                    // against piece layers that aren't part of it, so without them
+                                    // there would be nothing to verify.
+                                    let accepted = if torrent.is_v2_only() {
+                                        Err("v2-only torrents can't be added from a magnet link yet, add the .torrent file instead".to_string())
+                                    } else {
+                                        piece_count(&torrent).map(|_| ())
+                                    };
+                                    if let Err(e) = accepted {
+                                        event!(Level::ERROR, info_hash = %BASE32.encode(&self.info_hash), "Rejecting torrent from metadata: {}", e);
 
-                                            let _ = self.manager_event_tx.send(ManagerEvent::DeletionComplete(self.info_hash.clone(), Ok(()))).await;
-                                            break Ok(());
-                                        }
+                                        self.shutdown_background_tasks();
+                                        let _ = self.manager_event_tx.send(ManagerEvent::DeletionComplete(self.info_hash.clone(), Err(e))).await;
+                                        break Ok(());
+                                    }
+
+                                    self.torrent = Some((*torrent).clone());
+
+                                    // The magnet turned out to be private: stop looking for peers on the DHT
+                                    #[cfg(feature = "dht")]
+                                    if self.is_private() {
+                                        event!(Level::INFO, info_hash = %BASE32.encode(&self.info_hash), "Private torrent, only using its trackers from now on.");
+                                        self.spawn_dht_lookup_task();
                                     }
 
-                                    self.torrent = Some(torrent.clone());
-                                    self.torrent_metadata_length = Some(torrent_metadata_length);
+                                    self.torrent_metadata = Some(Arc::new(torrent.info_dict_bencode.clone()));
 
-                                    let multi_file_info = MultiFileInfo::new(
+                                    let content_root = content_root_dir(
                                         &self.root_download_path,
                                         &torrent.info.name,
+                                        !torrent.info.files.is_empty(),
+                                        self.settings.create_subfolder,
+                                    );
+                                    let mut multi_file_info = MultiFileInfo::new(
+                                        &content_root,
+                                        &torrent.info.name,
                                         if torrent.info.files.is_empty() { None } else { Some(&torrent.info.files) },
                                         if torrent.info.files.is_empty() { Some(torrent.info.length as u64) } else { None },
                                     )
                                     .expect("Failed to create multi-file info from DHT metadata");
+                                    multi_file_info.apply_renames(&self.root_download_path, &self.file_renames);
                                     self.multi_file_info = Some(multi_file_info);
+                                    self.map_existing_files().await;
 
-                                    let pieces_len = torrent.info.pieces.len();
-                                    let total_pieces = pieces_len / 20;
+                                    let total_pieces = torrent.piece_count();
 
-                                    self.piece_manager.set_initial_fields(pieces_len / 20, self.torrent_validation_status);
+                                    self.piece_manager.set_initial_fields(total_pieces, self.torrent_validation_status);
+                                    self.piece_manager.piece_limit = cap_piece_limit(self.max_download_bytes, torrent.info.piece_length as u64);
+                                    self.init_files();
                                     let bitfield = self.generate_bitfield();
 
-                                    let _ = self.validate_local_file().await;
+                                    if self.check_disk_space(false) {
+                                        let _ = self.validate_local_file().await;
+                                    } else {
+                                        validation_deferred = true;
+                                        self.disconnect_all_peers();
+                                    }
 
-                                    if let Some(announce) = torrent.announce {
-                                        self.trackers.insert(announce.clone(), TrackerState {
-                                            next_announce_time: Instant::now(),
-                                            leeching_interval: None,
-                                            seeding_interval: None,
-                                        });
+                                    for url in torrent.announce_tiers().into_iter().flatten() {
+                                        if !self.trackers.contains_key(&url) {
+                                            self.trackers.insert(url.clone(), TrackerState::new(&url));
+                                            self.tracker_tiers.push_tier(url);
+                                        }
                                     }
-                                    self.connect_to_tracker_peers().await;
-
-                                    for peer in self.peers_map.values_mut() {
-                                        peer.bitfield.resize(total_pieces, false);
-                                        let peer_tx_cloned = peer.peer_tx.clone();
-                                        let bitfield_clone = bitfield.clone();
-                                        let torrent_metadata_length_clone = self.torrent_metadata_length;
-                                        let _ =
-                                            peer_tx_cloned.try_send(TorrentCommand::ClientBitfield(bitfield_clone, torrent_metadata_length_clone));
+
+                                    if !self.is_paused {
+                                        self.connect_to_tracker_peers().await;
+
+                                        for peer in self.peers_map.values_mut() {
+                                            peer.bitfield.resize(total_pieces, peer.has_all);
+                                            let peer_tx_cloned = peer.peer_tx.clone();
+                                            let bitfield_clone = bitfield.clone();
+                                            let torrent_metadata_clone = self.torrent_metadata.clone();
+                                            let _ =
+                                                peer_tx_cloned.try_send(TorrentCommand::ClientBitfield(bitfield_clone, torrent_metadata_clone));
+                                        }
                                     }
+                                } else {
+                                    self.reject_metadata_source(&peer_id);
                                 }
                             }
                         }
                         TorrentCommand::AnnounceResponse(url, response) => {
                             self.last_activity = TorrentActivity::AnnouncingToTracker;
-                            for peer in response.peers {
-                                self.connect_to_peer(peer.ip, peer.port).await;
+                            if let Some(ip) = response.external_ip {
+                                let _ = self.manager_event_tx.try_send(ManagerEvent::ExternalIpReported {
+                                    ip,
+                                    source: IpSource::Tracker(url.clone()),
+                                });
                             }
+                            let tracker_peers = response.peers.into_iter().map(|peer| (peer.ip, peer.port)).collect();
+                            self.connect_to_peers(tracker_peers, PeerSource::Tracker).await;
 
                             if let Some(tracker) = self.trackers.get_mut(&url) {
+                                tracker.record_success();
+                                if response.tracker_id.is_some() {
+                                    tracker.tracker_id = response.tracker_id.clone();
+                                }
+
+                                // Announces carry seed/leech counts too; keep the completed
+                                // count from the last scrape since announces don't report it.
+                                if response.complete > 0 || response.incomplete > 0 {
+                                    let swarm = tracker.swarm.get_or_insert_with(SwarmCounts::default);
+                                    swarm.seeders = response.complete.clamp(0, u32::MAX as i64) as u32;
+                                    swarm.leechers = response.incomplete.clamp(0, u32::MAX as i64) as u32;
+                                }
+
                                 let seeding_interval_secs = if response.interval > 0 { (response.interval as u64) + 1 } else { FALLBACK_ANNOUNCE_INTERVAL };
                                 tracker.seeding_interval = Some(Duration::from_secs(seeding_interval_secs));
 
@@ -2342,6 +4908,15 @@ impl TorrentManager {
                                     _ => CLIENT_LEECHING_FALLBACK_INTERVAL,
                                 };
                                 tracker.leeching_interval = Some(Duration::from_secs(leeching_interval_secs));
+                                tracker.min_interval = response
+                                    .min_interval
+                                    .filter(|&min| min > 0)
+                                    .map(|min| Duration::from_secs(min as u64));
+                                // A late answer to an announce sent before pausing doesn't count;
+                                // the tracker has had 'stopped' since.
+                                if !self.is_paused {
+                                    tracker.started = true;
+                                }
 
                                 let next_interval = if self.torrent_status != TorrentStatus::Done {
                                     tracker.leeching_interval.unwrap()
@@ -2349,44 +4924,51 @@ impl TorrentManager {
                                     tracker.seeding_interval.unwrap()
                                 };
 
-                                tracker.next_announce_time = Instant::now() + next_interval;
+                                tracker.schedule_announce(Instant::now() + next_interval);
+                                let next_announce_time = tracker.next_announce_time;
                                 event!(Level::DEBUG, tracker = %url, next_announce_in_secs = next_interval.as_secs(), "Announce successful. STATUS {:?}", self.torrent_status);
+
+                                // The next announce starts over at the top tier, once this
+                                // tracker's interval is up.
+                                self.tracker_tiers.record_success(&url);
+                                if let Some(top) = self.tracker_tiers.current() {
+                                    if let Some(top_tracker) = self.trackers.get_mut(top) {
+                                        top_tracker.schedule_announce(next_announce_time);
+                                    }
+                                }
                             }
                         },
 
                         TorrentCommand::AnnounceFailed(url, error_message) => {
                             if let Some(tracker) = self.trackers.get_mut(&url) {
+                                let backoff = tracker.record_failure(error_message.clone());
+                                event!(Level::DEBUG, tracker = %url, error = %error_message, failures = tracker.failures, retry_in_secs = backoff.as_secs(), "Announce failed.");
+                            }
 
-                                let current_interval = if self.torrent_status != TorrentStatus::Done {
-                                    tracker.leeching_interval.unwrap_or(Duration::from_secs(CLIENT_LEECHING_FALLBACK_INTERVAL))
-                                } else {
-                                    tracker.seeding_interval.unwrap_or(Duration::from_secs(FALLBACK_ANNOUNCE_INTERVAL))
-                                };
-
-                                let backoff_secs = (current_interval.as_secs() * 2).min(FALLBACK_ANNOUNCE_INTERVAL * 2);
-                                let backoff_duration = Duration::from_secs(backoff_secs);
+                            // Fall through to the next tracker right away; once all have
+                            // failed, the top one is retried after its backoff.
+                            if let Some(next_url) = self.tracker_tiers.record_failure(&url) {
+                                if let Some(next_tracker) = self.trackers.get_mut(next_url) {
+                                    next_tracker.schedule_announce(Instant::now());
+                                }
+                            }
+                        },
 
-                                tracker.next_announce_time = Instant::now() + backoff_duration;
-                                event!(Level::DEBUG, tracker = %url, error = %error_message, retry_in_secs = backoff_secs, "Announce failed.");
+                        TorrentCommand::ScrapeResponse(url, counts) => {
+                            if let Some(tracker) = self.trackers.get_mut(&url) {
+                                tracker.swarm = Some(counts);
+                                event!(Level::DEBUG, tracker = %url, seeders = counts.seeders, leechers = counts.leechers, completed = counts.completed, "Scrape successful.");
                             }
                         },
 
+                        TorrentCommand::ScrapeFailed(url, error_message) => {
+                            // Counts from an earlier scrape stay up until the next one lands.
+                            event!(Level::DEBUG, tracker = %url, error = %error_message, "Scrape failed.");
+                        },
+
                         TorrentCommand::UnresponsivePeer(peer_ip_port) => {
-                            let now = Instant::now();
-
-                            let (failure_count, _) = self.timed_out_peers.get(&peer_ip_port).cloned().unwrap_or((0, now));
-                            let new_failure_count = (failure_count + 1).min(10);
-                            let backoff_duration_secs = (BASE_COOLDOWN_SECS * 2u64.pow(new_failure_count - 1))
-                                                        .min(MAX_COOLDOWN_SECS);
-                            let next_attempt_time = now + Duration::from_secs(backoff_duration_secs);
-
-                            event!(Level::DEBUG,
-                                peer = %peer_ip_port,
-                                failures = new_failure_count,
-                                cooldown_secs = backoff_duration_secs,
-                                "Peer timed out. Applying exponential backoff."
-                            );
-                            self.timed_out_peers.insert(peer_ip_port.clone(), (new_failure_count, next_attempt_time));
+                            // The backoff is applied by the disconnect that follows.
+                            self.request_holepunch(&peer_ip_port);
                         }
                         _ => {
                             println!("UNIMPLEMENTED TORRENT COMMEND {:?}",  command);
@@ -2397,3 +4979,866 @@ impl TorrentManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CreateSubfolder;
+    use crate::resource_manager::{ResourceManager, ResourceType};
+    use crate::torrent_file::Info;
+
+    const TEST_INFO_HASH_HEX: &str = "c9e15763f722f23e98a29decdfae341b98d53056";
+
+    fn magnet_test_parameters() -> (
+        TorrentParameters,
+        Sender<ManagerCommand>,
+        Receiver<ManagerEvent>,
+        broadcast::Receiver<TorrentState>,
+    ) {
+        let (_incoming_peer_tx, incoming_peer_rx) = mpsc::channel(1);
+        let (metrics_tx, metrics_rx) = broadcast::channel(16);
+        let (manager_command_tx, manager_command_rx) = mpsc::channel(8);
+        let (manager_event_tx, manager_event_rx) = mpsc::channel(16);
+
+        let mut limits = HashMap::new();
+        limits.insert(ResourceType::PeerConnection, (1, 1));
+        limits.insert(ResourceType::DiskRead, (1, 1));
+        limits.insert(ResourceType::DiskWrite, (1, 1));
+        limits.insert(ResourceType::ConnectionAttempt, (1, 1));
+        let (resource_shutdown_tx, _) = broadcast::channel(1);
+        let (resource_actor, resource_manager) = ResourceManager::new(limits, resource_shutdown_tx);
+        tokio::spawn(resource_actor.run());
+
+        #[cfg(feature = "dht")]
+        let dht_handle = {
+            let no_bootstrap: [&str; 0] = [];
+            mainline::Dht::builder()
+                .bootstrap(&no_bootstrap)
+                .port(0)
+                .build()
+                .expect("Failed to build local DHT node")
+                .as_async()
+        };
+        #[cfg(not(feature = "dht"))]
+        let dht_handle = ();
+
+        let params = TorrentParameters {
+            dht_handle,
+            dht4: None,
+            dht6: None,
+            incoming_peer_rx,
+            metrics_tx,
+            torrent_validation_status: false,
+            download_dir: std::env::temp_dir(),
+            completed_dir: None,
+            manager_command_rx,
+            manager_event_tx,
+            settings: Arc::new(Settings::default()),
+            resource_manager,
+            hash_pool: HashPool::new(1),
+            validation_permits: Arc::new(Semaphore::new(1)),
+            global_dl_bucket: Arc::new(Mutex::new(TokenBucket::new(0.0, 0.0))),
+            global_ul_bucket: Arc::new(Mutex::new(TokenBucket::new(0.0, 0.0))),
+            upload_disabled: false,
+            download_disabled: false,
+            max_download_bytes: None,
+            file_priorities: None,
+            file_renames: Vec::new(),
+            sequential: false,
+            bandwidth_priority: BandwidthPriority::Normal,
+            dht_disabled: false,
+            pex_disabled: false,
+            extra_trackers: Vec::new(),
+            extra_web_seeds: Vec::new(),
+            ban_list: BanList::new(),
+            utp_socket: None,
+        };
+        (params, manager_command_tx, manager_event_rx, metrics_rx)
+    }
+
+    #[test]
+    fn test_expected_piece_size_for_last_piece() {
+        // 40 bytes in 16 byte pieces: 16, 16, then a short last piece of 8.
+        assert_eq!(expected_piece_size(0, 16, 40), 16);
+        assert_eq!(expected_piece_size(1, 16, 40), 16);
+        assert_eq!(expected_piece_size(2, 16, 40), 8);
+        assert_eq!(expected_piece_size(3, 16, 40), 0);
+
+        // An exact multiple has a full-size last piece, not an empty extra one.
+        assert_eq!(expected_piece_size(1, 16, 32), 16);
+        assert_eq!(expected_piece_size(2, 16, 32), 0);
+
+        // A single byte past a piece boundary.
+        assert_eq!(expected_piece_size(2, 16, 33), 1);
+    }
+
+    #[test]
+    fn test_stream_priority_takes_turns_between_files() {
+        let windows = BTreeMap::from([(0, vec![0, 1, 2]), (3, vec![10, 11])]);
+        assert_eq!(stream_priority(&windows), vec![0, 10, 1, 11, 2]);
+        assert!(stream_priority(&BTreeMap::new()).is_empty());
+    }
+
+    #[test]
+    fn test_magnet_select_only() {
+        let hash = TEST_INFO_HASH_HEX;
+        assert_eq!(
+            magnet_select_only(&format!("magnet:?xt=urn:btih:{hash}&so=0,2,4-6&dn=x")),
+            Some(vec![0..=0, 2..=2, 4..=6])
+        );
+        assert_eq!(
+            magnet_select_only(&format!("magnet:?xt=urn:btih:{hash}&so=1%2C3-4")),
+            Some(vec![1..=1, 3..=4])
+        );
+        assert_eq!(
+            magnet_select_only(&format!("magnet:?xt=urn:btih:{hash}")),
+            None
+        );
+        assert_eq!(
+            magnet_select_only(&format!("magnet:?xt=urn:btih:{hash}&so=5-2,x")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_cap_piece_limit_covers_partial_piece() {
+        assert_eq!(cap_piece_limit(None, 16), None);
+        assert_eq!(cap_piece_limit(Some(0), 16), Some(0));
+        assert_eq!(cap_piece_limit(Some(32), 16), Some(2));
+        // A cap that ends inside a piece still includes that piece.
+        assert_eq!(cap_piece_limit(Some(33), 16), Some(3));
+        assert_eq!(cap_piece_limit(Some(u64::MAX), 1), Some(u32::MAX));
+    }
+
+    #[tokio::test]
+    async fn test_delete_during_metadata_fetch_removes_manager() {
+        let (params, manager_command_tx, mut manager_event_rx, _metrics_rx) =
+            magnet_test_parameters();
+        let magnet = Magnet::new(&format!("magnet:?xt=urn:btih:{}", TEST_INFO_HASH_HEX))
+            .expect("Failed to parse magnet link");
+
+        let manager = TorrentManager::from_magnet(params, magnet).unwrap();
+        assert!(manager.torrent.is_none());
+        let run_handle = tokio::spawn(manager.run(false));
+
+        manager_command_tx
+            .send(ManagerCommand::DeleteFile)
+            .await
+            .unwrap();
+
+        let event = timeout(Duration::from_secs(5), manager_event_rx.recv())
+            .await
+            .expect("Manager did not report deletion in time");
+        match event {
+            Some(ManagerEvent::DeletionComplete(info_hash, result)) => {
+                assert_eq!(info_hash, hex::decode(TEST_INFO_HASH_HEX).unwrap());
+                assert!(result.is_ok());
+            }
+            other => panic!("Expected DeletionComplete, got {:?}", other),
+        }
+
+        let run_result = timeout(Duration::from_secs(5), run_handle)
+            .await
+            .expect("Manager run loop did not exit after deletion")
+            .unwrap();
+        assert!(run_result.is_ok());
+
+        // The manager has dropped its command receiver, so nothing is left listening.
+        assert!(manager_command_tx
+            .send(ManagerCommand::Shutdown)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bad_metadata_sources_are_skipped() {
+        let (params, _manager_command_tx, _manager_event_rx, _metrics_rx) =
+            magnet_test_parameters();
+        let magnet = Magnet::new(&format!("magnet:?xt=urn:btih:{}", TEST_INFO_HASH_HEX))
+            .expect("Failed to parse magnet link");
+        let mut manager = TorrentManager::from_magnet(params, magnet).unwrap();
+
+        manager.reject_metadata_source("10.0.0.1:6881");
+        manager.reject_metadata_source("10.0.0.1:6881");
+        assert_eq!(manager.metadata_rejected_peers.len(), 1);
+        assert!(manager.is_rejected_source("10.0.0.1:6881"));
+        assert!(!manager.is_rejected_source("10.0.0.2:6881"));
+        assert_eq!(
+            manager.generate_activity_message(0, 0),
+            "Fetching metadata (rejected from 1 peers)"
+        );
+
+        manager
+            .hash_fail_strikes
+            .insert("10.0.0.2".parse().unwrap(), 3);
+        assert!(manager.is_rejected_source("10.0.0.2:6881"));
+        // Strikes follow the IP, whatever port it reconnects from
+        assert!(manager.is_rejected_source("10.0.0.2:51413"));
+    }
+
+    #[tokio::test]
+    async fn test_private_torrents_never_use_dht_or_pex() {
+        let (params, _manager_command_tx, _manager_event_rx, _metrics_rx) =
+            magnet_test_parameters();
+        let magnet = Magnet::new(&format!("magnet:?xt=urn:btih:{}", TEST_INFO_HASH_HEX))
+            .expect("Failed to parse magnet link");
+        let mut manager = TorrentManager::from_magnet(params, magnet).unwrap();
+
+        // Until the metadata arrives a magnet may use both
+        assert!(manager.pex_allowed());
+        #[cfg(feature = "dht")]
+        assert!(manager.dht_allowed());
+
+        manager.pex_disabled = true;
+        assert!(!manager.pex_allowed());
+        manager.pex_disabled = false;
+
+        manager.torrent = Some(Torrent {
+            info_dict_bencode: Vec::new(),
+            info: Info {
+                piece_length: 16384,
+                pieces: vec![0; 20],
+                private: Some(1),
+                files: Vec::new(),
+                name: "private".to_string(),
+                length: 16384,
+                md5sum: None,
+                meta_version: None,
+                file_tree: None,
+            },
+            announce: Some("http://tracker.example.org/announce".to_string()),
+            announce_list: None,
+            creation_date: None,
+            comment: None,
+            created_by: None,
+            encoding: None,
+            httpseeds: None,
+            piece_layers: None,
+            v2_pieces: Vec::new(),
+        });
+        assert!(!manager.pex_allowed());
+        #[cfg(feature = "dht")]
+        assert!(!manager.dht_allowed());
+
+        // Nor does it dial, or lift the backoff of, a peer a holepunch relay names
+        let backoff = (1, Instant::now() + Duration::from_secs(60));
+        manager
+            .timed_out_peers
+            .insert("127.0.0.1:9".to_string(), backoff);
+        manager
+            .holepunch_connect("127.0.0.1:9".parse().unwrap())
+            .await;
+        assert!(manager.peers_map.is_empty());
+        assert!(manager.timed_out_peers.contains_key("127.0.0.1:9"));
+    }
+
+    #[tokio::test]
+    async fn test_add_sources_only_adds_unknown_ones() {
+        let (params, _manager_command_tx, _manager_event_rx, _metrics_rx) =
+            magnet_test_parameters();
+        let magnet = Magnet::new(&format!(
+            "magnet:?xt=urn:btih:{}&tr=http%3A%2F%2Fa.example%2Fannounce",
+            TEST_INFO_HASH_HEX
+        ))
+        .expect("Failed to parse magnet link");
+        let mut manager = TorrentManager::from_magnet(params, magnet).unwrap();
+        assert!(manager.trackers.contains_key("http://a.example/announce"));
+
+        let (trackers, web_seeds) = manager.add_sources(
+            vec![
+                "http://a.example/announce".to_string(),
+                "http://b.example/announce".to_string(),
+                "http://b.example/announce".to_string(),
+            ],
+            vec!["http://seed.example/".to_string()],
+        );
+        assert_eq!(trackers, vec!["http://b.example/announce".to_string()]);
+        assert_eq!(web_seeds, vec!["http://seed.example/".to_string()]);
+        assert!(manager.tracker_tiers.contains("http://b.example/announce"));
+        assert!(manager.trackers.contains_key("http://b.example/announce"));
+        assert_eq!(manager.http_seeds.len(), 1);
+
+        // Adding the same again changes nothing
+        let (trackers, web_seeds) = manager.add_sources(
+            vec!["http://b.example/announce".to_string()],
+            vec!["http://seed.example/".to_string()],
+        );
+        assert!(trackers.is_empty() && web_seeds.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_hash_failures_ban_contributing_peers() {
+        let (mut params, _manager_command_tx, _manager_event_rx, _metrics_rx) =
+            magnet_test_parameters();
+        params.settings = Arc::new(Settings {
+            hash_fail_ban_threshold: 2,
+            ..Default::default()
+        });
+        let magnet = Magnet::new(&format!("magnet:?xt=urn:btih:{}", TEST_INFO_HASH_HEX))
+            .expect("Failed to parse magnet link");
+        let mut manager = TorrentManager::from_magnet(params, magnet).unwrap();
+
+        let shared = HashSet::from(["10.0.0.1:6881".to_string(), "10.0.0.2:6881".to_string()]);
+        manager.record_hash_failure(0, shared);
+        assert!(!manager.ban_list.is_peer_banned("10.0.0.1:6881"));
+        assert!(!manager.ban_list.is_peer_banned("10.0.0.2:6881"));
+
+        manager.record_hash_failure(1, HashSet::from(["10.0.0.1:6881".to_string()]));
+        assert!(manager.ban_list.is_peer_banned("10.0.0.1:6881"));
+        assert!(!manager.ban_list.is_peer_banned("10.0.0.2:6881"));
+        assert!(manager.is_rejected_source("10.0.0.1:6881"));
+    }
+
+    #[tokio::test]
+    async fn test_idle_peers_are_disconnected_down_to_minimum() {
+        let (mut params, _manager_command_tx, _manager_event_rx, _metrics_rx) =
+            magnet_test_parameters();
+        params.settings = Arc::new(Settings {
+            peer_idle_disconnect_secs: 60,
+            min_connected_peers: 2,
+            ..Default::default()
+        });
+        let magnet = Magnet::new(&format!("magnet:?xt=urn:btih:{}", TEST_INFO_HASH_HEX))
+            .expect("Failed to parse magnet link");
+        let mut manager = TorrentManager::from_magnet(params, magnet).unwrap();
+        manager.piece_manager.set_initial_fields(2, false);
+
+        // Oldest first: a's only piece is also on b, b alone has piece 1, c is busy.
+        let now = Instant::now();
+        let mut peer_rxs = HashMap::new();
+        for (address, idle_secs, pieces) in [
+            ("10.0.0.1:6881", 300, vec![0]),
+            ("10.0.0.2:6881", 200, vec![0, 1]),
+            ("10.0.0.3:6881", 0, vec![]),
+            ("10.0.0.4:6881", 100, vec![]),
+        ] {
+            let (peer_tx, peer_rx) = mpsc::channel(1);
+            let mut peer = PeerState::new(address.to_string(), peer_tx);
+            peer.bitfield = Bitfield::from_bytes(&[], 2);
+            for piece in pieces {
+                peer.bitfield.set(piece, true);
+            }
+            peer.last_transfer = now - Duration::from_secs(idle_secs);
+            manager.peers_map.insert(address.to_string(), peer);
+            peer_rxs.insert(address, peer_rx);
+        }
+        let peer_bitfields = manager.peers_map.values().map(|p| &p.bitfield);
+        manager.piece_manager.update_rarity(peer_bitfields);
+
+        manager.disconnect_idle_peers();
+
+        let disconnected: Vec<&str> = peer_rxs
+            .iter_mut()
+            .filter_map(|(address, rx)| {
+                matches!(rx.try_recv(), Ok(TorrentCommand::Disconnect(_))).then_some(*address)
+            })
+            .collect();
+        assert_eq!(disconnected.len(), 2);
+        assert!(disconnected.contains(&"10.0.0.1:6881"));
+        assert!(disconnected.contains(&"10.0.0.4:6881"));
+    }
+
+    #[tokio::test]
+    async fn test_stalled_peer_is_snubbed_and_its_pieces_released() {
+        let (params, _manager_command_tx, _manager_event_rx, _metrics_rx) =
+            magnet_test_parameters();
+        let magnet = Magnet::new(&format!("magnet:?xt=urn:btih:{}", TEST_INFO_HASH_HEX))
+            .expect("Failed to parse magnet link");
+        let mut manager = TorrentManager::from_magnet(params, magnet).unwrap();
+        manager.piece_manager.set_initial_fields(2, false);
+
+        let now = Instant::now();
+        let mut peer_rxs = HashMap::new();
+        for (address, piece, waiting_secs) in [("10.0.0.1:6881", 0, 120), ("10.0.0.2:6881", 1, 5)] {
+            let (peer_tx, peer_rx) = mpsc::channel(4);
+            let mut peer = PeerState::new(address.to_string(), peer_tx);
+            peer.pending_requests.insert(piece);
+            peer.awaiting_block_since = now - Duration::from_secs(waiting_secs);
+            manager
+                .piece_manager
+                .mark_as_pending(piece, address.to_string());
+            manager.peers_map.insert(address.to_string(), peer);
+            peer_rxs.insert(address, peer_rx);
+        }
+
+        manager.detect_snubbed_peers();
+
+        let stalled = &manager.peers_map["10.0.0.1:6881"];
+        assert!(stalled.snubbed);
+        assert!(stalled.pending_requests.is_empty());
+        assert!(manager.piece_manager.need_queue.contains(&0));
+        assert!(matches!(
+            peer_rxs.get_mut("10.0.0.1:6881").unwrap().try_recv(),
+            Ok(TorrentCommand::Cancel(0))
+        ));
+
+        let busy = &manager.peers_map["10.0.0.2:6881"];
+        assert!(!busy.snubbed);
+        assert!(busy.pending_requests.contains(&1));
+        assert!(manager.piece_manager.pending_queue.contains_key(&1));
+    }
+
+    #[tokio::test]
+    async fn test_chokes_keep_best_uploaders_and_one_optimistic_peer() {
+        let (mut params, _manager_command_tx, _manager_event_rx, _metrics_rx) =
+            magnet_test_parameters();
+        params.settings = Arc::new(Settings {
+            upload_slots: 2,
+            ..Default::default()
+        });
+        let magnet = Magnet::new(&format!("magnet:?xt=urn:btih:{}", TEST_INFO_HASH_HEX))
+            .expect("Failed to parse magnet link");
+        let mut manager = TorrentManager::from_magnet(params, magnet).unwrap();
+
+        let mut peer_rxs = HashMap::new();
+        for (address, downloaded) in [
+            ("10.0.0.1:6881", 500),
+            ("10.0.0.2:6881", 100),
+            ("10.0.0.3:6881", 900),
+            ("10.0.0.4:6881", 0),
+            ("10.0.0.5:6881", 0),
+        ] {
+            let (peer_tx, peer_rx) = mpsc::channel(4);
+            let mut peer = PeerState::new(address.to_string(), peer_tx);
+            peer.peer_is_interested_in_us = true;
+            peer.bytes_downloaded_from_peer = downloaded;
+            manager.peers_map.insert(address.to_string(), peer);
+            peer_rxs.insert(address, peer_rx);
+        }
+
+        manager.recalculate_chokes();
+
+        let unchoked: HashSet<&str> = peer_rxs
+            .iter_mut()
+            .filter_map(|(address, rx)| {
+                matches!(rx.try_recv(), Ok(TorrentCommand::PeerUnchoke)).then_some(*address)
+            })
+            .collect();
+        assert_eq!(unchoked.len(), 3);
+        assert!(unchoked.contains("10.0.0.3:6881"));
+        assert!(unchoked.contains("10.0.0.1:6881"));
+        let optimistic = manager.optimistic_unchoke_peer.clone().unwrap();
+        assert!(unchoked.contains(optimistic.as_str()));
+
+        // The optimistic unchoke holds for its interval even when it gave nothing back
+        for (address, downloaded) in [("10.0.0.1:6881", 300), ("10.0.0.3:6881", 700)] {
+            manager
+                .peers_map
+                .get_mut(address)
+                .unwrap()
+                .bytes_downloaded_from_peer = downloaded;
+        }
+        manager.recalculate_chokes();
+        assert_eq!(manager.optimistic_unchoke_peer, Some(optimistic.clone()));
+        assert_eq!(
+            manager.peers_map[&optimistic].am_choking,
+            ChokeStatus::Unchoke
+        );
+    }
+    #[test]
+    fn test_seeding_order_rotates_long_unchoked_peers() {
+        let now = Instant::now();
+        let mut peers = Vec::new();
+        for (address, uploaded, connected_secs_ago, unchoked_secs_ago) in [
+            // Fastest, but has held its slot past the rotation
+            ("10.0.0.1:6881", 900, 600, Some(120)),
+            ("10.0.0.2:6881", 500, 300, Some(20)),
+            // Still choked: the newer connection goes first
+            ("10.0.0.3:6881", 0, 400, None),
+            ("10.0.0.4:6881", 0, 5, None),
+        ] {
+            let (peer_tx, _peer_rx) = mpsc::channel(1);
+            let mut peer = PeerState::new(address.to_string(), peer_tx);
+            peer.bytes_uploaded_to_peer = uploaded;
+            peer.connected_at = now - Duration::from_secs(connected_secs_ago);
+            peer.unchoked_since = unchoked_secs_ago.map(|secs| now - Duration::from_secs(secs));
+            peers.push(peer);
+        }
+
+        let mut order: Vec<&PeerState> = peers.iter().collect();
+        sort_for_seeding(&mut order);
+        let order: Vec<&str> = order.iter().map(|p| p.ip_port.as_str()).collect();
+        assert_eq!(
+            order,
+            vec![
+                "10.0.0.2:6881",
+                "10.0.0.4:6881",
+                "10.0.0.3:6881",
+                "10.0.0.1:6881"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_peer_candidates_rank_by_source_then_freshness() {
+        let now = Instant::now();
+        let candidate = |ip: &str, source, age_secs| PeerCandidate {
+            ip: ip.to_string(),
+            port: 6881,
+            source,
+            discovered_at: now - Duration::from_secs(age_secs),
+        };
+        let old_tracker = candidate("10.0.0.1", PeerSource::Tracker, 600);
+        let new_tracker = candidate("10.0.0.2", PeerSource::Tracker, 0);
+        let pex = candidate("10.0.0.3", PeerSource::Pex, 0);
+        let saved = candidate("10.0.0.4", PeerSource::Saved, 300);
+
+        let mut ranked = vec![&pex, &old_tracker, &saved, &new_tracker];
+        rank_peer_candidates(&mut ranked, None);
+        let order: Vec<&str> = ranked.iter().map(|c| c.ip.as_str()).collect();
+        assert_eq!(order, ["10.0.0.2", "10.0.0.1", "10.0.0.4", "10.0.0.3"]);
+    }
+
+    #[tokio::test]
+    async fn test_candidates_wait_for_backoff_and_free_slots() {
+        let (params, _manager_command_tx, _manager_event_rx, _metrics_rx) =
+            magnet_test_parameters();
+        let magnet = Magnet::new(&format!("magnet:?xt=urn:btih:{}", TEST_INFO_HASH_HEX))
+            .expect("Failed to parse magnet link");
+        let mut manager = TorrentManager::from_magnet(params, magnet).unwrap();
+
+        manager.timed_out_peers.insert(
+            "10.0.0.1:6881".to_string(),
+            (1, Instant::now() + Duration::from_secs(60)),
+        );
+        manager
+            .connect_to_peers(
+                vec![
+                    ("10.0.0.1".to_string(), 6881),
+                    ("10.0.0.2".to_string(), 6881),
+                ],
+                PeerSource::Pex,
+            )
+            .await;
+        assert!(manager.peer_candidates.contains_key("10.0.0.1:6881"));
+        let dialed = &manager.peers_map["10.0.0.2:6881"];
+        assert!(dialed.connecting);
+        assert_eq!(dialed.source, PeerSource::Pex);
+
+        for i in 0..MAX_CONNECTING_PEERS {
+            let (peer_tx, _peer_rx) = mpsc::channel(1);
+            let address = format!("10.0.1.{}:6881", i);
+            let mut peer = PeerState::new(address.clone(), peer_tx);
+            peer.connecting = true;
+            manager.peers_map.insert(address, peer);
+        }
+        manager
+            .connect_to_peers(vec![("10.0.2.1".to_string(), 6881)], PeerSource::Tracker)
+            .await;
+        assert!(manager.peer_candidates.contains_key("10.0.2.1:6881"));
+        assert!(!manager.peers_map.contains_key("10.0.2.1:6881"));
+    }
+
+    #[tokio::test]
+    async fn test_have_is_only_sent_to_peers_missing_the_piece() {
+        let (params, _manager_command_tx, _manager_event_rx, _metrics_rx) =
+            magnet_test_parameters();
+        let magnet = Magnet::new(&format!("magnet:?xt=urn:btih:{}", TEST_INFO_HASH_HEX))
+            .expect("Failed to parse magnet link");
+        let mut manager = TorrentManager::from_magnet(params, magnet).unwrap();
+
+        let mut peer_rxs = HashMap::new();
+        for (address, has_piece) in [("10.0.0.1:6881", true), ("10.0.0.2:6881", false)] {
+            let (peer_tx, peer_rx) = mpsc::channel(1);
+            let mut peer = PeerState::new(address.to_string(), peer_tx);
+            peer.bitfield = Bitfield::from_bytes(&[], 2);
+            peer.bitfield.set(1, has_piece);
+            manager.peers_map.insert(address.to_string(), peer);
+            peer_rxs.insert(address, peer_rx);
+        }
+
+        manager.announce_piece(1);
+
+        assert!(peer_rxs
+            .get_mut("10.0.0.1:6881")
+            .unwrap()
+            .try_recv()
+            .is_err());
+        assert_eq!(
+            peer_rxs.get_mut("10.0.0.2:6881").unwrap().try_recv(),
+            Ok(TorrentCommand::PieceAcquired(1))
+        );
+    }
+
+    #[test]
+    fn test_pex_peer_flags() {
+        let (peer_tx, _peer_rx) = mpsc::channel(1);
+        let mut peer = PeerState::new("10.0.0.1:6881".to_string(), peer_tx);
+        peer.bitfield = Bitfield::from_bytes(&[], 2);
+        assert_eq!(pex_peer(&peer).map(|p| p.flags), Some(0));
+
+        peer.bitfield.set(0, true);
+        peer.bitfield.set(1, true);
+        peer.outgoing = true;
+        peer.supports_holepunch = true;
+        // Still dialing: not known to be connectable yet
+        assert_eq!(
+            pex_peer(&peer).map(|p| p.flags),
+            Some(PEX_SEED | PEX_SUPPORTS_HOLEPUNCH)
+        );
+
+        peer.peer_id = b"-SS1000-abcdefghijkl".to_vec();
+        assert_eq!(
+            pex_peer(&peer),
+            Some(PexPeer {
+                addr: "10.0.0.1:6881".parse().unwrap(),
+                flags: PEX_SEED | PEX_SUPPORTS_HOLEPUNCH | PEX_CONNECTABLE,
+            })
+        );
+
+        peer.ip_port = "not an address".to_string();
+        assert_eq!(pex_peer(&peer), None);
+    }
+
+    #[tokio::test]
+    async fn test_holepunch_rendezvous_is_relayed_to_both_peers() {
+        let (params, _manager_command_tx, _manager_event_rx, _metrics_rx) =
+            magnet_test_parameters();
+        let magnet = Magnet::new(&format!("magnet:?xt=urn:btih:{}", TEST_INFO_HASH_HEX))
+            .expect("Failed to parse magnet link");
+        let mut manager = TorrentManager::from_magnet(params, magnet).unwrap();
+
+        let mut peer_rxs = HashMap::new();
+        for (address, supports_holepunch) in [
+            ("10.0.0.1:6881", true),
+            ("10.0.0.2:6881", true),
+            ("10.0.0.3:6881", false),
+        ] {
+            let (peer_tx, peer_rx) = mpsc::channel(4);
+            let mut peer = PeerState::new(address.to_string(), peer_tx);
+            peer.peer_id = b"-XX0000-000000000000".to_vec();
+            peer.supports_holepunch = supports_holepunch;
+            manager.peers_map.insert(address.to_string(), peer);
+            peer_rxs.insert(address, peer_rx);
+        }
+        let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+
+        manager.relay_holepunch("10.0.0.1:6881", addr("10.0.0.2:6881"));
+        assert_eq!(
+            peer_rxs.get_mut("10.0.0.1:6881").unwrap().try_recv(),
+            Ok(TorrentCommand::SendHolepunch(HolepunchMessage::Connect(
+                addr("10.0.0.2:6881")
+            )))
+        );
+        assert_eq!(
+            peer_rxs.get_mut("10.0.0.2:6881").unwrap().try_recv(),
+            Ok(TorrentCommand::SendHolepunch(HolepunchMessage::Connect(
+                addr("10.0.0.1:6881")
+            )))
+        );
+
+        for (target, error) in [
+            ("10.0.0.3:6881", HolepunchError::NoSupport),
+            ("10.0.0.9:6881", HolepunchError::NoSuchPeer),
+            ("10.0.0.1:6881", HolepunchError::NoSuchPeer),
+        ] {
+            manager.relay_holepunch("10.0.0.1:6881", addr(target));
+            assert_eq!(
+                peer_rxs.get_mut("10.0.0.1:6881").unwrap().try_recv(),
+                Ok(TorrentCommand::SendHolepunch(HolepunchMessage::Error(
+                    addr(target),
+                    error
+                )))
+            );
+        }
+        assert!(peer_rxs
+            .get_mut("10.0.0.3:6881")
+            .unwrap()
+            .try_recv()
+            .is_err());
+    }
+
+    /// Waits for the manager to report where its files are after a SetLocation.
+    async fn files_moved(manager_event_rx: &mut Receiver<ManagerEvent>) -> Result<PathBuf, String> {
+        loop {
+            let event = timeout(Duration::from_secs(5), manager_event_rx.recv())
+                .await
+                .expect("Manager did not report the move in time");
+            match event {
+                Some(ManagerEvent::FilesMoved { result, .. }) => return result,
+                Some(_) => {}
+                None => panic!("Manager stopped before reporting the move"),
+            }
+        }
+    }
+
+    /// Writes a folder `payload` of two files into `dir` and returns a torrent of it.
+    fn payload_torrent(dir: &std::path::Path) -> Torrent {
+        std::fs::create_dir_all(dir.join("payload")).unwrap();
+        std::fs::write(dir.join("payload").join("a.txt"), vec![1u8; 40_000]).unwrap();
+        std::fs::write(dir.join("payload").join("b.txt"), vec![2u8; 30_000]).unwrap();
+        let bytes = crate::torrent_file::create::create_torrent(
+            &crate::torrent_file::create::CreateOptions {
+                path: dir.join("payload"),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        crate::torrent_file::parser::from_bytes(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_set_location_moves_or_relocates_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        let torrent = payload_torrent(&source);
+
+        let (mut params, manager_command_tx, mut manager_event_rx, _metrics_rx) =
+            magnet_test_parameters();
+        params.download_dir = source.clone();
+        params.settings = Arc::new(Settings {
+            create_subfolder: CreateSubfolder::Always,
+            ..Default::default()
+        });
+        let manager = TorrentManager::from_torrent(params, torrent).unwrap();
+        tokio::spawn(manager.run(false));
+
+        // Moving takes the files along
+        let moved = dir.path().join("moved");
+        manager_command_tx
+            .send(ManagerCommand::SetLocation {
+                path: moved.clone(),
+                move_data: true,
+                recheck: false,
+            })
+            .await
+            .unwrap();
+        assert_eq!(files_moved(&mut manager_event_rx).await, Ok(moved.clone()));
+        assert!(moved.join("payload").join("a.txt").exists());
+        assert!(!source.join("payload").join("a.txt").exists());
+
+        // Relocating onto a copy uses it where it is
+        let copy = dir.path().join("copy");
+        std::fs::create_dir_all(copy.join("payload")).unwrap();
+        for name in ["a.txt", "b.txt"] {
+            std::fs::copy(
+                moved.join("payload").join(name),
+                copy.join("payload").join(name),
+            )
+            .unwrap();
+        }
+        manager_command_tx
+            .send(ManagerCommand::SetLocation {
+                path: copy.clone(),
+                move_data: false,
+                recheck: true,
+            })
+            .await
+            .unwrap();
+        assert_eq!(files_moved(&mut manager_event_rx).await, Ok(copy.clone()));
+
+        // so deleting with data removes the copy, not the files it was moved off
+        manager_command_tx
+            .send(ManagerCommand::DeleteFile)
+            .await
+            .unwrap();
+        loop {
+            let event = timeout(Duration::from_secs(5), manager_event_rx.recv())
+                .await
+                .expect("Manager did not report deletion in time");
+            if let Some(ManagerEvent::DeletionComplete(_, result)) = event {
+                assert!(result.is_ok());
+                break;
+            }
+        }
+        assert!(!copy.join("payload").join("a.txt").exists());
+        assert!(moved.join("payload").join("a.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_closed_streams_give_up_their_priority() {
+        let dir = tempfile::tempdir().unwrap();
+        let torrent = payload_torrent(dir.path());
+        let (params, _manager_command_tx, _manager_event_rx, _metrics_rx) =
+            magnet_test_parameters();
+        let mut manager = TorrentManager::from_torrent(params, torrent).unwrap();
+        let last_piece = manager.torrent.as_ref().unwrap().piece_count() as u32 - 1;
+
+        let (reply_tx, _reply_rx) = mpsc::channel(1);
+        manager.queue_stream_read(1, 0, 0, 10, reply_tx.clone());
+        // File 1 is the pad file after a.txt
+        manager.queue_stream_read(2, 2, 29_990, 10, reply_tx);
+        assert_eq!(manager.piece_manager.priority_pieces.first(), Some(&0));
+        assert!(manager.piece_manager.priority_pieces.contains(&last_piece));
+
+        manager.end_stream(1);
+        assert!(!manager.piece_manager.priority_pieces.contains(&0));
+        assert!(manager.piece_manager.priority_pieces.contains(&last_piece));
+
+        manager.end_stream(2);
+        assert!(manager.piece_manager.priority_pieces.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pieces_failing_final_verification_are_downloaded_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let torrent = payload_torrent(dir.path());
+        let (mut params, _manager_command_tx, _manager_event_rx, _metrics_rx) =
+            magnet_test_parameters();
+        params.download_dir = dir.path().to_path_buf();
+        params.settings = Arc::new(Settings {
+            create_subfolder: CreateSubfolder::Always,
+            ..Default::default()
+        });
+        let mut manager = TorrentManager::from_torrent(params, torrent).unwrap();
+        manager.validate_local_file().await.unwrap();
+        let num_pieces = manager.piece_manager.bitfield.len();
+        assert!(manager.all_pieces_done());
+
+        let mut peer_rxs = HashMap::new();
+        for (address, is_seed) in [("10.0.0.1:6881", false), ("10.0.0.2:6881", true)] {
+            let (peer_tx, peer_rx) = mpsc::channel(4);
+            let mut peer = PeerState::new(address.to_string(), peer_tx);
+            peer.bitfield = Bitfield::from_bytes(&[], num_pieces);
+            for piece_index in 0..num_pieces {
+                peer.bitfield.set(piece_index, is_seed);
+            }
+            peer.peer_choking = ChokeStatus::Unchoke;
+            manager.peers_map.insert(address.to_string(), peer);
+            peer_rxs.insert(address, peer_rx);
+        }
+
+        manager.redownload_unverified_pieces(&[0]);
+
+        // The seed is asked for the piece again
+        assert_ne!(manager.piece_manager.bitfield[0], PieceStatus::Done);
+        assert!(matches!(
+            peer_rxs.get_mut("10.0.0.2:6881").unwrap().try_recv(),
+            Ok(TorrentCommand::RequestDownload(0, ..))
+        ));
+        // and the peer we told we had it is dropped, to be redialed
+        assert_eq!(
+            peer_rxs.get_mut("10.0.0.1:6881").unwrap().try_recv(),
+            Ok(TorrentCommand::Disconnect("10.0.0.1:6881".to_string()))
+        );
+        assert!(manager.peer_candidates.contains_key("10.0.0.1:6881"));
+    }
+
+    #[tokio::test]
+    async fn test_trusted_torrent_is_hashed_when_its_files_are_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let torrent = payload_torrent(dir.path());
+
+        for (download_dir, expect_done) in [
+            (dir.path().join("empty"), false),
+            (dir.path().to_path_buf(), true),
+        ] {
+            let (mut params, _manager_command_tx, _manager_event_rx, _metrics_rx) =
+                magnet_test_parameters();
+            params.torrent_validation_status = true;
+            params.download_dir = download_dir;
+            params.settings = Arc::new(Settings {
+                create_subfolder: CreateSubfolder::Always,
+                ..Default::default()
+            });
+            let mut manager = TorrentManager::from_torrent(params, torrent.clone()).unwrap();
+            manager.validate_local_file().await.unwrap();
+
+            let pieces = &manager.piece_manager.bitfield;
+            assert!(!pieces.is_empty());
+            assert!(pieces
+                .iter()
+                .all(|status| (*status == PieceStatus::Done) == expect_done));
+        }
+    }
+}
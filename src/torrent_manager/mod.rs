@@ -1,24 +1,27 @@
 // SPDX-FileCopyrightText: 2025 The superseedr Contributors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+pub mod files;
 pub mod manager;
 pub mod piece_manager;
 pub mod state;
 
 use crate::Settings;
 
-use crate::token_bucket::TokenBucket;
+use crate::token_bucket::{BandwidthPriority, TokenBucket};
 
 use crate::app::TorrentState;
 
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::time::Duration;
 
+use std::net::IpAddr;
+use std::ops::RangeInclusive;
 use std::path::PathBuf;
 use std::sync::Arc;
 
-use tokio::net::TcpStream;
 use tokio::sync::Mutex;
+use tokio::sync::Semaphore;
 
 use tokio::sync::broadcast;
 
@@ -26,21 +29,61 @@ use tokio::sync::broadcast;
 use mainline::async_dht::AsyncDht;
 #[cfg(not(feature = "dht"))]
 type AsyncDht = ();
-
+#[cfg(feature = "dht")]
+use crate::networking::dht_client::DhtClient;
+#[cfg(not(feature = "dht"))]
+type DhtClient = ();
+
+use crate::ban_list::BanList;
+use crate::errors::StorageError;
+use crate::external_ip::IpSource;
+use crate::hash_pool::HashPool;
+use crate::networking::utp::UtpSocket;
+use crate::networking::IncomingPeer;
 use crate::resource_manager::ResourceManagerClient;
+use crate::storage::FileRename;
+use crate::streaming::StreamFile;
+use crate::torrent_manager::files::FilePriority;
 
 pub struct TorrentParameters {
     pub dht_handle: AsyncDht,
-    pub incoming_peer_rx: Receiver<(TcpStream, Vec<u8>)>,
+    pub dht4: Option<DhtClient>,
+    pub dht6: Option<DhtClient>,
+    pub incoming_peer_rx: Receiver<IncomingPeer>,
     pub metrics_tx: broadcast::Sender<TorrentState>,
     pub torrent_validation_status: bool,
     pub download_dir: PathBuf,
+    // Where the files move once the download finishes
+    pub completed_dir: Option<PathBuf>,
     pub manager_command_rx: Receiver<ManagerCommand>,
     pub manager_event_tx: Sender<ManagerEvent>,
     pub settings: Arc<Settings>,
     pub resource_manager: ResourceManagerClient,
+    pub hash_pool: HashPool,
+    pub validation_permits: Arc<Semaphore>,
     pub global_dl_bucket: Arc<Mutex<TokenBucket>>,
     pub global_ul_bucket: Arc<Mutex<TokenBucket>>,
+    pub upload_disabled: bool,
+    pub download_disabled: bool,
+    // Only the first this many bytes of the torrent are downloaded
+    pub max_download_bytes: Option<u64>,
+    // Saved priority of each file, by index; None until the user or a magnet's so=
+    // sets one
+    pub file_priorities: Option<Vec<FilePriority>>,
+    pub file_renames: Vec<FileRename>,
+    // Download pieces in order instead of rarest first
+    pub sequential: bool,
+    // How much of the global limits it gets while other torrents want them too
+    pub bandwidth_priority: BandwidthPriority,
+    // Peer discovery the user switched off for this torrent
+    pub dht_disabled: bool,
+    pub pex_disabled: bool,
+    // Trackers and web seeds merged in from duplicates of the torrent
+    pub extra_trackers: Vec<String>,
+    pub extra_web_seeds: Vec<String>,
+    // Peers we neither accept nor connect to, shared with the app
+    pub ban_list: BanList,
+    pub utp_socket: Option<UtpSocket>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -83,6 +126,42 @@ pub enum ManagerEvent {
     BlockSent {
         info_hash: Vec<u8>,
     },
+    InsufficientDiskSpace {
+        info_hash: Vec<u8>,
+        required_bytes: u64,
+        available_bytes: u64,
+        // Found on resume rather than when the torrent was added or loaded
+        resuming: bool,
+    },
+    DownloadCapReached {
+        info_hash: Vec<u8>,
+    },
+    // A file was renamed on disk, or why it couldn't be
+    FileRenamed {
+        info_hash: Vec<u8>,
+        result: Result<FileRename, String>,
+    },
+    // The trackers and web seeds `AddSources` added; empty when all were known
+    SourcesAdded {
+        info_hash: Vec<u8>,
+        trackers: Vec<String>,
+        web_seeds: Vec<String>,
+    },
+    // Missing files were found under another root folder and are used from there
+    ExistingFilesFound {
+        info_hash: Vec<u8>,
+        renames: Vec<FileRename>,
+    },
+    // The finished download was moved to its completed folder, the new download path
+    FilesMoved {
+        info_hash: Vec<u8>,
+        result: Result<PathBuf, String>,
+    },
+    // A tracker or peer told us our external address
+    ExternalIpReported {
+        ip: IpAddr,
+        source: IpSource,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -92,7 +171,64 @@ pub enum ManagerCommand {
     Shutdown,
     DeleteFile,
     SetDataRate(u64),
+    SetUploadDisabled(bool),
+    SetDownloadDisabled(bool),
+    SetMaxDownloadBytes(Option<u64>),
+    // Folder to move the files to when the download finishes; None leaves them
+    SetCompletedDir(Option<PathBuf>),
+    // Put the torrent's files under another folder, moving them there or, without
+    // `move_data`, using what is already there; `recheck` hashes them afterwards
+    SetLocation {
+        path: PathBuf,
+        move_data: bool,
+        recheck: bool,
+    },
+    // Forget which pieces we have and hash the files on disk again
+    ForceRecheck,
+    // Announce to every tracker and query the DHT now instead of at the next interval
+    ForceReannounce,
+    // Pick pieces in order instead of rarest first
+    SetSequential(bool),
+    SetBandwidthPriority(BandwidthPriority),
+    // Stop (or start again) finding peers through the DHT or PEX; private torrents
+    // never use them either way
+    SetDhtDisabled(bool),
+    SetPexDisabled(bool),
+    // Trackers and web seeds from a duplicate of the torrent; known ones are skipped
+    AddSources {
+        trackers: Vec<String>,
+        web_seeds: Vec<String>,
+    },
+    // Give the files in these index ranges a new priority; Skip stops downloading them
+    SetFilePriority(Vec<RangeInclusive<usize>>, FilePriority),
+    // Move a file to another path, relative to the download folder
+    RenameFile {
+        index: usize,
+        path: PathBuf,
+    },
+    // Drop connected peers that have since been banned
+    DisconnectBannedPeers,
     UpdateListenPort(u16),
+    // Our external address changed; trackers and the DHT need to hear from us again
+    ExternalIpChanged(IpAddr),
+    // The uTP socket was rebound, or uTP was turned off
+    UpdateUtpSocket(Option<UtpSocket>),
+    // Streaming server: the torrent's files, empty until the metadata is known
+    StreamFiles(Sender<Vec<StreamFile>>),
+    // Streaming server: bytes of a file, sent once their pieces are on disk. Those
+    // pieces are fetched ahead of everything else while the stream is open. The
+    // reply sender is dropped if the read is out of range.
+    StreamRead {
+        stream_id: u64,
+        file_index: usize,
+        offset: u64,
+        length: usize,
+        reply_tx: Sender<Result<Vec<u8>, StorageError>>,
+    },
+    // Streaming server: a connection closed, so its pieces lose their priority
+    StreamEnded {
+        stream_id: u64,
+    },
 
     #[cfg(feature = "dht")]
     UpdateDhtHandle(AsyncDht),
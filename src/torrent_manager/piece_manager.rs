@@ -1,15 +1,20 @@
 // SPDX-FileCopyrightText: 2025 The superseedr Contributors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+use crate::bitfield::Bitfield;
+use crate::torrent_manager::files::FilePriority;
 use crate::torrent_manager::state::TorrentStatus;
 
 use rand::prelude::IndexedRandom;
 
 use tracing::{event, Level};
 
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::collections::HashSet;
 
+pub const BLOCK_SIZE: usize = 16384;
+
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub enum PieceStatus {
     Need,
@@ -30,6 +35,18 @@ pub struct PieceManager {
     pub piece_rarity: HashMap<u32, usize>,
     pub pieces_remaining: usize,
     pub piece_assemblers: HashMap<u32, PieceAssembler>,
+    // Pieces from this index on are never requested (set by a download cap)
+    pub piece_limit: Option<u32>,
+    // When set, only these pieces are downloaded (the files a magnet's so= picks)
+    pub selected_pieces: Option<HashSet<u32>>,
+    // Pieces of high and low priority files; the rest are normal. Rarest first only
+    // breaks ties within a priority.
+    pub piece_priorities: HashMap<u32, FilePriority>,
+    // Pieces the streaming server is waiting on, picked in this order before any
+    // other
+    pub priority_pieces: Vec<u32>,
+    // Within a file priority, pick the first needed piece instead of the rarest
+    pub sequential: bool,
 }
 
 impl PieceManager {
@@ -41,6 +58,11 @@ impl PieceManager {
             piece_rarity: HashMap::new(),
             pieces_remaining: 0,
             piece_assemblers: HashMap::new(),
+            piece_limit: None,
+            selected_pieces: None,
+            piece_priorities: HashMap::new(),
+            priority_pieces: Vec::new(),
+            sequential: false,
         }
     }
 
@@ -62,17 +84,30 @@ impl PieceManager {
 
     pub fn choose_piece_for_peer(
         &self,
-        peer_bitfield: &[bool],
+        peer_bitfield: &Bitfield,
         peer_pending: &HashSet<u32>,
         torrent_status: &TorrentStatus,
     ) -> Option<u32> {
+        let priority_piece = self.priority_pieces.iter().copied().find(|&piece_idx| {
+            self.bitfield.get(piece_idx as usize) == Some(&PieceStatus::Need)
+                && self.is_wanted(piece_idx)
+                && peer_bitfield.has(piece_idx as usize)
+                && !peer_pending.contains(&piece_idx)
+                && (*torrent_status == TorrentStatus::Endgame
+                    || !self.pending_queue.contains_key(&piece_idx))
+        });
+        if priority_piece.is_some() {
+            return priority_piece;
+        }
+
         if *torrent_status != TorrentStatus::Endgame {
             // --- STANDARD MODE: Rarest First ---
             self.need_queue
                 .iter()
-                .filter(|&&piece_idx| peer_bitfield.get(piece_idx as usize) == Some(&true))
+                .filter(|&&piece_idx| self.is_wanted(piece_idx))
+                .filter(|&&piece_idx| peer_bitfield.has(piece_idx as usize))
                 .filter(|&&piece_idx| !peer_pending.contains(&piece_idx))
-                .min_by_key(|&&piece_idx| self.piece_rarity.get(&piece_idx).unwrap_or(&usize::MAX))
+                .min_by_key(|&&piece_idx| self.pick_order(piece_idx))
                 .copied()
         } else {
             // --- ENDGAME MODE: Random from Pending ---
@@ -80,7 +115,8 @@ impl PieceManager {
                 .pending_queue
                 .keys()
                 .chain(self.need_queue.iter())
-                .filter(|&&piece_idx| peer_bitfield.get(piece_idx as usize) == Some(&true))
+                .filter(|&&piece_idx| self.is_wanted(piece_idx))
+                .filter(|&&piece_idx| peer_bitfield.has(piece_idx as usize))
                 .filter(|&&piece_idx| !peer_pending.contains(&piece_idx))
                 .copied()
                 .collect();
@@ -90,6 +126,67 @@ impl PieceManager {
         }
     }
 
+    /// Picks the rarest needed piece out of `candidates`, pieces the peer pointed us
+    /// at itself through its allowed fast set or suggestions (BEP 6).
+    pub fn choose_piece_from(
+        &self,
+        candidates: &HashSet<u32>,
+        peer_bitfield: &Bitfield,
+        peer_pending: &HashSet<u32>,
+    ) -> Option<u32> {
+        self.need_queue
+            .iter()
+            .filter(|&&piece_idx| candidates.contains(&piece_idx))
+            .filter(|&&piece_idx| self.is_wanted(piece_idx))
+            .filter(|&&piece_idx| peer_bitfield.has(piece_idx as usize))
+            .filter(|&&piece_idx| !peer_pending.contains(&piece_idx))
+            .min_by_key(|&&piece_idx| self.pick_order(piece_idx))
+            .copied()
+    }
+
+    /// Sort key for picking pieces outside endgame: higher file priority first, then
+    /// the rarest, or in sequential mode the first.
+    fn pick_order(&self, piece_index: u32) -> (Reverse<FilePriority>, usize) {
+        let priority = self
+            .piece_priorities
+            .get(&piece_index)
+            .copied()
+            .unwrap_or_default();
+        if self.sequential {
+            return (Reverse(priority), piece_index as usize);
+        }
+        let rarity = self
+            .piece_rarity
+            .get(&piece_index)
+            .copied()
+            .unwrap_or(usize::MAX);
+        (Reverse(priority), rarity)
+    }
+
+    /// Whether `piece_index` is inside the download cap and the file selection, if
+    /// there are any.
+    pub fn is_wanted(&self, piece_index: u32) -> bool {
+        self.piece_limit.is_none_or(|limit| piece_index < limit)
+            && self
+                .selected_pieces
+                .as_ref()
+                .is_none_or(|selected| selected.contains(&piece_index))
+    }
+
+    /// Whether every wanted piece is done while the torrent as a whole is not.
+    pub fn wanted_pieces_done(&self) -> bool {
+        self.pieces_remaining > 0
+            && self.bitfield.iter().enumerate().all(|(index, status)| {
+                *status == PieceStatus::Done || !self.is_wanted(index as u32)
+            })
+    }
+
+    /// Whether every wanted piece is done under a download cap. Always false without
+    /// a cap.
+    pub fn cap_reached(&self) -> bool {
+        self.piece_limit.is_some() && self.wanted_pieces_done()
+    }
+
     pub fn mark_as_pending(&mut self, piece_index: u32, peer_id: String) {
         self.need_queue.retain(|&p| p != piece_index);
         self.pending_queue
@@ -103,6 +200,18 @@ impl PieceManager {
         self.need_queue.push(piece_index);
     }
 
+    /// Takes `peer_id` off a pending piece it refused to send. The piece goes back to
+    /// the need queue once no other peer is fetching it.
+    pub fn release_pending(&mut self, piece_index: u32, peer_id: &str) {
+        let Some(peers) = self.pending_queue.get_mut(&piece_index) else {
+            return;
+        };
+        peers.retain(|p| p != peer_id);
+        if peers.is_empty() {
+            self.requeue_pending_to_need(piece_index);
+        }
+    }
+
     pub fn mark_as_complete(&mut self, piece_index: u32) -> Vec<String> {
         if self.bitfield.get(piece_index as usize) == Some(&PieceStatus::Done) {
             return Vec::new(); // Already complete, nothing to do.
@@ -115,6 +224,65 @@ impl PieceManager {
         self.pending_queue.remove(&piece_index).unwrap_or_default()
     }
 
+    /// Marks every piece as needed again and drops partly received ones, so a recheck
+    /// can mark what is on disk complete from scratch.
+    pub fn forget_pieces(&mut self) {
+        self.bitfield.fill(PieceStatus::Need);
+        self.need_queue = (0..self.bitfield.len() as u32).collect();
+        self.pending_queue.clear();
+        self.piece_assemblers.clear();
+        self.pieces_remaining = self.bitfield.len();
+    }
+
+    /// Puts a piece that was marked done back into the need queue, e.g. after it fails
+    /// a re-check against its hash.
+    pub fn mark_as_needed(&mut self, piece_index: u32) {
+        if self.bitfield.get(piece_index as usize) != Some(&PieceStatus::Done) {
+            return;
+        }
+
+        self.bitfield[piece_index as usize] = PieceStatus::Need;
+        self.pieces_remaining += 1;
+        self.need_queue.push(piece_index);
+    }
+
+    /// Replaces the streaming priority list, keeping the first mention of each piece.
+    pub fn set_priority_pieces(&mut self, pieces: impl IntoIterator<Item = u32>) {
+        let mut seen = HashSet::new();
+        self.priority_pieces = pieces
+            .into_iter()
+            .filter(|piece_idx| seen.insert(*piece_idx))
+            .collect();
+    }
+
+    /// Offsets of the blocks of `piece_index` that have arrived so far.
+    pub fn received_block_offsets(&self, piece_index: u32) -> Vec<u32> {
+        self.piece_assemblers
+            .get(&piece_index)
+            .map(|assembler| assembler.received_blocks.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Blocks still missing from wanted pieces. Pieces nothing has arrived for yet
+    /// count as `blocks_per_piece`.
+    pub fn remaining_blocks(&self, blocks_per_piece: usize) -> usize {
+        self.bitfield
+            .iter()
+            .enumerate()
+            .filter(|(index, status)| {
+                **status == PieceStatus::Need && self.is_wanted(*index as u32)
+            })
+            .map(
+                |(index, _)| match self.piece_assemblers.get(&(index as u32)) {
+                    Some(assembler) => assembler
+                        .total_blocks
+                        .saturating_sub(assembler.received_blocks.len()),
+                    None => blocks_per_piece,
+                },
+            )
+            .sum()
+    }
+
     pub fn reset_piece_assembly(&mut self, piece_index: u32) {
         // Simply remove the assembler. The next block to arrive for this piece
         // will trigger the creation of a new, clean assembler.
@@ -126,9 +294,70 @@ impl PieceManager {
         );
     }
 
+    /// The blocks of partly received pieces, so they survive a restart. Each piece is
+    /// written as its index, size and block count, then every block as its offset,
+    /// length and data, all numbers as big-endian u32s.
+    pub fn encode_partial_pieces(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for (piece_index, assembler) in &self.piece_assemblers {
+            bytes.extend_from_slice(&piece_index.to_be_bytes());
+            bytes.extend_from_slice(&(assembler.buffer.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(&(assembler.received_blocks.len() as u32).to_be_bytes());
+            for &block_offset in &assembler.received_blocks {
+                let start = block_offset as usize;
+                let end = std::cmp::min(start + BLOCK_SIZE, assembler.buffer.len());
+                bytes.extend_from_slice(&block_offset.to_be_bytes());
+                bytes.extend_from_slice(&((end - start) as u32).to_be_bytes());
+                bytes.extend_from_slice(&assembler.buffer[start..end]);
+            }
+        }
+        bytes
+    }
+
+    /// Puts back blocks saved by `encode_partial_pieces` for pieces that are still
+    /// needed and whose size, as `piece_size` gives it, hasn't changed. Stops at the
+    /// first malformed record. Returns how many blocks were restored.
+    pub fn restore_partial_pieces(
+        &mut self,
+        mut bytes: &[u8],
+        piece_size: impl Fn(u32) -> usize,
+    ) -> usize {
+        fn take_u32(bytes: &mut &[u8]) -> Option<u32> {
+            let (number, rest) = bytes.split_first_chunk::<4>()?;
+            *bytes = rest;
+            Some(u32::from_be_bytes(*number))
+        }
+
+        let mut restored = 0;
+        while let (Some(piece_index), Some(saved_size), Some(block_count)) = (
+            take_u32(&mut bytes),
+            take_u32(&mut bytes),
+            take_u32(&mut bytes),
+        ) {
+            let wanted = self.bitfield.get(piece_index as usize) == Some(&PieceStatus::Need)
+                && piece_size(piece_index) == saved_size as usize;
+            for _ in 0..block_count {
+                let (Some(block_offset), Some(block_len)) =
+                    (take_u32(&mut bytes), take_u32(&mut bytes))
+                else {
+                    return restored;
+                };
+                let Some((block_data, rest)) = bytes.split_at_checked(block_len as usize) else {
+                    return restored;
+                };
+                bytes = rest;
+                if wanted {
+                    self.handle_block(piece_index, block_offset, block_data, saved_size as usize);
+                    restored += 1;
+                }
+            }
+        }
+        restored
+    }
+
     pub fn update_rarity<'a, I>(&mut self, all_peer_bitfields: I)
     where
-        I: Iterator<Item = &'a Vec<bool>> + Clone, // Clone is needed because we iterate multiple times
+        I: Iterator<Item = &'a Bitfield> + Clone, // Clone is needed because we iterate multiple times
     {
         self.piece_rarity.clear();
         let pieces_to_check: Vec<u32> = self
@@ -141,7 +370,7 @@ impl PieceManager {
         for piece_idx in pieces_to_check {
             let count = all_peer_bitfields
                 .clone() // This is a cheap clone of the iterator, not the data
-                .filter(|p_bitfield| p_bitfield.get(piece_idx as usize) == Some(&true))
+                .filter(|p_bitfield| p_bitfield.has(piece_idx as usize))
                 .count();
             self.piece_rarity.insert(piece_idx, count);
         }
@@ -156,7 +385,7 @@ impl PieceManager {
     ) -> Option<Vec<u8>> {
         // Get or create the assembler for this piece
         let assembler = self.piece_assemblers.entry(piece_index).or_insert_with(|| {
-            let total_blocks = (piece_size as f64 / 16384.0).ceil() as usize;
+            let total_blocks = piece_size.div_ceil(BLOCK_SIZE);
             PieceAssembler {
                 buffer: vec![0; piece_size],
                 received_blocks: HashSet::new(),
@@ -283,6 +512,95 @@ mod tests {
         assert_eq!(pm.pieces_remaining, 3); // No change
     }
 
+    #[test]
+    fn test_release_pending_requeues_when_no_peer_left() {
+        let mut pm = setup_manager(3);
+        pm.mark_as_pending(1, "peer_A".to_string());
+        pm.mark_as_pending(1, "peer_B".to_string());
+
+        pm.release_pending(1, "peer_A");
+        assert_eq!(
+            pm.pending_queue.get(&1).unwrap(),
+            &vec!["peer_B".to_string()]
+        );
+        assert!(!pm.need_queue.contains(&1));
+
+        pm.release_pending(1, "peer_B");
+        assert!(!pm.pending_queue.contains_key(&1));
+        assert!(pm.need_queue.contains(&1));
+
+        // Releasing a piece that isn't pending does nothing.
+        pm.release_pending(1, "peer_B");
+        assert_eq!(pm.need_queue.iter().filter(|&&p| p == 1).count(), 1);
+    }
+
+    #[test]
+    fn test_choose_piece_from_candidates() {
+        let mut pm = setup_manager(4);
+        pm.piece_rarity.insert(2, 5);
+        pm.piece_rarity.insert(3, 1);
+        let peer_bitfield: Bitfield = [true, true, true, true].into_iter().collect();
+        let candidates: HashSet<u32> = [2, 3].into_iter().collect();
+
+        assert_eq!(
+            pm.choose_piece_from(&candidates, &peer_bitfield, &HashSet::new()),
+            Some(3)
+        );
+
+        let pending: HashSet<u32> = [3].into_iter().collect();
+        assert_eq!(
+            pm.choose_piece_from(&candidates, &peer_bitfield, &pending),
+            Some(2)
+        );
+
+        pm.mark_as_complete(2);
+        assert_eq!(
+            pm.choose_piece_from(&candidates, &peer_bitfield, &pending),
+            None
+        );
+    }
+
+    #[test]
+    fn test_mark_as_needed_after_completion() {
+        let mut pm = PieceManager::new();
+        pm.set_initial_fields(3, true);
+        assert_eq!(pm.pieces_remaining, 0);
+
+        pm.mark_as_needed(1);
+        assert_eq!(pm.bitfield[1], PieceStatus::Need);
+        assert_eq!(pm.pieces_remaining, 1);
+        assert_eq!(pm.need_queue, vec![1]);
+
+        // Pieces that are already needed are left alone
+        pm.mark_as_needed(1);
+        assert_eq!(pm.pieces_remaining, 1);
+        assert_eq!(pm.need_queue, vec![1]);
+
+        // Out of range pieces are ignored
+        pm.mark_as_needed(10);
+        assert_eq!(pm.pieces_remaining, 1);
+    }
+
+    #[test]
+    fn test_forget_pieces() {
+        let mut pm = PieceManager::new();
+        pm.set_initial_fields(4, false);
+        pm.mark_as_complete(0);
+        pm.mark_as_complete(3);
+        pm.mark_as_pending(1, "peer1".to_string());
+
+        pm.forget_pieces();
+        assert!(pm.bitfield.iter().all(|s| *s == PieceStatus::Need));
+        assert_eq!(pm.need_queue, vec![0, 1, 2, 3]);
+        assert!(pm.pending_queue.is_empty());
+        assert_eq!(pm.pieces_remaining, 4);
+
+        // Validation marks what it finds on disk as before
+        pm.mark_as_complete(2);
+        assert_eq!(pm.pieces_remaining, 3);
+        assert_eq!(pm.need_queue, vec![0, 1, 3]);
+    }
+
     #[test]
     fn test_piece_assembly_and_reset() {
         let mut pm = PieceManager::new();
@@ -330,8 +648,8 @@ mod tests {
         pm.mark_as_complete(0); // need = [1, 3], pending = [2], done = [0]
                                 // Pieces to check: 1, 3, 2
 
-        let peer1_bitfield = vec![true, true, false, true]; // Has 0, 1, 3
-        let peer2_bitfield = vec![true, false, true, true]; // Has 0, 2, 3
+        let peer1_bitfield: Bitfield = [true, true, false, true].into_iter().collect(); // Has 0, 1, 3
+        let peer2_bitfield: Bitfield = [true, false, true, true].into_iter().collect(); // Has 0, 2, 3
         let peer_bitfields = vec![peer1_bitfield, peer2_bitfield];
 
         pm.update_rarity(peer_bitfields.iter());
@@ -357,7 +675,7 @@ mod tests {
         pm.piece_rarity.insert(3, 5);
         pm.piece_rarity.insert(4, 2);
 
-        let peer_bitfield = vec![true, true, true, true, false]; // Has 0, 1, 2, 3
+        let peer_bitfield: Bitfield = [true, true, true, true, false].into_iter().collect(); // Has 0, 1, 2, 3
         let mut peer_pending = HashSet::new();
         let status = TorrentStatus::Standard;
 
@@ -390,18 +708,47 @@ mod tests {
         assert_eq!(choice, None);
 
         // 4. Peer has nothing we need
-        let empty_peer_bitfield = vec![false; 5];
+        let empty_peer_bitfield = Bitfield::from_bytes(&[], 5);
         let choice = pm.choose_piece_for_peer(&empty_peer_bitfield, &peer_pending, &status);
         assert_eq!(choice, None);
     }
 
+    #[test]
+    fn test_piece_limit_caps_choices() {
+        let mut pm = setup_manager(4); // need = [0, 1, 2, 3]
+        pm.piece_limit = Some(2);
+        pm.piece_rarity.insert(0, 5);
+        pm.piece_rarity.insert(1, 5);
+        pm.piece_rarity.insert(3, 1); // Rarest, but past the cap
+
+        let peer_bitfield: Bitfield = [true, true, true, true].into_iter().collect();
+        let mut peer_pending = HashSet::new();
+        let choice =
+            pm.choose_piece_for_peer(&peer_bitfield, &peer_pending, &TorrentStatus::Standard);
+        assert!(choice == Some(0) || choice == Some(1));
+
+        peer_pending.insert(0);
+        peer_pending.insert(1);
+        let choice =
+            pm.choose_piece_for_peer(&peer_bitfield, &peer_pending, &TorrentStatus::Endgame);
+        assert_eq!(choice, None);
+
+        assert!(!pm.cap_reached());
+        pm.mark_as_complete(0);
+        pm.mark_as_complete(1);
+        assert!(pm.cap_reached());
+
+        pm.piece_limit = None;
+        assert!(!pm.cap_reached());
+    }
+
     #[test]
     fn test_choose_piece_endgame_mode_prioritizes_pending() {
         let mut pm = setup_manager(5); // need = [0, 1, 2, 3, 4]
         pm.mark_as_pending(1, "peer_A".to_string()); // need = [0, 2, 3, 4], pending = [1]
         pm.mark_as_pending(2, "peer_B".to_string()); // need = [0, 3, 4], pending = [1, 2]
 
-        let peer_bitfield = vec![true, true, true, true, false]; // Has 0, 1, 2, 3
+        let peer_bitfield: Bitfield = [true, true, true, true, false].into_iter().collect(); // Has 0, 1, 2, 3
         let peer_pending = HashSet::new();
         let status = TorrentStatus::Endgame;
 
@@ -426,7 +773,7 @@ mod tests {
         pm.mark_as_pending(1, "peer_A".to_string()); // need = [0, 2, 3, 4], pending = [1]
         pm.mark_as_pending(2, "peer_B".to_string()); // need = [0, 3, 4], pending = [1, 2]
 
-        let peer_bitfield = vec![true, true, true, true, false]; // Has 0, 1, 2, 3
+        let peer_bitfield: Bitfield = [true, true, true, true, false].into_iter().collect(); // Has 0, 1, 2, 3
         let mut peer_pending = HashSet::new();
         peer_pending.insert(1); // Peer is already downloading piece 1
         let status = TorrentStatus::Endgame;
@@ -591,4 +938,178 @@ mod tests {
         assert_eq!(full_piece, correct_block_data); // Ensure only correct data was stored
         assert!(!pm.piece_assemblers.contains_key(&piece_index));
     }
+
+    #[test]
+    fn test_remaining_blocks_counts_partial_pieces() {
+        let mut pm = PieceManager::new();
+        pm.set_initial_fields(3, false);
+        let piece_size = 4 * BLOCK_SIZE;
+        assert_eq!(pm.remaining_blocks(4), 12);
+
+        pm.handle_block(1, 0, &vec![0; BLOCK_SIZE], piece_size);
+        pm.handle_block(1, 2 * BLOCK_SIZE as u32, &vec![0; BLOCK_SIZE], piece_size);
+        assert_eq!(pm.remaining_blocks(4), 10);
+        let mut offsets = pm.received_block_offsets(1);
+        offsets.sort();
+        assert_eq!(offsets, vec![0, 2 * BLOCK_SIZE as u32]);
+
+        pm.mark_as_complete(0);
+        pm.piece_limit = Some(2);
+        assert_eq!(pm.remaining_blocks(4), 2);
+    }
+
+    #[test]
+    fn test_partial_pieces_round_trip() {
+        let mut pm = PieceManager::new();
+        pm.set_initial_fields(4, false);
+        let piece_size = 3 * BLOCK_SIZE;
+        pm.handle_block(1, 0, &vec![1; BLOCK_SIZE], piece_size);
+        pm.handle_block(1, 2 * BLOCK_SIZE as u32, &vec![3; BLOCK_SIZE], piece_size);
+        pm.handle_block(2, BLOCK_SIZE as u32, &vec![7; BLOCK_SIZE], piece_size);
+        pm.handle_block(3, 0, &vec![9; BLOCK_SIZE], piece_size);
+        let bytes = pm.encode_partial_pieces();
+
+        let mut restored = PieceManager::new();
+        restored.set_initial_fields(4, false);
+        restored.mark_as_complete(2);
+        // Piece 3 comes out another size than saved, so its block is dropped
+        let restored_blocks = restored.restore_partial_pieces(&bytes, |index| match index {
+            3 => BLOCK_SIZE + 50,
+            _ => piece_size,
+        });
+        assert_eq!(restored_blocks, 2);
+        assert!(!restored.piece_assemblers.contains_key(&2));
+        assert!(!restored.piece_assemblers.contains_key(&3));
+        let mut offsets = restored.received_block_offsets(1);
+        offsets.sort();
+        assert_eq!(offsets, vec![0, 2 * BLOCK_SIZE as u32]);
+
+        // The missing block completes the piece with the restored data around it
+        let piece = restored
+            .handle_block(1, BLOCK_SIZE as u32, &vec![2; BLOCK_SIZE], piece_size)
+            .unwrap();
+        assert_eq!(&piece[..BLOCK_SIZE], &vec![1; BLOCK_SIZE][..]);
+        assert_eq!(&piece[2 * BLOCK_SIZE..], &vec![3; BLOCK_SIZE][..]);
+    }
+
+    #[test]
+    fn test_restore_partial_pieces_stops_at_truncated_data() {
+        let mut pm = PieceManager::new();
+        pm.set_initial_fields(2, false);
+        pm.handle_block(0, 0, &vec![1; BLOCK_SIZE], 2 * BLOCK_SIZE);
+        let bytes = pm.encode_partial_pieces();
+
+        let mut restored = PieceManager::new();
+        restored.set_initial_fields(2, false);
+        let truncated = &bytes[..bytes.len() - 1];
+        assert_eq!(
+            restored.restore_partial_pieces(truncated, |_| 2 * BLOCK_SIZE),
+            0
+        );
+        assert_eq!(restored.restore_partial_pieces(&[1, 2, 3], |_| 0), 0);
+        assert!(restored.piece_assemblers.is_empty());
+    }
+
+    #[test]
+    fn test_selected_pieces_limit_wanted_pieces() {
+        let mut pm = PieceManager::new();
+        pm.set_initial_fields(4, false);
+        pm.selected_pieces = Some(HashSet::from([1, 2]));
+        let peer_bitfield = Bitfield::from_bytes(&[0b1111_0000], 4);
+        let no_pending = HashSet::new();
+
+        assert!(!pm.is_wanted(0));
+        assert!(pm.is_wanted(2));
+        for _ in 0..2 {
+            let piece = pm
+                .choose_piece_for_peer(&peer_bitfield, &no_pending, &TorrentStatus::Standard)
+                .unwrap();
+            assert!(piece == 1 || piece == 2);
+            pm.mark_as_complete(piece);
+        }
+        assert_eq!(
+            pm.choose_piece_for_peer(&peer_bitfield, &no_pending, &TorrentStatus::Standard),
+            None
+        );
+        assert!(pm.wanted_pieces_done());
+        // Without a download cap this isn't a capped torrent
+        assert!(!pm.cap_reached());
+    }
+
+    #[test]
+    fn test_higher_priority_files_are_picked_before_rarer_pieces() {
+        let mut pm = PieceManager::new();
+        pm.set_initial_fields(4, false);
+        let peer_bitfield = Bitfield::from_bytes(&[0b1111_0000], 4);
+        let no_pending = HashSet::new();
+        pm.piece_rarity.extend([(0, 1), (1, 5), (2, 9), (3, 2)]);
+        pm.piece_priorities = HashMap::from([(0, FilePriority::Low), (2, FilePriority::High)]);
+
+        let mut order = Vec::new();
+        while let Some(piece) =
+            pm.choose_piece_for_peer(&peer_bitfield, &no_pending, &TorrentStatus::Standard)
+        {
+            order.push(piece);
+            pm.mark_as_complete(piece);
+        }
+        assert_eq!(order, vec![2, 3, 1, 0]);
+    }
+
+    #[test]
+    fn test_sequential_mode_picks_pieces_in_order() {
+        let mut pm = PieceManager::new();
+        pm.set_initial_fields(5, false);
+        pm.sequential = true;
+        let peer_bitfield = Bitfield::from_bytes(&[0b1111_1000], 5);
+        let no_pending = HashSet::new();
+        pm.piece_rarity
+            .extend([(0, 9), (1, 1), (2, 5), (3, 2), (4, 7)]);
+        pm.piece_priorities = HashMap::from([(3, FilePriority::High)]);
+
+        let mut order = Vec::new();
+        while let Some(piece) =
+            pm.choose_piece_for_peer(&peer_bitfield, &no_pending, &TorrentStatus::Standard)
+        {
+            order.push(piece);
+            pm.mark_as_complete(piece);
+        }
+        // File priorities still come first
+        assert_eq!(order, vec![3, 0, 1, 2, 4]);
+    }
+
+    #[test]
+    fn test_priority_pieces_are_picked_first() {
+        let mut pm = PieceManager::new();
+        pm.set_initial_fields(10, false);
+        let peer_bitfield = Bitfield::from_bytes(&[0b1111_1111, 0b1100_0000], 10);
+        let no_pending = HashSet::new();
+        pm.piece_rarity.insert(0, 1);
+
+        pm.set_priority_pieces([7, 9, 7, 0]);
+        assert_eq!(pm.priority_pieces, vec![7, 9, 0]);
+        assert_eq!(
+            pm.choose_piece_for_peer(&peer_bitfield, &no_pending, &TorrentStatus::Standard),
+            Some(7)
+        );
+
+        // Pieces another peer is already fetching are skipped outside endgame
+        pm.mark_as_pending(7, "peer_a".to_string());
+        assert_eq!(
+            pm.choose_piece_for_peer(&peer_bitfield, &no_pending, &TorrentStatus::Standard),
+            Some(9)
+        );
+        assert_eq!(
+            pm.choose_piece_for_peer(&peer_bitfield, &no_pending, &TorrentStatus::Endgame),
+            Some(7)
+        );
+
+        // Done pieces fall through to rarest first
+        pm.mark_as_complete(7);
+        pm.mark_as_complete(9);
+        pm.mark_as_complete(0);
+        pm.set_priority_pieces([7]);
+        assert!(pm
+            .choose_piece_for_peer(&peer_bitfield, &no_pending, &TorrentStatus::Standard)
+            .is_some_and(|piece| piece != 7));
+    }
 }
@@ -1,7 +1,10 @@
 // SPDX-FileCopyrightText: 2025 The superseedr Contributors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+use crate::bitfield::Bitfield;
 use crate::command::TorrentCommand;
+use crate::tracker::client::supports_scrape;
+use crate::tracker::SwarmCounts;
 
 use std::time::Duration;
 use std::time::Instant;
@@ -19,8 +22,108 @@ const PEER_UPLOAD_IN_FLIGHT_LIMIT: usize = 4;
 #[derive(Debug)]
 pub struct TrackerState {
     pub next_announce_time: Instant,
+    pub last_announce_time: Option<Instant>,
     pub leeching_interval: Option<Duration>,
     pub seeding_interval: Option<Duration>,
+    // The tracker's "min interval"; regular announces never come sooner than this
+    pub min_interval: Option<Duration>,
+    pub tracker_id: Option<String>,
+    // Whether the tracker has had 'started' since we last sent it 'stopped'
+    pub started: bool,
+    // None when the tracker can't be scraped
+    pub next_scrape_time: Option<Instant>,
+    pub swarm: Option<SwarmCounts>,
+    // Announces failed in a row, and why the last one did; cleared by a response
+    pub failures: u32,
+    pub last_error: Option<String>,
+}
+
+// Wait after an announce fails, doubled for each failure in a row
+const TRACKER_RETRY_BASE: Duration = Duration::from_secs(60);
+const TRACKER_RETRY_MAX: Duration = Duration::from_secs(3600);
+
+impl TrackerState {
+    pub fn new(url: &str) -> Self {
+        Self {
+            next_announce_time: Instant::now(),
+            last_announce_time: None,
+            leeching_interval: None,
+            seeding_interval: None,
+            min_interval: None,
+            tracker_id: None,
+            started: false,
+            next_scrape_time: supports_scrape(url).then(Instant::now),
+            swarm: None,
+            failures: 0,
+            last_error: None,
+        }
+    }
+
+    /// Backs off after a failed announce and returns how long until the retry.
+    pub fn record_failure(&mut self, error: String) -> Duration {
+        self.failures = self.failures.saturating_add(1);
+        self.last_error = Some(error);
+        let backoff = TRACKER_RETRY_BASE
+            .saturating_mul(1 << (self.failures - 1).min(6))
+            .min(TRACKER_RETRY_MAX);
+        let now = Instant::now();
+        self.schedule_announce(now + backoff);
+        self.next_announce_time.saturating_duration_since(now)
+    }
+
+    pub fn record_success(&mut self) {
+        self.failures = 0;
+        self.last_error = None;
+    }
+
+    /// Schedules the next regular announce for `at`, or for when the tracker's
+    /// min interval since the last announce is up if that is later.
+    pub fn schedule_announce(&mut self, at: Instant) {
+        self.next_announce_time = match (self.last_announce_time, self.min_interval) {
+            (Some(last), Some(min_interval)) => at.max(last + min_interval),
+            _ => at,
+        };
+    }
+}
+
+// Wait after an HTTP seed fails, doubled for each failure in a row
+const HTTP_SEED_RETRY_BASE: Duration = Duration::from_secs(30);
+const HTTP_SEED_RETRY_MAX: Duration = Duration::from_secs(3600);
+
+/// An HTTP seed (BEP 17) from the torrent file.
+#[derive(Debug)]
+pub struct HttpSeedState {
+    pub url: String,
+    pub piece_in_flight: Option<u32>,
+    pub retry_at: Instant,
+    pub failures: u32,
+}
+
+impl HttpSeedState {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            piece_in_flight: None,
+            retry_at: Instant::now(),
+            failures: 0,
+        }
+    }
+
+    /// Whether the seed can be asked for a piece now.
+    pub fn is_ready(&self, now: Instant) -> bool {
+        self.piece_in_flight.is_none() && now >= self.retry_at
+    }
+
+    /// Backs off after a failed request, for as long as the seed asked if it said.
+    pub fn record_failure(&mut self, retry_after: Option<Duration>) {
+        self.failures = self.failures.saturating_add(1);
+        let backoff = HTTP_SEED_RETRY_BASE.saturating_mul(1 << (self.failures - 1).min(7));
+        self.retry_at = Instant::now() + retry_after.unwrap_or(backoff).min(HTTP_SEED_RETRY_MAX);
+    }
+
+    pub fn record_success(&mut self) {
+        self.failures = 0;
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -51,10 +154,50 @@ pub enum ChokeStatus {
     Pending,
 }
 
+/// Where we heard of a peer. Candidates are dialed in this order: trackers list
+/// peers that announced recently, while PEX lists are second hand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum PeerSource {
+    Tracker,
+    // Connected before the torrent was last paused or shut down
+    Saved,
+    #[cfg(feature = "dht")]
+    Dht,
+    Pex,
+    // A relay introduced us (BEP 55)
+    Holepunch,
+    #[default]
+    Incoming,
+}
+
+impl PeerSource {
+    pub fn label(self) -> &'static str {
+        match self {
+            PeerSource::Tracker => "TRK",
+            PeerSource::Saved => "SAV",
+            #[cfg(feature = "dht")]
+            PeerSource::Dht => "DHT",
+            PeerSource::Pex => "PEX",
+            PeerSource::Holepunch => "HP",
+            PeerSource::Incoming => "IN",
+        }
+    }
+}
+
+/// A peer we know of but haven't dialed yet.
+#[derive(Debug, Clone)]
+pub struct PeerCandidate {
+    pub ip: String,
+    pub port: u16,
+    pub source: PeerSource,
+    // When a source last listed the peer
+    pub discovered_at: Instant,
+}
+
 pub struct PeerState {
     pub ip_port: String,
     pub peer_id: Vec<u8>,
-    pub bitfield: Vec<bool>,
+    pub bitfield: Bitfield,
     pub am_choking: ChokeStatus,
     pub peer_choking: ChokeStatus,
     pub peer_tx: Sender<TorrentCommand>,
@@ -74,6 +217,33 @@ pub struct PeerState {
     pub upload_slots_semaphore: Arc<Semaphore>,
     pub last_action: TorrentCommand,
     pub action_counts: HashMap<Discriminant<TorrentCommand>, u64>,
+    // When a block last went either way; starts at connect time
+    pub last_transfer: Instant,
+    pub connected_at: Instant,
+    // When we last unchoked the peer, while it stays unchoked
+    pub unchoked_since: Option<Instant>,
+    // Requests went unanswered too long; the peer gets one piece at a time until it
+    // sends a block again
+    pub snubbed: bool,
+    // When the peer last sent a block, or was given work while it had none
+    pub awaiting_block_since: Instant,
+    // The peer speaks the fast extension (BEP 6), so refused requests get a reject
+    pub supports_fast: bool,
+    // Sent have-all before we knew how many pieces the torrent has
+    pub has_all: bool,
+    // Pieces the peer lets us download while it chokes us
+    pub allowed_fast: HashSet<u32>,
+    // Pieces we let the peer download while we choke it
+    pub allowed_fast_for_peer: HashSet<u32>,
+    // Pieces the peer suggested we download from it
+    pub suggested_pieces: HashSet<u32>,
+    // The peer can relay holepunch rendezvous for us, or be introduced to others (BEP 55)
+    pub supports_holepunch: bool,
+    // We dialed this peer, so other peers can reach it at this address too
+    pub outgoing: bool,
+    pub source: PeerSource,
+    // Dialed, but the handshake hasn't finished yet
+    pub connecting: bool,
 }
 
 impl PeerState {
@@ -81,7 +251,7 @@ impl PeerState {
         Self {
             ip_port,
             peer_id: Vec::new(),
-            bitfield: Vec::new(),
+            bitfield: Bitfield::default(),
             am_choking: ChokeStatus::Choke,
             peer_choking: ChokeStatus::Choke,
             peer_tx,
@@ -101,6 +271,97 @@ impl PeerState {
             upload_slots_semaphore: Arc::new(Semaphore::new(PEER_UPLOAD_IN_FLIGHT_LIMIT)),
             last_action: TorrentCommand::SuccessfullyConnected(String::new()),
             action_counts: HashMap::new(),
+            last_transfer: Instant::now(),
+            connected_at: Instant::now(),
+            unchoked_since: None,
+            snubbed: false,
+            awaiting_block_since: Instant::now(),
+            supports_fast: false,
+            has_all: false,
+            allowed_fast: HashSet::new(),
+            allowed_fast_for_peer: HashSet::new(),
+            suggested_pieces: HashSet::new(),
+            supports_holepunch: false,
+            outgoing: false,
+            source: PeerSource::Incoming,
+            connecting: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "http://tracker.example/announce";
+
+    #[test]
+    fn test_early_announce_waits_for_min_interval() {
+        let mut tracker = TrackerState::new(URL);
+        let last = Instant::now();
+        tracker.last_announce_time = Some(last);
+        tracker.min_interval = Some(Duration::from_secs(300));
+
+        tracker.schedule_announce(last + Duration::from_secs(10));
+        assert_eq!(tracker.next_announce_time, last + Duration::from_secs(300));
+
+        // Later than the min interval is left alone.
+        tracker.schedule_announce(last + Duration::from_secs(900));
+        assert_eq!(tracker.next_announce_time, last + Duration::from_secs(900));
+    }
+
+    #[test]
+    fn test_announce_without_min_interval_is_not_clamped() {
+        let mut tracker = TrackerState::new(URL);
+        let at = Instant::now() + Duration::from_secs(5);
+        tracker.schedule_announce(at);
+        assert_eq!(tracker.next_announce_time, at);
+
+        // A min interval alone means nothing until there is a last announce.
+        tracker.min_interval = Some(Duration::from_secs(300));
+        tracker.schedule_announce(at);
+        assert_eq!(tracker.next_announce_time, at);
+    }
+
+    #[test]
+    fn test_tracker_failures_back_off_exponentially_up_to_the_cap() {
+        let mut tracker = TrackerState::new(URL);
+
+        assert_eq!(tracker.record_failure("a".into()), Duration::from_secs(60));
+        assert_eq!(tracker.record_failure("b".into()), Duration::from_secs(120));
+        assert_eq!(tracker.record_failure("c".into()), Duration::from_secs(240));
+        assert_eq!(tracker.failures, 3);
+        assert_eq!(tracker.last_error.as_deref(), Some("c"));
+
+        for _ in 0..10 {
+            tracker.record_failure("d".into());
         }
+        assert_eq!(tracker.record_failure("e".into()), TRACKER_RETRY_MAX);
+    }
+
+    #[test]
+    fn test_tracker_success_resets_the_backoff() {
+        let mut tracker = TrackerState::new(URL);
+        tracker.record_failure("a".into());
+        tracker.record_failure("b".into());
+
+        tracker.record_success();
+        assert_eq!(tracker.failures, 0);
+        assert_eq!(tracker.last_error, None);
+        assert_eq!(tracker.record_failure("c".into()), TRACKER_RETRY_BASE);
+    }
+
+    #[test]
+    fn test_tracker_failure_retries_no_sooner_than_min_interval() {
+        let mut tracker = TrackerState::new(URL);
+        tracker.last_announce_time = Some(Instant::now());
+        tracker.min_interval = Some(Duration::from_secs(600));
+
+        let retry_in = tracker.record_failure("a".into());
+        assert!(retry_in > Duration::from_secs(590));
+        assert!(
+            tracker.next_announce_time
+                >= tracker.last_announce_time.unwrap() + Duration::from_secs(600)
+        );
     }
 }
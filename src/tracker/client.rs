@@ -4,11 +4,16 @@
 use crate::tracker::TrackerEvent;
 
 use crate::errors::TrackerError;
-use crate::tracker::{Peer, TrackerResponse};
+use crate::tracker::{Peer, SwarmCounts, TrackerResponse};
 
 use serde_bencode::from_bytes;
+use serde_bencode::value::Value;
 use std::collections::HashSet;
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::time::timeout;
 
 use reqwest::header;
 use reqwest::Client;
@@ -18,23 +23,39 @@ use crate::tracker::RawTrackerResponse;
 
 static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
 
+// BEP 15 magic constant that opens every UDP tracker connect request
+const UDP_PROTOCOL_ID: u64 = 0x41727101980;
+const UDP_ACTION_CONNECT: u32 = 0;
+const UDP_ACTION_SCRAPE: u32 = 2;
+const UDP_ACTION_ERROR: u32 = 3;
+const UDP_TIMEOUT: Duration = Duration::from_secs(5);
+const UDP_ATTEMPTS: usize = 2;
+
+/// Identifies us to a tracker; the same for every announce a torrent sends.
+#[derive(Debug, Clone)]
+pub struct AnnounceClient {
+    pub peer_id: String,
+    pub port: u16,
+    // Random per torrent, so the tracker can tell it's still us if our IP changes
+    pub key: u32,
+}
+
 pub async fn announce_started(
     announce_link: String,
     hashed_info_dict: &[u8],
-    client_id: String,
-    client_port: u16,
+    client: AnnounceClient,
     torrent_size_left: usize,
 ) -> Result<TrackerResponse, TrackerError> {
     make_announce_request(AnnounceParams {
         announce_link,
         hashed_info_dict: hashed_info_dict.to_vec(),
-        client_id,
-        client_port,
+        client,
         uploaded: 0,
         downloaded: 0,
         left: torrent_size_left,
         num_peers_want: 50,
         event: Some(TrackerEvent::Started),
+        tracker_id: None,
     })
     .await
 }
@@ -42,22 +63,47 @@ pub async fn announce_started(
 pub async fn announce_periodic(
     announce_link: String,
     hashed_info_dict: &[u8],
-    client_id: String,
-    client_port: u16,
+    client: AnnounceClient,
     uploaded: usize,
     downloaded: usize,
     torrent_size_left: usize,
+    tracker_id: Option<String>,
 ) -> Result<TrackerResponse, TrackerError> {
     make_announce_request(AnnounceParams {
         announce_link,
         hashed_info_dict: hashed_info_dict.to_vec(),
-        client_id,
-        client_port,
+        client,
         uploaded,
         downloaded,
         left: torrent_size_left,
         num_peers_want: 50,
         event: None,
+        tracker_id,
+    })
+    .await
+}
+
+/// A regular announce while we are a partial seed (BEP 21), so the tracker doesn't
+/// count us as a leecher.
+pub async fn announce_paused(
+    announce_link: String,
+    hashed_info_dict: &[u8],
+    client: AnnounceClient,
+    uploaded: usize,
+    downloaded: usize,
+    torrent_size_left: usize,
+    tracker_id: Option<String>,
+) -> Result<TrackerResponse, TrackerError> {
+    make_announce_request(AnnounceParams {
+        announce_link,
+        hashed_info_dict: hashed_info_dict.to_vec(),
+        client,
+        uploaded,
+        downloaded,
+        left: torrent_size_left,
+        num_peers_want: 50,
+        event: Some(TrackerEvent::Paused),
+        tracker_id,
     })
     .await
 }
@@ -65,21 +111,21 @@ pub async fn announce_periodic(
 pub async fn announce_completed(
     announce_link: String,
     hashed_info_dict: &[u8],
-    client_id: String,
-    client_port: u16,
+    client: AnnounceClient,
     uploaded: usize,
     downloaded: usize,
+    tracker_id: Option<String>,
 ) -> Result<TrackerResponse, TrackerError> {
     make_announce_request(AnnounceParams {
         announce_link,
         hashed_info_dict: hashed_info_dict.to_vec(),
-        client_id,
-        client_port,
+        client,
         uploaded,
         downloaded,
         left: 0,
         num_peers_want: 0,
         event: Some(TrackerEvent::Completed),
+        tracker_id,
     })
     .await
 }
@@ -87,22 +133,22 @@ pub async fn announce_completed(
 pub async fn announce_stopped(
     announce_link: String,
     hashed_info_dict: &[u8],
-    client_id: String,
-    client_port: u16,
+    client: AnnounceClient,
     uploaded: usize,
     downloaded: usize,
     torrent_size_left: usize,
+    tracker_id: Option<String>,
 ) {
     let _ = make_announce_request(AnnounceParams {
         announce_link,
         hashed_info_dict: hashed_info_dict.to_vec(),
-        client_id,
-        client_port,
+        client,
         uploaded,
         downloaded,
         left: torrent_size_left,
         num_peers_want: 0,
         event: Some(TrackerEvent::Stopped),
+        tracker_id,
     })
     .await;
 }
@@ -110,51 +156,63 @@ pub async fn announce_stopped(
 struct AnnounceParams {
     announce_link: String,
     hashed_info_dict: Vec<u8>,
-    client_id: String,
-    client_port: u16,
+    client: AnnounceClient,
     uploaded: usize,
     downloaded: usize,
     left: usize,
     num_peers_want: usize,
     event: Option<TrackerEvent>,
+    tracker_id: Option<String>,
 }
 
 async fn make_announce_request(params: AnnounceParams) -> Result<TrackerResponse, TrackerError> {
+    let response = http_client()
+        .get(announce_url(&params))
+        .send()
+        .await?
+        .bytes()
+        .await?;
+    parse_announce_response(&response)
+}
+
+fn announce_url(params: &AnnounceParams) -> String {
     let mut link = format!(
-        "{}?info_hash={}&peer_id={}&port={}&uploaded={}&downloaded={}&left={}&numwant={}&compact=1",
+        "{}?info_hash={}&peer_id={}&port={}&uploaded={}&downloaded={}&left={}&numwant={}&key={:08X}&compact=1",
         params.announce_link,
         encode_url_nn(&params.hashed_info_dict),
-        encode_url_nn(params.client_id.as_bytes()),
-        params.client_port,
+        encode_url_nn(params.client.peer_id.as_bytes()),
+        params.client.port,
         params.uploaded,
         params.downloaded,
         params.left,
         params.num_peers_want,
+        params.client.key,
     );
 
     if let Some(event_val) = params.event {
         link.push_str(&format!("&event={}", event_val));
     }
 
-    let mut headers = header::HeaderMap::new();
-    headers.insert(
-        header::USER_AGENT,
-        header::HeaderValue::from_static(APP_USER_AGENT),
-    );
+    // Trackers that hand out a "tracker id" expect it back on every later announce.
+    if let Some(tracker_id) = &params.tracker_id {
+        link.push_str(&format!(
+            "&trackerid={}",
+            encode_url_nn(tracker_id.as_bytes())
+        ));
+    }
+    link
+}
 
-    let client = Client::builder()
-        .default_headers(headers)
-        .build()
-        .unwrap_or_else(|_| reqwest::Client::new());
-    let response = client.get(link).send().await?.bytes().await?;
-    let raw_response: RawTrackerResponse = from_bytes(&response)?;
+/// Turns a bencoded HTTP announce response into a `TrackerResponse`.
+fn parse_announce_response(response: &[u8]) -> Result<TrackerResponse, TrackerError> {
+    let raw_response: RawTrackerResponse = from_bytes(response)?;
 
     if let Some(reason) = raw_response.failure_reason {
         return Err(TrackerError::Tracker(reason));
     }
 
-    let peers: Vec<_> = match raw_response.peers {
-        Peers::Compact(bytes) => bytes
+    let mut peers: Vec<_> = match raw_response.peers {
+        Some(Peers::Compact(bytes)) => bytes
             .chunks_exact(6)
             .map(|chunk| {
                 let ip = Ipv4Addr::new(chunk[0], chunk[1], chunk[2], chunk[3]);
@@ -166,7 +224,7 @@ async fn make_announce_request(params: AnnounceParams) -> Result<TrackerResponse
                 }
             })
             .collect(),
-        Peers::Dicts(dicts) => dicts
+        Some(Peers::Dicts(dicts)) => dicts
             .into_iter()
             .map(|d| Peer {
                 peer_id: d.peer_id,
@@ -174,7 +232,17 @@ async fn make_announce_request(params: AnnounceParams) -> Result<TrackerResponse
                 port: d.port,
             })
             .collect(),
+        None => Vec::new(),
     };
+    peers.extend(raw_response.peers6.chunks_exact(18).map(|chunk| {
+        let mut octets = [0u8; 16];
+        octets.copy_from_slice(&chunk[..16]);
+        Peer {
+            peer_id: Vec::new(),
+            ip: Ipv6Addr::from(octets).to_string(),
+            port: u16::from_be_bytes([chunk[16], chunk[17]]),
+        }
+    }));
 
     let tracker_response = TrackerResponse {
         failure_reason: None,
@@ -185,11 +253,201 @@ async fn make_announce_request(params: AnnounceParams) -> Result<TrackerResponse
         complete: raw_response.complete,
         incomplete: raw_response.incomplete,
         peers,
+        external_ip: compact_ip(&raw_response.external_ip),
     };
 
     Ok(tracker_response)
 }
 
+/// A bare IPv4 or IPv6 address in network byte order.
+fn compact_ip(bytes: &[u8]) -> Option<IpAddr> {
+    if let Ok(octets) = <[u8; 4]>::try_from(bytes) {
+        return Some(IpAddr::from(octets));
+    }
+    <[u8; 16]>::try_from(bytes).ok().map(IpAddr::from)
+}
+
+/// Whether a tracker can be scraped at all: UDP trackers always can, HTTP
+/// trackers only when their announce path ends in `announce` (BEP 48).
+pub fn supports_scrape(announce_link: &str) -> bool {
+    announce_link.starts_with("udp://") || http_scrape_url(announce_link).is_some()
+}
+
+/// Asks a tracker how big the swarm for `info_hash` is without announcing.
+pub async fn scrape(announce_link: &str, info_hash: &[u8]) -> Result<SwarmCounts, TrackerError> {
+    if let Some(host) = announce_link.strip_prefix("udp://") {
+        udp_scrape(host, info_hash).await
+    } else {
+        http_scrape(announce_link, info_hash).await
+    }
+}
+
+async fn http_scrape(announce_link: &str, info_hash: &[u8]) -> Result<SwarmCounts, TrackerError> {
+    let scrape_url = http_scrape_url(announce_link).ok_or(TrackerError::ScrapeUnsupported)?;
+    let separator = if scrape_url.contains('?') { '&' } else { '?' };
+    let link = format!(
+        "{}{}info_hash={}",
+        scrape_url,
+        separator,
+        encode_url_nn(info_hash)
+    );
+
+    let response = http_client().get(link).send().await?.bytes().await?;
+    parse_scrape_response(&response, info_hash)
+}
+
+/// Reads the counts for `info_hash` out of a bencoded HTTP scrape response.
+fn parse_scrape_response(response: &[u8], info_hash: &[u8]) -> Result<SwarmCounts, TrackerError> {
+    let Value::Dict(root) = from_bytes::<Value>(response)? else {
+        return Err(TrackerError::InvalidResponse);
+    };
+
+    if let Some(Value::Bytes(reason)) = root.get(b"failure reason".as_slice()) {
+        return Err(TrackerError::Tracker(
+            String::from_utf8_lossy(reason).into_owned(),
+        ));
+    }
+
+    let Some(Value::Dict(files)) = root.get(b"files".as_slice()) else {
+        return Err(TrackerError::InvalidResponse);
+    };
+    let Some(Value::Dict(stats)) = files.get(info_hash) else {
+        return Err(TrackerError::InvalidResponse);
+    };
+
+    let count = |key: &[u8]| match stats.get(key) {
+        Some(Value::Int(n)) => (*n).clamp(0, u32::MAX as i64) as u32,
+        _ => 0,
+    };
+    Ok(SwarmCounts {
+        seeders: count(b"complete"),
+        leechers: count(b"incomplete"),
+        completed: count(b"downloaded"),
+    })
+}
+
+/// The scrape URL for an HTTP tracker, found by swapping `announce` at the start
+/// of the last path segment for `scrape`.
+fn http_scrape_url(announce_link: &str) -> Option<String> {
+    if !announce_link.starts_with("http://") && !announce_link.starts_with("https://") {
+        return None;
+    }
+    let (path, query) = match announce_link.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (announce_link, None),
+    };
+    let segment_start = path.rfind('/')? + 1;
+    let rest = path[segment_start..].strip_prefix("announce")?;
+
+    let mut url = format!("{}scrape{}", &path[..segment_start], rest);
+    if let Some(query) = query {
+        url.push('?');
+        url.push_str(query);
+    }
+    Some(url)
+}
+
+/// BEP 15 scrape: a connect round trip for a connection id, then the scrape itself.
+async fn udp_scrape(host: &str, info_hash: &[u8]) -> Result<SwarmCounts, TrackerError> {
+    // "udp://host:port/announce" -> "host:port"
+    let host = host.split('/').next().unwrap_or(host);
+    let addr = lookup_host(host)
+        .await?
+        .next()
+        .ok_or(TrackerError::InvalidResponse)?;
+    let bind_addr = if addr.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(bind_addr).await?;
+    socket.connect(addr).await?;
+
+    let mut connect_request = Vec::with_capacity(16);
+    connect_request.extend_from_slice(&UDP_PROTOCOL_ID.to_be_bytes());
+    connect_request.extend_from_slice(&UDP_ACTION_CONNECT.to_be_bytes());
+    let response = udp_transaction(&socket, connect_request, UDP_ACTION_CONNECT, 16).await?;
+    let connection_id = &response[8..16];
+
+    let mut scrape_request = Vec::with_capacity(36);
+    scrape_request.extend_from_slice(connection_id);
+    scrape_request.extend_from_slice(&UDP_ACTION_SCRAPE.to_be_bytes());
+    scrape_request.extend_from_slice(info_hash);
+    let response = udp_transaction(&socket, scrape_request, UDP_ACTION_SCRAPE, 20).await?;
+
+    let field = |at: usize| {
+        u32::from_be_bytes([
+            response[at],
+            response[at + 1],
+            response[at + 2],
+            response[at + 3],
+        ])
+    };
+    Ok(SwarmCounts {
+        seeders: field(8),
+        completed: field(12),
+        leechers: field(16),
+    })
+}
+
+/// Sends a UDP tracker request and waits for the reply carrying the same
+/// transaction id. `request` is everything before the transaction id; for
+/// connect and scrape that is the first 12 bytes, so the id is spliced in there.
+async fn udp_transaction(
+    socket: &UdpSocket,
+    mut request: Vec<u8>,
+    action: u32,
+    min_len: usize,
+) -> Result<Vec<u8>, TrackerError> {
+    let transaction_id: u32 = rand::random();
+    request.splice(12..12, transaction_id.to_be_bytes());
+
+    let mut buf = vec![0u8; 2048];
+    for _ in 0..UDP_ATTEMPTS {
+        socket.send(&request).await?;
+        let deadline = tokio::time::Instant::now() + UDP_TIMEOUT;
+        loop {
+            let len = match timeout(
+                deadline.saturating_duration_since(tokio::time::Instant::now()),
+                socket.recv(&mut buf),
+            )
+            .await
+            {
+                Ok(result) => result?,
+                Err(_) => break,
+            };
+            if len < 8 || buf[4..8] != transaction_id.to_be_bytes() {
+                continue;
+            }
+
+            let response_action = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
+            if response_action == UDP_ACTION_ERROR {
+                return Err(TrackerError::Tracker(
+                    String::from_utf8_lossy(&buf[8..len]).into_owned(),
+                ));
+            }
+            if response_action != action || len < min_len {
+                return Err(TrackerError::InvalidResponse);
+            }
+            return Ok(buf[..len].to_vec());
+        }
+    }
+    Err(TrackerError::Timeout)
+}
+
+fn http_client() -> Client {
+    let mut headers = header::HeaderMap::new();
+    headers.insert(
+        header::USER_AGENT,
+        header::HeaderValue::from_static(APP_USER_AGENT),
+    );
+
+    Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new())
+}
+
 fn encode_url_nn(param: &[u8]) -> String {
     let allowed_chars: HashSet<u8> =
         "0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-_~"
@@ -206,3 +464,95 @@ fn encode_url_nn(param: &[u8]) -> String {
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INFO_HASH: [u8; 20] = [b'a'; 20];
+
+    fn scrape_response(stats: &str) -> Vec<u8> {
+        let mut response = b"d5:filesd20:".to_vec();
+        response.extend_from_slice(&INFO_HASH);
+        response.extend_from_slice(stats.as_bytes());
+        response.extend_from_slice(b"ee");
+        response
+    }
+
+    #[test]
+    fn test_parse_scrape_response_reads_counts() {
+        let response = scrape_response("d8:completei5e10:downloadedi50e10:incompletei3ee");
+        let counts = parse_scrape_response(&response, &INFO_HASH).unwrap();
+        assert_eq!(
+            counts,
+            SwarmCounts {
+                seeders: 5,
+                leechers: 3,
+                completed: 50,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_scrape_response_defaults_missing_and_negative_counts() {
+        let response = scrape_response("d8:completei-1ee");
+        let counts = parse_scrape_response(&response, &INFO_HASH).unwrap();
+        assert_eq!(counts, SwarmCounts::default());
+    }
+
+    #[test]
+    fn test_parse_scrape_response_rejects_other_torrents_and_failures() {
+        let response = scrape_response("d8:completei5ee");
+        assert!(matches!(
+            parse_scrape_response(&response, &[b'b'; 20]),
+            Err(TrackerError::InvalidResponse)
+        ));
+
+        let failure = b"d14:failure reason6:bannede";
+        assert!(matches!(
+            parse_scrape_response(failure, &INFO_HASH),
+            Err(TrackerError::Tracker(reason)) if reason == "banned"
+        ));
+    }
+
+    #[test]
+    fn test_tracker_id_is_sent_back_on_next_announce() {
+        let response = b"d8:intervali1800e10:tracker id6:abc 12e";
+        let first = parse_announce_response(response).unwrap();
+        assert_eq!(first.tracker_id.as_deref(), Some("abc 12"));
+
+        let next = AnnounceParams {
+            announce_link: "http://t.example/announce".to_string(),
+            hashed_info_dict: INFO_HASH.to_vec(),
+            client: AnnounceClient {
+                peer_id: "-SS0001-000000000000".to_string(),
+                port: 6881,
+                key: 1,
+            },
+            uploaded: 0,
+            downloaded: 0,
+            left: 0,
+            num_peers_want: 50,
+            event: None,
+            tracker_id: first.tracker_id,
+        };
+        assert!(announce_url(&next).ends_with("&trackerid=abc%2012"));
+
+        let without_id = parse_announce_response(b"d8:intervali1800ee").unwrap();
+        let next = AnnounceParams {
+            tracker_id: without_id.tracker_id,
+            ..next
+        };
+        assert!(!announce_url(&next).contains("trackerid"));
+    }
+
+    #[test]
+    fn test_http_scrape_url_replaces_announce() {
+        assert_eq!(
+            http_scrape_url("http://t.example/x/announce.php?passkey=1").as_deref(),
+            Some("http://t.example/x/scrape.php?passkey=1")
+        );
+        assert_eq!(http_scrape_url("http://t.example/a"), None);
+        assert_eq!(http_scrape_url("udp://t.example:80/announce"), None);
+    }
+}
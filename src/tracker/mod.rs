@@ -2,8 +2,10 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 pub mod client;
+pub mod tiers;
 
 use std::fmt;
+use std::net::IpAddr;
 
 use serde::Deserialize;
 
@@ -12,6 +14,8 @@ pub enum TrackerEvent {
     Started,
     Completed,
     Stopped,
+    // Partial seed (BEP 21): we have every piece we want and only upload
+    Paused,
 }
 impl fmt::Display for TrackerEvent {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -19,6 +23,7 @@ impl fmt::Display for TrackerEvent {
             TrackerEvent::Started => write!(f, "started"),
             TrackerEvent::Completed => write!(f, "completed"),
             TrackerEvent::Stopped => write!(f, "stopped"),
+            TrackerEvent::Paused => write!(f, "paused"),
         }
     }
 }
@@ -33,6 +38,23 @@ pub struct TrackerResponse {
     pub complete: i64,
     pub incomplete: i64,
     pub peers: Vec<Peer>,
+    // Our address as the tracker saw it (BEP 24)
+    pub external_ip: Option<IpAddr>,
+}
+
+/// Swarm size as a tracker reports it, from a scrape or an announce.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct SwarmCounts {
+    pub seeders: u32,
+    pub leechers: u32,
+    // Finished downloads the tracker has seen; only scrapes report it
+    pub completed: u32,
+}
+
+impl SwarmCounts {
+    pub fn total(&self) -> u32 {
+        self.seeders.saturating_add(self.leechers)
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -74,5 +96,13 @@ struct RawTrackerResponse {
     complete: i64,
     #[serde(default)]
     incomplete: i64,
-    peers: Peers,
+    // Trackers reachable over IPv6 may only send `peers6`
+    #[serde(default)]
+    peers: Option<Peers>,
+    // Compact IPv6 peers (BEP 7): 16 address bytes and a port each
+    #[serde(default, with = "serde_bytes")]
+    peers6: Vec<u8>,
+    // 4 or 16 address bytes (BEP 24)
+    #[serde(rename = "external ip", default, with = "serde_bytes")]
+    external_ip: Vec<u8>,
 }
@@ -0,0 +1,144 @@
+// SPDX-FileCopyrightText: 2025 The superseedr Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::HashSet;
+
+use rand::seq::SliceRandom;
+
+/// Tracker tiers from an announce-list (BEP 12). Only one tracker is announced to at
+/// a time: each announce starts at the top tier and walks down the list until a
+/// tracker answers, which then moves to the front of its tier.
+#[derive(Debug, Default, Clone)]
+pub struct TrackerTiers {
+    tiers: Vec<Vec<String>>,
+    // Position of the next tracker to try in `urls()` order
+    cursor: usize,
+}
+
+impl TrackerTiers {
+    /// Builds the tiers, dropping empty tiers and trackers listed more than once,
+    /// and shuffles the trackers within each tier.
+    pub fn new(tiers: Vec<Vec<String>>) -> Self {
+        let mut seen = HashSet::new();
+        let mut rng = rand::rng();
+        let tiers = tiers
+            .into_iter()
+            .map(|tier| {
+                let mut tier: Vec<String> = tier
+                    .into_iter()
+                    .filter(|url| !url.is_empty() && seen.insert(url.clone()))
+                    .collect();
+                tier.shuffle(&mut rng);
+                tier
+            })
+            .filter(|tier| !tier.is_empty())
+            .collect();
+        Self { tiers, cursor: 0 }
+    }
+
+    pub fn contains(&self, url: &str) -> bool {
+        self.urls().any(|u| u == url)
+    }
+
+    /// Every tracker, in the order they are tried.
+    pub fn urls(&self) -> impl Iterator<Item = &String> {
+        self.tiers.iter().flatten()
+    }
+
+    /// The tracker the next announce goes to.
+    pub fn current(&self) -> Option<&str> {
+        self.urls().nth(self.cursor).map(String::as_str)
+    }
+
+    /// Adds a tracker as a new lowest tier, unless it is already known.
+    pub fn push_tier(&mut self, url: String) {
+        if !url.is_empty() && !self.contains(&url) {
+            self.tiers.push(vec![url]);
+        }
+    }
+
+    /// `url` answered: it moves to the front of its tier, and the next announce
+    /// starts from the top tier again.
+    pub fn record_success(&mut self, url: &str) {
+        for tier in &mut self.tiers {
+            if let Some(pos) = tier.iter().position(|u| u == url) {
+                let working = tier.remove(pos);
+                tier.insert(0, working);
+                break;
+            }
+        }
+        self.cursor = 0;
+    }
+
+    /// `url` failed. If it was the current tracker, moves on to the next one and
+    /// returns it; returns `None` once every tracker has been tried, starting over
+    /// from the top.
+    pub fn record_failure(&mut self, url: &str) -> Option<&str> {
+        if self.current() != Some(url) {
+            return None;
+        }
+        self.cursor += 1;
+        if self.cursor >= self.urls().count() {
+            self.cursor = 0;
+            return None;
+        }
+        self.current()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tiers(list: &[&[&str]]) -> TrackerTiers {
+        TrackerTiers::new(
+            list.iter()
+                .map(|tier| tier.iter().map(|u| u.to_string()).collect())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_new_drops_duplicates_and_empty_tiers() {
+        let t = tiers(&[&["a"], &[], &["a", ""], &["b"]]);
+        assert_eq!(t.urls().collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(t.current(), Some("a"));
+    }
+
+    #[test]
+    fn test_failure_falls_through_tiers_then_wraps() {
+        let mut t = tiers(&[&["a"], &["b"], &["c"]]);
+
+        assert_eq!(t.record_failure("a"), Some("b"));
+        assert_eq!(t.record_failure("b"), Some("c"));
+        assert_eq!(t.record_failure("c"), None);
+        assert_eq!(t.current(), Some("a"));
+    }
+
+    #[test]
+    fn test_failure_of_other_tracker_is_ignored() {
+        let mut t = tiers(&[&["a"], &["b"]]);
+
+        assert_eq!(t.record_failure("b"), None);
+        assert_eq!(t.current(), Some("a"));
+    }
+
+    #[test]
+    fn test_success_promotes_within_tier() {
+        let mut t = tiers(&[&["a"], &["b", "c"]]);
+        let second = t.urls().nth(2).unwrap().clone();
+
+        t.record_failure("a");
+        t.record_success(&second);
+        assert_eq!(t.urls().nth(1), Some(&second));
+        assert_eq!(t.current(), Some("a"));
+    }
+
+    #[test]
+    fn test_push_tier_appends_new_trackers_only() {
+        let mut t = tiers(&[&["a"]]);
+        t.push_tier("a".to_string());
+        t.push_tier("b".to_string());
+        assert_eq!(t.urls().collect::<Vec<_>>(), ["a", "b"]);
+    }
+}
@@ -8,29 +8,41 @@ use crate::tui_formatters::*;
 
 use crate::app::GraphDisplayMode;
 use crate::app::PeerInfo;
+use crate::geoip;
 
 use crate::app::{
-    AppMode, AppState, ConfigItem, SelectedHeader, TorrentControlState, PEER_HEADERS,
-    TORRENT_HEADERS,
+    displayed_peers, AddItem, AppMode, AppState, ConfigItem, CreateItem, DuplicateSources,
+    SelectedHeader, TorrentControlState,
 };
 
 use throbber_widgets_tui::Throbber;
 
 use crate::config::get_app_paths;
 
-use crate::config::{PeerSortColumn, Settings, SortDirection, TorrentSortColumn};
+use crate::config::{
+    AddOptions, DhtMode, PeerSortColumn, PerformanceProfile, SeedGoalAction, Settings,
+    SortDirection, TorrentSortColumn,
+};
 
+use crate::scheduler::RateProfile;
 use crate::theme;
+use crate::token_bucket::BandwidthPriority;
+use crate::torrent_file::create::CreateOptions;
+use crate::torrent_manager::files::FilePriority;
 
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 static APP_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 pub const SECONDS_HISTORY_MAX: usize = 3600; // 1 hour of per-second data
 pub const MINUTES_HISTORY_MAX: usize = 48 * 60; // 48 hours of per-minute data
 
+// Header plus a few rows; anything shorter gets the one-line peers summary.
+const MIN_PEER_TABLE_HEIGHT: u16 = 5;
+
 pub fn draw(f: &mut Frame, app_state: &AppState, settings: &Settings) {
     if app_state.show_help {
         draw_help_popup(f, app_state, &app_state.mode);
@@ -39,7 +51,7 @@ pub fn draw(f: &mut Frame, app_state: &AppState, settings: &Settings) {
 
     match &app_state.mode {
         AppMode::Welcome => {
-            draw_welcome_screen(f);
+            draw_welcome_screen(f, app_state);
             return;
         }
         AppMode::PowerSaving => {
@@ -69,22 +81,14 @@ pub fn draw(f: &mut Frame, app_state: &AppState, settings: &Settings) {
             let explorer_area = chunks[0];
             let footer_area = chunks[1];
 
-            let footer_text = Line::from(vec![
-                Span::styled("[Tab]", Style::default().fg(theme::GREEN)),
-                Span::raw(" Confirm | "),
-                Span::styled("[Esc]", Style::default().fg(theme::RED)),
-                Span::raw(" Cancel | "),
-                Span::styled("←→↑↓", Style::default().fg(theme::BLUE)),
-                Span::raw(" Navigate"),
-            ])
-            .alignment(Alignment::Center);
-
-            let footer_paragraph =
-                Paragraph::new(footer_text).style(Style::default().fg(theme::SUBTEXT1));
-
             f.render_widget(block, area);
             f.render_widget(&file_explorer.widget(), explorer_area);
-            f.render_widget(footer_paragraph, footer_area);
+            draw_file_picker_footer(
+                f,
+                footer_area,
+                app_state.file_picker_error.as_deref(),
+                false,
+            );
             return;
         }
         AppMode::Config {
@@ -100,15 +104,75 @@ pub fn draw(f: &mut Frame, app_state: &AppState, settings: &Settings) {
             draw_delete_confirm_dialog(f, app_state);
             return;
         }
-        AppMode::DownloadPathPicker(file_explorer) => {
+        AppMode::SeedGoalConfirm { info_hash, action } => {
+            draw_seed_goal_confirm_dialog(f, app_state, info_hash, *action);
+            return;
+        }
+        AppMode::MergeSourcesConfirm(sources) => {
+            draw_merge_sources_confirm_dialog(f, app_state, sources);
+            return;
+        }
+        AppMode::QuitConfirm => {
+            draw_quit_confirm_dialog(f);
+            return;
+        }
+        AppMode::NetworkAudit { scroll_offset } => {
+            draw_network_audit_screen(f, app_state, settings, *scroll_offset);
+            return;
+        }
+        AppMode::Files {
+            info_hash,
+            selected,
+        } => {
+            draw_files_screen(f, app_state, info_hash, *selected);
+            return;
+        }
+        AppMode::CreateTorrent {
+            options,
+            seed,
+            selected_index,
+            editing,
+        } => {
+            draw_create_torrent_screen(f, options, *seed, *selected_index, editing);
+            return;
+        }
+        AppMode::AddTorrent {
+            input,
+            options,
+            selected_index,
+        } => {
+            draw_add_torrent_screen(f, settings, input, options, *selected_index);
+            return;
+        }
+        AppMode::Rename {
+            info_hash,
+            file,
+            buffer,
+        } => {
+            if let Some(selected) = file {
+                draw_files_screen(f, app_state, info_hash, *selected);
+            }
+            draw_rename_dialog(f, file.is_some(), buffer);
+            return;
+        }
+        AppMode::DownloadPathPicker(file_explorer)
+        | AppMode::SetupFolderPicker(file_explorer)
+        | AppMode::CreatePathPicker(file_explorer)
+        | AppMode::AddTorrentPathPicker { file_explorer, .. }
+        | AppMode::LocationPicker { file_explorer, .. } => {
             let area = centered_rect(80, 70, f.area());
             f.render_widget(Clear, area);
 
+            let relocating = matches!(app_state.mode, AppMode::LocationPicker { .. });
+            let title = if matches!(app_state.mode, AppMode::CreatePathPicker(_)) {
+                "Select a File or Folder to Share"
+            } else if relocating {
+                "Select New Location"
+            } else {
+                "Select Download Folder"
+            };
             let block = Block::default()
-                .title(Span::styled(
-                    "Select Download Folder",
-                    Style::default().fg(theme::MAUVE),
-                ))
+                .title(Span::styled(title, Style::default().fg(theme::MAUVE)))
                 .borders(Borders::ALL)
                 .border_style(Style::default().fg(theme::SURFACE2));
 
@@ -120,22 +184,14 @@ pub fn draw(f: &mut Frame, app_state: &AppState, settings: &Settings) {
             let explorer_area = chunks[0];
             let footer_area = chunks[1];
 
-            let footer_text = Line::from(vec![
-                Span::styled("[Tab]", Style::default().fg(theme::GREEN)), // Use Enter
-                Span::raw(" Confirm | "),
-                Span::styled("[Esc]", Style::default().fg(theme::RED)),
-                Span::raw(" Cancel | "),
-                Span::styled("←→↑↓", Style::default().fg(theme::BLUE)),
-                Span::raw(" Navigate"),
-            ])
-            .alignment(Alignment::Center);
-
-            let footer_paragraph =
-                Paragraph::new(footer_text).style(Style::default().fg(theme::SUBTEXT1));
-
             f.render_widget(block, area);
             f.render_widget(&file_explorer.widget(), explorer_area);
-            f.render_widget(footer_paragraph, footer_area);
+            draw_file_picker_footer(
+                f,
+                footer_area,
+                app_state.file_picker_error.as_deref(),
+                relocating,
+            );
             return;
         }
         _ => {}
@@ -191,10 +247,10 @@ pub fn draw(f: &mut Frame, app_state: &AppState, settings: &Settings) {
     let peer_chart_chunk = details_chunks[1]; // Top-right-right (NEW)
 
     // draw_left_pane handles its own internal layout now
-    draw_left_pane(f, app_state, left_pane);
+    draw_left_pane(f, app_state, settings, left_pane);
 
     // Pass the new, smaller text chunk
-    draw_right_pane(f, app_state, details_text_chunk, peers_chunk);
+    draw_right_pane(f, app_state, settings, details_text_chunk, peers_chunk);
 
     draw_network_chart(f, app_state, chart_chunk);
 
@@ -212,6 +268,8 @@ pub fn draw(f: &mut Frame, app_state: &AppState, settings: &Settings) {
 
     draw_footer(f, app_state, settings, footer_chunk);
 
+    draw_status_messages(f, app_state, footer_chunk);
+
     if let Some(error_text) = &app_state.system_error {
         draw_status_error_popup(f, error_text);
     }
@@ -221,6 +279,112 @@ pub fn draw(f: &mut Frame, app_state: &AppState, settings: &Settings) {
     }
 }
 
+fn draw_seed_goal_confirm_dialog(
+    f: &mut Frame,
+    app_state: &AppState,
+    info_hash: &[u8],
+    action: SeedGoalAction,
+) {
+    let Some(torrent) = app_state.torrents.get(info_hash) else {
+        return;
+    };
+    let area = centered_rect(50, 25, f.area());
+    f.render_widget(Clear, area);
+
+    let remaining_secs = app_state
+        .seed_goal_deadlines
+        .get(info_hash)
+        .map(|deadline| deadline.saturating_duration_since(Instant::now()).as_secs())
+        .unwrap_or_default();
+    let action_style = if action == SeedGoalAction::RemoveWithData {
+        Style::default().fg(theme::YELLOW).bold().underlined()
+    } else {
+        Style::default().fg(theme::YELLOW).bold()
+    };
+
+    let text = vec![
+        Line::from(Span::styled(
+            "Seed Goals Reached",
+            Style::default().fg(theme::GREEN),
+        )),
+        Line::from(""),
+        Line::from(torrent.latest_state.name()),
+        Line::from(Span::styled(
+            format!("Ratio {:.2}", torrent.latest_state.seed_ratio()),
+            Style::default().fg(theme::SUBTEXT1),
+        )),
+        Line::from(""),
+        Line::from(vec![
+            Span::raw("It will be "),
+            Span::styled(action.describe(), action_style),
+            Span::raw(format!(" in {}.", format_time(remaining_secs))),
+        ]),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("[Enter]", Style::default().fg(theme::GREEN)),
+            Span::raw(" Now  "),
+            Span::styled("[Esc]", Style::default().fg(theme::RED)),
+            Span::raw(" Keep seeding"),
+        ]),
+    ];
+
+    let block = Block::default()
+        .title("Confirmation")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme::SURFACE2));
+    let paragraph = Paragraph::new(text)
+        .block(block)
+        .style(Style::default().fg(theme::TEXT));
+    f.render_widget(paragraph, area);
+}
+
+fn draw_merge_sources_confirm_dialog(
+    f: &mut Frame,
+    app_state: &AppState,
+    sources: &DuplicateSources,
+) {
+    let Some(torrent) = app_state.torrents.get(&sources.info_hash) else {
+        return;
+    };
+    let area = centered_rect(50, 25, f.area());
+    f.render_widget(Clear, area);
+
+    let text = vec![
+        Line::from(Span::styled(
+            "Already Added",
+            Style::default().fg(theme::YELLOW),
+        )),
+        Line::from(""),
+        Line::from(torrent.latest_state.name()),
+        Line::from(Span::styled(
+            format!(
+                "{} trackers, {} web seeds",
+                sources.trackers.len(),
+                sources.web_seeds.len()
+            ),
+            Style::default().fg(theme::SUBTEXT1),
+        )),
+        Line::from(""),
+        Line::from("Merge its trackers and web seeds into the torrent?"),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("[Enter]", Style::default().fg(theme::GREEN)),
+            Span::raw(" Merge  "),
+            Span::styled("[Esc]", Style::default().fg(theme::RED)),
+            Span::raw(" Keep its own"),
+        ]),
+    ];
+
+    let block = Block::default()
+        .title("Confirmation")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme::SURFACE2));
+    let paragraph = Paragraph::new(text)
+        .block(block)
+        .style(Style::default().fg(theme::TEXT));
+    f.render_widget(paragraph, area);
+}
+
 fn draw_delete_confirm_dialog(f: &mut Frame, app_state: &AppState) {
     if let AppMode::DeleteConfirm {
         info_hash,
@@ -231,7 +395,7 @@ fn draw_delete_confirm_dialog(f: &mut Frame, app_state: &AppState) {
             let area = centered_rect(50, 25, f.area());
             f.render_widget(Clear, area);
 
-            let torrent_name = &torrent_to_delete.latest_state.torrent_name;
+            let torrent_name = torrent_to_delete.latest_state.name();
             let download_path_str = torrent_to_delete
                 .latest_state
                 .download_path
@@ -243,7 +407,7 @@ fn draw_delete_confirm_dialog(f: &mut Frame, app_state: &AppState) {
                     Style::default().fg(theme::RED),
                 )),
                 Line::from(""),
-                Line::from(torrent_name.as_str()),
+                Line::from(torrent_name),
                 Line::from(Span::styled(
                     download_path_str.to_string(),
                     Style::default().fg(theme::SUBTEXT1),
@@ -303,7 +467,80 @@ fn draw_delete_confirm_dialog(f: &mut Frame, app_state: &AppState) {
     }
 }
 
-fn draw_left_pane(f: &mut Frame, app_state: &AppState, left_pane: Rect) {
+fn draw_rename_dialog(f: &mut Frame, is_file: bool, buffer: &str) {
+    let area = centered_rect(60, 20, f.area());
+    f.render_widget(Clear, area);
+
+    let (title, hint) = if is_file {
+        (
+            "Rename File",
+            "Path inside the download folder; new folders are created",
+        )
+    } else {
+        (
+            "Rename Torrent",
+            "Leave empty to use the name from the torrent",
+        )
+    };
+    let text = vec![
+        Line::from(Span::styled(hint, Style::default().fg(theme::SUBTEXT1))),
+        Line::from(""),
+        Line::from(Span::styled(buffer, Style::default().fg(theme::YELLOW))),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("[Enter]", Style::default().fg(theme::GREEN)),
+            Span::raw(" Rename  "),
+            Span::styled("[Esc]", Style::default().fg(theme::RED)),
+            Span::raw(" Cancel"),
+        ]),
+    ];
+
+    let block = Block::default()
+        .title(Span::styled(title, Style::default().fg(theme::MAUVE)))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme::SURFACE2));
+    let inner_area = block.inner(area);
+    let paragraph = Paragraph::new(text)
+        .block(block)
+        .style(Style::default().fg(theme::TEXT));
+    f.render_widget(paragraph, area);
+    f.set_cursor_position((inner_area.x + buffer.len() as u16, inner_area.y + 2));
+}
+
+fn draw_quit_confirm_dialog(f: &mut Frame) {
+    let area = centered_rect(40, 15, f.area());
+    f.render_widget(Clear, area);
+
+    let text = vec![
+        Line::from(Span::styled(
+            "Quit superseedr?",
+            Style::default().fg(theme::RED),
+        )),
+        Line::from(""),
+        Line::from("All torrents will stop downloading and seeding."),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("[Enter]", Style::default().fg(theme::GREEN)),
+            Span::raw(" Quit  "),
+            Span::styled("[Esc]", Style::default().fg(theme::RED)),
+            Span::raw(" Cancel"),
+        ]),
+    ];
+
+    let block = Block::default()
+        .title("Confirmation")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme::SURFACE2));
+
+    let paragraph = Paragraph::new(text)
+        .block(block)
+        .style(Style::default().fg(theme::TEXT));
+    f.render_widget(paragraph, area);
+}
+
+fn draw_left_pane(f: &mut Frame, app_state: &AppState, settings: &Settings, left_pane: Rect) {
+    let inactive_threshold = Duration::from_secs(settings.inactive_torrent_threshold_secs);
+
     let left_pane_chunks = Layout::vertical([
         Constraint::Min(0),    // Torrent list
         Constraint::Length(5), // Torrent UL/DL Sparklines
@@ -324,26 +561,25 @@ fn draw_left_pane(f: &mut Frame, app_state: &AppState, left_pane: Rect) {
             && state.number_of_pieces_completed < state.number_of_pieces_total
     });
 
-    let (widths, name_column_index): (Vec<Constraint>, usize) = if has_unfinished_torrents {
-        (
-            vec![
-                Constraint::Length(7),      // Progress
-                Constraint::Percentage(65), // Name
-                Constraint::Percentage(15), // DL
-                Constraint::Percentage(15), // UL
-            ],
-            1,
-        )
-    } else {
-        (
-            vec![
-                Constraint::Percentage(70),
-                Constraint::Percentage(15),
-                Constraint::Percentage(15),
-            ],
-            0,
-        )
-    };
+    let torrent_columns = settings.torrent_table_columns();
+    let queue_positions: HashMap<&Vec<u8>, usize> = app_state
+        .torrent_queue
+        .iter()
+        .enumerate()
+        .map(|(position, info_hash)| (info_hash, position + 1))
+        .collect();
+    let mut widths: Vec<Constraint> = torrent_columns
+        .iter()
+        .map(|c| Constraint::Percentage(c.width))
+        .collect();
+    if has_unfinished_torrents {
+        widths.insert(0, Constraint::Length(7)); // Progress
+    }
+    let column_count = widths.len();
+    let name_column_index = torrent_columns
+        .iter()
+        .position(|c| c.column == TorrentSortColumn::Name)
+        .map(|i| i + usize::from(has_unfinished_torrents));
 
     let table_block = Block::default().borders(Borders::ALL);
     let table_inner_area = table_block.inner(torrent_list_chunk);
@@ -354,20 +590,24 @@ fn draw_left_pane(f: &mut Frame, app_state: &AppState, left_pane: Rect) {
         .direction(Direction::Horizontal)
         .constraints(widths.clone())
         .split(Rect::new(0, 0, content_width, 1)); // A dummy rect of the correct width
-    let name_column_width = temp_layout_chunks[name_column_index].width as usize;
+    let name_column_width = name_column_index.map_or(0, |i| temp_layout_chunks[i].width as usize);
 
     let header_cells: Vec<Cell> = {
-        let mut cells: Vec<Cell> = TORRENT_HEADERS
+        let mut cells: Vec<Cell> = torrent_columns
             .iter()
+            .map(|c| &c.column)
             .enumerate()
             .map(|(i, h)| {
                 let is_selected = app_state.selected_header == SelectedHeader::Torrent(i);
                 let (sort_col, sort_dir) = app_state.torrent_sort;
                 let is_sorting_by_this = sort_col == *h;
                 let text = match h {
+                    TorrentSortColumn::Queue => "#",
                     TorrentSortColumn::Name => "Name",
+                    TorrentSortColumn::Category => "Category",
                     TorrentSortColumn::Down => "DL",
                     TorrentSortColumn::Up => "UL",
+                    TorrentSortColumn::Ratio => "Ratio",
                 };
                 let mut text_with_indicator = text.to_string();
                 let mut style = Style::default().fg(theme::YELLOW);
@@ -426,12 +666,21 @@ fn draw_left_pane(f: &mut Frame, app_state: &AppState, left_pane: Rect) {
                     let is_selected = i == app_state.selected_torrent_index;
 
                     let mut row_style = match state.torrent_control_state {
+                        TorrentControlState::Running
+                            if settings.dim_inactive_torrents
+                                && torrent.is_inactive(inactive_threshold) =>
+                        {
+                            Style::default().fg(theme::SURFACE1)
+                        }
                         TorrentControlState::Running => Style::default().fg(theme::TEXT),
                         TorrentControlState::Paused => Style::default().fg(theme::SURFACE1),
+                        TorrentControlState::Queued => Style::default().fg(theme::OVERLAY0),
                         TorrentControlState::Deleting => Style::default().fg(theme::RED),
                     };
                     row_style = if state.torrent_control_state == TorrentControlState::Deleting {
                         row_style.fg(theme::OVERLAY0)
+                    } else if torrent.load_error.is_some() {
+                        row_style.fg(theme::RED)
                     } else {
                         row_style
                     };
@@ -439,7 +688,7 @@ fn draw_left_pane(f: &mut Frame, app_state: &AppState, left_pane: Rect) {
                     let name_to_display = if app_state.anonymize_torrent_names {
                         format!("Torrent {}", i + 1)
                     } else {
-                        state.torrent_name.clone()
+                        state.name().to_string()
                     };
 
                     let mut name_cell =
@@ -449,30 +698,54 @@ fn draw_left_pane(f: &mut Frame, app_state: &AppState, left_pane: Rect) {
                         row_style = row_style.add_modifier(Modifier::BOLD);
                     }
 
-                    let mut row_cells = vec![
-                        name_cell,
-                        Cell::from(format_speed(torrent.smoothed_download_speed_bps))
-                            .style(speed_to_style(torrent.smoothed_download_speed_bps)),
-                        Cell::from(format_speed(torrent.smoothed_upload_speed_bps))
-                            .style(speed_to_style(torrent.smoothed_upload_speed_bps)),
-                    ];
+                    let mut row_cells: Vec<Cell> = torrent_columns
+                        .iter()
+                        .map(|c| match c.column {
+                            TorrentSortColumn::Queue => Cell::from(
+                                queue_positions
+                                    .get(info_hash)
+                                    .map_or(String::new(), |position| position.to_string()),
+                            ),
+                            TorrentSortColumn::Name => name_cell.clone(),
+                            TorrentSortColumn::Category => {
+                                Cell::from(state.category.clone().unwrap_or_default())
+                                    .style(Style::default().fg(theme::SUBTEXT0))
+                            }
+                            TorrentSortColumn::Down => {
+                                Cell::from(format_speed(torrent.smoothed_download_speed_bps))
+                                    .style(speed_to_style(torrent.smoothed_download_speed_bps))
+                            }
+                            TorrentSortColumn::Up => {
+                                Cell::from(format_speed(torrent.smoothed_upload_speed_bps))
+                                    .style(speed_to_style(torrent.smoothed_upload_speed_bps))
+                            }
+                            TorrentSortColumn::Ratio => Cell::from(format_ratio(
+                                state.total_uploaded,
+                                state.total_downloaded,
+                            )),
+                        })
+                        .collect();
 
                     if has_unfinished_torrents {
-                        row_cells.insert(
-                            0,
-                            Cell::from(format!("{:.1}%", progress)).style(progress_style),
-                        );
+                        let progress_cell = if torrent.load_error.is_some() {
+                            Cell::from("Error").style(Style::default().fg(theme::RED))
+                        } else if state.is_queued_for_validation {
+                            Cell::from("Queued").style(Style::default().fg(theme::OVERLAY0))
+                        } else {
+                            Cell::from(format!("{:.1}%", progress)).style(progress_style)
+                        };
+                        row_cells.insert(0, progress_cell);
                     }
 
                     Row::new(row_cells).style(row_style)
                 }
-                None => Row::new(vec![
-                    Cell::from(""),
-                    Cell::from("Missing torrent data..."),
-                    Cell::from(""),
-                    Cell::from(""),
-                    Cell::from(""),
-                ]),
+                None => Row::new((0..column_count).map(|i| {
+                    if Some(i) == name_column_index {
+                        Cell::from("Missing torrent data...")
+                    } else {
+                        Cell::from("")
+                    }
+                })),
             });
 
     let border_style = if matches!(app_state.selected_header, SelectedHeader::Torrent(_)) {
@@ -499,6 +772,12 @@ fn draw_left_pane(f: &mut Frame, app_state: &AppState, left_pane: Rect) {
         ));
         title_spans.push(Span::styled("]", Style::default().fg(theme::SUBTEXT1)));
     }
+    if let Some(category) = &app_state.category_filter {
+        title_spans.push(Span::styled(
+            format!("<{}> ", category),
+            Style::default().fg(theme::MAUVE),
+        ));
+    }
 
     if let Some(info_hash) = app_state
         .torrent_list_order
@@ -508,7 +787,7 @@ fn draw_left_pane(f: &mut Frame, app_state: &AppState, left_pane: Rect) {
             let name_to_display = if app_state.anonymize_torrent_names {
                 format!("Torrent {}", app_state.selected_torrent_index + 1)
             } else {
-                torrent.latest_state.torrent_name.clone()
+                torrent.latest_state.name().to_string()
             };
 
             let current_title_len: usize = title_spans.iter().map(|s| s.width()).sum();
@@ -789,7 +1068,7 @@ fn draw_stats_panel(f: &mut Frame, app_state: &AppState, settings: &Settings, st
         .sum::<usize>();
 
     let dl_speed = *app_state.avg_download_history.last().unwrap_or(&0);
-    let dl_limit = settings.global_download_limit_bps;
+    let (dl_limit, ul_limit) = settings.rate_limits(app_state.rate_profile);
 
     let mut dl_spans = vec![
         Span::styled("DL Speed: ", Style::default().fg(theme::SKY)),
@@ -809,7 +1088,6 @@ fn draw_stats_panel(f: &mut Frame, app_state: &AppState, settings: &Settings, st
     }
 
     let ul_speed = *app_state.avg_upload_history.last().unwrap_or(&0);
-    let ul_limit = settings.global_upload_limit_bps;
 
     let mut ul_spans = vec![
         Span::styled("UL Speed: ", Style::default().fg(theme::GREEN)),
@@ -865,7 +1143,23 @@ fn draw_stats_panel(f: &mut Frame, app_state: &AppState, settings: &Settings, st
         }
     }
 
-    let stats_text = vec![
+    // Combined progress of every torrent that still has pieces to get
+    let (downloading_count, downloading_written, downloading_size) = app_state
+        .torrents
+        .values()
+        .map(|torrent| &torrent.latest_state)
+        .filter(|state| {
+            state.total_size > 0 && state.number_of_pieces_completed < state.number_of_pieces_total
+        })
+        .fold((0usize, 0u64, 0u64), |(count, written, size), state| {
+            (
+                count + 1,
+                written + state.bytes_written.min(state.total_size),
+                size + state.total_size,
+            )
+        });
+
+    let mut stats_text = vec![
         Line::from(vec![
             Span::styled("Run Time: ", Style::default().fg(theme::TEAL)),
             Span::raw(format_time(app_state.run_time)),
@@ -874,6 +1168,44 @@ fn draw_stats_panel(f: &mut Frame, app_state: &AppState, settings: &Settings, st
             Span::styled("Torrents: ", Style::default().fg(theme::PEACH)),
             Span::raw(app_state.torrents.len().to_string()),
         ]),
+    ];
+    if downloading_count > 0 {
+        stats_text.push(Line::from(vec![
+            Span::styled("Overall: ", Style::default().fg(theme::PEACH)),
+            Span::raw(format!(
+                "{:.0}% ",
+                downloading_written as f64 / downloading_size as f64 * 100.0
+            )),
+            Span::styled(
+                format!(
+                    "({} / {} across {} downloading)",
+                    format_bytes(downloading_written),
+                    format_bytes(downloading_size),
+                    downloading_count
+                ),
+                Style::default().fg(theme::SUBTEXT0),
+            ),
+        ]));
+    }
+    if app_state.banned_peers > 0 {
+        stats_text.push(Line::from(vec![
+            Span::styled("Banned peers: ", Style::default().fg(theme::RED)),
+            Span::raw(app_state.banned_peers.to_string()),
+            Span::styled(" (B to lift)", Style::default().fg(theme::SUBTEXT0)),
+        ]));
+    }
+    if !settings.schedule.is_empty() {
+        let (profile_text, profile_color) = match app_state.rate_profile {
+            RateProfile::Normal => ("Normal limits", theme::SUBTEXT0),
+            RateProfile::Alternative => ("Alternative limits", theme::YELLOW),
+            RateProfile::Paused => ("Paused", theme::RED),
+        };
+        stats_text.push(Line::from(vec![
+            Span::styled("Schedule: ", Style::default().fg(theme::MAUVE)),
+            Span::styled(profile_text, Style::default().fg(profile_color)),
+        ]));
+    }
+    stats_text.extend([
         Line::from(""),
         Line::from(dl_spans),
         Line::from(vec![
@@ -898,10 +1230,30 @@ fn draw_stats_panel(f: &mut Frame, app_state: &AppState, settings: &Settings, st
                 app_state.lifetime_uploaded_from_config + app_state.session_total_uploaded,
             )),
         ]),
+        Line::from(vec![
+            Span::styled("Ratio: ", Style::default().fg(theme::GREEN)),
+            Span::raw(format_ratio(
+                app_state.session_total_uploaded,
+                app_state.session_total_downloaded,
+            )),
+            Span::styled(" session | ", Style::default().fg(theme::SUBTEXT0)),
+            Span::raw(format_ratio(
+                app_state.lifetime_uploaded_from_config + app_state.session_total_uploaded,
+                app_state.lifetime_downloaded_from_config + app_state.session_total_downloaded,
+            )),
+            Span::styled(" lifetime", Style::default().fg(theme::SUBTEXT0)),
+        ]),
         Line::from(""),
         Line::from(vec![
             Span::styled("CPU: ", Style::default().fg(theme::RED)),
             Span::raw(format!("{:.1}%", app_state.cpu_usage)),
+            Span::styled(
+                format!(
+                    " (hash {}/{})",
+                    app_state.hash_threads_busy, app_state.hash_threads
+                ),
+                Style::default().fg(theme::SUBTEXT0),
+            ),
         ]),
         Line::from(vec![
             Span::styled("RAM: ", Style::default().fg(theme::YELLOW)),
@@ -974,6 +1326,15 @@ fn draw_stats_panel(f: &mut Frame, app_state: &AppState, settings: &Settings, st
             Span::styled("Next Tuning in: ", Style::default().fg(theme::TEXT)),
             Span::raw(format!("{}s", app_state.tuning_countdown)),
         ]),
+        Line::from(vec![
+            Span::styled("Tune Score: ", Style::default().fg(theme::TEXT)),
+            Span::raw(format_speed(app_state.current_tuning_score)),
+            Span::styled(
+                format!(" / best {}", format_speed(app_state.last_tuning_score)),
+                Style::default().fg(theme::SUBTEXT0),
+            ),
+            format_score_delta(app_state.current_tuning_score, app_state.last_tuning_score),
+        ]),
         Line::from(vec![
             Span::styled("Disk Thrash: ", Style::default().fg(theme::TEAL)),
             Span::styled(thrash_text, thrash_style),
@@ -1015,15 +1376,35 @@ fn draw_stats_panel(f: &mut Frame, app_state: &AppState, settings: &Settings, st
                 app_state.last_tuning_limits.disk_write_permits,
             ),
         ]),
-    ];
+    ]);
+
+    let mut stats_block = Block::default()
+        .title("Stats")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme::SURFACE2));
+
+    if settings.show_clock {
+        let now = SystemTime::now();
+        let started_at = now - Duration::from_secs(app_state.run_time);
+        stats_text.insert(
+            1,
+            Line::from(vec![
+                Span::styled("Started: ", Style::default().fg(theme::TEAL)),
+                Span::raw(format_utc_timestamp(started_at, true)),
+                Span::styled(" UTC", Style::default().fg(theme::SUBTEXT0)),
+            ]),
+        );
+        stats_block = stats_block.title(
+            Line::from(Span::styled(
+                format!(" {} UTC ", format_utc_timestamp(now, false)),
+                Style::default().fg(theme::SUBTEXT1),
+            ))
+            .right_aligned(),
+        );
+    }
 
     let stats_paragraph = Paragraph::new(stats_text)
-        .block(
-            Block::default()
-                .title("Stats")
-                .borders(Borders::ALL)
-                .border_style(Style::default().fg(theme::SURFACE2)),
-        )
+        .block(stats_block)
         .style(Style::default().fg(theme::TEXT));
 
     f.render_widget(stats_paragraph, stats_chunk);
@@ -1032,6 +1413,7 @@ fn draw_stats_panel(f: &mut Frame, app_state: &AppState, settings: &Settings, st
 fn draw_right_pane(
     f: &mut Frame,
     app_state: &AppState,
+    settings: &Settings,
     details_text_chunk: Rect,
     peers_chunk: Rect,
 ) {
@@ -1083,7 +1465,11 @@ fn draw_right_pane(
                 .filled_style(Style::default().fg(theme::GREEN));
             f.render_widget(line_gauge, progress_chunks[1]);
 
-            let status_text = if state.activity_message.is_empty() {
+            let status_text = if let Some(error) = &torrent.load_error {
+                error.as_str()
+            } else if state.torrent_control_state == TorrentControlState::Queued {
+                "Queued, waiting for a free slot"
+            } else if state.activity_message.is_empty() {
                 "Waiting..."
             } else {
                 state.activity_message.as_str()
@@ -1096,15 +1482,47 @@ fn draw_right_pane(
                 detail_rows[1],
             );
 
-            f.render_widget(
-                Paragraph::new(Line::from(vec![
-                    Span::styled("Peers:    ", Style::default().fg(theme::TEXT)),
-                    Span::raw(state.number_of_successfully_connected_peers.to_string()),
-                ])),
-                detail_rows[2],
-            );
+            let mut peers_spans = vec![
+                Span::styled("Peers:    ", Style::default().fg(theme::TEXT)),
+                Span::raw(state.number_of_successfully_connected_peers.to_string()),
+            ];
+            if let Some(swarm) = state.swarm {
+                peers_spans.push(Span::styled(
+                    format!(" | Swarm: {}", format_swarm_counts(&swarm)),
+                    Style::default().fg(theme::SUBTEXT1),
+                ));
+            } else if let Some(dht_swarm) = state.dht_swarm.filter(|swarm| swarm.total() > 0) {
+                peers_spans.push(Span::styled(
+                    format!(" | Swarm: ~{} (DHT)", format_swarm_counts(&dht_swarm)),
+                    Style::default().fg(theme::SUBTEXT1),
+                ));
+            } else if let Some(dht_peers) = state.dht_peers.filter(|&n| n > 0) {
+                peers_spans.push(Span::styled(
+                    format!(" | Swarm: ~{} peers (DHT)", dht_peers),
+                    Style::default().fg(theme::SUBTEXT1),
+                ));
+            }
+            if state.bandwidth_priority != BandwidthPriority::Normal {
+                peers_spans.push(Span::styled(
+                    format!(" | {} priority", state.bandwidth_priority.label()),
+                    Style::default().fg(theme::SUBTEXT1),
+                ));
+            }
+            if state.upload_disabled || settings.upload_disabled {
+                peers_spans.push(Span::styled(
+                    " | Upload off",
+                    Style::default().fg(theme::YELLOW),
+                ));
+            }
+            if state.download_disabled || settings.download_disabled {
+                peers_spans.push(Span::styled(
+                    " | Download off",
+                    Style::default().fg(theme::YELLOW),
+                ));
+            }
+            f.render_widget(Paragraph::new(Line::from(peers_spans)), detail_rows[2]);
 
-            let written_size_spans =
+            let mut written_size_spans =
                 if state.number_of_pieces_completed < state.number_of_pieces_total {
                     vec![
                         Span::styled("Written:  ", Style::default().fg(theme::TEXT)),
@@ -1117,6 +1535,28 @@ fn draw_right_pane(
                         Span::raw(format_bytes(state.total_size)),
                     ]
                 };
+            if let Some(max_download_bytes) = state.max_download_bytes {
+                let capped_bytes = state.bytes_written.min(max_download_bytes);
+                let cap_percent = if max_download_bytes > 0 {
+                    capped_bytes as f64 / max_download_bytes as f64 * 100.0
+                } else {
+                    100.0
+                };
+                written_size_spans.push(Span::styled(
+                    format!(
+                        " | Cap: {} ({:.0}%)",
+                        format_bytes(max_download_bytes),
+                        cap_percent
+                    ),
+                    Style::default().fg(theme::YELLOW),
+                ));
+            }
+            if state.wasted_bytes > 0 {
+                written_size_spans.push(Span::styled(
+                    format!(" | Wasted: {}", format_bytes(state.wasted_bytes)),
+                    Style::default().fg(theme::PEACH),
+                ));
+            }
             f.render_widget(
                 Paragraph::new(Line::from(written_size_spans)),
                 detail_rows[3],
@@ -1133,140 +1573,192 @@ fn draw_right_pane(
                 detail_rows[4],
             );
 
-            f.render_widget(
-                Paragraph::new(Line::from(vec![
+            // Once done there is no ETA to show, so show how long it has seeded instead
+            let eta_spans = if state.number_of_pieces_total > 0
+                && state.number_of_pieces_completed >= state.number_of_pieces_total
+            {
+                let mut spans = vec![
+                    Span::styled("Seeded:   ", Style::default().fg(theme::TEXT)),
+                    Span::raw(format_time(state.seeding_time.as_secs())),
+                ];
+                let goals = state.seed_goals.as_ref().unwrap_or(&settings.seed_goals);
+                let targets: Vec<String> = goals
+                    .ratio
+                    .map(|ratio| format!("ratio {:.2}", ratio))
+                    .into_iter()
+                    .chain(goals.seed_time_secs.map(format_time))
+                    .collect();
+                if !targets.is_empty() {
+                    let eta = match state.seed_goal_eta(goals, torrent.smoothed_upload_speed_bps) {
+                        Some(Duration::ZERO) => " (reached)".to_string(),
+                        Some(eta) => format!(" (in {})", format_duration(eta)),
+                        None => String::new(),
+                    };
+                    spans.push(Span::styled(
+                        format!(
+                            " | Goal: {}, then {}{}",
+                            targets.join(" or "),
+                            goals.action.describe(),
+                            eta
+                        ),
+                        Style::default().fg(theme::SUBTEXT1),
+                    ));
+                }
+                spans
+            } else {
+                vec![
                     Span::styled("ETA:      ", Style::default().fg(theme::TEXT)),
                     Span::raw(format_duration(state.eta)),
-                ])),
-                detail_rows[5],
-            );
+                ]
+            };
+            f.render_widget(Paragraph::new(Line::from(eta_spans)), detail_rows[5]);
 
-            f.render_widget(
-                Paragraph::new(Line::from(vec![
-                    Span::styled("Announce: ", Style::default().fg(theme::TEXT)),
-                    Span::raw(format_countdown(state.next_announce_in)),
-                ])),
-                detail_rows[6],
-            );
+            let mut announce_spans = vec![
+                Span::styled("Announce: ", Style::default().fg(theme::TEXT)),
+                Span::raw(format_countdown(state.next_announce_in)),
+            ];
+            // Only worth flagging when no tracker is answering at all
+            if !state.trackers.is_empty() && state.trackers.iter().all(|t| t.last_error.is_some()) {
+                if let Some(error) = state.trackers.iter().find_map(|t| t.last_error.as_deref()) {
+                    announce_spans.push(Span::styled(
+                        format!(" | tracker error: {}", error),
+                        Style::default().fg(theme::RED),
+                    ));
+                }
+            }
+            if let Some(next_dht_refresh_in) = state.next_dht_refresh_in {
+                announce_spans.push(Span::styled(" | DHT: ", Style::default().fg(theme::TEXT)));
+                announce_spans.push(Span::raw(format_countdown(next_dht_refresh_in)));
+            }
+            f.render_widget(Paragraph::new(Line::from(announce_spans)), detail_rows[6]);
 
-            let has_established_peers =
-                state.peers.iter().any(|p| p.last_action != "Connecting...");
+            let peers_to_display = displayed_peers(state, app_state.peer_sort);
 
-            let mut peers_to_display: Vec<PeerInfo> = if has_established_peers {
-                state
-                    .peers
-                    .iter()
-                    .filter(|p| p.last_action != "Connecting...")
-                    .cloned()
-                    .collect()
+            let peer_border_style = if matches!(app_state.selected_header, SelectedHeader::Peer(_))
+            {
+                Style::default().fg(theme::MAUVE)
             } else {
-                state.peers.clone()
+                Style::default().fg(theme::SURFACE2)
             };
 
-            let (sort_by, sort_direction) = app_state.peer_sort;
-            peers_to_display.sort_by(|a, b| {
-                let ordering = match sort_by {
-                    PeerSortColumn::Flags => {
-                        let mut a_score = 0;
-                        if !a.peer_choking {
-                            a_score += 2;
-                        }
-                        if !a.am_choking {
-                            a_score += 1;
-                        }
-                        let mut b_score = 0;
-                        if !b.peer_choking {
-                            b_score += 2;
+            let peers_chunk = match &app_state.geoip {
+                Some(geoip) if !peers_to_display.is_empty() => {
+                    let geo_chunks = Layout::vertical([Constraint::Length(1), Constraint::Min(0)])
+                        .split(peers_chunk);
+                    let top_labels = geoip::top_labels(&peers_to_display, 5);
+                    let title = if geoip.is_asn() {
+                        " Top networks: "
+                    } else {
+                        " Top countries: "
+                    };
+                    let mut geo_spans =
+                        vec![Span::styled(title, Style::default().fg(theme::YELLOW))];
+                    for (i, stats) in top_labels.iter().enumerate() {
+                        if i > 0 {
+                            geo_spans
+                                .push(Span::styled(" · ", Style::default().fg(theme::SURFACE2)));
                         }
-                        if !b.am_choking {
-                            b_score += 1;
+                        geo_spans.push(Span::styled(
+                            stats.label.clone(),
+                            Style::default().fg(theme::SAPPHIRE),
+                        ));
+                        geo_spans.push(Span::raw(format!(" {}", stats.peers)));
+                        if stats.download_speed_bps > 0 {
+                            geo_spans.push(Span::styled(
+                                format!(" ↓{}", format_speed(stats.download_speed_bps)),
+                                Style::default().fg(theme::BLUE),
+                            ));
                         }
-                        b_score.cmp(&a_score)
-                    }
-                    PeerSortColumn::Completed => {
-                        let total_pieces = state.number_of_pieces_total as usize;
-                        if total_pieces == 0 {
-                            return std::cmp::Ordering::Equal;
+                        if stats.upload_speed_bps > 0 {
+                            geo_spans.push(Span::styled(
+                                format!(" ↑{}", format_speed(stats.upload_speed_bps)),
+                                Style::default().fg(theme::GREEN),
+                            ));
                         }
-                        let a_completed =
-                            a.bitfield.iter().take(total_pieces).filter(|&&h| h).count();
-                        let a_percent = a_completed as f64 / total_pieces as f64;
-                        let b_completed =
-                            b.bitfield.iter().take(total_pieces).filter(|&&h| h).count();
-                        let b_percent = b_completed as f64 / total_pieces as f64;
-                        b_percent.total_cmp(&a_percent)
                     }
-                    PeerSortColumn::Address => a.address.cmp(&b.address),
-                    PeerSortColumn::Client => a.peer_id.cmp(&b.peer_id),
-                    PeerSortColumn::Action => a.last_action.cmp(&b.last_action),
-                    PeerSortColumn::DL => a.download_speed_bps.cmp(&b.download_speed_bps),
-                    PeerSortColumn::UL => a.upload_speed_bps.cmp(&b.upload_speed_bps),
-                    PeerSortColumn::TotalDL => a.total_downloaded.cmp(&b.total_downloaded),
-                    PeerSortColumn::TotalUL => a.total_uploaded.cmp(&b.total_uploaded),
-                };
-
-                if sort_direction == SortDirection::Ascending {
-                    ordering
-                } else {
-                    ordering.reverse()
+                    f.render_widget(
+                        Paragraph::new(Line::from(geo_spans))
+                            .style(Style::default().fg(theme::SUBTEXT1)),
+                        geo_chunks[0],
+                    );
+                    geo_chunks[1]
                 }
-            });
-
-            let peer_border_style = if matches!(app_state.selected_header, SelectedHeader::Peer(_))
-            {
-                Style::default().fg(theme::MAUVE)
-            } else {
-                Style::default().fg(theme::SURFACE2)
+                _ => peers_chunk,
             };
 
             if peers_to_display.is_empty() {
                 draw_swarm_heatmap(f, &state.peers, state.number_of_pieces_total, peers_chunk);
+            } else if peers_chunk.height < MIN_PEER_TABLE_HEIGHT {
+                // Too short for a readable table, so collapse it into a single line.
+                let summary = Paragraph::new(Line::from(vec![
+                    Span::styled(" Peers ", Style::default().fg(theme::YELLOW)),
+                    Span::raw(format_peers_summary(&peers_to_display)),
+                ]))
+                .style(Style::default().fg(theme::SUBTEXT1));
+                f.render_widget(summary, peers_chunk);
             } else {
-                let peer_header_cells = PEER_HEADERS.iter().enumerate().map(|(i, h)| {
-                    let is_selected = app_state.selected_header == SelectedHeader::Peer(i);
-                    let (sort_col, sort_dir) = app_state.peer_sort;
-                    let is_sorting_by_this = sort_col == *h;
-                    let mut style = Style::default().fg(theme::YELLOW);
-                    let text = match h {
-                        PeerSortColumn::Flags => "Flags",
-                        PeerSortColumn::Address => "Address",
-                        PeerSortColumn::Client => "Client",
-                        PeerSortColumn::Action => "Action",
-                        PeerSortColumn::Completed => "Done %",
-                        PeerSortColumn::DL => "DL Speed",
-                        PeerSortColumn::UL => "UL Speed",
-                        PeerSortColumn::TotalDL => "Total DL",
-                        PeerSortColumn::TotalUL => "Total UL",
-                    };
-
-                    let mut text_with_indicator = text.to_string();
-                    if is_sorting_by_this {
-                        style = style.fg(theme::MAUVE);
-                        let indicator = if sort_dir == SortDirection::Ascending {
-                            " ▲"
-                        } else {
-                            " ▼"
-                        };
-                        text_with_indicator.push_str(indicator);
-                    }
-                    let mut text_span = Span::styled(text, style);
-                    if is_selected {
-                        text_span = text_span.underlined().bold();
-                    }
-                    let mut spans = vec![text_span];
-                    if is_sorting_by_this {
-                        let indicator = if sort_dir == SortDirection::Ascending {
-                            " ▲"
-                        } else {
-                            " ▼"
-                        };
-                        spans.push(Span::styled(indicator, style));
-                    }
-                    Cell::from(Line::from(spans))
-                });
+                let peer_columns = settings.peer_table_columns();
+                let peer_header_cells =
+                    peer_columns
+                        .iter()
+                        .map(|c| &c.column)
+                        .enumerate()
+                        .map(|(i, h)| {
+                            let is_selected = app_state.selected_header == SelectedHeader::Peer(i);
+                            let (sort_col, sort_dir) = app_state.peer_sort;
+                            let is_sorting_by_this = sort_col == *h;
+                            let mut style = Style::default().fg(theme::YELLOW);
+                            let text = match h {
+                                PeerSortColumn::Flags => "Flags",
+                                PeerSortColumn::Address => "Address",
+                                PeerSortColumn::Client => "Client",
+                                PeerSortColumn::Action => "Action",
+                                PeerSortColumn::Completed => "Done %",
+                                PeerSortColumn::DL => "DL Speed",
+                                PeerSortColumn::UL => "UL Speed",
+                                PeerSortColumn::TotalDL => "Total DL",
+                                PeerSortColumn::TotalUL => "Total UL",
+                                PeerSortColumn::Country => match &app_state.geoip {
+                                    Some(geoip) if geoip.is_asn() => "Network",
+                                    _ => "Country",
+                                },
+                                PeerSortColumn::Source => "From",
+                            };
+
+                            let mut text_with_indicator = text.to_string();
+                            if is_sorting_by_this {
+                                style = style.fg(theme::MAUVE);
+                                let indicator = if sort_dir == SortDirection::Ascending {
+                                    " ▲"
+                                } else {
+                                    " ▼"
+                                };
+                                text_with_indicator.push_str(indicator);
+                            }
+                            let mut text_span = Span::styled(text, style);
+                            if is_selected {
+                                text_span = text_span.underlined().bold();
+                            }
+                            let mut spans = vec![text_span];
+                            if is_sorting_by_this {
+                                let indicator = if sort_dir == SortDirection::Ascending {
+                                    " ▲"
+                                } else {
+                                    " ▼"
+                                };
+                                spans.push(Span::styled(indicator, style));
+                            }
+                            Cell::from(Line::from(spans))
+                        });
                 let peer_header = Row::new(peer_header_cells).height(1);
 
-                let peer_rows = peers_to_display.iter().map(|peer| {
+                let selected_peer = matches!(app_state.selected_header, SelectedHeader::Peer(_))
+                    .then(|| {
+                        app_state
+                            .selected_peer_index
+                            .min(peers_to_display.len() - 1)
+                    });
+                let peer_rows = peers_to_display.iter().enumerate().map(|(i, peer)| {
                     let row_color = if peer.download_speed_bps == 0 && peer.upload_speed_bps == 0 {
                         theme::SURFACE1
                     } else {
@@ -1314,7 +1806,7 @@ fn draw_right_pane(
                             .bitfield
                             .iter()
                             .take(total_pieces_from_torrent)
-                            .filter(|&&have| have)
+                            .filter(|&have| have)
                             .count();
                         if completed_pieces == total_pieces_from_torrent {
                             100.0
@@ -1324,31 +1816,37 @@ fn draw_right_pane(
                     } else {
                         0.0
                     };
-                    Row::new(vec![
-                        Cell::from(flags_spans),
-                        Cell::from(peer.address.clone()),
-                        Cell::from(parse_peer_id(&peer.peer_id)),
-                        Cell::from(peer.last_action.clone()),
-                        Cell::from(format!("{:.1}%", percentage)),
-                        Cell::from(format_speed(peer.download_speed_bps)),
-                        Cell::from(format_speed(peer.upload_speed_bps)),
-                        Cell::from(format_bytes(peer.total_downloaded)),
-                        Cell::from(format_bytes(peer.total_uploaded)),
-                    ])
-                    .style(Style::default().fg(row_color))
+                    Row::new(peer_columns.iter().map(|c| match c.column {
+                        PeerSortColumn::Flags => Cell::from(flags_spans.clone()),
+                        PeerSortColumn::Address => Cell::from(format_peer_address(
+                            &peer.address,
+                            (peers_chunk.width as usize * c.width as usize) / 100,
+                        )),
+                        PeerSortColumn::Client => Cell::from(parse_peer_id(&peer.peer_id)),
+                        PeerSortColumn::Action => Cell::from(peer.last_action.clone()),
+                        PeerSortColumn::Completed => Cell::from(format!("{:.1}%", percentage)),
+                        PeerSortColumn::DL => Cell::from(format_speed(peer.download_speed_bps)),
+                        PeerSortColumn::UL => Cell::from(format_speed(peer.upload_speed_bps)),
+                        PeerSortColumn::TotalDL => Cell::from(format_bytes(peer.total_downloaded)),
+                        PeerSortColumn::TotalUL => Cell::from(format_bytes(peer.total_uploaded)),
+                        PeerSortColumn::Country => {
+                            Cell::from(peer.country.clone().unwrap_or_default())
+                        }
+                        PeerSortColumn::Source => Cell::from(peer.source.label()),
+                    }))
+                    .style(if selected_peer == Some(i) {
+                        Style::default()
+                            .fg(row_color)
+                            .add_modifier(Modifier::REVERSED)
+                    } else {
+                        Style::default().fg(row_color)
+                    })
                 });
 
-                let peer_widths = [
-                    Constraint::Length(5),
-                    Constraint::Percentage(20),
-                    Constraint::Percentage(15),
-                    Constraint::Percentage(20),
-                    Constraint::Percentage(5),
-                    Constraint::Percentage(10),
-                    Constraint::Percentage(10),
-                    Constraint::Percentage(10),
-                    Constraint::Percentage(5),
-                ];
+                let peer_widths: Vec<Constraint> = peer_columns
+                    .iter()
+                    .map(|c| Constraint::Percentage(c.width))
+                    .collect();
 
                 let peers_table = Table::new(peer_rows, peer_widths)
                     .header(peer_header)
@@ -1401,8 +1899,8 @@ fn draw_footer(f: &mut Frame, app_state: &AppState, settings: &Settings, footer_
         .direction(Direction::Horizontal)
         .constraints([
             Constraint::Percentage(25),
-            Constraint::Percentage(60),
-            Constraint::Percentage(15),
+            Constraint::Percentage(55),
+            Constraint::Percentage(20),
         ])
         .split(footer_chunk);
 
@@ -1490,6 +1988,23 @@ fn draw_footer(f: &mut Frame, app_state: &AppState, settings: &Settings, footer_
         Span::raw("search | "),
     ]);
     footer_spans.extend(help_key);
+    if app_state.all_paused.is_some() {
+        footer_spans.spans.splice(
+            0..0,
+            [
+                Span::styled(
+                    " ALL PAUSED ",
+                    Style::default()
+                        .fg(theme::SURFACE0)
+                        .bg(theme::RED)
+                        .add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(" "),
+                Span::styled("[P]", Style::default().fg(theme::GREEN)),
+                Span::raw(" resume all | "),
+            ],
+        );
+    }
 
     let footer_keys = footer_spans.alignment(Alignment::Center);
     let footer_paragraph = Paragraph::new(footer_keys).style(Style::default().fg(theme::SUBTEXT1));
@@ -1506,12 +2021,24 @@ fn draw_footer(f: &mut Frame, app_state: &AppState, settings: &Settings, footer_
         "Closed"
     };
 
+    // When the port was last checked
+    let checked_text = if app_state.port_check_running {
+        " (checking)".to_string()
+    } else {
+        match app_state.port_checked_at.map(|at| at.elapsed()) {
+            Some(elapsed) if elapsed < Duration::from_secs(60) => " (now)".to_string(),
+            Some(elapsed) => format!(" ({} ago)", format_duration(elapsed)),
+            None => String::new(),
+        }
+    };
+
     let footer_status = Line::from(vec![
         Span::raw("Port: "),
         Span::styled(settings.client_port.to_string(), port_style),
         Span::raw(" ["),
         Span::styled(port_text, port_style),
         Span::raw("]"),
+        Span::styled(checked_text, Style::default().fg(theme::OVERLAY0)),
     ])
     .alignment(Alignment::Right);
 
@@ -1520,6 +2047,40 @@ fn draw_footer(f: &mut Frame, app_state: &AppState, settings: &Settings, footer_
     f.render_widget(status_paragraph, status_chunk);
 }
 
+/// Draws recent status messages as a small box in the bottom-right corner, just above
+/// the footer. Newest message is last.
+fn draw_status_messages(f: &mut Frame, app_state: &AppState, footer_chunk: Rect) {
+    if app_state.status_messages.is_empty() {
+        return;
+    }
+
+    let lines: Vec<Line> = app_state
+        .status_messages
+        .iter()
+        .map(|message| Line::from(message.text.as_str()))
+        .collect();
+
+    let content_width = lines.iter().map(|line| line.width()).max().unwrap_or(0) as u16;
+    let width = (content_width + 2).min(footer_chunk.width / 2);
+    let height = (lines.len() as u16 + 2).min(footer_chunk.y);
+    let area = Rect {
+        x: footer_chunk.right().saturating_sub(width),
+        y: footer_chunk.y.saturating_sub(height),
+        width,
+        height,
+    };
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme::SURFACE2));
+    let paragraph = Paragraph::new(lines)
+        .block(block)
+        .style(Style::default().fg(theme::SUBTEXT1));
+
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
+}
+
 fn draw_config_screen(
     f: &mut Frame,
     settings: &Settings,
@@ -1564,10 +2125,19 @@ fn draw_config_screen(
                 "Default Download Folder",
                 path_to_string(settings.default_download_folder.as_deref()),
             ),
-            ConfigItem::WatchFolder => (
-                "Torrent Watch Folder",
-                path_to_string(settings.watch_folder.as_deref()),
-            ),
+            ConfigItem::WatchFolder => {
+                let first = path_to_string(
+                    settings
+                        .watch_folders
+                        .first()
+                        .map(|rule| rule.path.as_path()),
+                );
+                let value = match settings.watch_folders.len() {
+                    0 | 1 => first,
+                    n => format!("{} (+{} more)", first, n - 1),
+                };
+                ("Torrent Watch Folder", value)
+            }
             ConfigItem::GlobalDownloadLimit => (
                 "Global DL Limit",
                 format_limit_bps(settings.global_download_limit_bps),
@@ -1576,6 +2146,14 @@ fn draw_config_screen(
                 "Global UL Limit",
                 format_limit_bps(settings.global_upload_limit_bps),
             ),
+            ConfigItem::PerformanceProfile => {
+                let value = match settings.performance_profile {
+                    PerformanceProfile::MaxSpeed => "Max Speed",
+                    PerformanceProfile::Balanced => "Balanced",
+                    PerformanceProfile::QuietDisk => "Quiet Disk",
+                };
+                ("Performance Profile", value.to_string())
+            }
         };
 
         // Create two columns for the name and value
@@ -1649,6 +2227,570 @@ fn draw_config_screen(
     f.render_widget(footer_paragraph, footer_area);
 }
 
+fn draw_create_torrent_screen(
+    f: &mut Frame,
+    options: &CreateOptions,
+    seed: bool,
+    selected_index: usize,
+    editing: &Option<(CreateItem, String)>,
+) {
+    let area = centered_rect(80, 60, f.area());
+    f.render_widget(Clear, f.area());
+
+    let block = Block::default()
+        .title(Span::styled(
+            format!("Create Torrent - {}", options.path.display()),
+            Style::default().fg(theme::MAUVE),
+        ))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme::SURFACE2));
+
+    let inner_area = block.inner(area);
+    f.render_widget(block, area);
+
+    let chunks = Layout::vertical([Constraint::Min(0), Constraint::Length(2)]).split(inner_area);
+    let rows_layout =
+        Layout::vertical(CreateItem::ALL.map(|_| Constraint::Length(1))).split(chunks[0]);
+
+    let list_or_none = |list: &[String]| {
+        if list.is_empty() {
+            "None".to_string()
+        } else {
+            list.join(", ")
+        }
+    };
+    let yes_no = |value: bool| if value { "Yes" } else { "No" };
+
+    for (i, item) in CreateItem::ALL.iter().enumerate() {
+        let (name_str, value_str) = match item {
+            CreateItem::Trackers => ("Trackers", list_or_none(&options.trackers)),
+            CreateItem::WebSeeds => ("Web Seeds", list_or_none(&options.web_seeds)),
+            CreateItem::PieceSize => (
+                "Piece Size",
+                options
+                    .piece_length
+                    .map(format_bytes)
+                    .unwrap_or_else(|| "Auto".to_string()),
+            ),
+            CreateItem::Version => ("Version", options.version.to_string()),
+            CreateItem::Private => ("Private", yes_no(options.private).to_string()),
+            CreateItem::Comment => (
+                "Comment",
+                options
+                    .comment
+                    .clone()
+                    .unwrap_or_else(|| "None".to_string()),
+            ),
+            CreateItem::Seed => ("Seed After Creating", yes_no(seed).to_string()),
+            CreateItem::Create => ("Create Torrent", String::new()),
+        };
+
+        let columns = Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+            .split(rows_layout[i]);
+
+        let is_highlighted = match editing {
+            Some((edited_item, _)) => edited_item == item,
+            None => i == selected_index,
+        };
+        let row_style = if is_highlighted {
+            Style::default().fg(theme::YELLOW)
+        } else {
+            Style::default().fg(theme::TEXT)
+        };
+        let selector = if is_highlighted { "▶" } else { " " };
+        f.render_widget(
+            Paragraph::new(format!("{} {}", selector, name_str)).style(row_style),
+            columns[0],
+        );
+
+        match editing {
+            Some((_, buffer)) if is_highlighted => {
+                f.set_cursor_position((columns[1].x + buffer.len() as u16, columns[1].y));
+                f.render_widget(Paragraph::new(buffer.as_str()).style(row_style), columns[1]);
+            }
+            _ => f.render_widget(Paragraph::new(value_str).style(row_style), columns[1]),
+        }
+    }
+
+    let help_text = if editing.is_some() {
+        Line::from(vec![
+            Span::raw("Separate URLs with spaces. "),
+            Span::styled("[Enter]", Style::default().fg(theme::GREEN)),
+            Span::raw(" to confirm, "),
+            Span::styled("[Esc]", Style::default().fg(theme::RED)),
+            Span::raw(" to cancel."),
+        ])
+    } else {
+        Line::from(vec![
+            Span::raw("Use "),
+            Span::styled("↑/↓/k/j", Style::default().fg(theme::YELLOW)),
+            Span::raw(" to navigate. "),
+            Span::styled("[Enter]", Style::default().fg(theme::YELLOW)),
+            Span::raw(" to edit or change. "),
+            Span::styled("[Esc]|[q]", Style::default().fg(theme::RED)),
+            Span::raw(" to cancel."),
+        ])
+    };
+    let footer_paragraph = Paragraph::new(help_text)
+        .alignment(Alignment::Center)
+        .style(Style::default().fg(theme::SUBTEXT1));
+    f.render_widget(footer_paragraph, chunks[1]);
+}
+
+fn draw_add_torrent_screen(
+    f: &mut Frame,
+    settings: &Settings,
+    input: &str,
+    options: &AddOptions,
+    selected_index: usize,
+) {
+    let area = centered_rect(80, 40, f.area());
+    f.render_widget(Clear, f.area());
+
+    let block = Block::default()
+        .title(Span::styled(
+            format!("Add Torrent - {}", input),
+            Style::default().fg(theme::MAUVE),
+        ))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme::SURFACE2));
+
+    let inner_area = block.inner(area);
+    f.render_widget(block, area);
+
+    let chunks = Layout::vertical([Constraint::Min(0), Constraint::Length(2)]).split(inner_area);
+    let rows_layout =
+        Layout::vertical(AddItem::ALL.map(|_| Constraint::Length(1))).split(chunks[0]);
+
+    // The folder it will download to: the one picked here, the category's, or the default
+    let download_folder = options
+        .download_path
+        .clone()
+        .or_else(|| {
+            options
+                .category
+                .as_deref()
+                .and_then(|name| settings.category_download_folder(name))
+        })
+        .or_else(|| settings.default_download_folder.clone())
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|| "Not set".to_string());
+    let yes_no = |value: bool| if value { "Yes" } else { "No" };
+
+    for (i, item) in AddItem::ALL.iter().enumerate() {
+        let (name_str, value_str) = match item {
+            AddItem::DownloadFolder => ("Download Folder", download_folder.clone()),
+            AddItem::Category => (
+                "Category",
+                options
+                    .category
+                    .clone()
+                    .unwrap_or_else(|| "None".to_string()),
+            ),
+            AddItem::Paused => ("Start Paused", yes_no(options.paused).to_string()),
+            AddItem::Sequential => ("Sequential", yes_no(options.sequential).to_string()),
+            AddItem::SkipCheck => ("Skip Hash Check", yes_no(options.skip_check).to_string()),
+            AddItem::Add => ("Add Torrent", String::new()),
+        };
+
+        let columns = Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+            .split(rows_layout[i]);
+
+        let row_style = if i == selected_index {
+            Style::default().fg(theme::YELLOW)
+        } else {
+            Style::default().fg(theme::TEXT)
+        };
+        let selector = if i == selected_index { "▶" } else { " " };
+        f.render_widget(
+            Paragraph::new(format!("{} {}", selector, name_str)).style(row_style),
+            columns[0],
+        );
+        f.render_widget(Paragraph::new(value_str).style(row_style), columns[1]);
+    }
+
+    let help_text = Line::from(vec![
+        Span::raw("Use "),
+        Span::styled("↑/↓/k/j", Style::default().fg(theme::YELLOW)),
+        Span::raw(" to navigate. "),
+        Span::styled("[Enter]", Style::default().fg(theme::YELLOW)),
+        Span::raw(" to change or add. "),
+        Span::styled("[Esc]|[q]", Style::default().fg(theme::RED)),
+        Span::raw(" to cancel."),
+    ]);
+    let footer_paragraph = Paragraph::new(help_text)
+        .alignment(Alignment::Center)
+        .style(Style::default().fg(theme::SUBTEXT1));
+    f.render_widget(footer_paragraph, chunks[1]);
+}
+
+/// Footer for the folder pickers: key hints, or the last navigation error in their place.
+/// `relocating` lists the ways of moving a torrent in place of a plain confirm.
+fn draw_file_picker_footer(f: &mut Frame, area: Rect, error: Option<&str>, relocating: bool) {
+    let mut spans = if relocating {
+        vec![
+            Span::styled("[Tab]", Style::default().fg(theme::GREEN)),
+            Span::raw(" Move here | "),
+            Span::styled("[V]", Style::default().fg(theme::GREEN)),
+            Span::raw(" Move & verify | "),
+            Span::styled("[L]", Style::default().fg(theme::YELLOW)),
+            Span::raw(" Files already here | "),
+        ]
+    } else {
+        vec![
+            Span::styled("[Tab]", Style::default().fg(theme::GREEN)),
+            Span::raw(" Confirm | "),
+        ]
+    };
+    let footer_text = match error {
+        Some(error) => Line::from(Span::styled(
+            error.to_string(),
+            Style::default().fg(theme::RED),
+        )),
+        None => {
+            spans.extend([
+                Span::styled("[Esc]", Style::default().fg(theme::RED)),
+                Span::raw(" Cancel | "),
+                Span::styled("←→↑↓", Style::default().fg(theme::BLUE)),
+                Span::raw(" Navigate"),
+            ]);
+            Line::from(spans)
+        }
+    }
+    .alignment(Alignment::Center);
+
+    let footer_paragraph = Paragraph::new(footer_text).style(Style::default().fg(theme::SUBTEXT1));
+    f.render_widget(footer_paragraph, area);
+}
+
+fn draw_network_audit_screen(
+    f: &mut Frame,
+    app_state: &AppState,
+    settings: &Settings,
+    scroll_offset: u16,
+) {
+    let area = centered_rect(80, 80, f.area());
+    f.render_widget(Clear, f.area());
+
+    let block = Block::default()
+        .title(Span::styled(
+            "Network Audit",
+            Style::default().fg(theme::MAUVE),
+        ))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme::SURFACE2));
+
+    let inner_area = block.inner(area);
+    f.render_widget(block, area);
+
+    let chunks = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).split(inner_area);
+
+    let on_off = |enabled: bool| -> Span<'static> {
+        if enabled {
+            Span::styled("ON", Style::default().fg(theme::GREEN))
+        } else {
+            Span::styled("OFF", Style::default().fg(theme::SUBTEXT0))
+        }
+    };
+
+    let mut lines: Vec<Line> = Vec::new();
+
+    lines.push(Line::from(Span::styled(
+        "DHT",
+        Style::default().fg(theme::YELLOW),
+    )));
+    if cfg!(feature = "dht") {
+        let mode_span = match settings.dht_mode {
+            DhtMode::Server => Span::styled("Server", Style::default().fg(theme::GREEN)),
+            DhtMode::ReadOnly => Span::styled("Read-only", Style::default().fg(theme::YELLOW)),
+            DhtMode::Off => Span::styled("Off", Style::default().fg(theme::SUBTEXT0)),
+        };
+        lines.push(Line::from(vec![Span::raw("  Mode: "), mode_span]));
+        if let Some(dht) = &app_state.dht_status {
+            let announcing = app_state
+                .torrents
+                .values()
+                .filter(|t| t.latest_state.next_dht_refresh_in.is_some())
+                .count();
+            let public_address = match dht.public_address {
+                Some(addr) if dht.firewalled => format!("{} (firewalled)", addr),
+                Some(addr) => addr.to_string(),
+                None => "Unknown".to_string(),
+            };
+            lines.push(Line::from(format!(
+                "  Routing table: {} nodes   DHT size: ~{} nodes   Incoming queries: {:.1}/s",
+                dht.routing_table_nodes, dht.network_size_estimate, dht.queries_per_sec
+            )));
+            lines.push(Line::from(format!(
+                "  Local port: {}   Public address: {}{}",
+                dht.local_port,
+                public_address,
+                if dht.server_mode {
+                    ""
+                } else {
+                    "   (client only)"
+                }
+            )));
+            lines.push(Line::from(format!(
+                "  Torrents using the DHT: {}",
+                announcing
+            )));
+        }
+        if settings.bootstrap_nodes.is_empty() {
+            lines.push(Line::from(Span::styled(
+                "  None configured",
+                Style::default().fg(theme::SUBTEXT0),
+            )));
+        }
+        for node in &settings.bootstrap_nodes {
+            lines.push(Line::from(vec![
+                Span::raw("  "),
+                Span::styled(node.clone(), Style::default().fg(theme::TEXT)),
+            ]));
+        }
+    } else {
+        lines.push(Line::from(Span::styled(
+            "  DHT is not included in this [PRIVATE] build of superseedr.",
+            Style::default().fg(theme::SUBTEXT0),
+        )));
+    }
+    lines.push(Line::from(""));
+
+    lines.push(Line::from(Span::styled(
+        "Torrents",
+        Style::default().fg(theme::YELLOW),
+    )));
+    if app_state.torrent_list_order.is_empty() {
+        lines.push(Line::from(Span::styled(
+            "  No torrents loaded",
+            Style::default().fg(theme::SUBTEXT0),
+        )));
+    }
+
+    for (i, info_hash) in app_state.torrent_list_order.iter().enumerate() {
+        let Some(torrent) = app_state.torrents.get(info_hash) else {
+            continue;
+        };
+        let state = &torrent.latest_state;
+
+        let name_to_display = if app_state.anonymize_torrent_names {
+            format!("Torrent {}", i + 1)
+        } else {
+            state.name().to_string()
+        };
+        lines.push(Line::from(Span::styled(
+            format!("  {}", name_to_display),
+            Style::default()
+                .fg(theme::TEXT)
+                .add_modifier(Modifier::BOLD),
+        )));
+
+        // Private torrents must only ever talk to their own trackers (BEP 27).
+        let dht_enabled = cfg!(feature = "dht")
+            && !state.is_private
+            && !state.dht_disabled
+            && settings.dht_mode != DhtMode::Off;
+        let pex_enabled = cfg!(feature = "pex") && !state.is_private && !state.pex_disabled;
+
+        let mut flags_line = vec![
+            Span::raw("    Private: "),
+            if state.is_private {
+                Span::styled("Yes", Style::default().fg(theme::PEACH))
+            } else {
+                Span::styled("No", Style::default().fg(theme::SUBTEXT0))
+            },
+            Span::raw(" | DHT: "),
+            if dht_enabled && settings.dht_mode == DhtMode::ReadOnly {
+                Span::styled("READ-ONLY", Style::default().fg(theme::YELLOW))
+            } else {
+                on_off(dht_enabled)
+            },
+            Span::raw(" | PEX: "),
+            on_off(pex_enabled),
+            Span::raw(" | LSD: "),
+            on_off(false),
+        ];
+        if state.is_private {
+            if !dht_enabled && !pex_enabled {
+                flags_line.push(Span::styled(
+                    "  ✓ Trackers only",
+                    Style::default().fg(theme::GREEN),
+                ));
+            } else {
+                flags_line.push(Span::styled(
+                    "  ✗ Contacts more than its trackers",
+                    Style::default().fg(theme::RED),
+                ));
+            }
+        }
+        lines.push(Line::from(flags_line));
+
+        if state.number_of_pieces_total == 0 {
+            lines.push(Line::from(Span::styled(
+                "    Waiting for metadata...",
+                Style::default().fg(theme::SUBTEXT0),
+            )));
+        } else if state.trackers.is_empty() {
+            lines.push(Line::from(Span::styled(
+                "    No trackers",
+                Style::default().fg(theme::SUBTEXT0),
+            )));
+        }
+
+        for tracker in &state.trackers {
+            let url_to_display = if app_state.anonymize_torrent_names {
+                "https://tracker.example/announce".to_string()
+            } else {
+                tracker.url.clone()
+            };
+            let interval_str = tracker
+                .interval
+                .map_or_else(|| "N/A".to_string(), |d| format_time(d.as_secs()));
+            let swarm_str = tracker
+                .swarm
+                .map_or_else(|| "N/A".to_string(), |s| format_swarm_counts(&s));
+            lines.push(Line::from(vec![
+                Span::raw("    "),
+                Span::styled(url_to_display, Style::default().fg(theme::SAPPHIRE)),
+                Span::styled(
+                    format!(
+                        "  next: {}  interval: {}  swarm: {}",
+                        format_countdown(tracker.next_announce_in),
+                        interval_str,
+                        swarm_str
+                    ),
+                    Style::default().fg(theme::SUBTEXT1),
+                ),
+            ]));
+            if let Some(error) = &tracker.last_error {
+                lines.push(Line::from(Span::styled(
+                    format!(
+                        "      tracker error: {} (failed {}x)",
+                        error, tracker.failures
+                    ),
+                    Style::default().fg(theme::RED),
+                )));
+            }
+        }
+        lines.push(Line::from(""));
+    }
+
+    let max_scroll = (lines.len() as u16).saturating_sub(chunks[0].height);
+    let audit_paragraph = Paragraph::new(lines)
+        .style(Style::default().fg(theme::TEXT))
+        .scroll((scroll_offset.min(max_scroll), 0));
+    f.render_widget(audit_paragraph, chunks[0]);
+
+    let footer_text = Line::from(vec![
+        Span::styled("↑↓", Style::default().fg(theme::BLUE)),
+        Span::raw(" Scroll | "),
+        Span::styled("[Esc]|[q]", Style::default().fg(theme::GREEN)),
+        Span::raw(" Back"),
+    ])
+    .alignment(Alignment::Center);
+    let footer_paragraph = Paragraph::new(footer_text).style(Style::default().fg(theme::SUBTEXT1));
+    f.render_widget(footer_paragraph, chunks[1]);
+}
+
+fn draw_files_screen(f: &mut Frame, app_state: &AppState, info_hash: &[u8], selected: usize) {
+    let area = centered_rect(80, 80, f.area());
+    f.render_widget(Clear, f.area());
+
+    let Some(torrent) = app_state.torrents.get(info_hash) else {
+        return;
+    };
+    let state = &torrent.latest_state;
+    let title = if app_state.anonymize_torrent_names {
+        "Files".to_string()
+    } else {
+        format!("Files - {}", state.name())
+    };
+    let block = Block::default()
+        .title(Span::styled(title, Style::default().fg(theme::MAUVE)))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme::SURFACE2));
+
+    let inner_area = block.inner(area);
+    f.render_widget(block, area);
+
+    let chunks = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).split(inner_area);
+
+    if state.files.is_empty() {
+        let waiting = Paragraph::new(Line::from(Span::styled(
+            "Waiting for metadata...",
+            Style::default().fg(theme::SUBTEXT0),
+        )));
+        f.render_widget(waiting, chunks[0]);
+    } else {
+        let header = Row::new(vec!["Priority", "Done", "Size", "Name"])
+            .style(Style::default().fg(theme::YELLOW));
+        let rows = state.files.iter().map(|file| {
+            let priority_style = match file.priority {
+                FilePriority::Skip => Style::default().fg(theme::SUBTEXT0),
+                FilePriority::Low => Style::default().fg(theme::SKY),
+                FilePriority::Normal => Style::default().fg(theme::TEXT),
+                FilePriority::High => Style::default().fg(theme::PEACH),
+            };
+            let percent = if file.size > 0 {
+                file.bytes_done as f64 / file.size as f64 * 100.0
+            } else {
+                100.0
+            };
+            let name = if app_state.anonymize_torrent_names {
+                format!("File {}", file.index + 1)
+            } else {
+                file.path.clone()
+            };
+            let row_style = if file.priority == FilePriority::Skip {
+                Style::default().fg(theme::SUBTEXT0)
+            } else {
+                Style::default().fg(theme::TEXT)
+            };
+            Row::new(vec![
+                Cell::from(Span::styled(file.priority.label(), priority_style)),
+                Cell::from(format!("{:.1}%", percent)),
+                Cell::from(format_bytes(file.size)),
+                Cell::from(name),
+            ])
+            .style(row_style)
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(8),
+                Constraint::Length(7),
+                Constraint::Length(10),
+                Constraint::Min(10),
+            ],
+        )
+        .header(header)
+        .row_highlight_style(
+            Style::default()
+                .bg(theme::SURFACE0)
+                .add_modifier(Modifier::BOLD),
+        );
+        let mut table_state = TableState::default();
+        table_state.select(Some(selected.min(state.files.len() - 1)));
+        f.render_stateful_widget(table, chunks[0], &mut table_state);
+    }
+
+    let footer_text = Line::from(vec![
+        Span::styled("↑↓", Style::default().fg(theme::BLUE)),
+        Span::raw(" Select | "),
+        Span::styled("+/-", Style::default().fg(theme::YELLOW)),
+        Span::raw(" Priority | "),
+        Span::styled("[Space]", Style::default().fg(theme::YELLOW)),
+        Span::raw(" Skip | "),
+        Span::styled("[n]", Style::default().fg(theme::YELLOW)),
+        Span::raw(" Rename | "),
+        Span::styled("[Esc]|[q]", Style::default().fg(theme::GREEN)),
+        Span::raw(" Back"),
+    ])
+    .alignment(Alignment::Center);
+    let footer_paragraph = Paragraph::new(footer_text).style(Style::default().fg(theme::SUBTEXT1));
+    f.render_widget(footer_paragraph, chunks[1]);
+}
+
 fn draw_help_popup(f: &mut Frame, app_state: &AppState, mode: &AppMode) {
     let (settings_path_str, log_path_str) = if let Some((config_dir, data_dir)) = get_app_paths() {
         (
@@ -1800,6 +2942,18 @@ fn draw_help_table(f: &mut Frame, mode: &AppMode, area: Rect) {
                     Cell::from(Span::styled("z", Style::default().fg(theme::SUBTEXT0))),
                     Cell::from("Toggle Zen/Power Saving mode"),
                 ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("S", Style::default().fg(theme::GREEN))),
+                    Cell::from("Save settings and torrent list now"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("n", Style::default().fg(theme::SAPPHIRE))),
+                    Cell::from("Open Network Audit (trackers, DHT status, PEX/LSD)"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("o", Style::default().fg(theme::SAPPHIRE))),
+                    Cell::from("Check again whether the listening port is open"),
+                ]),
                 Row::new(vec![Cell::from(""), Cell::from("")]).height(1),
                 // --- List Navigation & Sorting ---
                 Row::new(vec![Cell::from(Span::styled(
@@ -1811,7 +2965,11 @@ fn draw_help_table(f: &mut Frame, mode: &AppMode, area: Rect) {
                         "↑ / ↓ / k / j",
                         Style::default().fg(theme::BLUE),
                     )),
-                    Cell::from("Navigate torrents list"),
+                    Cell::from("Navigate torrents list (peers when a peer column is selected)"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("a", Style::default().fg(theme::BLUE))),
+                    Cell::from("Jump to the next downloading/uploading torrent"),
                 ]),
                 Row::new(vec![
                     Cell::from(Span::styled(
@@ -1834,10 +2992,66 @@ fn draw_help_table(f: &mut Frame, mode: &AppMode, area: Rect) {
                     Cell::from(Span::styled("p", Style::default().fg(theme::GREEN))),
                     Cell::from("Pause / Resume selected torrent"),
                 ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("K / J", Style::default().fg(theme::YELLOW))),
+                    Cell::from("Move selected torrent up / down the queue"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("g", Style::default().fg(theme::YELLOW))),
+                    Cell::from("List only the next category, then all torrents again"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("G", Style::default().fg(theme::YELLOW))),
+                    Cell::from("File selected torrent under the next category"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("w", Style::default().fg(theme::YELLOW))),
+                    Cell::from("Cycle selected torrent's bandwidth priority: low, normal, high"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("u / i", Style::default().fg(theme::YELLOW))),
+                    Cell::from("Turn uploads / downloads off or on for selected torrent"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("H / X", Style::default().fg(theme::YELLOW))),
+                    Cell::from("Turn DHT / PEX off or on for selected torrent"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("r", Style::default().fg(theme::YELLOW))),
+                    Cell::from("Retry selected torrent if it failed to start"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("R", Style::default().fg(theme::YELLOW))),
+                    Cell::from("Recheck selected torrent's files against their hashes"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("A", Style::default().fg(theme::YELLOW))),
+                    Cell::from("Announce selected torrent to trackers and DHT now"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("N", Style::default().fg(theme::YELLOW))),
+                    Cell::from("Rename selected torrent (display only)"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("P", Style::default().fg(theme::GREEN))),
+                    Cell::from("Pause all torrents, or resume them"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("L", Style::default().fg(theme::YELLOW))),
+                    Cell::from("Move selected torrent's files to another folder"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("f", Style::default().fg(theme::YELLOW))),
+                    Cell::from("Files of selected torrent: set priorities, skip files"),
+                ]),
                 Row::new(vec![
                     Cell::from(Span::styled("d / D", Style::default().fg(theme::RED))),
                     Cell::from("Delete torrent (D includes downloaded files)"),
                 ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("b / B", Style::default().fg(theme::RED))),
+                    Cell::from("Ban selected peer by IP / lift all peer bans"),
+                ]),
                 Row::new(vec![Cell::from(""), Cell::from("")]).height(1),
                 // --- Adding Torrents ---
                 Row::new(vec![Cell::from(Span::styled(
@@ -1851,10 +3065,18 @@ fn draw_help_table(f: &mut Frame, mode: &AppMode, area: Rect) {
                     )),
                     Cell::from("Paste a magnet link or local file path to add"),
                 ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("V", Style::default().fg(theme::SAPPHIRE))),
+                    Cell::from("Paste, then choose how the torrent is added"),
+                ]),
                 Row::new(vec![
                     Cell::from(Span::styled("CLI", Style::default().fg(theme::SAPPHIRE))),
                     Cell::from("Use `superseedr add ...` from another terminal"),
                 ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("C", Style::default().fg(theme::SAPPHIRE))),
+                    Cell::from("Create a torrent from a file or folder and seed it"),
+                ]),
                 Row::new(vec![Cell::from(""), Cell::from("")]).height(1),
                 // --- Graph Controls ---
                 Row::new(vec![Cell::from(Span::styled(
@@ -2014,7 +3236,107 @@ fn draw_help_table(f: &mut Frame, mode: &AppMode, area: Rect) {
                 ]),
             ],
         ),
-        AppMode::ConfigPathPicker { .. } | AppMode::DownloadPathPicker { .. } => (
+        AppMode::Files { .. } => (
+            " Help / Files ",
+            vec![
+                Row::new(vec![
+                    Cell::from(Span::styled(
+                        "Esc / q / f",
+                        Style::default().fg(theme::GREEN),
+                    )),
+                    Cell::from("Back to the torrent list"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled(
+                        "↑ / ↓ / k / j",
+                        Style::default().fg(theme::BLUE),
+                    )),
+                    Cell::from("Select a file"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled(
+                        "+ / → / l",
+                        Style::default().fg(theme::YELLOW),
+                    )),
+                    Cell::from("Raise priority (Skip, Low, Normal, High)"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled(
+                        "- / ← / h",
+                        Style::default().fg(theme::YELLOW),
+                    )),
+                    Cell::from("Lower priority"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("Space", Style::default().fg(theme::RED))),
+                    Cell::from("Skip the file, or download it again"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("n", Style::default().fg(theme::YELLOW))),
+                    Cell::from("Rename or move the file within the download folder"),
+                ]),
+            ],
+        ),
+        AppMode::CreateTorrent { .. } => (
+            " Help / Create Torrent ",
+            vec![
+                Row::new(vec![
+                    Cell::from(Span::styled("Esc / q", Style::default().fg(theme::RED))),
+                    Cell::from("Cancel"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled(
+                        "↑ / ↓ / k / j",
+                        Style::default().fg(theme::BLUE),
+                    )),
+                    Cell::from("Navigate items"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled(
+                        "← / → / h / l",
+                        Style::default().fg(theme::BLUE),
+                    )),
+                    Cell::from("Change piece size, version or a Yes / No item"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("Enter", Style::default().fg(theme::YELLOW))),
+                    Cell::from("Edit or change the item; on Create Torrent, start hashing"),
+                ]),
+            ],
+        ),
+        AppMode::AddTorrent { .. } => (
+            " Help / Add Torrent ",
+            vec![
+                Row::new(vec![
+                    Cell::from(Span::styled("Esc / q", Style::default().fg(theme::RED))),
+                    Cell::from("Cancel"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled(
+                        "↑ / ↓ / k / j",
+                        Style::default().fg(theme::BLUE),
+                    )),
+                    Cell::from("Navigate items"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled(
+                        "← / → / h / l",
+                        Style::default().fg(theme::BLUE),
+                    )),
+                    Cell::from("Change the category or a Yes / No item"),
+                ]),
+                Row::new(vec![
+                    Cell::from(Span::styled("Enter", Style::default().fg(theme::YELLOW))),
+                    Cell::from("Change the item or pick a folder; on Add Torrent, add it"),
+                ]),
+            ],
+        ),
+        AppMode::ConfigPathPicker { .. }
+        | AppMode::DownloadPathPicker { .. }
+        | AppMode::AddTorrentPathPicker { .. }
+        | AppMode::SetupFolderPicker { .. }
+        | AppMode::CreatePathPicker { .. }
+        | AppMode::LocationPicker { .. } => (
             " Help / File Browser ",
             vec![
                 Row::new(vec![
@@ -2134,8 +3456,7 @@ fn draw_power_saving_screen(f: &mut Frame, app_state: &AppState, settings: &Sett
 
     let dl_speed = *app_state.avg_download_history.last().unwrap_or(&0);
     let ul_speed = *app_state.avg_upload_history.last().unwrap_or(&0);
-    let dl_limit = settings.global_download_limit_bps;
-    let ul_limit = settings.global_upload_limit_bps;
+    let (dl_limit, ul_limit) = settings.rate_limits(app_state.rate_profile);
 
     // Define the main area for the pop-up
     let area = centered_rect(40, 60, f.area());
@@ -2289,8 +3610,8 @@ fn draw_status_error_popup(f: &mut Frame, error_text: &str) {
     f.render_widget(paragraph, area);
 }
 
-fn draw_welcome_screen(f: &mut Frame) {
-    let text = vec![
+fn draw_welcome_screen(f: &mut Frame, app_state: &AppState) {
+    let mut text = vec![
         Line::from(Span::styled(
             "A BitTorrent Client in your Terminal",
             Style::default(),
@@ -2328,16 +3649,46 @@ fn draw_welcome_screen(f: &mut Frame) {
             Span::styled("[c]", Style::default().fg(theme::MAUVE)),
             Span::raw(" to configure."),
         ]),
+        Line::from(""),
+        Line::from(Span::styled(
+            "Setup Check:",
+            Style::default().fg(theme::YELLOW).bold(),
+        )),
+        Line::from(""),
+    ];
+
+    for check in &app_state.setup_checks {
+        let (mark, mark_color, detail) = match &check.result {
+            Ok(detail) => (" ✓ ", theme::GREEN, detail),
+            Err(problem) => (" ✗ ", theme::RED, problem),
+        };
+        text.push(Line::from(vec![
+            Span::styled(mark, Style::default().fg(mark_color)),
+            Span::styled(
+                format!("{}: ", check.label),
+                Style::default().fg(theme::SUBTEXT1),
+            ),
+            Span::raw(detail.clone()),
+        ]));
+    }
+
+    text.extend([
         Line::from(""),
         Line::from(""),
         Line::from(vec![
-            Span::styled(" [m] ", Style::default().fg(theme::TEAL)),
+            Span::styled(" [f] ", Style::default().fg(theme::GREEN)),
+            Span::styled(
+                "to set download folder",
+                Style::default().fg(theme::SUBTEXT1),
+            ),
+            Span::styled(" | ", Style::default().fg(theme::SURFACE2)),
+            Span::styled("[m] ", Style::default().fg(theme::TEAL)),
             Span::styled("for manual/help", Style::default().fg(theme::SUBTEXT1)),
             Span::styled(" | ", Style::default().fg(theme::SURFACE2)),
             Span::styled("[Esc] ", Style::default().fg(theme::RED)),
             Span::styled("to dismiss", Style::default().fg(theme::SUBTEXT1)),
         ]),
-    ];
+    ]);
 
     // 1. Calculate content dimensions
     let text_height = text.len() as u16;
@@ -2819,7 +4170,7 @@ fn draw_swarm_heatmap(f: &mut Frame, peers: &[PeerInfo], total_pieces: u32, area
     if total_pieces_usize > 0 {
         for peer in peers {
             for (i, has_piece) in peer.bitfield.iter().enumerate().take(total_pieces_usize) {
-                if *has_piece {
+                if has_piece {
                     availability[i] += 1;
                 }
             }
@@ -2,15 +2,21 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use crate::app::{
-    App, AppMode, ConfigItem, SelectedHeader, TorrentControlState, PEER_HEADERS, TORRENT_HEADERS,
+    AddItem, App, AppMode, ConfigItem, CreateItem, SelectedHeader, TorrentControlState,
 };
+use crate::torrent_file::create::{CreateOptions, MAX_PIECE_LENGTH, MIN_PIECE_LENGTH};
+use crate::torrent_manager::files::FilePriority;
 use crate::torrent_manager::ManagerCommand;
 
-use crate::config::SortDirection;
+use crate::config::{AddOptions, PerformanceProfile, SortDirection, WatchFolderRule};
+use crate::scheduler::RateProfile;
+use crate::token_bucket::TokenBucket;
 use ratatui::crossterm::event::{Event as CrosstermEvent, KeyCode, KeyEventKind};
 use ratatui::style::{Color, Style};
 use ratatui_explorer::{FileExplorer, Theme};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
 use tracing::{event as tracing_event, Level};
 
 use directories::UserDirs;
@@ -49,6 +55,15 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
             return;
         }
 
+        // Names can contain the help key, so typing is handled first.
+        if matches!(app.app_state.mode, AppMode::Rename { .. }) {
+            if key.kind == KeyEventKind::Press {
+                handle_rename_key(key.code, app);
+            }
+            app.app_state.ui_needs_redraw = true;
+            return;
+        }
+
         #[cfg(windows)]
         {
             let mut help_key_handled = false;
@@ -91,11 +106,56 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
         }
     }
 
+    let mut retune_profile = None;
     match &mut app.app_state.mode {
         AppMode::Welcome => {
             if let CrosstermEvent::Key(key) = event {
-                if key.kind == KeyEventKind::Press && key.code == KeyCode::Esc {
-                    app.app_state.mode = AppMode::Normal;
+                if key.kind == KeyEventKind::Press {
+                    match key.code {
+                        KeyCode::Esc => app.app_state.mode = AppMode::Normal,
+                        KeyCode::Char('f') => {
+                            let current_folder = app.client_configs.default_download_folder.clone();
+                            let theme = Theme::default().add_default_title();
+                            match app.new_file_explorer(theme, current_folder) {
+                                Ok(file_explorer) => {
+                                    app.app_state.mode = AppMode::SetupFolderPicker(file_explorer);
+                                }
+                                Err(e) => tracing_event!(
+                                    Level::ERROR,
+                                    "Failed to create FileExplorer for setup: {}",
+                                    e
+                                ),
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        AppMode::SetupFolderPicker(file_explorer) => {
+            if let CrosstermEvent::Key(key) = event {
+                match key.code {
+                    KeyCode::Tab => {
+                        let path = file_explorer.current().path().clone();
+                        let dir_path = if path.is_dir() {
+                            path
+                        } else {
+                            path.parent().unwrap_or(&path).to_path_buf()
+                        };
+                        app.client_configs.default_download_folder = Some(dir_path);
+                        app.app_state.file_picker_error = None;
+                        app.app_state.mode = AppMode::Welcome;
+                        app.refresh_setup_checks();
+                    }
+                    KeyCode::Esc => {
+                        app.app_state.file_picker_error = None;
+                        app.app_state.mode = AppMode::Welcome;
+                    }
+                    _ => handle_file_explorer_input(
+                        file_explorer,
+                        &event,
+                        &mut app.app_state.file_picker_error,
+                    ),
                 }
             }
         }
@@ -119,7 +179,11 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
                             return;
                         }
                         KeyCode::Char('q') => {
-                            app.app_state.should_quit = true;
+                            if app.client_configs.confirm_quit {
+                                app.app_state.mode = AppMode::QuitConfirm;
+                            } else {
+                                app.app_state.should_quit = true;
+                            }
                         }
                         KeyCode::Char('c') => {
                             let items = vec![
@@ -128,6 +192,7 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
                                 ConfigItem::WatchFolder,
                                 ConfigItem::GlobalDownloadLimit,
                                 ConfigItem::GlobalUploadLimit,
+                                ConfigItem::PerformanceProfile,
                             ];
                             app.app_state.mode = AppMode::Config {
                                 settings_edit: Box::new(app.client_configs.clone()),
@@ -136,6 +201,73 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
                                 editing: None,
                             };
                         }
+                        KeyCode::Char('n') => {
+                            app.app_state.mode = AppMode::NetworkAudit { scroll_offset: 0 };
+                        }
+                        KeyCode::Char('f') => {
+                            if let Some(info_hash) = app
+                                .app_state
+                                .torrent_list_order
+                                .get(app.app_state.selected_torrent_index)
+                                .cloned()
+                            {
+                                app.app_state.mode = AppMode::Files {
+                                    info_hash,
+                                    selected: 0,
+                                };
+                            }
+                        }
+                        KeyCode::Char('N') => {
+                            if let Some(info_hash) = app
+                                .app_state
+                                .torrent_list_order
+                                .get(app.app_state.selected_torrent_index)
+                                .cloned()
+                            {
+                                let buffer = app
+                                    .app_state
+                                    .torrents
+                                    .get(&info_hash)
+                                    .map(|torrent| torrent.latest_state.name().to_string())
+                                    .unwrap_or_default();
+                                app.app_state.mode = AppMode::Rename {
+                                    info_hash,
+                                    file: None,
+                                    buffer,
+                                };
+                            }
+                        }
+                        KeyCode::Char('V') => {
+                            app.app_state.paste_with_options = true;
+                            #[cfg(windows)]
+                            match ClipboardContext::new().and_then(|mut ctx| ctx.get_contents()) {
+                                Ok(text) => handle_pasted_text(app, text.trim()).await,
+                                Err(e) => {
+                                    tracing_event!(Level::ERROR, "Clipboard read error: {}", e);
+                                    app.app_state.system_error =
+                                        Some(format!("Clipboard read error: {}", e));
+                                }
+                            }
+                            #[cfg(not(windows))]
+                            app.push_status_message(
+                                "Paste a magnet link or torrent path to choose how it's added",
+                            );
+                        }
+                        KeyCode::Char('C') => {
+                            let theme = Theme::default().add_default_title();
+                            match app.new_file_explorer(theme, None) {
+                                Ok(file_explorer) => {
+                                    app.app_state.mode = AppMode::CreatePathPicker(file_explorer);
+                                }
+                                Err(e) => {
+                                    tracing_event!(
+                                        Level::ERROR,
+                                        "Failed to create FileExplorer: {}",
+                                        e
+                                    );
+                                }
+                            }
+                        }
                         KeyCode::Char('t') => {
                             app.app_state.graph_mode = app.app_state.graph_mode.next();
                         }
@@ -163,33 +295,72 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
                                 .app_state
                                 .torrent_list_order
                                 .get(app.app_state.selected_torrent_index)
+                                .cloned()
                             {
-                                if let (Some(torrent_display), Some(torrent_manager_command_tx)) = (
-                                    app.app_state.torrents.get_mut(info_hash),
-                                    app.torrent_manager_command_txs.get(info_hash),
-                                ) {
-                                    let (new_state, command) =
-                                        match torrent_display.latest_state.torrent_control_state {
-                                            TorrentControlState::Running => (
-                                                TorrentControlState::Paused,
-                                                crate::torrent_manager::ManagerCommand::Pause,
-                                            ),
-                                            TorrentControlState::Paused => (
-                                                TorrentControlState::Running,
-                                                crate::torrent_manager::ManagerCommand::Resume,
-                                            ),
-                                            TorrentControlState::Deleting => return,
-                                        };
-                                    torrent_display.latest_state.torrent_control_state = new_state;
-                                    let torrent_manager_command_tx_clone =
-                                        torrent_manager_command_tx.clone();
-                                    tokio::spawn(async move {
-                                        let _ =
-                                            torrent_manager_command_tx_clone.send(command).await;
+                                let paused =
+                                    app.app_state
+                                        .torrents
+                                        .get(&info_hash)
+                                        .is_some_and(|torrent| {
+                                            torrent.latest_state.torrent_control_state
+                                                == TorrentControlState::Paused
+                                        });
+                                app.set_torrent_paused(&info_hash, !paused).await;
+                            }
+                        }
+                        KeyCode::Char('g') => {
+                            let categories = app.category_names();
+                            app.app_state.category_filter = next_category(
+                                &categories,
+                                app.app_state.category_filter.as_deref(),
+                            );
+                            app.app_state.selected_torrent_index = 0;
+                            app.sort_and_filter_torrent_list();
+                        }
+                        KeyCode::Char('G') => {
+                            if let Some(info_hash) = app
+                                .app_state
+                                .torrent_list_order
+                                .get(app.app_state.selected_torrent_index)
+                                .cloned()
+                            {
+                                let categories = app.category_names();
+                                let current =
+                                    app.app_state.torrents.get(&info_hash).and_then(|torrent| {
+                                        torrent.latest_state.category.as_deref()
                                     });
+                                let category = next_category(&categories, current);
+                                app.set_torrent_category(&info_hash, category);
+                                app.app_state.selected_torrent_index = app
+                                    .app_state
+                                    .selected_torrent_index
+                                    .min(app.app_state.torrent_list_order.len().saturating_sub(1));
+                            }
+                        }
+                        KeyCode::Char('w') => {
+                            if let Some(info_hash) = app
+                                .app_state
+                                .torrent_list_order
+                                .get(app.app_state.selected_torrent_index)
+                                .cloned()
+                            {
+                                if let Some(torrent) = app.app_state.torrents.get(&info_hash) {
+                                    let priority = torrent.latest_state.bandwidth_priority.next();
+                                    app.set_bandwidth_priority(&info_hash, priority);
                                 }
                             }
                         }
+                        KeyCode::Char('K') | KeyCode::Char('J') => {
+                            if let Some(info_hash) = app
+                                .app_state
+                                .torrent_list_order
+                                .get(app.app_state.selected_torrent_index)
+                                .cloned()
+                            {
+                                app.move_in_queue(&info_hash, key.code == KeyCode::Char('K'))
+                                    .await;
+                            }
+                        }
                         KeyCode::Char('d') => {
                             if let Some(info_hash) = app
                                 .app_state
@@ -197,10 +368,14 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
                                 .get(app.app_state.selected_torrent_index)
                                 .cloned()
                             {
-                                app.app_state.mode = AppMode::DeleteConfirm {
-                                    info_hash,
-                                    with_files: false,
-                                };
+                                if app.client_configs.confirm_delete {
+                                    app.app_state.mode = AppMode::DeleteConfirm {
+                                        info_hash,
+                                        with_files: false,
+                                    };
+                                } else {
+                                    app.delete_torrent(&info_hash, false);
+                                }
                             }
                         }
                         KeyCode::Char('D') => {
@@ -210,16 +385,27 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
                                 .get(app.app_state.selected_torrent_index)
                                 .cloned()
                             {
-                                app.app_state.mode = AppMode::DeleteConfirm {
-                                    info_hash,
-                                    with_files: true,
-                                };
+                                if app.client_configs.confirm_delete_with_files {
+                                    app.app_state.mode = AppMode::DeleteConfirm {
+                                        info_hash,
+                                        with_files: true,
+                                    };
+                                } else {
+                                    app.delete_torrent(&info_hash, true);
+                                }
                             }
                         }
                         KeyCode::Char('s') => {
                             match app.app_state.selected_header {
                                 SelectedHeader::Torrent(i) => {
-                                    let column = TORRENT_HEADERS[i];
+                                    let Some(column) = app
+                                        .client_configs
+                                        .torrent_table_columns()
+                                        .get(i)
+                                        .map(|c| c.column)
+                                    else {
+                                        return;
+                                    };
                                     if app.app_state.torrent_sort.0 == column {
                                         app.app_state.torrent_sort.1 =
                                             if app.app_state.torrent_sort.1
@@ -236,7 +422,14 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
                                     app.sort_and_filter_torrent_list();
                                 }
                                 SelectedHeader::Peer(i) => {
-                                    let column = PEER_HEADERS[i];
+                                    let Some(column) = app
+                                        .client_configs
+                                        .peer_table_columns()
+                                        .get(i)
+                                        .map(|c| c.column)
+                                    else {
+                                        return;
+                                    };
                                     if app.app_state.peer_sort.0 == column {
                                         app.app_state.peer_sort.1 = if app.app_state.peer_sort.1
                                             == SortDirection::Ascending
@@ -252,9 +445,28 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
                                 }
                             };
                         }
+                        // With a peer column selected, up and down move through peers.
+                        KeyCode::Up | KeyCode::Char('k')
+                            if matches!(app.app_state.selected_header, SelectedHeader::Peer(_)) =>
+                        {
+                            let last = app.selected_torrent_peers().len().saturating_sub(1);
+                            app.app_state.selected_peer_index = app
+                                .app_state
+                                .selected_peer_index
+                                .min(last)
+                                .saturating_sub(1);
+                        }
+                        KeyCode::Down | KeyCode::Char('j')
+                            if matches!(app.app_state.selected_header, SelectedHeader::Peer(_)) =>
+                        {
+                            let last = app.selected_torrent_peers().len().saturating_sub(1);
+                            app.app_state.selected_peer_index =
+                                (app.app_state.selected_peer_index + 1).min(last);
+                        }
                         KeyCode::Up | KeyCode::Char('k') => {
                             app.app_state.selected_torrent_index =
                                 app.app_state.selected_torrent_index.saturating_sub(1);
+                            app.app_state.selected_peer_index = 0;
                         }
                         KeyCode::Down | KeyCode::Char('j') => {
                             if !app.app_state.torrent_list_order.is_empty() {
@@ -262,28 +474,163 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
                                     app.app_state.selected_torrent_index.saturating_add(1);
                                 if new_index < app.app_state.torrent_list_order.len() {
                                     app.app_state.selected_torrent_index = new_index;
+                                    app.app_state.selected_peer_index = 0;
                                 }
                             }
                         }
+                        KeyCode::Char('b') => {
+                            if matches!(app.app_state.selected_header, SelectedHeader::Peer(_)) {
+                                app.ban_selected_peer();
+                            }
+                        }
+                        KeyCode::Char('B') => {
+                            app.unban_all_peers();
+                        }
+                        KeyCode::Char('S') => {
+                            app.save_state_now();
+                        }
+                        KeyCode::Char('o') => match app.start_port_check() {
+                            Ok(()) => app.push_status_message("Checking port..."),
+                            Err(e) => app.push_status_message(format!("Port check: {}", e)),
+                        },
+                        KeyCode::Char('r') => {
+                            if let Some(info_hash) = app
+                                .app_state
+                                .torrent_list_order
+                                .get(app.app_state.selected_torrent_index)
+                                .cloned()
+                            {
+                                app.retry_failed_torrent(&info_hash).await;
+                            }
+                        }
+                        KeyCode::Char('R') => {
+                            if let Some(info_hash) = app
+                                .app_state
+                                .torrent_list_order
+                                .get(app.app_state.selected_torrent_index)
+                                .cloned()
+                            {
+                                app.force_recheck(&info_hash).await;
+                            }
+                        }
+                        KeyCode::Char('P') => {
+                            if app.app_state.all_paused.is_some() {
+                                app.resume_all().await;
+                            } else {
+                                app.pause_all().await;
+                            }
+                        }
+                        KeyCode::Char('L') => {
+                            if let Some(torrent) = app
+                                .app_state
+                                .torrent_list_order
+                                .get(app.app_state.selected_torrent_index)
+                                .and_then(|info_hash| app.app_state.torrents.get(info_hash))
+                            {
+                                let info_hash = torrent.latest_state.info_hash.clone();
+                                let current_path = torrent.latest_state.download_path.clone();
+                                let theme = Theme::default().add_default_title();
+                                match app.new_file_explorer(theme, Some(current_path)) {
+                                    Ok(file_explorer) => {
+                                        app.app_state.mode = AppMode::LocationPicker {
+                                            info_hash,
+                                            file_explorer,
+                                        };
+                                    }
+                                    Err(e) => {
+                                        tracing_event!(
+                                            Level::ERROR,
+                                            "Failed to create FileExplorer: {}",
+                                            e
+                                        );
+                                    }
+                                }
+                            }
+                        }
+                        KeyCode::Char('A') => {
+                            if let Some(info_hash) = app
+                                .app_state
+                                .torrent_list_order
+                                .get(app.app_state.selected_torrent_index)
+                                .cloned()
+                            {
+                                app.force_reannounce(&info_hash).await;
+                            }
+                        }
+                        KeyCode::Char('u') | KeyCode::Char('i') => {
+                            if let Some(info_hash) = app
+                                .app_state
+                                .torrent_list_order
+                                .get(app.app_state.selected_torrent_index)
+                                .cloned()
+                            {
+                                let upload = key.code == KeyCode::Char('u');
+                                app.toggle_transfer_disabled(&info_hash, upload);
+                            }
+                        }
+                        KeyCode::Char('H') | KeyCode::Char('X') => {
+                            if let Some(info_hash) = app
+                                .app_state
+                                .torrent_list_order
+                                .get(app.app_state.selected_torrent_index)
+                                .cloned()
+                            {
+                                let dht = key.code == KeyCode::Char('H');
+                                if let Some(torrent) = app.app_state.torrents.get(&info_hash) {
+                                    let state = &torrent.latest_state;
+                                    let disabled = if dht {
+                                        state.dht_disabled
+                                    } else {
+                                        state.pex_disabled
+                                    };
+                                    app.set_peer_discovery(&info_hash, dht, disabled);
+                                }
+                            }
+                        }
+                        KeyCode::Char('a') => {
+                            // Jump to the next torrent that is moving data, wrapping around.
+                            let order = &app.app_state.torrent_list_order;
+                            let len = order.len();
+                            let current = app.app_state.selected_torrent_index;
+                            let next_active = (1..=len)
+                                .map(|offset| (current + offset) % len)
+                                .find(|&index| {
+                                    app.app_state.torrents.get(&order[index]).is_some_and(
+                                        |torrent| {
+                                            torrent.smoothed_download_speed_bps > 0
+                                                || torrent.smoothed_upload_speed_bps > 0
+                                        },
+                                    )
+                                });
+                            if let Some(index) = next_active {
+                                app.app_state.selected_torrent_index = index;
+                            }
+                        }
                         KeyCode::Left | KeyCode::Char('h') => {
+                            let torrent_column_count =
+                                app.client_configs.torrent_table_columns().len();
+                            let peer_column_count = app.client_configs.peer_table_columns().len();
                             app.app_state.selected_header = match app.app_state.selected_header {
                                 SelectedHeader::Torrent(0) => {
                                     if !app.app_state.torrent_list_order.is_empty() {
-                                        SelectedHeader::Peer(PEER_HEADERS.len() - 1)
+                                        SelectedHeader::Peer(peer_column_count - 1)
                                     } else {
                                         SelectedHeader::Torrent(0)
                                     }
                                 }
                                 SelectedHeader::Torrent(i) => SelectedHeader::Torrent(i - 1),
                                 SelectedHeader::Peer(0) => {
-                                    SelectedHeader::Torrent(TORRENT_HEADERS.len() - 1)
+                                    SelectedHeader::Torrent(torrent_column_count - 1)
                                 }
                                 SelectedHeader::Peer(i) => SelectedHeader::Peer(i - 1),
                             };
                         }
                         KeyCode::Right | KeyCode::Char('l') => {
+                            let torrent_column_count =
+                                app.client_configs.torrent_table_columns().len();
+                            let peer_column_count = app.client_configs.peer_table_columns().len();
                             app.app_state.selected_header = match app.app_state.selected_header {
-                                SelectedHeader::Torrent(i) if i < TORRENT_HEADERS.len() - 1 => {
+                                SelectedHeader::Torrent(i) if i < torrent_column_count - 1 => {
                                     SelectedHeader::Torrent(i + 1)
                                 }
                                 SelectedHeader::Torrent(i) => {
@@ -293,7 +640,7 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
                                         SelectedHeader::Torrent(i)
                                     }
                                 }
-                                SelectedHeader::Peer(i) if i < PEER_HEADERS.len() - 1 => {
+                                SelectedHeader::Peer(i) if i < peer_column_count - 1 => {
                                     SelectedHeader::Peer(i + 1)
                                 }
                                 SelectedHeader::Peer(_) => SelectedHeader::Torrent(0),
@@ -337,6 +684,24 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
                 }
             }
         }
+        AppMode::NetworkAudit { scroll_offset } => {
+            if let CrosstermEvent::Key(key) = event {
+                if key.kind == KeyEventKind::Press {
+                    match key.code {
+                        KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('n') => {
+                            app.app_state.mode = AppMode::Normal;
+                        }
+                        KeyCode::Up | KeyCode::Char('k') => {
+                            *scroll_offset = scroll_offset.saturating_sub(1);
+                        }
+                        KeyCode::Down | KeyCode::Char('j') => {
+                            *scroll_offset = scroll_offset.saturating_add(1);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
         AppMode::Config {
             settings_edit,
             selected_index,
@@ -368,19 +733,21 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
                                     ConfigItem::GlobalDownloadLimit => {
                                         if let Ok(new_rate) = buffer.parse::<u64>() {
                                             settings_edit.global_download_limit_bps = new_rate;
-                                            let bucket = app.global_dl_bucket.clone();
-                                            tokio::spawn(async move {
-                                                bucket.lock().await.set_rate(new_rate as f64);
-                                            });
+                                            set_normal_rate_limit(
+                                                app.app_state.rate_profile,
+                                                &app.global_dl_bucket,
+                                                new_rate,
+                                            );
                                         }
                                     }
                                     ConfigItem::GlobalUploadLimit => {
                                         if let Ok(new_rate) = buffer.parse::<u64>() {
                                             settings_edit.global_upload_limit_bps = new_rate;
-                                            let bucket = app.global_ul_bucket.clone();
-                                            tokio::spawn(async move {
-                                                bucket.lock().await.set_rate(new_rate as f64);
-                                            });
+                                            set_normal_rate_limit(
+                                                app.app_state.rate_profile,
+                                                &app.global_ul_bucket,
+                                                new_rate,
+                                            );
                                         }
                                     }
                                     _ => {}
@@ -395,8 +762,20 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
                 if key.kind == KeyEventKind::Press {
                     match key.code {
                         KeyCode::Esc | KeyCode::Char('q') => {
+                            let folder_changed = app.client_configs.default_download_folder
+                                != settings_edit.default_download_folder;
+                            // The profile retunes the resource limits, so it only takes
+                            // effect once saved
+                            if app.client_configs.performance_profile
+                                != settings_edit.performance_profile
+                            {
+                                retune_profile = Some(settings_edit.performance_profile);
+                            }
                             app.client_configs = *settings_edit.clone();
                             app.app_state.mode = AppMode::Normal;
+                            if folder_changed {
+                                app.check_default_download_folder();
+                            }
                         }
                         KeyCode::Enter => {
                             let selected_item = items[*selected_index];
@@ -406,12 +785,27 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
                                 | ConfigItem::ClientPort => {
                                     *editing = Some((selected_item, String::new()));
                                 }
+                                ConfigItem::PerformanceProfile => {
+                                    let profile = cycle_performance_profile(
+                                        settings_edit.performance_profile,
+                                        true,
+                                    );
+                                    settings_edit.performance_profile = profile;
+                                }
                                 ConfigItem::DefaultDownloadFolder | ConfigItem::WatchFolder => {
+                                    let settings_edit = settings_edit.clone();
+                                    let current_path = match selected_item {
+                                        ConfigItem::WatchFolder => settings_edit
+                                            .watch_folders
+                                            .first()
+                                            .map(|rule| rule.path.clone()),
+                                        _ => settings_edit.default_download_folder.clone(),
+                                    };
                                     let theme = Theme::default().add_default_title();
-                                    match FileExplorer::with_theme(theme) {
+                                    match app.new_file_explorer(theme, current_path) {
                                         Ok(file_explorer) => {
                                             app.app_state.mode = AppMode::ConfigPathPicker {
-                                                settings_edit: settings_edit.clone(),
+                                                settings_edit,
                                                 for_item: selected_item,
                                                 file_explorer,
                                             };
@@ -445,7 +839,7 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
                                         default_settings.default_download_folder;
                                 }
                                 ConfigItem::WatchFolder => {
-                                    settings_edit.watch_folder = default_settings.watch_folder;
+                                    settings_edit.watch_folders = default_settings.watch_folders;
                                 }
                                 ConfigItem::GlobalDownloadLimit => {
                                     settings_edit.global_download_limit_bps =
@@ -455,6 +849,10 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
                                     settings_edit.global_upload_limit_bps =
                                         default_settings.global_upload_limit_bps;
                                 }
+                                ConfigItem::PerformanceProfile => {
+                                    settings_edit.performance_profile =
+                                        default_settings.performance_profile;
+                                }
                             }
                         }
                         KeyCode::Right | KeyCode::Char('l') => {
@@ -466,20 +864,29 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
                                         .global_download_limit_bps
                                         .saturating_add(increment);
                                     settings_edit.global_download_limit_bps = new_rate;
-                                    let bucket = app.global_dl_bucket.clone();
-                                    tokio::spawn(async move {
-                                        bucket.lock().await.set_rate(new_rate as f64);
-                                    });
+                                    set_normal_rate_limit(
+                                        app.app_state.rate_profile,
+                                        &app.global_dl_bucket,
+                                        new_rate,
+                                    );
                                 }
                                 ConfigItem::GlobalUploadLimit => {
                                     let new_rate = settings_edit
                                         .global_upload_limit_bps
                                         .saturating_add(increment);
                                     settings_edit.global_upload_limit_bps = new_rate;
-                                    let bucket = app.global_ul_bucket.clone();
-                                    tokio::spawn(async move {
-                                        bucket.lock().await.set_rate(new_rate as f64);
-                                    });
+                                    set_normal_rate_limit(
+                                        app.app_state.rate_profile,
+                                        &app.global_ul_bucket,
+                                        new_rate,
+                                    );
+                                }
+                                ConfigItem::PerformanceProfile => {
+                                    let profile = cycle_performance_profile(
+                                        settings_edit.performance_profile,
+                                        true,
+                                    );
+                                    settings_edit.performance_profile = profile;
                                 }
                                 _ => {}
                             }
@@ -494,20 +901,29 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
                                         .global_download_limit_bps
                                         .saturating_sub(decrement);
                                     settings_edit.global_download_limit_bps = new_rate;
-                                    let bucket = app.global_dl_bucket.clone();
-                                    tokio::spawn(async move {
-                                        bucket.lock().await.set_rate(new_rate as f64);
-                                    });
+                                    set_normal_rate_limit(
+                                        app.app_state.rate_profile,
+                                        &app.global_dl_bucket,
+                                        new_rate,
+                                    );
                                 }
                                 ConfigItem::GlobalUploadLimit => {
                                     let new_rate = settings_edit
                                         .global_upload_limit_bps
                                         .saturating_sub(decrement);
                                     settings_edit.global_upload_limit_bps = new_rate;
-                                    let bucket = app.global_ul_bucket.clone();
-                                    tokio::spawn(async move {
-                                        bucket.lock().await.set_rate(new_rate as f64);
-                                    });
+                                    set_normal_rate_limit(
+                                        app.app_state.rate_profile,
+                                        &app.global_ul_bucket,
+                                        new_rate,
+                                    );
+                                }
+                                ConfigItem::PerformanceProfile => {
+                                    let profile = cycle_performance_profile(
+                                        settings_edit.performance_profile,
+                                        false,
+                                    );
+                                    settings_edit.performance_profile = profile;
                                 }
                                 _ => {}
                             }
@@ -555,15 +971,30 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
                             ConfigItem::DefaultDownloadFolder => {
                                 settings_edit.default_download_folder = Some(dir_path)
                             }
-                            ConfigItem::WatchFolder => settings_edit.watch_folder = Some(dir_path),
+                            // The config screen edits the first rule; extra rules live in settings.toml.
+                            ConfigItem::WatchFolder => {
+                                match settings_edit.watch_folders.first_mut() {
+                                    Some(rule) => rule.path = dir_path,
+                                    None => settings_edit.watch_folders.push(WatchFolderRule {
+                                        path: dir_path,
+                                        ..Default::default()
+                                    }),
+                                }
+                            }
                             _ => {}
                         }
+                        app.app_state.file_picker_error = None;
                         app.app_state.mode = return_to_config(settings_edit.clone(), *for_item);
                     }
                     KeyCode::Esc => {
+                        app.app_state.file_picker_error = None;
                         app.app_state.mode = return_to_config(settings_edit.clone(), *for_item)
                     }
-                    _ => if file_explorer.handle(&event).is_err() {},
+                    _ => handle_file_explorer_input(
+                        file_explorer,
+                        &event,
+                        &mut app.app_state.file_picker_error,
+                    ),
                 }
             }
         }
@@ -624,43 +1055,296 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
 
                         app.app_state.mode = AppMode::Normal;
                         app.app_state.system_error = None;
+                        app.app_state.file_picker_error = None;
                     }
                     KeyCode::Esc => {
                         app.app_state.mode = AppMode::Normal;
                         app.app_state.system_error = None;
+                        app.app_state.file_picker_error = None;
                         app.app_state.pending_torrent_path = None;
                         app.app_state.pending_torrent_link.clear();
                     }
-                    _ => {
-                        if let Err(e) = file_explorer.handle(&event) {
-                            tracing_event!(Level::ERROR, "File explorer error: {}", e);
+                    _ => handle_file_explorer_input(
+                        file_explorer,
+                        &event,
+                        &mut app.app_state.file_picker_error,
+                    ),
+                }
+            }
+        }
+        AppMode::LocationPicker {
+            info_hash,
+            file_explorer,
+        } => {
+            if let CrosstermEvent::Key(key) = event {
+                match key.code {
+                    // Tab moves the files, V moves and verifies them, L uses files already there
+                    KeyCode::Tab | KeyCode::Char('V') | KeyCode::Char('L') => {
+                        let mut path = file_explorer.current().path().clone();
+                        if !path.is_dir() {
+                            if let Some(parent) = path.parent() {
+                                path = parent.to_path_buf();
+                            }
                         }
+                        let info_hash = info_hash.clone();
+                        let move_data = key.code != KeyCode::Char('L');
+                        let recheck = key.code != KeyCode::Tab;
+                        app.app_state.mode = AppMode::Normal;
+                        app.app_state.file_picker_error = None;
+                        app.set_location(&info_hash, path, move_data, recheck).await;
+                    }
+                    KeyCode::Esc => {
+                        app.app_state.mode = AppMode::Normal;
+                        app.app_state.file_picker_error = None;
                     }
+                    _ => handle_file_explorer_input(
+                        file_explorer,
+                        &event,
+                        &mut app.app_state.file_picker_error,
+                    ),
                 }
             }
         }
-        AppMode::DeleteConfirm {
+        AppMode::QuitConfirm => {
+            if let CrosstermEvent::Key(key) = event {
+                if key.kind == KeyEventKind::Press {
+                    match key.code {
+                        KeyCode::Enter | KeyCode::Char('q') => app.app_state.should_quit = true,
+                        KeyCode::Esc => app.app_state.mode = AppMode::Normal,
+                        _ => {}
+                    }
+                }
+            }
+        }
+        AppMode::Files {
             info_hash,
-            with_files,
+            selected,
         } => {
+            if let CrosstermEvent::Key(key) = event {
+                if key.kind == KeyEventKind::Press {
+                    let files = app
+                        .app_state
+                        .torrents
+                        .get(info_hash.as_slice())
+                        .map(|torrent| torrent.latest_state.files.as_slice())
+                        .unwrap_or_default();
+                    match key.code {
+                        KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('f') => {
+                            app.app_state.mode = AppMode::Normal;
+                        }
+                        KeyCode::Up | KeyCode::Char('k') => {
+                            *selected = selected.saturating_sub(1);
+                        }
+                        KeyCode::Down | KeyCode::Char('j') => {
+                            *selected = (*selected + 1).min(files.len().saturating_sub(1));
+                        }
+                        KeyCode::Char('+')
+                        | KeyCode::Char('=')
+                        | KeyCode::Right
+                        | KeyCode::Char('l')
+                        | KeyCode::Char('-')
+                        | KeyCode::Left
+                        | KeyCode::Char('h')
+                        | KeyCode::Char(' ') => {
+                            let Some(file) = files.get(*selected) else {
+                                return;
+                            };
+                            let priority = match key.code {
+                                KeyCode::Char('-') | KeyCode::Left | KeyCode::Char('h') => {
+                                    file.priority.lower()
+                                }
+                                KeyCode::Char(' ') if file.priority == FilePriority::Skip => {
+                                    FilePriority::Normal
+                                }
+                                KeyCode::Char(' ') => FilePriority::Skip,
+                                _ => file.priority.raise(),
+                            };
+                            if priority != file.priority {
+                                let (info_hash, index) = (info_hash.clone(), file.index);
+                                app.set_file_priority(&info_hash, vec![index..=index], priority);
+                            }
+                        }
+                        KeyCode::Char('n') => {
+                            if let Some(file) = files.get(*selected) {
+                                app.app_state.mode = AppMode::Rename {
+                                    info_hash: info_hash.clone(),
+                                    file: Some(*selected),
+                                    buffer: file.path.clone(),
+                                };
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        AppMode::CreatePathPicker(file_explorer) => {
             if let CrosstermEvent::Key(key) = event {
                 match key.code {
+                    KeyCode::Tab => {
+                        let path = file_explorer.current().path().clone();
+                        app.app_state.file_picker_error = None;
+                        app.app_state.mode = AppMode::CreateTorrent {
+                            options: Box::new(CreateOptions {
+                                path,
+                                ..Default::default()
+                            }),
+                            seed: true,
+                            selected_index: 0,
+                            editing: None,
+                        };
+                    }
+                    KeyCode::Esc => {
+                        app.app_state.file_picker_error = None;
+                        app.app_state.mode = AppMode::Normal;
+                    }
+                    _ => handle_file_explorer_input(
+                        file_explorer,
+                        &event,
+                        &mut app.app_state.file_picker_error,
+                    ),
+                }
+            }
+        }
+        AppMode::CreateTorrent {
+            options,
+            seed,
+            selected_index,
+            editing,
+        } => {
+            let CrosstermEvent::Key(key) = event else {
+                return;
+            };
+            if key.kind != KeyEventKind::Press {
+                return;
+            }
+            if let Some((item, buffer)) = editing {
+                match key.code {
+                    KeyCode::Char(c) => buffer.push(c),
+                    KeyCode::Backspace => {
+                        buffer.pop();
+                    }
+                    KeyCode::Esc => *editing = None,
                     KeyCode::Enter => {
-                        let command = if *with_files {
-                            crate::torrent_manager::ManagerCommand::DeleteFile
-                        } else {
-                            crate::torrent_manager::ManagerCommand::Shutdown
+                        // Lists are separated by spaces or commas.
+                        let list = || {
+                            buffer
+                                .split(|c: char| c.is_whitespace() || c == ',')
+                                .filter(|part| !part.is_empty())
+                                .map(str::to_string)
+                                .collect()
                         };
-                        if let Some(manager_tx) = app.torrent_manager_command_txs.get(info_hash) {
-                            let manager_tx_clone = manager_tx.clone();
-                            tokio::spawn(async move {
-                                let _ = manager_tx_clone.send(command).await;
-                            });
+                        match item {
+                            CreateItem::Trackers => options.trackers = list(),
+                            CreateItem::WebSeeds => options.web_seeds = list(),
+                            CreateItem::Comment => {
+                                let comment = buffer.trim();
+                                options.comment =
+                                    (!comment.is_empty()).then(|| comment.to_string());
+                            }
+                            _ => {}
                         }
-                        if let Some(torrent) = app.app_state.torrents.get_mut(info_hash) {
-                            torrent.latest_state.torrent_control_state =
-                                TorrentControlState::Deleting;
+                        *editing = None;
+                    }
+                    _ => {}
+                }
+            } else {
+                let item = CreateItem::ALL[*selected_index];
+                match key.code {
+                    KeyCode::Esc | KeyCode::Char('q') => app.app_state.mode = AppMode::Normal,
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        *selected_index = selected_index.saturating_sub(1);
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        *selected_index = (*selected_index + 1).min(CreateItem::ALL.len() - 1);
+                    }
+                    KeyCode::Enter
+                    | KeyCode::Right
+                    | KeyCode::Char('l')
+                    | KeyCode::Left
+                    | KeyCode::Char('h') => {
+                        let forward = !matches!(key.code, KeyCode::Left | KeyCode::Char('h'));
+                        match item {
+                            CreateItem::Trackers | CreateItem::WebSeeds | CreateItem::Comment
+                                if key.code == KeyCode::Enter =>
+                            {
+                                let current = match item {
+                                    CreateItem::Trackers => options.trackers.join(" "),
+                                    CreateItem::WebSeeds => options.web_seeds.join(" "),
+                                    _ => options.comment.clone().unwrap_or_default(),
+                                };
+                                *editing = Some((item, current));
+                            }
+                            CreateItem::PieceSize => {
+                                options.piece_length =
+                                    cycle_piece_length(options.piece_length, forward);
+                            }
+                            CreateItem::Version => options.version = options.version.next(),
+                            CreateItem::Private => options.private = !options.private,
+                            CreateItem::Seed => *seed = !*seed,
+                            CreateItem::Create if key.code == KeyCode::Enter => {
+                                let (options, seed) = (*options.clone(), *seed);
+                                app.app_state.mode = AppMode::Normal;
+                                app.start_torrent_creation(options, seed);
+                            }
+                            _ => {}
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        AppMode::AddTorrent { .. } => {
+            if let CrosstermEvent::Key(key) = event {
+                if key.kind == KeyEventKind::Press {
+                    handle_add_torrent_key(key.code, app).await;
+                }
+            }
+        }
+        AppMode::AddTorrentPathPicker {
+            input,
+            options,
+            file_explorer,
+        } => {
+            if let CrosstermEvent::Key(key) = event {
+                match key.code {
+                    KeyCode::Tab | KeyCode::Esc => {
+                        let (input, mut options) = (input.clone(), options.clone());
+                        if key.code == KeyCode::Tab {
+                            let mut path = file_explorer.current().path().clone();
+                            if !path.is_dir() {
+                                if let Some(parent) = path.parent() {
+                                    path = parent.to_path_buf();
+                                }
+                            }
+                            options.download_path = Some(path);
                         }
+                        app.app_state.file_picker_error = None;
+                        app.app_state.mode = AppMode::AddTorrent {
+                            input,
+                            options,
+                            selected_index: 0,
+                        };
+                    }
+                    _ => handle_file_explorer_input(
+                        file_explorer,
+                        &event,
+                        &mut app.app_state.file_picker_error,
+                    ),
+                }
+            }
+        }
+        // Typing goes to handle_rename_key before anything else
+        AppMode::Rename { .. } => {}
+        AppMode::DeleteConfirm {
+            info_hash,
+            with_files,
+        } => {
+            if let CrosstermEvent::Key(key) = event {
+                match key.code {
+                    KeyCode::Enter => {
+                        let (info_hash, with_files) = (info_hash.clone(), *with_files);
+                        app.delete_torrent(&info_hash, with_files);
                         app.app_state.mode = AppMode::Normal;
                     }
                     KeyCode::Esc => app.app_state.mode = AppMode::Normal,
@@ -668,10 +1352,231 @@ pub async fn handle_event(event: CrosstermEvent, app: &mut App) {
                 }
             }
         }
+        AppMode::SeedGoalConfirm { info_hash, action } => {
+            if let CrosstermEvent::Key(key) = event {
+                match key.code {
+                    KeyCode::Enter => {
+                        let (info_hash, action) = (info_hash.clone(), *action);
+                        app.finish_seeding(&info_hash, action).await;
+                    }
+                    KeyCode::Esc => {
+                        let info_hash = info_hash.clone();
+                        app.keep_seeding(&info_hash);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        AppMode::MergeSourcesConfirm(sources) => {
+            if let CrosstermEvent::Key(key) = event {
+                match key.code {
+                    KeyCode::Enter => {
+                        let sources = sources.clone();
+                        app.app_state.mode = AppMode::Normal;
+                        app.merge_duplicate_sources(sources);
+                    }
+                    KeyCode::Esc => app.app_state.mode = AppMode::Normal,
+                    _ => {}
+                }
+            }
+        }
+    }
+    if let Some(profile) = retune_profile {
+        app.apply_performance_profile(profile).await;
     }
     app.app_state.ui_needs_redraw = true;
 }
+
+/// Typing in the rename box. Enter renames; an empty torrent name goes back to the
+/// one from the metadata. Either way a file rename returns to the files list.
+fn handle_rename_key(code: KeyCode, app: &mut App) {
+    let AppMode::Rename {
+        info_hash,
+        file,
+        buffer,
+    } = &mut app.app_state.mode
+    else {
+        return;
+    };
+    match code {
+        KeyCode::Char(c) => buffer.push(c),
+        KeyCode::Backspace => {
+            buffer.pop();
+        }
+        KeyCode::Esc | KeyCode::Enter => {
+            let (info_hash, file, name) = (info_hash.clone(), *file, buffer.trim().to_string());
+            app.app_state.mode = match file {
+                Some(selected) => AppMode::Files {
+                    info_hash: info_hash.clone(),
+                    selected,
+                },
+                None => AppMode::Normal,
+            };
+            if code != KeyCode::Enter {
+                return;
+            }
+            match file {
+                Some(selected) => {
+                    let index = app
+                        .app_state
+                        .torrents
+                        .get(&info_hash)
+                        .and_then(|torrent| torrent.latest_state.files.get(selected))
+                        .map(|file| file.index);
+                    if let (Some(index), false) = (index, name.is_empty()) {
+                        app.rename_file(&info_hash, index, PathBuf::from(name));
+                    }
+                }
+                None => app.rename_torrent(&info_hash, (!name.is_empty()).then_some(name)),
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Keys in the add torrent dialog. Enter or left/right changes the selected option;
+/// Enter on the download folder opens a folder picker, and on Add adds the torrent.
+async fn handle_add_torrent_key(code: KeyCode, app: &mut App) {
+    let categories = app.category_names();
+    let AppMode::AddTorrent {
+        input,
+        options,
+        selected_index,
+    } = &mut app.app_state.mode
+    else {
+        return;
+    };
+    let item = AddItem::ALL[*selected_index];
+    match code {
+        KeyCode::Esc | KeyCode::Char('q') => app.app_state.mode = AppMode::Normal,
+        KeyCode::Up | KeyCode::Char('k') => {
+            *selected_index = selected_index.saturating_sub(1);
+        }
+        KeyCode::Down | KeyCode::Char('j') => {
+            *selected_index = (*selected_index + 1).min(AddItem::ALL.len() - 1);
+        }
+        KeyCode::Enter
+        | KeyCode::Right
+        | KeyCode::Char('l')
+        | KeyCode::Left
+        | KeyCode::Char('h') => match item {
+            AddItem::DownloadFolder if code == KeyCode::Enter => {
+                let (input, options) = (input.clone(), options.clone());
+                let theme = Theme::default().add_default_title();
+                match app.new_file_explorer(theme, options.download_path.clone()) {
+                    Ok(file_explorer) => {
+                        app.app_state.mode = AppMode::AddTorrentPathPicker {
+                            input,
+                            options,
+                            file_explorer,
+                        };
+                    }
+                    Err(e) => {
+                        tracing_event!(Level::ERROR, "Failed to create FileExplorer: {}", e);
+                    }
+                }
+            }
+            AddItem::Category => {
+                options.category = next_category(&categories, options.category.as_deref());
+            }
+            AddItem::Paused => options.paused = !options.paused,
+            AddItem::Sequential => options.sequential = !options.sequential,
+            AddItem::SkipCheck => options.skip_check = !options.skip_check,
+            AddItem::Add if code == KeyCode::Enter => {
+                let (input, options) = (input.clone(), options.clone());
+                app.app_state.mode = AppMode::Normal;
+                let download_path = app.client_configs.default_download_folder.clone();
+                app.add_with_options(input, options, download_path).await;
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+fn cycle_performance_profile(profile: PerformanceProfile, forward: bool) -> PerformanceProfile {
+    match (profile, forward) {
+        (PerformanceProfile::MaxSpeed, true) => PerformanceProfile::Balanced,
+        (PerformanceProfile::Balanced, true) => PerformanceProfile::QuietDisk,
+        (PerformanceProfile::QuietDisk, true) => PerformanceProfile::MaxSpeed,
+        (PerformanceProfile::MaxSpeed, false) => PerformanceProfile::QuietDisk,
+        (PerformanceProfile::Balanced, false) => PerformanceProfile::MaxSpeed,
+        (PerformanceProfile::QuietDisk, false) => PerformanceProfile::Balanced,
+    }
+}
+
+/// The category after `current` in `categories`; None (no category) comes after the
+/// last one and before the first.
+fn next_category(categories: &[String], current: Option<&str>) -> Option<String> {
+    let next_index = match current {
+        Some(current) => categories.iter().position(|name| name == current)? + 1,
+        None => 0,
+    };
+    categories.get(next_index).cloned()
+}
+
+/// Applies an edited global limit to `bucket` at once, unless the schedule has the
+/// alternative limits in force; it then takes over when the schedule switches back.
+fn set_normal_rate_limit(profile: RateProfile, bucket: &Arc<Mutex<TokenBucket>>, rate_bps: u64) {
+    if profile != RateProfile::Normal {
+        return;
+    }
+    let bucket = bucket.clone();
+    tokio::spawn(async move {
+        bucket.lock().await.set_rate(rate_bps as f64);
+    });
+}
+
+/// Steps through Auto and the powers of two a piece size can be.
+fn cycle_piece_length(piece_length: Option<u64>, forward: bool) -> Option<u64> {
+    match (piece_length, forward) {
+        (None, true) => Some(MIN_PIECE_LENGTH),
+        (None, false) => Some(MAX_PIECE_LENGTH),
+        (Some(length), true) if length >= MAX_PIECE_LENGTH => None,
+        (Some(length), false) if length <= MIN_PIECE_LENGTH => None,
+        (Some(length), true) => Some(length * 2),
+        (Some(length), false) => Some(length / 2),
+    }
+}
+
+/// Passes input on to a file explorer. If the explorer can't read the directory it
+/// moved into (permission denied, or the directory is gone), it is put back where it
+/// was and the error is kept so the picker can show it inline.
+fn handle_file_explorer_input(
+    file_explorer: &mut FileExplorer,
+    event: &CrosstermEvent,
+    file_picker_error: &mut Option<String>,
+) {
+    let previous_cwd = file_explorer.cwd().clone();
+    match file_explorer.handle(event) {
+        Ok(()) => *file_picker_error = None,
+        Err(e) => {
+            tracing_event!(Level::WARN, "File explorer could not open directory: {}", e);
+            *file_picker_error = Some(format!("Cannot open folder: {}", e));
+
+            if file_explorer.set_cwd(previous_cwd).is_err() {
+                if let Some(home) = UserDirs::new().map(|ud| ud.home_dir().to_path_buf()) {
+                    file_explorer.set_cwd(home).ok();
+                }
+            }
+        }
+    }
+}
+
 async fn handle_pasted_text(app: &mut App, pasted_text: &str) {
+    if std::mem::take(&mut app.app_state.paste_with_options) {
+        let path = Path::new(pasted_text);
+        if pasted_text.starts_with("magnet:")
+            || (path.is_file() && path.extension().is_some_and(|ext| ext == "torrent"))
+        {
+            app.app_state.mode = AppMode::AddTorrent {
+                input: pasted_text.to_string(),
+                options: AddOptions::default(),
+                selected_index: 0,
+            };
+            return;
+        }
+    }
     if pasted_text.starts_with("magnet:") {
         // If a default download folder is configured, use it directly.
         if let Some(download_path) = app.client_configs.default_download_folder.clone() {
@@ -689,15 +1594,9 @@ async fn handle_pasted_text(app: &mut App, pasted_text: &str) {
                 .add_default_title()
                 .with_item_style(Style::default().fg(Color::DarkGray))
                 .with_dir_style(Style::default());
-            match FileExplorer::with_theme(theme) {
-                Ok(mut file_explorer) => {
-                    // Since no default path is set, try to find the most common path to start the picker in
-                    let initial_path = app
-                        .find_most_common_download_path()
-                        .or_else(|| UserDirs::new().map(|ud| ud.home_dir().to_path_buf()));
-                    if let Some(common_path) = initial_path {
-                        file_explorer.set_cwd(common_path).ok();
-                    }
+            // Since no default path is set, start the picker in the most common download path
+            match app.new_file_explorer(theme, None) {
+                Ok(file_explorer) => {
                     app.app_state.mode = AppMode::DownloadPathPicker(file_explorer);
                 }
                 Err(e) => {
@@ -719,14 +1618,8 @@ async fn handle_pasted_text(app: &mut App, pasted_text: &str) {
             } else {
                 // Show the download path picker.
                 app.app_state.pending_torrent_path = Some(path.to_path_buf());
-                match FileExplorer::new() {
-                    Ok(mut file_explorer) => {
-                        let initial_path = app
-                            .find_most_common_download_path()
-                            .or_else(|| UserDirs::new().map(|ud| ud.home_dir().to_path_buf()));
-                        if let Some(common_path) = initial_path {
-                            file_explorer.set_cwd(common_path).ok();
-                        }
+                match app.new_file_explorer(Theme::default(), None) {
+                    Ok(file_explorer) => {
                         app.app_state.mode = AppMode::DownloadPathPicker(file_explorer);
                     }
                     Err(e) => {
@@ -4,7 +4,7 @@
 use crate::theme; // You'll need this for ip_to_color and speed_to_style
 use ratatui::style::{Color, Style}; // And these
 use std::path::Path;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use ratatui::prelude::Constraint;
 use ratatui::prelude::Direction;
@@ -13,6 +13,8 @@ use ratatui::prelude::Rect;
 use ratatui::text::Span;
 
 use crate::app::GraphDisplayMode;
+use crate::app::PeerInfo;
+use crate::tracker::SwarmCounts;
 
 pub fn format_speed(bits_per_second: u64) -> String {
     if bits_per_second < 1_000 {
@@ -87,6 +89,62 @@ pub fn format_time(seconds: u64) -> String {
     parts.join(" ")
 }
 
+/// Uploaded divided by downloaded. Infinite when only uploads happened, `None`
+/// when nothing moved yet.
+pub fn share_ratio(uploaded: u64, downloaded: u64) -> Option<f64> {
+    match (uploaded, downloaded) {
+        (0, 0) => None,
+        (_, 0) => Some(f64::INFINITY),
+        _ => Some(uploaded as f64 / downloaded as f64),
+    }
+}
+
+pub fn format_ratio(uploaded: u64, downloaded: u64) -> String {
+    match share_ratio(uploaded, downloaded) {
+        None => "-".to_string(),
+        Some(ratio) if ratio.is_infinite() => "∞".to_string(),
+        Some(ratio) => format!("{:.2}", ratio),
+    }
+}
+
+/// Formats `time` in UTC as `YYYY-MM-DD HH:MM:SS`, or only `HH:MM:SS` when
+/// `with_date` is false.
+pub fn format_utc_timestamp(time: SystemTime, with_date: bool) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let secs_of_day = secs % 86_400;
+    let clock = format!(
+        "{:02}:{:02}:{:02}",
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    );
+    if !with_date {
+        return clock;
+    }
+
+    // Days since 1970-01-01 to a civil date, counting years from March so leap
+    // days fall at the end (Howard Hinnant's civil_from_days).
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = era * 400 + year_of_era + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02} {}", year, month, day, clock)
+}
+
 pub fn format_duration(duration: Duration) -> String {
     if duration == Duration::MAX {
         return "∞".to_string();
@@ -152,6 +210,29 @@ pub fn path_to_string(path: Option<&Path>) -> String {
         .unwrap_or_else(|| "Not Set".to_string())
 }
 
+/// A peer address cut to `max_width` columns. Long IPv6 addresses lose the middle
+/// of the IP rather than the port, e.g. "[2001:db8…7334]:6881".
+pub fn format_peer_address(address: &str, max_width: usize) -> String {
+    if address.len() <= max_width {
+        return address.to_string();
+    }
+    let Some((ip, port)) = address
+        .strip_prefix('[')
+        .and_then(|rest| rest.split_once("]:"))
+    else {
+        return address.to_string();
+    };
+
+    // Room for the IP once the brackets, colon, ellipsis and port are in
+    let room = max_width.saturating_sub(port.len() + 4);
+    if room < 2 {
+        return address.to_string();
+    }
+    let tail = room / 2;
+    let head = room - tail;
+    format!("[{}…{}]:{}", &ip[..head], &ip[ip.len() - tail..], port)
+}
+
 pub fn ip_to_color(ip: &str) -> Color {
     // A curated list of pastel-like colors from your theme.
     let colors = [
@@ -289,6 +370,15 @@ pub fn format_countdown(duration: Duration) -> String {
     parts.join(" ").to_string()
 }
 
+/// Tracker swarm counts, e.g. "12 seeds, 30 leechers, 480 done".
+pub fn format_swarm_counts(swarm: &SwarmCounts) -> String {
+    let mut text = format!("{} seeds, {} leechers", swarm.seeders, swarm.leechers);
+    if swarm.completed > 0 {
+        text.push_str(&format!(", {} done", swarm.completed));
+    }
+    text
+}
+
 pub fn format_limit_bps(bps: u64) -> String {
     if bps == 0 {
         "Unlimited".to_string()
@@ -451,3 +541,33 @@ pub fn format_limit_delta(current: usize, last: usize) -> Span<'static> {
     };
     Span::styled(format!(" ({}{})", sign, delta.abs()), style)
 }
+
+/// Percentage difference between the latest tuning score and the best one so far.
+pub fn format_score_delta(current: u64, best: u64) -> Span<'static> {
+    if best == 0 {
+        return Span::raw("");
+    }
+    let percentage = (current as f64 - best as f64) / best as f64 * 100.0;
+    let style = if percentage >= 0.0 {
+        Style::default().fg(theme::GREEN)
+    } else {
+        Style::default().fg(theme::RED)
+    };
+    Span::styled(format!(" ({:+.1}%)", percentage), style)
+}
+
+pub fn format_peers_summary(peers: &[PeerInfo]) -> String {
+    let downloading = peers.iter().filter(|p| p.download_speed_bps > 0).count();
+    let uploading = peers
+        .iter()
+        .filter(|p| p.download_speed_bps == 0 && p.upload_speed_bps > 0)
+        .count();
+    let idle = peers.len() - downloading - uploading;
+    format!(
+        "{} peers: {} downloading, {} uploading, {} idle",
+        peers.len(),
+        downloading,
+        uploading,
+        idle
+    )
+}